@@ -0,0 +1,12 @@
+fn main() {
+    // The gRPC service code is generated from proto/aidb.proto, using a
+    // bundled protoc so no system install is needed.
+    #[cfg(feature = "server-grpc")]
+    {
+        let protoc =
+            protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc for this platform");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::compile_protos("proto/aidb.proto")
+            .expect("Failed to compile proto/aidb.proto");
+    }
+}
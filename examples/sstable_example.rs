@@ -40,7 +40,7 @@ fn main() -> Result<()> {
             builder.add(key, value)?;
         }
 
-        let file_size = builder.finish()?;
+        let file_size = builder.finish()?.file_size;
         println!("   ✓ SSTable created: {} bytes", file_size);
         println!("   ✓ {} entries written\n", entries.len());
     }
@@ -117,7 +117,7 @@ fn main() -> Result<()> {
             builder.add(key.as_bytes(), value.as_bytes())?;
         }
 
-        let file_size = builder.finish()?;
+        let file_size = builder.finish()?.file_size;
         println!("   ✓ Large SSTable created: {} bytes", file_size);
 
         // Read it back
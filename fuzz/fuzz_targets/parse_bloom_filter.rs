@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (&[u8], &[u8])| {
+    let (filter, key) = input;
+    let _ = aidb::fuzzing::parse_bloom_filter(filter, key);
+});
@@ -0,0 +1,24 @@
+#![no_main]
+
+use aidb::compaction::VersionEdit;
+use aidb::sstable::Footer;
+use aidb::{fuzzing, WriteBatch};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+enum Input {
+    Block(Vec<(Vec<u8>, Vec<u8>)>, u8),
+    Footer(Footer),
+    Wal(Vec<WriteBatch>),
+    Bloom(Vec<Vec<u8>>),
+    Manifest(Vec<VersionEdit>),
+}
+
+fuzz_target!(|input: Input| match input {
+    Input::Block(entries, restart_interval) => fuzzing::block_round_trip(&entries, restart_interval),
+    Input::Footer(footer) => fuzzing::footer_round_trip(&footer),
+    Input::Wal(batches) => fuzzing::wal_round_trip(&batches),
+    Input::Bloom(keys) => fuzzing::bloom_round_trip(&keys),
+    Input::Manifest(edits) => fuzzing::manifest_round_trip(&edits),
+});
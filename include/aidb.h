@@ -0,0 +1,80 @@
+/*
+ * C API of the AiDb storage engine.
+ *
+ * Build the library with `cargo build --release --features ffi`.
+ * See src/ffi.rs for the conventions: fallible functions return an AIDB_*
+ * status code and store an error message in *errptr (release it with
+ * aidb_free); memory returned by the library is released with aidb_free.
+ */
+#ifndef AIDB_H
+#define AIDB_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes */
+#define AIDB_OK 0
+#define AIDB_NOT_FOUND 1
+#define AIDB_CORRUPTION 2
+#define AIDB_NOT_SUPPORTED 3
+#define AIDB_INVALID_ARGUMENT 4
+#define AIDB_IO_ERROR 5
+#define AIDB_INVALID_STATE 6
+#define AIDB_NO_SPACE 7
+#define AIDB_ALREADY_EXISTS 8
+#define AIDB_INTERNAL 9
+
+/* Opaque handles */
+typedef struct aidb_t aidb_t;
+typedef struct aidb_options_t aidb_options_t;
+typedef struct aidb_writebatch_t aidb_writebatch_t;
+typedef struct aidb_iterator_t aidb_iterator_t;
+
+/* Memory */
+void aidb_free(char* ptr);
+
+/* Options */
+aidb_options_t* aidb_options_create(void);
+void aidb_options_destroy(aidb_options_t* options);
+int aidb_options_set(aidb_options_t* options, const char* name, const char* value,
+                     char** errptr);
+
+/* Database */
+int aidb_open(const aidb_options_t* options, const char* name, aidb_t** db, char** errptr);
+int aidb_close(aidb_t* db, char** errptr);
+int aidb_put(aidb_t* db, const char* key, size_t key_len, const char* value,
+             size_t value_len, char** errptr);
+int aidb_get(aidb_t* db, const char* key, size_t key_len, char** value, size_t* value_len,
+             char** errptr);
+int aidb_delete(aidb_t* db, const char* key, size_t key_len, char** errptr);
+int aidb_write(aidb_t* db, const aidb_writebatch_t* batch, char** errptr);
+
+/* Write batches */
+aidb_writebatch_t* aidb_writebatch_create(void);
+void aidb_writebatch_destroy(aidb_writebatch_t* batch);
+void aidb_writebatch_put(aidb_writebatch_t* batch, const char* key, size_t key_len,
+                         const char* value, size_t value_len);
+void aidb_writebatch_delete(aidb_writebatch_t* batch, const char* key, size_t key_len);
+void aidb_writebatch_clear(aidb_writebatch_t* batch);
+size_t aidb_writebatch_count(const aidb_writebatch_t* batch);
+
+/* Iterators */
+aidb_iterator_t* aidb_create_iterator(aidb_t* db, char** errptr);
+void aidb_iter_destroy(aidb_iterator_t* iter);
+unsigned char aidb_iter_valid(const aidb_iterator_t* iter);
+void aidb_iter_seek_to_first(aidb_iterator_t* iter);
+void aidb_iter_seek_to_last(aidb_iterator_t* iter);
+void aidb_iter_seek(aidb_iterator_t* iter, const char* key, size_t key_len);
+void aidb_iter_next(aidb_iterator_t* iter);
+void aidb_iter_prev(aidb_iterator_t* iter);
+const char* aidb_iter_key(const aidb_iterator_t* iter, size_t* len);
+const char* aidb_iter_value(const aidb_iterator_t* iter, size_t* len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* AIDB_H */
@@ -0,0 +1,259 @@
+//! Async API (requires the `tokio` feature).
+//!
+//! [`DB`] wraps a [`crate::DB`] and runs every call on tokio's blocking
+//! thread pool, so WAL fsyncs, SSTable reads and the waits for flushes and
+//! compactions never stall executor threads. Keys and values are copied
+//! into the blocking task.
+//!
+//! [`scan`](DB::scan) returns a [`Scan`] that produces entries on the
+//! blocking pool while the caller consumes them, a bounded number at a
+//! time. A read error ends the scan with an `Err` entry.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::r#async::DB;
+//! use aidb::Options;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), aidb::Error> {
+//!     let db = DB::open("./data", Options::default()).await?;
+//!     db.put(b"key", b"value").await?;
+//!     assert_eq!(db.get(b"key").await?, Some(b"value".to_vec()));
+//!
+//!     let mut scan = db.scan(None, None).await?;
+//!     while let Some(entry) = scan.next().await {
+//!         let (key, value) = entry?;
+//!         println!("{:?} => {:?}", key, value);
+//!     }
+//!     db.close().await
+//! }
+//! ```
+
+use crate::error::{Error, Result};
+use crate::{Options, WriteBatch};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Entries buffered between a scan and its consumer
+const SCAN_BUFFER: usize = 256;
+
+/// A database handle for async code.
+///
+/// Cloning is cheap; all clones share the same database.
+#[derive(Clone)]
+pub struct DB {
+    inner: Arc<crate::DB>,
+}
+
+impl From<Arc<crate::DB>> for DB {
+    fn from(inner: Arc<crate::DB>) -> Self {
+        Self { inner }
+    }
+}
+
+impl DB {
+    /// Opens the database at `path`. See [`crate::DB::open`].
+    pub async fn open<P: AsRef<Path>>(path: P, options: Options) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let inner = run_blocking(move || crate::DB::open(path, options)).await?;
+        Ok(Self { inner: Arc::new(inner) })
+    }
+
+    /// Returns the underlying synchronous database.
+    pub fn inner(&self) -> &Arc<crate::DB> {
+        &self.inner
+    }
+
+    /// Runs a blocking database call on the blocking thread pool.
+    async fn call<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&crate::DB) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let db = Arc::clone(&self.inner);
+        run_blocking(move || f(&db)).await
+    }
+
+    /// Returns the value for `key`. See [`crate::DB::get`].
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = key.to_vec();
+        self.call(move |db| db.get(&key)).await
+    }
+
+    /// Inserts or replaces the value for `key`. See [`crate::DB::put`].
+    pub async fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let (key, value) = (key.to_vec(), value.to_vec());
+        self.call(move |db| db.put(&key, &value)).await
+    }
+
+    /// Deletes `key`. See [`crate::DB::delete`].
+    pub async fn delete(&self, key: &[u8]) -> Result<()> {
+        let key = key.to_vec();
+        self.call(move |db| db.delete(&key)).await
+    }
+
+    /// Applies `batch` atomically. See [`crate::DB::write`].
+    pub async fn write(&self, batch: WriteBatch) -> Result<()> {
+        self.call(move |db| db.write(batch)).await
+    }
+
+    /// Streams the entries with `start <= key < end` in key order. See
+    /// [`crate::DB::scan`].
+    ///
+    /// The scan stops producing entries when the returned [`Scan`] is
+    /// dropped. If reading fails midway, the entries read so far are
+    /// followed by the error.
+    pub async fn scan(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<Scan> {
+        let (start, end) = (start.map(<[u8]>::to_vec), end.map(<[u8]>::to_vec));
+        let db = Arc::clone(&self.inner);
+        let mut iter = run_blocking(move || db.scan(start.as_deref(), end.as_deref())).await?;
+
+        let (tx, rx) = mpsc::channel(SCAN_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            while iter.valid() {
+                let entry = (iter.key().to_vec(), iter.value().to_vec());
+                // The consumer went away
+                if tx.blocking_send(Ok(entry)).is_err() {
+                    return;
+                }
+                iter.next();
+            }
+            // Otherwise an error would look like the end of the range
+            if let Err(e) = iter.status() {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+        Ok(Scan { rx })
+    }
+
+    /// Flushes the MemTable to an SSTable. See [`crate::DB::flush`].
+    pub async fn flush(&self) -> Result<()> {
+        self.call(|db| db.flush()).await
+    }
+
+    /// Flushes and closes the database. See [`crate::DB::close`].
+    pub async fn close(&self) -> Result<()> {
+        self.call(|db| db.close()).await
+    }
+}
+
+/// Entries produced by [`DB::scan`], in key order.
+#[derive(Debug)]
+pub struct Scan {
+    rx: mpsc::Receiver<Result<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl Scan {
+    /// Returns the next entry, or `None` at the end of the range.
+    ///
+    /// After an `Err` the scan is over and returns `None`.
+    pub async fn next(&mut self) -> Option<Result<(Vec<u8>, Vec<u8>)>> {
+        self.rx.recv().await
+    }
+}
+
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Error::internal(format!("blocking task failed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_async_db() {
+        let dir = TempDir::new().unwrap();
+        let db = DB::open(dir.path(), Options::default()).await.unwrap();
+
+        db.put(b"a", b"1").await.unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"b", b"2");
+        batch.put(b"c", b"3");
+        db.write(batch).await.unwrap();
+        db.delete(b"c").await.unwrap();
+        db.flush().await.unwrap();
+
+        assert_eq!(db.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(db.get(b"c").await.unwrap(), None);
+
+        let mut scan = db.scan(None, None).await.unwrap();
+        let mut entries = Vec::new();
+        while let Some(entry) = scan.next().await {
+            entries.push(entry.unwrap());
+        }
+        assert_eq!(entries, [(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]);
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_async_scan_dropped_early() {
+        let dir = TempDir::new().unwrap();
+        let db = DB::open(dir.path(), Options::default()).await.unwrap();
+        for i in 0..(SCAN_BUFFER * 2) {
+            db.put(format!("key{:04}", i).as_bytes(), b"v").await.unwrap();
+        }
+
+        let mut scan = db.scan(Some(b"key0010"), None).await.unwrap();
+        let (key, _) = scan.next().await.unwrap().unwrap();
+        assert_eq!(key, b"key0010");
+        drop(scan);
+
+        // The producer stops once the receiver is gone
+        db.put(b"after", b"scan").await.unwrap();
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_async_scan_read_error() {
+        let dir = TempDir::new().unwrap();
+        let options = Options { block_cache_size: 0, ..Default::default() };
+        let db = DB::open(dir.path(), options.clone()).await.unwrap();
+        // Values that do not compress, so data blocks fill the file
+        let mut seed = 1u64;
+        for i in 0..1000 {
+            let value: Vec<u8> = (0..100)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    (seed >> 56) as u8
+                })
+                .collect();
+            db.put(format!("key{:04}", i).as_bytes(), &value).await.unwrap();
+        }
+        db.close().await.unwrap();
+        drop(db);
+
+        // Damage a data block halfway through the only SSTable
+        let sst = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "sst"))
+            .unwrap();
+        let mut data = std::fs::read(&sst).unwrap();
+        let middle = data.len() / 2;
+        data[middle] ^= 0xff;
+        std::fs::write(&sst, data).unwrap();
+
+        let db = DB::open(dir.path(), options).await.unwrap();
+        let mut scan = db.scan(None, None).await.unwrap();
+        let mut read = 0;
+        let error = loop {
+            match scan.next().await.expect("the scan ends with the error") {
+                Ok(_) => read += 1,
+                Err(e) => break e,
+            }
+        };
+        assert!(read > 0 && read < 1000);
+        assert!(
+            matches!(error, Error::Corruption(_) | Error::ChecksumMismatch { .. }),
+            "{}",
+            error
+        );
+        assert!(scan.next().await.is_none());
+    }
+}
@@ -0,0 +1,922 @@
+//! Incremental backups.
+//!
+//! A [`BackupEngine`] keeps numbered backups of a database in a
+//! [`BackupTarget`]: a local directory ([`LocalBackupTarget`]), or any
+//! object store the application implements the trait for, such as S3 or
+//! GCS. Each backup is taken from a [checkpoint](crate::checkpoint), and
+//! SSTables are shared between backups: a table already stored by an
+//! earlier backup is not uploaded again, so a new backup only costs the
+//! tables written since the last one.
+//!
+//! The checkpoint is staged in the database directory, where SSTables are
+//! hard-linked rather than copied, and every file is streamed from there to
+//! the target; no second copy of the database is needed on local disk.
+//!
+//! A target holds these objects:
+//! - `shared/<number>_<crc32>_<size>.sst`: SSTables, referenced by any
+//!   number of backups
+//! - `shared/<number>_<crc32>_<size>.blob`: the blob files of values stored
+//!   with [`DB::put_reader`], shared the same way
+//! - `private/<id>/<name>`: the MANIFEST, WAL and OPTIONS file of each
+//!   backup
+//! - `meta/<id>`: the list of files in each backup with their sizes and
+//!   CRC32 checksums (JSON)
+//!
+//! Checksums are verified when a backup is restored, and on request with
+//! [`BackupEngine::verify_backup`].
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::backup::BackupEngine;
+//! use aidb::{Options, DB};
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = DB::open("./data", Options::default())?;
+//! let mut backups = BackupEngine::open("./backups")?;
+//!
+//! backups.create_new_backup(&db)?;
+//! backups.purge_old_backups(7)?;
+//!
+//! // Later, after losing ./data
+//! backups.restore_db_from_latest_backup("./data-restored")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::blob::{self, BlobReference};
+use crate::config::OptionsFile;
+use crate::env::{self, Env};
+use crate::transaction_log::{self, TransactionLogIterator};
+use crate::wal;
+use crate::write_batch::WriteOp;
+use crate::{Error, Result, WriteBatch, DB};
+
+/// Identifies a backup; backups are numbered from 1 in creation order.
+pub type BackupId = u32;
+
+const SHARED_PREFIX: &str = "shared/";
+const PRIVATE_PREFIX: &str = "private/";
+const META_PREFIX: &str = "meta/";
+/// Directory inside the database directory a backup is checkpointed to
+const STAGING_DIR: &str = "backup-staging";
+/// Buffer size for streaming files to and from the target
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Storage that backups are written to.
+///
+/// Objects are named by `/`-separated keys, as in an object store. They
+/// are written once and never modified; the engine only lists the `meta/`
+/// prefix, whose keys contain no further `/`.
+pub trait BackupTarget: Send + Sync {
+    /// Stores `reader`'s contents under `key`, replacing any existing
+    /// object.
+    ///
+    /// `size` is the exact length of the data, so an implementation can
+    /// choose a multipart upload's part size up front. The object must
+    /// only become visible once it is complete.
+    fn put_object(&self, key: &str, reader: &mut dyn Read, size: u64) -> Result<()>;
+
+    /// Opens the object stored under `key` for reading.
+    fn get_object(&self, key: &str) -> Result<Box<dyn Read + Send>>;
+
+    /// Returns the keys that start with `prefix`, in any order.
+    fn list(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// Deletes the object stored under `key`; deleting a missing object
+    /// succeeds.
+    fn delete(&self, key: &str) -> Result<()>;
+
+    /// Returns true if an object is stored under `key`.
+    fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.list(key)?.iter().any(|found| found == key))
+    }
+
+    /// Stores the file at `path`, read through `env`, under `key`.
+    ///
+    /// The default implementation streams the file to
+    /// [`put_object`](Self::put_object).
+    fn put_file(&self, key: &str, env: &dyn Env, path: &Path) -> Result<()> {
+        let size = env.file_size(path)?;
+        let mut file = env.new_sequential_file(path)?;
+        self.put_object(key, &mut file, size)
+    }
+}
+
+/// A [`BackupTarget`] storing objects as files in a local directory.
+pub struct LocalBackupTarget {
+    dir: PathBuf,
+    env: Arc<dyn Env>,
+}
+
+impl LocalBackupTarget {
+    /// Stores objects below `dir` through `env`, creating `dir` if needed.
+    pub fn new<P: AsRef<Path>>(dir: P, env: Arc<dyn Env>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        env.create_dir_all(&dir)?;
+        Ok(Self { dir, env })
+    }
+
+    /// Creates the directory an object is stored in
+    fn object_path(&self, key: &str) -> Result<PathBuf> {
+        let path = self.dir.join(key);
+        if let Some(parent) = path.parent() {
+            self.env.create_dir_all(parent)?;
+        }
+        Ok(path)
+    }
+}
+
+impl BackupTarget for LocalBackupTarget {
+    fn put_object(&self, key: &str, reader: &mut dyn Read, _size: u64) -> Result<()> {
+        let path = self.object_path(key)?;
+        let tmp = crate::sstable::temp_file_path(&path);
+        let mut file = self.env.new_writable_file(&tmp)?;
+        io::copy(reader, &mut file)?;
+        file.sync()?;
+        drop(file);
+        self.env.rename_file(&tmp, &path)?;
+        self.env.sync_dir(path.parent().unwrap_or(&self.dir))?;
+        Ok(())
+    }
+
+    fn get_object(&self, key: &str) -> Result<Box<dyn Read + Send>> {
+        Ok(Box::new(self.env.new_sequential_file(&self.dir.join(key))?))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let (dir, name_prefix) = prefix.rsplit_once('/').unwrap_or(("", prefix));
+        let path = self.dir.join(dir);
+        if !self.env.file_exists(&path) {
+            return Ok(Vec::new());
+        }
+        let parent = if dir.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", dir)
+        };
+        Ok(self
+            .env
+            .get_children(&path)?
+            .into_iter()
+            .filter(|name| name.starts_with(name_prefix))
+            .map(|name| format!("{}{}", parent, name))
+            .collect())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let path = self.dir.join(key);
+        match self.env.remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        // Remove the directory of a deleted backup's private files
+        if let Some(parent) = path.parent().filter(|_| key.starts_with(PRIVATE_PREFIX)) {
+            if self.env.get_children(parent)?.is_empty() {
+                self.env.remove_dir(parent)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.env.file_exists(&self.dir.join(key)))
+    }
+
+    fn put_file(&self, key: &str, env: &dyn Env, path: &Path) -> Result<()> {
+        // Hard-link where possible, as for a checkpoint
+        let target = self.object_path(key)?;
+        let tmp = crate::sstable::temp_file_path(&target);
+        env.link_file(path, &tmp)?;
+        self.env.rename_file(&tmp, &target)?;
+        self.env.sync_dir(target.parent().unwrap_or(&self.dir))?;
+        Ok(())
+    }
+}
+
+/// Description of a stored backup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupInfo {
+    /// The backup's id
+    pub id: BackupId,
+    /// When the backup was taken, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Total size of the backup's files in bytes, including shared SSTables
+    pub size: u64,
+    /// Number of files in the backup
+    pub num_files: usize,
+    files: Vec<BackupFile>,
+}
+
+/// A file of a backup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct BackupFile {
+    /// Name of the file in the database directory
+    name: String,
+    /// Key of the object in the backup target
+    path: String,
+    size: u64,
+    crc32: u32,
+}
+
+/// Creates, verifies and restores backups in a [`BackupTarget`].
+pub struct BackupEngine {
+    target: Arc<dyn BackupTarget>,
+    env: Arc<dyn Env>,
+    backups: BTreeMap<BackupId, BackupInfo>,
+}
+
+impl BackupEngine {
+    /// Opens the backup directory `backup_dir`, creating it if needed.
+    pub fn open<P: AsRef<Path>>(backup_dir: P) -> Result<Self> {
+        Self::open_with_env(backup_dir, env::default_env())
+    }
+
+    /// Opens the backup directory through `env`, which is also used to
+    /// write restored databases.
+    pub fn open_with_env<P: AsRef<Path>>(backup_dir: P, env: Arc<dyn Env>) -> Result<Self> {
+        let target = LocalBackupTarget::new(backup_dir, Arc::clone(&env))?;
+        Self::open_with_target(Arc::new(target), env)
+    }
+
+    /// Opens the backups stored in `target`. Restored databases are
+    /// written through `env`.
+    pub fn open_with_target(target: Arc<dyn BackupTarget>, env: Arc<dyn Env>) -> Result<Self> {
+        let mut backups = BTreeMap::new();
+        for key in target.list(META_PREFIX)? {
+            // Skip metadata left half-written by a crash
+            let Ok(id) = key[META_PREFIX.len()..].parse::<BackupId>() else {
+                continue;
+            };
+            let mut data = Vec::new();
+            target.get_object(&key)?.read_to_end(&mut data)?;
+            let info: BackupInfo = serde_json::from_slice(&data).map_err(|e| {
+                Error::corruption(format!("invalid metadata for backup {}: {}", id, e))
+            })?;
+            backups.insert(id, info);
+        }
+
+        Ok(Self { target, env, backups })
+    }
+
+    /// Backs up `db` and returns the new backup's id.
+    ///
+    /// The database is flushed and checkpointed into a staging directory
+    /// inside its own directory first (see [`DB::create_checkpoint`]);
+    /// SSTables the target already holds are not stored again.
+    pub fn create_new_backup(&mut self, db: &DB) -> Result<BackupId> {
+        let id = self.backups.keys().next_back().map_or(1, |id| id + 1);
+        let env = &*db.env;
+        let staging_dir = db.path.join(STAGING_DIR);
+        if env.file_exists(&staging_dir) {
+            // Left by a backup that failed part way
+            remove_dir_all(env, &staging_dir)?;
+        }
+        db.create_checkpoint(&staging_dir)?;
+
+        let result = self.store_checkpoint(id, env, &staging_dir);
+        remove_dir_all(env, &staging_dir)?;
+        let files = result?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let info = BackupInfo {
+            id,
+            timestamp,
+            size: files.iter().map(|file| file.size).sum(),
+            num_files: files.len(),
+            files,
+        };
+        self.write_meta(&info)?;
+        self.backups.insert(id, info);
+
+        log::info!("Created backup {}", id);
+        Ok(id)
+    }
+
+    /// Stores the files of the checkpoint in `dir` as backup `id`
+    fn store_checkpoint(&self, id: BackupId, env: &dyn Env, dir: &Path) -> Result<Vec<BackupFile>> {
+        let mut names = env.get_children(dir)?;
+        if names.iter().any(|name| name == blob::BLOB_DIR) {
+            names.retain(|name| name != blob::BLOB_DIR);
+            for blob in env.get_children(&dir.join(blob::BLOB_DIR))? {
+                names.push(format!("{}/{}", blob::BLOB_DIR, blob));
+            }
+        }
+        names.sort();
+        let mut files = Vec::with_capacity(names.len());
+        for name in names {
+            let path = dir.join(&name);
+            let mut reader = ChecksumReader::new(env.new_sequential_file(&path)?);
+            io::copy(&mut reader, &mut io::sink())?;
+            let (size, crc32) = (reader.size, reader.crc32());
+
+            // SSTables and blob files never change once written
+            let file_name = name.rsplit('/').next().unwrap_or(&name);
+            let key = match file_name.rsplit_once('.') {
+                Some((number, extension @ ("sst" | "blob"))) => {
+                    format!("{}{}_{:08x}_{}.{}", SHARED_PREFIX, number, crc32, size, extension)
+                }
+                _ => format!("{}{}/{}", PRIVATE_PREFIX, id, name),
+            };
+            if !key.starts_with(SHARED_PREFIX) || !self.target.exists(&key)? {
+                self.target.put_file(&key, env, &path)?;
+            }
+            files.push(BackupFile { name, path: key, size, crc32 });
+        }
+        Ok(files)
+    }
+
+    /// Returns the stored backups, oldest first.
+    pub fn get_backup_info(&self) -> Vec<BackupInfo> {
+        self.backups.values().cloned().collect()
+    }
+
+    /// Checks that every file of backup `id` is present with the recorded
+    /// size and checksum.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` for an unknown id and `Corruption` if a file is
+    /// missing or damaged.
+    pub fn verify_backup(&self, id: BackupId) -> Result<()> {
+        for file in &self.backup(id)?.files {
+            self.copy_verified(file, &mut io::sink())?;
+        }
+        Ok(())
+    }
+
+    /// Deletes backup `id`, and the shared SSTables no other backup uses.
+    pub fn delete_backup(&mut self, id: BackupId) -> Result<()> {
+        let info = self.backup(id)?.clone();
+
+        // Forget the backup first, so a crash part way leaves unreferenced
+        // files rather than a backup with files missing
+        self.target.delete(&format!("{}{}", META_PREFIX, id))?;
+        self.backups.remove(&id);
+
+        let in_use: HashSet<&str> = self
+            .backups
+            .values()
+            .flat_map(|backup| backup.files.iter().map(|file| file.path.as_str()))
+            .collect();
+        for file in info.files.iter().filter(|file| !in_use.contains(file.path.as_str())) {
+            self.target.delete(&file.path)?;
+        }
+
+        log::info!("Deleted backup {}", id);
+        Ok(())
+    }
+
+    /// Deletes all but the `num_backups_to_keep` newest backups.
+    pub fn purge_old_backups(&mut self, num_backups_to_keep: usize) -> Result<()> {
+        let excess = self.backups.len().saturating_sub(num_backups_to_keep);
+        let old: Vec<BackupId> = self.backups.keys().take(excess).copied().collect();
+        for id in old {
+            self.delete_backup(id)?;
+        }
+        Ok(())
+    }
+
+    /// Restores backup `id` into `db_dir`, which must not exist or be empty.
+    ///
+    /// Every file's checksum is verified as it is copied. The restored
+    /// directory can be opened with [`DB::open`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` for an unknown id, `InvalidArgument` if `db_dir`
+    /// is not empty, and `Corruption` if a backup file is missing or
+    /// damaged.
+    pub fn restore_db_from_backup<P: AsRef<Path>>(&self, id: BackupId, db_dir: P) -> Result<()> {
+        let info = self.backup(id)?;
+        let db_dir = db_dir.as_ref();
+        if self.env.file_exists(db_dir) && !self.env.get_children(db_dir)?.is_empty() {
+            return Err(Error::invalid_argument(format!(
+                "restore target {:?} is not empty",
+                db_dir
+            )));
+        }
+        self.env.create_dir_all(db_dir)?;
+
+        let blob_dir = db_dir.join(blob::BLOB_DIR);
+        for file in &info.files {
+            let path = db_dir.join(&file.name);
+            if path.starts_with(&blob_dir) && !self.env.file_exists(&blob_dir) {
+                self.env.create_dir_all(&blob_dir)?;
+            }
+            let mut out = self.env.new_writable_file(&path)?;
+            self.copy_verified(file, &mut out)?;
+            out.sync()?;
+        }
+        if self.env.file_exists(&blob_dir) {
+            self.env.sync_dir(&blob_dir)?;
+        }
+        self.env.sync_dir(db_dir)?;
+
+        log::info!("Restored backup {} to {:?}", id, db_dir);
+        Ok(())
+    }
+
+    /// Restores the newest backup into `db_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if there are no backups; otherwise as
+    /// [`restore_db_from_backup`](Self::restore_db_from_backup).
+    pub fn restore_db_from_latest_backup<P: AsRef<Path>>(&self, db_dir: P) -> Result<()> {
+        let id = *self.backups.keys().next_back().ok_or_else(|| Error::not_found("no backups"))?;
+        self.restore_db_from_backup(id, db_dir)
+    }
+
+    /// Restores backup `id` into `db_dir` and replays the source database's
+    /// WAL on top of it, up to and including `target_sequence`.
+    ///
+    /// `wal_dir` is the source database's directory. Its WAL files, those
+    /// in its `archive` directory included (see
+    /// [`Options::wal_archive_size_limit`](crate::Options)), are read for
+    /// the writes committed after the backup was taken. Whole write batches
+    /// are applied, so the restored database stops at the last batch that
+    /// ends at or before `target_sequence`; that batch's last sequence
+    /// number is returned. It is lower than `target_sequence` if the WAL
+    /// ends first.
+    ///
+    /// Values written with [`DB::put_reader`] are copied from the source
+    /// database's blob files. The restored database is opened with the
+    /// options of its OPTIONS file, so it must use the default
+    /// [comparator](crate::comparator).
+    ///
+    /// WAL records carry no timestamps, so the target can only be a
+    /// sequence number (see `TODO.md`).
+    ///
+    /// If replaying fails, the restored files are removed again, and so is
+    /// `db_dir` if it did not exist before.
+    ///
+    /// # Errors
+    ///
+    /// As [`restore_db_from_backup`](Self::restore_db_from_backup); also
+    /// returns `InvalidArgument` if the backup is newer than
+    /// `target_sequence`, and `NotFound` if the WAL written right after the
+    /// backup, or the blob file of a value to replay, has been deleted.
+    pub fn restore_to_point_in_time<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        id: BackupId,
+        wal_dir: P,
+        target_sequence: u64,
+        db_dir: Q,
+    ) -> Result<u64> {
+        let (wal_dir, db_dir) = (wal_dir.as_ref(), db_dir.as_ref());
+        let existed = self.env.file_exists(db_dir);
+        self.restore_db_from_backup(id, db_dir)?;
+
+        let sequence = match self.replay_wal(id, wal_dir, target_sequence, db_dir) {
+            Ok(sequence) => sequence,
+            Err(e) => {
+                let removed = if existed {
+                    remove_dir_contents(&*self.env, db_dir)
+                } else {
+                    remove_dir_all(&*self.env, db_dir)
+                };
+                if let Err(remove_err) = removed {
+                    log::warn!(
+                        "Failed to remove the restored files in {:?}: {}",
+                        db_dir,
+                        remove_err
+                    );
+                }
+                return Err(e);
+            }
+        };
+
+        log::info!("Restored backup {} to {:?} at sequence {}", id, db_dir, sequence);
+        Ok(sequence)
+    }
+
+    /// Replays the WAL in `wal_dir` up to `target_sequence` onto backup
+    /// `id`, restored in `db_dir`, and returns the last sequence number
+    /// applied
+    fn replay_wal(
+        &self,
+        id: BackupId,
+        wal_dir: &Path,
+        target_sequence: u64,
+        db_dir: &Path,
+    ) -> Result<u64> {
+        let mut options = OptionsFile::load_latest_with_env(db_dir, &*self.env)?
+            .map(|file| file.options)
+            .unwrap_or_default();
+        options.env = Arc::clone(&self.env);
+        options.listeners.clear();
+        options.statistics = None;
+        let db = DB::open(db_dir, options)?;
+
+        let mut sequence = db.sequence.load(Ordering::SeqCst);
+        if sequence > target_sequence {
+            return Err(Error::invalid_argument(format!(
+                "backup {} is at sequence {}, after the target {}",
+                id, sequence, target_sequence
+            )));
+        }
+
+        let mut files = transaction_log::wal_files(&*self.env, &wal_dir.join(wal::ARCHIVE_DIR))?;
+        files.extend(transaction_log::wal_files(&*self.env, wal_dir)?);
+        let updates = TransactionLogIterator::new(
+            Arc::clone(&self.env),
+            files.into(),
+            sequence + 1,
+            target_sequence,
+            None,
+        );
+        for update in updates {
+            let update = update?;
+            let end = update.sequence + update.batch.len() as u64 - 1;
+            if end > target_sequence {
+                break;
+            }
+            replay_batch(&*self.env, &db, wal_dir, update.batch)?;
+            sequence = end;
+        }
+        db.close()?;
+        Ok(sequence)
+    }
+
+    fn backup(&self, id: BackupId) -> Result<&BackupInfo> {
+        self.backups.get(&id).ok_or_else(|| Error::not_found(format!("backup {}", id)))
+    }
+
+    /// Streams a backup file to `out`, checking its size and checksum
+    fn copy_verified(&self, file: &BackupFile, out: &mut dyn Write) -> Result<()> {
+        let unreadable =
+            |e: Error| Error::corruption(format!("cannot read backup file {}: {}", file.path, e));
+        let mut reader =
+            ChecksumReader::new(self.target.get_object(&file.path).map_err(unreadable)?);
+        let mut buf = vec![0; COPY_BUFFER_SIZE];
+        loop {
+            let n = reader.read(&mut buf).map_err(|e| unreadable(e.into()))?;
+            if n == 0 {
+                break;
+            }
+            out.write_all(&buf[..n])?;
+        }
+        if reader.size != file.size || reader.crc32() != file.crc32 {
+            return Err(Error::corruption(format!("backup file {} is damaged", file.path)));
+        }
+        Ok(())
+    }
+
+    /// Writes the metadata of a backup; the backup exists once this succeeds
+    fn write_meta(&self, info: &BackupInfo) -> Result<()> {
+        let data = serde_json::to_vec_pretty(info)
+            .map_err(|e| Error::internal(format!("failed to serialize backup metadata: {}", e)))?;
+        let key = format!("{}{}", META_PREFIX, info.id);
+        self.target.put_object(&key, &mut data.as_slice(), data.len() as u64)
+    }
+}
+
+/// Removes a directory of files, and of the blob files and archived WAL
+/// files of a database
+fn remove_dir_all(env: &dyn Env, dir: &Path) -> Result<()> {
+    remove_dir_contents(env, dir)?;
+    env.remove_dir(dir)?;
+    Ok(())
+}
+
+/// Empties a directory as [`remove_dir_all`] does, keeping the directory
+fn remove_dir_contents(env: &dyn Env, dir: &Path) -> Result<()> {
+    for name in env.get_children(dir)? {
+        if name == blob::BLOB_DIR || name == wal::ARCHIVE_DIR {
+            remove_dir_all(env, &dir.join(name))?;
+        } else {
+            env.remove_file(&dir.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies a batch read from the WAL of the database at `wal_dir` to `db`.
+/// Values stored with `put_reader` are streamed from that database's blob
+/// files into new ones.
+fn replay_batch(env: &dyn Env, db: &DB, wal_dir: &Path, batch: WriteBatch) -> Result<()> {
+    let mut pending = WriteBatch::new();
+    for op in batch.iter() {
+        match op {
+            WriteOp::Put { key, value } => match BlobReference::decode(value) {
+                Some(reference) => {
+                    db.write(std::mem::take(&mut pending))?;
+                    let path = reference.path(wal_dir);
+                    let file = env.new_sequential_file(&path).map_err(|e| match e.kind() {
+                        io::ErrorKind::NotFound => {
+                            Error::not_found(format!("blob file {:?} was deleted", path))
+                        }
+                        _ => e.into(),
+                    })?;
+                    let mut reader = ChecksumReader::new(file);
+                    db.put_reader(key, &mut reader, reference.size())?;
+                    if reader.crc32() != reference.checksum() {
+                        return Err(Error::corruption(format!(
+                            "blob file {:?} does not match its reference",
+                            path
+                        )));
+                    }
+                }
+                None => pending.put(key, value),
+            },
+            WriteOp::Delete { key } => pending.delete(key),
+            WriteOp::Append { key, suffix } => pending.append(key, suffix),
+        }
+    }
+    db.write(pending)
+}
+
+/// Computes the size and CRC32 of the data read through it
+struct ChecksumReader<R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+    size: u64,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, hasher: crc32fast::Hasher::new(), size: 0 }
+    }
+
+    fn crc32(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn shared_files(dir: &Path) -> usize {
+        std::fs::read_dir(dir.join("shared")).map_or(0, |entries| entries.count())
+    }
+
+    /// An object store kept in memory
+    #[derive(Default)]
+    struct MemTarget {
+        objects: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    impl BackupTarget for MemTarget {
+        fn put_object(&self, key: &str, reader: &mut dyn Read, size: u64) -> Result<()> {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            assert_eq!(data.len() as u64, size);
+            self.objects.lock().insert(key.to_string(), data);
+            Ok(())
+        }
+
+        fn get_object(&self, key: &str) -> Result<Box<dyn Read + Send>> {
+            let data = self.objects.lock().get(key).cloned();
+            let data = data.ok_or_else(|| Error::not_found(key.to_string()))?;
+            Ok(Box::new(io::Cursor::new(data)))
+        }
+
+        fn list(&self, prefix: &str) -> Result<Vec<String>> {
+            Ok(self
+                .objects
+                .lock()
+                .keys()
+                .filter(|key| key.starts_with(prefix))
+                .cloned()
+                .collect())
+        }
+
+        fn delete(&self, key: &str) -> Result<()> {
+            self.objects.lock().remove(key);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_incremental_backup_and_restore() {
+        let temp_dir = TempDir::new().unwrap();
+        let backup_dir = temp_dir.path().join("backups");
+        let db = DB::open(temp_dir.path().join("db"), Options::default()).unwrap();
+        let mut engine = BackupEngine::open(&backup_dir).unwrap();
+
+        db.put(b"key1", b"v1").unwrap();
+        let first = engine.create_new_backup(&db).unwrap();
+        assert_eq!(shared_files(&backup_dir), 1);
+        assert!(!temp_dir.path().join("db").join(STAGING_DIR).exists());
+
+        // Only the new SSTable is stored by the second backup
+        db.put(b"key2", b"v2").unwrap();
+        let second = engine.create_new_backup(&db).unwrap();
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(shared_files(&backup_dir), 2);
+        engine.verify_backup(first).unwrap();
+        engine.verify_backup(second).unwrap();
+
+        // Backups are found again after reopening
+        let mut engine = BackupEngine::open(&backup_dir).unwrap();
+        let ids: Vec<_> = engine.get_backup_info().iter().map(|info| info.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+
+        engine.restore_db_from_backup(first, temp_dir.path().join("restored1")).unwrap();
+        let restored = DB::open(temp_dir.path().join("restored1"), Options::default()).unwrap();
+        assert_eq!(restored.get(b"key1").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(restored.get(b"key2").unwrap(), None);
+
+        // The first backup's table is still used by the second
+        engine.purge_old_backups(1).unwrap();
+        assert_eq!(shared_files(&backup_dir), 2);
+        assert!(engine.verify_backup(first).is_err());
+        assert!(!backup_dir.join("private").join(first.to_string()).exists());
+
+        engine.restore_db_from_latest_backup(temp_dir.path().join("restored2")).unwrap();
+        let restored = DB::open(temp_dir.path().join("restored2"), Options::default()).unwrap();
+        assert_eq!(restored.get(b"key1").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(restored.get(b"key2").unwrap(), Some(b"v2".to_vec()));
+        assert!(engine.restore_db_from_latest_backup(temp_dir.path().join("restored2")).is_err());
+
+        engine.delete_backup(second).unwrap();
+        assert_eq!(shared_files(&backup_dir), 0);
+        assert!(engine.restore_db_from_latest_backup(temp_dir.path().join("restored3")).is_err());
+    }
+
+    #[test]
+    fn test_backup_includes_blob_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let backup_dir = temp_dir.path().join("backups");
+        let db = DB::open(temp_dir.path().join("db"), Options::default()).unwrap();
+        let mut engine = BackupEngine::open(&backup_dir).unwrap();
+
+        db.put_reader(b"big", io::Cursor::new(vec![7u8; 100_000]), 100_000).unwrap();
+        engine.create_new_backup(&db).unwrap();
+        // The blob file is stored once, like the SSTable
+        db.put(b"small", b"value").unwrap();
+        let second = engine.create_new_backup(&db).unwrap();
+        assert_eq!(shared_files(&backup_dir), 3);
+        assert!(!temp_dir.path().join("db").join(STAGING_DIR).exists());
+        engine.verify_backup(second).unwrap();
+        drop(db);
+
+        let restored_dir = temp_dir.path().join("restored");
+        engine.restore_db_from_backup(second, &restored_dir).unwrap();
+        let restored = DB::open(&restored_dir, Options::default()).unwrap();
+        assert_eq!(restored.get(b"big").unwrap(), Some(vec![7u8; 100_000]));
+        assert_eq!(restored.get(b"small").unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[test]
+    fn test_restore_to_point_in_time_copies_blob_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let options = Options::default().wal_archive_size_limit(1024 * 1024);
+        let db = DB::open(&db_path, options).unwrap();
+        let mut engine = BackupEngine::open(temp_dir.path().join("backups")).unwrap();
+
+        let id = engine.create_new_backup(&db).unwrap();
+        db.put(b"small", b"value").unwrap();
+        db.put_reader(b"big", io::Cursor::new(vec![7u8; 100_000]), 100_000).unwrap();
+
+        let restored = temp_dir.path().join("restored");
+        assert_eq!(engine.restore_to_point_in_time(id, &db_path, 2, &restored).unwrap(), 2);
+        let copy = DB::open(&restored, Options::default()).unwrap();
+        assert_eq!(copy.get(b"big").unwrap(), Some(vec![7u8; 100_000]));
+        assert_eq!(copy.get(b"small").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(blob::blob_files(&*copy.env, &restored).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_verify_detects_damaged_backup() {
+        let temp_dir = TempDir::new().unwrap();
+        let backup_dir = temp_dir.path().join("backups");
+        let db = DB::open(temp_dir.path().join("db"), Options::default()).unwrap();
+        let mut engine = BackupEngine::open(&backup_dir).unwrap();
+
+        db.put(b"key", b"value").unwrap();
+        let id = engine.create_new_backup(&db).unwrap();
+
+        let table = std::fs::read_dir(backup_dir.join("shared")).unwrap().next().unwrap();
+        let table = table.unwrap().path();
+        let mut data = std::fs::read(&table).unwrap();
+        data[0] ^= 0xff;
+        std::fs::write(&table, data).unwrap();
+
+        assert!(matches!(engine.verify_backup(id), Err(Error::Corruption(_))));
+        let target = temp_dir.path().join("restored");
+        assert!(matches!(engine.restore_db_from_backup(id, &target), Err(Error::Corruption(_))));
+    }
+
+    #[test]
+    fn test_backup_to_custom_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path().join("db"), Options::default()).unwrap();
+        let target = Arc::new(MemTarget::default());
+        let mut engine =
+            BackupEngine::open_with_target(target.clone(), env::default_env()).unwrap();
+
+        db.put(b"key1", b"v1").unwrap();
+        engine.create_new_backup(&db).unwrap();
+        db.put(b"key2", b"v2").unwrap();
+        let id = engine.create_new_backup(&db).unwrap();
+        // The first SSTable is not uploaded again
+        assert_eq!(target.list(SHARED_PREFIX).unwrap().len(), 2);
+
+        let engine = BackupEngine::open_with_target(target.clone(), env::default_env()).unwrap();
+        engine.verify_backup(id).unwrap();
+        engine.restore_db_from_backup(id, temp_dir.path().join("restored")).unwrap();
+        let restored = DB::open(temp_dir.path().join("restored"), Options::default()).unwrap();
+        assert_eq!(restored.get(b"key2").unwrap(), Some(b"v2".to_vec()));
+
+        let key = target.list(SHARED_PREFIX).unwrap().remove(0);
+        target.delete(&key).unwrap();
+        assert!(matches!(engine.verify_backup(id), Err(Error::Corruption(_))));
+    }
+
+    #[test]
+    fn test_restore_to_point_in_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let options = Options::default().wal_archive_size_limit(1024 * 1024);
+        let db = DB::open(&db_path, options).unwrap();
+        let mut engine = BackupEngine::open(temp_dir.path().join("backups")).unwrap();
+
+        db.put(b"a", b"1").unwrap();
+        let id = engine.create_new_backup(&db).unwrap();
+        db.put(b"b", b"2").unwrap();
+        db.flush().unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"c", b"3");
+        batch.put(b"a", b"5");
+        db.write(batch).unwrap();
+        db.put(b"d", b"4").unwrap();
+
+        // Sequence 3 is inside the batch, so only sequence 2 is applied
+        let restored = temp_dir.path().join("at-3");
+        assert_eq!(engine.restore_to_point_in_time(id, &db_path, 3, &restored).unwrap(), 2);
+        let copy = DB::open(&restored, Options::default()).unwrap();
+        assert_eq!(copy.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(copy.get(b"b").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(copy.get(b"c").unwrap(), None);
+
+        let restored = temp_dir.path().join("at-4");
+        assert_eq!(engine.restore_to_point_in_time(id, &db_path, 4, &restored).unwrap(), 4);
+        let copy = DB::open(&restored, Options::default()).unwrap();
+        assert_eq!(copy.get(b"a").unwrap(), Some(b"5".to_vec()));
+        assert_eq!(copy.get(b"c").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(copy.get(b"d").unwrap(), None);
+
+        // A target past the end of the WAL restores everything
+        let restored = temp_dir.path().join("latest");
+        assert_eq!(engine.restore_to_point_in_time(id, &db_path, 100, &restored).unwrap(), 5);
+
+        // Nothing is left behind when the restore fails
+        let restored = temp_dir.path().join("before-backup");
+        assert!(matches!(
+            engine.restore_to_point_in_time(id, &db_path, 0, &restored),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(!restored.exists());
+        std::fs::create_dir(&restored).unwrap();
+        assert!(engine.restore_to_point_in_time(id, &db_path, 0, &restored).is_err());
+        assert_eq!(std::fs::read_dir(&restored).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_restore_to_point_in_time_without_wal() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let db = DB::open(&db_path, Options::default()).unwrap();
+        let mut engine = BackupEngine::open(temp_dir.path().join("backups")).unwrap();
+
+        db.put(b"a", b"1").unwrap();
+        let id = engine.create_new_backup(&db).unwrap();
+        // The WAL holding "b" is deleted by the flush
+        db.put(b"b", b"2").unwrap();
+        db.flush().unwrap();
+        db.put(b"c", b"3").unwrap();
+
+        let restored = temp_dir.path().join("restored");
+        assert!(matches!(
+            engine.restore_to_point_in_time(id, &db_path, 3, &restored),
+            Err(Error::NotFound(_))
+        ));
+        assert!(!restored.exists());
+    }
+}
@@ -0,0 +1,46 @@
+//! `aidb` command-line tool (requires the `cli` feature).
+//!
+//! ```text
+//! aidb shell <path>    Open an interactive shell on the database at <path>
+//! ```
+
+use aidb::shell::Shell;
+use aidb::{Options, DB};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const USAGE: &str = "usage: aidb shell <path>";
+
+fn main() -> ExitCode {
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let path = match args.as_slice() {
+        [command, path] if command == "shell" => PathBuf::from(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Exploring a typo'd path should not leave an empty database behind
+    let db = match DB::open(&path, Options::default().create_if_missing(false)) {
+        Ok(db) => Arc::new(db),
+        Err(e) => {
+            eprintln!("Failed to open {:?}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".aidb_history"));
+    let result = Shell::new(Arc::clone(&db)).run(history.as_deref());
+    let result = result.and_then(|()| db.close());
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,819 @@
+//! Streaming very large values in and out of the database.
+//!
+//! [`DB::put_reader`] copies a value from any [`Read`] into a blob file in
+//! the `blobs` directory of the database, 64KB at a time, and stores a
+//! small reference to it under the key. [`DB::get_writer`] streams the
+//! value back into any [`Write`]. Neither holds more than one buffer of the
+//! value in memory, and the WAL, MemTables and SSTables only ever see the
+//! reference.
+//!
+//! Other reads resolve references transparently: [`DB::get`],
+//! [`DB::get_entry`], snapshots and iterators return the whole value, read
+//! from the blob file. Appending to a streamed value is not supported and
+//! fails with [`Error::InvalidArgument`], as do writes whose value, or
+//! value after appends, would be read back as a reference.
+//!
+//! Overwriting or deleting a streamed key does not delete its blob file,
+//! since older versions may still be read. [`DB::delete_obsolete_blobs`]
+//! removes the blob files no version in the database refers to anymore.
+//! Checkpoints link the blob files, and backups share them between backups
+//! like SSTables.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::{Options, DB};
+//! use std::fs::File;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = DB::open("./data", Options::default())?;
+//!
+//! let file = File::open("video.mp4")?;
+//! let len = file.metadata()?.len();
+//! db.put_reader(b"video", file, len)?;
+//!
+//! let mut out = File::create("copy.mp4")?;
+//! db.get_writer(b"video", &mut out)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
+
+use crate::config::ReadOptions;
+use crate::env::Env;
+use crate::memtable::{MemTable, RetiredMemTable};
+use crate::statistics::Ticker;
+use crate::write_batch::WriteOp;
+use crate::{Error, KeyEntry, Result, WriteBatch, DB};
+
+/// Directory of the database holding the blob files
+pub(crate) const BLOB_DIR: &str = "blobs";
+
+/// Identifies a stored value as a blob reference
+const REFERENCE_MAGIC: &[u8; 8] = b"\0aidbblb";
+
+/// Magic, file number, size, value checksum and reference checksum
+const REFERENCE_SIZE: usize = 8 + 8 + 8 + 4 + 4;
+
+/// Bytes copied at a time
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Where a streamed value is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BlobReference {
+    file_number: u64,
+    size: u64,
+    /// CRC32 of the value
+    checksum: u32,
+}
+
+impl BlobReference {
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(REFERENCE_SIZE);
+        data.extend_from_slice(REFERENCE_MAGIC);
+        data.extend_from_slice(&self.file_number.to_le_bytes());
+        data.extend_from_slice(&self.size.to_le_bytes());
+        data.extend_from_slice(&self.checksum.to_le_bytes());
+        let crc = crc32fast::hash(&data);
+        data.extend_from_slice(&crc.to_le_bytes());
+        data
+    }
+
+    /// Length of the value
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// CRC32 of the value
+    pub(crate) fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    /// Path of the blob file in the database at `db_path`
+    pub(crate) fn path(&self, db_path: &Path) -> PathBuf {
+        blob_path(db_path, self.file_number)
+    }
+
+    /// The reference `value` holds, if it is one
+    pub(crate) fn decode(value: &[u8]) -> Option<Self> {
+        if value.len() != REFERENCE_SIZE || !value.starts_with(REFERENCE_MAGIC) {
+            return None;
+        }
+        let (body, crc) = value.split_at(REFERENCE_SIZE - 4);
+        if crc32fast::hash(body).to_le_bytes() != crc {
+            return None;
+        }
+        let u64_at = |offset: usize| {
+            u64::from_le_bytes(body[offset..offset + 8].try_into().expect("8 bytes"))
+        };
+        Some(Self {
+            file_number: u64_at(8),
+            size: u64_at(16),
+            checksum: u32::from_le_bytes(body[24..28].try_into().expect("4 bytes")),
+        })
+    }
+}
+
+/// Length of the value `value` stands for
+pub(crate) fn value_len(value: &[u8]) -> u64 {
+    BlobReference::decode(value).map_or(value.len() as u64, |reference| reference.size)
+}
+
+/// Fails if `value` would be read back as a blob reference, which only
+/// [`DB::put_reader`] may store
+pub(crate) fn check_value(key: &[u8], value: &[u8]) -> Result<()> {
+    if BlobReference::decode(value).is_some() {
+        return Err(Error::invalid_argument(format!(
+            "the value of {:?} is a blob reference, which only put_reader may store",
+            String::from_utf8_lossy(key)
+        )));
+    }
+    Ok(())
+}
+
+/// Fails if `value`, which appends to `key` made up, would be read back
+/// as a blob reference
+pub(crate) fn check_appended(key: &[u8], value: &[u8]) -> Result<()> {
+    if BlobReference::decode(value).is_some() {
+        return Err(Error::corruption(format!(
+            "appends to {:?} make up a blob reference",
+            String::from_utf8_lossy(key)
+        )));
+    }
+    Ok(())
+}
+
+/// Whether appends could turn `value` into a blob reference
+fn could_start_reference(value: &[u8]) -> bool {
+    !value.is_empty()
+        && value.len() < REFERENCE_SIZE
+        && (value.starts_with(REFERENCE_MAGIC) || REFERENCE_MAGIC.starts_with(value))
+}
+
+/// Held while a batch checked by [`DB::check_blob_writes`] is written
+pub(crate) enum BlobWriteGuard<'a> {
+    /// The batch appends, or puts values appends could complete
+    Shared { _guard: RwLockReadGuard<'a, ()> },
+    /// The batch appends suffixes short enough to complete a reference
+    Exclusive { _guard: RwLockWriteGuard<'a, ()> },
+}
+
+/// Path of blob file `number` of the database at `db_path`
+fn blob_path(db_path: &Path, number: u64) -> PathBuf {
+    db_path.join(BLOB_DIR).join(format!("{:06}.blob", number))
+}
+
+/// Blob files of the database at `db_path` and their numbers
+pub(crate) fn blob_files(env: &dyn Env, db_path: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let dir = db_path.join(BLOB_DIR);
+    if !env.file_exists(&dir) {
+        return Ok(Vec::new());
+    }
+    Ok(env
+        .get_children(&dir)?
+        .into_iter()
+        .filter_map(|name| {
+            let number = name.strip_suffix(".blob")?.parse::<u64>().ok()?;
+            Some((number, dir.join(name)))
+        })
+        .collect())
+}
+
+impl DB {
+    /// Stores a value of `len` bytes read from `reader` under `key`,
+    /// without holding the whole value in memory.
+    ///
+    /// The value is written to a blob file and synced before the key is
+    /// written; see the [module documentation](crate::blob). It is not
+    /// subject to `max_value_size`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `reader` ends before `len` bytes. The
+    /// key is left unchanged if any error occurs.
+    pub fn put_reader(&self, key: &[u8], reader: impl Read, len: u64) -> Result<()> {
+        self.options.read().check_entry_size(key, None)?;
+        self.check_background_error()?;
+
+        // Keeps delete_obsolete_blobs from removing the file before the
+        // reference is written
+        let _blobs = self.blob_lock.read();
+        let file_number = self.next_file_number.fetch_add(1, Ordering::SeqCst);
+        let path = blob_path(&self.path, file_number);
+
+        let result = self.write_blob(&path, reader, len).and_then(|checksum| {
+            let reference = BlobReference { file_number, size: len, checksum };
+            let _references = self.blob_reference_lock.write();
+            self.has_blobs.store(true, Ordering::SeqCst);
+            self.put_internal(key, &reference.encode())
+        });
+        if result.is_err() {
+            let _ = self.env.remove_file(&path);
+        }
+        result
+    }
+
+    /// Copies `len` bytes of `reader` to a new blob file at `path` and
+    /// returns their checksum
+    fn write_blob(&self, path: &Path, reader: impl Read, len: u64) -> Result<u32> {
+        let dir = self.path.join(BLOB_DIR);
+        self.env.create_dir_all(&dir)?;
+        let mut file = self.env.new_writable_file(path)?;
+
+        let mut reader = reader.take(len);
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0u8; STREAM_BUFFER_SIZE];
+        let mut written = 0u64;
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            hasher.update(&buf[..n]);
+            file.write_all(&buf[..n])?;
+            written += n as u64;
+        }
+        if written != len {
+            return Err(Error::invalid_argument(format!(
+                "reader ended after {} of {} bytes",
+                written, len
+            )));
+        }
+
+        file.sync()?;
+        self.env.sync_dir(&dir)?;
+        Ok(hasher.finalize())
+    }
+
+    /// Fails if `value` is a blob reference. Unless appends could not
+    /// complete it into one, the returned guard keeps appends from being
+    /// checked until the value is written.
+    pub(crate) fn check_blob_put(
+        &self,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<RwLockReadGuard<'_, ()>>> {
+        check_value(key, value)?;
+        Ok(could_start_reference(value).then(|| self.blob_reference_lock.read()))
+    }
+
+    /// Fails if a value in `batch` is a blob reference, an append in it
+    /// would extend a value stored with [`put_reader`](Self::put_reader),
+    /// or appends would make up a blob reference. Unless the batch has
+    /// neither appends nor values they could complete, the returned guard
+    /// keeps blob references and conflicting appends from being written
+    /// until the batch is.
+    pub(crate) fn check_blob_writes(
+        &self,
+        batch: &WriteBatch,
+    ) -> Result<Option<BlobWriteGuard<'_>>> {
+        let mut has_appends = false;
+        let mut short_appends = false;
+        let mut starts_reference = false;
+        for op in batch.iter() {
+            match op {
+                WriteOp::Put { key, value } => {
+                    check_value(key, value)?;
+                    starts_reference |= could_start_reference(value);
+                }
+                // An append to a missing key stores the suffix as it is
+                WriteOp::Append { key, suffix } => {
+                    check_value(key, suffix)?;
+                    has_appends = true;
+                    short_appends |= suffix.len() < REFERENCE_SIZE;
+                }
+                WriteOp::Delete { .. } => {}
+            }
+        }
+
+        // Appends short enough to complete a reference are checked and
+        // written one at a time, so none of them extends a value another
+        // one changed since it was checked
+        let guard = if short_appends {
+            BlobWriteGuard::Exclusive { _guard: self.blob_reference_lock.write() }
+        } else if has_appends || starts_reference {
+            BlobWriteGuard::Shared { _guard: self.blob_reference_lock.read() }
+        } else {
+            return Ok(None);
+        };
+        if !has_appends {
+            return Ok(Some(guard));
+        }
+
+        // The value of each appended key so far, starting from the one in
+        // the database. A longer suffix leaves the value too long to be a
+        // reference, so it stands in for the value it extends
+        let has_blobs = self.has_blobs.load(Ordering::SeqCst);
+        let sequence = self.sequence.load(Ordering::SeqCst);
+        let mut values: HashMap<&[u8], Cow<'_, [u8]>> = HashMap::new();
+        for op in batch.iter() {
+            match op {
+                WriteOp::Put { key, value } => {
+                    values.insert(key.as_slice(), Cow::Borrowed(value));
+                }
+                WriteOp::Delete { key } => {
+                    values.insert(key.as_slice(), Cow::Borrowed(&[]));
+                }
+                WriteOp::Append { key, suffix } => {
+                    let value = match values.remove(key.as_slice()) {
+                        Some(value) => value,
+                        None if has_blobs || suffix.len() < REFERENCE_SIZE => {
+                            let value = self
+                                .get_value_at_sequence(key, sequence, &ReadOptions::default())?
+                                .unwrap_or_default();
+                            if BlobReference::decode(&value).is_some() {
+                                return Err(Error::invalid_argument(format!(
+                                    "cannot append to {:?}, its value was stored with put_reader",
+                                    String::from_utf8_lossy(key)
+                                )));
+                            }
+                            Cow::Owned(value)
+                        }
+                        None => Cow::Borrowed(&[][..]),
+                    };
+                    let mut value = value.into_owned();
+                    value.extend_from_slice(suffix);
+                    if BlobReference::decode(&value).is_some() {
+                        return Err(Error::invalid_argument(format!(
+                            "appending to {:?} would make its value a blob reference",
+                            String::from_utf8_lossy(key)
+                        )));
+                    }
+                    values.insert(key.as_slice(), Cow::Owned(value));
+                }
+            }
+        }
+        Ok(Some(guard))
+    }
+
+    /// Writes the value of `key` to `writer` and returns its length, or
+    /// returns `None` if the key does not exist.
+    ///
+    /// Values stored with [`put_reader`](Self::put_reader) are streamed
+    /// from their blob file without holding them in memory; others are
+    /// written as they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Corruption` if the blob file is missing or does not match
+    /// its checksum. Since the value is checked as it is streamed, part of
+    /// it may have been written by then.
+    pub fn get_writer(&self, key: &[u8], mut writer: impl Write) -> Result<Option<u64>> {
+        let value = self.get_value_at_sequence(
+            key,
+            self.sequence.load(Ordering::SeqCst),
+            &ReadOptions::default(),
+        )?;
+        let Some(value) = value else {
+            return Ok(None);
+        };
+
+        let len = self.copy_value(&value, &mut writer)?;
+        writer.flush()?;
+
+        if let Some(ref stats) = self.statistics {
+            stats.record_tick(Ticker::KeysRead, 1);
+            stats.record_tick(Ticker::BytesRead, len);
+        }
+        Ok(Some(len))
+    }
+
+    /// Writes the value `value` stands for to `writer` and returns its
+    /// length: the blob it refers to, or `value` itself
+    pub(crate) fn copy_value(&self, value: &[u8], writer: &mut impl Write) -> Result<u64> {
+        match BlobReference::decode(value) {
+            Some(reference) => {
+                self.copy_blob(&reference, writer)?;
+                Ok(reference.size)
+            }
+            None => {
+                writer.write_all(value)?;
+                Ok(value.len() as u64)
+            }
+        }
+    }
+
+    /// Returns the value a blob reference points to, or `value` itself if it
+    /// is not a reference
+    pub(crate) fn resolve_blob(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        resolve_value(&*self.env, &self.path, value)
+    }
+
+    /// Like [`resolve_blob`](Self::resolve_blob), for the value of `entry`
+    pub(crate) fn resolve_blob_entry(&self, entry: KeyEntry) -> Result<KeyEntry> {
+        Ok(KeyEntry { value: self.resolve_blob(entry.value)?, ..entry })
+    }
+
+    fn copy_blob(&self, reference: &BlobReference, writer: &mut impl Write) -> Result<()> {
+        copy_blob(&*self.env, &self.path, reference, writer)
+    }
+
+    /// Remembers `memtable`, which is leaving the database, for as long as
+    /// iterators read it, so its blob references stay in use
+    pub(crate) fn retire_memtable(&self, memtable: &MemTable) {
+        let mut retired = self.retired_memtables.lock();
+        retired.retain(RetiredMemTable::is_alive);
+        retired.extend(memtable.retire());
+    }
+
+    /// Deletes the blob files that no version of any key refers to, and
+    /// returns how many were deleted.
+    ///
+    /// This reads every entry of the MemTables and SSTables, so it is as
+    /// expensive as a full scan. Versions that compactions have not dropped
+    /// yet keep their blob files, and so do the MemTables and SSTables that
+    /// flushes and compactions replaced while iterators still read them.
+    pub fn delete_obsolete_blobs(&self) -> Result<usize> {
+        self.check_writable()?;
+        let _blobs = self.blob_lock.write();
+        // Keeps MemTables and SSTables from moving between the lists read
+        let _compaction = self.compaction_lock.lock();
+        let _flush = self.flush_lock.lock();
+        self.delete_unreferenced_blobs()
+    }
+
+    /// [`delete_obsolete_blobs`](Self::delete_obsolete_blobs), for a caller
+    /// holding `blob_lock` exclusively and the compaction and flush locks
+    pub(crate) fn delete_unreferenced_blobs(&self) -> Result<usize> {
+        let files = blob_files(&*self.env, &self.path)?;
+        if files.is_empty() {
+            return Ok(0);
+        }
+
+        let mut referenced = HashSet::new();
+        let mut add = |value: &[u8]| {
+            if let Some(reference) = BlobReference::decode(value) {
+                referenced.insert(reference.file_number);
+            }
+        };
+        for entry in self.memtable.read().iter() {
+            add(entry.value());
+        }
+        for memtable in self.immutable_memtables.read().iter() {
+            for entry in memtable.iter() {
+                add(entry.value());
+            }
+        }
+        for memtable in self.retired_memtables.lock().iter() {
+            memtable.for_each_value(&mut add);
+        }
+        let mut tables: Vec<_> = self.sstables.read().iter().flatten().cloned().collect();
+        tables.extend(self.obsolete_files.lock().iter().filter_map(|file| file.table.upgrade()));
+        for table in tables {
+            let mut iter = table.iter_versions(&ReadOptions::default());
+            iter.seek_to_first()?;
+            iter.advance()?;
+            while iter.valid() {
+                add(iter.value());
+                iter.advance()?;
+            }
+        }
+
+        let mut deleted = 0;
+        for (number, path) in files {
+            if !referenced.contains(&number) {
+                self.env.remove_file(&path)?;
+                deleted += 1;
+            }
+        }
+        if deleted > 0 {
+            self.env.sync_dir(&self.path.join(BLOB_DIR))?;
+            log::info!("Deleted {} obsolete blob files", deleted);
+        }
+        Ok(deleted)
+    }
+}
+
+/// Returns the value a blob reference in the database at `db_path` points
+/// to, or `value` itself if it is not a reference
+pub(crate) fn resolve_value(env: &dyn Env, db_path: &Path, value: Vec<u8>) -> Result<Vec<u8>> {
+    match BlobReference::decode(&value) {
+        Some(reference) => {
+            let mut resolved = Vec::with_capacity(reference.size as usize);
+            copy_blob(env, db_path, &reference, &mut resolved)?;
+            Ok(resolved)
+        }
+        None => Ok(value),
+    }
+}
+
+/// Streams the blob `reference` points to into `writer`, checking its size
+/// and checksum
+fn copy_blob(
+    env: &dyn Env,
+    db_path: &Path,
+    reference: &BlobReference,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let path = reference.path(db_path);
+    let file = env.new_sequential_file(&path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => Error::corruption(format!("blob file {:?} is missing", path)),
+        _ => e.into(),
+    })?;
+
+    let mut reader = file.take(reference.size);
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0u8; STREAM_BUFFER_SIZE.min(reference.size as usize)];
+    let mut copied = 0u64;
+    while copied < reference.size {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        hasher.update(&buf[..n]);
+        writer.write_all(&buf[..n])?;
+        copied += n as u64;
+    }
+
+    if copied != reference.size || hasher.finalize() != reference.checksum {
+        return Err(Error::corruption(format!(
+            "blob file {:?} does not match its reference",
+            path
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watch::KeyEvent;
+    use crate::Options;
+    use std::io::Cursor;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// A reader of `len` bytes that never holds more than one buffer
+    struct Pattern {
+        remaining: u64,
+    }
+
+    impl Read for Pattern {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.remaining as usize);
+            for (i, byte) in buf[..n].iter_mut().enumerate() {
+                *byte = (self.remaining - i as u64) as u8;
+            }
+            self.remaining -= n as u64;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_put_reader_get_writer() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        let len = 3 * 1024 * 1024 + 17;
+        db.put_reader(b"big", Pattern { remaining: len }, len).unwrap();
+        let mut expected = Vec::new();
+        Pattern { remaining: len }.read_to_end(&mut expected).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(db.get_writer(b"big", &mut out).unwrap(), Some(len));
+        assert!(out == expected);
+        // Other reads resolve the reference
+        assert!(db.get(b"big").unwrap().unwrap() == expected);
+
+        // Plain values are written as they are
+        db.put(b"small", b"value").unwrap();
+        let mut out = Vec::new();
+        assert_eq!(db.get_writer(b"small", &mut out).unwrap(), Some(5));
+        assert_eq!(out, b"value");
+        assert_eq!(db.get_writer(b"missing", &mut out).unwrap(), None);
+
+        // A short reader leaves the key unchanged
+        let result = db.put_reader(b"small", Cursor::new(vec![1u8; 10]), 11);
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        assert_eq!(db.get(b"small").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(blob_files(&*db.env, temp_dir.path()).unwrap().len(), 1);
+
+        // Flushed references still resolve, and survive a reopen
+        db.flush().unwrap();
+        drop(db);
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        let mut iter = db.iter();
+        iter.seek(b"big");
+        assert!(iter.value() == &expected[..]);
+        drop(iter);
+
+        // Checkpoints take the blob files along
+        let checkpoint_dir = TempDir::new().unwrap();
+        let checkpoint = checkpoint_dir.path().join("checkpoint");
+        db.create_checkpoint(&checkpoint).unwrap();
+        drop(db);
+        let copy = DB::open(&checkpoint, Options::default()).unwrap();
+        assert_eq!(copy.get_writer(b"big", std::io::sink()).unwrap(), Some(len));
+    }
+
+    #[test]
+    fn test_delete_obsolete_blobs() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        db.put_reader(b"a", Cursor::new(b"first"), 5).unwrap();
+        db.put_reader(b"b", Cursor::new(b"kept"), 4).unwrap();
+        db.put_reader(b"a", Cursor::new(b"second"), 6).unwrap();
+        assert_eq!(db.delete_obsolete_blobs().unwrap(), 0);
+
+        // The flush drops the overwritten reference
+        db.flush().unwrap();
+        assert_eq!(db.delete_obsolete_blobs().unwrap(), 1);
+        assert_eq!(db.get(b"a").unwrap(), Some(b"second".to_vec()));
+        assert_eq!(db.get(b"b").unwrap(), Some(b"kept".to_vec()));
+
+        // A missing blob file is reported as corruption
+        let (_, path) = blob_files(&*db.env, temp_dir.path()).unwrap().remove(0);
+        std::fs::remove_file(path).unwrap();
+        let a = db.get(b"a");
+        let b = db.get(b"b");
+        assert!([a, b].iter().any(|r| matches!(r, Err(Error::Corruption(_)))));
+    }
+
+    #[test]
+    fn test_append_to_streamed_value_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.append(b"log", b"a").unwrap();
+        db.put_reader(b"big", Cursor::new(b"streamed"), 8).unwrap();
+
+        for flush in [false, true] {
+            if flush {
+                db.flush().unwrap();
+            }
+            let result = db.append(b"big", b"more");
+            assert!(matches!(result, Err(Error::InvalidArgument(_))));
+            let mut batch = WriteBatch::new();
+            batch.put(b"other", b"value");
+            batch.append(b"big", b"more");
+            assert!(matches!(db.write(batch), Err(Error::InvalidArgument(_))));
+            assert_eq!(db.get(b"big").unwrap(), Some(b"streamed".to_vec()));
+            assert_eq!(db.get(b"other").unwrap(), None);
+        }
+
+        // Other keys, and the key once overwritten, can be appended to
+        db.append(b"log", b"b").unwrap();
+        assert_eq!(db.get(b"log").unwrap(), Some(b"ab".to_vec()));
+        db.put(b"big", b"small").unwrap();
+        db.append(b"big", b"er").unwrap();
+        assert_eq!(db.get(b"big").unwrap(), Some(b"smaller".to_vec()));
+    }
+
+    #[test]
+    fn test_writes_cannot_store_blob_references() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.put_reader(b"big", Cursor::new(b"streamed"), 8).unwrap();
+        // The bytes put_reader stored, as a raw feed would have shown them
+        let reference =
+            BlobReference { file_number: 1, size: 8, checksum: crc32fast::hash(b"streamed") };
+        assert_eq!(BlobReference::decode(&reference.encode()), Some(reference));
+
+        let result = db.put(b"copy", &reference.encode());
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        let result = db.append(b"copy", &reference.encode());
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        let mut batch = WriteBatch::new();
+        batch.put(b"other", b"value");
+        batch.put(b"copy", &reference.encode());
+        assert!(matches!(db.write(batch), Err(Error::InvalidArgument(_))));
+        assert_eq!(db.get(b"copy").unwrap(), None);
+        assert_eq!(db.get(b"other").unwrap(), None);
+
+        let mut writer = crate::ingest::SstFileWriter::create(
+            temp_dir.path().join("external.sst"),
+            &Options::default(),
+        )
+        .unwrap();
+        let result = writer.put(b"copy", &reference.encode());
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+
+        // Values that only look alike are stored as they are
+        let mut value = reference.encode();
+        value[REFERENCE_SIZE - 1] ^= 1;
+        db.put(b"copy", &value).unwrap();
+        assert_eq!(db.get(b"copy").unwrap(), Some(value));
+    }
+
+    #[test]
+    fn test_appends_cannot_make_up_blob_references() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        let reference = BlobReference { file_number: 1, size: 4, checksum: 0 }.encode();
+
+        // Before any blob is stored, appends still look up short values
+        db.append(b"log", &reference[..8]).unwrap();
+        let result = db.append(b"log", &reference[8..]);
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        assert_eq!(db.get(b"log").unwrap(), Some(reference[..8].to_vec()));
+
+        db.put_reader(b"big", Cursor::new(b"blob"), 4).unwrap();
+        let reference =
+            BlobReference { file_number: 1, size: 4, checksum: crc32fast::hash(b"blob") }.encode();
+        db.put(b"forged", &reference[..16]).unwrap();
+        for flush in [false, true] {
+            if flush {
+                db.flush().unwrap();
+            }
+            let result = db.append(b"forged", &reference[16..]);
+            assert!(matches!(result, Err(Error::InvalidArgument(_))));
+            assert_eq!(db.get(b"forged").unwrap(), Some(reference[..16].to_vec()));
+        }
+
+        // Nor can a batch put part of a reference and append the rest
+        let mut batch = WriteBatch::new();
+        batch.put(b"other", b"value");
+        batch.put(b"batch", &reference[..20]);
+        batch.append(b"batch", &reference[20..]);
+        assert!(matches!(db.write(batch), Err(Error::InvalidArgument(_))));
+        assert_eq!(db.get(b"batch").unwrap(), None);
+        assert_eq!(db.get(b"other").unwrap(), None);
+
+        // Appends written around the check are not resolved, nor flushed
+        let sequence = db.sequence.load(Ordering::SeqCst);
+        db.memtable.read().put(b"forged", &reference[..16], sequence + 1);
+        db.memtable.read().append(b"forged", &reference[16..], sequence + 2);
+        db.sequence.store(sequence + 2, Ordering::SeqCst);
+        assert!(matches!(db.get(b"forged"), Err(Error::Corruption(_))));
+        let mut iter = db.iter();
+        iter.seek(b"forged");
+        assert!(matches!(iter.status(), Err(Error::Corruption(_))));
+        drop(iter);
+        assert!(db.flush().is_err());
+    }
+
+    #[test]
+    fn test_feeds_carry_streamed_values() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().wal_archive_size_limit(1024 * 1024);
+        let db = DB::open(temp_dir.path(), options).unwrap();
+        let events = db.watch(b"");
+
+        db.put_reader(b"big", Cursor::new(b"streamed"), 8).unwrap();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            KeyEvent::StreamedPut { sequence: 1, key: b"big".to_vec(), len: 8 }
+        );
+
+        let update = db.get_updates_since(1).unwrap().next().unwrap().unwrap();
+        let ops: Vec<_> = update.batch.iter().cloned().collect();
+        assert_eq!(ops, vec![WriteOp::Put { key: b"big".to_vec(), value: b"streamed".to_vec() }]);
+
+        // Once the blob file is gone the feed cannot return the value
+        db.put(b"big", b"small").unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.delete_obsolete_blobs().unwrap(), 1);
+        let result = db.get_updates_since(1).unwrap().next().unwrap();
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn test_delete_obsolete_blobs_keeps_pinned_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        let value_at = |iter: &mut crate::DBIterator, key: &[u8]| {
+            iter.seek(key);
+            iter.value().to_vec()
+        };
+
+        // An iterator holds a MemTable the flush replaces
+        db.put_reader(b"a", Cursor::new(b"first"), 5).unwrap();
+        let mut iter = db.iter();
+        db.put_reader(b"a", Cursor::new(b"second"), 6).unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.delete_obsolete_blobs().unwrap(), 0);
+        assert_eq!(value_at(&mut iter, b"a"), b"first");
+        drop(iter);
+        assert_eq!(db.delete_obsolete_blobs().unwrap(), 1);
+
+        // An iterator holds an SSTable a compaction replaces
+        db.put_reader(b"b", Cursor::new(b"old"), 3).unwrap();
+        db.flush().unwrap();
+        let mut iter = db.iter();
+        db.put_reader(b"b", Cursor::new(b"new"), 3).unwrap();
+        db.flush().unwrap();
+        let files: Vec<u64> = db.dump_lsm_state().unwrap().levels[0]
+            .files
+            .iter()
+            .map(|file| file.file_number.unwrap())
+            .collect();
+        db.compact_files(&files, 1).unwrap();
+        assert_eq!(db.delete_obsolete_blobs().unwrap(), 0);
+        assert_eq!(value_at(&mut iter, b"b"), b"old");
+        drop(iter);
+        assert_eq!(db.delete_obsolete_blobs().unwrap(), 1);
+        assert_eq!(db.get(b"a").unwrap(), Some(b"second".to_vec()));
+        assert_eq!(db.get(b"b").unwrap(), Some(b"new".to_vec()));
+    }
+}
@@ -0,0 +1,299 @@
+//! Named key spaces within one database.
+//!
+//! A [`Bucket`] (`db.bucket("users")`) is a view of the database whose keys
+//! are transparently prefixed with the bucket's name: reads and writes take
+//! and return keys without the prefix, and iterators only see the bucket's
+//! own keys. Buckets are cheap handles, not column families: they share the
+//! MemTable, WAL, SSTables and options of the database, and a bucket exists
+//! as long as it has keys.
+//!
+//! The prefix is the name followed by a zero byte, so no bucket's keys are
+//! a prefix range of another's (`"user"` and `"users"` stay apart). Keys
+//! written directly through the [`DB`] API that start with a bucket's
+//! prefix are part of that bucket.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = Arc::new(DB::open("./data", Options::default())?);
+//! let tenant = db.bucket("tenant-42")?;
+//!
+//! tenant.put(b"config", b"{}")?;
+//! let mut iter = tenant.iter()?;
+//! while iter.valid() {
+//!     println!("{:?} => {:?}", iter.key(), iter.value());
+//!     iter.next();
+//! }
+//!
+//! // Offboarding
+//! tenant.clear()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::iterator::prefix_successor;
+use crate::write_batch::WriteOp;
+use crate::{DBIterator, Error, Result, WriteBatch, DB};
+
+/// A view of the keys of a database under one name.
+///
+/// See the [module documentation](crate::bucket).
+#[derive(Clone)]
+pub struct Bucket {
+    db: Arc<DB>,
+    name: String,
+    /// The name and a zero byte
+    prefix: Vec<u8>,
+}
+
+impl fmt::Debug for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bucket").field("name", &self.name).finish()
+    }
+}
+
+impl DB {
+    /// Returns the bucket `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `name` is empty or contains a zero
+    /// byte.
+    pub fn bucket(self: &Arc<Self>, name: &str) -> Result<Bucket> {
+        if name.is_empty() || name.contains('\0') {
+            return Err(Error::invalid_argument(format!("invalid bucket name {:?}", name)));
+        }
+        let mut prefix = name.as_bytes().to_vec();
+        prefix.push(0);
+        Ok(Bucket { db: Arc::clone(self), name: name.to_string(), prefix })
+    }
+}
+
+impl Bucket {
+    /// The bucket's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The prefix of the bucket's keys in the database.
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Returns the underlying database.
+    pub fn db(&self) -> &Arc<DB> {
+        &self.db
+    }
+
+    fn db_key(&self, key: &[u8]) -> Vec<u8> {
+        [self.prefix.as_slice(), key].concat()
+    }
+
+    /// Inserts or replaces the value for `key`.
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.db.put(&self.db_key(key), value)
+    }
+
+    /// Returns the value for `key`, or `None` if it does not exist.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.db.get(&self.db_key(key))
+    }
+
+    /// Deletes `key`.
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        self.db.delete(&self.db_key(key))
+    }
+
+    /// Appends `suffix` to the value of `key`.
+    pub fn append(&self, key: &[u8], suffix: &[u8]) -> Result<()> {
+        self.db.append(&self.db_key(key), suffix)
+    }
+
+    /// Applies all operations in `batch` atomically, with its keys in this
+    /// bucket.
+    pub fn write(&self, batch: WriteBatch) -> Result<()> {
+        let mut prefixed = WriteBatch::new();
+        for op in batch.iter() {
+            match op {
+                WriteOp::Put { key, value } => prefixed.put(&self.db_key(key), value),
+                WriteOp::Delete { key } => prefixed.delete(&self.db_key(key)),
+                WriteOp::Append { key, suffix } => prefixed.append(&self.db_key(key), suffix),
+            }
+        }
+        self.db.write(prefixed)
+    }
+
+    /// Iterates over the bucket's entries in key order.
+    pub fn iter(&self) -> Result<BucketIterator> {
+        self.scan(None, None)
+    }
+
+    /// Iterates over the bucket's entries with `start <= key < end` in key
+    /// order. Either bound may be `None` for an open range.
+    pub fn scan(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<BucketIterator> {
+        let start = self.db_key(start.unwrap_or_default());
+        let end = match end {
+            Some(end) => Some(self.db_key(end)),
+            None => prefix_successor(&self.prefix),
+        };
+        let inner = self.db.scan(Some(&start), end.as_deref())?;
+        Ok(BucketIterator { inner, prefix: self.prefix.clone() })
+    }
+
+    /// Iterates over the bucket's entries whose key starts with `prefix`.
+    pub fn prefix_scan(&self, prefix: &[u8]) -> Result<BucketIterator> {
+        let inner = self.db.prefix_scan(&self.db_key(prefix))?;
+        Ok(BucketIterator { inner, prefix: self.prefix.clone() })
+    }
+
+    /// Deletes every key of the bucket.
+    ///
+    /// Uses [`DB::delete_prefix`], so it takes about the same time however
+    /// many keys the bucket has.
+    pub fn clear(&self) -> Result<()> {
+        self.db.delete_prefix(&self.prefix)
+    }
+}
+
+/// An iterator over the entries of a [`Bucket`], with keys relative to the
+/// bucket.
+///
+/// It moves like a [`DBIterator`] restricted to the bucket.
+pub struct BucketIterator {
+    inner: DBIterator,
+    prefix: Vec<u8>,
+}
+
+impl BucketIterator {
+    /// Returns true if the iterator is positioned at a valid entry.
+    pub fn valid(&self) -> bool {
+        self.inner.valid()
+    }
+
+    /// Returns the error that made the iterator invalid, if any.
+    pub fn status(&self) -> Result<()> {
+        self.inner.status()
+    }
+
+    /// Returns the key at the current position, without the bucket prefix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator is not valid.
+    pub fn key(&self) -> &[u8] {
+        &self.inner.key()[self.prefix.len()..]
+    }
+
+    /// Returns the value at the current position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator is not valid.
+    pub fn value(&self) -> &[u8] {
+        self.inner.value()
+    }
+
+    /// Moves to the next entry.
+    pub fn next(&mut self) {
+        self.inner.next()
+    }
+
+    /// Moves to the previous entry.
+    pub fn prev(&mut self) {
+        self.inner.prev()
+    }
+
+    /// Seeks to the first key that is greater than or equal to `target`.
+    pub fn seek(&mut self, target: &[u8]) {
+        self.inner.seek(&[self.prefix.as_slice(), target].concat())
+    }
+
+    /// Seeks to the last key that is less than or equal to `target`.
+    pub fn seek_for_prev(&mut self, target: &[u8]) {
+        self.inner.seek_for_prev(&[self.prefix.as_slice(), target].concat())
+    }
+
+    /// Seeks to the bucket's first key.
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first()
+    }
+
+    /// Seeks to the bucket's last key.
+    pub fn seek_to_last(&mut self) {
+        self.inner.seek_to_last()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use tempfile::TempDir;
+
+    fn keys(mut iter: BucketIterator) -> Vec<Vec<u8>> {
+        let mut keys = Vec::new();
+        while iter.valid() {
+            keys.push(iter.key().to_vec());
+            iter.next();
+        }
+        keys
+    }
+
+    #[test]
+    fn test_buckets_are_isolated() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        let user = db.bucket("user").unwrap();
+        let users = db.bucket("users").unwrap();
+        assert!(db.bucket("").is_err());
+        assert!(db.bucket("a\0b").is_err());
+
+        user.put(b"a", b"1").unwrap();
+        users.put(b"a", b"2").unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"b", b"3");
+        batch.put(b"c", b"4");
+        users.write(batch).unwrap();
+        db.put(b"usersz", b"outside").unwrap();
+
+        assert_eq!(user.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(users.get(b"a").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(db.get(b"users\0b").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(keys(user.iter().unwrap()), vec![b"a".to_vec()]);
+        assert_eq!(keys(users.iter().unwrap()), vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(keys(users.scan(Some(b"b"), None).unwrap()), vec![b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(keys(users.prefix_scan(b"c").unwrap()), vec![b"c".to_vec()]);
+
+        let mut iter = users.iter().unwrap();
+        iter.seek_to_last();
+        assert_eq!(iter.key(), b"c");
+        iter.seek_for_prev(b"bz");
+        assert_eq!(iter.key(), b"b");
+        iter.seek(b"bb");
+        assert_eq!(iter.key(), b"c");
+    }
+
+    #[test]
+    fn test_bucket_clear() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        let tenant = db.bucket("tenant").unwrap();
+        let other = db.bucket("other").unwrap();
+        for i in 0..2500u32 {
+            tenant.put(&i.to_be_bytes(), b"v").unwrap();
+        }
+        db.flush().unwrap();
+        other.put(b"key", b"v").unwrap();
+
+        tenant.clear().unwrap();
+        assert!(keys(tenant.iter().unwrap()).is_empty());
+        assert_eq!(other.get(b"key").unwrap(), Some(b"v".to_vec()));
+    }
+}
@@ -0,0 +1,335 @@
+//! Bulk export and import in CSV and JSON-lines formats.
+//!
+//! [`DB::export`] writes a key range to any [`Write`] and [`DB::import`]
+//! loads entries from any [`BufRead`], one record at a time, so files
+//! larger than memory can be moved in and out.
+//!
+//! Keys and values are written as text when they are valid UTF-8. Binary
+//! data, and text that itself starts with `base64:`, is written as
+//! `base64:` followed by standard base64 (the same convention as the
+//! `aidb shell` arguments).
+//!
+//! - [`DataFormat::Csv`]: a `key,value` header followed by one record per
+//!   entry, quoted as in RFC 4180 when a field contains a comma, quote or
+//!   line break
+//! - [`DataFormat::JsonLines`]: one `{"key":"...","value":"..."}` object per
+//!   line
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::bulk::DataFormat;
+//! use aidb::{Options, DB};
+//! use std::fs::File;
+//! use std::io::{BufReader, BufWriter};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = Arc::new(DB::open("./data", Options::default())?);
+//! let file = BufWriter::new(File::create("users.jsonl")?);
+//! db.export(Some(b"user:"), Some(b"user;"), file, DataFormat::JsonLines)?;
+//!
+//! let seed = Arc::new(DB::open("./seed", Options::default())?);
+//! seed.import(BufReader::new(File::open("users.jsonl")?), DataFormat::JsonLines)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+use crate::migrate::{self, ImportOptions, ImportReport};
+use crate::{Error, Result, DB};
+
+const BASE64_PREFIX: &str = "base64:";
+const CSV_HEADER: &str = "key,value";
+
+/// File format for [`DB::export`] and [`DB::import`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+    /// Comma-separated values with a `key,value` header
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+impl std::str::FromStr for DataFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(DataFormat::Csv),
+            "jsonl" | "json-lines" | "ndjson" => Ok(DataFormat::JsonLines),
+            _ => Err(Error::invalid_argument(format!("Unknown data format: {}", s))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonRecord {
+    key: String,
+    value: String,
+}
+
+/// Encodes bytes as text, falling back to base64
+fn encode_field(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.starts_with(BASE64_PREFIX) => text.to_string(),
+        _ => format!("{}{}", BASE64_PREFIX, BASE64.encode(bytes)),
+    }
+}
+
+fn decode_field(field: &str, record: u64) -> Result<Vec<u8>> {
+    match field.strip_prefix(BASE64_PREFIX) {
+        Some(data) => BASE64.decode(data).map_err(|e| {
+            Error::invalid_argument(format!("record {}: invalid base64: {}", record, e))
+        }),
+        None => Ok(field.as_bytes().to_vec()),
+    }
+}
+
+fn write_csv_field<W: Write>(writer: &mut W, field: &str) -> std::io::Result<()> {
+    if field.contains([',', '"', '\n', '\r']) {
+        write!(writer, "\"{}\"", field.replace('"', "\"\""))
+    } else {
+        writer.write_all(field.as_bytes())
+    }
+}
+
+/// Splits one CSV record into fields; None if a quoted field is still open
+fn parse_csv_record(record: &str) -> Option<Vec<String>> {
+    let mut fields = vec![String::new()];
+    let mut chars = record.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().expect("at least one field");
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => fields.push(String::new()),
+            (false, c) => field.push(c),
+        }
+    }
+    (!quoted).then_some(fields)
+}
+
+/// Reads CSV records, joining lines of quoted fields that contain line breaks
+struct CsvRecords<R> {
+    reader: R,
+    record: u64,
+}
+
+impl<R: BufRead> Iterator for CsvRecords<R> {
+    type Item = Result<(u64, Vec<String>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut text = String::new();
+        loop {
+            match self.reader.read_line(&mut text) {
+                Ok(0) if text.is_empty() => return None,
+                Ok(0) => {
+                    let msg = format!("record {}: unterminated quoted field", self.record + 1);
+                    return Some(Err(Error::invalid_argument(msg)));
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+            let line = text.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                text.clear();
+                continue;
+            }
+            if let Some(fields) = parse_csv_record(line) {
+                self.record += 1;
+                return Some(Ok((self.record, fields)));
+            }
+        }
+    }
+}
+
+impl DB {
+    /// Writes all entries with `start <= key < end` to `writer` in `format`,
+    /// in key order, and returns the number of entries written.
+    ///
+    /// Entries are read one at a time by the underlying
+    /// [`DBIterator`](crate::DBIterator), so the range is never held in memory.
+    pub fn export<W: Write>(
+        self: &Arc<Self>,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        mut writer: W,
+        format: DataFormat,
+    ) -> Result<u64> {
+        let mut iter = self.scan(start, end)?;
+        let mut count = 0;
+        if format == DataFormat::Csv {
+            writeln!(writer, "{}", CSV_HEADER)?;
+        }
+        while iter.valid() {
+            let (key, value) = (encode_field(iter.key()), encode_field(iter.value()));
+            match format {
+                DataFormat::Csv => {
+                    write_csv_field(&mut writer, &key)?;
+                    writer.write_all(b",")?;
+                    write_csv_field(&mut writer, &value)?;
+                    writer.write_all(b"\n")?;
+                }
+                DataFormat::JsonLines => {
+                    serde_json::to_writer(&mut writer, &JsonRecord { key, value })
+                        .map_err(|e| Error::Serialization(e.to_string()))?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+            count += 1;
+            iter.next();
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Loads entries from `reader` in `format`, as written by
+    /// [`export`](Self::export), and returns an [`ImportReport`].
+    ///
+    /// Entries are written in batches as they are read. A CSV header line
+    /// `key,value` is skipped; blank lines are ignored. A malformed record
+    /// fails the import with [`Error::InvalidArgument`] naming the record
+    /// number; batches written before it stay in the database. Entries with
+    /// an empty value are skipped, as in [`migrate::import_entries`].
+    pub fn import<R: BufRead>(&self, reader: R, format: DataFormat) -> Result<ImportReport> {
+        let options = ImportOptions::default();
+        match format {
+            DataFormat::Csv => {
+                let records = CsvRecords { reader, record: 0 }.filter_map(|record| {
+                    let (n, fields) = match record {
+                        Ok(record) => record,
+                        Err(e) => return Some(Err(e)),
+                    };
+                    match fields.as_slice() {
+                        [key, value] if n == 1 && key == "key" && value == "value" => None,
+                        [key, value] => Some(
+                            decode_field(key, n).and_then(|key| Ok((key, decode_field(value, n)?))),
+                        ),
+                        _ => Some(Err(Error::invalid_argument(format!(
+                            "record {}: expected 2 fields, found {}",
+                            n,
+                            fields.len()
+                        )))),
+                    }
+                });
+                migrate::import_entries(self, records, &options)
+            }
+            DataFormat::JsonLines => {
+                let records =
+                    (1u64..).zip(reader.lines()).filter_map(|(n, line)| {
+                        let line = match line {
+                            Ok(line) if line.trim().is_empty() => return None,
+                            Ok(line) => line,
+                            Err(e) => return Some(Err(e.into())),
+                        };
+                        let record = serde_json::from_str::<JsonRecord>(&line)
+                            .map_err(|e| Error::invalid_argument(format!("record {}: {}", n, e)));
+                        Some(record.and_then(|r| {
+                            Ok((decode_field(&r.key, n)?, decode_field(&r.value, n)?))
+                        }))
+                    });
+                migrate::import_entries(self, records, &options)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use tempfile::TempDir;
+
+    fn open(dir: &TempDir) -> Arc<DB> {
+        Arc::new(DB::open(dir.path(), Options::default()).unwrap())
+    }
+
+    fn sample_entries() -> Vec<(Vec<u8>, Vec<u8>)> {
+        vec![
+            (b"a".to_vec(), b"plain".to_vec()),
+            (b"b".to_vec(), b"comma, \"quote\"\nnewline".to_vec()),
+            (b"c".to_vec(), b"base64:looks encoded".to_vec()),
+            (vec![0xff, 0x00], vec![0x80, 0x81]),
+        ]
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        for format in [DataFormat::Csv, DataFormat::JsonLines] {
+            let src_dir = TempDir::new().unwrap();
+            let src = open(&src_dir);
+            for (key, value) in sample_entries() {
+                src.put(&key, &value).unwrap();
+            }
+
+            let mut out = Vec::new();
+            assert_eq!(src.export(None, None, &mut out, format).unwrap(), 4);
+
+            let dst_dir = TempDir::new().unwrap();
+            let dst = open(&dst_dir);
+            let report = dst.import(out.as_slice(), format).unwrap();
+            assert_eq!(report.entries_imported, 4, "{:?}", format);
+            for (key, value) in sample_entries() {
+                assert_eq!(dst.get(&key).unwrap(), Some(value), "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn test_export_format() {
+        let dir = TempDir::new().unwrap();
+        let db = open(&dir);
+        for (key, value) in sample_entries() {
+            db.put(&key, &value).unwrap();
+        }
+
+        let mut csv = Vec::new();
+        db.export(Some(b"a"), Some(b"c"), &mut csv, DataFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "key,value\na,plain\nb,\"comma, \"\"quote\"\"\nnewline\"\n"
+        );
+
+        let mut jsonl = Vec::new();
+        db.export(Some(b"c"), None, &mut jsonl, DataFormat::JsonLines).unwrap();
+        assert_eq!(
+            String::from_utf8(jsonl).unwrap(),
+            "{\"key\":\"c\",\"value\":\"base64:YmFzZTY0Omxvb2tzIGVuY29kZWQ=\"}\n\
+             {\"key\":\"base64:/wA=\",\"value\":\"base64:gIE=\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = TempDir::new().unwrap();
+        let db = open(&dir);
+
+        let err = db.import(&b"key,value\nx,1\ny,2,3\n"[..], DataFormat::Csv).unwrap_err();
+        assert!(err.to_string().contains("record 3"), "{}", err);
+        assert_eq!(db.get(b"x").unwrap(), None, "failed batch is not written");
+
+        let err = db.import(&b"a,\"unterminated\n"[..], DataFormat::Csv).unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)));
+
+        let err = db.import(&b"{\"key\":\"a\"}\n"[..], DataFormat::JsonLines).unwrap_err();
+        assert!(err.to_string().contains("record 1"), "{}", err);
+
+        let err = db.import(&b"a,base64:***\n"[..], DataFormat::Csv).unwrap_err();
+        assert!(err.to_string().contains("invalid base64"), "{}", err);
+
+        assert!("parquet".parse::<DataFormat>().is_err());
+        assert_eq!("JSONL".parse::<DataFormat>().unwrap(), DataFormat::JsonLines);
+    }
+}
@@ -3,10 +3,12 @@
 //! This module provides a thread-safe LRU cache specifically designed for
 //! caching SSTable data blocks.
 
+use crate::statistics::{Statistics, Ticker};
 use bytes::Bytes;
 use parking_lot::RwLock;
 use std::collections::{HashMap, VecDeque};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 /// A unique identifier for a cached block.
 ///
@@ -74,7 +76,7 @@ impl CacheStats {
 #[derive(Debug)]
 pub struct BlockCache {
     /// Maximum cache capacity in bytes
-    capacity: usize,
+    capacity: AtomicUsize,
     /// Current cache size in bytes
     current_size: AtomicU64,
     /// Cache entries stored by key
@@ -83,6 +85,8 @@ pub struct BlockCache {
     lru_queue: RwLock<VecDeque<CacheKey>>,
     /// Cache statistics
     stats: RwLock<CacheStats>,
+    /// Database statistics that also receive hit and miss counts
+    statistics: Option<Arc<Statistics>>,
 }
 
 impl BlockCache {
@@ -102,14 +106,21 @@ impl BlockCache {
     /// ```
     pub fn new(capacity: usize) -> Self {
         Self {
-            capacity,
+            capacity: AtomicUsize::new(capacity),
             current_size: AtomicU64::new(0),
             cache: RwLock::new(HashMap::new()),
             lru_queue: RwLock::new(VecDeque::new()),
             stats: RwLock::new(CacheStats::default()),
+            statistics: None,
         }
     }
 
+    /// Also count hits and misses in `statistics`.
+    pub fn with_statistics(mut self, statistics: Option<Arc<Statistics>>) -> Self {
+        self.statistics = statistics;
+        self
+    }
+
     /// Get a block from the cache.
     ///
     /// Returns `Some(block)` if the block is in the cache (cache hit),
@@ -125,7 +136,7 @@ impl BlockCache {
         }
 
         // Check if disabled
-        if self.capacity == 0 {
+        if self.capacity() == 0 {
             return None;
         }
 
@@ -144,6 +155,9 @@ impl BlockCache {
                 let mut stats = self.stats.write();
                 stats.hits += 1;
             }
+            if let Some(ref statistics) = self.statistics {
+                statistics.record_tick(Ticker::BlockCacheHit, 1);
+            }
 
             Some(result)
         } else {
@@ -153,6 +167,9 @@ impl BlockCache {
                 let mut stats = self.stats.write();
                 stats.misses += 1;
             }
+            if let Some(ref statistics) = self.statistics {
+                statistics.record_tick(Ticker::BlockCacheMiss, 1);
+            }
             None
         }
     }
@@ -162,21 +179,25 @@ impl BlockCache {
     /// If the cache is at capacity, evicts the least recently used blocks
     /// to make room for the new entry.
     pub fn insert(&self, key: CacheKey, value: Bytes) {
+        let capacity = self.capacity();
+
         // Check if disabled
-        if self.capacity == 0 {
+        if capacity == 0 {
             return;
         }
 
         let value_size = value.len();
 
         // Don't cache blocks larger than capacity
-        if value_size > self.capacity {
+        if value_size > capacity {
             return;
         }
 
         // Evict until we have space
-        while self.current_size.load(Ordering::Relaxed) as usize + value_size > self.capacity {
-            self.evict_one();
+        while self.current_size.load(Ordering::Relaxed) as usize + value_size > capacity {
+            if !self.evict_one() {
+                break;
+            }
         }
 
         // Insert into cache
@@ -231,26 +252,31 @@ impl BlockCache {
     }
 
     /// Evict the least recently used entry from the cache.
-    fn evict_one(&self) {
+    ///
+    /// Returns `false` if there was nothing left to evict.
+    fn evict_one(&self) -> bool {
         let mut lru_queue = self.lru_queue.write();
 
-        if let Some(key) = lru_queue.pop_front() {
-            drop(lru_queue); // Release lock before acquiring cache lock
-
-            let mut cache = self.cache.write();
-            if let Some(value) = cache.remove(&key) {
-                // Update size
-                let size = value.len();
-                self.current_size.fetch_sub(size as u64, Ordering::Relaxed);
-
-                // Update stats
-                drop(cache);
-                {
-                    let mut stats = self.stats.write();
-                    stats.evictions += 1;
-                }
+        let Some(key) = lru_queue.pop_front() else {
+            return false;
+        };
+        drop(lru_queue); // Release lock before acquiring cache lock
+
+        let mut cache = self.cache.write();
+        if let Some(value) = cache.remove(&key) {
+            // Update size
+            let size = value.len();
+            self.current_size.fetch_sub(size as u64, Ordering::Relaxed);
+
+            // Update stats
+            drop(cache);
+            {
+                let mut stats = self.stats.write();
+                stats.evictions += 1;
             }
         }
+
+        true
     }
 
     /// Get current cache statistics.
@@ -281,7 +307,20 @@ impl BlockCache {
 
     /// Get the cache capacity in bytes.
     pub fn capacity(&self) -> usize {
-        self.capacity
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Change the cache capacity.
+    ///
+    /// Shrinking the cache evicts least recently used blocks until the cached
+    /// data fits; a capacity of 0 disables caching and empties the cache.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        while self.size() > capacity {
+            if !self.evict_one() {
+                break;
+            }
+        }
     }
 
     /// Get the number of entries in the cache.
@@ -426,6 +465,26 @@ mod tests {
         assert_eq!(cache.get(&key2), None);
     }
 
+    #[test]
+    fn test_set_capacity_evicts() {
+        let cache = BlockCache::new(100);
+        for i in 0..5 {
+            cache.insert(CacheKey::new(1, i), Bytes::from(vec![0u8; 20]));
+        }
+        assert_eq!(cache.size(), 100);
+
+        cache.set_capacity(40);
+        assert_eq!(cache.capacity(), 40);
+        assert_eq!(cache.size(), 40);
+        // The most recently inserted blocks survive
+        assert!(cache.get(&CacheKey::new(1, 4)).is_some());
+        assert!(cache.get(&CacheKey::new(1, 0)).is_none());
+
+        cache.set_capacity(200);
+        cache.insert(CacheKey::new(1, 9), Bytes::from(vec![0u8; 100]));
+        assert_eq!(cache.size(), 140);
+    }
+
     #[test]
     fn test_cache_disabled_when_capacity_zero() {
         let cache = BlockCache::new(0);
@@ -1,8 +1,11 @@
 //! Block cache implementation for SSTable data blocks.
 //!
 //! Provides an LRU (Least Recently Used) cache to speed up repeated reads
-//! of the same data blocks from SSTables.
+//! of the same data blocks from SSTables, and a [`TableCache`] bounding the
+//! number of open SSTable files.
 
 mod lru;
+mod table_cache;
 
 pub use lru::{BlockCache, CacheKey, CacheStats};
+pub use table_cache::TableCache;
@@ -0,0 +1,169 @@
+//! Cache of open SSTable file handles.
+//!
+//! Each SSTable reader keeps its index and Bloom filter in memory but, when
+//! a [`TableCache`] is attached, only borrows its file handle from the
+//! cache. The cache keeps at most `capacity` files open and closes the
+//! least recently used one when another has to be opened; a table whose
+//! handle was closed reopens it on its next read. Handles held by running
+//! iterators stay open until the iterator is dropped.
+
+use crate::env::{Env, RandomAccessFile};
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+struct OpenFile {
+    file: Arc<dyn RandomAccessFile>,
+    /// Position in the LRU order
+    last_use: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    files: HashMap<u64, OpenFile>,
+    /// File ids by last use, least recent first
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+    opens: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, file_id: u64) {
+        self.clock += 1;
+        if let Some(open) = self.files.get_mut(&file_id) {
+            self.lru.remove(&open.last_use);
+            open.last_use = self.clock;
+            self.lru.insert(self.clock, file_id);
+        }
+    }
+
+    fn insert(&mut self, file_id: u64, file: Arc<dyn RandomAccessFile>, capacity: usize) {
+        self.clock += 1;
+        if let Some(old) = self.files.insert(file_id, OpenFile { file, last_use: self.clock }) {
+            self.lru.remove(&old.last_use);
+        }
+        self.lru.insert(self.clock, file_id);
+        while self.files.len() > capacity {
+            let Some((_, evicted)) = self.lru.pop_first() else {
+                break;
+            };
+            self.files.remove(&evicted);
+        }
+    }
+}
+
+/// Keeps a bounded number of SSTable files open.
+///
+/// See the [module documentation](self).
+pub struct TableCache {
+    env: Arc<dyn Env>,
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl fmt::Debug for TableCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TableCache")
+            .field("capacity", &self.capacity)
+            .field("open_files", &self.len())
+            .finish()
+    }
+}
+
+impl TableCache {
+    /// Creates a cache that keeps up to `capacity` (at least 1) files open,
+    /// opening them through `env`.
+    pub fn new(env: Arc<dyn Env>, capacity: usize) -> Self {
+        Self { env, capacity: capacity.max(1), state: Mutex::new(CacheState::default()) }
+    }
+
+    /// Maximum number of open files.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of files currently open.
+    pub fn len(&self) -> usize {
+        self.state.lock().files.len()
+    }
+
+    /// Whether no file is open.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of files opened because they were not in the cache.
+    pub fn opens(&self) -> u64 {
+        self.state.lock().opens
+    }
+
+    /// Returns the handle of the file `file_id` at `path`, opening it if it
+    /// is not open.
+    pub fn get(&self, file_id: u64, path: &Path) -> io::Result<Arc<dyn RandomAccessFile>> {
+        {
+            let mut state = self.state.lock();
+            if let Some(open) = state.files.get(&file_id) {
+                let file = Arc::clone(&open.file);
+                state.touch(file_id);
+                return Ok(file);
+            }
+        }
+
+        // Opened without the lock; a concurrent open of the same file just
+        // replaces the other handle
+        let file = self.env.new_random_access_file(path)?;
+        let mut state = self.state.lock();
+        state.opens += 1;
+        state.insert(file_id, Arc::clone(&file), self.capacity);
+        Ok(file)
+    }
+
+    /// Adds a handle opened elsewhere, e.g. while the table was loaded.
+    pub fn insert(&self, file_id: u64, file: Arc<dyn RandomAccessFile>) {
+        self.state.lock().insert(file_id, file, self.capacity);
+    }
+
+    /// Closes the file `file_id`, e.g. because its table was dropped.
+    pub fn erase(&self, file_id: u64) {
+        let mut state = self.state.lock();
+        if let Some(open) = state.files.remove(&file_id) {
+            state.lru.remove(&open.last_use);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::MemEnv;
+
+    #[test]
+    fn test_table_cache_evicts_least_recently_used() {
+        let env: Arc<dyn Env> = Arc::new(MemEnv::new());
+        for i in 0..3 {
+            env.write_file(Path::new(&format!("/{}.sst", i)), b"data").unwrap();
+        }
+        let cache = TableCache::new(Arc::clone(&env), 2);
+
+        cache.get(0, Path::new("/0.sst")).unwrap();
+        cache.get(1, Path::new("/1.sst")).unwrap();
+        cache.get(0, Path::new("/0.sst")).unwrap();
+        assert_eq!((cache.len(), cache.opens()), (2, 2));
+
+        // File 1 is the least recently used
+        cache.get(2, Path::new("/2.sst")).unwrap();
+        assert_eq!(cache.len(), 2);
+        cache.get(0, Path::new("/0.sst")).unwrap();
+        assert_eq!(cache.opens(), 3);
+        let mut buf = [0u8; 4];
+        cache.get(1, Path::new("/1.sst")).unwrap().read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"data");
+        assert_eq!(cache.opens(), 4);
+
+        cache.erase(1);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,315 @@
+//! Durable, resumable change feeds.
+//!
+//! A [`Changefeed`] is a named consumer of the database's writes. It reads
+//! the write batches committed after its position in sequence order (see
+//! [`DB::get_updates_since`]) and the consumer acknowledges them once they
+//! are processed. Acknowledged positions are stored in the `CHANGEFEEDS`
+//! file of the database directory, so after a restart, or downtime of the
+//! downstream system, [`DB::changefeed`] with the same name resumes where
+//! the consumer left off instead of requiring a full rescan.
+//!
+//! While any change feed exists, rotated WAL files are archived (see
+//! [`Options::wal_archive_size_limit`](crate::Options)) and an archived file
+//! holding writes some feed has not acknowledged is never deleted, even
+//! over the size limit. A consumer that is gone for good must be removed
+//! with [`DB::drop_changefeed`], or the archive grows without bound.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = Arc::new(DB::open("./data", Options::default())?);
+//! let mut feed = db.changefeed("search-index")?;
+//!
+//! loop {
+//!     let updates = feed.poll(100)?;
+//!     let Some(last) = updates.last() else { break };
+//!     let end = last.sequence + last.batch.len() as u64 - 1;
+//!     for update in &updates {
+//!         // Apply update.batch to the search index
+//!     }
+//!     feed.acknowledge(end)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::env::Env;
+use crate::transaction_log::BatchResult;
+use crate::{Error, Result, DB};
+
+/// File in the database directory holding the acknowledged positions
+pub const CHANGEFEEDS_FILE: &str = "CHANGEFEEDS";
+
+/// Acknowledged positions of the change feeds of a database
+pub(crate) struct Changefeeds {
+    positions: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Changefeeds {
+    /// Loads the positions stored in `db_path`
+    pub(crate) fn load(env: &dyn Env, db_path: &Path) -> Result<Self> {
+        let path = db_path.join(CHANGEFEEDS_FILE);
+        let positions = if env.file_exists(&path) {
+            serde_json::from_slice(&env.read_file(&path)?).map_err(|e| {
+                Error::corruption(format!("invalid {} file: {}", CHANGEFEEDS_FILE, e))
+            })?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self { positions: Mutex::new(positions) })
+    }
+
+    /// The lowest acknowledged position, if there are change feeds
+    pub(crate) fn min_position(&self) -> Option<u64> {
+        self.positions.lock().values().copied().min()
+    }
+
+    /// Applies `update` to the positions and stores them
+    fn update(
+        &self,
+        env: &dyn Env,
+        db_path: &Path,
+        update: impl FnOnce(&mut BTreeMap<String, u64>),
+    ) -> Result<()> {
+        let mut positions = self.positions.lock();
+        let mut updated = positions.clone();
+        update(&mut updated);
+
+        let data = serde_json::to_vec_pretty(&updated).map_err(|e| {
+            Error::internal(format!("failed to serialize change feed positions: {}", e))
+        })?;
+        let path = db_path.join(CHANGEFEEDS_FILE);
+        let tmp_path = path.with_extension("tmp");
+        env.write_file(&tmp_path, &data)?;
+        env.rename_file(&tmp_path, &path)?;
+        env.sync_dir(db_path)?;
+
+        *positions = updated;
+        Ok(())
+    }
+}
+
+/// A named, resumable reader of the database's writes.
+///
+/// Created by [`DB::changefeed`]. See the
+/// [module documentation](crate::changefeed).
+pub struct Changefeed {
+    db: Arc<DB>,
+    name: String,
+    /// Last sequence number returned by `poll`
+    read_position: u64,
+}
+
+impl DB {
+    /// Opens the change feed `name`, creating it if it does not exist.
+    ///
+    /// A new feed starts at the current sequence number, so it returns the
+    /// writes made from now on. An existing one resumes after its last
+    /// acknowledged position.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidState` if the WAL is disabled, and `InvalidArgument`
+    /// for an empty name.
+    pub fn changefeed(self: &Arc<Self>, name: &str) -> Result<Changefeed> {
+        if !self.options.read().use_wal {
+            return Err(Error::InvalidState("change feeds need the WAL".to_string()));
+        }
+        if name.is_empty() {
+            return Err(Error::invalid_argument("change feed name is empty"));
+        }
+
+        let existing = self.changefeeds.positions.lock().get(name).copied();
+        let position = match existing {
+            Some(position) => position,
+            None => {
+                let position = self.sequence.load(Ordering::SeqCst);
+                self.changefeeds.update(&*self.env, &self.path, |positions| {
+                    positions.entry(name.to_string()).or_insert(position);
+                })?;
+                log::info!("Created change feed {:?} at sequence {}", name, position);
+                self.changefeeds.positions.lock()[name]
+            }
+        };
+
+        Ok(Changefeed { db: Arc::clone(self), name: name.to_string(), read_position: position })
+    }
+
+    /// Removes the change feed `name`, releasing the archived WAL files it
+    /// kept. Does nothing if it does not exist.
+    pub fn drop_changefeed(&self, name: &str) -> Result<()> {
+        if !self.changefeeds.positions.lock().contains_key(name) {
+            return Ok(());
+        }
+        self.changefeeds.update(&*self.env, &self.path, |positions| {
+            positions.remove(name);
+        })?;
+        log::info!("Dropped change feed {:?}", name);
+        Ok(())
+    }
+
+    /// Returns the change feeds and their acknowledged positions, by name.
+    pub fn list_changefeeds(&self) -> Vec<(String, u64)> {
+        self.changefeeds
+            .positions
+            .lock()
+            .iter()
+            .map(|(name, &p)| (name.clone(), p))
+            .collect()
+    }
+}
+
+impl Changefeed {
+    /// The feed's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The last acknowledged sequence number.
+    pub fn acknowledged(&self) -> u64 {
+        self.db.changefeeds.positions.lock().get(&self.name).copied().unwrap_or(0)
+    }
+
+    /// Returns up to `max_batches` write batches committed after those
+    /// already returned, oldest first. Empty if there are none yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if the WAL holding the next batch was deleted,
+    /// e.g. because the feed was dropped meanwhile.
+    pub fn poll(&mut self, max_batches: usize) -> Result<Vec<BatchResult>> {
+        let mut updates = Vec::new();
+        for update in self.db.get_updates_since(self.read_position + 1)? {
+            if updates.len() >= max_batches {
+                break;
+            }
+            let update = update?;
+            self.read_position = update.sequence + update.batch.len() as u64 - 1;
+            updates.push(update);
+        }
+        Ok(updates)
+    }
+
+    /// Records that the writes up to and including `sequence` have been
+    /// processed; a reopened feed resumes after them.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `sequence` is beyond what `poll`
+    /// returned, and `NotFound` if the feed was dropped.
+    pub fn acknowledge(&mut self, sequence: u64) -> Result<()> {
+        if sequence > self.read_position {
+            return Err(Error::invalid_argument(format!(
+                "cannot acknowledge sequence {} of change feed {:?}, only {} was read",
+                sequence, self.name, self.read_position
+            )));
+        }
+        let mut found = false;
+        self.db.changefeeds.update(&*self.db.env, &self.db.path, |positions| {
+            if let Some(position) = positions.get_mut(&self.name) {
+                *position = (*position).max(sequence);
+                found = true;
+            }
+        })?;
+        if !found {
+            return Err(Error::not_found(format!("change feed {:?}", self.name)));
+        }
+        Ok(())
+    }
+
+    /// Makes `poll` return the unacknowledged batches again, e.g. after
+    /// the consumer failed to process them.
+    pub fn rewind(&mut self) {
+        self.read_position = self.acknowledged();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, WriteBatch};
+    use tempfile::TempDir;
+
+    fn sequences(updates: &[BatchResult]) -> Vec<u64> {
+        updates.iter().map(|update| update.sequence).collect()
+    }
+
+    #[test]
+    fn test_changefeed_resumes_after_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        db.put(b"before", b"v").unwrap();
+
+        let mut feed = db.changefeed("index").unwrap();
+        assert_eq!(feed.acknowledged(), 1);
+        db.put(b"a", b"1").unwrap();
+        db.flush().unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"b", b"2");
+        batch.delete(b"a");
+        db.write(batch).unwrap();
+        db.flush().unwrap();
+        db.put(b"c", b"3").unwrap();
+
+        assert_eq!(sequences(&feed.poll(2).unwrap()), vec![2, 3]);
+        feed.acknowledge(2).unwrap();
+        assert!(feed.acknowledge(10).is_err());
+        assert_eq!(sequences(&feed.poll(10).unwrap()), vec![5]);
+        assert!(feed.poll(10).unwrap().is_empty());
+
+        // Unacknowledged batches are returned again
+        feed.rewind();
+        assert_eq!(sequences(&feed.poll(10).unwrap()), vec![3, 5]);
+        feed.acknowledge(4).unwrap();
+        drop(feed);
+        drop(db);
+
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        assert_eq!(db.list_changefeeds(), vec![("index".to_string(), 4)]);
+        let mut feed = db.changefeed("index").unwrap();
+        assert_eq!(sequences(&feed.poll(10).unwrap()), vec![5]);
+    }
+
+    #[test]
+    fn test_changefeed_pins_archived_wal() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        let mut slow = db.changefeed("slow").unwrap();
+        let mut fast = db.changefeed("fast").unwrap();
+
+        for i in 0..5u8 {
+            db.put(&[i], b"v").unwrap();
+            db.flush().unwrap();
+        }
+        assert_eq!(fast.poll(10).unwrap().len(), 5);
+        fast.acknowledge(5).unwrap();
+
+        // Everything after the slowest feed's position is kept
+        assert_eq!(sequences(&slow.poll(10).unwrap()), vec![1, 2, 3, 4, 5]);
+        slow.acknowledge(3).unwrap();
+        db.put(b"x", b"v").unwrap();
+        db.flush().unwrap();
+        slow.rewind();
+        assert_eq!(sequences(&slow.poll(10).unwrap()), vec![4, 5, 6]);
+
+        // Without feeds, rotated WAL files are deleted again
+        db.drop_changefeed("slow").unwrap();
+        db.drop_changefeed("fast").unwrap();
+        db.put(b"y", b"v").unwrap();
+        db.flush().unwrap();
+        let archived =
+            crate::transaction_log::archived_wal_files(&*db.env, temp_dir.path()).unwrap();
+        assert!(archived.is_empty());
+        assert!(matches!(slow.acknowledge(6), Err(Error::NotFound(_))));
+    }
+}
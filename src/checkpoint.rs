@@ -0,0 +1,276 @@
+//! Consistent on-disk copies of a database.
+//!
+//! [`DB::create_checkpoint`] fills a new directory with everything needed to
+//! open the database as it was at that moment: the live SSTables, the
+//! MANIFEST, the current WAL and an OPTIONS file. SSTables are immutable,
+//! so they are hard-linked rather than copied (see [`Env::link_file`]); a
+//! checkpoint of a large database on the same filesystem takes little time
+//! and space until the source compacts the shared files away.
+//!
+//! The checkpoint directory is an ordinary database directory. It can be
+//! opened with [`DB::open`], copied elsewhere as a backup, or used to start
+//! a replica.
+//!
+//! [`DB::copy_to`] builds on this to clone a database, or only a key range
+//! of it (one tenant's keys, say), while the source keeps serving reads and
+//! writes.
+//!
+//! [`Env::link_file`]: crate::env::Env::link_file
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::{Options, DB};
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = DB::open("./data", Options::default())?;
+//! db.put(b"key", b"value")?;
+//! db.create_checkpoint("./data-checkpoint")?;
+//!
+//! let copy = DB::open("./data-checkpoint", Options::default())?;
+//! assert_eq!(copy.get(b"key")?, Some(b"value".to_vec()));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::blob;
+use crate::config::OptionsFile;
+use crate::prefix_delete::PREFIX_DELETES_FILE;
+use crate::{Error, Result, DB};
+
+/// Directory inside a range copy its exported SSTables are staged in
+const COPY_STAGING_DIR: &str = "copy-staging";
+
+impl DB {
+    /// Creates a checkpoint of the database in `checkpoint_dir`, which must
+    /// not exist yet.
+    ///
+    /// The MemTable is flushed first, so the checkpoint is mostly SSTables.
+    /// Flushes and compactions wait while the files are linked; writes only
+    /// wait while the WAL is copied. Writes made after the WAL is copied
+    /// are not part of the checkpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `checkpoint_dir` exists. If linking or
+    /// copying a file fails, the error is returned and the incomplete
+    /// directory is left behind.
+    pub fn create_checkpoint<P: AsRef<Path>>(&self, checkpoint_dir: P) -> Result<()> {
+        let dir = checkpoint_dir.as_ref();
+        if self.env.file_exists(dir) {
+            return Err(Error::invalid_argument(format!(
+                "checkpoint directory {:?} already exists",
+                dir
+            )));
+        }
+
+        self.flush()?;
+        self.env.create_dir_all(dir)?;
+
+        {
+            // Keep the set of live SSTables fixed until the WAL is copied:
+            // a flush installing a table and rotating the WAL in between
+            // would leave its writes out of the checkpoint
+            let _compaction = self.compaction_lock.lock();
+            let _flush = self.flush_lock.lock();
+
+            let tables: Vec<_> = self.sstables.read().iter().flatten().cloned().collect();
+            for table in &tables {
+                let from = table.file_path();
+                let name = from.file_name().expect("SSTable path has a file name");
+                self.env.link_file(from, &dir.join(name))?;
+            }
+
+            {
+                // Held so an edit is not appended while the file is read
+                let _version_set = self.version_set.read();
+                let manifest = self.env.read_file(&self.path.join("MANIFEST"))?;
+                self.env.write_file(&dir.join("MANIFEST"), &manifest)?;
+            }
+
+            // Prefix tombstones still covering the linked SSTables
+            let prefix_deletes = self.path.join(PREFIX_DELETES_FILE);
+            if self.env.file_exists(&prefix_deletes) {
+                let data = self.env.read_file(&prefix_deletes)?;
+                self.env.write_file(&dir.join(PREFIX_DELETES_FILE), &data)?;
+            }
+
+            // Replaced WAL files may still hold writes of MemTables frozen
+            // since the flush
+            let mut wal = self.wal.write();
+            wal.sync()?;
+            let frozen: Vec<_> = self.frozen_wals().into_iter().map(|(path, _)| path).collect();
+            for path in frozen.iter().map(|path| path.as_path()).chain([wal.path()]) {
+                let name = path.file_name().expect("WAL path has a file name");
+                let data = self.env.read_file(path)?;
+                self.env.write_file(&dir.join(name), &data)?;
+            }
+            drop(wal);
+
+            // Every blob the copied SSTables and WAL refer to was written
+            // before its reference; files deleted meanwhile were unreferenced
+            let blobs = blob::blob_files(&*self.env, &self.path)?;
+            if !blobs.is_empty() {
+                let blob_dir = dir.join(blob::BLOB_DIR);
+                self.env.create_dir_all(&blob_dir)?;
+                for (_, from) in &blobs {
+                    let name = from.file_name().expect("blob path has a file name");
+                    match self.env.link_file(from, &blob_dir.join(name)) {
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        result => result?,
+                    }
+                }
+                self.env.sync_dir(&blob_dir)?;
+            }
+        }
+
+        let options = self.options.read().clone();
+        OptionsFile::new(&options).persist_with_env(dir, &*self.env)?;
+        self.env.sync_dir(dir)?;
+        if let Some(parent) = dir.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            self.env.sync_dir(parent)?;
+        }
+
+        log::info!("Created checkpoint at {:?}", dir);
+        self.info_log.info(format_args!("Created checkpoint at {:?}", dir));
+        Ok(())
+    }
+
+    /// Copies the database to `path`, which must not exist yet, as an
+    /// independent database.
+    ///
+    /// Without a range this is a [checkpoint](Self::create_checkpoint).
+    /// With `Some((start, end))`, only the keys with `start <= key < end`
+    /// are copied: they are read at the current sequence number, written
+    /// to SSTables and ingested into a new database at `path` (see
+    /// [`DB::ingest_external_file`]), so the copy holds no data outside the
+    /// range and needs no compaction to drop it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `path` exists. If copying fails, the
+    /// error is returned and the incomplete directory is left behind.
+    pub fn copy_to<P: AsRef<Path>>(
+        self: &Arc<Self>,
+        path: P,
+        range: Option<(&[u8], &[u8])>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let Some((start, end)) = range else {
+            return self.create_checkpoint(path);
+        };
+        if self.env.file_exists(path) {
+            return Err(Error::invalid_argument(format!("copy target {:?} already exists", path)));
+        }
+
+        let staging_dir = path.join(COPY_STAGING_DIR);
+        let sequence = self.sequence.load(Ordering::SeqCst);
+        let metadata = self.export_range(sequence, Some(start), Some(end), &staging_dir)?;
+
+        let mut options = self.options.read().clone();
+        // The copy must not report to the source's listeners and statistics
+        options.listeners.clear();
+        options.statistics = None;
+        options.create_if_missing = true;
+        let copy = DB::open(path, options)?;
+        copy.ingest_external_file(&metadata.file_paths(&staging_dir))?;
+        copy.close()?;
+
+        for name in self.env.get_children(&staging_dir)? {
+            self.env.remove_file(&staging_dir.join(name))?;
+        }
+        self.env.remove_dir(&staging_dir)?;
+        self.env.sync_dir(path)?;
+
+        log::info!("Copied {} entries of a key range to {:?}", metadata.num_entries, path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_create_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let checkpoint_path = temp_dir.path().join("checkpoint");
+
+        let db = DB::open(&db_path, Options::default()).unwrap();
+        for i in 0..100 {
+            db.put(format!("key{:03}", i).as_bytes(), b"v1").unwrap();
+        }
+        db.flush().unwrap();
+        db.put(b"key001", b"v2").unwrap();
+        db.put(b"key100", b"v2").unwrap();
+        db.delete(b"key100").unwrap();
+
+        db.create_checkpoint(&checkpoint_path).unwrap();
+        assert!(db.create_checkpoint(&checkpoint_path).is_err());
+
+        // Later writes to the source are not in the checkpoint
+        db.put(b"key002", b"v3").unwrap();
+
+        let copy = DB::open(&checkpoint_path, Options::default()).unwrap();
+        assert_eq!(copy.get(b"key000").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(copy.get(b"key100").unwrap(), None);
+        assert_eq!(copy.get(b"key001").unwrap(), Some(b"v2".to_vec()));
+        assert_eq!(copy.get(b"key002").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(copy.get(b"key099").unwrap(), Some(b"v1".to_vec()));
+
+        // The two databases are independent
+        copy.put(b"key003", b"copy").unwrap();
+        drop(copy);
+        assert_eq!(db.get(b"key003").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(db.get(b"key002").unwrap(), Some(b"v3".to_vec()));
+    }
+
+    #[test]
+    fn test_copy_key_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path().join("db"), Options::default()).unwrap());
+        for tenant in ["a", "b", "c"] {
+            for i in 0..50 {
+                db.put(format!("{}/{:02}", tenant, i).as_bytes(), tenant.as_bytes()).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        db.put(b"b/10", b"changed").unwrap();
+        db.delete(b"b/11").unwrap();
+
+        let copy_path = temp_dir.path().join("tenant-b");
+        db.copy_to(&copy_path, Some((b"b/", b"b0"))).unwrap();
+        assert!(db.copy_to(&copy_path, Some((b"b/", b"b0"))).is_err());
+        assert!(!copy_path.join(COPY_STAGING_DIR).exists());
+
+        // The source keeps working independently
+        db.put(b"b/12", b"after copy").unwrap();
+
+        let copy = Arc::new(DB::open(&copy_path, Options::default()).unwrap());
+        let mut keys = Vec::new();
+        let mut iter = copy.iter();
+        while iter.valid() {
+            keys.push(iter.key().to_vec());
+            iter.next();
+        }
+        assert_eq!(keys.len(), 49);
+        assert!(keys.iter().all(|key| key.starts_with(b"b/")));
+        assert_eq!(copy.get(b"b/10").unwrap(), Some(b"changed".to_vec()));
+        assert_eq!(copy.get(b"b/11").unwrap(), None);
+        assert_eq!(copy.get(b"b/12").unwrap(), Some(b"b".to_vec()));
+
+        // Without a range the whole database is copied
+        let full_path = temp_dir.path().join("full");
+        db.copy_to(&full_path, None).unwrap();
+        let full = DB::open(&full_path, Options::default()).unwrap();
+        assert_eq!(full.get(b"a/00").unwrap(), Some(b"a".to_vec()));
+        assert_eq!(full.get(b"b/12").unwrap(), Some(b"after copy".to_vec()));
+    }
+}
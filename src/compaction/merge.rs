@@ -3,6 +3,8 @@
 //! This module provides an iterator that merges multiple SSTable iterators
 //! into a single sorted stream.
 
+use crate::comparator::{self, Comparator};
+use crate::config::ReadOptions;
 use crate::error::Result;
 use crate::sstable::SSTableReader;
 use std::cmp::Ordering;
@@ -13,7 +15,9 @@ use std::sync::Arc;
 struct MergeEntry {
     key: Vec<u8>,
     value: Vec<u8>,
+    sequence: u64,
     iterator_index: usize,
+    comparator: Arc<dyn Comparator>,
 }
 
 impl PartialEq for MergeEntry {
@@ -33,7 +37,7 @@ impl PartialOrd for MergeEntry {
 impl Ord for MergeEntry {
     fn cmp(&self, other: &Self) -> Ordering {
         // Reverse ordering for min-heap (smallest key first)
-        other.key.cmp(&self.key).then_with(|| {
+        self.comparator.compare(&other.key, &self.key).then_with(|| {
             // For equal keys, prefer smaller iterator index (newer data)
             other.iterator_index.cmp(&self.iterator_index)
         })
@@ -42,22 +46,34 @@ impl Ord for MergeEntry {
 
 /// Multi-way merge iterator over multiple SSTables
 ///
-/// This iterator merges entries from multiple SSTables in sorted order.
-/// For duplicate keys, it keeps the entry from the iterator with the smallest index
-/// (which typically corresponds to the newest data).
+/// This iterator merges entries from multiple SSTables in sorted order,
+/// including every stored version of each key. Entries with equal keys come
+/// from the iterator with the smallest index (which typically corresponds to
+/// the newest data) first.
 pub struct MergeIterator {
     heap: BinaryHeap<MergeEntry>,
     iterators: Vec<crate::sstable::reader::SSTableIterator>,
+    comparator: Arc<dyn Comparator>,
 }
 
 impl MergeIterator {
     /// Create a new merge iterator from multiple SSTable readers
     pub fn new(readers: Vec<Arc<SSTableReader>>) -> Result<Self> {
+        Self::with_comparator(readers, comparator::default_comparator())
+    }
+
+    /// Create a new merge iterator from SSTables whose keys are ordered by
+    /// `comparator`
+    pub fn with_comparator(
+        readers: Vec<Arc<SSTableReader>>,
+        comparator: Arc<dyn Comparator>,
+    ) -> Result<Self> {
         let mut iterators = Vec::new();
         let mut heap = BinaryHeap::new();
 
         for (idx, reader) in readers.into_iter().enumerate() {
-            let mut iter = reader.iter();
+            // Compaction reads every block once; keep it from evicting hot ones
+            let mut iter = reader.iter_versions(&ReadOptions::new().fill_cache(false));
             iter.seek_to_first()?;
 
             // Add the first entry from this iterator to the heap
@@ -65,14 +81,16 @@ impl MergeIterator {
                 heap.push(MergeEntry {
                     key: iter.key().to_vec(),
                     value: iter.value().to_vec(),
+                    sequence: iter.sequence(),
                     iterator_index: idx,
+                    comparator: Arc::clone(&comparator),
                 });
             }
 
             iterators.push(iter);
         }
 
-        Ok(Self { heap, iterators })
+        Ok(Self { heap, iterators, comparator })
     }
 
     /// Advance the iterator at the given index and add its next entry to the heap
@@ -86,18 +104,18 @@ impl MergeIterator {
             self.heap.push(MergeEntry {
                 key: iter.key().to_vec(),
                 value: iter.value().to_vec(),
+                sequence: iter.sequence(),
                 iterator_index: index,
+                comparator: Arc::clone(&self.comparator),
             });
         }
 
         Ok(())
     }
-}
 
-impl Iterator for MergeIterator {
-    type Item = (Vec<u8>, Vec<u8>);
-
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Returns the next entry with its sequence number (0 if its table has
+    /// none) and the index of the reader it comes from
+    pub(crate) fn next_entry(&mut self) -> Option<(Vec<u8>, Vec<u8>, u64, usize)> {
         // Pop the smallest entry from the heap
         let entry = self.heap.pop()?;
 
@@ -107,7 +125,15 @@ impl Iterator for MergeIterator {
             return None;
         }
 
-        Some((entry.key, entry.value))
+        Some((entry.key, entry.value, entry.sequence, entry.iterator_index))
+    }
+}
+
+impl Iterator for MergeIterator {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().map(|(key, value, _, _)| (key, value))
     }
 }
 
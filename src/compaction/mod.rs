@@ -1,7 +1,8 @@
 //! Compaction module for managing SSTable compaction.
 //!
 //! This module implements the compaction process that merges multiple SSTables
-//! into a single SSTable, removing deleted entries and old versions.
+//! into a single SSTable, removing deleted entries and old versions that no
+//! read can see any more.
 //!
 //! ## Compaction Strategy
 //!
@@ -31,9 +32,19 @@ pub use merge::MergeIterator;
 pub use picker::{CompactionPicker, CompactionTask};
 pub use version::{Version, VersionEdit, VersionSet};
 
-use crate::error::Result;
-use crate::sstable::{SSTableBuilder, SSTableReader};
-use std::path::PathBuf;
+use crate::comparator::{self, Comparator};
+use crate::config::{
+    self, ChecksumType, CompressionType, MaintenanceWindow, Options, PrefixExtractor, ReadOptions,
+};
+use crate::env::{AccessHint, Env};
+use crate::error::{Error, Result};
+use crate::prefix_delete::PrefixDeletes;
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::record_auth::RecordAuthenticator;
+use crate::sstable::{SSTableBuilder, SSTableReader, TableMetadata};
+use crate::timestamp;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 /// Compaction job that executes the compaction process
@@ -46,6 +57,40 @@ pub struct CompactionJob {
     pub db_path: PathBuf,
     /// Block size for output SSTables
     pub block_size: usize,
+    /// Compression for output SSTables
+    pub compression: CompressionType,
+    /// Checksum algorithm for output SSTables
+    pub checksum_type: ChecksumType,
+    /// Bloom filter false positive rate for output SSTables (`None` disables the filter)
+    pub bloom_filter_fp_rate: Option<f64>,
+    /// Prefix extractor whose prefixes are added to the Bloom filters
+    pub prefix_extractor: PrefixExtractor,
+    /// Maximum size of a single output SSTable (`None` writes a single file)
+    pub target_file_size: Option<u64>,
+    /// Environment the outputs are written through
+    pub env: Arc<dyn Env>,
+    /// Limiter the output writes are charged to, at low priority
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Windows in which outputs are charged at a multiple of the rate
+    pub windows: Vec<MaintenanceWindow>,
+    /// Pass page cache hints for the inputs and outputs to the OS
+    pub advise_io: bool,
+    /// Prefix deletions whose covered entries are dropped
+    pub(crate) prefix_deletes: PrefixDeletes,
+    /// Tags the values of the outputs
+    pub record_authenticator: Option<Arc<RecordAuthenticator>>,
+    /// Read each output back and check it before returning it
+    pub verify_outputs: bool,
+    /// Order of the keys of the inputs and outputs
+    pub comparator: Arc<dyn Comparator>,
+    /// Oldest sequence number reads may still ask for: a version is kept
+    /// while the next newer version of its key was written after it.
+    /// `u64::MAX` keeps only the newest version of each key.
+    pub history_cutoff: u64,
+    /// Oldest user timestamp reads may still ask for, if the comparator
+    /// orders timestamps: a version of a key is kept while the next newer
+    /// one has a later timestamp. 0 keeps every version.
+    pub timestamp_cutoff: u64,
 }
 
 impl CompactionJob {
@@ -56,86 +101,338 @@ impl CompactionJob {
         db_path: PathBuf,
         block_size: usize,
     ) -> Self {
-        Self { inputs, output_level, db_path, block_size }
+        Self {
+            inputs,
+            output_level,
+            db_path,
+            block_size,
+            compression: CompressionType::None,
+            checksum_type: ChecksumType::Crc32,
+            bloom_filter_fp_rate: Some(0.01),
+            prefix_extractor: PrefixExtractor::None,
+            target_file_size: None,
+            env: crate::env::default_env(),
+            rate_limiter: None,
+            windows: Vec::new(),
+            advise_io: false,
+            prefix_deletes: PrefixDeletes::default(),
+            record_authenticator: None,
+            verify_outputs: false,
+            comparator: comparator::default_comparator(),
+            history_cutoff: u64::MAX,
+            timestamp_cutoff: 0,
+        }
+    }
+
+    /// Apply the settings `options` specifies for the output level
+    pub fn with_options(mut self, options: &Options) -> Self {
+        self.block_size = options.block_size;
+        self.compression = options.compression_for_level(self.output_level);
+        self.checksum_type = options.checksum;
+        self.bloom_filter_fp_rate = options.bloom_filter_for_level(self.output_level);
+        self.prefix_extractor = options.prefix_extractor;
+        self.target_file_size =
+            options.target_file_size_for_level(self.output_level).map(|size| size as u64);
+        self.env = Arc::clone(&options.env);
+        self.rate_limiter = options.rate_limiter.clone();
+        self.windows = options.compaction_windows.clone();
+        self.advise_io = options.advise_compaction_io;
+        self.verify_outputs = options.verify_compaction_output;
+        self.comparator = Arc::clone(&options.comparator);
+        self
+    }
+
+    /// Drop the input entries `prefix_deletes` covers
+    pub(crate) fn with_prefix_deletes(mut self, prefix_deletes: PrefixDeletes) -> Self {
+        self.prefix_deletes = prefix_deletes;
+        self
+    }
+
+    /// Keep the versions reads at `history_cutoff` or later can see
+    pub fn with_history_cutoff(mut self, history_cutoff: u64) -> Self {
+        self.history_cutoff = history_cutoff;
+        self
+    }
+
+    /// Keep the versions reads at user timestamps of `timestamp_cutoff` or
+    /// later can see
+    pub fn with_timestamp_cutoff(mut self, timestamp_cutoff: u64) -> Self {
+        self.timestamp_cutoff = timestamp_cutoff;
+        self
+    }
+
+    /// Tag the values of the outputs with `authenticator`
+    pub fn with_record_authenticator(
+        mut self,
+        authenticator: Option<Arc<RecordAuthenticator>>,
+    ) -> Self {
+        self.record_authenticator = authenticator;
+        self
     }
 
     /// Execute the compaction
     ///
     /// This will:
     /// 1. Create a merge iterator over all input SSTables
-    /// 2. Write merged data to a new SSTable
-    /// 3. Return the file number of the new SSTable
-    pub fn run(&self, file_number: u64) -> Result<CompactionResult> {
+    /// 2. Write merged data to new SSTables, starting a new file whenever
+    ///    `target_file_size` is reached
+    /// 3. Read each output back if `verify_outputs` is set
+    /// 4. Return one result per output SSTable, in key order
+    ///
+    /// `next_file_number` is called to allocate the file number of each
+    /// output. If writing or verifying an output fails, every output is
+    /// deleted.
+    pub fn run(&self, mut next_file_number: impl FnMut() -> u64) -> Result<Vec<CompactionResult>> {
         log::info!(
             "Starting compaction: {} input files -> level {}",
             self.inputs.len(),
             self.output_level
         );
 
-        // Create output SSTable path
-        let output_path = self.db_path.join(format!("{:06}.sst", file_number));
+        self.advise_inputs(AccessHint::Sequential);
 
-        // Create merge iterator
-        let merge_iter = MergeIterator::new(self.inputs.clone())?;
+        // Remove partially written outputs if the compaction fails (e.g. the
+        // disk is full) so they don't get loaded as SSTables on the next open
+        let mut created = Vec::new();
+        let results = self
+            .write_outputs(&mut next_file_number, &mut created)
+            .and_then(|results| {
+                if self.verify_outputs {
+                    results.iter().try_for_each(|result| self.verify_output(result))?;
+                }
+                Ok(results)
+            })
+            .inspect_err(|_| {
+                for path in &created {
+                    match self.env.remove_file(path) {
+                        Ok(()) => {}
+                        // Unfinished outputs only exist as temporary files,
+                        // which the builder removes itself
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        Err(e) => log::warn!(
+                            "Failed to remove partial compaction output {:?}: {}",
+                            path,
+                            e
+                        ),
+                    }
+                }
+            })?;
 
-        // Create SSTable builder
-        let mut builder = SSTableBuilder::new(&output_path)?;
-        builder.set_block_size(self.block_size);
+        // The inputs are about to be deleted; their pages are of no use
+        self.advise_inputs(AccessHint::DontNeed);
 
-        // Merge all entries
-        let mut entry_count = 0;
+        log::info!(
+            "Compaction completed: {} entries written to {} files",
+            results.iter().map(|r| r.entry_count).sum::<usize>(),
+            results.len()
+        );
+
+        Ok(results)
+    }
+
+    /// Pass `hint` for every input file to the OS, if enabled
+    fn advise_inputs(&self, hint: AccessHint) {
+        if !self.advise_io {
+            return;
+        }
+        for input in &self.inputs {
+            if let Err(e) = input.advise(hint) {
+                log::debug!("Failed to advise {:?}: {}", input.file_path(), e);
+            }
+        }
+    }
+
+    /// Merge the inputs into output SSTables, recording each file created in `created`
+    fn write_outputs(
+        &self,
+        next_file_number: &mut impl FnMut() -> u64,
+        created: &mut Vec<PathBuf>,
+    ) -> Result<Vec<CompactionResult>> {
+        // Create merge iterator
+        let mut merge_iter =
+            MergeIterator::with_comparator(self.inputs.clone(), Arc::clone(&self.comparator))?;
+
+        let mut results = Vec::new();
+        let mut output: Option<(SSTableBuilder, u64, PathBuf, usize)> = None;
         let mut last_user_key: Option<Vec<u8>> = None;
+        // Whether the next version of the current key is still visible
+        let mut keep_older = false;
+        // Likewise for the key with the next older timestamp
+        let timestamps = self.comparator.timestamp_size() > 0;
+        let mut keep_older_timestamp = false;
 
-        for (key, value) in merge_iter {
-            // Skip duplicate keys (keep only the newest version)
-            if let Some(ref last_key) = last_user_key {
-                if last_key.as_slice() == key.as_slice() {
+        while let Some((key, value, sequence, source)) = merge_iter.next_entry() {
+            // Drop entries deleted with their prefix
+            if self.prefix_deletes.covers_file(&key, self.inputs[source].file_number()) {
+                continue;
+            }
+
+            // Versions of a key come newest first; an older version is
+            // only kept while a read at the cutoff or later can see it
+            let new_key = last_user_key.as_deref() != Some(key.as_slice());
+            if new_key {
+                // Keys with timestamps are versions of the key before them
+                // too, from the newest timestamp down
+                let older_timestamp = timestamps
+                    && last_user_key
+                        .as_deref()
+                        .and_then(timestamp::split_timestamp)
+                        .zip(timestamp::split_timestamp(&key))
+                        .is_some_and(|((last, _), (current, _))| last == current);
+                last_user_key = Some(key.to_vec());
+                if older_timestamp && !keep_older_timestamp {
+                    keep_older = false;
                     continue;
                 }
+                keep_older_timestamp = timestamps
+                    && timestamp::split_timestamp(&key)
+                        .is_some_and(|(_, ts)| ts > self.timestamp_cutoff);
+            } else if !keep_older {
+                continue;
             }
+            keep_older = sequence > self.history_cutoff;
 
             // Skip tombstones (empty values) during compaction to level 1+
-            // This removes deleted keys from the database
-            if self.output_level > 0 && value.is_empty() {
-                last_user_key = Some(key.to_vec());
+            // unless older versions they hide are kept. This removes deleted
+            // keys from the database
+            if self.output_level > 0 && value.is_empty() && !keep_older && !keep_older_timestamp {
                 continue;
             }
 
-            builder.add(&key, &value)?;
-            entry_count += 1;
-            last_user_key = Some(key.to_vec());
+            // Start a new output once the current one is full, between keys
+            // so all versions of a key stay in one file
+            if new_key
+                && output.as_ref().is_some_and(|(builder, ..)| {
+                    self.target_file_size.is_some_and(|target| builder.current_size() >= target)
+                })
+            {
+                let (builder, file_number, output_path, entry_count) =
+                    output.take().expect("output exists");
+                results.push(Self::finish_output(builder, file_number, output_path, entry_count)?);
+            }
+
+            if output.is_none() {
+                let file_number = next_file_number();
+                let output_path = self.db_path.join(format!("{:06}.sst", file_number));
+                created.push(output_path.clone());
+                output = Some((self.new_builder(&output_path)?, file_number, output_path, 0));
+            }
+
+            let (builder, _, _, entry_count) = output.as_mut().expect("output was just created");
+            builder.add_version(&key, sequence, &value)?;
+            *entry_count += 1;
+        }
+
+        if let Some((builder, file_number, output_path, entry_count)) = output {
+            results.push(Self::finish_output(builder, file_number, output_path, entry_count)?);
+        }
+
+        Ok(results)
+    }
+
+    /// Create a builder for an output SSTable
+    fn new_builder(&self, path: &Path) -> Result<SSTableBuilder> {
+        let mut builder = SSTableBuilder::new_with_env(path, Arc::clone(&self.env))?;
+        builder.set_comparator(Arc::clone(&self.comparator));
+        builder.set_block_size(self.block_size);
+        builder.set_compression(self.compression);
+        builder.set_checksum_type(self.checksum_type);
+        match self.bloom_filter_fp_rate {
+            Some(rate) => builder.set_bloom_filter_fp_rate(rate),
+            None => builder.set_bloom_filter_enabled(false),
+        }
+        builder.set_prefix_extractor(self.prefix_extractor);
+        if let Some(limiter) = &self.rate_limiter {
+            builder.set_rate_limiter(Arc::clone(limiter), IoPriority::Low);
+            builder.set_rate_multiplier(config::current_rate_multiplier(
+                &self.windows,
+                self.env.now_micros(),
+            ));
+        }
+        builder.set_drop_page_cache(self.advise_io);
+        if let Some(authenticator) = &self.record_authenticator {
+            builder.set_record_authenticator(Arc::clone(authenticator))?;
+        }
+        Ok(builder)
+    }
+
+    /// Reads an output back and checks its blocks, key order and entry
+    /// count against what was written
+    fn verify_output(&self, result: &CompactionResult) -> Result<()> {
+        let corrupt = |message: String| {
+            log::error!("Compaction output {:?} {}", result.output_path, message);
+            Error::corruption(format!("compaction output {:?} {}", result.output_path, message))
+        };
+        let reader = SSTableReader::open_with_env(&result.output_path, None, &*self.env)?
+            .with_record_authenticator(self.record_authenticator.clone())?
+            .with_comparator(Arc::clone(&self.comparator));
+        if let Some((offset, e)) = reader.verify_checksums(None)?.into_iter().next() {
+            return Err(corrupt(format!("has a bad block at offset {}: {}", offset, e)));
         }
 
-        // If no entries were written, clean up and return
-        if entry_count == 0 {
-            builder.abandon()?;
-            if output_path.exists() {
-                std::fs::remove_file(&output_path)?;
+        let mut iter = reader.iter_versions(&ReadOptions::default());
+        iter.seek_to_first()?;
+        iter.advance()?;
+        let mut entries = 0;
+        let mut last: Option<(Vec<u8>, u64)> = None;
+        while iter.valid() {
+            // Versions of a key go from the newest down
+            if last.as_ref().is_some_and(|(last_key, last_sequence)| {
+                match self.comparator.compare(last_key, iter.key()) {
+                    Ordering::Less => false,
+                    Ordering::Equal => iter.sequence() >= *last_sequence,
+                    Ordering::Greater => true,
+                }
+            }) {
+                return Err(corrupt(format!(
+                    "has key {:?} out of order",
+                    String::from_utf8_lossy(iter.key())
+                )));
             }
-            return Ok(CompactionResult { file_number: 0, entry_count: 0, output_path });
+            last = Some((iter.key().to_vec(), iter.sequence()));
+            entries += 1;
+            iter.advance()?;
+        }
+        if entries != result.entry_count {
+            return Err(corrupt(format!(
+                "has {} entries but {} were written",
+                entries, result.entry_count
+            )));
         }
+        Ok(())
+    }
 
-        // Finish building the SSTable
-        let file_size = builder.finish()?;
+    /// Finish an output SSTable
+    fn finish_output(
+        builder: SSTableBuilder,
+        file_number: u64,
+        output_path: PathBuf,
+        entry_count: usize,
+    ) -> Result<CompactionResult> {
+        let table = builder.finish()?;
 
         log::info!(
-            "Compaction completed: {} entries written, file size: {} bytes",
+            "Compaction output {:06}.sst: {} entries, file size: {} bytes",
+            file_number,
             entry_count,
-            file_size
+            table.file_size
         );
 
-        Ok(CompactionResult { file_number, entry_count, output_path })
+        Ok(CompactionResult { file_number, entry_count, output_path, table })
     }
 }
 
 /// Result of a compaction operation
 pub struct CompactionResult {
-    /// File number of the output SSTable (0 if no file was created)
+    /// File number of the output SSTable
     pub file_number: u64,
     /// Number of entries written
     pub entry_count: usize,
     /// Path to the output file
     pub output_path: PathBuf,
+    /// Metadata returned by the builder, for opening the output without
+    /// re-reading it
+    pub table: TableMetadata,
 }
 
 /// Target size for each level (in bytes)
@@ -157,6 +454,170 @@ pub const MAX_LEVEL0_FILES: usize = 4;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::LevelOptions;
+    use tempfile::TempDir;
+
+    fn create_sstable(dir: &TempDir, file_num: u64, num_entries: usize) -> Arc<SSTableReader> {
+        let path = dir.path().join(format!("{:06}.sst", file_num));
+        let mut builder = SSTableBuilder::new(&path).unwrap();
+        for i in 0..num_entries {
+            builder.add(format!("key{:06}", i).as_bytes(), &[b'v'; 100]).unwrap();
+        }
+        builder.finish().unwrap();
+        Arc::new(SSTableReader::open(&path).unwrap())
+    }
+
+    #[test]
+    fn test_compaction_job_splits_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = create_sstable(&temp_dir, 1, 1000);
+
+        let options = Options::default().level_options(
+            1,
+            LevelOptions::new()
+                .compression(CompressionType::None)
+                .target_file_size(16 * 1024),
+        );
+        let job = CompactionJob::new(vec![input], 1, temp_dir.path().to_path_buf(), 4096)
+            .with_options(&options);
+        assert_eq!(job.compression, CompressionType::None);
+
+        let mut next = 10;
+        let results = job
+            .run(|| {
+                next += 1;
+                next
+            })
+            .unwrap();
+
+        assert!(results.len() > 1, "expected multiple outputs, got {}", results.len());
+        assert_eq!(results.iter().map(|r| r.entry_count).sum::<usize>(), 1000);
+
+        // Outputs are numbered in order and cover disjoint, increasing key ranges
+        let mut last_largest: Option<Vec<u8>> = None;
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(result.file_number, 11 + i as u64);
+            let reader = SSTableReader::open(&result.output_path).unwrap();
+            let smallest = reader.smallest_key().unwrap().unwrap();
+            if let Some(prev) = last_largest {
+                assert!(prev < smallest);
+            }
+            last_largest = reader.largest_key().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compaction_job_without_target_writes_one_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = create_sstable(&temp_dir, 1, 1000);
+
+        let job = CompactionJob::new(vec![input], 1, temp_dir.path().to_path_buf(), 4096)
+            .with_options(&Options::default());
+        assert!(job.advise_io);
+        let results = job.run(|| 2).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].entry_count, 1000);
+        // Outputs dropped from the page cache still read back
+        let reader = SSTableReader::open(&results[0].output_path).unwrap();
+        assert_eq!(reader.get(b"key000999").unwrap(), Some(vec![b'v'; 100]));
+    }
+
+    #[test]
+    fn test_compaction_job_follows_windows() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = create_sstable(&temp_dir, 1, 1000);
+        let limiter = Arc::new(RateLimiter::new(100_000));
+        // Use up the first second's worth
+        limiter.request(100_000, IoPriority::Low);
+
+        let always = MaintenanceWindow::new(0, 0, f64::INFINITY);
+        let options = Options::default()
+            .compression(CompressionType::None)
+            .rate_limiter(Arc::clone(&limiter))
+            .compaction_windows(vec![always]);
+        let job = CompactionJob::new(vec![input], 1, temp_dir.path().to_path_buf(), 4096)
+            .with_options(&options);
+        let start = std::time::Instant::now();
+        job.run(|| 2).unwrap();
+        // About 100KB written without waiting for the limiter
+        assert!(start.elapsed() < std::time::Duration::from_millis(500));
+        assert!(limiter.total_bytes_through(IoPriority::Low) > 200_000);
+    }
+
+    #[test]
+    fn test_compaction_job_verifies_outputs() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = create_sstable(&temp_dir, 1, 1000);
+
+        let options = Options::default().verify_compaction_output(true);
+        let job = CompactionJob::new(vec![input], 1, temp_dir.path().to_path_buf(), 4096)
+            .with_options(&options);
+        assert!(job.verify_outputs);
+        let mut results = job.run(|| 2).unwrap();
+        assert_eq!(results[0].entry_count, 1000);
+
+        // An output missing an entry
+        results[0].entry_count += 1;
+        assert!(matches!(job.verify_output(&results[0]), Err(Error::Corruption(_))));
+        results[0].entry_count -= 1;
+
+        // A damaged block
+        let path = &results[0].output_path;
+        let mut data = std::fs::read(path).unwrap();
+        data[100] ^= 0xff;
+        std::fs::write(path, data).unwrap();
+        assert!(matches!(job.verify_output(&results[0]), Err(Error::Corruption(_))));
+    }
+
+    #[test]
+    fn test_compaction_job_keeps_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let build = |file_num: u64, entries: &[(&[u8], u64, &[u8])]| {
+            let path = temp_dir.path().join(format!("{:06}.sst", file_num));
+            let mut builder = SSTableBuilder::new(&path).unwrap();
+            for (key, sequence, value) in entries {
+                builder.add_version(key, *sequence, value).unwrap();
+            }
+            builder.finish().unwrap();
+            Arc::new(SSTableReader::open(&path).unwrap())
+        };
+        let newer = build(1, &[(b"a", 30, b""), (b"b", 12, b"b12")]);
+        let older = build(2, &[(b"a", 20, b"a20"), (b"a", 10, b"a10"), (b"b", 5, b"b5")]);
+
+        let run = |cutoff: u64| {
+            let job = CompactionJob::new(
+                vec![Arc::clone(&newer), Arc::clone(&older)],
+                1,
+                temp_dir.path().to_path_buf(),
+                4096,
+            )
+            .with_history_cutoff(cutoff);
+            let results = job.run(|| 3).unwrap();
+            let reader = SSTableReader::open(&results[0].output_path).unwrap();
+            let mut iter = reader.iter_versions(&ReadOptions::default());
+            iter.seek_to_first().unwrap();
+            let mut entries = Vec::new();
+            while iter.advance().unwrap() {
+                entries.push((iter.key().to_vec(), iter.sequence(), iter.value().to_vec()));
+            }
+            entries
+        };
+
+        // Reads at 15 or later see the deleted a, a20, a10 (until 20) and
+        // b12 but not b5
+        assert_eq!(
+            run(15),
+            vec![
+                (b"a".to_vec(), 30, vec![]),
+                (b"a".to_vec(), 20, b"a20".to_vec()),
+                (b"a".to_vec(), 10, b"a10".to_vec()),
+                (b"b".to_vec(), 12, b"b12".to_vec()),
+            ]
+        );
+        // Only the newest versions, without the tombstone
+        assert_eq!(run(u64::MAX), vec![(b"b".to_vec(), 12, b"b12".to_vec())]);
+    }
 
     #[test]
     fn test_target_size_for_level() {
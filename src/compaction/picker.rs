@@ -3,8 +3,12 @@
 //! This module selects which files should be compacted based on the
 //! Leveled Compaction strategy.
 
-use crate::compaction::{target_size_for_level, MAX_LEVEL0_FILES};
+use crate::compaction::MAX_LEVEL0_FILES;
+use crate::comparator::{self, Comparator};
+use crate::config::Options;
+use crate::error::{Error, Result};
 use crate::sstable::SSTableReader;
+use std::cmp::{self, Ordering};
 use std::sync::Arc;
 
 /// A compaction task selected by the picker
@@ -16,18 +20,73 @@ pub struct CompactionTask {
     pub level: usize,
     /// Target level (level + 1)
     pub output_level: usize,
+    /// Files of the target level whose key range overlaps the inputs. They
+    /// are merged with the inputs so the target level stays non-overlapping.
+    pub overlapping: Vec<Arc<SSTableReader>>,
+}
+
+impl CompactionTask {
+    /// All files read by the compaction, newest first: the inputs, then
+    /// the overlapping files of the target level
+    pub fn all_inputs(&self) -> Vec<Arc<SSTableReader>> {
+        self.inputs.iter().chain(&self.overlapping).cloned().collect()
+    }
 }
 
 /// Picker for selecting files to compact
 pub struct CompactionPicker {
     /// Maximum number of levels
     max_levels: usize,
+    /// Number of Level 0 files that triggers a compaction
+    level0_threshold: usize,
+    /// Target size of Level 1 in bytes
+    base_level_size: u64,
+    /// Size ratio between adjacent levels
+    level_size_multiplier: u64,
+    /// Order of the keys
+    comparator: Arc<dyn Comparator>,
 }
 
 impl CompactionPicker {
-    /// Create a new compaction picker
+    /// Create a new compaction picker with the default triggers
     pub fn new(max_levels: usize) -> Self {
-        Self { max_levels }
+        Self {
+            max_levels,
+            level0_threshold: MAX_LEVEL0_FILES,
+            base_level_size: 10 * 1024 * 1024,
+            level_size_multiplier: 10,
+            comparator: comparator::default_comparator(),
+        }
+    }
+
+    /// Create a compaction picker using the triggers configured in `options`
+    pub fn from_options(options: &Options) -> Self {
+        Self {
+            max_levels: options.max_levels,
+            level0_threshold: options.level0_compaction_threshold,
+            base_level_size: options.base_level_size as u64,
+            level_size_multiplier: options.level_size_multiplier as u64,
+            comparator: Arc::clone(&options.comparator),
+        }
+    }
+
+    /// The order of the keys
+    pub(crate) fn comparator(&self) -> &dyn Comparator {
+        &*self.comparator
+    }
+
+    /// Target size of a level (in bytes)
+    ///
+    /// Level 1 targets `base_level_size`; each further level is
+    /// `level_size_multiplier` times larger than the previous one.
+    pub fn target_size_for_level(&self, level: usize) -> u64 {
+        if level == 0 {
+            // Level 0 is controlled by file count, not size
+            return u64::MAX;
+        }
+        self.level_size_multiplier
+            .saturating_pow(level as u32 - 1)
+            .saturating_mul(self.base_level_size)
     }
 
     /// Pick files for compaction
@@ -39,14 +98,14 @@ impl CompactionPicker {
         // 2. Check other levels (size based)
 
         // Level 0: Trigger if too many files
-        if levels[0].len() >= MAX_LEVEL0_FILES {
+        if levels[0].len() >= self.level0_threshold {
             return self.pick_level0_compaction(levels);
         }
 
         // Level 1+: Trigger if size exceeds threshold
         for level in 1..self.max_levels - 1 {
             let total_size = self.calculate_level_size(&levels[level]);
-            let target_size = target_size_for_level(level);
+            let target_size = self.target_size_for_level(level);
 
             if total_size > target_size {
                 return self.pick_level_compaction(levels, level);
@@ -56,6 +115,89 @@ impl CompactionPicker {
         None
     }
 
+    /// Builds the compaction of the SSTables numbered `file_numbers` into
+    /// `output_level`
+    ///
+    /// The files must be live and on one level, and `output_level` must not
+    /// be above it; Level 0 is never compacted into itself. Files of the
+    /// output level that overlap the inputs are merged with them, as in a
+    /// picked compaction. Returns [`Error::InvalidArgument`] otherwise, or
+    /// if an older version of a key could end up shadowing a newer one:
+    /// when an older Level 0 file overlapping the inputs is left out, or a
+    /// level between the input and the output level has files overlapping
+    /// them.
+    pub fn pick_files(
+        &self,
+        levels: &[Vec<Arc<SSTableReader>>],
+        file_numbers: &[u64],
+        output_level: usize,
+    ) -> Result<CompactionTask> {
+        if file_numbers.is_empty() {
+            return Err(Error::invalid_argument("no files to compact"));
+        }
+        if output_level >= self.max_levels.min(levels.len()) {
+            return Err(Error::invalid_argument(format!(
+                "output level {} is out of range for max_levels {}",
+                output_level, self.max_levels
+            )));
+        }
+
+        let mut input_level = None;
+        for &number in file_numbers {
+            let level = levels
+                .iter()
+                .position(|files| files.iter().any(|t| t.file_number() == Some(number)))
+                .ok_or_else(|| {
+                    Error::invalid_argument(format!("{:06}.sst is not a live SSTable", number))
+                })?;
+            if *input_level.get_or_insert(level) != level {
+                return Err(Error::invalid_argument(format!(
+                    "input files are on levels {} and {}; they must be on one level",
+                    input_level.unwrap_or(level),
+                    level
+                )));
+            }
+        }
+        let level = input_level.expect("at least one input");
+        if output_level < level || output_level == 0 {
+            return Err(Error::invalid_argument(format!(
+                "files of level {} cannot be compacted into level {}",
+                level, output_level
+            )));
+        }
+
+        // Keep the order of the level: newest first in Level 0
+        let is_input = |t: &Arc<SSTableReader>| {
+            t.file_number().is_some_and(|number| file_numbers.contains(&number))
+        };
+        let inputs: Vec<_> = levels[level].iter().filter(|t| is_input(t)).cloned().collect();
+
+        if level == 0 {
+            let newest = levels[0].iter().position(is_input).expect("an input is in Level 0");
+            let older: Vec<_> =
+                levels[0][newest..].iter().filter(|t| !is_input(t)).cloned().collect();
+            if let Some(file) = overlapping_files(&*self.comparator, &inputs, &older).first() {
+                return Err(Error::invalid_argument(format!(
+                    "{:?} is older than an input and overlaps it; compact it as well",
+                    file.file_path()
+                )));
+            }
+        }
+        for (between, files) in levels.iter().enumerate().take(output_level).skip(level + 1) {
+            if !overlapping_files(&*self.comparator, &inputs, files).is_empty() {
+                return Err(Error::invalid_argument(format!(
+                    "level {} has files overlapping the inputs; compact into level {} at most",
+                    between, between
+                )));
+            }
+        }
+
+        let candidates: Vec<_> =
+            levels[output_level].iter().filter(|t| !is_input(t)).cloned().collect();
+        let overlapping = overlapping_files(&*self.comparator, &inputs, &candidates);
+        Ok(CompactionTask { inputs, level, output_level, overlapping })
+    }
+
     /// Pick files for Level 0 compaction
     ///
     /// Level 0 files may overlap, so we compact all of them into Level 1
@@ -69,7 +211,8 @@ impl CompactionPicker {
         // Take all Level 0 files
         let inputs = levels[0].clone();
 
-        Some(CompactionTask { inputs, level: 0, output_level: 1 })
+        let overlapping = overlapping_files(&*self.comparator, &inputs, &levels[1]);
+        Some(CompactionTask { inputs, level: 0, output_level: 1, overlapping })
     }
 
     /// Pick files for Level N compaction (N >= 1)
@@ -96,7 +239,35 @@ impl CompactionPicker {
         // (e.g., round-robin, or picking the file that hasn't been compacted recently)
         let inputs = vec![levels[level][0].clone()];
 
-        Some(CompactionTask { inputs, level, output_level: level + 1 })
+        let overlapping = levels
+            .get(level + 1)
+            .map_or_else(Vec::new, |next| overlapping_files(&*self.comparator, &inputs, next));
+        Some(CompactionTask { inputs, level, output_level: level + 1, overlapping })
+    }
+
+    /// Estimate of the bytes compactions have to rewrite until Level 0 is
+    /// below its file trigger and every other level within its target size
+    ///
+    /// A Level 0 compaction rewrites Level 0 and Level 1. Each byte a level
+    /// is over its target is merged into the next level, rewriting about
+    /// `level_size_multiplier` bytes there, and the bytes moved down count
+    /// towards the next level's size.
+    pub fn estimate_pending_compaction_bytes(&self, levels: &[Vec<Arc<SSTableReader>>]) -> u64 {
+        let mut pending = 0u64;
+        let mut moved_down = 0u64;
+        if levels.len() > 1 && levels[0].len() >= self.level0_threshold {
+            moved_down = self.calculate_level_size(&levels[0]);
+            pending = moved_down + self.calculate_level_size(&levels[1]);
+        }
+        let last_level = self.max_levels.min(levels.len()).saturating_sub(1);
+        for (level, files) in levels.iter().enumerate().take(last_level).skip(1) {
+            let size = self.calculate_level_size(files) + moved_down;
+            moved_down = size.saturating_sub(self.target_size_for_level(level));
+            pending = pending.saturating_add(
+                moved_down.saturating_mul(self.level_size_multiplier.saturating_add(1)),
+            );
+        }
+        pending
     }
 
     /// Calculate total size of a level
@@ -105,6 +276,49 @@ impl CompactionPicker {
     }
 }
 
+/// Returns the files of `level` whose key range overlaps that of `inputs`.
+///
+/// Files whose bounds cannot be read are treated as overlapping, so that
+/// a compaction never leaves two overlapping files in one level.
+fn overlapping_files(
+    comparator: &dyn Comparator,
+    inputs: &[Arc<SSTableReader>],
+    level: &[Arc<SSTableReader>],
+) -> Vec<Arc<SSTableReader>> {
+    let mut range: Option<(&[u8], &[u8])> = None;
+    for input in inputs {
+        match input.key_range() {
+            Ok(Some((smallest, largest))) => {
+                range = Some(match range {
+                    Some((lo, hi)) => (
+                        cmp::min_by(lo, smallest, |a, b| comparator.compare(a, b)),
+                        cmp::max_by(hi, largest, |a, b| comparator.compare(a, b)),
+                    ),
+                    None => (smallest, largest),
+                });
+            }
+            Ok(None) => {}
+            // Unknown bounds: take the whole level
+            Err(_) => return level.to_vec(),
+        }
+    }
+    let Some((lo, hi)) = range else {
+        return Vec::new();
+    };
+    level
+        .iter()
+        .filter(|file| match file.key_range() {
+            Ok(Some((smallest, largest))) => {
+                comparator.compare(smallest, hi) != Ordering::Greater
+                    && comparator.compare(largest, lo) != Ordering::Less
+            }
+            Ok(None) => false,
+            Err(_) => true,
+        })
+        .cloned()
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -149,6 +363,33 @@ mod tests {
         assert_eq!(task.inputs.len(), 4);
     }
 
+    #[test]
+    fn test_pick_includes_overlapping_files_of_output_level() {
+        let temp_dir = TempDir::new().unwrap();
+        let picker = CompactionPicker::new(7);
+        let table = |file_num: u64, keys: &[&str]| {
+            let path = temp_dir.path().join(format!("{:06}.sst", file_num));
+            let mut builder = SSTableBuilder::new(&path).unwrap();
+            for key in keys {
+                builder.add(key.as_bytes(), b"value").unwrap();
+            }
+            builder.finish().unwrap();
+            Arc::new(SSTableReader::open(&path).unwrap())
+        };
+
+        let mut levels: Vec<Vec<Arc<SSTableReader>>> = vec![Vec::new(); 7];
+        for i in 0..4 {
+            levels[0].push(table(10 + i, &["d", "f"]));
+        }
+        levels[1] = vec![table(1, &["a", "b"]), table(2, &["c", "e"]), table(3, &["g", "h"])];
+
+        let task = picker.pick_compaction(&levels).unwrap();
+        assert_eq!(task.inputs.len(), 4);
+        assert_eq!(task.overlapping.len(), 1);
+        assert!(Arc::ptr_eq(&task.overlapping[0], &levels[1][1]));
+        assert_eq!(task.all_inputs().len(), 5);
+    }
+
     #[test]
     fn test_no_compaction_needed() {
         let temp_dir = TempDir::new().unwrap();
@@ -214,6 +455,37 @@ mod tests {
         assert_eq!(task.level, 0, "Level 0 should be picked first");
     }
 
+    #[test]
+    fn test_picker_from_options() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default()
+            .level0_compaction_threshold(2)
+            .base_level_size(1024)
+            .level_size_multiplier(4);
+        let picker = CompactionPicker::from_options(&options);
+
+        assert_eq!(picker.target_size_for_level(1), 1024);
+        assert_eq!(picker.target_size_for_level(3), 16 * 1024);
+
+        // Two Level 0 files are enough with the lowered threshold
+        let mut levels: Vec<Vec<Arc<SSTableReader>>> = vec![Vec::new(); 7];
+        for i in 0..2 {
+            levels[0].push(create_sstable_with_size(&temp_dir, i, 10));
+        }
+        assert_eq!(picker.pick_compaction(&levels).unwrap().level, 0);
+    }
+
+    #[test]
+    fn test_default_picker_matches_level_targets() {
+        let picker = CompactionPicker::new(7);
+        for level in 0..5 {
+            assert_eq!(
+                picker.target_size_for_level(level),
+                crate::compaction::target_size_for_level(level)
+            );
+        }
+    }
+
     #[test]
     fn test_calculate_level_size() {
         let temp_dir = TempDir::new().unwrap();
@@ -228,4 +500,28 @@ mod tests {
         assert!(total_size > 0);
         assert_eq!(total_size, table1.file_size() + table2.file_size());
     }
+
+    #[test]
+    fn test_estimate_pending_compaction_bytes() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut levels: Vec<Vec<Arc<SSTableReader>>> = vec![Vec::new(); 7];
+        levels[0].push(create_sstable_with_size(&temp_dir, 1, 10));
+        levels[1].push(create_sstable_with_size(&temp_dir, 2, 100));
+        let level0 = levels[0][0].file_size();
+        let level1 = levels[1][0].file_size();
+
+        let options = Options::default()
+            .level0_compaction_threshold(2)
+            .base_level_size((level0 + level1) as usize - 100)
+            .level_size_multiplier(4);
+        let picker = CompactionPicker::from_options(&options);
+        assert_eq!(picker.estimate_pending_compaction_bytes(&levels), 0);
+
+        // Level 0 reached its trigger; it and Level 1 are rewritten, and
+        // the 100 bytes then over Level 1's target are merged into Level 2
+        levels[0].push(create_sstable_with_size(&temp_dir, 3, 10));
+        let level0 = picker.calculate_level_size(&levels[0]);
+        let excess = level0 + level1 - picker.target_size_for_level(1);
+        assert_eq!(picker.estimate_pending_compaction_bytes(&levels), level0 + level1 + excess * 5);
+    }
 }
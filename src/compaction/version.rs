@@ -3,16 +3,22 @@
 //! This module manages SSTable file metadata and version history.
 //! The Manifest file records all version changes (file additions/deletions).
 
+use crate::env::{Env, WritableFile};
 use crate::error::{Error, Result};
 use crate::sstable::SSTableReader;
 use serde::{Deserialize, Serialize};
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use std::collections::HashSet;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+/// Levels a manifest may use beyond `max_levels`; an edit naming a level
+/// past both is corrupt
+const MAX_MANIFEST_LEVELS: usize = 64;
+
 /// A version edit describes changes to the database version
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub enum VersionEdit {
     /// Add a new SSTable file
     AddFile {
@@ -38,6 +44,16 @@ pub enum VersionEdit {
     SetNextFileNumber(u64),
     /// Set the sequence number
     SetSequenceNumber(u64),
+    /// Discard all data: remove every file and every write up to a
+    /// sequence number
+    DropAll {
+        /// SSTables removed, including ones the version does not list
+        file_numbers: Vec<u64>,
+        /// Writes up to this sequence number are discarded
+        sequence: u64,
+    },
+    /// Name of the comparator the keys are ordered by
+    SetComparator(String),
 }
 
 /// A version represents the set of SSTables at a point in time
@@ -82,8 +98,12 @@ impl Version {
             VersionEdit::DeleteFile { level, file_number } => {
                 new_version.levels[*level].retain(|f| f.file_number != *file_number);
             }
+            VersionEdit::DropAll { .. } => {
+                new_version.levels.iter_mut().for_each(Vec::clear);
+            }
             _ => {
-                // SetNextFileNumber and SetSequenceNumber are handled by VersionSet
+                // SetNextFileNumber, SetSequenceNumber and SetComparator are
+                // handled by VersionSet
             }
         }
 
@@ -112,16 +132,36 @@ pub struct VersionSet {
     /// Path to the manifest file
     manifest_path: PathBuf,
     /// Manifest file handle
-    manifest_file: Option<File>,
+    manifest_file: Option<Box<dyn WritableFile>>,
+    /// Environment the manifest and SSTables are accessed through
+    env: Arc<dyn Env>,
     /// Maximum number of levels
     max_levels: usize,
     /// Next file number
     next_file_number: u64,
+    /// Last sequence number persisted by a flush
+    last_sequence: u64,
+    /// Files removed from the version whose deletion from disk may not
+    /// have happened yet
+    deleted_files: HashSet<u64>,
+    /// Writes up to this sequence number were discarded by a `DropAll`
+    dropped_sequence: u64,
+    /// Name of the comparator, if the manifest records one
+    comparator_name: Option<String>,
 }
 
 impl VersionSet {
     /// Create a new version set
     pub fn new<P: AsRef<Path>>(db_path: P, max_levels: usize) -> Result<Self> {
+        Self::new_with_env(db_path, max_levels, crate::env::default_env())
+    }
+
+    /// Create a new version set whose manifest is accessed through `env`
+    pub fn new_with_env<P: AsRef<Path>>(
+        db_path: P,
+        max_levels: usize,
+        env: Arc<dyn Env>,
+    ) -> Result<Self> {
         let db_path = db_path.as_ref();
         let manifest_path = db_path.join("MANIFEST");
 
@@ -129,12 +169,17 @@ impl VersionSet {
             current: Version::new(max_levels),
             manifest_path: manifest_path.clone(),
             manifest_file: None,
+            env,
             max_levels,
             next_file_number: 1,
+            last_sequence: 0,
+            deleted_files: HashSet::new(),
+            dropped_sequence: 0,
+            comparator_name: None,
         };
 
         // Try to recover from existing manifest
-        if manifest_path.exists() {
+        if version_set.env.file_exists(&manifest_path) {
             version_set.recover()?;
         } else {
             // Create new manifest file
@@ -144,31 +189,99 @@ impl VersionSet {
         Ok(version_set)
     }
 
+    /// Reads the manifest of `db_path`, if there is one, without opening
+    /// it for writing. Edits are applied but not logged.
+    ///
+    /// The manifest may be appended to meanwhile: a last line without a
+    /// line break is an edit still being written and is ignored.
+    pub fn open_read_only<P: AsRef<Path>>(
+        db_path: P,
+        max_levels: usize,
+        env: Arc<dyn Env>,
+    ) -> Result<Self> {
+        let mut version_set = Self {
+            current: Version::new(max_levels),
+            manifest_path: db_path.as_ref().join("MANIFEST"),
+            manifest_file: None,
+            env,
+            max_levels,
+            next_file_number: 1,
+            last_sequence: 0,
+            deleted_files: HashSet::new(),
+            dropped_sequence: 0,
+            comparator_name: None,
+        };
+        if version_set.env.file_exists(&version_set.manifest_path) {
+            version_set.replay(true)?;
+        }
+        Ok(version_set)
+    }
+
     /// Recover from an existing manifest file
     fn recover(&mut self) -> Result<()> {
+        self.replay(false)?;
+
+        // Reopen manifest for appending
+        self.manifest_file = Some(self.env.new_appendable_file(&self.manifest_path)?);
+
+        log::info!("Recovered {} files from manifest", self.current.num_files());
+
+        Ok(())
+    }
+
+    /// Applies the edits of the manifest file, except for an unterminated
+    /// last line if `skip_partial_tail` is set
+    fn replay(&mut self, skip_partial_tail: bool) -> Result<()> {
         log::info!("Recovering from manifest: {:?}", self.manifest_path);
 
-        let file = File::open(&self.manifest_path)?;
-        let reader = BufReader::new(file);
+        let mut contents = String::new();
+        self.env
+            .new_sequential_file(&self.manifest_path)?
+            .read_to_string(&mut contents)?;
 
-        for line in reader.lines() {
-            let line = line?;
+        for line in contents.split_inclusive('\n') {
+            if skip_partial_tail && !line.ends_with('\n') {
+                break;
+            }
             if line.trim().is_empty() {
                 continue;
             }
 
-            let edit: VersionEdit = serde_json::from_str(&line)
+            let edit: VersionEdit = serde_json::from_str(line)
                 .map_err(|e| Error::corruption(format!("Failed to parse manifest entry: {}", e)))?;
 
-            self.apply_edit(&edit)?;
-        }
-
-        // Reopen manifest for appending
-        self.manifest_file =
-            Some(OpenOptions::new().create(true).append(true).open(&self.manifest_path)?);
+            // The database may have been written with more levels than
+            // `max_levels`; replay into as many levels as the manifest uses
+            // and check what remains populated afterwards.
+            if let VersionEdit::AddFile { level, .. } | VersionEdit::DeleteFile { level, .. } =
+                &edit
+            {
+                if *level >= self.max_levels.max(MAX_MANIFEST_LEVELS) {
+                    return Err(Error::corruption(format!(
+                        "Manifest entry refers to level {}",
+                        level
+                    )));
+                }
+                if *level >= self.current.levels.len() {
+                    self.current.levels.resize(level + 1, Vec::new());
+                }
+            }
 
-        log::info!("Recovered {} files from manifest", self.current.num_files());
+            self.apply_edit_unchecked(&edit);
+        }
 
+        if let Some(level) = self.current.levels.iter().rposition(|files| !files.is_empty()) {
+            if level >= self.max_levels {
+                return Err(Error::invalid_config(format!(
+                    "database has files at level {} but max_levels is {}; \
+                     reopen with max_levels >= {}",
+                    level,
+                    self.max_levels,
+                    level + 1
+                )));
+            }
+        }
+        self.current.levels.truncate(self.max_levels);
         Ok(())
     }
 
@@ -176,34 +289,64 @@ impl VersionSet {
     fn create_manifest(&mut self) -> Result<()> {
         log::info!("Creating new manifest: {:?}", self.manifest_path);
 
-        self.manifest_file = Some(
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(&self.manifest_path)?,
-        );
+        self.manifest_file = Some(self.env.new_writable_file(&self.manifest_path)?);
+        if let Some(dir) = self.manifest_path.parent() {
+            self.env.sync_dir(dir)?;
+        }
 
         Ok(())
     }
 
     /// Apply a version edit
+    ///
+    /// Returns [`Error::InvalidConfig`] if the edit refers to a level beyond
+    /// `max_levels`.
     pub fn apply_edit(&mut self, edit: &VersionEdit) -> Result<()> {
+        if let VersionEdit::AddFile { level, .. } | VersionEdit::DeleteFile { level, .. } = edit {
+            if *level >= self.max_levels {
+                return Err(Error::invalid_config(format!(
+                    "level {} is out of range for max_levels {}",
+                    level, self.max_levels
+                )));
+            }
+        }
+
+        self.apply_edit_unchecked(edit);
+        Ok(())
+    }
+
+    /// Apply a version edit whose level is known to exist in the current version
+    fn apply_edit_unchecked(&mut self, edit: &VersionEdit) {
         // Update internal state
         match edit {
             VersionEdit::SetNextFileNumber(num) => {
                 self.next_file_number = *num;
             }
-            VersionEdit::SetSequenceNumber(_) => {
-                // Handled by DB
+            VersionEdit::SetSequenceNumber(seq) => {
+                self.last_sequence = *seq;
+            }
+            VersionEdit::SetComparator(name) => {
+                self.comparator_name = Some(name.clone());
             }
             _ => {
+                match *edit {
+                    VersionEdit::AddFile { file_number, .. } => {
+                        self.deleted_files.remove(&file_number);
+                    }
+                    VersionEdit::DeleteFile { file_number, .. } => {
+                        self.deleted_files.insert(file_number);
+                    }
+                    VersionEdit::DropAll { ref file_numbers, sequence } => {
+                        self.deleted_files.extend(file_numbers);
+                        self.dropped_sequence = self.dropped_sequence.max(sequence);
+                        self.last_sequence = self.last_sequence.max(sequence);
+                    }
+                    _ => {}
+                }
                 // Apply to current version
                 self.current = self.current.apply(edit);
             }
         }
-
-        Ok(())
     }
 
     /// Log a version edit to the manifest
@@ -216,22 +359,113 @@ impl VersionSet {
             let json = serde_json::to_string(edit)
                 .map_err(|e| Error::internal(format!("Failed to serialize edit: {}", e)))?;
             writeln!(file, "{}", json)?;
-            file.flush()?;
+            file.sync()?;
         }
 
         Ok(())
     }
 
+    /// Rewrite the manifest from the current state
+    ///
+    /// The new manifest records the current files, the counters and the
+    /// removed files in place of the full edit history. It is written to a
+    /// temporary file that then replaces the old one, so a crash leaves
+    /// either of them intact.
+    pub fn rewrite_manifest(&mut self) -> Result<()> {
+        let mut edits = vec![
+            VersionEdit::SetNextFileNumber(self.next_file_number),
+            VersionEdit::SetSequenceNumber(self.last_sequence),
+        ];
+        if let Some(ref name) = self.comparator_name {
+            edits.push(VersionEdit::SetComparator(name.clone()));
+        }
+        if self.dropped_sequence > 0 {
+            edits.push(VersionEdit::DropAll {
+                file_numbers: Vec::new(),
+                sequence: self.dropped_sequence,
+            });
+        }
+        for (level, files) in self.current.levels.iter().enumerate() {
+            for file in files {
+                edits.push(VersionEdit::AddFile {
+                    level,
+                    file_number: file.file_number,
+                    file_size: file.file_size,
+                    smallest_key: file.smallest_key.clone(),
+                    largest_key: file.largest_key.clone(),
+                });
+            }
+        }
+        let mut deleted: Vec<u64> = self.deleted_files.iter().copied().collect();
+        deleted.sort_unstable();
+        edits.extend(
+            deleted
+                .into_iter()
+                .map(|file_number| VersionEdit::DeleteFile { level: 0, file_number }),
+        );
+
+        let tmp_path = self.manifest_path.with_file_name("MANIFEST.tmp");
+        let mut file = self.env.new_writable_file(&tmp_path)?;
+        for edit in &edits {
+            let json = serde_json::to_string(edit)
+                .map_err(|e| Error::internal(format!("Failed to serialize edit: {}", e)))?;
+            writeln!(file, "{}", json)?;
+        }
+        file.sync()?;
+        drop(file);
+
+        self.manifest_file = None;
+        self.env.rename_file(&tmp_path, &self.manifest_path)?;
+        if let Some(dir) = self.manifest_path.parent() {
+            self.env.sync_dir(dir)?;
+        }
+        self.manifest_file = Some(self.env.new_appendable_file(&self.manifest_path)?);
+
+        log::info!("Rewrote manifest {:?} with {} edits", self.manifest_path, edits.len());
+        Ok(())
+    }
+
+    /// Name of the comparator recorded in the manifest, `None` if it
+    /// records none (databases created before the name was recorded are
+    /// ordered bytewise)
+    pub fn comparator_name(&self) -> Option<&str> {
+        self.comparator_name.as_deref()
+    }
+
     /// Get the current version
     pub fn current(&self) -> &Version {
         &self.current
     }
 
+    /// File numbers of SSTables that were removed from the database.
+    ///
+    /// Removed files are deleted once no reader uses them any more, so a
+    /// crash can leave some on disk; they must not be loaded again.
+    pub fn deleted_files(&self) -> &HashSet<u64> {
+        &self.deleted_files
+    }
+
     /// Get the next file number
     pub fn next_file_number(&self) -> u64 {
         self.next_file_number
     }
 
+    /// Get the last sequence number recorded in the manifest
+    ///
+    /// Writes up to this sequence number have been flushed to SSTables, so
+    /// the database must continue numbering after it even if the WAL is empty.
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    /// Get the sequence number up to which writes were discarded by
+    /// [`VersionEdit::DropAll`]
+    ///
+    /// WAL records up to it must not be replayed.
+    pub fn dropped_sequence(&self) -> u64 {
+        self.dropped_sequence
+    }
+
     /// Allocate a new file number
     pub fn allocate_file_number(&mut self) -> u64 {
         let num = self.next_file_number;
@@ -246,8 +480,8 @@ impl VersionSet {
         for (level_idx, level) in self.current.levels.iter().enumerate() {
             for file_meta in level {
                 let path = db_path.join(format!("{:06}.sst", file_meta.file_number));
-                if path.exists() {
-                    match SSTableReader::open(&path) {
+                if self.env.file_exists(&path) {
+                    match SSTableReader::open_with_env(&path, None, &*self.env) {
                         Ok(reader) => {
                             levels[level_idx].push(Arc::new(reader));
                         }
@@ -362,6 +596,102 @@ mod tests {
         assert_eq!(version_set.current().levels[0].len(), 5);
     }
 
+    #[test]
+    fn test_version_set_recover_with_fewer_levels() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let mut version_set = VersionSet::new(temp_dir.path(), 7).unwrap();
+            for (level, file_number) in [(3, 1), (5, 2)] {
+                let edit = VersionEdit::AddFile {
+                    level,
+                    file_number,
+                    file_size: 1024,
+                    smallest_key: b"a".to_vec(),
+                    largest_key: b"z".to_vec(),
+                };
+                version_set.log_edit(&edit).unwrap();
+            }
+            version_set
+                .log_edit(&VersionEdit::DeleteFile { level: 5, file_number: 2 })
+                .unwrap();
+        }
+
+        // Level 5 is empty again, so 4 levels are enough
+        let version_set = VersionSet::new(temp_dir.path(), 4).unwrap();
+        assert_eq!(version_set.current().levels.len(), 4);
+        assert_eq!(version_set.current().levels[3].len(), 1);
+
+        // Level 3 is populated
+        let result = VersionSet::new(temp_dir.path(), 3);
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_version_set_rewrite_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let mut version_set = VersionSet::new(temp_dir.path(), 7).unwrap();
+            for file_number in 1..=3 {
+                version_set
+                    .log_edit(&VersionEdit::AddFile {
+                        level: 1,
+                        file_number,
+                        file_size: 100,
+                        smallest_key: b"a".to_vec(),
+                        largest_key: b"z".to_vec(),
+                    })
+                    .unwrap();
+            }
+            version_set
+                .log_edit(&VersionEdit::DeleteFile { level: 1, file_number: 2 })
+                .unwrap();
+            version_set.log_edit(&VersionEdit::SetSequenceNumber(42)).unwrap();
+            version_set.rewrite_manifest().unwrap();
+            version_set.log_edit(&VersionEdit::SetNextFileNumber(10)).unwrap();
+        }
+
+        let manifest = std::fs::read_to_string(temp_dir.path().join("MANIFEST")).unwrap();
+        assert_eq!(manifest.lines().count(), 6);
+        let version_set = VersionSet::new(temp_dir.path(), 7).unwrap();
+        let files: Vec<u64> =
+            version_set.current().levels[1].iter().map(|f| f.file_number).collect();
+        assert_eq!(files, vec![1, 3]);
+        assert!(version_set.deleted_files().contains(&2));
+        assert_eq!(version_set.last_sequence(), 42);
+        assert_eq!(version_set.next_file_number(), 10);
+    }
+
+    #[test]
+    fn test_version_set_drop_all() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let mut version_set = VersionSet::new(temp_dir.path(), 7).unwrap();
+            version_set
+                .log_edit(&VersionEdit::AddFile {
+                    level: 2,
+                    file_number: 4,
+                    file_size: 100,
+                    smallest_key: b"a".to_vec(),
+                    largest_key: b"z".to_vec(),
+                })
+                .unwrap();
+            version_set
+                .log_edit(&VersionEdit::DropAll { file_numbers: vec![3, 4], sequence: 20 })
+                .unwrap();
+            assert_eq!(version_set.current().num_files(), 0);
+            version_set.rewrite_manifest().unwrap();
+        }
+
+        // The dropped sequence survives a rewrite of the manifest
+        let version_set = VersionSet::new(temp_dir.path(), 7).unwrap();
+        assert_eq!(version_set.current().num_files(), 0);
+        assert!(version_set.deleted_files().contains(&3));
+        assert!(version_set.deleted_files().contains(&4));
+        assert_eq!(version_set.dropped_sequence(), 20);
+        assert_eq!(version_set.last_sequence(), 20);
+    }
+
     #[test]
     fn test_version_set_allocate_file_number() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,176 @@
+//! Key ordering.
+//!
+//! A [`Comparator`] defines the order of the keys of a database: the order
+//! of MemTable entries, of the entries and blocks of SSTables, of the merge
+//! done by iterators and compactions, and of the key ranges of files. The
+//! default, [`BytewiseComparator`], compares keys as byte strings.
+//!
+//! A database is created with the comparator of
+//! [`Options::comparator`](crate::Options::comparator) and its name is
+//! recorded in the manifest. Opening it again with a comparator of another
+//! name fails with [`Error::InvalidConfig`](crate::Error::InvalidConfig),
+//! since its files are sorted in the old order.
+//!
+//! [`BytewiseTimestampComparator`] orders keys that end in a user
+//! [`timestamp`](crate::timestamp), keeping the versions of a key together
+//! from the newest down.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::comparator::Comparator;
+//! use aidb::{Options, DB};
+//! use std::cmp::Ordering;
+//! use std::sync::Arc;
+//!
+//! /// Orders 8-byte big-endian keys from the largest number down
+//! struct Descending;
+//!
+//! impl Comparator for Descending {
+//!     fn name(&self) -> &str {
+//!         "example.Descending"
+//!     }
+//!
+//!     fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+//!         b.cmp(a)
+//!     }
+//! }
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let options = Options::default().comparator(Arc::new(Descending));
+//! let db = DB::open("./data", options)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+/// Name of [`BytewiseComparator`]
+pub const BYTEWISE_COMPARATOR_NAME: &str = "aidb.BytewiseComparator";
+
+/// Name of [`BytewiseTimestampComparator`]
+pub const BYTEWISE_TIMESTAMP_COMPARATOR_NAME: &str = "aidb.BytewiseComparator.u64ts";
+
+/// A total order over keys.
+///
+/// `compare` must only return [`Ordering::Equal`] for identical keys:
+/// keys with different bytes are different keys, even if the order puts
+/// them side by side (a case-insensitive order, for example, breaks ties
+/// by comparing the bytes). Features working on key prefixes
+/// ([`PrefixExtractor`](crate::config::PrefixExtractor), prefix scans and
+/// deletions, buckets) expect the keys starting with a prefix to sort
+/// together, right after the prefix itself, as they do bytewise.
+pub trait Comparator: Send + Sync {
+    /// Name recorded in the manifest; a database can only be opened with a
+    /// comparator of the name it was created with. Change it whenever the
+    /// order changes.
+    fn name(&self) -> &str;
+
+    /// Compares two keys.
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+
+    /// Size of the timestamp every key ends in (see
+    /// [`timestamp`](crate::timestamp)), 0 if keys have none. Only 0 and
+    /// [`TIMESTAMP_SIZE`](crate::timestamp::TIMESTAMP_SIZE) are supported.
+    fn timestamp_size(&self) -> usize {
+        0
+    }
+}
+
+impl fmt::Debug for dyn Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Comparator({})", self.name())
+    }
+}
+
+/// Orders keys as byte strings, the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BytewiseComparator;
+
+impl Comparator for BytewiseComparator {
+    fn name(&self) -> &str {
+        BYTEWISE_COMPARATOR_NAME
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Orders keys ending in a [`timestamp`](crate::timestamp) by the bytes
+/// before it, then from the newest timestamp down.
+///
+/// Keys shorter than a timestamp are compared as a whole, after the keys
+/// with the same bytes and a timestamp. Features working on key prefixes
+/// work as long as the prefixes are not longer than the keys without their
+/// timestamps.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BytewiseTimestampComparator;
+
+impl Comparator for BytewiseTimestampComparator {
+    fn name(&self) -> &str {
+        BYTEWISE_TIMESTAMP_COMPARATOR_NAME
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        let (a_key, a_timestamp) = crate::timestamp::split_bytes(a);
+        let (b_key, b_timestamp) = crate::timestamp::split_bytes(b);
+        // Big-endian timestamps compare as numbers
+        a_key.cmp(b_key).then_with(|| b_timestamp.cmp(a_timestamp))
+    }
+
+    fn timestamp_size(&self) -> usize {
+        crate::timestamp::TIMESTAMP_SIZE
+    }
+}
+
+/// Returns the shared [`BytewiseComparator`], the default of
+/// [`Options::comparator`](crate::Options::comparator).
+pub fn default_comparator() -> Arc<dyn Comparator> {
+    static DEFAULT: OnceLock<Arc<dyn Comparator>> = OnceLock::new();
+    Arc::clone(DEFAULT.get_or_init(|| Arc::new(BytewiseComparator)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytewise_comparator() {
+        let comparator = default_comparator();
+        assert_eq!(comparator.name(), BYTEWISE_COMPARATOR_NAME);
+        assert_eq!(comparator.compare(b"a", b"b"), Ordering::Less);
+        assert_eq!(comparator.compare(b"ab", b"a"), Ordering::Greater);
+        assert_eq!(comparator.compare(b"a", b"a"), Ordering::Equal);
+        assert_eq!(format!("{:?}", comparator), "Comparator(aidb.BytewiseComparator)");
+        assert_eq!(comparator.timestamp_size(), 0);
+    }
+
+    #[test]
+    fn test_bytewise_timestamp_comparator() {
+        use crate::timestamp::key_with_timestamp;
+
+        let comparator = BytewiseTimestampComparator;
+        assert_eq!(comparator.timestamp_size(), 8);
+        let mut keys = vec![
+            key_with_timestamp(b"b", 1),
+            key_with_timestamp(b"a", 1),
+            key_with_timestamp(b"a", 300),
+            b"a".to_vec(),
+            key_with_timestamp(b"ab", 2),
+        ];
+        keys.sort_by(|a, b| comparator.compare(a, b));
+        assert_eq!(
+            keys,
+            vec![
+                key_with_timestamp(b"a", 300),
+                key_with_timestamp(b"a", 1),
+                b"a".to_vec(),
+                key_with_timestamp(b"ab", 2),
+                key_with_timestamp(b"b", 1),
+            ]
+        );
+    }
+}
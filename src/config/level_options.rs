@@ -0,0 +1,94 @@
+//! Per-level overrides for SSTable settings.
+//!
+//! Data at different levels of the LSM-tree has very different access
+//! patterns: Level 0/1 files are short-lived and rewritten often, while the
+//! bottom level holds most of the data and is rarely rewritten. A
+//! [`LevelOptions`] entry lets a single level use its own compression,
+//! target file size and Bloom filter settings instead of the global ones.
+
+use super::CompressionType;
+use serde::{Deserialize, Serialize};
+
+/// Settings that override the global [`Options`](super::Options) for
+/// SSTables written to one level.
+///
+/// Every field is optional; `None` falls back to the global value.
+///
+/// # Example
+///
+/// ```rust
+/// use aidb::config::{CompressionType, LevelOptions};
+/// use aidb::Options;
+///
+/// // No compression at L0/L1, larger files at L2
+/// let options = Options::default()
+///     .level_options(0, LevelOptions::new().compression(CompressionType::None))
+///     .level_options(1, LevelOptions::new().compression(CompressionType::None))
+///     .level_options(2, LevelOptions::new().target_file_size(64 * 1024 * 1024));
+///
+/// assert_eq!(options.compression_for_level(0), CompressionType::None);
+/// assert_eq!(options.target_file_size_for_level(2), Some(64 * 1024 * 1024));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelOptions {
+    /// Compression algorithm for data blocks at this level
+    pub compression: Option<CompressionType>,
+    /// Maximum size of a single SSTable at this level in bytes.
+    /// Compaction output is split into several files once this is reached.
+    pub target_file_size: Option<usize>,
+    /// Whether to build Bloom filters for SSTables at this level
+    pub use_bloom_filter: Option<bool>,
+    /// Bloom filter false positive rate at this level
+    pub bloom_filter_fp_rate: Option<f64>,
+}
+
+impl LevelOptions {
+    /// Creates an empty set of overrides.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the compression algorithm.
+    pub fn compression(mut self, compression: CompressionType) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Sets the target file size.
+    pub fn target_file_size(mut self, size: usize) -> Self {
+        self.target_file_size = Some(size);
+        self
+    }
+
+    /// Enables or disables Bloom filters.
+    pub fn use_bloom_filter(mut self, enabled: bool) -> Self {
+        self.use_bloom_filter = Some(enabled);
+        self
+    }
+
+    /// Sets the Bloom filter false positive rate.
+    pub fn bloom_filter_fp_rate(mut self, rate: f64) -> Self {
+        self.bloom_filter_fp_rate = Some(rate);
+        self
+    }
+
+    /// Validates the overrides for the given level.
+    pub(crate) fn validate(&self, level: usize) -> crate::Result<()> {
+        if self.target_file_size == Some(0) {
+            return Err(crate::Error::invalid_argument(format!(
+                "level {} target_file_size must be > 0",
+                level
+            )));
+        }
+        if let Some(rate) = self.bloom_filter_fp_rate {
+            if rate <= 0.0 || rate >= 1.0 {
+                return Err(crate::Error::invalid_argument(format!(
+                    "level {} bloom_filter_fp_rate must be between 0 and 1",
+                    level
+                )));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+//! Time windows that change how fast compactions write.
+//!
+//! Compactions are charged to [`Options::rate_limiter`](super::Options::rate_limiter)
+//! at low priority. With [`Options::compaction_windows`](super::Options::compaction_windows)
+//! set, a compaction output started inside a window is charged at the
+//! window's rate multiplier instead, so the limiter can hold compactions
+//! back during the day and let them run at full speed overnight, without
+//! changing its rate by hand. Outside every window the limiter's own rate
+//! applies. Flushes are not affected.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A daily time window, in UTC, with the factor applied to the compaction
+/// rate during it.
+///
+/// The window starts at `start` and ends before `end`, both in minutes
+/// after midnight; if `end` is not after `start` it runs past midnight, and
+/// if they are equal it covers the whole day. A multiplier of
+/// `f64::INFINITY` lets compactions write without waiting for the limiter.
+///
+/// In the OPTIONS file and [`Options::set_option`](super::Options::set_option)
+/// a window is written as `HH:MM-HH:MM*<multiplier>`, and a list of them
+/// separated by commas.
+///
+/// # Example
+///
+/// ```rust
+/// use aidb::config::MaintenanceWindow;
+///
+/// // From 22:00 to 06:00 UTC at full speed
+/// let night = MaintenanceWindow::new(22 * 60, 6 * 60, f64::INFINITY);
+/// assert!(night.contains(23 * 60));
+/// assert!(!night.contains(12 * 60));
+/// assert_eq!(MaintenanceWindow::from_name("22:00-06:00*inf"), Some(night));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MaintenanceWindow {
+    /// First minute of the window, after midnight UTC
+    pub start: u16,
+    /// Minute after the window, after midnight UTC
+    pub end: u16,
+    /// Factor applied to the rate of the limiter
+    pub rate_multiplier: f64,
+}
+
+impl MaintenanceWindow {
+    /// Creates a window from `start` to `end`, in minutes after midnight
+    /// UTC, taken modulo one day.
+    pub fn new(start: u16, end: u16, rate_multiplier: f64) -> Self {
+        Self { start: start % MINUTES_PER_DAY, end: end % MINUTES_PER_DAY, rate_multiplier }
+    }
+
+    /// Whether `minute` (after midnight UTC) lies in the window.
+    pub fn contains(&self, minute: u16) -> bool {
+        match self.start.cmp(&self.end) {
+            std::cmp::Ordering::Less => (self.start..self.end).contains(&minute),
+            std::cmp::Ordering::Greater => minute >= self.start || minute < self.end,
+            std::cmp::Ordering::Equal => true,
+        }
+    }
+
+    /// Returns the window as written in the OPTIONS file.
+    pub fn name(&self) -> String {
+        let time = |minute: u16| format!("{:02}:{:02}", minute / 60, minute % 60);
+        format!("{}-{}*{}", time(self.start), time(self.end), self.rate_multiplier)
+    }
+
+    /// Parses a window written as `HH:MM-HH:MM*<multiplier>`. The
+    /// multiplier must be positive.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let time = |time: &str| -> Option<u16> {
+            let (hours, minutes) = time.trim().split_once(':')?;
+            let (hours, minutes): (u16, u16) = (hours.parse().ok()?, minutes.parse().ok()?);
+            (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+        };
+        let (range, multiplier) = name.split_once('*')?;
+        let (start, end) = range.split_once('-')?;
+        let multiplier: f64 = multiplier.trim().parse().ok()?;
+        (multiplier > 0.0).then_some(Self::new(time(start)?, time(end)?, multiplier))
+    }
+
+    /// Parses a comma-separated list of windows; an empty string is no
+    /// window.
+    pub fn parse_list(list: &str) -> Option<Vec<Self>> {
+        list.split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(Self::from_name)
+            .collect()
+    }
+}
+
+/// Rate multiplier of the first of `windows` that contains the time
+/// `now_micros` (microseconds since the epoch), or 1 outside all of them
+pub(crate) fn current_rate_multiplier(windows: &[MaintenanceWindow], now_micros: u64) -> f64 {
+    if windows.is_empty() {
+        return 1.0;
+    }
+    let minute = ((now_micros / 60_000_000) % MINUTES_PER_DAY as u64) as u16;
+    windows.iter().find(|w| w.contains(minute)).map_or(1.0, |w| w.rate_multiplier)
+}
+
+impl fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+impl From<MaintenanceWindow> for String {
+    fn from(window: MaintenanceWindow) -> Self {
+        window.name()
+    }
+}
+
+impl TryFrom<String> for MaintenanceWindow {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::from_name(&name).ok_or_else(|| format!("invalid maintenance window '{}'", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_window() {
+        let day = MaintenanceWindow::from_name("09:30-17:00*0.5").unwrap();
+        assert_eq!((day.start, day.end, day.rate_multiplier), (570, 1020, 0.5));
+        assert!(day.contains(570) && day.contains(1019));
+        assert!(!day.contains(1020) && !day.contains(0));
+        assert_eq!(day.name(), "09:30-17:00*0.5");
+
+        let always = MaintenanceWindow::new(0, 24 * 60, 2.0);
+        assert!(always.contains(0) && always.contains(1439));
+        assert_eq!(current_rate_multiplier(&[always], 0), 2.0);
+        assert_eq!(current_rate_multiplier(&[], 0), 1.0);
+        assert_eq!(current_rate_multiplier(&[day], 600 * 60_000_000), 0.5);
+        assert_eq!(current_rate_multiplier(&[day], 1020 * 60_000_000), 1.0);
+
+        for invalid in ["", "09:30-17:00", "24:00-01:00*1", "09:30-17:00*0", "9-17*1"] {
+            assert_eq!(MaintenanceWindow::from_name(invalid), None, "{}", invalid);
+        }
+        let windows = MaintenanceWindow::parse_list("22:00-06:00*inf, 12:00-13:00*2").unwrap();
+        assert_eq!(windows.len(), 2);
+        assert!(windows[0].rate_multiplier.is_infinite());
+        assert_eq!(MaintenanceWindow::parse_list(""), Some(Vec::new()));
+        assert_eq!(MaintenanceWindow::parse_list("12:00-13:00*2,x"), None);
+    }
+}
@@ -0,0 +1,1859 @@
+//! Configuration options for AiDb storage engine.
+
+mod level_options;
+mod maintenance_window;
+mod options_file;
+mod prefix_extractor;
+mod read_options;
+
+pub use level_options::LevelOptions;
+pub(crate) use maintenance_window::current_rate_multiplier;
+pub use maintenance_window::MaintenanceWindow;
+pub use options_file::{OptionsFile, FORMAT_VERSION, OPTIONS_FILE_PREFIX};
+pub use prefix_extractor::PrefixExtractor;
+pub use read_options::ReadOptions;
+
+use crate::comparator::Comparator;
+use crate::env::{Env, KeyProvider};
+use crate::listener::EventListener;
+use crate::rate_limiter::RateLimiter;
+use crate::sst_file_manager::SstFileManager;
+use crate::statistics::Statistics;
+use crate::write_buffer_manager::WriteBufferManager;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Configuration options for opening a database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    /// Create the database if it doesn't exist.
+    /// Default: true
+    pub create_if_missing: bool,
+
+    /// Error if the database already exists.
+    /// Default: false
+    pub error_if_exists: bool,
+
+    /// Size threshold for flushing MemTable to SSTable (in bytes).
+    /// Default: 4MB
+    pub memtable_size: usize,
+
+    /// Maximum number of Level 0 files before triggering compaction.
+    /// Default: 4
+    pub level0_compaction_threshold: usize,
+
+    /// Size multiplier between levels.
+    /// Default: 10 (Level N+1 is 10x larger than Level N)
+    pub level_size_multiplier: usize,
+
+    /// Base level size (Level 1 target size in bytes).
+    /// Default: 10MB
+    pub base_level_size: usize,
+
+    /// Estimated pending compaction bytes above which each write is
+    /// delayed by 1ms (0 = off).
+    /// Default: 64GB
+    pub soft_pending_compaction_bytes_limit: u64,
+
+    /// Estimated pending compaction bytes above which writes wait for
+    /// compactions to bring the estimate back under the limit (0 = off).
+    /// Default: 256GB
+    pub hard_pending_compaction_bytes_limit: u64,
+
+    /// Maximum number of levels.
+    /// Default: 7 (Level 0 through Level 6)
+    pub max_levels: usize,
+
+    /// Block size for SSTables (in bytes).
+    /// Default: 4KB
+    pub block_size: usize,
+
+    /// Block cache size (in bytes).
+    /// Set to 0 to disable caching.
+    /// Default: 8MB
+    pub block_cache_size: usize,
+
+    /// Maximum number of SSTable files kept open (0 = no limit). Above it
+    /// the least recently read files are closed and reopened on demand.
+    /// Default: 0
+    pub max_open_files: usize,
+
+    /// Advise the OS that compactions read their inputs sequentially and
+    /// that neither inputs nor outputs need to stay in the page cache, so
+    /// compactions do not evict the pages foreground reads use. Only has
+    /// an effect on Linux.
+    /// Default: true
+    pub advise_compaction_io: bool,
+
+    /// Read every compaction output back before it replaces the inputs:
+    /// all block checksums, the key order and the number of entries are
+    /// checked. A mismatch fails the compaction with
+    /// [`Error::Corruption`](crate::Error::Corruption), deletes the outputs
+    /// and keeps the inputs. Costs one extra read of the written data.
+    /// Default: false
+    pub verify_compaction_output: bool,
+
+    /// Keep the versions of keys that reads at sequence numbers written in
+    /// the last this many seconds can see, for
+    /// [`DB::get_at_sequence`](crate::DB::get_at_sequence), instead of only
+    /// the newest version of each key (0 = newest only).
+    /// Default: 0
+    pub preserve_versions_seconds: u64,
+
+    /// With a comparator that orders [`timestamp`](crate::timestamp)s, keep
+    /// the versions of keys that reads at timestamps no older than the
+    /// newest timestamp written minus this can see (0 = keep every version).
+    /// Default: 0
+    pub timestamp_history_window: u64,
+
+    /// Enable bloom filter for SSTables.
+    /// Default: true
+    pub use_bloom_filter: bool,
+
+    /// Bloom filter false positive rate.
+    /// Default: 0.01 (1%)
+    pub bloom_filter_fp_rate: f64,
+
+    /// Extracts key prefixes that are added to SSTable Bloom filters, for
+    /// prefix iteration. Tables written with another extractor (or none)
+    /// are read without using their filter for prefix seeks.
+    /// Default: PrefixExtractor::None
+    pub prefix_extractor: PrefixExtractor,
+
+    /// Compression algorithm for SSTables.
+    /// Default: CompressionType::Snappy
+    pub compression: CompressionType,
+
+    /// Checksum algorithm for new SSTable blocks.
+    /// Existing files keep the algorithm recorded in their footer.
+    /// Default: ChecksumType::Crc32
+    pub checksum: ChecksumType,
+
+    /// Enable write-ahead log (WAL).
+    /// Disabling reduces durability but increases performance.
+    /// Default: true
+    pub use_wal: bool,
+
+    /// Sync WAL writes to disk.
+    /// Default: true
+    pub sync_wal: bool,
+
+    /// Let the WAL write of one write group overlap with the MemTable
+    /// insertion of the previous group. Writes still become visible in
+    /// sequence order. Mostly helps when `sync_wal` is on.
+    /// Default: false
+    pub enable_pipelined_write: bool,
+
+    /// Size in bytes of the buffer WAL records are collected in before they
+    /// are written to the file (0 = write every record immediately).
+    /// Without `sync_wal`, buffered records are lost if the process crashes.
+    /// Default: 64KB
+    pub wal_buffer_size: usize,
+
+    /// Total size in bytes of the WAL files kept in the `archive`
+    /// directory after they are rotated out, so that
+    /// `DB::get_updates_since` can still read writes that have been
+    /// flushed. The oldest archived files are deleted first. 0 deletes WAL
+    /// files when they are rotated out.
+    /// Default: 0
+    pub wal_archive_size_limit: u64,
+
+    /// Number of threads that apply WAL entries to the MemTable when the
+    /// database is opened (0 or 1 = apply them on the opening thread).
+    /// Default: 4
+    pub wal_recovery_threads: usize,
+
+    /// Number of background compaction threads.
+    /// Default: 1
+    pub compaction_threads: usize,
+
+    /// Number of immutable MemTables written to SSTables at the same time
+    /// when several are waiting to be flushed (0 or 1 = one at a time).
+    /// Default: 2
+    pub flush_threads: usize,
+
+    /// Per-level overrides, indexed by level.
+    /// Levels without an entry use the global settings.
+    /// Default: empty
+    pub level_options: Vec<LevelOptions>,
+
+    /// Move SSTables that cannot be opened to the `lost/` subdirectory at
+    /// open instead of just skipping them.
+    /// Default: false
+    pub quarantine_corrupt_files: bool,
+
+    /// With `quarantine_corrupt_files`, allow the database to open after
+    /// quarantining files. When false, open fails with a corruption error
+    /// and leaves the files in place.
+    /// Default: true
+    pub allow_data_loss: bool,
+
+    /// Maximum key size in bytes accepted by `put`, `delete` and `write`.
+    /// Default: 64KB
+    pub max_key_size: usize,
+
+    /// Maximum value size in bytes accepted by `put` and `write`.
+    /// Values are held in the MemTable and written as a single WAL record and
+    /// SSTable entry, so very large values should be split by the caller (or
+    /// stored outside the database) until a streaming write path exists.
+    /// Default: 64MB
+    pub max_value_size: usize,
+
+    /// Maximum size in bytes of a batch passed to `write`, as returned by
+    /// `WriteBatch::data_size`. A batch is held in the MemTable and
+    /// written as a single WAL record, so one far larger than
+    /// `memtable_size` defeats flushing. 0 means no limit.
+    /// Default: 0
+    pub max_batch_bytes: usize,
+
+    /// Maximum number of operations in a batch passed to `write`.
+    /// 0 means no limit.
+    /// Default: 0
+    pub max_batch_ops: usize,
+
+    /// Space in bytes reserved on disk at open (in a `RESERVED` file).
+    /// When the disk fills up the reservation is released so that
+    /// recovering from the error has room to flush and update metadata.
+    /// Default: 1MB
+    pub reserved_disk_space: u64,
+
+    /// Collects tickers and latency histograms when set. Not persisted in
+    /// the OPTIONS file.
+    /// Default: None
+    #[serde(skip)]
+    pub statistics: Option<Arc<Statistics>>,
+
+    /// Tracks the space used by SSTables and paces their deletion; may be
+    /// shared by several databases. Not persisted in the OPTIONS file.
+    /// Default: None
+    #[serde(skip)]
+    pub sst_file_manager: Option<Arc<SstFileManager>>,
+
+    /// Budget for the MemTable memory of all databases sharing it. Not
+    /// persisted in the OPTIONS file.
+    /// Default: None
+    #[serde(skip)]
+    pub write_buffer_manager: Option<Arc<WriteBufferManager>>,
+
+    /// Limiter of the bytes flushes and compactions write per second,
+    /// possibly shared by several databases. Not persisted in the OPTIONS
+    /// file.
+    /// Default: None
+    #[serde(skip)]
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// Daily windows in which compactions are charged to `rate_limiter` at
+    /// a multiple of its rate, e.g. at full speed overnight. Outside them
+    /// its own rate applies. See [`MaintenanceWindow`].
+    /// Default: none
+    pub compaction_windows: Vec<MaintenanceWindow>,
+
+    /// Write engine events to a `LOG` file in the database directory.
+    /// Default: true
+    pub info_log: bool,
+
+    /// Size in bytes at which the `LOG` file is rotated (0 = no limit).
+    /// Default: 16MB
+    pub max_log_file_size: u64,
+
+    /// Age in seconds after which the `LOG` file is rotated (0 = never).
+    /// Default: 0
+    pub log_file_time_to_roll: u64,
+
+    /// Number of rotated `LOG.old.*` files to keep.
+    /// Default: 10
+    pub keep_log_file_num: usize,
+
+    /// Log `get` calls that take at least this many milliseconds, with their
+    /// [`PerfContext`](crate::perf_context::PerfContext) counters (0 = off).
+    /// Default: 0
+    pub slow_get_threshold_ms: u64,
+
+    /// Log `put`, `delete` and `write` calls that take at least this many
+    /// milliseconds (0 = off).
+    /// Default: 0
+    pub slow_write_threshold_ms: u64,
+
+    /// Log iterator creation and seeks that take at least this many
+    /// milliseconds (0 = off).
+    /// Default: 0
+    pub slow_iterator_threshold_ms: u64,
+
+    /// Callbacks notified of flushes, compactions, SSTable and WAL changes
+    /// and background errors. Not persisted in the OPTIONS file.
+    /// Default: empty
+    #[serde(skip)]
+    pub listeners: Vec<Arc<dyn EventListener>>,
+
+    /// Storage backend used for all file access. Not persisted in the
+    /// OPTIONS file.
+    /// Default: the local filesystem ([`PosixEnv`](crate::env::PosixEnv))
+    #[serde(skip)]
+    pub env: Arc<dyn Env>,
+
+    /// Order of the keys (see [`comparator`](crate::comparator)). Must be
+    /// the one the database was created with; its name is checked on open.
+    /// Not persisted in the OPTIONS file.
+    /// Default: [`BytewiseComparator`](crate::comparator::BytewiseComparator)
+    #[serde(skip)]
+    pub comparator: Arc<dyn Comparator>,
+
+    /// Encrypt every file at rest with the key it provides, by wrapping
+    /// `env` in an `EncryptedEnv`. Needs the `encryption` feature. Not
+    /// persisted in the OPTIONS file.
+    /// Default: None
+    #[serde(skip)]
+    pub key_provider: Option<Arc<dyn KeyProvider>>,
+
+    /// Append an HMAC tag to every SSTable entry and WAL record and verify
+    /// it on read, with keys derived from those of `key_provider` (see
+    /// [`record_auth`](crate::record_auth)). Must be set when the database
+    /// is created: files written without tags fail to verify.
+    /// Default: false
+    pub authenticate_records: bool,
+
+    /// Milliseconds after which a read on a database opened with
+    /// [`DB::open_as_secondary`](crate::DB::open_as_secondary) first
+    /// catches up with the primary (see [`secondary`](crate::secondary)).
+    /// 0 = only catch up in `try_catch_up_with_primary`.
+    /// Default: 1000
+    pub secondary_refresh_interval_ms: u64,
+}
+
+/// Prefix of environment variables read by [`Options::with_env_overrides`].
+pub const ENV_PREFIX: &str = "AIDB_";
+
+/// Extracts option overrides from environment variables.
+///
+/// `AIDB_BLOCK_CACHE_SIZE` becomes `block_cache_size`; variables without the
+/// prefix are skipped.
+fn env_overrides(vars: impl IntoIterator<Item = (String, String)>) -> Vec<(String, String)> {
+    vars.into_iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(ENV_PREFIX).map(|name| (name.to_ascii_lowercase(), value))
+        })
+        .collect()
+}
+
+/// Options that can be changed on a running database via
+/// [`DB::set_options`](crate::DB::set_options).
+///
+/// Changes to these apply to subsequent writes, flushes and compactions;
+/// existing SSTables are not rewritten.
+pub const MUTABLE_OPTIONS: &[&str] = &[
+    "memtable_size",
+    "level0_compaction_threshold",
+    "level_size_multiplier",
+    "base_level_size",
+    "soft_pending_compaction_bytes_limit",
+    "hard_pending_compaction_bytes_limit",
+    "advise_compaction_io",
+    "compaction_windows",
+    "verify_compaction_output",
+    "preserve_versions_seconds",
+    "timestamp_history_window",
+    "block_size",
+    "block_cache_size",
+    "use_bloom_filter",
+    "bloom_filter_fp_rate",
+    "prefix_extractor",
+    "compression",
+    "checksum",
+    "sync_wal",
+    "enable_pipelined_write",
+    "wal_archive_size_limit",
+    "flush_threads",
+    "max_key_size",
+    "max_value_size",
+    "max_batch_bytes",
+    "max_batch_ops",
+    "slow_get_threshold_ms",
+    "slow_write_threshold_ms",
+    "slow_iterator_threshold_ms",
+];
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            create_if_missing: true,
+            error_if_exists: false,
+            memtable_size: 4 * 1024 * 1024, // 4MB
+            level0_compaction_threshold: 4,
+            level_size_multiplier: 10,
+            base_level_size: 10 * 1024 * 1024, // 10MB
+            soft_pending_compaction_bytes_limit: 64 * 1024 * 1024 * 1024, // 64GB
+            hard_pending_compaction_bytes_limit: 256 * 1024 * 1024 * 1024, // 256GB
+            max_levels: 7,
+            block_size: 4 * 1024,              // 4KB
+            block_cache_size: 8 * 1024 * 1024, // 8MB
+            max_open_files: 0,
+            advise_compaction_io: true,
+            verify_compaction_output: false,
+            preserve_versions_seconds: 0,
+            timestamp_history_window: 0,
+            use_bloom_filter: true,
+            bloom_filter_fp_rate: 0.01,
+            prefix_extractor: PrefixExtractor::None,
+            compression: CompressionType::Snappy,
+            checksum: ChecksumType::Crc32,
+            use_wal: true,
+            sync_wal: true,
+            enable_pipelined_write: false,
+            wal_buffer_size: 64 * 1024, // 64KB
+            wal_archive_size_limit: 0,
+            wal_recovery_threads: 4,
+            compaction_threads: 1,
+            flush_threads: 2,
+            level_options: Vec::new(),
+            quarantine_corrupt_files: false,
+            allow_data_loss: true,
+            max_key_size: 64 * 1024,          // 64KB
+            max_value_size: 64 * 1024 * 1024, // 64MB
+            max_batch_bytes: 0,
+            max_batch_ops: 0,
+            reserved_disk_space: 1024 * 1024, // 1MB
+            info_log: true,
+            max_log_file_size: 16 * 1024 * 1024, // 16MB
+            log_file_time_to_roll: 0,
+            keep_log_file_num: 10,
+            slow_get_threshold_ms: 0,
+            slow_write_threshold_ms: 0,
+            slow_iterator_threshold_ms: 0,
+            statistics: None,
+            sst_file_manager: None,
+            write_buffer_manager: None,
+            rate_limiter: None,
+            compaction_windows: Vec::new(),
+            listeners: Vec::new(),
+            env: crate::env::default_env(),
+            comparator: crate::comparator::default_comparator(),
+            key_provider: None,
+            authenticate_records: false,
+            secondary_refresh_interval_ms: 1000,
+        }
+    }
+}
+
+/// Compression algorithms supported by AiDb.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum CompressionType {
+    /// No compression.
+    None = 0,
+
+    /// Snappy compression (fast, moderate compression ratio).
+    #[cfg(feature = "snappy")]
+    Snappy = 1,
+
+    /// LZ4 compression (very fast, lower compression ratio).
+    #[cfg(feature = "lz4-compression")]
+    Lz4 = 2,
+}
+
+impl CompressionType {
+    /// Returns the name used for this compression type in the OPTIONS file.
+    pub fn name(self) -> &'static str {
+        match self {
+            CompressionType::None => "none",
+            #[cfg(feature = "snappy")]
+            CompressionType::Snappy => "snappy",
+            #[cfg(feature = "lz4-compression")]
+            CompressionType::Lz4 => "lz4",
+        }
+    }
+
+    /// Parses a compression name as written in the OPTIONS file.
+    ///
+    /// Returns `None` if the name is unknown or the corresponding feature is
+    /// not enabled in this build.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(CompressionType::None),
+            #[cfg(feature = "snappy")]
+            "snappy" => Some(CompressionType::Snappy),
+            #[cfg(feature = "lz4-compression")]
+            "lz4" => Some(CompressionType::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Convert from u8
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(CompressionType::None),
+            #[cfg(feature = "snappy")]
+            1 => Some(CompressionType::Snappy),
+            #[cfg(feature = "lz4-compression")]
+            2 => Some(CompressionType::Lz4),
+            _ => None,
+        }
+    }
+}
+
+/// Checksum algorithms for SSTable blocks.
+///
+/// The algorithm used by a file is recorded in its footer, so files written
+/// with different algorithms can be read side by side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum ChecksumType {
+    /// CRC32 (IEEE). Used by all files written before the checksum type was recorded.
+    #[default]
+    Crc32 = 0,
+
+    /// CRC32C (Castagnoli), hardware accelerated with SSE4.2 / ARMv8 CRC instructions.
+    Crc32c = 1,
+
+    /// XXH3 (64-bit, truncated to 32 bits). Fastest on CPUs without CRC instructions.
+    Xxh3 = 2,
+}
+
+impl ChecksumType {
+    /// Computes the checksum of `data`.
+    pub fn checksum(self, data: &[u8]) -> u32 {
+        match self {
+            ChecksumType::Crc32 => crc32fast::hash(data),
+            ChecksumType::Crc32c => crc32c::crc32c(data),
+            ChecksumType::Xxh3 => xxhash_rust::xxh3::xxh3_64(data) as u32,
+        }
+    }
+
+    /// Returns the name used for this checksum type in the OPTIONS file.
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumType::Crc32 => "crc32",
+            ChecksumType::Crc32c => "crc32c",
+            ChecksumType::Xxh3 => "xxh3",
+        }
+    }
+
+    /// Parses a checksum type name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "crc32" => Some(ChecksumType::Crc32),
+            "crc32c" => Some(ChecksumType::Crc32c),
+            "xxh3" => Some(ChecksumType::Xxh3),
+            _ => None,
+        }
+    }
+
+    /// Converts from the id stored in an SSTable footer.
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ChecksumType::Crc32),
+            1 => Some(ChecksumType::Crc32c),
+            2 => Some(ChecksumType::Xxh3),
+            _ => None,
+        }
+    }
+}
+
+impl Default for CompressionType {
+    fn default() -> Self {
+        #[cfg(feature = "snappy")]
+        return CompressionType::Snappy;
+
+        #[cfg(not(feature = "snappy"))]
+        CompressionType::None
+    }
+}
+
+impl Options {
+    /// Creates a new Options with default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to create the database if it doesn't exist.
+    pub fn create_if_missing(mut self, value: bool) -> Self {
+        self.create_if_missing = value;
+        self
+    }
+
+    /// Sets whether to error if the database already exists.
+    pub fn error_if_exists(mut self, value: bool) -> Self {
+        self.error_if_exists = value;
+        self
+    }
+
+    /// Sets the MemTable size threshold.
+    pub fn memtable_size(mut self, size: usize) -> Self {
+        self.memtable_size = size;
+        self
+    }
+
+    /// Sets the Level 0 compaction threshold.
+    pub fn level0_compaction_threshold(mut self, threshold: usize) -> Self {
+        self.level0_compaction_threshold = threshold;
+        self
+    }
+
+    /// Sets the compaction debt above which writes are delayed.
+    pub fn soft_pending_compaction_bytes_limit(mut self, bytes: u64) -> Self {
+        self.soft_pending_compaction_bytes_limit = bytes;
+        self
+    }
+
+    /// Sets the compaction debt above which writes wait for compactions.
+    pub fn hard_pending_compaction_bytes_limit(mut self, bytes: u64) -> Self {
+        self.hard_pending_compaction_bytes_limit = bytes;
+        self
+    }
+
+    /// Sets the maximum number of SSTable files kept open.
+    pub fn max_open_files(mut self, count: usize) -> Self {
+        self.max_open_files = count;
+        self
+    }
+
+    /// Enables or disables page cache hints for compaction I/O.
+    pub fn advise_compaction_io(mut self, value: bool) -> Self {
+        self.advise_compaction_io = value;
+        self
+    }
+
+    /// Enables or disables reading compaction outputs back before they
+    /// are installed.
+    pub fn verify_compaction_output(mut self, value: bool) -> Self {
+        self.verify_compaction_output = value;
+        self
+    }
+
+    /// Sets how many seconds of old versions flushes and compactions keep.
+    pub fn preserve_versions_seconds(mut self, seconds: u64) -> Self {
+        self.preserve_versions_seconds = seconds;
+        self
+    }
+
+    /// Sets how far back from the newest user timestamp compactions keep
+    /// versions.
+    pub fn timestamp_history_window(mut self, window: u64) -> Self {
+        self.timestamp_history_window = window;
+        self
+    }
+
+    /// Sets the level size multiplier.
+    pub fn level_size_multiplier(mut self, multiplier: usize) -> Self {
+        self.level_size_multiplier = multiplier;
+        self
+    }
+
+    /// Sets the base level size (Level 1 target size).
+    pub fn base_level_size(mut self, size: usize) -> Self {
+        self.base_level_size = size;
+        self
+    }
+
+    /// Sets the maximum number of levels.
+    pub fn max_levels(mut self, levels: usize) -> Self {
+        self.max_levels = levels;
+        self
+    }
+
+    /// Sets the block size for SSTables.
+    pub fn block_size(mut self, size: usize) -> Self {
+        self.block_size = size;
+        self
+    }
+
+    /// Sets the block cache size.
+    pub fn block_cache_size(mut self, size: usize) -> Self {
+        self.block_cache_size = size;
+        self
+    }
+
+    /// Enables or disables bloom filters.
+    pub fn use_bloom_filter(mut self, value: bool) -> Self {
+        self.use_bloom_filter = value;
+        self
+    }
+
+    /// Sets the bloom filter false positive rate.
+    pub fn bloom_filter_fp_rate(mut self, rate: f64) -> Self {
+        self.bloom_filter_fp_rate = rate;
+        self
+    }
+
+    /// Sets the prefix extractor used for prefix Bloom filters.
+    pub fn prefix_extractor(mut self, extractor: PrefixExtractor) -> Self {
+        self.prefix_extractor = extractor;
+        self
+    }
+
+    /// Sets the compression algorithm.
+    pub fn compression(mut self, compression: CompressionType) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the checksum algorithm for new SSTable blocks.
+    pub fn checksum(mut self, checksum: ChecksumType) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Enables or disables the write-ahead log.
+    pub fn use_wal(mut self, value: bool) -> Self {
+        self.use_wal = value;
+        self
+    }
+
+    /// Enables or disables WAL sync.
+    pub fn sync_wal(mut self, value: bool) -> Self {
+        self.sync_wal = value;
+        self
+    }
+
+    /// Enables or disables pipelined writes (see
+    /// [`enable_pipelined_write`](Self::enable_pipelined_write)).
+    pub fn enable_pipelined_write(mut self, value: bool) -> Self {
+        self.enable_pipelined_write = value;
+        self
+    }
+
+    /// Sets the size of the WAL record buffer.
+    pub fn wal_buffer_size(mut self, size: usize) -> Self {
+        self.wal_buffer_size = size;
+        self
+    }
+
+    /// Sets how many bytes of rotated WAL files to keep (0 to keep none).
+    pub fn wal_archive_size_limit(mut self, bytes: u64) -> Self {
+        self.wal_archive_size_limit = bytes;
+        self
+    }
+
+    /// Sets the number of threads used to replay the WAL at open.
+    pub fn wal_recovery_threads(mut self, threads: usize) -> Self {
+        self.wal_recovery_threads = threads;
+        self
+    }
+
+    /// Sets the number of background compaction threads.
+    pub fn compaction_threads(mut self, threads: usize) -> Self {
+        self.compaction_threads = threads;
+        self
+    }
+
+    /// Sets the number of MemTables flushed at the same time.
+    pub fn flush_threads(mut self, threads: usize) -> Self {
+        self.flush_threads = threads;
+        self
+    }
+
+    /// Enables or disables quarantining of corrupt SSTables at open.
+    pub fn quarantine_corrupt_files(mut self, value: bool) -> Self {
+        self.quarantine_corrupt_files = value;
+        self
+    }
+
+    /// Sets whether open may proceed after quarantining corrupt SSTables.
+    pub fn allow_data_loss(mut self, value: bool) -> Self {
+        self.allow_data_loss = value;
+        self
+    }
+
+    /// Sets the maximum key size.
+    pub fn max_key_size(mut self, size: usize) -> Self {
+        self.max_key_size = size;
+        self
+    }
+
+    /// Sets the maximum value size.
+    pub fn max_value_size(mut self, size: usize) -> Self {
+        self.max_value_size = size;
+        self
+    }
+
+    /// Sets the maximum size of a write batch (0 for no limit).
+    pub fn max_batch_bytes(mut self, size: usize) -> Self {
+        self.max_batch_bytes = size;
+        self
+    }
+
+    /// Sets the maximum number of operations in a write batch (0 for no limit).
+    pub fn max_batch_ops(mut self, count: usize) -> Self {
+        self.max_batch_ops = count;
+        self
+    }
+
+    /// Sets the amount of disk space reserved at open.
+    pub fn reserved_disk_space(mut self, bytes: u64) -> Self {
+        self.reserved_disk_space = bytes;
+        self
+    }
+
+    /// Enables or disables the `LOG` file.
+    pub fn info_log(mut self, enabled: bool) -> Self {
+        self.info_log = enabled;
+        self
+    }
+
+    /// Sets the size at which the `LOG` file is rotated.
+    pub fn max_log_file_size(mut self, bytes: u64) -> Self {
+        self.max_log_file_size = bytes;
+        self
+    }
+
+    /// Sets the age in seconds after which the `LOG` file is rotated.
+    pub fn log_file_time_to_roll(mut self, secs: u64) -> Self {
+        self.log_file_time_to_roll = secs;
+        self
+    }
+
+    /// Sets the number of rotated `LOG` files to keep.
+    pub fn keep_log_file_num(mut self, count: usize) -> Self {
+        self.keep_log_file_num = count;
+        self
+    }
+
+    /// Sets the latency above which `get` calls are logged.
+    pub fn slow_get_threshold_ms(mut self, ms: u64) -> Self {
+        self.slow_get_threshold_ms = ms;
+        self
+    }
+
+    /// Sets the latency above which writes are logged.
+    pub fn slow_write_threshold_ms(mut self, ms: u64) -> Self {
+        self.slow_write_threshold_ms = ms;
+        self
+    }
+
+    /// Sets the latency above which iterator operations are logged.
+    pub fn slow_iterator_threshold_ms(mut self, ms: u64) -> Self {
+        self.slow_iterator_threshold_ms = ms;
+        self
+    }
+
+    /// Attaches a [`Statistics`] object that collects counters and latencies.
+    pub fn statistics(mut self, statistics: Arc<Statistics>) -> Self {
+        self.statistics = Some(statistics);
+        self
+    }
+
+    /// Attaches an [`SstFileManager`] that tracks SSTable space usage and
+    /// deletes obsolete SSTables at a limited rate.
+    pub fn sst_file_manager(mut self, manager: Arc<SstFileManager>) -> Self {
+        self.sst_file_manager = Some(manager);
+        self
+    }
+
+    /// Attaches a [`WriteBufferManager`] whose MemTable memory budget is
+    /// shared with the other databases using it.
+    pub fn write_buffer_manager(mut self, manager: Arc<WriteBufferManager>) -> Self {
+        self.write_buffer_manager = Some(manager);
+        self
+    }
+
+    /// Attaches a [`RateLimiter`] bounding the disk bandwidth of flushes
+    /// (high priority) and compactions (low priority).
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Sets the windows in which compactions write at a multiple of the
+    /// rate of the rate limiter.
+    pub fn compaction_windows(mut self, windows: Vec<MaintenanceWindow>) -> Self {
+        self.compaction_windows = windows;
+        self
+    }
+
+    /// Registers a listener for database events.
+    pub fn add_listener(mut self, listener: Arc<dyn EventListener>) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    /// Sets the storage backend, e.g. [`MemEnv`](crate::env::MemEnv).
+    pub fn env(mut self, env: Arc<dyn Env>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Sets the order of the keys.
+    pub fn comparator(mut self, comparator: Arc<dyn Comparator>) -> Self {
+        self.comparator = comparator;
+        self
+    }
+
+    /// Encrypts the database's files at rest with the key of `provider`.
+    pub fn key_provider(mut self, provider: Arc<dyn KeyProvider>) -> Self {
+        self.key_provider = Some(provider);
+        self
+    }
+
+    /// Tags every record so that changes made without the key are detected
+    /// on read; needs a [`key_provider`](Self::key_provider).
+    pub fn authenticate_records(mut self, value: bool) -> Self {
+        self.authenticate_records = value;
+        self
+    }
+
+    /// Sets how often reads on a secondary catch up with the primary, in
+    /// milliseconds (0 = only on request).
+    pub fn secondary_refresh_interval_ms(mut self, ms: u64) -> Self {
+        self.secondary_refresh_interval_ms = ms;
+        self
+    }
+
+    /// Sets overrides for SSTables written to `level`.
+    pub fn level_options(mut self, level: usize, options: LevelOptions) -> Self {
+        if self.level_options.len() <= level {
+            self.level_options.resize(level + 1, LevelOptions::default());
+        }
+        self.level_options[level] = options;
+        self
+    }
+
+    /// Returns the compression algorithm used for SSTables at `level`.
+    pub fn compression_for_level(&self, level: usize) -> CompressionType {
+        self.level_options
+            .get(level)
+            .and_then(|l| l.compression)
+            .unwrap_or(self.compression)
+    }
+
+    /// Returns the target SSTable size at `level`, or `None` if output
+    /// files are not split by size.
+    pub fn target_file_size_for_level(&self, level: usize) -> Option<usize> {
+        self.level_options.get(level).and_then(|l| l.target_file_size)
+    }
+
+    /// Returns the Bloom filter false positive rate for SSTables at `level`,
+    /// or `None` if Bloom filters are disabled there.
+    pub fn bloom_filter_for_level(&self, level: usize) -> Option<f64> {
+        let overrides = self.level_options.get(level);
+        let enabled = overrides.and_then(|l| l.use_bloom_filter).unwrap_or(self.use_bloom_filter);
+        enabled.then(|| {
+            overrides
+                .and_then(|l| l.bloom_filter_fp_rate)
+                .unwrap_or(self.bloom_filter_fp_rate)
+        })
+    }
+
+    /// Creates a minimal configuration for testing or development.
+    ///
+    /// This uses smaller sizes and disables features that slow down tests.
+    pub fn for_testing() -> Self {
+        Self {
+            create_if_missing: true,
+            error_if_exists: false,
+            memtable_size: 64 * 1024, // 64KB
+            level0_compaction_threshold: 2,
+            level_size_multiplier: 10,
+            base_level_size: 1024 * 1024, // 1MB
+            soft_pending_compaction_bytes_limit: 64 * 1024 * 1024 * 1024, // 64GB
+            hard_pending_compaction_bytes_limit: 256 * 1024 * 1024 * 1024, // 256GB
+            max_levels: 4,
+            block_size: 1024,              // 1KB
+            block_cache_size: 1024 * 1024, // 1MB
+            max_open_files: 0,
+            advise_compaction_io: true,
+            verify_compaction_output: false,
+            preserve_versions_seconds: 0,
+            timestamp_history_window: 0,
+            use_bloom_filter: false, // Disable for faster tests
+            bloom_filter_fp_rate: 0.01,
+            prefix_extractor: PrefixExtractor::None,
+            compression: CompressionType::None, // Disable for faster tests
+            checksum: ChecksumType::Crc32,
+            use_wal: true,
+            sync_wal: false, // Disable for faster tests
+            enable_pipelined_write: false,
+            wal_buffer_size: 64 * 1024, // 64KB
+            wal_archive_size_limit: 0,
+            wal_recovery_threads: 4,
+            compaction_threads: 1,
+            flush_threads: 2,
+            level_options: Vec::new(),
+            quarantine_corrupt_files: false,
+            allow_data_loss: true,
+            max_key_size: 64 * 1024,          // 64KB
+            max_value_size: 64 * 1024 * 1024, // 64MB
+            max_batch_bytes: 0,
+            max_batch_ops: 0,
+            reserved_disk_space: 0, // Disable for faster tests
+            info_log: true,
+            max_log_file_size: 16 * 1024 * 1024, // 16MB
+            log_file_time_to_roll: 0,
+            keep_log_file_num: 10,
+            slow_get_threshold_ms: 0,
+            slow_write_threshold_ms: 0,
+            slow_iterator_threshold_ms: 0,
+            statistics: None,
+            sst_file_manager: None,
+            write_buffer_manager: None,
+            rate_limiter: None,
+            compaction_windows: Vec::new(),
+            listeners: Vec::new(),
+            env: crate::env::default_env(),
+            comparator: crate::comparator::default_comparator(),
+            key_provider: None,
+            authenticate_records: false,
+            secondary_refresh_interval_ms: 1000,
+        }
+    }
+
+    /// Creates a configuration optimized for high write throughput.
+    ///
+    /// This uses larger buffers and less aggressive compaction.
+    pub fn for_high_write_throughput() -> Self {
+        Self {
+            create_if_missing: true,
+            error_if_exists: false,
+            memtable_size: 16 * 1024 * 1024, // 16MB
+            level0_compaction_threshold: 8,  // More files before compaction
+            level_size_multiplier: 10,
+            base_level_size: 100 * 1024 * 1024, // 100MB
+            soft_pending_compaction_bytes_limit: 64 * 1024 * 1024 * 1024, // 64GB
+            hard_pending_compaction_bytes_limit: 256 * 1024 * 1024 * 1024, // 256GB
+            max_levels: 7,
+            block_size: 16 * 1024,              // 16KB
+            block_cache_size: 16 * 1024 * 1024, // 16MB
+            max_open_files: 0,
+            advise_compaction_io: true,
+            verify_compaction_output: false,
+            preserve_versions_seconds: 0,
+            timestamp_history_window: 0,
+            use_bloom_filter: true,
+            bloom_filter_fp_rate: 0.01,
+            prefix_extractor: PrefixExtractor::None,
+            compression: CompressionType::default(),
+            checksum: ChecksumType::Crc32,
+            use_wal: true,
+            sync_wal: false, // Trade durability for speed
+            enable_pipelined_write: false,
+            wal_buffer_size: 64 * 1024, // 64KB
+            wal_archive_size_limit: 0,
+            wal_recovery_threads: 4,
+            compaction_threads: 2,
+            flush_threads: 2,
+            level_options: Vec::new(),
+            quarantine_corrupt_files: false,
+            allow_data_loss: true,
+            max_key_size: 64 * 1024,          // 64KB
+            max_value_size: 64 * 1024 * 1024, // 64MB
+            max_batch_bytes: 0,
+            max_batch_ops: 0,
+            reserved_disk_space: 1024 * 1024, // 1MB
+            info_log: true,
+            max_log_file_size: 16 * 1024 * 1024, // 16MB
+            log_file_time_to_roll: 0,
+            keep_log_file_num: 10,
+            slow_get_threshold_ms: 0,
+            slow_write_threshold_ms: 0,
+            slow_iterator_threshold_ms: 0,
+            statistics: None,
+            sst_file_manager: None,
+            write_buffer_manager: None,
+            rate_limiter: None,
+            compaction_windows: Vec::new(),
+            listeners: Vec::new(),
+            env: crate::env::default_env(),
+            comparator: crate::comparator::default_comparator(),
+            key_provider: None,
+            authenticate_records: false,
+            secondary_refresh_interval_ms: 1000,
+        }
+    }
+
+    /// Creates a configuration optimized for read-heavy workloads.
+    ///
+    /// This uses larger caches and bloom filters.
+    pub fn for_high_read_throughput() -> Self {
+        Self {
+            create_if_missing: true,
+            error_if_exists: false,
+            memtable_size: 4 * 1024 * 1024, // 4MB
+            level0_compaction_threshold: 4,
+            level_size_multiplier: 10,
+            base_level_size: 10 * 1024 * 1024, // 10MB
+            soft_pending_compaction_bytes_limit: 64 * 1024 * 1024 * 1024, // 64GB
+            hard_pending_compaction_bytes_limit: 256 * 1024 * 1024 * 1024, // 256GB
+            max_levels: 7,
+            block_size: 8 * 1024,               // 8KB
+            block_cache_size: 64 * 1024 * 1024, // 64MB - large cache
+            max_open_files: 0,
+            advise_compaction_io: true,
+            verify_compaction_output: false,
+            preserve_versions_seconds: 0,
+            timestamp_history_window: 0,
+            use_bloom_filter: true,
+            bloom_filter_fp_rate: 0.001, // Lower FP rate
+            prefix_extractor: PrefixExtractor::None,
+            compression: CompressionType::default(),
+            checksum: ChecksumType::Crc32,
+            use_wal: true,
+            sync_wal: true,
+            enable_pipelined_write: false,
+            wal_buffer_size: 64 * 1024, // 64KB
+            wal_archive_size_limit: 0,
+            wal_recovery_threads: 4,
+            compaction_threads: 2,
+            flush_threads: 2,
+            level_options: Vec::new(),
+            quarantine_corrupt_files: false,
+            allow_data_loss: true,
+            max_key_size: 64 * 1024,          // 64KB
+            max_value_size: 64 * 1024 * 1024, // 64MB
+            max_batch_bytes: 0,
+            max_batch_ops: 0,
+            reserved_disk_space: 1024 * 1024, // 1MB
+            info_log: true,
+            max_log_file_size: 16 * 1024 * 1024, // 16MB
+            log_file_time_to_roll: 0,
+            keep_log_file_num: 10,
+            slow_get_threshold_ms: 0,
+            slow_write_threshold_ms: 0,
+            slow_iterator_threshold_ms: 0,
+            statistics: None,
+            sst_file_manager: None,
+            write_buffer_manager: None,
+            rate_limiter: None,
+            compaction_windows: Vec::new(),
+            listeners: Vec::new(),
+            env: crate::env::default_env(),
+            comparator: crate::comparator::default_comparator(),
+            key_provider: None,
+            authenticate_records: false,
+            secondary_refresh_interval_ms: 1000,
+        }
+    }
+
+    /// Creates a configuration optimized for point lookups (`get`).
+    ///
+    /// This uses small blocks, a block cache of `block_cache_mb` megabytes and
+    /// low false positive bloom filters, so most lookups touch at most one
+    /// uncached block. Range scans become comparatively more expensive.
+    pub fn optimize_for_point_lookup(block_cache_mb: usize) -> Self {
+        Self {
+            create_if_missing: true,
+            error_if_exists: false,
+            memtable_size: 4 * 1024 * 1024, // 4MB
+            level0_compaction_threshold: 4,
+            level_size_multiplier: 10,
+            base_level_size: 10 * 1024 * 1024, // 10MB
+            soft_pending_compaction_bytes_limit: 64 * 1024 * 1024 * 1024, // 64GB
+            hard_pending_compaction_bytes_limit: 256 * 1024 * 1024 * 1024, // 256GB
+            max_levels: 7,
+            block_size: 4 * 1024, // 4KB - less data read per lookup
+            block_cache_size: block_cache_mb * 1024 * 1024,
+            max_open_files: 0,
+            advise_compaction_io: true,
+            verify_compaction_output: false,
+            preserve_versions_seconds: 0,
+            timestamp_history_window: 0,
+            use_bloom_filter: true,
+            bloom_filter_fp_rate: 0.001, // Skip almost all tables without the key
+            prefix_extractor: PrefixExtractor::None,
+            compression: CompressionType::default(),
+            checksum: ChecksumType::Crc32,
+            use_wal: true,
+            sync_wal: true,
+            enable_pipelined_write: false,
+            wal_buffer_size: 64 * 1024, // 64KB
+            wal_archive_size_limit: 0,
+            wal_recovery_threads: 4,
+            compaction_threads: 2,
+            flush_threads: 2,
+            level_options: Vec::new(),
+            quarantine_corrupt_files: false,
+            allow_data_loss: true,
+            max_key_size: 64 * 1024,          // 64KB
+            max_value_size: 64 * 1024 * 1024, // 64MB
+            max_batch_bytes: 0,
+            max_batch_ops: 0,
+            reserved_disk_space: 1024 * 1024, // 1MB
+            info_log: true,
+            max_log_file_size: 16 * 1024 * 1024, // 16MB
+            log_file_time_to_roll: 0,
+            keep_log_file_num: 10,
+            slow_get_threshold_ms: 0,
+            slow_write_threshold_ms: 0,
+            slow_iterator_threshold_ms: 0,
+            statistics: None,
+            sst_file_manager: None,
+            write_buffer_manager: None,
+            rate_limiter: None,
+            compaction_windows: Vec::new(),
+            listeners: Vec::new(),
+            env: crate::env::default_env(),
+            comparator: crate::comparator::default_comparator(),
+            key_provider: None,
+            authenticate_records: false,
+            secondary_refresh_interval_ms: 1000,
+        }
+    }
+
+    /// Creates a configuration for loading a large amount of data at once.
+    ///
+    /// This uses a large MemTable, defers Level 0 compaction and disables the
+    /// write-ahead log. Data written since the last flush is lost on a crash,
+    /// so call [`DB::flush`](crate::DB::flush) when the load completes and
+    /// reopen with regular options afterwards.
+    pub fn optimize_for_bulk_load() -> Self {
+        Self {
+            create_if_missing: true,
+            error_if_exists: false,
+            memtable_size: 64 * 1024 * 1024, // 64MB
+            level0_compaction_threshold: 32, // Defer compaction until the load is done
+            level_size_multiplier: 10,
+            base_level_size: 256 * 1024 * 1024,     // 256MB
+            soft_pending_compaction_bytes_limit: 0, // Compaction is deferred anyway
+            hard_pending_compaction_bytes_limit: 0,
+            max_levels: 7,
+            block_size: 16 * 1024,             // 16KB
+            block_cache_size: 8 * 1024 * 1024, // 8MB - loads rarely read back
+            max_open_files: 0,
+            advise_compaction_io: true,
+            verify_compaction_output: false,
+            preserve_versions_seconds: 0,
+            timestamp_history_window: 0,
+            use_bloom_filter: true,
+            bloom_filter_fp_rate: 0.01,
+            prefix_extractor: PrefixExtractor::None,
+            compression: CompressionType::default(),
+            checksum: ChecksumType::Crc32,
+            use_wal: false, // The load can be restarted from its source
+            sync_wal: false,
+            enable_pipelined_write: false,
+            wal_buffer_size: 64 * 1024, // 64KB
+            wal_archive_size_limit: 0,
+            wal_recovery_threads: 4,
+            compaction_threads: 4,
+            flush_threads: 4,
+            level_options: Vec::new(),
+            quarantine_corrupt_files: false,
+            allow_data_loss: true,
+            max_key_size: 64 * 1024,          // 64KB
+            max_value_size: 64 * 1024 * 1024, // 64MB
+            max_batch_bytes: 0,
+            max_batch_ops: 0,
+            reserved_disk_space: 1024 * 1024, // 1MB
+            info_log: true,
+            max_log_file_size: 16 * 1024 * 1024, // 16MB
+            log_file_time_to_roll: 0,
+            keep_log_file_num: 10,
+            slow_get_threshold_ms: 0,
+            slow_write_threshold_ms: 0,
+            slow_iterator_threshold_ms: 0,
+            statistics: None,
+            sst_file_manager: None,
+            write_buffer_manager: None,
+            rate_limiter: None,
+            compaction_windows: Vec::new(),
+            listeners: Vec::new(),
+            env: crate::env::default_env(),
+            comparator: crate::comparator::default_comparator(),
+            key_provider: None,
+            authenticate_records: false,
+            secondary_refresh_interval_ms: 1000,
+        }
+    }
+
+    /// Creates a configuration for small databases (up to a few hundred MB).
+    ///
+    /// This keeps memory usage low and uses fewer, smaller levels.
+    pub fn small_db() -> Self {
+        Self {
+            create_if_missing: true,
+            error_if_exists: false,
+            memtable_size: 1024 * 1024, // 1MB
+            level0_compaction_threshold: 4,
+            level_size_multiplier: 10,
+            base_level_size: 4 * 1024 * 1024, // 4MB
+            soft_pending_compaction_bytes_limit: 64 * 1024 * 1024 * 1024, // 64GB
+            hard_pending_compaction_bytes_limit: 256 * 1024 * 1024 * 1024, // 256GB
+            max_levels: 4,
+            block_size: 4 * 1024,          // 4KB
+            block_cache_size: 1024 * 1024, // 1MB
+            max_open_files: 0,
+            advise_compaction_io: true,
+            verify_compaction_output: false,
+            preserve_versions_seconds: 0,
+            timestamp_history_window: 0,
+            use_bloom_filter: true,
+            bloom_filter_fp_rate: 0.01,
+            prefix_extractor: PrefixExtractor::None,
+            compression: CompressionType::default(),
+            checksum: ChecksumType::Crc32,
+            use_wal: true,
+            sync_wal: true,
+            enable_pipelined_write: false,
+            wal_buffer_size: 64 * 1024, // 64KB
+            wal_archive_size_limit: 0,
+            wal_recovery_threads: 4,
+            compaction_threads: 1,
+            flush_threads: 1,
+            level_options: Vec::new(),
+            quarantine_corrupt_files: false,
+            allow_data_loss: true,
+            max_key_size: 64 * 1024,          // 64KB
+            max_value_size: 64 * 1024 * 1024, // 64MB
+            max_batch_bytes: 0,
+            max_batch_ops: 0,
+            reserved_disk_space: 1024 * 1024, // 1MB
+            info_log: true,
+            max_log_file_size: 16 * 1024 * 1024, // 16MB
+            log_file_time_to_roll: 0,
+            keep_log_file_num: 10,
+            slow_get_threshold_ms: 0,
+            slow_write_threshold_ms: 0,
+            slow_iterator_threshold_ms: 0,
+            statistics: None,
+            sst_file_manager: None,
+            write_buffer_manager: None,
+            rate_limiter: None,
+            compaction_windows: Vec::new(),
+            listeners: Vec::new(),
+            env: crate::env::default_env(),
+            comparator: crate::comparator::default_comparator(),
+            key_provider: None,
+            authenticate_records: false,
+            secondary_refresh_interval_ms: 1000,
+        }
+    }
+
+    /// Loads the options most recently persisted in a database directory.
+    ///
+    /// Every successful [`DB::open`](crate::DB::open) writes the effective
+    /// options to an `OPTIONS-NNNNNN` file; this reads the latest one back.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`](crate::Error::NotFound) if the directory
+    /// contains no OPTIONS file.
+    pub fn load_latest<P: AsRef<std::path::Path>>(path: P) -> crate::Result<Self> {
+        let path = path.as_ref();
+        OptionsFile::load_latest(path)?
+            .map(|file| file.options)
+            .ok_or_else(|| crate::Error::not_found(format!("No OPTIONS file in {:?}", path)))
+    }
+
+    /// Sets a single option from its string representation.
+    ///
+    /// `name` is the field name of the option (e.g. `"memtable_size"`). The
+    /// result is not validated; call [`validate`](Self::validate) once all
+    /// changes have been applied.
+    pub fn set_option(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        fn parse<T: std::str::FromStr>(name: &str, value: &str) -> crate::Result<T> {
+            value.trim().parse().map_err(|_| {
+                crate::Error::invalid_argument(format!(
+                    "invalid value '{}' for option '{}'",
+                    value, name
+                ))
+            })
+        }
+
+        match name {
+            "create_if_missing" => self.create_if_missing = parse(name, value)?,
+            "error_if_exists" => self.error_if_exists = parse(name, value)?,
+            "memtable_size" => self.memtable_size = parse(name, value)?,
+            "level0_compaction_threshold" => self.level0_compaction_threshold = parse(name, value)?,
+            "soft_pending_compaction_bytes_limit" => {
+                self.soft_pending_compaction_bytes_limit = parse(name, value)?
+            }
+            "hard_pending_compaction_bytes_limit" => {
+                self.hard_pending_compaction_bytes_limit = parse(name, value)?
+            }
+            "level_size_multiplier" => self.level_size_multiplier = parse(name, value)?,
+            "base_level_size" => self.base_level_size = parse(name, value)?,
+            "max_levels" => self.max_levels = parse(name, value)?,
+            "block_size" => self.block_size = parse(name, value)?,
+            "block_cache_size" => self.block_cache_size = parse(name, value)?,
+            "max_open_files" => self.max_open_files = parse(name, value)?,
+            "advise_compaction_io" => self.advise_compaction_io = parse(name, value)?,
+            "compaction_windows" => {
+                self.compaction_windows =
+                    MaintenanceWindow::parse_list(value).ok_or_else(|| {
+                        crate::Error::invalid_argument(format!(
+                            "invalid compaction windows '{}'",
+                            value
+                        ))
+                    })?;
+            }
+            "verify_compaction_output" => self.verify_compaction_output = parse(name, value)?,
+            "preserve_versions_seconds" => self.preserve_versions_seconds = parse(name, value)?,
+            "timestamp_history_window" => self.timestamp_history_window = parse(name, value)?,
+            "use_bloom_filter" => self.use_bloom_filter = parse(name, value)?,
+            "bloom_filter_fp_rate" => self.bloom_filter_fp_rate = parse(name, value)?,
+            "prefix_extractor" => {
+                self.prefix_extractor =
+                    PrefixExtractor::from_name(value.trim()).ok_or_else(|| {
+                        crate::Error::invalid_argument(format!(
+                            "unknown prefix extractor '{}'",
+                            value
+                        ))
+                    })?
+            }
+            "compression" => {
+                self.compression = CompressionType::from_name(value.trim()).ok_or_else(|| {
+                    crate::Error::invalid_argument(format!(
+                        "unknown or unsupported compression '{}'",
+                        value
+                    ))
+                })?
+            }
+            "checksum" => {
+                self.checksum = ChecksumType::from_name(value.trim()).ok_or_else(|| {
+                    crate::Error::invalid_argument(format!("unknown checksum type '{}'", value))
+                })?
+            }
+            "use_wal" => self.use_wal = parse(name, value)?,
+            "sync_wal" => self.sync_wal = parse(name, value)?,
+            "enable_pipelined_write" => self.enable_pipelined_write = parse(name, value)?,
+            "wal_buffer_size" => self.wal_buffer_size = parse(name, value)?,
+            "wal_archive_size_limit" => self.wal_archive_size_limit = parse(name, value)?,
+            "wal_recovery_threads" => self.wal_recovery_threads = parse(name, value)?,
+            "compaction_threads" => self.compaction_threads = parse(name, value)?,
+            "flush_threads" => self.flush_threads = parse(name, value)?,
+            "quarantine_corrupt_files" => self.quarantine_corrupt_files = parse(name, value)?,
+            "allow_data_loss" => self.allow_data_loss = parse(name, value)?,
+            "max_key_size" => self.max_key_size = parse(name, value)?,
+            "max_value_size" => self.max_value_size = parse(name, value)?,
+            "max_batch_bytes" => self.max_batch_bytes = parse(name, value)?,
+            "max_batch_ops" => self.max_batch_ops = parse(name, value)?,
+            "reserved_disk_space" => self.reserved_disk_space = parse(name, value)?,
+            "info_log" => self.info_log = parse(name, value)?,
+            "max_log_file_size" => self.max_log_file_size = parse(name, value)?,
+            "log_file_time_to_roll" => self.log_file_time_to_roll = parse(name, value)?,
+            "keep_log_file_num" => self.keep_log_file_num = parse(name, value)?,
+            "slow_get_threshold_ms" => self.slow_get_threshold_ms = parse(name, value)?,
+            "slow_write_threshold_ms" => self.slow_write_threshold_ms = parse(name, value)?,
+            "slow_iterator_threshold_ms" => self.slow_iterator_threshold_ms = parse(name, value)?,
+            _ => return Err(crate::Error::invalid_argument(format!("unknown option '{}'", name))),
+        }
+        Ok(())
+    }
+
+    /// Applies a set of `(name, value)` overrides on top of these options.
+    ///
+    /// Names are the field names of [`Options`], as accepted by
+    /// [`set_option`](Self::set_option). Unknown names are rejected rather than
+    /// ignored, and the combined result is validated.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use aidb::Options;
+    ///
+    /// let opts = Options::for_high_write_throughput()
+    ///     .with_overrides([("level0_compaction_threshold", "12"), ("sync_wal", "true")])
+    ///     .unwrap();
+    /// assert_eq!(opts.level0_compaction_threshold, 12);
+    /// assert!(opts.sync_wal);
+    /// ```
+    pub fn with_overrides<I, K, V>(mut self, overrides: I) -> crate::Result<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (name, value) in overrides {
+            self.set_option(name.as_ref(), value.as_ref())?;
+        }
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Applies overrides from environment variables on top of these options.
+    ///
+    /// Every variable starting with [`ENV_PREFIX`] (`AIDB_`) is treated as an
+    /// option override: `AIDB_MEMTABLE_SIZE=16777216` sets `memtable_size`.
+    /// A variable that does not name a known option is an error, so typos are
+    /// caught instead of silently ignored.
+    pub fn with_env_overrides(self) -> crate::Result<Self> {
+        self.with_overrides(env_overrides(std::env::vars()))
+    }
+
+    /// Validates the options and returns an error if any are invalid.
+    pub fn validate(&self) -> crate::Result<()> {
+        if self.memtable_size == 0 {
+            return Err(crate::Error::invalid_argument("memtable_size must be > 0"));
+        }
+        if !matches!(self.comparator.timestamp_size(), 0 | crate::timestamp::TIMESTAMP_SIZE) {
+            return Err(crate::Error::invalid_argument(format!(
+                "comparator timestamps must be {} bytes",
+                crate::timestamp::TIMESTAMP_SIZE
+            )));
+        }
+        if self.block_size == 0 {
+            return Err(crate::Error::invalid_argument("block_size must be > 0"));
+        }
+        if self.max_levels == 0 {
+            return Err(crate::Error::invalid_argument("max_levels must be > 0"));
+        }
+        if self.bloom_filter_fp_rate <= 0.0 || self.bloom_filter_fp_rate >= 1.0 {
+            return Err(crate::Error::invalid_argument(
+                "bloom_filter_fp_rate must be between 0 and 1",
+            ));
+        }
+        if self.level0_compaction_threshold == 0 {
+            return Err(crate::Error::invalid_argument("level0_compaction_threshold must be > 0"));
+        }
+        if self.level_size_multiplier == 0 {
+            return Err(crate::Error::invalid_argument("level_size_multiplier must be > 0"));
+        }
+        if self.base_level_size == 0 {
+            return Err(crate::Error::invalid_argument("base_level_size must be > 0"));
+        }
+        if self.soft_pending_compaction_bytes_limit > 0
+            && self.hard_pending_compaction_bytes_limit > 0
+            && self.soft_pending_compaction_bytes_limit > self.hard_pending_compaction_bytes_limit
+        {
+            return Err(crate::Error::invalid_argument(
+                "soft_pending_compaction_bytes_limit must not exceed \
+                 hard_pending_compaction_bytes_limit",
+            ));
+        }
+        if self.max_key_size == 0 || self.max_key_size > u32::MAX as usize {
+            return Err(crate::Error::invalid_argument(format!(
+                "max_key_size must be between 1 and {}",
+                u32::MAX
+            )));
+        }
+        if self.max_value_size == 0 {
+            return Err(crate::Error::invalid_argument("max_value_size must be > 0"));
+        }
+        for (level, overrides) in self.level_options.iter().enumerate() {
+            overrides.validate(level)?;
+        }
+        if self.authenticate_records && self.key_provider.is_none() {
+            return Err(crate::Error::invalid_config("authenticate_records needs a key_provider"));
+        }
+        #[cfg(not(feature = "encryption"))]
+        if self.key_provider.is_some() {
+            return Err(crate::Error::invalid_config(
+                "key_provider needs the \"encryption\" feature",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Moves the key provider into `env`, which then encrypts every file,
+    /// and also returns that environment. The returned options have no key
+    /// provider, so they can be reused without encrypting twice.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn with_encrypted_env(mut self) -> (Self, Option<Arc<crate::env::EncryptedEnv>>) {
+        let Some(provider) = self.key_provider.take() else {
+            return (self, None);
+        };
+        let env = Arc::new(crate::env::EncryptedEnv::new(self.env, provider));
+        self.env = Arc::clone(&env) as Arc<dyn Env>;
+        (self, Some(env))
+    }
+
+    /// Checks a key, and optionally a value, against `max_key_size` and
+    /// `max_value_size`.
+    pub(crate) fn check_entry_size(&self, key: &[u8], value: Option<&[u8]>) -> crate::Result<()> {
+        if key.len() > self.max_key_size {
+            return Err(crate::Error::invalid_argument(format!(
+                "key size {} exceeds max_key_size {}",
+                key.len(),
+                self.max_key_size
+            )));
+        }
+        if let Some(value) = value {
+            if value.len() > self.max_value_size {
+                return Err(crate::Error::invalid_argument(format!(
+                    "value size {} exceeds max_value_size {}",
+                    value.len(),
+                    self.max_value_size
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a batch against `max_batch_ops` and `max_batch_bytes`.
+    pub(crate) fn check_batch_size(&self, batch: &crate::WriteBatch) -> crate::Result<()> {
+        if self.max_batch_ops > 0 && batch.len() > self.max_batch_ops {
+            return Err(crate::Error::invalid_argument(format!(
+                "batch of {} operations exceeds max_batch_ops {}",
+                batch.len(),
+                self.max_batch_ops
+            )));
+        }
+        if self.max_batch_bytes > 0 && batch.data_size() > self.max_batch_bytes {
+            return Err(crate::Error::invalid_argument(format!(
+                "batch size {} exceeds max_batch_bytes {}",
+                batch.data_size(),
+                self.max_batch_bytes
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_options() {
+        let opts = Options::default();
+        assert!(opts.create_if_missing);
+        assert!(!opts.error_if_exists);
+        assert_eq!(opts.memtable_size, 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_options_builder() {
+        let opts = Options::new()
+            .memtable_size(8 * 1024 * 1024)
+            .block_size(8 * 1024)
+            .use_wal(false);
+
+        assert_eq!(opts.memtable_size, 8 * 1024 * 1024);
+        assert_eq!(opts.block_size, 8 * 1024);
+        assert!(!opts.use_wal);
+    }
+
+    #[test]
+    fn test_options_validation() {
+        let mut opts = Options::default();
+        assert!(opts.validate().is_ok());
+
+        opts.memtable_size = 0;
+        assert!(opts.validate().is_err());
+
+        opts.memtable_size = 1024;
+        opts.bloom_filter_fp_rate = 1.5;
+        assert!(opts.validate().is_err());
+    }
+
+    #[test]
+    fn test_level_options_fallback() {
+        let opts = Options::default()
+            .compression(CompressionType::None)
+            .level_options(
+                2,
+                LevelOptions::new().target_file_size(2 * 1024 * 1024).use_bloom_filter(false),
+            )
+            .level_options(3, LevelOptions::new().bloom_filter_fp_rate(0.001));
+
+        assert_eq!(opts.level_options.len(), 4);
+        assert_eq!(opts.compression_for_level(0), CompressionType::None);
+        assert_eq!(opts.compression_for_level(6), CompressionType::None);
+        assert_eq!(opts.target_file_size_for_level(1), None);
+        assert_eq!(opts.target_file_size_for_level(2), Some(2 * 1024 * 1024));
+        assert_eq!(opts.bloom_filter_for_level(1), Some(0.01));
+        assert_eq!(opts.bloom_filter_for_level(2), None);
+        assert_eq!(opts.bloom_filter_for_level(3), Some(0.001));
+        assert!(opts.validate().is_ok());
+
+        let invalid = Options::default().level_options(1, LevelOptions::new().target_file_size(0));
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_checksum_types() {
+        let data = b"hello world";
+        assert_eq!(ChecksumType::Crc32.checksum(data), crc32fast::hash(data));
+        assert_ne!(ChecksumType::Crc32.checksum(data), ChecksumType::Crc32c.checksum(data));
+        assert_ne!(ChecksumType::Crc32.checksum(data), ChecksumType::Xxh3.checksum(data));
+
+        for checksum in [ChecksumType::Crc32, ChecksumType::Crc32c, ChecksumType::Xxh3] {
+            assert_eq!(ChecksumType::from_u8(checksum as u8), Some(checksum));
+            assert_eq!(ChecksumType::from_name(checksum.name()), Some(checksum));
+        }
+        assert_eq!(ChecksumType::from_u8(3), None);
+        assert_eq!(ChecksumType::default(), ChecksumType::Crc32);
+    }
+
+    #[test]
+    fn test_set_option() {
+        let mut opts = Options::default();
+        opts.set_option("memtable_size", "1024").unwrap();
+        opts.set_option("sync_wal", "false").unwrap();
+        opts.set_option("bloom_filter_fp_rate", "0.05").unwrap();
+        opts.set_option("compression", "none").unwrap();
+
+        assert_eq!(opts.memtable_size, 1024);
+        assert!(!opts.sync_wal);
+        assert_eq!(opts.bloom_filter_fp_rate, 0.05);
+        assert_eq!(opts.compression, CompressionType::None);
+
+        assert!(opts.set_option("memtable_size", "-1").is_err());
+        assert!(opts.set_option("compression", "brotli").is_err());
+        assert!(opts.set_option("no_such_option", "1").is_err());
+    }
+
+    #[test]
+    fn test_with_overrides() {
+        let opts = Options::default()
+            .with_overrides([("memtable_size", "2048"), ("compression", "none")])
+            .unwrap();
+        assert_eq!(opts.memtable_size, 2048);
+        assert_eq!(opts.compression, CompressionType::None);
+
+        // Unknown keys are rejected
+        assert!(Options::default().with_overrides([("memtable_sise", "2048")]).is_err());
+        // The result is validated
+        assert!(Options::default().with_overrides([("block_size", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = vec![
+            ("AIDB_LEVEL0_COMPACTION_THRESHOLD".to_string(), "6".to_string()),
+            ("AIDB_SYNC_WAL".to_string(), "false".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ];
+        let overrides = env_overrides(vars);
+        assert_eq!(overrides.len(), 2);
+
+        let opts = Options::default().with_overrides(overrides).unwrap();
+        assert_eq!(opts.level0_compaction_threshold, 6);
+        assert!(!opts.sync_wal);
+
+        let typo = env_overrides(vec![("AIDB_MEMTABLE".to_string(), "1".to_string())]);
+        assert!(Options::default().with_overrides(typo).is_err());
+    }
+
+    #[test]
+    fn test_for_testing_config() {
+        let opts = Options::for_testing();
+        assert_eq!(opts.memtable_size, 64 * 1024);
+        assert_eq!(opts.block_size, 1024);
+        assert!(!opts.use_bloom_filter);
+        assert_eq!(opts.compression, CompressionType::None);
+        assert!(!opts.sync_wal);
+        assert!(opts.validate().is_ok());
+    }
+
+    #[test]
+    fn test_for_high_write_throughput_config() {
+        let opts = Options::for_high_write_throughput();
+        assert_eq!(opts.memtable_size, 16 * 1024 * 1024);
+        assert_eq!(opts.level0_compaction_threshold, 8);
+        assert!(!opts.sync_wal);
+        assert!(opts.validate().is_ok());
+    }
+
+    #[test]
+    fn test_for_high_read_throughput_config() {
+        let opts = Options::for_high_read_throughput();
+        assert_eq!(opts.block_cache_size, 64 * 1024 * 1024);
+        assert!(opts.use_bloom_filter);
+        assert_eq!(opts.bloom_filter_fp_rate, 0.001);
+        assert!(opts.validate().is_ok());
+    }
+
+    #[test]
+    fn test_optimize_for_point_lookup_config() {
+        let opts = Options::optimize_for_point_lookup(128);
+        assert_eq!(opts.block_cache_size, 128 * 1024 * 1024);
+        assert!(opts.use_bloom_filter);
+        assert_eq!(opts.bloom_filter_fp_rate, 0.001);
+        assert!(opts.validate().is_ok());
+    }
+
+    #[test]
+    fn test_optimize_for_bulk_load_config() {
+        let opts = Options::optimize_for_bulk_load();
+        assert_eq!(opts.memtable_size, 64 * 1024 * 1024);
+        assert_eq!(opts.level0_compaction_threshold, 32);
+        assert!(!opts.use_wal);
+        assert!(opts.validate().is_ok());
+    }
+
+    #[test]
+    fn test_small_db_config() {
+        let opts = Options::small_db();
+        assert_eq!(opts.memtable_size, 1024 * 1024);
+        assert_eq!(opts.block_cache_size, 1024 * 1024);
+        assert_eq!(opts.max_levels, 4);
+        assert!(opts.validate().is_ok());
+    }
+
+    #[test]
+    fn test_all_builder_methods() {
+        let opts = Options::new()
+            .create_if_missing(false)
+            .error_if_exists(true)
+            .memtable_size(1024)
+            .level0_compaction_threshold(5)
+            .level_size_multiplier(8)
+            .base_level_size(2048)
+            .max_levels(5)
+            .block_size(512)
+            .block_cache_size(1024)
+            .use_bloom_filter(false)
+            .bloom_filter_fp_rate(0.05)
+            .compression(CompressionType::None)
+            .use_wal(false)
+            .sync_wal(false)
+            .compaction_threads(4);
+
+        assert!(!opts.create_if_missing);
+        assert!(opts.error_if_exists);
+        assert_eq!(opts.memtable_size, 1024);
+        assert_eq!(opts.level0_compaction_threshold, 5);
+        assert_eq!(opts.level_size_multiplier, 8);
+        assert_eq!(opts.base_level_size, 2048);
+        assert_eq!(opts.max_levels, 5);
+        assert_eq!(opts.block_size, 512);
+        assert_eq!(opts.block_cache_size, 1024);
+        assert!(!opts.use_bloom_filter);
+        assert_eq!(opts.bloom_filter_fp_rate, 0.05);
+        assert_eq!(opts.compression, CompressionType::None);
+        assert!(!opts.use_wal);
+        assert!(!opts.sync_wal);
+        assert_eq!(opts.compaction_threads, 4);
+    }
+
+    #[test]
+    fn test_validation_comprehensive() {
+        let mut opts = Options::default();
+
+        // Valid config
+        assert!(opts.validate().is_ok());
+
+        // Invalid memtable_size
+        opts = Options::default();
+        opts.memtable_size = 0;
+        assert!(opts.validate().is_err());
+
+        // Invalid block_size
+        opts = Options::default();
+        opts.block_size = 0;
+        assert!(opts.validate().is_err());
+
+        // Invalid max_levels
+        opts = Options::default();
+        opts.max_levels = 0;
+        assert!(opts.validate().is_err());
+
+        // Invalid bloom_filter_fp_rate (too low)
+        opts = Options::default();
+        opts.bloom_filter_fp_rate = 0.0;
+        assert!(opts.validate().is_err());
+
+        // Invalid bloom_filter_fp_rate (too high)
+        opts = Options::default();
+        opts.bloom_filter_fp_rate = 1.0;
+        assert!(opts.validate().is_err());
+
+        // Invalid level0_compaction_threshold
+        opts = Options::default();
+        opts.level0_compaction_threshold = 0;
+        assert!(opts.validate().is_err());
+
+        // Invalid level_size_multiplier
+        opts = Options::default();
+        opts.level_size_multiplier = 0;
+        assert!(opts.validate().is_err());
+
+        // Invalid base_level_size
+        opts = Options::default();
+        opts.base_level_size = 0;
+        assert!(opts.validate().is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! OPTIONS file persistence.
+//!
+//! Every time a database is opened, the effective [`Options`] are written to an
+//! `OPTIONS-NNNNNN` file (TOML) inside the database directory. Alongside the
+//! options, the file records the settings that can never change over the
+//! lifetime of a database (on-disk format version and key comparator). On the
+//! next open these are checked against the caller's configuration so that a
+//! misconfigured reopen fails with [`Error::InvalidConfig`] instead of silently
+//! misreading data.
+
+use super::{CompressionType, Options};
+use crate::env::{Env, PosixEnv};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Filename prefix of persisted options files.
+pub const OPTIONS_FILE_PREFIX: &str = "OPTIONS-";
+
+/// Current on-disk format version.
+///
+/// Version 2 stores sequence numbers in SSTables.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Contents of an OPTIONS file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptionsFile {
+    /// On-disk format version of the database.
+    pub format_version: u32,
+    /// Name of the key comparator the database was created with.
+    pub comparator: String,
+    /// Version of AiDb that wrote the file.
+    pub aidb_version: String,
+    /// The effective options at the time the file was written.
+    pub options: Options,
+}
+
+impl OptionsFile {
+    /// Creates an OPTIONS file description for the given options.
+    pub fn new(options: &Options) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            comparator: options.comparator.name().to_string(),
+            aidb_version: env!("CARGO_PKG_VERSION").to_string(),
+            options: options.clone(),
+        }
+    }
+
+    /// Parses an OPTIONS file from its TOML representation.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let value: toml::Value = toml::from_str(contents)
+            .map_err(|e| Error::corruption(format!("Failed to parse OPTIONS file: {}", e)))?;
+
+        // Check the compression type before deserializing so that a database
+        // written with a compression feature missing from this build gets a
+        // descriptive error rather than an "unknown variant" message.
+        if let Some(options) = value.get("options") {
+            let level_compressions = options
+                .get("level_options")
+                .and_then(|l| l.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|l| l.get("compression"));
+            for name in options
+                .get("compression")
+                .into_iter()
+                .chain(level_compressions)
+                .filter_map(|c| c.as_str())
+            {
+                if CompressionType::from_name(name).is_none() {
+                    return Err(Error::invalid_config(format!(
+                        "database was written with compression '{}', which is not enabled in this build",
+                        name
+                    )));
+                }
+            }
+        }
+
+        value
+            .try_into()
+            .map_err(|e| Error::corruption(format!("Failed to parse OPTIONS file: {}", e)))
+    }
+
+    /// Serializes the OPTIONS file to TOML.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self)
+            .map_err(|e| Error::Serialization(format!("Failed to serialize options: {}", e)))
+    }
+
+    /// Loads the most recent OPTIONS file from a database directory.
+    ///
+    /// Returns `None` if the directory contains no OPTIONS file.
+    pub fn load_latest<P: AsRef<Path>>(db_path: P) -> Result<Option<Self>> {
+        Self::load_latest_with_env(db_path, &PosixEnv)
+    }
+
+    /// Loads the most recent OPTIONS file from a database directory in `env`.
+    pub fn load_latest_with_env<P: AsRef<Path>>(db_path: P, env: &dyn Env) -> Result<Option<Self>> {
+        match latest_options_file(db_path.as_ref(), env)? {
+            Some((_, path)) => {
+                let contents = String::from_utf8(env.read_file(&path)?).map_err(|e| {
+                    Error::corruption(format!("OPTIONS file {:?} is not UTF-8: {}", path, e))
+                })?;
+                Ok(Some(Self::parse(&contents)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Writes this OPTIONS file into the database directory.
+    ///
+    /// The file is written under the next free number via a temporary file and
+    /// a rename, after which older OPTIONS files are removed.
+    pub fn persist<P: AsRef<Path>>(&self, db_path: P) -> Result<()> {
+        self.persist_with_env(db_path, &PosixEnv)
+    }
+
+    /// Writes this OPTIONS file into the database directory in `env`.
+    pub fn persist_with_env<P: AsRef<Path>>(&self, db_path: P, env: &dyn Env) -> Result<()> {
+        let db_path = db_path.as_ref();
+        let existing = list_options_files(db_path, env)?;
+        let number = existing.iter().map(|(n, _)| *n).max().unwrap_or(0) + 1;
+
+        let path = db_path.join(options_file_name(number));
+        let tmp_path = path.with_extension("tmp");
+        env.write_file(&tmp_path, self.to_toml()?.as_bytes())?;
+        env.rename_file(&tmp_path, &path)?;
+        env.sync_dir(db_path)?;
+
+        for (_, old_path) in existing {
+            if let Err(e) = env.remove_file(&old_path) {
+                log::warn!("Failed to remove old OPTIONS file {:?}: {}", old_path, e);
+            }
+        }
+        env.sync_dir(db_path)?;
+
+        Ok(())
+    }
+
+    /// Checks that `options` can be used to open the database this file describes.
+    ///
+    /// Returns [`Error::InvalidConfig`] if an immutable setting conflicts with
+    /// what is recorded on disk.
+    pub fn check_compatibility(&self, options: &Options) -> Result<()> {
+        if self.format_version > FORMAT_VERSION {
+            return Err(Error::invalid_config(format!(
+                "database format version {} is newer than the supported version {} \
+                 (written by AiDb {})",
+                self.format_version, FORMAT_VERSION, self.aidb_version
+            )));
+        }
+
+        if self.comparator != options.comparator.name() {
+            return Err(Error::invalid_config(format!(
+                "database was created with comparator '{}', but '{}' was supplied",
+                self.comparator,
+                options.comparator.name()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Generates the OPTIONS filename for a given number.
+pub fn options_file_name(number: u64) -> String {
+    format!("{}{:06}", OPTIONS_FILE_PREFIX, number)
+}
+
+/// Parses an OPTIONS filename to extract its number.
+pub fn parse_options_file_name(filename: &str) -> Option<u64> {
+    filename.strip_prefix(OPTIONS_FILE_PREFIX)?.parse().ok()
+}
+
+/// Lists all OPTIONS files in a database directory.
+fn list_options_files(db_path: &Path, env: &dyn Env) -> Result<Vec<(u64, PathBuf)>> {
+    let mut files = Vec::new();
+    if !env.file_exists(db_path) {
+        return Ok(files);
+    }
+
+    for name in env.get_children(db_path)? {
+        if let Some(number) = parse_options_file_name(&name) {
+            files.push((number, db_path.join(name)));
+        }
+    }
+
+    Ok(files)
+}
+
+/// Finds the OPTIONS file with the highest number.
+fn latest_options_file(db_path: &Path, env: &dyn Env) -> Result<Option<(u64, PathBuf)>> {
+    Ok(list_options_files(db_path, env)?.into_iter().max_by_key(|(n, _)| *n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LevelOptions;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_options_file_roundtrip() {
+        let options = Options::default().memtable_size(1234).max_levels(5);
+        let file = OptionsFile::new(&options);
+
+        let parsed = OptionsFile::parse(&file.to_toml().unwrap()).unwrap();
+        assert_eq!(parsed.format_version, FORMAT_VERSION);
+        assert_eq!(parsed.comparator, crate::comparator::BYTEWISE_COMPARATOR_NAME);
+        assert_eq!(parsed.options.memtable_size, 1234);
+        assert_eq!(parsed.options.max_levels, 5);
+        assert_eq!(parsed.options.compression, options.compression);
+    }
+
+    #[test]
+    fn test_options_file_missing_fields_use_defaults() {
+        let contents = r#"
+            format_version = 1
+            comparator = "aidb.BytewiseComparator"
+            aidb_version = "0.1.0"
+
+            [options]
+            memtable_size = 2048
+        "#;
+        let parsed = OptionsFile::parse(contents).unwrap();
+        assert_eq!(parsed.options.memtable_size, 2048);
+        assert_eq!(parsed.options.block_size, Options::default().block_size);
+    }
+
+    #[test]
+    fn test_persist_and_load_latest() {
+        let temp_dir = TempDir::new().unwrap();
+
+        assert!(OptionsFile::load_latest(temp_dir.path()).unwrap().is_none());
+
+        OptionsFile::new(&Options::default().memtable_size(1024))
+            .persist(temp_dir.path())
+            .unwrap();
+        OptionsFile::new(&Options::default().memtable_size(4096))
+            .persist(temp_dir.path())
+            .unwrap();
+
+        let loaded = OptionsFile::load_latest(temp_dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.options.memtable_size, 4096);
+
+        // Older OPTIONS files are cleaned up
+        let files = list_options_files(temp_dir.path(), &PosixEnv).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, 2);
+    }
+
+    #[test]
+    fn test_newer_format_version_is_rejected() {
+        let mut file = OptionsFile::new(&Options::default());
+        file.format_version = FORMAT_VERSION + 1;
+
+        let result = file.check_compatibility(&Options::default());
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_comparator_mismatch_is_rejected() {
+        let mut file = OptionsFile::new(&Options::default());
+        file.comparator = "custom.ReverseComparator".to_string();
+
+        let result = file.check_compatibility(&Options::default());
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_unsupported_compression_is_rejected() {
+        let contents = r#"
+            format_version = 1
+            comparator = "aidb.BytewiseComparator"
+            aidb_version = "0.1.0"
+
+            [options]
+            compression = "zstd"
+        "#;
+        let result = OptionsFile::parse(contents);
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_level_options_roundtrip() {
+        let options = Options::default().level_options(
+            2,
+            LevelOptions::new().compression(CompressionType::None).target_file_size(1 << 20),
+        );
+
+        let parsed = OptionsFile::parse(&OptionsFile::new(&options).to_toml().unwrap()).unwrap();
+        assert_eq!(parsed.options.level_options, options.level_options);
+    }
+
+    #[test]
+    fn test_parse_options_file_name() {
+        assert_eq!(options_file_name(7), "OPTIONS-000007");
+        assert_eq!(parse_options_file_name("OPTIONS-000007"), Some(7));
+        assert_eq!(parse_options_file_name("OPTIONS-000007.tmp"), None);
+        assert_eq!(parse_options_file_name("MANIFEST"), None);
+    }
+}
@@ -0,0 +1,163 @@
+//! Key prefixes for prefix Bloom filters and prefix iteration.
+//!
+//! Many workloads group keys under a common prefix (`tenant:123:...`) and
+//! mostly read one group at a time. With a [`PrefixExtractor`] configured,
+//! the prefix of every key is added to the SSTable's Bloom filter as well,
+//! so a prefix iterator (see
+//! [`ReadOptions::prefix_same_as_start`](super::ReadOptions::prefix_same_as_start))
+//! can skip tables without keys under the prefix and stop at its end.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Extracts the prefix of a key.
+///
+/// Keys the extractor returns no prefix for (keys shorter than a fixed
+/// prefix, or with too few delimiters) are outside its domain; they are
+/// stored and read normally but never match a prefix seek.
+///
+/// In the OPTIONS file and [`Options::set_option`](super::Options::set_option)
+/// an extractor is written by name: `none`, `fixed:<len>`, or
+/// `delimited:<count>:<delimiter>`.
+///
+/// # Example
+///
+/// ```rust
+/// use aidb::config::PrefixExtractor;
+///
+/// // Everything up to and including the second ':'
+/// let extractor = PrefixExtractor::Delimited { delimiter: b':', count: 2 };
+/// assert_eq!(extractor.prefix(b"tenant:123:orders:7"), Some(&b"tenant:123:"[..]));
+/// assert_eq!(extractor.prefix(b"tenant:123"), None);
+///
+/// assert_eq!(PrefixExtractor::from_name("delimited:2::"), Some(extractor));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum PrefixExtractor {
+    /// No prefix extraction.
+    #[default]
+    None,
+
+    /// The first `n` bytes of the key.
+    Fixed(usize),
+
+    /// The key up to and including the `count`-th occurrence of `delimiter`.
+    Delimited {
+        /// Byte that ends a prefix component
+        delimiter: u8,
+        /// Number of components in the prefix
+        count: usize,
+    },
+}
+
+impl PrefixExtractor {
+    /// Returns the prefix of `key`, or `None` if the key is outside the
+    /// extractor's domain.
+    pub fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        match *self {
+            PrefixExtractor::None => None,
+            PrefixExtractor::Fixed(len) => key.get(..len).filter(|_| len > 0),
+            PrefixExtractor::Delimited { delimiter, count } => {
+                if count == 0 {
+                    return None;
+                }
+                let end = key
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &byte)| byte == delimiter)
+                    .nth(count - 1)
+                    .map(|(position, _)| position)?;
+                Some(&key[..=end])
+            }
+        }
+    }
+
+    /// Whether an extractor is configured.
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self, PrefixExtractor::None)
+    }
+
+    /// Returns the name of this extractor, as written in the OPTIONS file
+    /// and recorded in SSTables.
+    pub fn name(&self) -> String {
+        match *self {
+            PrefixExtractor::None => "none".to_string(),
+            PrefixExtractor::Fixed(len) => format!("fixed:{}", len),
+            PrefixExtractor::Delimited { delimiter, count } => {
+                format!("delimited:{}:{}", count, delimiter as char)
+            }
+        }
+    }
+
+    /// Parses an extractor name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut parts = name.splitn(3, ':');
+        match (parts.next()?, parts.next(), parts.next()) {
+            ("none", None, None) => Some(PrefixExtractor::None),
+            ("fixed", Some(len), None) => len.parse().ok().map(PrefixExtractor::Fixed),
+            ("delimited", Some(count), Some(delimiter)) => match delimiter.as_bytes() {
+                &[delimiter] if delimiter.is_ascii() => {
+                    Some(PrefixExtractor::Delimited { delimiter, count: count.parse().ok()? })
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PrefixExtractor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+impl From<PrefixExtractor> for String {
+    fn from(extractor: PrefixExtractor) -> Self {
+        extractor.name()
+    }
+}
+
+impl TryFrom<String> for PrefixExtractor {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::from_name(&name).ok_or_else(|| format!("unknown prefix extractor '{}'", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix() {
+        let fixed = PrefixExtractor::Fixed(3);
+        assert_eq!(fixed.prefix(b"abcdef"), Some(&b"abc"[..]));
+        assert_eq!(fixed.prefix(b"abc"), Some(&b"abc"[..]));
+        assert_eq!(fixed.prefix(b"ab"), None);
+
+        let delimited = PrefixExtractor::Delimited { delimiter: b'/', count: 1 };
+        assert_eq!(delimited.prefix(b"a/b/c"), Some(&b"a/"[..]));
+        assert_eq!(delimited.prefix(b"abc"), None);
+
+        assert_eq!(PrefixExtractor::None.prefix(b"abc"), None);
+        assert_eq!(PrefixExtractor::Fixed(0).prefix(b"abc"), None);
+    }
+
+    #[test]
+    fn test_name_round_trip() {
+        for extractor in [
+            PrefixExtractor::None,
+            PrefixExtractor::Fixed(8),
+            PrefixExtractor::Delimited { delimiter: b':', count: 2 },
+        ] {
+            assert_eq!(PrefixExtractor::from_name(&extractor.name()), Some(extractor));
+        }
+        assert_eq!(PrefixExtractor::from_name("fixed"), None);
+        assert_eq!(PrefixExtractor::from_name("fixed:x"), None);
+        assert_eq!(PrefixExtractor::from_name("delimited:2:ab"), None);
+        assert_eq!(PrefixExtractor::from_name("capped:4"), None);
+    }
+}
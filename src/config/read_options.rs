@@ -0,0 +1,73 @@
+//! Per-read settings.
+//!
+//! [`Options`](super::Options) configure a database for its whole lifetime;
+//! a [`ReadOptions`] value is passed to a single read or iterator instead.
+
+/// Settings for one read or iterator.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use aidb::{DB, Options, ReadOptions};
+/// use std::sync::Arc;
+///
+/// # fn main() -> Result<(), aidb::Error> {
+/// let db = Arc::new(DB::open("./data", Options::default())?);
+///
+/// // A one-off full scan that should not evict hot blocks from the cache
+/// let mut iter = db.scan_with_options(&ReadOptions::new().fill_cache(false), None, None)?;
+/// while iter.valid() {
+///     iter.next();
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadOptions {
+    /// Whether blocks read from SSTables are added to the block cache.
+    /// Blocks already cached are used either way. Default: `true`
+    pub fill_cache: bool,
+
+    /// Confine iterators to the prefix of their seek target, as returned by
+    /// [`Options::prefix_extractor`](super::Options::prefix_extractor).
+    /// SSTables whose prefix Bloom filter rules the prefix out are skipped,
+    /// and the iterator becomes invalid past the last key with the prefix.
+    /// Has no effect for targets without a prefix. Default: `false`
+    pub prefix_same_as_start: bool,
+
+    /// Create a tailing iterator, which picks up writes made after it was
+    /// created when [`next`](crate::DBIterator::next) is called again after
+    /// the last entry, and on every forward seek. Default: `false`
+    pub tailing: bool,
+}
+
+impl ReadOptions {
+    /// Creates the default read options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether blocks read from disk are added to the block cache.
+    pub fn fill_cache(mut self, fill_cache: bool) -> Self {
+        self.fill_cache = fill_cache;
+        self
+    }
+
+    /// Sets whether iterators stay within the prefix of their seek target.
+    pub fn prefix_same_as_start(mut self, value: bool) -> Self {
+        self.prefix_same_as_start = value;
+        self
+    }
+
+    /// Sets whether iterators pick up writes made after their creation.
+    pub fn tailing(mut self, tailing: bool) -> Self {
+        self.tailing = tailing;
+        self
+    }
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self { fill_cache: true, prefix_same_as_start: false, tailing: false }
+    }
+}
@@ -0,0 +1,489 @@
+//! [`Env`] that encrypts every file it writes.
+//!
+//! [`EncryptedEnv`] wraps another environment and encrypts file contents
+//! with AES-256 in CTR mode. Each file starts with a 36 byte header holding
+//! the version of its key, a random 16 byte IV, so no two files share a
+//! keystream, and a check value that tells a wrong key apart from a
+//! damaged file. Since CTR
+//! encrypts each 16 byte block independently of the others, positional
+//! reads decrypt only the bytes they read. Offsets and sizes seen by the
+//! engine exclude the header.
+//!
+//! Setting [`Options::key_provider`](crate::Options::key_provider) wraps
+//! the database's [`Options::env`](crate::Options::env) in an
+//! [`EncryptedEnv`], so that SSTables, WAL files, the MANIFEST and every
+//! other file are encrypted. Directory listings and file names are not.
+//! Files given to [`DB::ingest_external_file`](crate::DB::ingest_external_file)
+//! must be written through an [`EncryptedEnv`] with the same keys.
+//!
+//! # Key rotation
+//!
+//! New files are encrypted with the active key of the [`KeyProvider`];
+//! files written with older keys are read with the key of the version in
+//! their header. Once a new key is active,
+//! [`DB::rewrite_with_latest_key`](crate::DB::rewrite_with_latest_key)
+//! re-encrypts the remaining files, after which the old keys can be
+//! dropped from the provider.
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, InnerIvInit, KeyInit, StreamCipher, StreamCipherSeek};
+use aes::Aes256;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{
+    AccessHint, Env, FileLock, KeyProvider, RandomAccessFile, SequentialFile, WritableFile,
+};
+
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+/// Identifies an encrypted file, followed by the format version
+const MAGIC: &[u8; 7] = b"AIDBENC";
+const FORMAT_VERSION: u8 = 1;
+
+/// Magic, format version, key version, IV and key check
+const HEADER_SIZE: u64 = 7 + 1 + 4 + 16 + 8;
+
+/// Encrypted with the key to tell whether a file was written with it
+const KEY_CHECK_BLOCK: &[u8; 16] = b"aidb key check\0\0";
+
+/// The key and IV a file is encrypted with
+#[derive(Clone)]
+struct FileCipher {
+    key_version: u32,
+    aes: Aes256,
+    iv: [u8; 16],
+}
+
+impl FileCipher {
+    /// A keystream starting at byte `offset` of the file's contents
+    fn keystream_at(&self, offset: u64) -> Aes256Ctr {
+        let core = ctr::CtrCore::inner_iv_init(self.aes.clone(), &self.iv.into());
+        let mut cipher = Aes256Ctr::from_core(core);
+        cipher.seek(offset);
+        cipher
+    }
+
+    fn key_check(aes: &Aes256) -> [u8; 8] {
+        let mut block = GenericArray::from(*KEY_CHECK_BLOCK);
+        aes.encrypt_block(&mut block);
+        block[..8].try_into().expect("8 bytes")
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(MAGIC);
+        header.push(FORMAT_VERSION);
+        header.extend_from_slice(&self.key_version.to_le_bytes());
+        header.extend_from_slice(&self.iv);
+        header.extend_from_slice(&Self::key_check(&self.aes));
+        header
+    }
+
+    /// Returns the key version of a file from its `header`
+    fn key_version(header: &[u8], path: &Path) -> io::Result<u32> {
+        let invalid =
+            |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}: {}", path, msg));
+        if header.len() < HEADER_SIZE as usize || &header[..7] != MAGIC {
+            return Err(invalid("not an encrypted file"));
+        }
+        if header[7] != FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported encryption format {}", header[7])));
+        }
+        Ok(u32::from_le_bytes(header[8..12].try_into().expect("4 bytes")))
+    }
+
+    /// Reads the cipher of a file from its `header`, checking that it was
+    /// written with the key of its version
+    fn from_header(header: &[u8], keys: &dyn KeyProvider, path: &Path) -> io::Result<Self> {
+        let key_version = Self::key_version(header, path)?;
+        let aes = Aes256::new(&keys.key(key_version)?.into());
+        if header[28..36] != Self::key_check(&aes) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?}: encrypted with a different key", path),
+            ));
+        }
+        Ok(Self { key_version, aes, iv: header[12..28].try_into().expect("16 bytes") })
+    }
+}
+
+/// Wraps an [`Env`] so that file contents are encrypted at rest.
+///
+/// See the [module documentation](self).
+pub struct EncryptedEnv {
+    base: Arc<dyn Env>,
+    key_provider: Arc<dyn KeyProvider>,
+}
+
+impl std::fmt::Debug for EncryptedEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedEnv").finish_non_exhaustive()
+    }
+}
+
+impl EncryptedEnv {
+    /// Encrypts the files of `base` with the keys of `key_provider`.
+    pub fn new(base: Arc<dyn Env>, key_provider: Arc<dyn KeyProvider>) -> Self {
+        Self { base, key_provider }
+    }
+
+    /// A cipher with a fresh IV for a new file
+    fn new_cipher(&self) -> io::Result<FileCipher> {
+        let (key_version, key) = self.key_provider.active_key()?;
+        let mut iv = [0u8; 16];
+        getrandom::fill(&mut iv).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(FileCipher { key_version, aes: Aes256::new(&key.into()), iv })
+    }
+
+    /// The header of the existing file at `path`
+    fn read_header(
+        &self,
+        path: &Path,
+        file: &dyn RandomAccessFile,
+    ) -> io::Result<[u8; HEADER_SIZE as usize]> {
+        let mut header = [0u8; HEADER_SIZE as usize];
+        if file.size()? < HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?}: not an encrypted file", path),
+            ));
+        }
+        file.read_exact_at(&mut header, 0)?;
+        Ok(header)
+    }
+
+    /// The cipher of the existing file at `path`
+    fn read_cipher(&self, path: &Path, file: &dyn RandomAccessFile) -> io::Result<FileCipher> {
+        let header = self.read_header(path, file)?;
+        FileCipher::from_header(&header, &*self.key_provider, path)
+    }
+
+    /// Returns the version of the key the file at `path` is encrypted with.
+    pub fn key_version(&self, path: &Path) -> io::Result<u32> {
+        let file = self.base.new_random_access_file(path)?;
+        FileCipher::key_version(&self.read_header(path, &*file)?, path)
+    }
+
+    /// Re-encrypts the file at `path` with the active key unless it already
+    /// uses it; returns whether it was rewritten.
+    ///
+    /// The file is copied to a temporary file that then replaces it, so
+    /// handles opened before keep reading the old contents. It must not be
+    /// written meanwhile.
+    pub fn reencrypt_file(&self, path: &Path) -> io::Result<bool> {
+        let (active_version, _) = self.key_provider.active_key()?;
+        if self.key_version(path)? == active_version {
+            return Ok(false);
+        }
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".rekey");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let mut reader = self.new_sequential_file(path)?;
+        let mut writer = self.create(&tmp_path)?;
+        io::copy(&mut reader, &mut writer)?;
+        writer.sync()?;
+        drop(writer);
+        self.base.rename_file(&tmp_path, path)?;
+        Ok(true)
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        let cipher = self.new_cipher()?;
+        let mut file = self.base.new_writable_file(path)?;
+        file.write_all(&cipher.header())?;
+        Ok(Box::new(EncryptedWritableFile { file, keystream: cipher.keystream_at(0) }))
+    }
+}
+
+impl Env for EncryptedEnv {
+    fn new_writable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        self.create(path)
+    }
+
+    fn new_appendable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        if !self.base.file_exists(path) || self.base.file_size(path)? == 0 {
+            return self.create(path);
+        }
+        let existing = self.base.new_random_access_file(path)?;
+        let cipher = self.read_cipher(path, &*existing)?;
+        let size = existing.size()? - HEADER_SIZE;
+        let file = self.base.new_appendable_file(path)?;
+        Ok(Box::new(EncryptedWritableFile { file, keystream: cipher.keystream_at(size) }))
+    }
+
+    fn new_sequential_file(&self, path: &Path) -> io::Result<Box<dyn SequentialFile>> {
+        let mut file = self.base.new_sequential_file(path)?;
+        let mut header = [0u8; HEADER_SIZE as usize];
+        file.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?}: not an encrypted file", path),
+            ),
+            _ => e,
+        })?;
+        let cipher = FileCipher::from_header(&header, &*self.key_provider, path)?;
+        Ok(Box::new(EncryptedSequentialFile { file, keystream: cipher.keystream_at(0) }))
+    }
+
+    fn new_random_access_file(&self, path: &Path) -> io::Result<Arc<dyn RandomAccessFile>> {
+        let file = self.base.new_random_access_file(path)?;
+        let cipher = self.read_cipher(path, &*file)?;
+        Ok(Arc::new(EncryptedRandomAccessFile { file, cipher }))
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        self.base.file_exists(path)
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        Ok(self.base.file_size(path)?.saturating_sub(HEADER_SIZE))
+    }
+
+    fn get_children(&self, dir: &Path) -> io::Result<Vec<String>> {
+        self.base.get_children(dir)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        self.base.create_dir_all(dir)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.base.remove_file(path)
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        self.base.remove_dir(dir)
+    }
+
+    fn rename_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.base.rename_file(from, to)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        self.base.sync_dir(dir)
+    }
+
+    // The encrypted bytes are shared as they are; the copy keeps the IV
+    fn link_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.base.link_file(from, to)
+    }
+
+    // Lock files are empty; they are not encrypted
+    fn lock_file(&self, path: &Path) -> io::Result<Box<dyn FileLock>> {
+        self.base.lock_file(path)
+    }
+
+    fn now_micros(&self) -> u64 {
+        self.base.now_micros()
+    }
+}
+
+struct EncryptedWritableFile {
+    file: Box<dyn WritableFile>,
+    /// Positioned at the end of the file
+    keystream: Aes256Ctr,
+}
+
+impl Write for EncryptedWritableFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut encrypted = buf.to_vec();
+        self.keystream.apply_keystream(&mut encrypted);
+        // All or nothing, since the keystream has moved past `buf`
+        self.file.write_all(&encrypted)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl WritableFile for EncryptedWritableFile {
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync()
+    }
+
+    fn advise(&self, hint: AccessHint) -> io::Result<()> {
+        self.file.advise(hint)
+    }
+}
+
+struct EncryptedSequentialFile {
+    file: Box<dyn SequentialFile>,
+    /// Positioned at the file's read position
+    keystream: Aes256Ctr,
+}
+
+impl Read for EncryptedSequentialFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read(buf)?;
+        self.keystream.apply_keystream(&mut buf[..n]);
+        Ok(n)
+    }
+}
+
+impl Seek for EncryptedSequentialFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => SeekFrom::Start(offset + HEADER_SIZE),
+            pos => pos,
+        };
+        let position = self.file.seek(pos)?;
+        let Some(offset) = position.checked_sub(HEADER_SIZE) else {
+            self.file.seek(SeekFrom::Start(HEADER_SIZE))?;
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start of file"));
+        };
+        self.keystream.seek(offset);
+        Ok(offset)
+    }
+}
+
+struct EncryptedRandomAccessFile {
+    file: Arc<dyn RandomAccessFile>,
+    cipher: FileCipher,
+}
+
+impl RandomAccessFile for EncryptedRandomAccessFile {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.file.read_exact_at(buf, offset + HEADER_SIZE)?;
+        self.cipher.keystream_at(offset).apply_keystream(buf);
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.file.size()?.saturating_sub(HEADER_SIZE))
+    }
+
+    fn read_exact_at_batch(&self, reads: &mut [(u64, &mut [u8])]) -> io::Result<()> {
+        let mut shifted: Vec<(u64, &mut [u8])> = reads
+            .iter_mut()
+            .map(|(offset, buf)| (*offset + HEADER_SIZE, &mut **buf))
+            .collect();
+        self.file.read_exact_at_batch(&mut shifted)?;
+        for (offset, buf) in reads.iter_mut() {
+            self.cipher.keystream_at(*offset).apply_keystream(buf);
+        }
+        Ok(())
+    }
+
+    fn advise(&self, hint: AccessHint) -> io::Result<()> {
+        self.file.advise(hint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::{MemEnv, StaticKeyProvider};
+    use crate::{Options, DB};
+
+    fn encrypted(base: &Arc<MemEnv>, key: u8) -> EncryptedEnv {
+        EncryptedEnv::new(base.clone(), Arc::new(StaticKeyProvider::new([key; 32])))
+    }
+
+    #[test]
+    fn test_encrypted_env_files() {
+        let base = Arc::new(MemEnv::new());
+        let env = encrypted(&base, 1);
+        let path = Path::new("/file");
+
+        env.write_file(path, b"hello encrypted world").unwrap();
+        let raw = base.read_file(path).unwrap();
+        assert_eq!(raw.len() as u64, HEADER_SIZE + 21);
+        assert!(!raw.windows(5).any(|w| w == b"hello"));
+        assert_eq!(env.file_size(path).unwrap(), 21);
+        assert_eq!(env.read_file(path).unwrap(), b"hello encrypted world");
+
+        // Appends continue the keystream
+        let mut file = env.new_appendable_file(path).unwrap();
+        file.write_all(b"!").unwrap();
+        file.sync().unwrap();
+        let file = env.new_random_access_file(path).unwrap();
+        let mut buf = [0u8; 9];
+        file.read_exact_at(&mut buf, 6).unwrap();
+        assert_eq!(&buf, b"encrypted");
+        assert_eq!(file.size().unwrap(), 22);
+
+        let mut file = env.new_sequential_file(path).unwrap();
+        file.seek(SeekFrom::Start(16)).unwrap();
+        let mut rest = String::new();
+        file.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "world!");
+
+        // The same plaintext gets a different IV
+        env.write_file(Path::new("/other"), b"hello encrypted world!").unwrap();
+        assert_ne!(base.read_file(Path::new("/other")).unwrap(), base.read_file(path).unwrap());
+
+        let err = encrypted(&base, 2).read_file(path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_encrypted_db() {
+        let base = Arc::new(MemEnv::new());
+        let options = Options::default()
+            .env(base.clone())
+            .key_provider(Arc::new(StaticKeyProvider::new([7; 32])));
+        let db = DB::open("/db", options.clone()).unwrap();
+        db.put(b"secret-key", b"secret-value").unwrap();
+        db.flush().unwrap();
+        db.put(b"in-wal", b"wal-value").unwrap();
+        drop(db);
+
+        for name in base.get_children(Path::new("/db")).unwrap() {
+            let data = base.read_file(&Path::new("/db").join(&name)).unwrap();
+            assert!(!data.windows(6).any(|w| w == b"secret" || w == b"wal-va"), "{}", name);
+        }
+
+        let db = DB::open("/db", options).unwrap();
+        assert_eq!(db.get(b"secret-key").unwrap(), Some(b"secret-value".to_vec()));
+        assert_eq!(db.get(b"in-wal").unwrap(), Some(b"wal-value".to_vec()));
+        drop(db);
+
+        let wrong_key = Options::default()
+            .env(base)
+            .key_provider(Arc::new(StaticKeyProvider::new([8; 32])));
+        assert!(DB::open("/db", wrong_key).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_db_key_rotation() {
+        let base = Arc::new(MemEnv::new());
+        let old_keys = Arc::new(StaticKeyProvider::new([1; 32]));
+        let options = Options::default().env(base.clone()).key_provider(old_keys);
+        let db = DB::open("/db", options).unwrap();
+        db.put(b"flushed", b"old key").unwrap();
+        db.flush().unwrap();
+        db.put(b"in-wal", b"old key").unwrap();
+        drop(db);
+
+        // Files written with version 0 stay readable once version 1 is active
+        let rotated = Arc::new(StaticKeyProvider::new([1; 32]).with_key(1, [2; 32]));
+        let options = Options::default().env(base.clone()).key_provider(rotated.clone());
+        let db = DB::open("/db", options).unwrap();
+        assert_eq!(db.get(b"flushed").unwrap(), Some(b"old key".to_vec()));
+        db.put(b"new", b"new key").unwrap();
+        assert!(db.rewrite_with_latest_key().unwrap() > 0);
+        assert_eq!(db.rewrite_with_latest_key().unwrap(), 0);
+        assert_eq!(db.get(b"in-wal").unwrap(), Some(b"old key".to_vec()));
+        drop(db);
+
+        let env = EncryptedEnv::new(base.clone(), rotated);
+        for name in base.get_children(Path::new("/db")).unwrap() {
+            if let Ok(version) = env.key_version(&Path::new("/db").join(&name)) {
+                assert_eq!(version, 1, "{}", name);
+            }
+        }
+
+        // Only the new key is needed from now on
+        let new_keys = Arc::new(StaticKeyProvider::new([9; 32]).with_key(1, [2; 32]));
+        let options = Options::default().env(base).key_provider(new_keys);
+        let db = DB::open("/db", options).unwrap();
+        for key in [&b"flushed"[..], b"in-wal", b"new"] {
+            assert!(db.get(key).unwrap().is_some());
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! [`Env`] that fails, delays and forgets writes on demand, for testing.
+//!
+//! [`FaultInjectionEnv`] passes every call to another environment and
+//! remembers how much of each file it wrote has been synced. Tests use it
+//! to reproduce what a real machine does to the files of a process that
+//! dies:
+//!
+//! - [`simulate_crash`](FaultInjectionEnv::simulate_crash) drops everything
+//!   written since the last sync of each file, as a power loss would, and
+//!   [`simulate_torn_crash`](FaultInjectionEnv::simulate_torn_crash) keeps
+//!   only part of it, tearing the last records in two
+//! - [`fail_after`](FaultInjectionEnv::fail_after) makes the N-th following
+//!   write operation, and all later ones, fail with an I/O error or a full
+//!   disk
+//! - [`set_sync_delay`](FaultInjectionEnv::set_sync_delay) makes syncs slow
+//!
+//! Needs the `testing` feature.
+//!
+//! # Example
+//!
+//! ```rust
+//! use aidb::env::{FaultInjectionEnv, MemEnv};
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let env = Arc::new(FaultInjectionEnv::new(Arc::new(MemEnv::new())));
+//! let options = Options::default().env(env.clone()).sync_wal(false);
+//! let db = DB::open("/db", options.clone())?;
+//! db.put(b"key", b"value")?;
+//!
+//! // The write was never synced, so the crash loses it
+//! db.simulate_crash();
+//! env.simulate_crash()?;
+//! let db = DB::open("/db", options)?;
+//! assert_eq!(db.get(b"key")?, None);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use super::{AccessHint, Env, FileLock, RandomAccessFile, SequentialFile, WritableFile};
+
+/// The error injected by [`FaultInjectionEnv::fail_after`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// A generic I/O error, as `EIO` from a failing disk
+    Io,
+    /// A full disk ([`io::ErrorKind::StorageFull`], as `ENOSPC`)
+    NoSpace,
+}
+
+impl Fault {
+    fn error(self) -> io::Error {
+        match self {
+            Fault::Io => io::Error::other("injected I/O error"),
+            Fault::NoSpace => {
+                io::Error::new(io::ErrorKind::StorageFull, "injected out of space error")
+            }
+        }
+    }
+}
+
+/// Wraps another [`Env`] with controllable failures.
+///
+/// Only files created or appended to through this environment are
+/// tracked; the contents files had before are assumed to be synced.
+/// Write operations are file creations, writes, syncs, renames, links,
+/// deletions and directory operations; reads are passed through and
+/// never fail.
+///
+/// See the [module documentation](self).
+pub struct FaultInjectionEnv {
+    base: Arc<dyn Env>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Written and synced length of the tracked files
+    files: HashMap<PathBuf, FileState>,
+    /// Write operations so far
+    operations: u64,
+    /// Operation from which on writes fail, and how
+    fault: Option<(u64, Fault)>,
+    sync_delay: Duration,
+    /// Bumped by each crash; files opened before one fail
+    generation: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct FileState {
+    len: u64,
+    synced: u64,
+}
+
+impl State {
+    /// Counts a write operation and returns the injected error, if any.
+    fn operation(&mut self) -> io::Result<()> {
+        self.operations += 1;
+        match self.fault {
+            Some((from, fault)) if self.operations >= from => Err(fault.error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FaultInjectionEnv {
+    /// Wraps `base`, without any fault injected.
+    pub fn new(base: Arc<dyn Env>) -> Self {
+        Self { base, state: Arc::new(Mutex::new(State::default())) }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Number of write operations done through the environment so far.
+    pub fn operation_count(&self) -> u64 {
+        self.state().operations
+    }
+
+    /// Makes the `n`-th following write operation (counting from 1) fail
+    /// with `fault`, and every one after it, until
+    /// [`clear_fault`](Self::clear_fault).
+    pub fn fail_after(&self, n: u64, fault: Fault) {
+        let mut state = self.state();
+        state.fault = Some((state.operations + n.max(1), fault));
+    }
+
+    /// Lets write operations succeed again.
+    pub fn clear_fault(&self) {
+        self.state().fault = None;
+    }
+
+    /// Makes every file and directory sync wait `delay` before syncing.
+    pub fn set_sync_delay(&self, delay: Duration) {
+        self.state().sync_delay = delay;
+    }
+
+    /// Number of bytes written to `path` through the environment that
+    /// have not been synced.
+    pub fn unsynced_bytes(&self, path: &Path) -> u64 {
+        self.state().files.get(path).map_or(0, |file| file.len - file.synced)
+    }
+
+    /// Simulates a power loss: truncates every tracked file to the length
+    /// it had at its last sync.
+    ///
+    /// Files opened for writing before the crash fail from then on, so
+    /// that threads of a database abandoned with
+    /// [`DB::simulate_crash`](crate::DB::simulate_crash) cannot write to
+    /// the files again. Injected faults and the sync delay are cleared.
+    pub fn simulate_crash(&self) -> io::Result<()> {
+        self.simulate_torn_crash(0)
+    }
+
+    /// Like [`simulate_crash`](Self::simulate_crash), but keeps up to
+    /// `keep` bytes of each file's unsynced tail, so that the last record
+    /// written before the crash may be cut in two.
+    pub fn simulate_torn_crash(&self, keep: u64) -> io::Result<()> {
+        let mut state = self.state();
+        state.generation += 1;
+        state.fault = None;
+        state.sync_delay = Duration::ZERO;
+        for (path, file) in state.files.iter_mut() {
+            if file.len == file.synced || !self.base.file_exists(path) {
+                continue;
+            }
+            let mut data = self.base.read_file(path)?;
+            data.truncate((file.synced + keep).min(file.len) as usize);
+            self.base.write_file(path, &data)?;
+            *file = FileState { len: data.len() as u64, synced: data.len() as u64 };
+        }
+        Ok(())
+    }
+
+    fn track(&self, path: &Path, file: Box<dyn WritableFile>, len: u64) -> Box<dyn WritableFile> {
+        let mut state = self.state();
+        let synced = state.files.get(path).map_or(len, |file| file.synced.min(len));
+        state.files.insert(path.to_path_buf(), FileState { len, synced });
+        Box::new(FaultInjectionFile {
+            inner: file,
+            path: path.to_path_buf(),
+            state: Arc::clone(&self.state),
+            generation: state.generation,
+        })
+    }
+
+    fn sync_delay(&self) -> io::Result<()> {
+        let delay = {
+            let mut state = self.state();
+            state.operation()?;
+            state.sync_delay
+        };
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+        Ok(())
+    }
+}
+
+/// A file written through a [`FaultInjectionEnv`]
+struct FaultInjectionFile {
+    inner: Box<dyn WritableFile>,
+    path: PathBuf,
+    state: Arc<Mutex<State>>,
+    generation: u64,
+}
+
+/// Locks the state for a write operation on a file opened in `generation`.
+fn file_operation<'a>(
+    state: &'a Mutex<State>,
+    generation: u64,
+    path: &Path,
+) -> io::Result<MutexGuard<'a, State>> {
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    if state.generation != generation {
+        return Err(io::Error::other(format!("{:?}: file lost in a simulated crash", path)));
+    }
+    state.operation()?;
+    Ok(state)
+}
+
+impl Write for FaultInjectionFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = file_operation(&self.state, self.generation, &self.path)?;
+        let written = self.inner.write(buf)?;
+        if let Some(file) = state.files.get_mut(&self.path) {
+            file.len += written as u64;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl WritableFile for FaultInjectionFile {
+    fn sync(&mut self) -> io::Result<()> {
+        let delay = file_operation(&self.state, self.generation, &self.path)?.sync_delay;
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+        self.inner.sync()?;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.generation == self.generation {
+            if let Some(file) = state.files.get_mut(&self.path) {
+                file.synced = file.len;
+            }
+        }
+        Ok(())
+    }
+
+    fn advise(&self, hint: AccessHint) -> io::Result<()> {
+        self.inner.advise(hint)
+    }
+}
+
+impl Env for FaultInjectionEnv {
+    fn new_writable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        self.state().operation()?;
+        let file = self.base.new_writable_file(path)?;
+        Ok(self.track(path, file, 0))
+    }
+
+    fn new_appendable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        self.state().operation()?;
+        let file = self.base.new_appendable_file(path)?;
+        let len = self.base.file_size(path)?;
+        Ok(self.track(path, file, len))
+    }
+
+    fn new_sequential_file(&self, path: &Path) -> io::Result<Box<dyn SequentialFile>> {
+        self.base.new_sequential_file(path)
+    }
+
+    fn new_random_access_file(&self, path: &Path) -> io::Result<Arc<dyn RandomAccessFile>> {
+        self.base.new_random_access_file(path)
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        self.base.file_exists(path)
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        self.base.file_size(path)
+    }
+
+    fn get_children(&self, dir: &Path) -> io::Result<Vec<String>> {
+        self.base.get_children(dir)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        self.state().operation()?;
+        self.base.create_dir_all(dir)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.state().operation()?;
+        self.base.remove_file(path)?;
+        self.state().files.remove(path);
+        Ok(())
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        self.state().operation()?;
+        self.base.remove_dir(dir)
+    }
+
+    fn rename_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.state().operation()?;
+        self.base.rename_file(from, to)?;
+        let mut state = self.state();
+        match state.files.remove(from) {
+            Some(file) => state.files.insert(to.to_path_buf(), file),
+            None => state.files.remove(to),
+        };
+        Ok(())
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        self.sync_delay()?;
+        self.base.sync_dir(dir)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.base.read_file(path)
+    }
+
+    fn link_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.state().operation()?;
+        self.base.link_file(from, to)?;
+        let mut state = self.state();
+        match state.files.get(from).copied() {
+            Some(file) => state.files.insert(to.to_path_buf(), file),
+            None => state.files.remove(to),
+        };
+        Ok(())
+    }
+
+    fn lock_file(&self, path: &Path) -> io::Result<Box<dyn FileLock>> {
+        self.base.lock_file(path)
+    }
+
+    fn now_micros(&self) -> u64 {
+        self.base.now_micros()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::MemEnv;
+
+    #[test]
+    fn test_fault_injection_env() {
+        let env = FaultInjectionEnv::new(Arc::new(MemEnv::new()));
+        env.create_dir_all(Path::new("/db")).unwrap();
+        let path = Path::new("/db/a");
+
+        let mut file = env.new_writable_file(path).unwrap();
+        file.write_all(b"synced").unwrap();
+        file.sync().unwrap();
+        file.write_all(b" unsynced").unwrap();
+        assert_eq!(env.unsynced_bytes(path), 9);
+
+        env.simulate_torn_crash(3).unwrap();
+        assert_eq!(env.read_file(path).unwrap(), b"synced un");
+        assert!(file.write_all(b"after the crash").is_err());
+
+        let mut file = env.new_appendable_file(path).unwrap();
+        file.write_all(b"lost").unwrap();
+        env.simulate_crash().unwrap();
+        assert_eq!(env.read_file(path).unwrap(), b"synced un");
+
+        env.fail_after(2, Fault::NoSpace);
+        let mut file = env.new_writable_file(Path::new("/db/b")).unwrap();
+        let err = file.write_all(b"data").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+        assert!(env.remove_file(path).is_err());
+        env.clear_fault();
+        env.remove_file(path).unwrap();
+        assert_eq!(env.unsynced_bytes(path), 0);
+
+        env.fail_after(1, Fault::Io);
+        assert_eq!(env.sync_dir(Path::new("/db")).unwrap_err().kind(), io::ErrorKind::Other);
+    }
+}
@@ -0,0 +1,330 @@
+//! [`Env`] that keeps every file in memory.
+
+use super::{Env, FileLock, RandomAccessFile, SequentialFile, WritableFile};
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+type FileData = Arc<RwLock<Vec<u8>>>;
+
+/// Stores files in memory instead of on disk.
+///
+/// Directories must be created before files are created in them, as on a
+/// real filesystem; the root and the current directory (the empty path)
+/// always exist. Writes are visible to readers immediately and `sync` is a
+/// no-op. Everything is lost when the `MemEnv` is dropped.
+#[derive(Debug, Default)]
+pub struct MemEnv {
+    state: Mutex<State>,
+    /// Paths locked with `lock_file`
+    locks: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+/// Unlocks its path when dropped
+struct MemFileLock {
+    path: PathBuf,
+    locks: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl FileLock for MemFileLock {}
+
+impl Drop for MemFileLock {
+    fn drop(&mut self) {
+        self.locks.lock().remove(&self.path);
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    files: HashMap<PathBuf, FileData>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl State {
+    fn dir_exists(&self, dir: &Path) -> bool {
+        dir.as_os_str().is_empty() || dir.parent().is_none() || self.dirs.contains(dir)
+    }
+
+    /// Fails unless the directory that will contain `path` exists
+    fn check_parent(&self, path: &Path) -> io::Result<()> {
+        if self.dirs.contains(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{:?} is a directory", path),
+            ));
+        }
+        match path.parent() {
+            Some(parent) if !self.dir_exists(parent) => Err(not_found(parent)),
+            _ => Ok(()),
+        }
+    }
+
+    fn file(&self, path: &Path) -> io::Result<&FileData> {
+        self.files.get(path).ok_or_else(|| not_found(path))
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{:?} not found", path))
+}
+
+impl MemEnv {
+    /// Creates an empty environment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the total size of all files in bytes.
+    pub fn total_size(&self) -> u64 {
+        self.state.lock().files.values().map(|data| data.read().len() as u64).sum()
+    }
+}
+
+/// Appends to the shared contents of a file
+struct MemWritableFile {
+    data: FileData,
+}
+
+impl Write for MemWritableFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.write().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WritableFile for MemWritableFile {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the current contents of a file
+struct MemRandomAccessFile {
+    data: FileData,
+}
+
+impl RandomAccessFile for MemRandomAccessFile {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let data = self.data.read();
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        match start.checked_add(buf.len()).and_then(|end| data.get(start..end)) {
+            Some(bytes) => {
+                buf.copy_from_slice(bytes);
+                Ok(())
+            }
+            None => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.data.read().len() as u64)
+    }
+}
+
+impl Env for MemEnv {
+    fn new_writable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        let mut state = self.state.lock();
+        state.check_parent(path)?;
+        let data = FileData::default();
+        state.files.insert(path.to_path_buf(), Arc::clone(&data));
+        Ok(Box::new(MemWritableFile { data }))
+    }
+
+    fn new_appendable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        let mut state = self.state.lock();
+        state.check_parent(path)?;
+        let data = state.files.entry(path.to_path_buf()).or_default();
+        Ok(Box::new(MemWritableFile { data: Arc::clone(data) }))
+    }
+
+    fn new_sequential_file(&self, path: &Path) -> io::Result<Box<dyn SequentialFile>> {
+        let data = self.state.lock().file(path)?.read().clone();
+        Ok(Box::new(Cursor::new(data)))
+    }
+
+    fn new_random_access_file(&self, path: &Path) -> io::Result<Arc<dyn RandomAccessFile>> {
+        let data = Arc::clone(self.state.lock().file(path)?);
+        Ok(Arc::new(MemRandomAccessFile { data }))
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        let state = self.state.lock();
+        state.files.contains_key(path) || state.dir_exists(path)
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        Ok(self.state.lock().file(path)?.read().len() as u64)
+    }
+
+    fn get_children(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let state = self.state.lock();
+        if !state.dir_exists(dir) {
+            return Err(not_found(dir));
+        }
+        let mut children: Vec<String> = state
+            .files
+            .keys()
+            .chain(&state.dirs)
+            .filter(|path| path.parent() == Some(dir))
+            .filter_map(|path| path.file_name()?.to_str().map(str::to_string))
+            .collect();
+        // In name order, so that runs on a MemEnv are repeatable
+        children.sort();
+        Ok(children)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        let mut state = self.state.lock();
+        for ancestor in dir.ancestors().filter(|a| !a.as_os_str().is_empty()) {
+            if state.files.contains_key(ancestor) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{:?} is a file", ancestor),
+                ));
+            }
+        }
+        for ancestor in dir.ancestors() {
+            if state.dir_exists(ancestor) {
+                break;
+            }
+            state.dirs.insert(ancestor.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.state.lock().files.remove(path).map(|_| ()).ok_or_else(|| not_found(path))
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        let mut state = self.state.lock();
+        if !state.dirs.contains(dir) {
+            return Err(not_found(dir));
+        }
+        if state.files.keys().chain(&state.dirs).any(|path| path.parent() == Some(dir)) {
+            return Err(io::Error::new(
+                io::ErrorKind::DirectoryNotEmpty,
+                format!("{:?} is not empty", dir),
+            ));
+        }
+        state.dirs.remove(dir);
+        Ok(())
+    }
+
+    fn rename_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.state.lock();
+        state.file(from)?;
+        state.check_parent(to)?;
+        let data = state.files.remove(from).expect("checked above");
+        state.files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn link_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.state.lock();
+        let data = Arc::clone(state.file(from)?);
+        state.check_parent(to)?;
+        if state.files.contains_key(to) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} already exists", to),
+            ));
+        }
+        state.files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        match self.state.lock().dir_exists(dir) {
+            true => Ok(()),
+            false => Err(not_found(dir)),
+        }
+    }
+
+    fn lock_file(&self, path: &Path) -> io::Result<Box<dyn FileLock>> {
+        drop(self.new_appendable_file(path)?);
+        if !self.locks.lock().insert(path.to_path_buf()) {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{:?} is already locked", path),
+            ));
+        }
+        Ok(Box::new(MemFileLock {
+            path: path.to_path_buf(),
+            locks: Arc::clone(&self.locks),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_mem_env_files() {
+        let env = MemEnv::new();
+        let dir = Path::new("/db");
+        assert!(env.new_writable_file(&dir.join("a")).is_err());
+
+        env.create_dir_all(dir).unwrap();
+        env.write_file(&dir.join("a"), b"hello").unwrap();
+        let mut file = env.new_appendable_file(&dir.join("a")).unwrap();
+        file.write_all(b" world").unwrap();
+        file.sync().unwrap();
+
+        assert_eq!(env.read_file(&dir.join("a")).unwrap(), b"hello world");
+        assert_eq!(env.file_size(&dir.join("a")).unwrap(), 11);
+        let reader = env.new_random_access_file(&dir.join("a")).unwrap();
+        let mut buf = [0u8; 5];
+        reader.read_exact_at(&mut buf, 6).unwrap();
+        assert_eq!(&buf, b"world");
+        assert!(reader.read_exact_at(&mut buf, 7).is_err());
+
+        let mut sequential = env.new_sequential_file(&dir.join("a")).unwrap();
+        let mut contents = String::new();
+        sequential.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello world");
+    }
+
+    #[test]
+    fn test_mem_env_directories() {
+        let env = MemEnv::new();
+        let dir = Path::new("/db");
+        env.create_dir_all(&dir.join("lost")).unwrap();
+        env.write_file(&dir.join("a"), b"1").unwrap();
+        env.write_file(&dir.join("lost/b"), b"2").unwrap();
+
+        let mut children = env.get_children(dir).unwrap();
+        children.sort();
+        assert_eq!(children, ["a", "lost"]);
+        assert!(env.get_children(Path::new("/missing")).is_err());
+
+        env.rename_file(&dir.join("a"), &dir.join("c")).unwrap();
+        assert!(!env.file_exists(&dir.join("a")));
+        assert_eq!(env.read_file(&dir.join("c")).unwrap(), b"1");
+        assert!(env.rename_file(&dir.join("c"), &dir.join("lost")).is_err());
+        assert!(env.rename_file(&dir.join("a"), &dir.join("d")).is_err());
+
+        env.remove_file(&dir.join("c")).unwrap();
+        assert_eq!(env.remove_file(&dir.join("c")).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(env.total_size(), 1);
+        env.sync_dir(dir).unwrap();
+        assert!(env.sync_dir(Path::new("/missing")).is_err());
+
+        env.link_file(&dir.join("lost/b"), &dir.join("e")).unwrap();
+        assert_eq!(env.read_file(&dir.join("e")).unwrap(), b"2");
+        assert!(env.link_file(&dir.join("lost/b"), &dir.join("e")).is_err());
+        assert!(env.remove_dir(&dir.join("lost")).is_err());
+        env.remove_file(&dir.join("lost/b")).unwrap();
+        env.remove_dir(&dir.join("lost")).unwrap();
+        assert!(!env.file_exists(&dir.join("lost")));
+        assert_eq!(env.read_file(&dir.join("e")).unwrap(), b"2");
+    }
+}
@@ -0,0 +1,308 @@
+//! Storage environment used for all file access.
+//!
+//! The engine never calls `std::fs` directly; WAL, SSTable, MANIFEST,
+//! OPTIONS and `LOG` files are all created, read, renamed and deleted
+//! through the [`Env`] set in [`Options::env`](crate::Options::env). This
+//! makes the storage backend pluggable:
+//!
+//! - [`PosixEnv`] (the default) uses the local filesystem
+//! - [`MemEnv`] keeps every file in memory, for tests and for runtimes
+//!   without a filesystem such as `wasm32-unknown-unknown` in the browser
+//! - `IoUringEnv` (Linux, with the `io-uring` feature) submits reads,
+//!   writes and fsyncs through io_uring, batching block reads
+//! - `EncryptedEnv` (with the `encryption` feature) encrypts the files of
+//!   another environment with the key of a [`KeyProvider`]
+//! - [`ReadOnlyEnv`] reads the files of another environment and refuses
+//!   to change them
+//! - `FaultInjectionEnv` (with the `testing` feature) wraps another
+//!   environment to inject I/O errors and lose unsynced writes in tests
+//!
+//! Other backends (e.g. the browser's Origin Private File System) can be
+//! plugged in by implementing [`Env`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use aidb::env::MemEnv;
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let env = Arc::new(MemEnv::new());
+//! let db = DB::open("/db", Options::default().env(env.clone()))?;
+//! db.put(b"key", b"value")?;
+//! drop(db);
+//!
+//! // The files survive in the MemEnv and can be opened again
+//! let db = DB::open("/db", Options::default().env(env))?;
+//! assert_eq!(db.get(b"key")?, Some(b"value".to_vec()));
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "encryption")]
+mod encrypted;
+#[cfg(feature = "testing")]
+mod fault_injection;
+mod mem;
+mod posix;
+mod read_only;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+
+#[cfg(feature = "encryption")]
+pub use encrypted::EncryptedEnv;
+#[cfg(feature = "testing")]
+pub use fault_injection::{Fault, FaultInjectionEnv};
+pub use mem::MemEnv;
+pub use posix::PosixEnv;
+pub use read_only::ReadOnlyEnv;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use uring::{IoUringEnv, DEFAULT_QUEUE_DEPTH};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::util::{SystemTime, UNIX_EPOCH};
+
+/// A file being written.
+///
+/// Writes may be buffered; [`sync`](Self::sync) makes everything written so
+/// far durable.
+pub trait WritableFile: Write + Send + Sync {
+    /// Flushes buffered data and persists it to stable storage.
+    fn sync(&mut self) -> io::Result<()>;
+
+    /// Tells the OS how the file will be accessed. Hints are best effort;
+    /// the default ignores them.
+    fn advise(&self, _hint: AccessHint) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How a file is about to be accessed, for [`RandomAccessFile::advise`]
+/// and [`WritableFile::advise`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessHint {
+    /// The file will be read from start to end, so reading ahead pays off
+    Sequential,
+    /// The file's cached pages will not be needed again soon
+    DontNeed,
+}
+
+/// A file read from start to end, such as a WAL during recovery.
+pub trait SequentialFile: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> SequentialFile for T {}
+
+/// A file read at arbitrary offsets by several threads at once, such as an
+/// SSTable.
+pub trait RandomAccessFile: Send + Sync {
+    /// Reads exactly `buf.len()` bytes starting at `offset`.
+    ///
+    /// Fails with [`io::ErrorKind::UnexpectedEof`] if the file is too short.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+
+    /// Returns the size of the file in bytes.
+    fn size(&self) -> io::Result<u64>;
+
+    /// Tells the OS how the file will be accessed. Hints are best effort;
+    /// the default ignores them.
+    fn advise(&self, _hint: AccessHint) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Fills every `(offset, buffer)` pair, as [`read_exact_at`](Self::read_exact_at)
+    /// would for each.
+    ///
+    /// Used for readahead and verification; implementations that can
+    /// submit several reads at once (such as [`IoUringEnv`]) override it.
+    fn read_exact_at_batch(&self, reads: &mut [(u64, &mut [u8])]) -> io::Result<()> {
+        for (offset, buf) in reads.iter_mut() {
+            self.read_exact_at(buf, *offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for dyn RandomAccessFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RandomAccessFile")
+    }
+}
+
+/// A lock on a file, held until it is dropped.
+///
+/// Returned by [`Env::lock_file`].
+pub trait FileLock: Send + Sync {}
+
+impl FileLock for std::fs::File {}
+
+/// Held by backends that do not lock files
+struct NoLock;
+
+impl FileLock for NoLock {}
+
+/// File system operations used by the engine.
+///
+/// Paths are the ones the database was opened with, joined with file
+/// names; an implementation may map them to any storage it likes. Errors
+/// use [`io::Error`] so that the usual kinds (`NotFound`, `AlreadyExists`,
+/// the out-of-space error) are reported as for the local filesystem.
+pub trait Env: Send + Sync {
+    /// Creates a file for writing, truncating it if it exists.
+    fn new_writable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>>;
+
+    /// Opens a file for appending, creating it if it does not exist.
+    fn new_appendable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>>;
+
+    /// Opens an existing file for sequential reading.
+    fn new_sequential_file(&self, path: &Path) -> io::Result<Box<dyn SequentialFile>>;
+
+    /// Opens an existing file for positional reads.
+    fn new_random_access_file(&self, path: &Path) -> io::Result<Arc<dyn RandomAccessFile>>;
+
+    /// Returns true if a file or directory exists at `path`.
+    fn file_exists(&self, path: &Path) -> bool;
+
+    /// Returns the size of the file at `path`.
+    fn file_size(&self, path: &Path) -> io::Result<u64>;
+
+    /// Returns the names of the entries of directory `dir`.
+    fn get_children(&self, dir: &Path) -> io::Result<Vec<String>>;
+
+    /// Creates `dir` and any missing parents.
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()>;
+
+    /// Deletes the file at `path`.
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Deletes the empty directory `dir`.
+    fn remove_dir(&self, dir: &Path) -> io::Result<()>;
+
+    /// Atomically renames `from` to `to`, replacing `to` if it exists.
+    fn rename_file(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Makes creations, renames and deletions in `dir` durable.
+    fn sync_dir(&self, dir: &Path) -> io::Result<()>;
+
+    /// Reads the whole file at `path`.
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.new_sequential_file(path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Creates (or truncates) the file at `path` with `data` and syncs it.
+    fn write_file(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut file = self.new_writable_file(path)?;
+        file.write_all(data)?;
+        file.sync()
+    }
+
+    /// Makes the immutable file at `from` available at `to` as well,
+    /// sharing its storage where possible (a hard link on the local
+    /// filesystem). The default implementation copies it.
+    fn link_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let data = self.read_file(from)?;
+        self.write_file(to, &data)
+    }
+
+    /// Locks the file at `path`, creating it if needed, until the returned
+    /// lock is dropped.
+    ///
+    /// Fails with [`io::ErrorKind::WouldBlock`] if the file is already
+    /// locked, by this process or another one. The default implementation
+    /// locks nothing; backends whose files can be shared override it.
+    fn lock_file(&self, _path: &Path) -> io::Result<Box<dyn FileLock>> {
+        Ok(Box::new(NoLock))
+    }
+
+    /// Returns the current time in microseconds since the Unix epoch.
+    ///
+    /// The engine reads the time of day here wherever its behavior depends
+    /// on it (compaction windows, info log rotation, retrying after a full
+    /// disk), so a simulated environment can run it on a virtual clock.
+    /// The default implementation reads the system clock.
+    fn now_micros(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_micros() as u64)
+    }
+}
+
+impl fmt::Debug for dyn Env {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Env")
+    }
+}
+
+/// Supplies the keys files are encrypted with at rest.
+///
+/// Set in [`Options::key_provider`](crate::Options::key_provider); needs
+/// the `encryption` feature. Keys are numbered by version: new files are
+/// encrypted with the active key, and each file records the version of
+/// its key so files written before a key rotation stay readable. Keys are
+/// asked for whenever a file is created or opened, so an implementation
+/// fetching them from a key management service should cache them.
+pub trait KeyProvider: Send + Sync {
+    /// Returns the version and the 256-bit AES key new files are
+    /// encrypted with.
+    fn active_key(&self) -> io::Result<(u32, [u8; 32])>;
+
+    /// Returns the key of `version`.
+    fn key(&self, version: u32) -> io::Result<[u8; 32]>;
+}
+
+impl fmt::Debug for dyn KeyProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("KeyProvider")
+    }
+}
+
+/// A [`KeyProvider`] holding fixed keys; the one with the highest version
+/// is active.
+pub struct StaticKeyProvider {
+    keys: BTreeMap<u32, [u8; 32]>,
+}
+
+impl StaticKeyProvider {
+    /// Provides `key` as version 0.
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { keys: BTreeMap::from([(0, key)]) }
+    }
+
+    /// Adds `key` as `version`, replacing any key of that version.
+    pub fn with_key(mut self, version: u32, key: [u8; 32]) -> Self {
+        self.keys.insert(version, key);
+        self
+    }
+}
+
+impl fmt::Debug for StaticKeyProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticKeyProvider")
+            .field("versions", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn active_key(&self) -> io::Result<(u32, [u8; 32])> {
+        let (version, key) = self.keys.last_key_value().expect("at least one key");
+        Ok((*version, *key))
+    }
+
+    fn key(&self, version: u32) -> io::Result<[u8; 32]> {
+        self.keys.get(&version).copied().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no key with version {}", version))
+        })
+    }
+}
+
+/// Returns the shared [`PosixEnv`], the default of [`Options::env`](crate::Options::env).
+pub fn default_env() -> Arc<dyn Env> {
+    static DEFAULT: OnceLock<Arc<dyn Env>> = OnceLock::new();
+    Arc::clone(DEFAULT.get_or_init(|| Arc::new(PosixEnv)))
+}
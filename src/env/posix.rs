@@ -0,0 +1,115 @@
+//! [`Env`] backed by the local filesystem.
+
+use super::{AccessHint, Env, FileLock, RandomAccessFile, SequentialFile, WritableFile};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Accesses files through `std::fs`. This is the default environment.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixEnv;
+
+impl WritableFile for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_all()
+    }
+
+    fn advise(&self, hint: AccessHint) -> io::Result<()> {
+        crate::util::fadvise(self, hint)
+    }
+}
+
+impl RandomAccessFile for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        crate::util::read_exact_at(self, buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn advise(&self, hint: AccessHint) -> io::Result<()> {
+        crate::util::fadvise(self, hint)
+    }
+}
+
+impl Env for PosixEnv {
+    fn new_writable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        Ok(Box::new(File::create(path)?))
+    }
+
+    fn new_appendable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        Ok(Box::new(OpenOptions::new().create(true).append(true).open(path)?))
+    }
+
+    fn new_sequential_file(&self, path: &Path) -> io::Result<Box<dyn SequentialFile>> {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+
+    fn new_random_access_file(&self, path: &Path) -> io::Result<Arc<dyn RandomAccessFile>> {
+        Ok(Arc::new(File::open(path)?))
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        Ok(std::fs::metadata(path)?.len())
+    }
+
+    fn get_children(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            if let Ok(name) = entry?.file_name().into_string() {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(dir)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        std::fs::remove_dir(dir)
+    }
+
+    fn rename_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn link_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        match std::fs::hard_link(from, to) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(e),
+            // Another filesystem, or one without hard links
+            Err(_) => {
+                std::fs::copy(from, to)?;
+                File::open(to)?.sync_all()
+            }
+        }
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        crate::util::sync_dir(dir)
+    }
+
+    fn lock_file(&self, path: &Path) -> io::Result<Box<dyn FileLock>> {
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Box::new(file)),
+            Err(TryLockError::WouldBlock) => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{:?} is already locked", path),
+            )),
+            Err(TryLockError::Error(e)) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! [`Env`] that refuses every change to the files of another one.
+//!
+//! [`DB::open_read_only`](crate::DB::open_read_only) accesses the database
+//! directory through a [`ReadOnlyEnv`], so a code path that would still
+//! create, append to, rename or delete a file fails with
+//! [`io::ErrorKind::PermissionDenied`] instead of changing the directory.
+//! Reads are passed through; [`PosixEnv`](super::PosixEnv) opens files for
+//! reading with `O_RDONLY`, which read-only mounts allow.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{Env, FileLock, RandomAccessFile, SequentialFile, WritableFile};
+
+/// Passes reads to another [`Env`] and fails every write.
+///
+/// See the [module documentation](self).
+pub struct ReadOnlyEnv {
+    base: Arc<dyn Env>,
+}
+
+impl ReadOnlyEnv {
+    /// Wraps `base`.
+    pub fn new(base: Arc<dyn Env>) -> Self {
+        Self { base }
+    }
+}
+
+/// The error returned for every write
+fn denied(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{:?}: the database is opened read-only", path),
+    )
+}
+
+impl Env for ReadOnlyEnv {
+    fn new_writable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        Err(denied(path))
+    }
+
+    fn new_appendable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        Err(denied(path))
+    }
+
+    fn new_sequential_file(&self, path: &Path) -> io::Result<Box<dyn SequentialFile>> {
+        self.base.new_sequential_file(path)
+    }
+
+    fn new_random_access_file(&self, path: &Path) -> io::Result<Arc<dyn RandomAccessFile>> {
+        self.base.new_random_access_file(path)
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        self.base.file_exists(path)
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        self.base.file_size(path)
+    }
+
+    fn get_children(&self, dir: &Path) -> io::Result<Vec<String>> {
+        self.base.get_children(dir)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        Err(denied(dir))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        Err(denied(path))
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        Err(denied(dir))
+    }
+
+    fn rename_file(&self, from: &Path, _to: &Path) -> io::Result<()> {
+        Err(denied(from))
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        Err(denied(dir))
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.base.read_file(path)
+    }
+
+    fn link_file(&self, _from: &Path, to: &Path) -> io::Result<()> {
+        Err(denied(to))
+    }
+
+    fn lock_file(&self, path: &Path) -> io::Result<Box<dyn FileLock>> {
+        Err(denied(path))
+    }
+
+    fn now_micros(&self) -> u64 {
+        self.base.now_micros()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::MemEnv;
+
+    #[test]
+    fn test_read_only_env_refuses_writes() {
+        let base = Arc::new(MemEnv::new());
+        base.create_dir_all(Path::new("/db")).unwrap();
+        base.write_file(Path::new("/db/a"), b"data").unwrap();
+
+        let env = ReadOnlyEnv::new(base.clone());
+        assert_eq!(env.read_file(Path::new("/db/a")).unwrap(), b"data");
+        assert_eq!(env.get_children(Path::new("/db")).unwrap(), vec!["a".to_string()]);
+
+        let denied = |result: io::Result<()>| {
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        };
+        denied(env.write_file(Path::new("/db/b"), b"data"));
+        denied(env.new_appendable_file(Path::new("/db/a")).map(drop));
+        denied(env.remove_file(Path::new("/db/a")));
+        denied(env.rename_file(Path::new("/db/a"), Path::new("/db/c")));
+        denied(env.create_dir_all(Path::new("/db/dir")));
+        denied(env.sync_dir(Path::new("/db")));
+        assert_eq!(base.get_children(Path::new("/db")).unwrap(), vec!["a".to_string()]);
+    }
+}
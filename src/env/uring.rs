@@ -0,0 +1,320 @@
+//! [`Env`] that reads and writes files through io_uring (Linux only,
+//! requires the `io-uring` feature).
+
+use super::{AccessHint, Env, FileLock, PosixEnv, RandomAccessFile, SequentialFile, WritableFile};
+use io_uring::{opcode, squeue, types, IoUring};
+use parking_lot::Mutex;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Default number of submission queue entries per ring.
+pub const DEFAULT_QUEUE_DEPTH: u32 = 64;
+
+/// Performs positional reads, writes and fsyncs with io_uring.
+///
+/// A batch of reads ([`RandomAccessFile::read_exact_at_batch`], used for
+/// SSTable readahead and checksum verification) is submitted with a single
+/// system call instead of one `pread` per block. Every thread doing I/O
+/// takes a ring from a shared pool, so concurrent readers do not contend on
+/// one queue.
+///
+/// Opening, renaming and deleting files and all directory operations go
+/// through [`PosixEnv`]. Select it with
+/// `Options::default().env(Arc::new(IoUringEnv::new()?))`.
+#[derive(Debug, Clone)]
+pub struct IoUringEnv {
+    rings: Arc<RingPool>,
+}
+
+impl IoUringEnv {
+    /// Creates an environment with rings of [`DEFAULT_QUEUE_DEPTH`] entries.
+    ///
+    /// Fails if the kernel does not support io_uring or it is disabled (for
+    /// example by a container's seccomp profile).
+    pub fn new() -> io::Result<Self> {
+        Self::with_queue_depth(DEFAULT_QUEUE_DEPTH)
+    }
+
+    /// Creates an environment with rings of `queue_depth` entries; larger
+    /// batches are submitted `queue_depth` requests at a time.
+    pub fn with_queue_depth(queue_depth: u32) -> io::Result<Self> {
+        let rings = RingPool { queue_depth, idle: Mutex::new(Vec::new()) };
+        // Fail now rather than on the first read
+        let ring = IoUring::new(queue_depth)?;
+        rings.idle.lock().push(ring);
+        Ok(Self { rings: Arc::new(rings) })
+    }
+}
+
+/// Rings not currently in use by a thread
+struct RingPool {
+    queue_depth: u32,
+    idle: Mutex<Vec<IoUring>>,
+}
+
+impl std::fmt::Debug for RingPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RingPool").field("queue_depth", &self.queue_depth).finish()
+    }
+}
+
+impl RingPool {
+    /// Submits `entries` and waits for all of them to complete, returning
+    /// each result (bytes transferred, or a negated errno) by position
+    ///
+    /// # Safety
+    ///
+    /// Every buffer referenced by `entries` must stay valid until this
+    /// returns. Entries are tagged with their position as user data.
+    unsafe fn submit(&self, entries: &[squeue::Entry]) -> io::Result<Vec<i32>> {
+        let mut ring = match self.idle.lock().pop() {
+            Some(ring) => ring,
+            None => IoUring::new(self.queue_depth)?,
+        };
+        let mut results = vec![0; entries.len()];
+        for chunk in entries.chunks(self.queue_depth as usize) {
+            if ring.submission().push_multiple(chunk).is_err() {
+                return Err(io::Error::other("io_uring submission queue is full"));
+            }
+            let mut completed = 0;
+            while completed < chunk.len() {
+                match ring.submit_and_wait(chunk.len() - completed) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    // Entries the kernel did not consume are never run; the
+                    // ring is dropped so they cannot run later
+                    Err(e) => return Err(e),
+                }
+                for cqe in ring.completion() {
+                    results[cqe.user_data() as usize] = cqe.result();
+                    completed += 1;
+                }
+            }
+        }
+        self.idle.lock().push(ring);
+        Ok(results)
+    }
+}
+
+/// Converts a completion result into a byte count
+fn completion_result(result: i32) -> io::Result<usize> {
+    match result {
+        n if n < 0 => Err(io::Error::from_raw_os_error(-n)),
+        n => Ok(n as usize),
+    }
+}
+
+/// A file read through io_uring
+struct UringFile {
+    file: File,
+    rings: Arc<RingPool>,
+}
+
+impl RandomAccessFile for UringFile {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.read_exact_at_batch(&mut [(offset, buf)])
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn advise(&self, hint: AccessHint) -> io::Result<()> {
+        crate::util::fadvise(&self.file, hint)
+    }
+
+    fn read_exact_at_batch(&self, reads: &mut [(u64, &mut [u8])]) -> io::Result<()> {
+        let fd = types::Fd(self.file.as_raw_fd());
+        let entries: Vec<squeue::Entry> = reads
+            .iter_mut()
+            .enumerate()
+            .map(|(i, (offset, buf))| {
+                let len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+                opcode::Read::new(fd, buf.as_mut_ptr(), len)
+                    .offset(*offset)
+                    .build()
+                    .user_data(i as u64)
+            })
+            .collect();
+        // SAFETY: the buffers are borrowed from `reads` for the whole call
+        let results = unsafe { self.rings.submit(&entries)? };
+
+        // Short reads (at the end of the file, or very large buffers) are
+        // finished with plain positional reads
+        for ((offset, buf), result) in reads.iter_mut().zip(results) {
+            let read = completion_result(result)?;
+            if read < buf.len() {
+                crate::util::read_exact_at(&self.file, &mut buf[read..], *offset + read as u64)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A file written through io_uring at an explicitly tracked offset
+struct UringWritableFile {
+    file: File,
+    offset: u64,
+    rings: Arc<RingPool>,
+}
+
+impl Write for UringWritableFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+        let entry = opcode::Write::new(types::Fd(self.file.as_raw_fd()), buf.as_ptr(), len)
+            .offset(self.offset)
+            .build()
+            .user_data(0);
+        // SAFETY: `buf` is borrowed for the whole call
+        let written = completion_result(unsafe { self.rings.submit(&[entry])? }[0])?;
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WritableFile for UringWritableFile {
+    fn sync(&mut self) -> io::Result<()> {
+        let entry = opcode::Fsync::new(types::Fd(self.file.as_raw_fd())).build().user_data(0);
+        // SAFETY: fsync references no buffers
+        completion_result(unsafe { self.rings.submit(&[entry])? }[0]).map(|_| ())
+    }
+
+    fn advise(&self, hint: AccessHint) -> io::Result<()> {
+        crate::util::fadvise(&self.file, hint)
+    }
+}
+
+impl Env for IoUringEnv {
+    fn new_writable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        let file = File::create(path)?;
+        Ok(Box::new(UringWritableFile { file, offset: 0, rings: Arc::clone(&self.rings) }))
+    }
+
+    fn new_appendable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        let file = OpenOptions::new().create(true).write(true).truncate(false).open(path)?;
+        let offset = file.metadata()?.len();
+        Ok(Box::new(UringWritableFile { file, offset, rings: Arc::clone(&self.rings) }))
+    }
+
+    fn new_sequential_file(&self, path: &Path) -> io::Result<Box<dyn SequentialFile>> {
+        PosixEnv.new_sequential_file(path)
+    }
+
+    fn new_random_access_file(&self, path: &Path) -> io::Result<Arc<dyn RandomAccessFile>> {
+        let file = File::open(path)?;
+        Ok(Arc::new(UringFile { file, rings: Arc::clone(&self.rings) }))
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        PosixEnv.file_exists(path)
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        PosixEnv.file_size(path)
+    }
+
+    fn get_children(&self, dir: &Path) -> io::Result<Vec<String>> {
+        PosixEnv.get_children(dir)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        PosixEnv.create_dir_all(dir)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        PosixEnv.remove_file(path)
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        PosixEnv.remove_dir(dir)
+    }
+
+    fn rename_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        PosixEnv.rename_file(from, to)
+    }
+
+    fn link_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        PosixEnv.link_file(from, to)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        PosixEnv.sync_dir(dir)
+    }
+
+    fn lock_file(&self, path: &Path) -> io::Result<Box<dyn FileLock>> {
+        PosixEnv.lock_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, DB};
+    use tempfile::TempDir;
+
+    /// io_uring is often disabled in containers; skip rather than fail there
+    fn env() -> Option<IoUringEnv> {
+        match IoUringEnv::with_queue_depth(4) {
+            Ok(env) => Some(env),
+            Err(e) => {
+                eprintln!("io_uring unavailable, skipping: {}", e);
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_io_uring_files() {
+        let Some(env) = env() else { return };
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("file");
+
+        env.write_file(&path, b"hello").unwrap();
+        let mut file = env.new_appendable_file(&path).unwrap();
+        file.write_all(b" world").unwrap();
+        file.sync().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello world");
+
+        let file = env.new_random_access_file(&path).unwrap();
+        assert_eq!(file.size().unwrap(), 11);
+        // More reads than the queue depth, including one cut short by EOF
+        let mut bufs = [[0u8; 2]; 6];
+        let mut reads: Vec<(u64, &mut [u8])> =
+            bufs.iter_mut().enumerate().map(|(i, b)| (i as u64 * 2, &mut b[..])).collect();
+        assert!(file.read_exact_at_batch(&mut reads).is_err());
+        reads.pop();
+        file.read_exact_at_batch(&mut reads).unwrap();
+        assert_eq!(bufs[..5].concat(), b"hello worl");
+    }
+
+    #[test]
+    fn test_io_uring_db() {
+        let Some(env) = env() else { return };
+        let dir = TempDir::new().unwrap();
+        let db = DB::open(dir.path(), Options::default().env(Arc::new(env.clone()))).unwrap();
+        for i in 0..2000u32 {
+            db.put(format!("key{:05}", i).as_bytes(), &[b'v'; 100]).unwrap();
+        }
+        db.flush().unwrap();
+        db.close().unwrap();
+        drop(db);
+
+        let db = Arc::new(DB::open(dir.path(), Options::default().env(Arc::new(env))).unwrap());
+        assert_eq!(db.get(b"key01234").unwrap(), Some(vec![b'v'; 100]));
+        let mut iter = db.iter();
+        let mut count = 0;
+        while iter.valid() {
+            count += 1;
+            iter.next();
+        }
+        assert_eq!(count, 2000);
+        assert!(db.verify_checksums().unwrap().is_ok());
+    }
+}
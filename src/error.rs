@@ -41,6 +41,21 @@ pub enum Error {
     /// The database or file is already in use.
     AlreadyExists(String),
 
+    /// The supplied configuration conflicts with the database on disk.
+    InvalidConfig(String),
+
+    /// A background flush or compaction failed; the database rejects writes
+    /// until [`DB::resume`](crate::DB::resume) succeeds.
+    BackgroundError(String),
+
+    /// The disk is full. Converted from `ENOSPC` I/O errors so that callers
+    /// can tell it apart from other I/O failures.
+    NoSpace(String),
+
+    /// A record does not match its authentication tag: it was changed
+    /// by someone without the key (see [`record_auth`](crate::record_auth)).
+    AuthenticationFailed(String),
+
     /// An internal error occurred.
     Internal(String),
 }
@@ -61,12 +76,42 @@ impl Error {
         Error::InvalidArgument(msg.into())
     }
 
+    /// Creates a new invalid configuration error.
+    pub fn invalid_config(msg: impl Into<String>) -> Self {
+        Error::InvalidConfig(msg.into())
+    }
+
     /// Creates a new internal error.
     pub fn internal(msg: impl Into<String>) -> Self {
         Error::Internal(msg.into())
     }
 }
 
+/// I/O errors are copied with their kind and message, since `io::Error`
+/// itself cannot be cloned; the copy has no `source`.
+impl Clone for Error {
+    fn clone(&self) -> Self {
+        match self {
+            Error::Io(e) => Error::Io(io::Error::new(e.kind(), e.to_string())),
+            Error::Corruption(msg) => Error::Corruption(msg.clone()),
+            Error::NotFound(msg) => Error::NotFound(msg.clone()),
+            Error::InvalidArgument(msg) => Error::InvalidArgument(msg.clone()),
+            Error::NotImplemented(msg) => Error::NotImplemented(msg.clone()),
+            Error::InvalidState(msg) => Error::InvalidState(msg.clone()),
+            Error::Serialization(msg) => Error::Serialization(msg.clone()),
+            Error::ChecksumMismatch { expected, actual } => {
+                Error::ChecksumMismatch { expected: *expected, actual: *actual }
+            }
+            Error::AlreadyExists(msg) => Error::AlreadyExists(msg.clone()),
+            Error::InvalidConfig(msg) => Error::InvalidConfig(msg.clone()),
+            Error::BackgroundError(msg) => Error::BackgroundError(msg.clone()),
+            Error::NoSpace(msg) => Error::NoSpace(msg.clone()),
+            Error::AuthenticationFailed(msg) => Error::AuthenticationFailed(msg.clone()),
+            Error::Internal(msg) => Error::Internal(msg.clone()),
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -81,6 +126,10 @@ impl fmt::Display for Error {
                 write!(f, "Checksum mismatch: expected {:#x}, got {:#x}", expected, actual)
             }
             Error::AlreadyExists(msg) => write!(f, "Already exists: {}", msg),
+            Error::InvalidConfig(msg) => write!(f, "Invalid configuration: {}", msg),
+            Error::BackgroundError(msg) => write!(f, "Background error: {}", msg),
+            Error::NoSpace(msg) => write!(f, "No space left on device: {}", msg),
+            Error::AuthenticationFailed(msg) => write!(f, "Authentication failed: {}", msg),
             Error::Internal(msg) => write!(f, "Internal error: {}", msg),
         }
     }
@@ -97,7 +146,10 @@ impl std::error::Error for Error {
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
-        Error::Io(err)
+        match err.kind() {
+            io::ErrorKind::StorageFull => Error::NoSpace(err.to_string()),
+            _ => Error::Io(err),
+        }
     }
 }
 
@@ -127,4 +179,23 @@ mod tests {
         let err: Error = io_err.into();
         assert!(matches!(err, Error::Io(_)));
     }
+
+    #[test]
+    fn test_error_clone() {
+        let err = Error::Io(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+        match err.clone() {
+            Error::Io(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+                assert_eq!(e.to_string(), "denied");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(Error::NoSpace("full".into()).clone(), Error::NoSpace(m) if m == "full"));
+    }
+
+    #[test]
+    fn test_error_from_io_storage_full() {
+        let err: Error = io::Error::from(io::ErrorKind::StorageFull).into();
+        assert!(matches!(err, Error::NoSpace(_)));
+    }
 }
@@ -0,0 +1,707 @@
+//! C API (requires the `ffi` feature).
+//!
+//! Exposes the database to C and other languages with a C FFI through the
+//! `aidb_*` functions declared in `include/aidb.h`. Build the shared library
+//! with `cargo build --release --features ffi` and link against
+//! `libaidb.so` / `libaidb.dylib` / `aidb.dll`.
+//!
+//! The API follows the RocksDB C API: handles are opaque pointers created
+//! and destroyed by the library, keys and values are passed as pointer and
+//! length, and fallible functions take a `char** errptr`. In addition,
+//! fallible functions return an `AIDB_*` status code:
+//!
+//! ```c
+//! char* err = NULL;
+//! aidb_options_t* options = aidb_options_create();
+//! aidb_t* db = NULL;
+//! if (aidb_open(options, "./data", &db, &err) != AIDB_OK) {
+//!     fprintf(stderr, "open failed: %s\n", err);
+//!     aidb_free(err);
+//!     return 1;
+//! }
+//! aidb_options_destroy(options);
+//!
+//! aidb_put(db, "key", 3, "value", 5, &err);
+//!
+//! char* value;
+//! size_t value_len;
+//! if (aidb_get(db, "key", 3, &value, &value_len, &err) == AIDB_OK) {
+//!     printf("%.*s\n", (int)value_len, value);
+//!     aidb_free(value);
+//! }
+//! aidb_close(db, &err);
+//! ```
+//!
+//! Memory returned by the library (values and error messages) must be
+//! released with `aidb_free`, not `free`. A panic inside the library is
+//! reported as `AIDB_INTERNAL` instead of unwinding into C.
+
+use crate::error::Error;
+use crate::{DBIterator, Options, WriteBatch, DB};
+use std::alloc::{self, Layout};
+use std::ffi::{c_char, c_int, c_uchar, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+
+/// Success
+pub const AIDB_OK: c_int = 0;
+/// `aidb_get` found no value for the key
+pub const AIDB_NOT_FOUND: c_int = 1;
+/// Data on disk is corrupted (including checksum mismatches)
+pub const AIDB_CORRUPTION: c_int = 2;
+/// The operation is not implemented
+pub const AIDB_NOT_SUPPORTED: c_int = 3;
+/// An argument or option is invalid
+pub const AIDB_INVALID_ARGUMENT: c_int = 4;
+/// An I/O error occurred
+pub const AIDB_IO_ERROR: c_int = 5;
+/// The database rejects writes after a background error, or is in an
+/// invalid state
+pub const AIDB_INVALID_STATE: c_int = 6;
+/// The disk is full
+pub const AIDB_NO_SPACE: c_int = 7;
+/// The database already exists or is in use
+pub const AIDB_ALREADY_EXISTS: c_int = 8;
+/// Any other error, including a panic inside the library
+pub const AIDB_INTERNAL: c_int = 9;
+
+/// An open database (`aidb_t`)
+pub struct AidbT {
+    db: Arc<DB>,
+}
+
+/// Options for `aidb_open` (`aidb_options_t`)
+pub struct AidbOptionsT {
+    options: Options,
+}
+
+/// A batch of writes (`aidb_writebatch_t`)
+pub struct AidbWritebatchT {
+    batch: WriteBatch,
+}
+
+/// An iterator over a database (`aidb_iterator_t`)
+pub struct AidbIteratorT {
+    iter: DBIterator,
+}
+
+/// Size of the header that records the allocation size in front of
+/// memory handed to C
+const ALLOC_HEADER: usize = std::mem::size_of::<usize>();
+
+/// Copies `data` into memory that C releases with [`aidb_free`], appending
+/// a NUL byte so strings can be used directly.
+fn alloc_copy(data: &[u8]) -> *mut c_char {
+    let size = ALLOC_HEADER + data.len() + 1;
+    let Ok(layout) = Layout::from_size_align(size, ALLOC_HEADER) else {
+        return ptr::null_mut();
+    };
+    // SAFETY: the layout has a non-zero size. The header and the data
+    // (plus NUL) fit exactly in the allocation.
+    unsafe {
+        let base = alloc::alloc(layout);
+        if base.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        (base as *mut usize).write(size);
+        let out = base.add(ALLOC_HEADER);
+        ptr::copy_nonoverlapping(data.as_ptr(), out, data.len());
+        out.add(data.len()).write(0);
+        out as *mut c_char
+    }
+}
+
+/// Stores the message of `error` in `*errptr`, replacing a previous message.
+unsafe fn save_error(errptr: *mut *mut c_char, message: &str) {
+    if errptr.is_null() {
+        return;
+    }
+    aidb_free((*errptr).cast());
+    *errptr = alloc_copy(message.replace('\0', " ").as_bytes());
+}
+
+/// Status code of an error
+fn status_code(error: &Error) -> c_int {
+    match error {
+        Error::NotFound(_) => AIDB_NOT_FOUND,
+        Error::Corruption(_) | Error::ChecksumMismatch { .. } | Error::AuthenticationFailed(_) => {
+            AIDB_CORRUPTION
+        }
+        Error::NotImplemented(_) => AIDB_NOT_SUPPORTED,
+        Error::InvalidArgument(_) | Error::InvalidConfig(_) => AIDB_INVALID_ARGUMENT,
+        Error::Io(_) => AIDB_IO_ERROR,
+        Error::InvalidState(_) | Error::BackgroundError(_) => AIDB_INVALID_STATE,
+        Error::NoSpace(_) => AIDB_NO_SPACE,
+        Error::AlreadyExists(_) => AIDB_ALREADY_EXISTS,
+        Error::Serialization(_) | Error::Internal(_) => AIDB_INTERNAL,
+    }
+}
+
+/// Runs `f`, reporting an error or panic through `errptr` and the returned
+/// status code.
+unsafe fn guard(errptr: *mut *mut c_char, f: impl FnOnce() -> crate::Result<c_int>) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(code)) => code,
+        Ok(Err(error)) => {
+            save_error(errptr, &error.to_string());
+            status_code(&error)
+        }
+        Err(_) => {
+            save_error(errptr, "panic inside aidb");
+            AIDB_INTERNAL
+        }
+    }
+}
+
+/// Borrows `len` bytes at `data`, treating a null pointer as empty.
+unsafe fn bytes<'a>(data: *const c_char, len: usize) -> &'a [u8] {
+    if data.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data.cast(), len)
+    }
+}
+
+/// Borrows a NUL-terminated UTF-8 string.
+unsafe fn c_str<'a>(s: *const c_char, what: &str) -> crate::Result<&'a str> {
+    if s.is_null() {
+        return Err(Error::invalid_argument(format!("{} is NULL", what)));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| Error::invalid_argument(format!("{} is not valid UTF-8", what)))
+}
+
+/// Releases memory returned by the library. Does nothing for NULL.
+///
+/// # Safety
+///
+/// `ptr` must be NULL or a value or error message returned by this library
+/// that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_free(ptr: *mut c_char) {
+    if ptr.is_null() {
+        return;
+    }
+    let base = (ptr as *mut u8).sub(ALLOC_HEADER);
+    let size = (base as *const usize).read();
+    alloc::dealloc(base, Layout::from_size_align_unchecked(size, ALLOC_HEADER));
+}
+
+/// Creates options with the default settings.
+#[no_mangle]
+pub extern "C" fn aidb_options_create() -> *mut AidbOptionsT {
+    Box::into_raw(Box::new(AidbOptionsT { options: Options::default() }))
+}
+
+/// Destroys options.
+///
+/// # Safety
+///
+/// `options` must be NULL or returned by [`aidb_options_create`] and not
+/// destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_options_destroy(options: *mut AidbOptionsT) {
+    if !options.is_null() {
+        drop(Box::from_raw(options));
+    }
+}
+
+/// Sets an option by name, as in the `OPTIONS` file (for example
+/// `"memtable_size"`, `"4194304"`).
+///
+/// # Safety
+///
+/// `options` must be a live options handle, `name` and `value` valid
+/// NUL-terminated strings, and `errptr` NULL or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_options_set(
+    options: *mut AidbOptionsT,
+    name: *const c_char,
+    value: *const c_char,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || {
+        let options = &mut (*options).options;
+        options.set_option(c_str(name, "name")?, c_str(value, "value")?)?;
+        Ok(AIDB_OK)
+    })
+}
+
+/// Opens the database at `name`, storing the handle in `*db`.
+///
+/// # Safety
+///
+/// `options` must be a live options handle, `name` a valid NUL-terminated
+/// string, `db` a valid pointer and `errptr` NULL or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_open(
+    options: *const AidbOptionsT,
+    name: *const c_char,
+    db: *mut *mut AidbT,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || {
+        let opened = DB::open(c_str(name, "name")?, (*options).options.clone())?;
+        *db = Box::into_raw(Box::new(AidbT { db: Arc::new(opened) }));
+        Ok(AIDB_OK)
+    })
+}
+
+/// Flushes and closes the database and destroys the handle, even if
+/// closing reports an error.
+///
+/// Iterators that are still open keep the database files open until they
+/// are destroyed.
+///
+/// # Safety
+///
+/// `db` must be NULL or returned by [`aidb_open`] and not closed yet, and
+/// `errptr` NULL or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_close(db: *mut AidbT, errptr: *mut *mut c_char) -> c_int {
+    if db.is_null() {
+        return AIDB_OK;
+    }
+    let handle = Box::from_raw(db);
+    guard(errptr, move || {
+        handle.db.close()?;
+        Ok(AIDB_OK)
+    })
+}
+
+/// Stores `value` under `key`.
+///
+/// # Safety
+///
+/// `db` must be a live database handle, `key` and `value` must point to
+/// `key_len` and `value_len` readable bytes, and `errptr` must be NULL or
+/// a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_put(
+    db: *mut AidbT,
+    key: *const c_char,
+    key_len: usize,
+    value: *const c_char,
+    value_len: usize,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || {
+        (*db).db.put(bytes(key, key_len), bytes(value, value_len))?;
+        Ok(AIDB_OK)
+    })
+}
+
+/// Reads the value of `key`.
+///
+/// On `AIDB_OK`, `*value` holds a copy of the value (NUL-terminated for
+/// convenience) to release with [`aidb_free`] and `*value_len` its length.
+/// Returns `AIDB_NOT_FOUND` and sets `*value` to NULL if the key does not
+/// exist.
+///
+/// # Safety
+///
+/// `db` must be a live database handle, `key` must point to `key_len`
+/// readable bytes, `value` and `value_len` must be valid pointers and
+/// `errptr` must be NULL or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_get(
+    db: *mut AidbT,
+    key: *const c_char,
+    key_len: usize,
+    value: *mut *mut c_char,
+    value_len: *mut usize,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    *value = ptr::null_mut();
+    *value_len = 0;
+    guard(errptr, || match (*db).db.get(bytes(key, key_len))? {
+        Some(found) => {
+            *value = alloc_copy(&found);
+            *value_len = found.len();
+            Ok(AIDB_OK)
+        }
+        None => Ok(AIDB_NOT_FOUND),
+    })
+}
+
+/// Deletes `key`. Deleting a missing key is not an error.
+///
+/// # Safety
+///
+/// `db` must be a live database handle, `key` must point to `key_len`
+/// readable bytes and `errptr` must be NULL or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_delete(
+    db: *mut AidbT,
+    key: *const c_char,
+    key_len: usize,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || {
+        (*db).db.delete(bytes(key, key_len))?;
+        Ok(AIDB_OK)
+    })
+}
+
+/// Applies the writes in `batch` atomically. The batch is left unchanged
+/// and can be reused.
+///
+/// # Safety
+///
+/// `db` and `batch` must be live handles and `errptr` must be NULL or a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_write(
+    db: *mut AidbT,
+    batch: *const AidbWritebatchT,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || {
+        (*db).db.write((*batch).batch.clone())?;
+        Ok(AIDB_OK)
+    })
+}
+
+/// Creates an empty write batch.
+#[no_mangle]
+pub extern "C" fn aidb_writebatch_create() -> *mut AidbWritebatchT {
+    Box::into_raw(Box::new(AidbWritebatchT { batch: WriteBatch::new() }))
+}
+
+/// Destroys a write batch.
+///
+/// # Safety
+///
+/// `batch` must be NULL or returned by [`aidb_writebatch_create`] and not
+/// destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_writebatch_destroy(batch: *mut AidbWritebatchT) {
+    if !batch.is_null() {
+        drop(Box::from_raw(batch));
+    }
+}
+
+/// Adds a put to the batch.
+///
+/// # Safety
+///
+/// `batch` must be a live batch handle, and `key` and `value` must point to
+/// `key_len` and `value_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_writebatch_put(
+    batch: *mut AidbWritebatchT,
+    key: *const c_char,
+    key_len: usize,
+    value: *const c_char,
+    value_len: usize,
+) {
+    (*batch).batch.put(bytes(key, key_len), bytes(value, value_len));
+}
+
+/// Adds a delete to the batch.
+///
+/// # Safety
+///
+/// `batch` must be a live batch handle and `key` must point to `key_len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_writebatch_delete(
+    batch: *mut AidbWritebatchT,
+    key: *const c_char,
+    key_len: usize,
+) {
+    (*batch).batch.delete(bytes(key, key_len));
+}
+
+/// Removes every write from the batch.
+///
+/// # Safety
+///
+/// `batch` must be a live batch handle.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_writebatch_clear(batch: *mut AidbWritebatchT) {
+    (*batch).batch.clear();
+}
+
+/// Returns the number of writes in the batch.
+///
+/// # Safety
+///
+/// `batch` must be a live batch handle.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_writebatch_count(batch: *const AidbWritebatchT) -> usize {
+    (*batch).batch.len()
+}
+
+/// Creates an iterator over the current contents of the database,
+/// positioned at the first key. Returns NULL on error.
+///
+/// # Safety
+///
+/// `db` must be a live database handle and `errptr` must be NULL or a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_create_iterator(
+    db: *mut AidbT,
+    errptr: *mut *mut c_char,
+) -> *mut AidbIteratorT {
+    let mut iterator = ptr::null_mut();
+    guard(errptr, || {
+        let iter = (*db).db.scan(None, None)?;
+        iterator = Box::into_raw(Box::new(AidbIteratorT { iter }));
+        Ok(AIDB_OK)
+    });
+    iterator
+}
+
+/// Destroys an iterator.
+///
+/// # Safety
+///
+/// `iter` must be NULL or returned by [`aidb_create_iterator`] and not
+/// destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_iter_destroy(iter: *mut AidbIteratorT) {
+    if !iter.is_null() {
+        drop(Box::from_raw(iter));
+    }
+}
+
+/// Returns 1 if the iterator is positioned at an entry, 0 otherwise.
+///
+/// # Safety
+///
+/// `iter` must be a live iterator handle.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_iter_valid(iter: *const AidbIteratorT) -> c_uchar {
+    c_uchar::from((*iter).iter.valid())
+}
+
+/// Moves to the first entry.
+///
+/// # Safety
+///
+/// `iter` must be a live iterator handle.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_iter_seek_to_first(iter: *mut AidbIteratorT) {
+    (*iter).iter.seek_to_first();
+}
+
+/// Moves to the last entry.
+///
+/// # Safety
+///
+/// `iter` must be a live iterator handle.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_iter_seek_to_last(iter: *mut AidbIteratorT) {
+    (*iter).iter.seek_to_last();
+}
+
+/// Moves to the first entry whose key is at least `key`.
+///
+/// # Safety
+///
+/// `iter` must be a live iterator handle and `key` must point to `key_len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_iter_seek(
+    iter: *mut AidbIteratorT,
+    key: *const c_char,
+    key_len: usize,
+) {
+    (*iter).iter.seek(bytes(key, key_len));
+}
+
+/// Moves to the next entry.
+///
+/// # Safety
+///
+/// `iter` must be a live iterator handle.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_iter_next(iter: *mut AidbIteratorT) {
+    (*iter).iter.next();
+}
+
+/// Moves to the previous entry.
+///
+/// # Safety
+///
+/// `iter` must be a live iterator handle.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_iter_prev(iter: *mut AidbIteratorT) {
+    (*iter).iter.prev();
+}
+
+/// Returns the key at the current position and stores its length in
+/// `*len`, or returns NULL if the iterator is not valid. The key stays
+/// valid until the iterator is moved or destroyed.
+///
+/// # Safety
+///
+/// `iter` must be a live iterator handle and `len` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_iter_key(
+    iter: *const AidbIteratorT,
+    len: *mut usize,
+) -> *const c_char {
+    let iter = &(*iter).iter;
+    if !iter.valid() {
+        *len = 0;
+        return ptr::null();
+    }
+    let key = iter.key();
+    *len = key.len();
+    key.as_ptr().cast()
+}
+
+/// Returns the value at the current position and stores its length in
+/// `*len`, or returns NULL if the iterator is not valid. The value stays
+/// valid until the iterator is moved or destroyed.
+///
+/// # Safety
+///
+/// `iter` must be a live iterator handle and `len` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn aidb_iter_value(
+    iter: *const AidbIteratorT,
+    len: *mut usize,
+) -> *const c_char {
+    let iter = &(*iter).iter;
+    if !iter.valid() {
+        *len = 0;
+        return ptr::null();
+    }
+    let value = iter.value();
+    *len = value.len();
+    value.as_ptr().cast()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use tempfile::TempDir;
+
+    unsafe fn take_string(ptr: *mut c_char) -> String {
+        let s = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+        aidb_free(ptr);
+        s
+    }
+
+    unsafe fn get(db: *mut AidbT, key: &[u8]) -> Option<Vec<u8>> {
+        let mut value = ptr::null_mut();
+        let mut value_len = 0;
+        let mut err = ptr::null_mut();
+        let code =
+            aidb_get(db, key.as_ptr().cast(), key.len(), &mut value, &mut value_len, &mut err);
+        assert!(err.is_null());
+        if code == AIDB_NOT_FOUND {
+            assert!(value.is_null());
+            return None;
+        }
+        assert_eq!(code, AIDB_OK);
+        let found = slice::from_raw_parts(value.cast::<u8>(), value_len).to_vec();
+        assert_eq!(*value.add(value_len), 0);
+        aidb_free(value);
+        Some(found)
+    }
+
+    #[test]
+    fn test_c_api() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = CString::new(temp_dir.path().to_str().unwrap()).unwrap();
+        unsafe {
+            let mut err = ptr::null_mut();
+            let options = aidb_options_create();
+            let name = CString::new("memtable_size").unwrap();
+            let value = CString::new("not a number").unwrap();
+            assert_eq!(
+                aidb_options_set(options, name.as_ptr(), value.as_ptr(), &mut err),
+                AIDB_INVALID_ARGUMENT
+            );
+            assert!(!take_string(err).is_empty());
+            err = ptr::null_mut();
+
+            let mut db = ptr::null_mut();
+            assert_eq!(aidb_open(options, path.as_ptr(), &mut db, &mut err), AIDB_OK);
+            aidb_options_destroy(options);
+
+            assert_eq!(
+                aidb_put(db, b"a".as_ptr().cast(), 1, b"1".as_ptr().cast(), 1, &mut err),
+                AIDB_OK
+            );
+            assert_eq!(get(db, b"a"), Some(b"1".to_vec()));
+            assert_eq!(get(db, b"zz"), None);
+
+            let batch = aidb_writebatch_create();
+            aidb_writebatch_put(batch, b"b".as_ptr().cast(), 1, b"2".as_ptr().cast(), 1);
+            aidb_writebatch_put(batch, b"c".as_ptr().cast(), 1, b"3".as_ptr().cast(), 1);
+            aidb_writebatch_delete(batch, b"a".as_ptr().cast(), 1);
+            assert_eq!(aidb_writebatch_count(batch), 3);
+            assert_eq!(aidb_write(db, batch, &mut err), AIDB_OK);
+            assert_eq!(aidb_writebatch_count(batch), 3);
+            aidb_writebatch_clear(batch);
+            assert_eq!(aidb_writebatch_count(batch), 0);
+            aidb_writebatch_destroy(batch);
+            assert_eq!(get(db, b"a"), None);
+
+            assert_eq!(aidb_delete(db, b"b".as_ptr().cast(), 1, &mut err), AIDB_OK);
+
+            let mut key_len = 0;
+            let mut value_len = 0;
+            let iter = aidb_create_iterator(db, &mut err);
+            assert!(!iter.is_null());
+            assert_eq!(aidb_iter_valid(iter), 1);
+            let key = aidb_iter_key(iter, &mut key_len);
+            assert_eq!(slice::from_raw_parts(key.cast::<u8>(), key_len), b"c");
+            let value = aidb_iter_value(iter, &mut value_len);
+            assert_eq!(slice::from_raw_parts(value.cast::<u8>(), value_len), b"3");
+            aidb_iter_next(iter);
+            assert_eq!(aidb_iter_valid(iter), 0);
+            assert!(aidb_iter_key(iter, &mut key_len).is_null());
+            aidb_iter_seek(iter, b"a".as_ptr().cast(), 1);
+            assert_eq!(aidb_iter_valid(iter), 1);
+            aidb_iter_destroy(iter);
+
+            assert!(err.is_null());
+            assert_eq!(aidb_close(db, &mut err), AIDB_OK);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = CString::new(temp_dir.path().join("missing").to_str().unwrap()).unwrap();
+        unsafe {
+            let mut err = ptr::null_mut();
+            let options = aidb_options_create();
+            let name = CString::new("create_if_missing").unwrap();
+            let value = CString::new("false").unwrap();
+            assert_eq!(aidb_options_set(options, name.as_ptr(), value.as_ptr(), &mut err), AIDB_OK);
+
+            let mut db = ptr::null_mut();
+            assert_ne!(aidb_open(options, path.as_ptr(), &mut db, &mut err), AIDB_OK);
+            assert!(db.is_null());
+            let first = err;
+            assert!(!first.is_null());
+
+            // A second error replaces (and frees) the first message
+            assert_ne!(aidb_open(options, ptr::null(), &mut db, &mut err), AIDB_OK);
+            assert_eq!(take_string(err), "Invalid argument: name is NULL");
+            aidb_options_destroy(options);
+
+            aidb_free(ptr::null_mut());
+            assert_eq!(aidb_close(ptr::null_mut(), ptr::null_mut()), AIDB_OK);
+        }
+    }
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(status_code(&Error::corruption("x")), AIDB_CORRUPTION);
+        assert_eq!(status_code(&Error::NoSpace("full".into())), AIDB_NO_SPACE);
+        assert_eq!(status_code(&Error::BackgroundError("x".into())), AIDB_INVALID_STATE);
+    }
+}
@@ -242,7 +242,13 @@ impl Filter for BloomFilter {
             data[4], data[5], data[6], data[7], data[8], data[9], data[10], data[11],
         ]) as usize;
 
-        // Validate
+        // Validate; every constructor uses 1 to 30 hash functions
+        if !(1..=30).contains(&num_hashes) || num_bits == 0 {
+            return Err(Error::corruption(format!(
+                "Invalid Bloom filter: {} hash functions over {} bits",
+                num_hashes, num_bits
+            )));
+        }
         let expected_bytes = num_bits.div_ceil(8);
         if data.len() != 12 + expected_bytes {
             return Err(Error::corruption("Bloom filter size mismatch"));
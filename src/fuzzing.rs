@@ -0,0 +1,324 @@
+//! Entry points for fuzzing the on-disk formats.
+//!
+//! Fuzz targets (for example the cargo-fuzz targets in the `fuzz`
+//! directory of the repository) call these functions instead of reaching
+//! into the modules that read each format:
+//!
+//! - the `parse_*` functions and [`replay_manifest`] take untrusted bytes
+//!   as they would be found in a file: a data or index block, an SSTable
+//!   footer, a WAL, a Bloom filter or a MANIFEST. On any input they must
+//!   return a result, never panic, hang or allocate without bound.
+//! - the `*_round_trip` functions encode structured values, typically
+//!   built by the fuzzer through [`arbitrary`], decode them again and
+//!   panic if anything differs.
+//!
+//! With the `fuzzing` feature, the types these functions take implement
+//! [`arbitrary::Arbitrary`]: [`VersionEdit`], [`WriteBatch`] and
+//! [`WriteOp`](crate::write_batch::WriteOp), [`Footer`] and
+//! [`BlockHandle`](crate::sstable::BlockHandle), and the
+//! [`ChecksumType`](crate::config::ChecksumType),
+//! [`CompressionType`](crate::config::CompressionType) and
+//! [`RecordType`](crate::wal::RecordType) enums.
+//!
+//! # Example
+//!
+//! ```rust
+//! use aidb::fuzzing;
+//! use aidb::WriteBatch;
+//! use arbitrary::{Arbitrary, Unstructured};
+//!
+//! // Garbage is rejected, not a crash
+//! assert!(fuzzing::parse_block(&[0xff; 8]).is_err());
+//!
+//! // What a fuzz target does with the fuzzer's data
+//! let data = [7u8; 64];
+//! let mut input = Unstructured::new(&data);
+//! let batches = Vec::<WriteBatch>::arbitrary(&mut input).unwrap();
+//! fuzzing::wal_round_trip(&batches);
+//! ```
+
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::compaction::{Version, VersionEdit, VersionSet};
+use crate::env::{Env, MemEnv};
+use crate::error::Result;
+use crate::filter::{BloomFilter, Filter};
+use crate::sstable::{Block, BlockBuilder, Footer};
+use crate::wal::{entry, WALReader, WALWriter, WalEntry};
+use crate::write_batch::WriteBatch;
+
+/// Directory the files are written to in a [`MemEnv`]
+const DIR: &str = "/fuzz";
+
+/// Levels of the version sets the manifest is replayed into
+const MAX_LEVELS: usize = 7;
+
+/// Creates a [`MemEnv`] holding `data` in a file named `name`.
+fn env_with_file(name: &str, data: &[u8]) -> (Arc<dyn Env>, std::path::PathBuf) {
+    let env: Arc<dyn Env> = Arc::new(MemEnv::new());
+    let path = Path::new(DIR).join(name);
+    env.create_dir_all(Path::new(DIR)).expect("creating a directory in a MemEnv");
+    env.write_file(&path, data).expect("writing a file in a MemEnv");
+    (env, path)
+}
+
+/// Parses a data or index block and returns its entries.
+pub fn parse_block(data: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let block = Block::new(Bytes::copy_from_slice(data))?;
+    let mut iter = block.iter();
+    iter.seek_to_first();
+    let mut entries = Vec::new();
+    while iter.advance() {
+        entries.push((iter.key().to_vec(), iter.value().to_vec()));
+    }
+    Ok(entries)
+}
+
+/// Parses an SSTable footer from the start of `data`.
+pub fn parse_footer(mut data: &[u8]) -> Result<Footer> {
+    Footer::read_from(&mut data)
+}
+
+/// Reads a WAL and decodes its entries, up to the first error.
+pub fn parse_wal(data: &[u8]) -> Result<Vec<WalEntry>> {
+    let (env, path) = env_with_file("000001.log", data);
+    let mut reader = WALReader::new_with_env(&path, &*env)?;
+    let mut entries: Vec<WalEntry> = Vec::new();
+    while let Some(record) = reader.read_next()? {
+        let next_sequence = entries.last().map_or(1, |entry| entry.sequence + 1);
+        entries.extend(entry::decode(&record, next_sequence)?);
+    }
+    Ok(entries)
+}
+
+/// Decodes a Bloom filter and checks whether it may contain `key`.
+pub fn parse_bloom_filter(data: &[u8], key: &[u8]) -> Result<bool> {
+    Ok(BloomFilter::decode(data)?.may_contain(key))
+}
+
+/// Replays a MANIFEST and returns the version it describes.
+pub fn replay_manifest(data: &[u8]) -> Result<Version> {
+    let (env, _) = env_with_file("MANIFEST", data);
+    Ok(VersionSet::open_read_only(DIR, MAX_LEVELS, env)?.current().clone())
+}
+
+/// Builds a block from `entries` and checks that it parses back to them.
+///
+/// # Panics
+///
+/// If the parsed entries differ.
+pub fn block_round_trip(entries: &[(Vec<u8>, Vec<u8>)], restart_interval: u8) {
+    let mut builder = BlockBuilder::new(restart_interval.max(1) as usize);
+    for (key, value) in entries {
+        builder.add(key, value);
+    }
+    let parsed = parse_block(&builder.finish()).expect("parsing a block just built");
+    assert_eq!(parsed, entries);
+}
+
+/// Encodes `footer` and checks that it parses back to it.
+///
+/// # Panics
+///
+/// If the parsed footer differs.
+pub fn footer_round_trip(footer: &Footer) {
+    let parsed = parse_footer(&footer.encode()).expect("parsing a footer just encoded");
+    assert_eq!(&parsed, footer);
+}
+
+/// Writes `batches` to a WAL, numbered from sequence 1, and checks that
+/// reading it gives back their operations.
+///
+/// # Panics
+///
+/// If the entries read differ.
+pub fn wal_round_trip(batches: &[WriteBatch]) {
+    let (env, path) = env_with_file("000001.log", &[]);
+    let mut writer = WALWriter::new_with_env(&path, &*env).expect("creating a WAL");
+    let mut expected = Vec::new();
+    let mut sequence = 1;
+    for batch in batches {
+        writer
+            .append(&entry::encode_batch(sequence, batch))
+            .expect("appending to a WAL");
+        for op in batch.iter() {
+            expected.push(WalEntry { sequence, op: op.clone() });
+            sequence += 1;
+        }
+    }
+    writer.sync().expect("syncing a WAL");
+
+    let data = env.read_file(&path).expect("reading a WAL");
+    assert_eq!(parse_wal(&data).expect("parsing a WAL just written"), expected);
+}
+
+/// Builds a Bloom filter of `keys` and checks that it decodes to a filter
+/// that may contain each of them.
+///
+/// # Panics
+///
+/// If the decoded filter differs or rules out one of the keys.
+pub fn bloom_round_trip(keys: &[Vec<u8>]) {
+    let mut filter = BloomFilter::default_with_keys(keys.len());
+    for key in keys {
+        filter.add(key);
+    }
+    let encoded = filter.encode();
+    let decoded = BloomFilter::decode(&encoded).expect("decoding a Bloom filter just encoded");
+    assert_eq!(decoded.encode(), encoded);
+    for key in keys {
+        assert!(parse_bloom_filter(&encoded, key).unwrap(), "false negative for {:?}", key);
+    }
+}
+
+/// Logs `edits` to a MANIFEST and checks that replaying it, before and
+/// after rewriting it, gives the same state as applying them. Edits the
+/// version set refuses (files beyond the last level) are skipped.
+///
+/// # Panics
+///
+/// If a replayed state differs.
+pub fn manifest_round_trip(edits: &[VersionEdit]) {
+    let env: Arc<dyn Env> = Arc::new(MemEnv::new());
+    env.create_dir_all(Path::new(DIR)).expect("creating a directory in a MemEnv");
+    let mut written =
+        VersionSet::new_with_env(DIR, MAX_LEVELS, Arc::clone(&env)).expect("creating a MANIFEST");
+    for edit in edits {
+        let _ = written.log_edit(edit);
+    }
+
+    let replayed = VersionSet::open_read_only(DIR, MAX_LEVELS, Arc::clone(&env))
+        .expect("replaying a MANIFEST just written");
+    assert_eq!(version_set_state(&replayed), version_set_state(&written));
+
+    written.rewrite_manifest().expect("rewriting a MANIFEST");
+    let rewritten = VersionSet::open_read_only(DIR, MAX_LEVELS, env)
+        .expect("replaying a MANIFEST just rewritten");
+    assert_eq!(version_set_state(&rewritten), version_set_state(&written));
+}
+
+/// Files per level (number, size and key range) and counters of a version set
+#[allow(clippy::type_complexity)]
+fn version_set_state(
+    version_set: &VersionSet,
+) -> (Vec<Vec<(u64, u64, Vec<u8>, Vec<u8>)>>, u64, u64, u64) {
+    let levels = version_set
+        .current()
+        .levels
+        .iter()
+        .map(|files| {
+            files
+                .iter()
+                .map(|f| {
+                    (f.file_number, f.file_size, f.smallest_key.clone(), f.largest_key.clone())
+                })
+                .collect()
+        })
+        .collect();
+    (
+        levels,
+        version_set.next_file_number(),
+        version_set.last_sequence(),
+        version_set.dropped_sequence(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChecksumType;
+    use crate::sstable::BlockHandle;
+    use arbitrary::{Arbitrary, Unstructured};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Random inputs and random mutations of valid encodings must never
+    /// make a parser panic
+    #[test]
+    fn test_parsers_do_not_panic() {
+        let mut builder = BlockBuilder::new(2);
+        for key in ["apple", "apricot", "banana"] {
+            builder.add(key.as_bytes(), b"value");
+        }
+        let mut batch = WriteBatch::new();
+        batch.put(b"key", b"value");
+        batch.delete(b"old");
+        let (env, path) = env_with_file("000001.log", &[]);
+        let mut writer = WALWriter::new_with_env(&path, &*env).unwrap();
+        writer.append(&entry::encode_batch(1, &batch)).unwrap();
+        writer.sync().unwrap();
+        let mut filter = BloomFilter::default_with_keys(3);
+        filter.add(b"key");
+        let edit = VersionEdit::AddFile {
+            level: 1,
+            file_number: 5,
+            file_size: 100,
+            smallest_key: b"a".to_vec(),
+            largest_key: b"z".to_vec(),
+        };
+        let footer = Footer::new(BlockHandle::new(10, 20), BlockHandle::new(30, 40));
+        let seeds = [
+            builder.finish().to_vec(),
+            env.read_file(&path).unwrap(),
+            filter.encode(),
+            format!("{}\n", serde_json::to_string(&edit).unwrap()).into_bytes(),
+            footer.encode(),
+        ];
+
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20_000 {
+            let mut data = seeds[rng.random_range(0..seeds.len())].clone();
+            match rng.random_range(0..3) {
+                0 => data = (0..rng.random_range(0..64)).map(|_| rng.random()).collect(),
+                1 if !data.is_empty() => {
+                    let at = rng.random_range(0..data.len());
+                    data[at] = rng.random();
+                }
+                _ => data.truncate(rng.random_range(0..=data.len())),
+            }
+            let _ = parse_block(&data);
+            let _ = parse_footer(&data);
+            let _ = parse_wal(&data);
+            let _ = parse_bloom_filter(&data, b"key");
+            let _ = replay_manifest(&data);
+        }
+    }
+
+    #[test]
+    fn test_replay_manifest_rejects_huge_level() {
+        let manifest = br#"{"DeleteFile":{"level":1000000000000,"file_number":1}}"#;
+        assert!(replay_manifest(&[&manifest[..], b"\n"].concat()).is_err());
+    }
+
+    #[test]
+    fn test_round_trips() {
+        block_round_trip(&[(b"a".to_vec(), b"1".to_vec()), (b"ab".to_vec(), Vec::new())], 1);
+        let footer = Footer {
+            checksum_type: ChecksumType::Xxh3,
+            record_key_version: Some(3),
+            ..Footer::new(BlockHandle::new(1, 2), BlockHandle::new(3, 4))
+        };
+        footer_round_trip(&footer);
+        bloom_round_trip(&[b"x".to_vec(), b"y".to_vec()]);
+
+        let data: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut input = Unstructured::new(&data);
+        wal_round_trip(&Vec::<WriteBatch>::arbitrary(&mut input).unwrap());
+        manifest_round_trip(&Vec::<VersionEdit>::arbitrary(&mut input).unwrap());
+        manifest_round_trip(&[
+            VersionEdit::SetNextFileNumber(9),
+            VersionEdit::AddFile {
+                level: 2,
+                file_number: 4,
+                file_size: 10,
+                smallest_key: b"a".to_vec(),
+                largest_key: b"b".to_vec(),
+            },
+            VersionEdit::DeleteFile { level: 2, file_number: 4 },
+            VersionEdit::DropAll { file_numbers: vec![1], sequence: 7 },
+        ]);
+    }
+}
@@ -0,0 +1,303 @@
+//! Engine event log kept inside the database directory.
+//!
+//! Opens, flushes, compactions, errors and option changes are appended to a
+//! `LOG` file in the database directory, independent of the `log` backend
+//! (if any) configured by the application. This keeps a record for
+//! post-mortem debugging even when stderr was not captured.
+//!
+//! Each line starts with a UTC timestamp and level:
+//!
+//! ```text
+//! 2025/11/06-08:15:42.103512 INFO  Flush completed: 000012.sst, 1024 entries, 65536 bytes
+//! ```
+//!
+//! When the file reaches [`Options::max_log_file_size`] or is older than
+//! [`Options::log_file_time_to_roll`], it is renamed to `LOG.old.<micros>`
+//! and a new `LOG` is started; an existing `LOG` is also rotated when the
+//! database is opened. Only the newest [`Options::keep_log_file_num`] old
+//! files are kept.
+
+use crate::config::Options;
+use crate::env::{Env, PosixEnv, WritableFile};
+use crate::error::Result;
+use crate::util::{SystemTime, UNIX_EPOCH};
+use parking_lot::Mutex;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Name of the active info log file.
+pub const LOG_FILE_NAME: &str = "LOG";
+
+/// Prefix of rotated info log files (followed by microseconds since the epoch).
+pub const OLD_LOG_PREFIX: &str = "LOG.old.";
+
+/// Severity of an info log line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+/// The file currently being written
+struct ActiveLog {
+    file: Box<dyn WritableFile>,
+    size: u64,
+    /// Time the file was started, in microseconds since the epoch
+    created: u64,
+}
+
+/// Writes engine events to the `LOG` file of a database.
+///
+/// Failures to write the log are reported through the `log` crate and
+/// otherwise ignored; they never fail a database operation.
+pub(crate) struct InfoLog {
+    env: Arc<dyn Env>,
+    dir: PathBuf,
+    max_file_size: u64,
+    time_to_roll: Option<Duration>,
+    keep_file_num: usize,
+    active: Mutex<Option<ActiveLog>>,
+}
+
+impl InfoLog {
+    /// Starts a new `LOG` in `dir`, rotating any existing one.
+    ///
+    /// Returns a no-op log if `options.info_log` is false.
+    pub(crate) fn open(dir: &Path, options: &Options) -> Result<Self> {
+        let log = Self {
+            env: Arc::clone(&options.env),
+            dir: dir.to_path_buf(),
+            max_file_size: options.max_log_file_size,
+            time_to_roll: (options.log_file_time_to_roll > 0)
+                .then(|| Duration::from_secs(options.log_file_time_to_roll)),
+            keep_file_num: options.keep_log_file_num,
+            active: Mutex::new(None),
+        };
+        if options.info_log {
+            *log.active.lock() = Some(log.start_new_file()?);
+        }
+        Ok(log)
+    }
+
+    pub(crate) fn info(&self, args: fmt::Arguments<'_>) {
+        self.write(Level::Info, args);
+    }
+
+    pub(crate) fn warn(&self, args: fmt::Arguments<'_>) {
+        self.write(Level::Warn, args);
+    }
+
+    pub(crate) fn error(&self, args: fmt::Arguments<'_>) {
+        self.write(Level::Error, args);
+    }
+
+    /// Starts a new `LOG` now, e.g. so that it is written with a new
+    /// encryption key.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn roll(&self) -> Result<()> {
+        let mut active = self.active.lock();
+        if let Some(current) = active.as_mut() {
+            *current = self.start_new_file()?;
+        }
+        Ok(())
+    }
+
+    /// Appends a line, rotating the file first if it is due.
+    fn write(&self, level: Level, args: fmt::Arguments<'_>) {
+        let mut active = self.active.lock();
+        let Some(current) = active.as_mut() else {
+            return;
+        };
+
+        let too_big = self.max_file_size > 0 && current.size >= self.max_file_size;
+        let too_old = self.time_to_roll.is_some_and(|roll| {
+            self.env.now_micros().saturating_sub(current.created) >= roll.as_micros() as u64
+        });
+        if too_big || too_old {
+            match self.start_new_file() {
+                Ok(new_log) => *current = new_log,
+                Err(e) => log::warn!("Failed to rotate info log in {:?}: {}", self.dir, e),
+            }
+        }
+
+        let now = UNIX_EPOCH + Duration::from_micros(self.env.now_micros());
+        let line = format!("{} {:<5} {}\n", format_timestamp(now), level.as_str(), args);
+        match current.file.write_all(line.as_bytes()) {
+            Ok(()) => current.size += line.len() as u64,
+            Err(e) => log::warn!("Failed to write info log in {:?}: {}", self.dir, e),
+        }
+    }
+
+    /// Renames an existing `LOG` out of the way, removes old files beyond
+    /// the limit and creates an empty `LOG`.
+    fn start_new_file(&self) -> Result<ActiveLog> {
+        let path = self.dir.join(LOG_FILE_NAME);
+        if self.env.file_exists(&path) {
+            let micros = self.env.now_micros();
+            let mut old_path = self.dir.join(format!("{}{}", OLD_LOG_PREFIX, micros));
+            // Two rotations within the same microsecond
+            let mut suffix = micros;
+            while self.env.file_exists(&old_path) {
+                suffix += 1;
+                old_path = self.dir.join(format!("{}{}", OLD_LOG_PREFIX, suffix));
+            }
+            self.env.rename_file(&path, &old_path)?;
+        }
+        self.purge_old_files()?;
+
+        let file = self.env.new_writable_file(&path)?;
+        Ok(ActiveLog { file, size: 0, created: self.env.now_micros() })
+    }
+
+    /// Removes the oldest rotated files so at most `keep_file_num` remain
+    fn purge_old_files(&self) -> Result<()> {
+        let mut old_files = old_log_files_with_env(&self.dir, &*self.env)?;
+        if old_files.len() > self.keep_file_num {
+            let excess = old_files.len() - self.keep_file_num;
+            for (_, path) in old_files.drain(..excess) {
+                self.env.remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists the rotated info log files in `dir`, oldest first.
+pub fn old_log_files(dir: &Path) -> Result<Vec<(u128, PathBuf)>> {
+    old_log_files_with_env(dir, &PosixEnv)
+}
+
+/// Lists the rotated info log files in directory `dir` of `env`, oldest first.
+pub fn old_log_files_with_env(dir: &Path, env: &dyn Env) -> Result<Vec<(u128, PathBuf)>> {
+    let mut files = Vec::new();
+    for name in env.get_children(dir)? {
+        let Some(micros) =
+            name.strip_prefix(OLD_LOG_PREFIX).and_then(|micros| micros.parse::<u128>().ok())
+        else {
+            continue;
+        };
+        files.push((micros, dir.join(name)));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Formats a time as `YYYY/MM/DD-HH:MM:SS.micros` in UTC
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}/{:02}/{:02}-{:02}:{:02}:{:02}.{:06}",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn read_log(dir: &Path) -> String {
+        std::fs::read_to_string(dir.join(LOG_FILE_NAME)).unwrap()
+    }
+
+    #[test]
+    fn test_writes_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = InfoLog::open(temp_dir.path(), &Options::default()).unwrap();
+        log.info(format_args!("hello {}", 42));
+        log.error(format_args!("broken"));
+
+        let contents = read_log(temp_dir.path());
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" INFO  hello 42"), "{}", lines[0]);
+        assert!(lines[1].ends_with(" ERROR broken"), "{}", lines[1]);
+    }
+
+    #[test]
+    fn test_rotates_on_open_and_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().max_log_file_size(100).keep_log_file_num(2);
+
+        let log = InfoLog::open(temp_dir.path(), &options).unwrap();
+        log.info(format_args!("first"));
+        drop(log);
+
+        // Reopening rotates the previous file
+        let log = InfoLog::open(temp_dir.path(), &options).unwrap();
+        assert_eq!(old_log_files(temp_dir.path()).unwrap().len(), 1);
+        assert_eq!(read_log(temp_dir.path()), "");
+
+        // Each line is about 50 bytes, so the file rotates every few lines
+        for i in 0..20 {
+            log.info(format_args!("line {}", i));
+        }
+        assert_eq!(old_log_files(temp_dir.path()).unwrap().len(), 2);
+        assert!(read_log(temp_dir.path()).contains("line 19"));
+        assert!(std::fs::metadata(temp_dir.path().join(LOG_FILE_NAME)).unwrap().len() <= 150);
+    }
+
+    #[test]
+    fn test_rotates_on_age() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = InfoLog::open(temp_dir.path(), &Options::default()).unwrap();
+        log.info(format_args!("old"));
+
+        // Pretend the file was created long ago
+        log.active.lock().as_mut().unwrap().created -= 10_000_000;
+        let log = InfoLog { time_to_roll: Some(Duration::from_secs(5)), ..log };
+        log.info(format_args!("new"));
+
+        assert_eq!(old_log_files(temp_dir.path()).unwrap().len(), 1);
+        assert!(!read_log(temp_dir.path()).contains("old"));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = InfoLog::open(temp_dir.path(), &Options::default().info_log(false)).unwrap();
+        log.info(format_args!("ignored"));
+        assert!(!temp_dir.path().join(LOG_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970/01/01-00:00:00.000000");
+        let time = UNIX_EPOCH + Duration::from_micros(1_709_210_096_123_456);
+        assert_eq!(format_timestamp(time), "2024/02/29-12:34:56.123456");
+    }
+}
@@ -0,0 +1,702 @@
+//! Bulk loading with externally built SSTables.
+//!
+//! [`SstFileWriter`] writes sorted entries straight into an SSTable in
+//! AiDb's format, without a database. [`DB::ingest_external_file`] then adds
+//! such files to a database as they are: nothing is written to the WAL or a
+//! MemTable, and no entry is rewritten, so loading a large sorted data set
+//! costs little more than writing it once.
+//!
+//! Ingested files are newer than every write made before the call. Files
+//! from an [`SstFileWriter`] do not store sequence numbers; they are ordered
+//! by where they are placed instead. Each one goes to the deepest level none of whose
+//! files, or the files of the levels above, overlap its key range, so it is
+//! read before any older version of its keys and rarely needs compacting. A
+//! MemTable holding keys in the file's range is flushed first.
+//!
+//! [`DB::export_snapshot`] goes the other way: it writes the live entries of
+//! a [`Snapshot`] to SSTables in a new directory, described by an
+//! [`ExportMetadata`] file. Unlike a checkpoint, the export does not depend
+//! on the source's level layout or options, so it can be ingested into any
+//! other database.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::ingest::SstFileWriter;
+//! use aidb::{Options, DB};
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let options = Options::default();
+//! let mut writer = SstFileWriter::create("users.sst", &options)?;
+//! for i in 0..1_000_000u32 {
+//!     writer.put(format!("user:{:08}", i).as_bytes(), b"{}")?;
+//! }
+//! writer.finish()?;
+//!
+//! let db = DB::open("./data", options)?;
+//! db.ingest_external_file(&["users.sst"])?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Copying a snapshot of one database into another:
+//!
+//! ```rust,no_run
+//! use aidb::ingest::ExportMetadata;
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let source = Arc::new(DB::open("./data", Options::default())?);
+//! let snapshot = source.snapshot();
+//! source.export_snapshot(&snapshot, "./export")?;
+//!
+//! let metadata = ExportMetadata::load("./export")?;
+//! let target = DB::open("./copy", Options::default())?;
+//! target.ingest_external_file(&metadata.file_paths("./export"))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp::Ordering as CmpOrdering;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::blob;
+use crate::comparator::Comparator;
+use crate::env::Env;
+use crate::record_auth::RecordAuthenticator;
+use crate::sstable::{self, SSTableBuilder, SSTableReader};
+use crate::{listener, DBIterator, Error, Options, ReadOptions, Result, Snapshot, VersionEdit, DB};
+
+/// Name of the metadata file in a directory written by
+/// [`DB::export_snapshot`]
+pub const EXPORT_METADATA_FILE: &str = "EXPORT.json";
+
+/// Size at which [`DB::export_snapshot`] starts a new file, unless the
+/// bottom level has a target file size
+const EXPORT_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Writes sorted entries to a standalone SSTable for
+/// [`DB::ingest_external_file`].
+///
+/// The table uses the comparator, block size, compression, checksum, Bloom
+/// filter and prefix extractor settings of the options it is created with.
+pub struct SstFileWriter {
+    builder: SSTableBuilder,
+}
+
+/// Description of a file written by [`SstFileWriter`].
+#[derive(Debug, Clone)]
+pub struct ExternalSstFileInfo {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Smallest key in the file
+    pub smallest_key: Vec<u8>,
+    /// Largest key in the file
+    pub largest_key: Vec<u8>,
+    /// Number of entries (puts and deletes)
+    pub num_entries: u64,
+    /// Size of the file in bytes
+    pub file_size: u64,
+}
+
+impl SstFileWriter {
+    /// Creates a writer for a new SSTable at `path`, written through
+    /// `options.env`.
+    ///
+    /// The file only appears at `path` once [`finish`](Self::finish)
+    /// succeeds.
+    pub fn create<P: AsRef<Path>>(path: P, options: &Options) -> Result<Self> {
+        let mut builder = SSTableBuilder::new_with_env(path, Arc::clone(&options.env))?;
+        builder.set_comparator(Arc::clone(&options.comparator));
+        builder.set_block_size(options.block_size);
+        builder.set_compression(options.compression_for_level(0));
+        builder.set_checksum_type(options.checksum);
+        match options.bloom_filter_for_level(0) {
+            Some(rate) => builder.set_bloom_filter_fp_rate(rate),
+            None => builder.set_bloom_filter_enabled(false),
+        }
+        builder.set_prefix_extractor(options.prefix_extractor);
+        if let (true, Some(keys)) = (options.authenticate_records, &options.key_provider) {
+            let authenticator = RecordAuthenticator::new(Arc::clone(keys));
+            builder.set_record_authenticator(Arc::new(authenticator))?;
+        }
+        Ok(Self { builder })
+    }
+
+    /// Adds an entry. Keys must be added in strictly increasing order.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if the key is not greater than the previous
+    /// one, if the key or value is empty (an empty value marks a deleted
+    /// key in an SSTable), or if the value has the format of a
+    /// [`DB::put_reader`](crate::DB::put_reader) blob reference.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        if value.is_empty() {
+            return Err(Error::invalid_argument("SstFileWriter cannot write an empty value"));
+        }
+        blob::check_value(key, value)?;
+        self.builder.add(key, value)
+    }
+
+    /// Marks `key` as deleted, hiding older versions of it in the database
+    /// the file is ingested into. Keys must be added in strictly increasing
+    /// order.
+    pub fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.builder.add(key, &[])
+    }
+
+    /// Returns the number of entries added so far.
+    pub fn num_entries(&self) -> u64 {
+        self.builder.num_entries()
+    }
+
+    /// Returns the approximate size of the file so far, in bytes.
+    pub fn file_size(&self) -> u64 {
+        self.builder.current_size()
+    }
+
+    /// Completes the file and moves it to its final path.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if no entry was added; the incomplete file
+    /// is removed.
+    pub fn finish(self) -> Result<ExternalSstFileInfo> {
+        if self.builder.num_entries() == 0 {
+            self.builder.abandon()?;
+            return Err(Error::invalid_argument("cannot finish an SST file with no entries"));
+        }
+        let table = self.builder.finish()?;
+        Ok(ExternalSstFileInfo {
+            smallest_key: table.smallest_key.clone().expect("table has entries"),
+            largest_key: table.largest_key.clone().expect("table has entries"),
+            path: table.path,
+            num_entries: table.num_entries,
+            file_size: table.file_size,
+        })
+    }
+}
+
+/// Contents of the [`EXPORT_METADATA_FILE`] written by
+/// [`DB::export_snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportMetadata {
+    /// Sequence number of the exported snapshot
+    pub sequence: u64,
+    /// Number of entries in all files
+    pub num_entries: u64,
+    /// The SSTables, in key order
+    pub files: Vec<ExportedFile>,
+}
+
+/// An SSTable written by [`DB::export_snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedFile {
+    /// File name inside the export directory
+    pub name: String,
+    /// Smallest key in the file
+    pub smallest_key: Vec<u8>,
+    /// Largest key in the file
+    pub largest_key: Vec<u8>,
+    /// Number of entries in the file
+    pub num_entries: u64,
+    /// Size of the file in bytes
+    pub file_size: u64,
+}
+
+impl ExportMetadata {
+    /// Reads the metadata of the export in `dir`.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Self::load_with_env(dir, &*crate::env::default_env())
+    }
+
+    /// Reads the metadata of the export in `dir` through `env`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Corruption` if the metadata file cannot be parsed.
+    pub fn load_with_env<P: AsRef<Path>>(dir: P, env: &dyn Env) -> Result<Self> {
+        let data = env.read_file(&dir.as_ref().join(EXPORT_METADATA_FILE))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| Error::corruption(format!("invalid export metadata: {}", e)))
+    }
+
+    /// Returns the paths of the exported SSTables, for
+    /// [`DB::ingest_external_file`].
+    pub fn file_paths<P: AsRef<Path>>(&self, dir: P) -> Vec<PathBuf> {
+        self.files.iter().map(|file| dir.as_ref().join(&file.name)).collect()
+    }
+}
+
+/// An external file being ingested
+struct IngestedFile {
+    source: PathBuf,
+    smallest_key: Vec<u8>,
+    largest_key: Vec<u8>,
+}
+
+impl DB {
+    /// Adds the SSTables at `paths` to the database.
+    ///
+    /// The files must have been written by [`SstFileWriter`] (or be AiDb
+    /// SSTables from another database) with the database's comparator, and
+    /// their key ranges must not overlap each other. They are hard-linked into the database directory
+    /// where possible and copied otherwise; the originals are left in place.
+    ///
+    /// Writes made while the files are being ingested may be ordered before
+    /// or after them.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if a file is empty, uses a compression this
+    /// build cannot read, or overlaps another of the files. Nothing is
+    /// added to the database if any file is rejected.
+    pub fn ingest_external_file<P: AsRef<Path>>(&self, paths: &[P]) -> Result<()> {
+        self.check_background_error()?;
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let source = path.as_ref().to_path_buf();
+            let reader = SSTableReader::open_with_env(&source, None, &*self.env)?
+                .with_record_authenticator(self.record_auth.clone())?
+                .with_comparator(Arc::clone(&self.comparator));
+            Self::check_sstable_compression(&reader)?;
+            let (smallest_key, largest_key) = match reader.key_range()? {
+                Some((smallest, largest)) => (smallest.to_vec(), largest.to_vec()),
+                None => {
+                    return Err(Error::invalid_argument(format!(
+                        "cannot ingest {:?}: it has no entries",
+                        source
+                    )))
+                }
+            };
+            files.push(IngestedFile { source, smallest_key, largest_key });
+        }
+        files.sort_by(|a, b| self.comparator.compare(&a.smallest_key, &b.smallest_key));
+        for pair in files.windows(2) {
+            if self.comparator.compare(&pair[1].smallest_key, &pair[0].largest_key)
+                != CmpOrdering::Greater
+            {
+                return Err(Error::invalid_argument(format!(
+                    "cannot ingest {:?} and {:?} together: their key ranges overlap",
+                    pair[0].source, pair[1].source
+                )));
+            }
+        }
+
+        // Newer writes to the same keys must move to SSTables, which the
+        // ingested files are then placed above
+        if files.iter().any(|f| self.memtables_overlap(&f.smallest_key, &f.largest_key)) {
+            self.flush()?;
+        }
+
+        // Bring the files into the database directory under their new
+        // names; the temporary names are cleaned up on open after a crash
+        let mut installed = Vec::with_capacity(files.len());
+        for file in &files {
+            let file_number = self.next_file_number.fetch_add(1, Ordering::SeqCst);
+            let path = self.path.join(format!("{:06}.sst", file_number));
+            let temp_path = sstable::temp_file_path(&path);
+            let result = self
+                .env
+                .link_file(&file.source, &temp_path)
+                .and_then(|()| self.env.rename_file(&temp_path, &path));
+            if let Err(e) = result {
+                let _ = self.env.remove_file(&temp_path);
+                self.remove_ingested(&installed);
+                return Err(e.into());
+            }
+            installed.push((file_number, path));
+        }
+        if let Err(e) = self.env.sync_dir(&self.path) {
+            self.remove_ingested(&installed);
+            return Err(e.into());
+        }
+
+        let mut readers = Vec::with_capacity(installed.len());
+        for (file_number, path) in &installed {
+            match SSTableReader::open_with_env(
+                path,
+                Some(Arc::clone(&self.block_cache)),
+                &*self.env,
+            )
+            .and_then(|reader| reader.with_record_authenticator(self.record_auth.clone()))
+            .map(|reader| reader.with_comparator(Arc::clone(&self.comparator)))
+            {
+                Ok(reader) => {
+                    readers.push((*file_number, Arc::new(self.attach_table_cache(reader))))
+                }
+                Err(e) => {
+                    drop(readers);
+                    self.remove_ingested(&installed);
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut created = Vec::with_capacity(readers.len());
+        {
+            // No flush or compaction may add a file the level choice misses
+            let _compaction = self.compaction_lock.lock();
+            let _flush = self.flush_lock.lock();
+            let mut version_set = self.version_set.write();
+            let mut sstables = self.sstables.write();
+
+            for ((file_number, reader), file) in readers.into_iter().zip(&files) {
+                let level = pick_ingestion_level(
+                    &*self.comparator,
+                    &sstables,
+                    &file.smallest_key,
+                    &file.largest_key,
+                );
+                version_set.log_edit(&VersionEdit::AddFile {
+                    level,
+                    file_number,
+                    file_size: reader.file_size(),
+                    smallest_key: file.smallest_key.clone(),
+                    largest_key: file.largest_key.clone(),
+                })?;
+                if level == 0 {
+                    sstables[0].insert(0, Arc::clone(&reader));
+                } else {
+                    sstables[level].push(Arc::clone(&reader));
+                    crate::sort_level_by_key(&mut sstables[level]);
+                }
+                if let Some(ref manager) = self.sst_file_manager {
+                    manager.on_add_file(reader.file_path(), reader.file_size());
+                }
+                created.push((file_number, reader, level));
+            }
+        }
+
+        let bytes: u64 = created.iter().map(|(_, reader, _)| reader.file_size()).sum();
+        log::info!("Ingested {} external SSTable(s), {} bytes", created.len(), bytes);
+        for ((file_number, _, level), file) in created.iter().zip(&files) {
+            self.info_log.info(format_args!(
+                "Ingested {:?} as {:06}.sst at level {}",
+                file.source, file_number, level
+            ));
+        }
+        self.notify(|l| {
+            for (file_number, reader, level) in &created {
+                l.on_table_file_created(&listener::TableFileCreationInfo {
+                    file_number: *file_number,
+                    file_path: reader.file_path().to_path_buf(),
+                    file_size: reader.file_size(),
+                    level: *level,
+                    reason: listener::TableFileReason::Ingestion,
+                });
+            }
+        });
+
+        self.maybe_trigger_compaction().map_err(|e| self.set_background_error(e))
+    }
+
+    /// Writes the entries visible in `snapshot` to SSTables in
+    /// `export_dir`, which must not exist yet, together with an
+    /// [`ExportMetadata`] file.
+    ///
+    /// Deleted keys are left out. Files are split at the bottom level's
+    /// target file size, or at 64MB. As with other snapshot reads, writes
+    /// made after the snapshot can show up once they are flushed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `export_dir` exists or `snapshot` was
+    /// taken on another database. If writing fails, the error is returned
+    /// and the incomplete directory is left behind.
+    pub fn export_snapshot<P: AsRef<Path>>(
+        self: &Arc<Self>,
+        snapshot: &Snapshot,
+        export_dir: P,
+    ) -> Result<ExportMetadata> {
+        if !std::ptr::eq(Arc::as_ptr(snapshot.db()), Arc::as_ptr(self)) {
+            return Err(Error::invalid_argument("snapshot belongs to another database"));
+        }
+        self.export_range(snapshot.sequence(), None, None, export_dir.as_ref())
+    }
+
+    /// Writes the entries with `start <= key < end` visible at `sequence`
+    /// to SSTables in `dir`, as [`export_snapshot`](Self::export_snapshot)
+    pub(crate) fn export_range(
+        self: &Arc<Self>,
+        sequence: u64,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        dir: &Path,
+    ) -> Result<ExportMetadata> {
+        if self.env.file_exists(dir) {
+            return Err(Error::invalid_argument(format!(
+                "export directory {:?} already exists",
+                dir
+            )));
+        }
+        self.env.create_dir_all(dir)?;
+
+        let options = self.options.read().clone();
+        let file_size = options
+            .target_file_size_for_level(options.max_levels - 1)
+            .map_or(EXPORT_FILE_SIZE, |size| size as u64);
+
+        let mut iter =
+            DBIterator::new_range(Arc::clone(self), sequence, &ReadOptions::default(), start, end)?;
+        let mut metadata = ExportMetadata { sequence, num_entries: 0, files: Vec::new() };
+        let mut writer: Option<SstFileWriter> = None;
+        while iter.valid() {
+            let current = match writer.as_mut() {
+                Some(writer) => writer,
+                None => {
+                    let name = format!("{:06}.sst", metadata.files.len() + 1);
+                    writer.insert(SstFileWriter::create(dir.join(name), &options)?)
+                }
+            };
+            current.put(iter.key(), iter.value())?;
+            if current.file_size() >= file_size {
+                metadata.files.push(finish_export_file(writer.take().expect("writer is open"))?);
+            }
+            iter.next();
+        }
+        iter.status()?;
+        if let Some(writer) = writer {
+            metadata.files.push(finish_export_file(writer)?);
+        }
+        metadata.num_entries = metadata.files.iter().map(|file| file.num_entries).sum();
+
+        let json = serde_json::to_vec_pretty(&metadata)
+            .map_err(|e| Error::internal(format!("Failed to serialize export metadata: {}", e)))?;
+        self.env.write_file(&dir.join(EXPORT_METADATA_FILE), &json)?;
+        self.env.sync_dir(dir)?;
+
+        log::info!(
+            "Exported snapshot {} to {:?}: {} entries in {} files",
+            metadata.sequence,
+            dir,
+            metadata.num_entries,
+            metadata.files.len()
+        );
+        Ok(metadata)
+    }
+
+    /// Whether a MemTable holds a key (or deletion) in `smallest..=largest`
+    fn memtables_overlap(&self, smallest: &[u8], largest: &[u8]) -> bool {
+        let overlaps = |memtable: &crate::memtable::MemTable| {
+            let mut cursor = memtable.cursor(u64::MAX, Default::default());
+            cursor.seek(smallest);
+            cursor
+                .key()
+                .is_some_and(|key| self.comparator.compare(key, largest) != CmpOrdering::Greater)
+        };
+        overlaps(&self.memtable.read())
+            || self.immutable_memtables.read().iter().any(|m| overlaps(m))
+    }
+
+    /// Removes files linked in by an ingestion that failed
+    fn remove_ingested(&self, installed: &[(u64, PathBuf)]) {
+        for (_, path) in installed {
+            if let Err(e) = self.env.remove_file(path) {
+                log::warn!("Failed to remove ingested SSTable {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+/// Completes one file of an export
+fn finish_export_file(writer: SstFileWriter) -> Result<ExportedFile> {
+    let info = writer.finish()?;
+    Ok(ExportedFile {
+        name: info
+            .path
+            .file_name()
+            .expect("SSTable path has a file name")
+            .to_string_lossy()
+            .into(),
+        smallest_key: info.smallest_key,
+        largest_key: info.largest_key,
+        num_entries: info.num_entries,
+        file_size: info.file_size,
+    })
+}
+
+/// Returns the deepest level a file with keys `smallest..=largest` can go
+/// to: no file in that level or any level above may overlap it.
+fn pick_ingestion_level(
+    comparator: &dyn Comparator,
+    sstables: &[Vec<Arc<SSTableReader>>],
+    smallest: &[u8],
+    largest: &[u8],
+) -> usize {
+    let mut target = 0;
+    for (level, tables) in sstables.iter().enumerate() {
+        let overlaps = tables.iter().any(|table| {
+            !matches!(table.key_range(), Ok(Some((first, last)))
+                if comparator.compare(last, smallest) == CmpOrdering::Less
+                    || comparator.compare(first, largest) == CmpOrdering::Greater)
+        });
+        if overlaps {
+            break;
+        }
+        target = level;
+    }
+    target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_file(path: &Path, keys: impl IntoIterator<Item = u32>, value: &[u8]) {
+        let mut writer = SstFileWriter::create(path, &Options::default()).unwrap();
+        for i in keys {
+            writer.put(format!("key{:04}", i).as_bytes(), value).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn level_of(db: &DB, file_number: u64) -> Option<usize> {
+        db.sstables
+            .read()
+            .iter()
+            .position(|level| level.iter().any(|t| t.file_number() == Some(file_number)))
+    }
+
+    #[test]
+    fn test_sst_file_writer() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.sst");
+
+        let mut writer = SstFileWriter::create(&path, &Options::default()).unwrap();
+        writer.put(b"b", b"1").unwrap();
+        assert!(writer.put(b"a", b"2").is_err());
+        assert!(writer.put(b"c", b"").is_err());
+        writer.delete(b"d").unwrap();
+        assert!(!path.exists());
+
+        let info = writer.finish().unwrap();
+        assert_eq!(info.path, path);
+        assert_eq!(
+            (info.smallest_key.as_slice(), info.largest_key.as_slice()),
+            (&b"b"[..], &b"d"[..])
+        );
+        assert_eq!(info.num_entries, 2);
+        assert_eq!(info.file_size, std::fs::metadata(&path).unwrap().len());
+
+        let empty = SstFileWriter::create(temp_dir.path().join("empty.sst"), &Options::default());
+        assert!(empty.unwrap().finish().is_err());
+        assert!(!temp_dir.path().join("empty.sst").exists());
+    }
+
+    #[test]
+    fn test_ingest_external_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let db = DB::open(&db_path, Options::default()).unwrap();
+
+        for i in 0..10 {
+            db.put(format!("key{:04}", i).as_bytes(), b"old").unwrap();
+        }
+        db.flush().unwrap();
+        db.put(b"key0005", b"memtable").unwrap();
+
+        // Overlaps the flushed file and the MemTable
+        let overlapping = temp_dir.path().join("overlapping.sst");
+        write_file(&overlapping, 5..15, b"ingested");
+        // Overlaps nothing
+        let disjoint = temp_dir.path().join("disjoint.sst");
+        write_file(&disjoint, 100..110, b"ingested");
+
+        let first = db.next_file_number.load(Ordering::SeqCst);
+        db.ingest_external_file(&[&disjoint, &overlapping]).unwrap();
+        assert!(overlapping.exists() && disjoint.exists());
+
+        assert_eq!(db.get(b"key0004").unwrap(), Some(b"old".to_vec()));
+        assert_eq!(db.get(b"key0005").unwrap(), Some(b"ingested".to_vec()));
+        assert_eq!(db.get(b"key0014").unwrap(), Some(b"ingested".to_vec()));
+        assert_eq!(db.get(b"key0105").unwrap(), Some(b"ingested".to_vec()));
+
+        // The overlapping file sits above the flushed data, the other one at
+        // the bottom
+        let max_level = db.options.read().max_levels - 1;
+        let levels: Vec<_> = (first..first + 3).filter_map(|n| level_of(&db, n)).collect();
+        assert!(levels.contains(&0));
+        assert!(levels.contains(&max_level));
+
+        // Later writes win over ingested data
+        db.put(b"key0006", b"new").unwrap();
+        assert_eq!(db.get(b"key0006").unwrap(), Some(b"new".to_vec()));
+
+        drop(db);
+        let db = DB::open(&db_path, Options::default()).unwrap();
+        assert_eq!(db.get(b"key0005").unwrap(), Some(b"ingested".to_vec()));
+        assert_eq!(db.get(b"key0006").unwrap(), Some(b"new".to_vec()));
+        assert_eq!(db.get(b"key0105").unwrap(), Some(b"ingested".to_vec()));
+        let levels: Vec<_> = (first..first + 3).filter_map(|n| level_of(&db, n)).collect();
+        assert!(levels.contains(&max_level));
+    }
+
+    #[test]
+    fn test_ingest_rejects_overlapping_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path().join("db"), Options::default()).unwrap();
+
+        let a = temp_dir.path().join("a.sst");
+        let b = temp_dir.path().join("b.sst");
+        write_file(&a, 0..10, b"a");
+        write_file(&b, 9..20, b"b");
+
+        assert!(matches!(db.ingest_external_file(&[&a, &b]), Err(Error::InvalidArgument(_))));
+        assert!(db.ingest_external_file(&[temp_dir.path().join("missing.sst")]).is_err());
+        assert_eq!(db.get(b"key0005").unwrap(), None);
+    }
+
+    #[test]
+    fn test_export_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let export_dir = temp_dir.path().join("export");
+        let options = Options::default()
+            .level_options(6, crate::config::LevelOptions::new().target_file_size(4 * 1024));
+        let db = Arc::new(DB::open(temp_dir.path().join("db"), options).unwrap());
+
+        for i in 0..1000 {
+            db.put(format!("key{:04}", i).as_bytes(), &[b'v'; 100]).unwrap();
+        }
+        db.flush().unwrap();
+        db.delete(b"key0000").unwrap();
+        db.put(b"key0001", b"changed").unwrap();
+
+        let snapshot = db.snapshot();
+        db.put(b"key0002", b"after snapshot").unwrap();
+        db.put(b"key9999", b"after snapshot").unwrap();
+
+        let metadata = db.export_snapshot(&snapshot, &export_dir).unwrap();
+        assert!(db.export_snapshot(&snapshot, &export_dir).is_err());
+        assert_eq!(metadata.sequence, snapshot.sequence());
+        assert_eq!(metadata.num_entries, 999);
+        assert!(metadata.files.len() > 1);
+        assert_eq!(ExportMetadata::load(&export_dir).unwrap(), metadata);
+        for pair in metadata.files.windows(2) {
+            assert!(pair[0].largest_key < pair[1].smallest_key);
+        }
+
+        let copy = DB::open(temp_dir.path().join("copy"), Options::default()).unwrap();
+        copy.ingest_external_file(&metadata.file_paths(&export_dir)).unwrap();
+        assert_eq!(copy.get(b"key0000").unwrap(), None);
+        assert_eq!(copy.get(b"key0001").unwrap(), Some(b"changed".to_vec()));
+        assert_eq!(copy.get(b"key0002").unwrap(), Some(vec![b'v'; 100]));
+        assert_eq!(copy.get(b"key0999").unwrap(), Some(vec![b'v'; 100]));
+        assert_eq!(copy.get(b"key9999").unwrap(), None);
+
+        let other = Arc::new(DB::open(temp_dir.path().join("other"), Options::default()).unwrap());
+        let result = other.export_snapshot(&snapshot, temp_dir.path().join("other-export"));
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+    }
+}
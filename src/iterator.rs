@@ -1,10 +1,40 @@
 //! Database iterator for scanning key-value pairs.
 //!
 //! Provides sequential and range-based iteration over the database.
-
+//!
+//! A [`DBIterator`] merges one cursor per MemTable and SSTable. Each cursor
+//! stops once per user key, on the newest version visible to the iterator;
+//! a binary heap orders the cursors by key and, for equal keys, by source
+//! age, so the newest source decides a key's value. Deleted keys are
+//! skipped. Iteration works in both directions: when the direction changes,
+//! every cursor is repositioned relative to the current key.
+//!
+//! With [`ReadOptions::prefix_same_as_start`], a seek to a key with a prefix
+//! (see [`PrefixExtractor`]) confines the iterator to keys with that prefix:
+//! SSTables whose prefix Bloom filter rules it out are not read at all, and
+//! the iterator becomes invalid at the end of the prefix.
+//!
+//! A tailing iterator ([`ReadOptions::tailing`]) is not tied to the sequence
+//! number it was created at. Once it has run past the last entry, calling
+//! [`next`](DBIterator::next) again picks up keys written since: the MemTable
+//! cursors are recreated at the latest sequence and SSTables created in the
+//! meantime are added, while the cursors of unchanged SSTables are kept.
+
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
-use crate::{Result, DB};
+use crate::blob::{self, BlobReference};
+use crate::comparator::Comparator;
+use crate::config::PrefixExtractor;
+use crate::memtable::{MemTable, MemTableCursor, Version};
+use crate::perf_context::{self, SlowOperationLog};
+use crate::prefix_delete::PrefixDeletes;
+use crate::sstable::reader::SSTableIterator;
+use crate::sstable::SSTableReader;
+use crate::statistics::Ticker;
+use crate::{Error, ReadOptions, Result, DB};
 
 /// An iterator over key-value pairs in the database.
 ///
@@ -12,6 +42,13 @@ use crate::{Result, DB};
 /// data from MemTables and SSTables. It automatically handles tombstones
 /// (deleted keys).
 ///
+/// Positioning methods do not return errors; if reading fails the iterator
+/// becomes invalid and [`status`](Self::status) returns the error.
+///
+/// The iterator pins the MemTables and SSTables it reads: files compacted
+/// away while it is alive are only deleted once it is dropped or
+/// [refreshed](Self::refresh).
+///
 /// # Example
 ///
 /// ```rust,no_run
@@ -37,6 +74,14 @@ use crate::{Result, DB};
 ///     println!("{:?} => {:?}", key, value);
 ///     iter.next();
 /// }
+///
+/// // And backwards from the last key before "key3"
+/// iter.seek_for_prev(b"key2");
+/// while iter.valid() {
+///     println!("{:?}", iter.key());
+///     iter.prev();
+/// }
+/// iter.status()?;
 /// # Ok(())
 /// # }
 /// ```
@@ -44,118 +89,249 @@ pub struct DBIterator {
     /// Reference to the database
     db: Arc<DB>,
 
+    /// Sequence number the MemTable cursors read at
+    sequence: u64,
+
+    /// Settings the iterator was created with
+    read_options: ReadOptions,
+
+    /// Cursors over the MemTables and SSTables, newest first
+    sources: Vec<Source>,
+
+    /// Positioned sources, ordered for the current direction
+    heap: BinaryHeap<HeapEntry>,
+
+    /// Direction of the last move
+    direction: Direction,
+
     /// Current key-value pair
     current: Option<(Vec<u8>, Vec<u8>)>,
 
-    /// Sequence number for consistent reads
-    sequence: u64,
+    /// Inclusive lower bound of the iterated range
+    lower_bound: Option<Vec<u8>>,
 
-    /// All keys in sorted order (cached for simplicity)
-    keys: Vec<Vec<u8>>,
+    /// Exclusive upper bound of the iterated range
+    upper_bound: Option<Vec<u8>>,
 
-    /// Current position in the keys vector
-    position: usize,
-}
+    /// Extractor used for prefix seeks
+    prefix_extractor: PrefixExtractor,
 
-impl DBIterator {
-    /// Creates a new iterator starting from the beginning.
-    pub(crate) fn new(db: Arc<DB>, sequence: u64) -> Result<Self> {
-        let mut iter = Self { db, current: None, sequence, keys: Vec::new(), position: 0 };
+    /// Prefix of the last seek target, when confined to it
+    prefix: Option<Vec<u8>>,
 
-        // Collect all keys from the database
-        iter.collect_keys(None, None)?;
+    /// Error that invalidated the iterator
+    error: Option<Error>,
 
-        // Position at the first key
-        if !iter.keys.is_empty() {
-            iter.position = 0;
-            iter.load_current()?;
-        }
+    /// Bytes of keys and values held, as reported in `DB::memory_usage`
+    held_bytes: usize,
 
-        Ok(iter)
-    }
+    /// Where a tailing iterator continues once it has run off the end
+    resume: Option<Resume>,
 
-    /// Creates a new iterator with a range.
-    pub(crate) fn new_range(
-        db: Arc<DB>,
-        sequence: u64,
-        start: Option<&[u8]>,
-        end: Option<&[u8]>,
-    ) -> Result<Self> {
-        let mut iter = Self { db, current: None, sequence, keys: Vec::new(), position: 0 };
+    /// Prefix deletions visible at `sequence`
+    prefix_deletes: PrefixDeletes,
+}
 
-        // Collect keys in the specified range
-        iter.collect_keys(start.map(|s| s.to_vec()), end.map(|e| e.to_vec()))?;
+/// Position a tailing iterator moves to after picking up new writes
+#[derive(Debug, Clone)]
+enum Resume {
+    /// The first key in range
+    Start,
+    /// The first key `>=` the given one
+    From(Vec<u8>),
+    /// The first key `>` the given one
+    After(Vec<u8>),
+}
 
-        // Position at the first key
-        if !iter.keys.is_empty() {
-            iter.position = 0;
-            iter.load_current()?;
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Forward,
+    Backward,
+}
 
-        Ok(iter)
+/// One sorted input of a [`DBIterator`].
+///
+/// A source is on at most one entry per user key.
+enum Source {
+    MemTable(MemTableCursor),
+    Table { table: Arc<SSTableReader>, iter: Box<SSTableIterator> },
+}
+
+impl Source {
+    fn seek_to_first(&mut self) -> Result<()> {
+        match self {
+            Source::MemTable(cursor) => cursor.seek_to_first(),
+            Source::Table { iter, .. } => {
+                iter.seek_to_first()?;
+                iter.advance()?;
+            }
+        }
+        Ok(())
     }
 
-    /// Collects all keys from the database that fall within the specified range.
-    fn collect_keys(&mut self, start: Option<Vec<u8>>, end: Option<Vec<u8>>) -> Result<()> {
-        use std::collections::BTreeSet;
+    fn seek_to_last(&mut self) -> Result<()> {
+        match self {
+            Source::MemTable(cursor) => cursor.seek_to_last(),
+            Source::Table { iter, .. } => iter.seek_to_last()?,
+        }
+        Ok(())
+    }
 
-        let mut all_keys = BTreeSet::new();
+    fn seek(&mut self, target: &[u8]) -> Result<()> {
+        match self {
+            Source::MemTable(cursor) => cursor.seek(target),
+            Source::Table { iter, .. } => iter.seek(target)?,
+        }
+        Ok(())
+    }
 
-        // Collect from current MemTable
-        {
-            let memtable = self.db.memtable.read();
-            all_keys.extend(memtable.keys());
+    fn seek_for_prev(&mut self, target: &[u8]) -> Result<()> {
+        match self {
+            Source::MemTable(cursor) => cursor.seek_for_prev(target),
+            Source::Table { iter, .. } => iter.seek_for_prev(target)?,
         }
+        Ok(())
+    }
 
-        // Collect from immutable MemTables
-        {
-            let immutable = self.db.immutable_memtables.read();
-            for memtable in immutable.iter() {
-                all_keys.extend(memtable.keys());
+    fn next(&mut self) -> Result<()> {
+        match self {
+            Source::MemTable(cursor) => cursor.next(),
+            Source::Table { iter, .. } => {
+                iter.advance()?;
             }
         }
+        Ok(())
+    }
 
-        // Collect from SSTables
-        {
-            let sstables = self.db.sstables.read();
-            for level_tables in sstables.iter() {
-                for table in level_tables.iter() {
-                    all_keys.extend(table.keys()?);
-                }
+    fn prev(&mut self) -> Result<()> {
+        match self {
+            Source::MemTable(cursor) => cursor.prev(),
+            Source::Table { iter, .. } => {
+                iter.prev()?;
             }
         }
+        Ok(())
+    }
 
-        // Filter by range and convert to Vec
-        self.keys = all_keys
-            .into_iter()
-            .filter(|key| {
-                let after_start = start.as_ref().is_none_or(|s| key >= s);
-                let before_end = end.as_ref().is_none_or(|e| key < e);
-                after_start && before_end
-            })
-            .collect();
+    /// Moves to the first key `> key`
+    fn seek_after(&mut self, key: &[u8]) -> Result<()> {
+        self.seek(key)?;
+        if self.key() == Some(key) {
+            self.next()?;
+        }
+        Ok(())
+    }
 
+    /// Moves to the last key `< key`
+    fn seek_before(&mut self, key: &[u8]) -> Result<()> {
+        self.seek_for_prev(key)?;
+        if self.key() == Some(key) {
+            self.prev()?;
+        }
         Ok(())
     }
 
-    /// Loads the current key-value pair from the database.
-    fn load_current(&mut self) -> Result<()> {
-        if self.position >= self.keys.len() {
-            self.current = None;
-            return Ok(());
+    fn key(&self) -> Option<&[u8]> {
+        match self {
+            Source::MemTable(cursor) => cursor.key(),
+            Source::Table { iter, .. } => iter.valid().then(|| iter.key()),
         }
+    }
+}
+
+/// A positioned source in the merge heap
+struct HeapEntry {
+    key: Vec<u8>,
+    source: usize,
+    direction: Direction,
+    comparator: Arc<dyn Comparator>,
+}
+
+impl Ord for HeapEntry {
+    /// The heap pops the greatest entry: the smallest key going forward, the
+    /// largest going backward, and for equal keys the newest source.
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        let by_key = match self.direction {
+            Direction::Forward => self.comparator.compare(&other.key, &self.key),
+            Direction::Backward => self.comparator.compare(&self.key, &other.key),
+        };
+        by_key.then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
 
-        let key = &self.keys[self.position];
+impl DBIterator {
+    /// Creates a new iterator starting from the beginning.
+    pub(crate) fn new(db: Arc<DB>, sequence: u64) -> Result<Self> {
+        Self::new_range(db, sequence, &ReadOptions::default(), None, None)
+    }
 
-        // Get the value using the snapshot sequence
-        if let Some(value) = self.db.get_at_sequence(key, self.sequence)? {
-            self.current = Some((key.clone(), value));
-        } else {
-            // Key was deleted or doesn't exist at this sequence, skip it
-            self.next();
+    /// Creates a new iterator with a range.
+    pub(crate) fn new_range(
+        db: Arc<DB>,
+        sequence: u64,
+        read_options: &ReadOptions,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Self> {
+        let (threshold, prefix_extractor) = {
+            let options = db.options.read();
+            (options.slow_iterator_threshold_ms, options.prefix_extractor)
+        };
+        let log_db = Arc::clone(&db);
+        let mut slow_log = SlowOperationLog::start(
+            &log_db.info_log,
+            "iterator creation",
+            start.unwrap_or_default(),
+            0,
+            threshold,
+        );
+
+        let prefix_deletes = db.prefix_tombstones.get().visible_at(sequence);
+        let sources = collect_sources(&db, sequence, &prefix_deletes, read_options, Vec::new());
+        if let Some(slow_log) = slow_log.as_mut() {
+            slow_log.set_entries(sources.len());
         }
 
-        Ok(())
+        let mut iter = Self {
+            db,
+            sequence,
+            read_options: read_options.clone(),
+            sources,
+            heap: BinaryHeap::new(),
+            direction: Direction::Forward,
+            current: None,
+            lower_bound: start.map(<[u8]>::to_vec),
+            upper_bound: end.map(<[u8]>::to_vec),
+            prefix_extractor,
+            prefix: None,
+            error: None,
+            held_bytes: 0,
+            resume: None,
+            prefix_deletes,
+        };
+
+        // Position at the first key, within the start key's prefix in prefix mode
+        match start {
+            Some(start) if iter.read_options.prefix_same_as_start => iter.seek(start),
+            _ => iter.seek_to_first(),
+        }
+        iter.status()?;
+
+        Ok(iter)
     }
 
     /// Returns true if the iterator is positioned at a valid entry.
@@ -163,6 +339,14 @@ impl DBIterator {
         self.current.is_some()
     }
 
+    /// Returns the error that made the iterator invalid, if any.
+    pub fn status(&self) -> Result<()> {
+        match self.error {
+            Some(ref e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
     /// Returns the key at the current position.
     ///
     /// # Panics
@@ -182,50 +366,460 @@ impl DBIterator {
     }
 
     /// Moves to the next entry in forward direction.
+    ///
+    /// On a tailing iterator that has run past the last entry, this looks
+    /// for entries written since and moves to the first one after the last
+    /// key returned.
     pub fn next(&mut self) {
-        self.position += 1;
-        let _ = self.load_current();
+        self.run(|iter| {
+            let Some((key, _)) = iter.current.take() else {
+                return match iter.resume.clone() {
+                    Some(resume) if iter.read_options.tailing => iter.tail(resume),
+                    _ => Ok(()),
+                };
+            };
+            if iter.read_options.tailing {
+                iter.resume = Some(Resume::After(key.clone()));
+            }
+            if iter.direction == Direction::Backward {
+                iter.reposition(Direction::Forward, |source| source.seek_after(&key))?;
+            }
+            iter.find_entry()
+        });
     }
 
     /// Moves to the previous entry in backward direction.
     pub fn prev(&mut self) {
-        if self.position > 0 {
-            self.position -= 1;
-            let _ = self.load_current();
-        } else {
-            self.current = None;
-        }
+        self.run(|iter| {
+            iter.resume = None;
+            let Some((key, _)) = iter.current.take() else {
+                return Ok(());
+            };
+            if iter.direction == Direction::Forward {
+                iter.reposition(Direction::Backward, |source| source.seek_before(&key))?;
+            }
+            iter.find_entry()
+        });
     }
 
     /// Seeks to the first key that is greater than or equal to the target.
+    ///
+    /// A tailing iterator picks up new writes first.
     pub fn seek(&mut self, target: &[u8]) {
-        // Binary search for the target key
-        match self.keys.binary_search_by(|k| k.as_slice().cmp(target)) {
-            Ok(pos) => {
-                self.position = pos;
+        let threshold = self.db.options.read().slow_iterator_threshold_ms;
+        let log_db = Arc::clone(&self.db);
+        let _slow_log =
+            SlowOperationLog::start(&log_db.info_log, "iterator seek", target, 1, threshold);
+
+        self.run(|iter| {
+            if iter.read_options.tailing {
+                return iter.tail(Resume::From(target.to_vec()));
             }
-            Err(pos) => {
-                self.position = pos;
+            iter.set_prefix(Some(target));
+            let target = match iter.lower_bound {
+                Some(ref lower) if iter.compare(lower, target) == CmpOrdering::Greater => {
+                    lower.clone()
+                }
+                _ => target.to_vec(),
+            };
+            iter.reposition(Direction::Forward, |source| source.seek(&target))?;
+            iter.find_entry()
+        });
+    }
+
+    /// Seeks to the last key that is less than or equal to the target.
+    pub fn seek_for_prev(&mut self, target: &[u8]) {
+        let threshold = self.db.options.read().slow_iterator_threshold_ms;
+        let log_db = Arc::clone(&self.db);
+        let _slow_log = SlowOperationLog::start(
+            &log_db.info_log,
+            "iterator seek_for_prev",
+            target,
+            1,
+            threshold,
+        );
+
+        self.run(|iter| {
+            iter.resume = None;
+            iter.set_prefix(Some(target));
+            match iter.upper_bound.clone() {
+                Some(upper) if iter.compare(&upper, target) != CmpOrdering::Greater => {
+                    iter.reposition(Direction::Backward, |source| source.seek_before(&upper))?
+                }
+                _ => iter.reposition(Direction::Backward, |source| source.seek_for_prev(target))?,
             }
-        }
-        let _ = self.load_current();
+            iter.find_entry()
+        });
     }
 
     /// Seeks to the first key in the database.
+    ///
+    /// A tailing iterator picks up new writes first.
     pub fn seek_to_first(&mut self) {
-        self.position = 0;
-        let _ = self.load_current();
+        self.run(|iter| {
+            if iter.read_options.tailing {
+                return iter.tail(Resume::Start);
+            }
+            iter.set_prefix(None);
+            match iter.lower_bound.clone() {
+                Some(lower) => iter.reposition(Direction::Forward, |source| source.seek(&lower))?,
+                None => iter.reposition(Direction::Forward, Source::seek_to_first)?,
+            }
+            iter.find_entry()
+        });
     }
 
     /// Seeks to the last key in the database.
     pub fn seek_to_last(&mut self) {
-        if !self.keys.is_empty() {
-            self.position = self.keys.len() - 1;
-            let _ = self.load_current();
-        } else {
+        self.run(|iter| {
+            iter.resume = None;
+            iter.set_prefix(None);
+            match iter.upper_bound.clone() {
+                Some(upper) => {
+                    iter.reposition(Direction::Backward, |source| source.seek_before(&upper))?
+                }
+                None => iter.reposition(Direction::Backward, Source::seek_to_last)?,
+            }
+            iter.find_entry()
+        });
+    }
+
+    /// Runs a move, invalidating the iterator if it fails
+    fn run(&mut self, step: impl FnOnce(&mut Self) -> Result<()>) {
+        self.error = None;
+        if let Err(e) = step(self) {
             self.current = None;
+            self.heap.clear();
+            self.error = Some(e);
+        }
+        self.update_memory();
+    }
+
+    /// Re-binds the iterator to the latest state of the database, keeping
+    /// its position.
+    ///
+    /// Writes made since the iterator was created (or last refreshed) become
+    /// visible, and MemTables and SSTables that have been flushed or
+    /// compacted away are released. The iterator stays on its current key,
+    /// or moves on to the next key in its direction if that key has since
+    /// been deleted. An invalid iterator stays invalid.
+    ///
+    /// Cursors over SSTables that are still live are reused, so a refresh is
+    /// much cheaper than creating a new iterator.
+    pub fn refresh(&mut self) -> Result<()> {
+        self.run(|iter| {
+            iter.refresh_sources();
+            let Some((key, _)) = iter.current.take() else {
+                iter.heap.clear();
+                return Ok(());
+            };
+            match iter.direction {
+                Direction::Forward => {
+                    iter.reposition(Direction::Forward, |source| source.seek(&key))?
+                }
+                Direction::Backward => {
+                    iter.reposition(Direction::Backward, |source| source.seek_for_prev(&key))?
+                }
+            }
+            iter.find_entry()
+        });
+        self.status()
+    }
+
+    /// Recreates the sources at the latest sequence number
+    fn refresh_sources(&mut self) {
+        self.sequence = self.db.sequence.load(Ordering::SeqCst);
+        self.prefix_deletes = self.db.prefix_tombstones.get().visible_at(self.sequence);
+        let sources = std::mem::take(&mut self.sources);
+        self.sources = collect_sources(
+            &self.db,
+            self.sequence,
+            &self.prefix_deletes,
+            &self.read_options,
+            sources,
+        );
+        // SSTables compacted away since are no longer pinned
+        self.db.purge_obsolete_files();
+    }
+
+    /// Picks up new writes and moves forward to `resume`
+    fn tail(&mut self, resume: Resume) -> Result<()> {
+        self.refresh_sources();
+
+        match &resume {
+            Resume::Start => {
+                self.set_prefix(None);
+                match self.lower_bound.clone() {
+                    Some(lower) => {
+                        self.reposition(Direction::Forward, |source| source.seek(&lower))?
+                    }
+                    None => self.reposition(Direction::Forward, Source::seek_to_first)?,
+                }
+            }
+            Resume::From(target) => {
+                self.set_prefix(Some(target));
+                let target = match self.lower_bound {
+                    Some(ref lower) if self.compare(lower, target) == CmpOrdering::Greater => {
+                        lower.clone()
+                    }
+                    _ => target.clone(),
+                };
+                self.reposition(Direction::Forward, |source| source.seek(&target))?
+            }
+            Resume::After(key) => {
+                self.reposition(Direction::Forward, |source| source.seek_after(key))?
+            }
+        }
+        self.resume = Some(resume);
+        self.find_entry()
+    }
+
+    /// Confines the iterator to the prefix of `target` in prefix mode, or
+    /// lifts the confinement
+    fn set_prefix(&mut self, target: Option<&[u8]>) {
+        self.prefix = target
+            .filter(|_| self.read_options.prefix_same_as_start)
+            .and_then(|target| self.prefix_extractor.prefix(target))
+            .map(<[u8]>::to_vec);
+    }
+
+    /// Moves every source with `position` and rebuilds the heap for `direction`.
+    /// Tables ruled out for the current prefix are left out.
+    fn reposition(
+        &mut self,
+        direction: Direction,
+        mut position: impl FnMut(&mut Source) -> Result<()>,
+    ) -> Result<()> {
+        self.direction = direction;
+        self.current = None;
+        self.heap.clear();
+        let mut skipped = 0;
+        for index in 0..self.sources.len() {
+            if let (Some(prefix), Source::Table { table, .. }) =
+                (&self.prefix, &self.sources[index])
+            {
+                if !table.may_contain_prefix(&self.prefix_extractor, prefix) {
+                    skipped += 1;
+                    continue;
+                }
+            }
+            position(&mut self.sources[index])?;
+            self.push(index);
+        }
+        if skipped > 0 {
+            perf_context::update(|perf| perf.bloom_filter_skips += skipped);
+            if let Some(ref stats) = self.db.statistics {
+                stats.record_tick(Ticker::BloomFilterUseful, skipped);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds source `index` to the heap if it is on an entry
+    fn push(&mut self, index: usize) {
+        if let Some(key) = self.sources[index].key() {
+            self.heap.push(HeapEntry {
+                key: key.to_vec(),
+                source: index,
+                direction: self.direction,
+                comparator: Arc::clone(&self.db.comparator),
+            });
+        }
+    }
+
+    /// Moves source `index` one entry in the current direction
+    fn step(&mut self, index: usize) -> Result<()> {
+        match self.direction {
+            Direction::Forward => self.sources[index].next()?,
+            Direction::Backward => self.sources[index].prev()?,
+        }
+        self.push(index);
+        Ok(())
+    }
+
+    /// Takes the next key in the current direction off the heap, skipping
+    /// deleted keys. The newest source decides each key's value, unless it
+    /// only appends to the older ones; every source on the key is moved
+    /// past it.
+    fn find_entry(&mut self) -> Result<()> {
+        while let Some(top) = self.heap.pop() {
+            if !self.in_range(&top.key) {
+                self.heap.clear();
+                break;
+            }
+
+            let mut version = self.version_at(top.source);
+            // Only a MemTable holds appends, already applied to its older
+            // versions of the key when they complete the value
+            let appended = match (&self.sources[top.source], &version) {
+                (Source::MemTable(_), Version::Appended(_)) => true,
+                (Source::MemTable(cursor), Version::Value(value)) => {
+                    BlobReference::decode(value).is_some() && cursor.appended()
+                }
+                _ => false,
+            };
+            self.step(top.source)?;
+            while self.heap.peek().is_some_and(|entry| entry.key == top.key) {
+                let entry = self.heap.pop().expect("peeked above");
+                // Appends take the older sources' version into account
+                if !version.is_complete() {
+                    version = version.over(self.version_at(entry.source));
+                }
+                self.step(entry.source)?;
+            }
+
+            if let Some(value) = version.into_value() {
+                if appended {
+                    blob::check_appended(&top.key, &value)?;
+                }
+                self.current = Some((top.key, self.db.resolve_blob(value)?));
+                return Ok(());
+            }
+        }
+        self.current = None;
+        Ok(())
+    }
+
+    /// Version of the key source `index` is on, prefix deletions included.
+    ///
+    /// SSTables store deletions as empty values.
+    fn version_at(&self, index: usize) -> Version {
+        match &self.sources[index] {
+            Source::MemTable(cursor) => cursor.version().cloned().expect("source is on an entry"),
+            Source::Table { table, iter } => {
+                let value = iter.value();
+                if value.is_empty()
+                    || self.prefix_deletes.covers_file(iter.key(), table.file_number())
+                {
+                    Version::Deleted
+                } else {
+                    Version::Value(value.to_vec())
+                }
+            }
+        }
+    }
+
+    /// Whether `key` is within the bounds on the side the iterator moves to,
+    /// and has the current prefix
+    fn in_range(&self, key: &[u8]) -> bool {
+        if self.prefix.as_deref().is_some_and(|prefix| !key.starts_with(prefix)) {
+            return false;
+        }
+        match self.direction {
+            Direction::Forward => self
+                .upper_bound
+                .as_deref()
+                .is_none_or(|upper| self.compare(key, upper) == CmpOrdering::Less),
+            Direction::Backward => self
+                .lower_bound
+                .as_deref()
+                .is_none_or(|lower| self.compare(key, lower) != CmpOrdering::Less),
+        }
+    }
+
+    /// Compares two keys in the database's order
+    fn compare(&self, a: &[u8], b: &[u8]) -> CmpOrdering {
+        self.db.comparator.compare(a, b)
+    }
+
+    /// Updates this iterator's share of `DB::memory_usage`
+    fn update_memory(&mut self) {
+        let held = self.current.as_ref().map_or(0, |(key, value)| key.len() + value.len())
+            + self.heap.iter().map(|entry| entry.key.len()).sum::<usize>();
+        self.db.iterator_memory.fetch_add(held, Ordering::Relaxed);
+        self.db.iterator_memory.fetch_sub(self.held_bytes, Ordering::Relaxed);
+        self.held_bytes = held;
+    }
+}
+
+/// Creates cursors over the MemTables and SSTables of `db`, newest first.
+///
+/// The cursors of SSTables in `previous` that are still live are reused;
+/// MemTable cursors are always recreated at `sequence`.
+fn collect_sources(
+    db: &DB,
+    sequence: u64,
+    prefix_deletes: &PrefixDeletes,
+    read_options: &ReadOptions,
+    previous: Vec<Source>,
+) -> Vec<Source> {
+    let mut reusable: HashMap<*const SSTableReader, Box<SSTableIterator>> = previous
+        .into_iter()
+        .filter_map(|source| match source {
+            Source::Table { table, iter } => Some((Arc::as_ptr(&table), iter)),
+            Source::MemTable(_) => None,
+        })
+        .collect();
+
+    // Take the MemTables before the SSTables: a MemTable flushed in
+    // between is then seen twice, which is harmless, rather than not at all
+    let cursor = |memtable: &MemTable| memtable.cursor(sequence, prefix_deletes.clone());
+    let mut sources = vec![Source::MemTable(cursor(&db.memtable.read()))];
+    for memtable in db.immutable_memtables.read().iter().rev() {
+        sources.push(Source::MemTable(cursor(memtable)));
+    }
+    for level_tables in db.sstables.read().iter() {
+        for table in level_tables {
+            let iter = reusable
+                .remove(&Arc::as_ptr(table))
+                .unwrap_or_else(|| Box::new(table.iter_with_options(read_options)));
+            sources.push(Source::Table { table: Arc::clone(table), iter });
+        }
+    }
+    sources
+}
+
+/// Returns the first key of `db` in `[start, end]` at `sequence`, deleted
+/// keys included, for finding the newest version of a key at or before a
+/// user [`timestamp`](crate::timestamp).
+pub(crate) fn first_key_in(
+    db: &DB,
+    start: &[u8],
+    end: &[u8],
+    sequence: u64,
+) -> Result<Option<Vec<u8>>> {
+    let comparator = &*db.comparator;
+    let prefix_deletes = db.prefix_tombstones.get().visible_at(sequence);
+
+    // As in `collect_sources`, but only with the SSTables overlapping the range
+    let cursor = |memtable: &MemTable| memtable.cursor(sequence, prefix_deletes.clone());
+    let mut sources = vec![Source::MemTable(cursor(&db.memtable.read()))];
+    for memtable in db.immutable_memtables.read().iter().rev() {
+        sources.push(Source::MemTable(cursor(memtable)));
+    }
+    for table in db.sstables.read().iter().flatten() {
+        let overlaps = table.key_range()?.is_some_and(|(smallest, largest)| {
+            comparator.compare(smallest, end) != CmpOrdering::Greater
+                && comparator.compare(largest, start) != CmpOrdering::Less
+        });
+        if overlaps {
+            sources.push(Source::Table { table: Arc::clone(table), iter: Box::new(table.iter()) });
+        }
+    }
+
+    let mut first: Option<Vec<u8>> = None;
+    for mut source in sources {
+        source.seek(start)?;
+        if let Some(key) = source.key() {
+            if comparator.compare(key, end) != CmpOrdering::Greater
+                && first
+                    .as_deref()
+                    .is_none_or(|first| comparator.compare(key, first) == CmpOrdering::Less)
+            {
+                first = Some(key.to_vec());
+            }
         }
     }
+    Ok(first)
+}
+
+impl Drop for DBIterator {
+    fn drop(&mut self) {
+        self.db.iterator_memory.fetch_sub(self.held_bytes, Ordering::Relaxed);
+        // Release the SSTables so files compacted away can be deleted
+        self.sources.clear();
+        self.db.purge_obsolete_files();
+    }
 }
 
 impl DB {
@@ -250,6 +844,9 @@ impl DB {
     /// # }
     /// ```
     pub fn iter(self: &Arc<Self>) -> DBIterator {
+        if let Err(e) = self.maybe_catch_up() {
+            log::warn!("Failed to catch up with the primary: {}", e);
+        }
         let seq = self.sequence.load(std::sync::atomic::Ordering::SeqCst);
         DBIterator::new(Arc::clone(self), seq).unwrap()
     }
@@ -281,15 +878,75 @@ impl DB {
     /// # }
     /// ```
     pub fn scan(self: &Arc<Self>, start: Option<&[u8]>, end: Option<&[u8]>) -> Result<DBIterator> {
+        self.scan_with_options(&ReadOptions::default(), start, end)
+    }
+
+    /// Creates an iterator over a range of keys with the given read options.
+    ///
+    /// See [`scan`](Self::scan) for the meaning of `start` and `end`.
+    pub fn scan_with_options(
+        self: &Arc<Self>,
+        read_options: &ReadOptions,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<DBIterator> {
+        self.maybe_catch_up()?;
         let seq = self.sequence.load(std::sync::atomic::Ordering::SeqCst);
-        DBIterator::new_range(Arc::clone(self), seq, start, end)
+        DBIterator::new_range(Arc::clone(self), seq, read_options, start, end)
+    }
+
+    /// Creates an iterator over the keys that start with `prefix`.
+    ///
+    /// When `prefix` is a whole prefix as returned by the configured
+    /// [`PrefixExtractor`], SSTables whose Bloom filter rules it out are not
+    /// read (see [`ReadOptions::prefix_same_as_start`]); any other `prefix`
+    /// is scanned as a plain key range.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use aidb::config::PrefixExtractor;
+    /// use aidb::{DB, Options};
+    /// use std::sync::Arc;
+    ///
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// let options = Options::default()
+    ///     .prefix_extractor(PrefixExtractor::Delimited { delimiter: b':', count: 2 });
+    /// let db = Arc::new(DB::open("./data", options)?);
+    ///
+    /// let mut iter = db.prefix_scan(b"tenant:123:")?;
+    /// while iter.valid() {
+    ///     println!("{:?} => {:?}", iter.key(), iter.value());
+    ///     iter.next();
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prefix_scan(self: &Arc<Self>, prefix: &[u8]) -> Result<DBIterator> {
+        let end = prefix_successor(prefix);
+        self.scan_with_options(
+            &ReadOptions::new().prefix_same_as_start(true),
+            Some(prefix),
+            end.as_deref(),
+        )
     }
 }
 
+/// Returns the smallest key greater than every key starting with `prefix`,
+/// or `None` if there is none (the prefix is empty or all `0xff`)
+pub(crate) fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&byte| byte != 0xff)?;
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+    Some(end)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::PrefixExtractor;
     use crate::Options;
+    use std::collections::BTreeMap;
     use tempfile::TempDir;
 
     #[test]
@@ -423,4 +1080,300 @@ mod tests {
         let iter = db.iter();
         assert!(!iter.valid());
     }
+
+    /// Builds a database whose keys are spread over the MemTable, an
+    /// immutable MemTable and SSTables on two levels, with overwrites and
+    /// deletes across them. Returns it with the expected contents.
+    fn layered_db(dir: &TempDir) -> (Arc<DB>, BTreeMap<Vec<u8>, Vec<u8>>) {
+        let options = Options::default().block_size(128).level0_compaction_threshold(100);
+        let db = Arc::new(DB::open(dir.path(), options).unwrap());
+        let mut expected = BTreeMap::new();
+
+        for round in 0..5u32 {
+            for i in (round..200).step_by(3) {
+                let key = format!("key{:03}", i).into_bytes();
+                if (i + round) % 7 == 0 {
+                    db.delete(&key).unwrap();
+                    expected.remove(&key);
+                } else {
+                    let value = format!("value{}-{}", i, round).into_bytes();
+                    db.put(&key, &value).unwrap();
+                    expected.insert(key, value);
+                }
+            }
+            match round {
+                0 => {
+                    db.flush().unwrap();
+                    let tables = std::mem::take(&mut db.sstables.write()[0]);
+                    db.sstables.write()[1] = tables;
+                }
+                1 | 2 => db.flush().unwrap(),
+                3 => db.freeze_memtable().unwrap(),
+                _ => {}
+            }
+        }
+        assert!(!db.immutable_memtables.read().is_empty());
+        (db, expected)
+    }
+
+    fn collect_forward(iter: &mut DBIterator) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut entries = Vec::new();
+        while iter.valid() {
+            entries.push((iter.key().to_vec(), iter.value().to_vec()));
+            iter.next();
+        }
+        entries
+    }
+
+    #[test]
+    fn test_iterator_merges_all_sources() {
+        let tmp_dir = TempDir::new().unwrap();
+        let (db, expected) = layered_db(&tmp_dir);
+        let expected: Vec<_> = expected.into_iter().collect();
+
+        let mut iter = db.iter();
+        assert_eq!(collect_forward(&mut iter), expected);
+        iter.status().unwrap();
+
+        iter.seek_to_last();
+        let mut backward = Vec::new();
+        while iter.valid() {
+            backward.push((iter.key().to_vec(), iter.value().to_vec()));
+            iter.prev();
+        }
+        backward.reverse();
+        assert_eq!(backward, expected);
+    }
+
+    #[test]
+    fn test_iterator_seek_and_seek_for_prev() {
+        let tmp_dir = TempDir::new().unwrap();
+        let (db, expected) = layered_db(&tmp_dir);
+        let mut iter = db.iter();
+
+        for i in 0..=200 {
+            let target = format!("key{:03}", i).into_bytes();
+
+            iter.seek(&target);
+            let next = expected.range(target.clone()..).next();
+            assert_eq!(iter.valid().then(|| iter.key().to_vec()), next.map(|(k, _)| k.clone()));
+
+            iter.seek_for_prev(&target);
+            let prev = expected.range(..=target.clone()).next_back();
+            assert_eq!(iter.valid().then(|| iter.key().to_vec()), prev.map(|(k, _)| k.clone()));
+            if let Some((_, value)) = prev {
+                assert_eq!(iter.value(), value.as_slice());
+            }
+        }
+    }
+
+    #[test]
+    fn test_iterator_changes_direction() {
+        let tmp_dir = TempDir::new().unwrap();
+        let (db, expected) = layered_db(&tmp_dir);
+        let keys: Vec<_> = expected.keys().cloned().collect();
+        let mut iter = db.iter();
+
+        // Walk forward two steps and back one, over the whole range
+        iter.seek_to_first();
+        let mut position = 0;
+        while position + 2 < keys.len() {
+            iter.next();
+            iter.next();
+            iter.prev();
+            position += 1;
+            assert_eq!(iter.key(), keys[position].as_slice());
+        }
+    }
+
+    #[test]
+    fn test_iterator_ignores_later_writes() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(tmp_dir.path(), Options::default()).unwrap());
+        db.put(b"a", b"1").unwrap();
+        db.put(b"c", b"3").unwrap();
+
+        let mut iter = db.iter();
+        db.put(b"b", b"2").unwrap();
+        db.put(b"c", b"4").unwrap();
+        db.delete(b"a").unwrap();
+        db.flush().unwrap();
+
+        assert_eq!(
+            collect_forward(&mut iter),
+            vec![(b"a".to_vec(), b"1".to_vec()), (b"c".to_vec(), b"3".to_vec())]
+        );
+        iter.seek_for_prev(b"b");
+        assert_eq!(iter.key(), b"a");
+    }
+
+    #[test]
+    fn test_scan_range_backward() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(tmp_dir.path(), Options::default()).unwrap());
+        for key in [b"a", b"b", b"c", b"d", b"e"] {
+            db.put(key, b"v").unwrap();
+        }
+
+        let mut iter = db.scan(Some(b"b"), Some(b"d")).unwrap();
+        iter.seek_to_last();
+        assert_eq!(iter.key(), b"c");
+        iter.prev();
+        assert_eq!(iter.key(), b"b");
+        iter.prev();
+        assert!(!iter.valid());
+
+        // Seeks are clamped to the range
+        iter.seek(b"a");
+        assert_eq!(iter.key(), b"b");
+        iter.seek_for_prev(b"z");
+        assert_eq!(iter.key(), b"c");
+        iter.seek(b"d");
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_prefix_scan() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = Options::default()
+            .prefix_extractor(PrefixExtractor::Delimited { delimiter: b':', count: 2 })
+            .level0_compaction_threshold(100);
+        let db = Arc::new(DB::open(tmp_dir.path(), options).unwrap());
+
+        // One table per tenant, plus unflushed writes
+        for tenant in 0..4 {
+            for i in 0..20 {
+                db.put(format!("tenant:{}:{:02}", tenant, i).as_bytes(), b"v").unwrap();
+            }
+            db.flush().unwrap();
+        }
+        db.put(b"tenant:1:99", b"v").unwrap();
+        db.put(b"tenant:10:00", b"v").unwrap();
+
+        let before = perf_context::get();
+        let mut iter = db.prefix_scan(b"tenant:1:").unwrap();
+        let perf = perf_context::get().since(&before);
+        let mut keys = Vec::new();
+        while iter.valid() {
+            keys.push(String::from_utf8(iter.key().to_vec()).unwrap());
+            iter.next();
+        }
+        let mut expected: Vec<_> = (0..20).map(|i| format!("tenant:1:{:02}", i)).collect();
+        expected.push("tenant:1:99".to_string());
+        assert_eq!(keys, expected);
+        assert!(perf.bloom_filter_skips >= 2, "{}", perf);
+
+        // Backward iteration stops at the prefix as well
+        iter.seek_for_prev(b"tenant:1:05");
+        iter.prev();
+        iter.prev();
+        assert_eq!(iter.key(), b"tenant:1:03");
+    }
+
+    #[test]
+    fn test_prefix_same_as_start() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = Options::default().prefix_extractor(PrefixExtractor::Fixed(2));
+        let db = Arc::new(DB::open(tmp_dir.path(), options).unwrap());
+        for key in [&b"aa1"[..], b"aa2", b"ab1", b"b"] {
+            db.put(key, b"v").unwrap();
+        }
+        db.flush().unwrap();
+
+        let read_options = ReadOptions::new().prefix_same_as_start(true);
+        let mut iter = db.scan_with_options(&read_options, None, None).unwrap();
+        assert_eq!(iter.key(), b"aa1");
+
+        iter.seek(b"aa2");
+        assert_eq!(iter.key(), b"aa2");
+        iter.next();
+        assert!(!iter.valid());
+
+        // Targets without a prefix and seek_to_first iterate in total order
+        iter.seek(b"a");
+        assert_eq!(collect_forward(&mut iter).len(), 4);
+        iter.seek(b"ab");
+        iter.seek_to_first();
+        assert_eq!(collect_forward(&mut iter).len(), 4);
+    }
+
+    #[test]
+    fn test_tailing_iterator() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(tmp_dir.path(), Options::default()).unwrap());
+        db.put(b"job1", b"a").unwrap();
+        db.flush().unwrap();
+        db.put(b"job2", b"b").unwrap();
+
+        let read_options = ReadOptions::new().tailing(true);
+        let mut tailing = db.scan_with_options(&read_options, None, None).unwrap();
+        let mut plain = db.iter();
+        assert_eq!(collect_forward(&mut tailing).len(), 2);
+        collect_forward(&mut plain);
+
+        // Nothing new yet
+        tailing.next();
+        assert!(!tailing.valid());
+
+        // New writes in the MemTable, an SSTable, and a key before the last one
+        db.put(b"job3", b"c").unwrap();
+        db.flush().unwrap();
+        db.put(b"job0", b"z").unwrap();
+        db.put(b"job4", b"d").unwrap();
+        db.delete(b"job3").unwrap();
+        db.put(b"job5", b"e").unwrap();
+
+        tailing.next();
+        assert_eq!(
+            collect_forward(&mut tailing),
+            vec![(b"job4".to_vec(), b"d".to_vec()), (b"job5".to_vec(), b"e".to_vec())]
+        );
+        tailing.status().unwrap();
+
+        plain.next();
+        assert!(!plain.valid());
+
+        // A seek sees everything written so far
+        tailing.seek_to_first();
+        assert_eq!(tailing.key(), b"job0");
+    }
+
+    #[test]
+    fn test_iterator_refresh() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = Options::default().level0_compaction_threshold(2);
+        let db = Arc::new(DB::open(tmp_dir.path(), options).unwrap());
+        for key in [b"a", b"b", b"c", b"d"] {
+            db.put(key, b"1").unwrap();
+        }
+        db.flush().unwrap();
+        let table = Arc::downgrade(&db.sstables.read()[0][0]);
+
+        let mut iter = db.iter();
+        iter.next();
+        assert_eq!(iter.key(), b"b");
+
+        // The second flush compacts both tables into Level 1
+        db.put(b"b", b"2").unwrap();
+        db.put(b"bb", b"2").unwrap();
+        db.flush().unwrap();
+        db.maybe_trigger_compaction().unwrap();
+        assert!(db.sstables.read()[0].is_empty());
+        db.put(b"e", b"2").unwrap();
+
+        assert!(table.upgrade().is_some());
+        iter.refresh().unwrap();
+        assert!(table.upgrade().is_none());
+        assert_eq!((iter.key(), iter.value()), (&b"b"[..], &b"2"[..]));
+        iter.next();
+        assert_eq!(iter.key(), b"bb");
+
+        // A deleted current key moves the iterator on in its direction
+        iter.seek_for_prev(b"c");
+        db.delete(b"c").unwrap();
+        iter.refresh().unwrap();
+        assert_eq!(iter.key(), b"bb");
+        iter.seek_to_last();
+        assert_eq!(iter.key(), b"e");
+    }
 }
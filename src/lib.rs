@@ -44,35 +44,184 @@
 #![warn(rust_2018_idioms)]
 
 // Module declarations
+#[cfg(feature = "tokio")]
+pub mod r#async;
+pub mod backup;
+pub mod blob;
+pub mod bucket;
+pub mod bulk;
 pub mod cache;
+pub mod changefeed;
+pub mod checkpoint;
 pub mod compaction;
+pub mod comparator;
 pub mod config;
+pub mod env;
 pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod filter;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod info_log;
+pub mod ingest;
 pub mod iterator;
+pub mod listener;
+pub mod lsm_state;
 pub mod memtable;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod migrate;
+pub mod pagination;
+pub mod perf_context;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod rate_limiter;
+pub mod record_auth;
+pub mod repair;
+pub mod secondary;
+#[cfg(any(feature = "server-resp", feature = "server-grpc"))]
+pub mod server;
+#[cfg(feature = "cli")]
+pub mod shell;
+#[cfg(feature = "testing")]
+pub mod simulation;
 pub mod snapshot;
+pub mod sst_file_manager;
 pub mod sstable;
+pub mod statistics;
+pub mod timestamp;
+pub mod transaction_log;
+pub mod typed;
+pub mod verify;
 pub mod wal;
+pub mod watch;
 pub mod write_batch;
+pub mod write_batch_with_index;
+pub mod write_buffer_manager;
+
+mod prefix_delete;
+mod sequence_time;
+mod util;
+mod write_queue;
 
 // Re-exports
-pub use config::Options;
+pub use config::{Options, ReadOptions};
 pub use error::{Error, Result};
 pub use iterator::DBIterator;
+pub use listener::EventListener;
+pub use lsm_state::LsmState;
+pub use memtable::ValueType;
 pub use snapshot::Snapshot;
+pub use statistics::Statistics;
 pub use write_batch::WriteBatch;
+pub use write_batch_with_index::WriteBatchWithIndex;
 
-use cache::BlockCache;
+use crate::util::Instant;
+use cache::{BlockCache, TableCache};
 use compaction::{CompactionJob, CompactionPicker, VersionEdit, VersionSet};
-use memtable::MemTable;
-use parking_lot::RwLock;
-use sstable::{SSTableBuilder, SSTableReader};
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use info_log::InfoLog;
+use memtable::{MemTable, Version};
+use parking_lot::{Mutex, RwLock};
+use perf_context::SlowOperationLog;
+use record_auth::RecordAuthenticator;
+use sstable::{SSTableBuilder, SSTableReader, TableMetadata};
+use statistics::{HistogramType, StopWatch, Ticker};
+use std::cmp::Ordering as CmpOrdering;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 use wal::WAL;
 
+/// Minimum time between automatic attempts to resume after the disk filled up.
+const NO_SPACE_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// File in the database directory the writing instance holds locked.
+pub const LOCK_FILE_NAME: &str = "LOCK";
+
+/// How a database is opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenMode {
+    /// [`DB::open`]
+    ReadWrite,
+    /// [`DB::open_read_only`]
+    ReadOnly,
+    /// [`DB::open_as_secondary`]
+    Secondary,
+}
+
+/// The error that put the database into read-only mode.
+struct BackgroundErrorState {
+    /// Description of the original error
+    message: String,
+    /// The disk was full. Writes retry `resume()` automatically, at most once
+    /// per `NO_SPACE_RETRY_INTERVAL`, so the database recovers on its own
+    /// once space is freed.
+    no_space: bool,
+    /// Time of the last automatic resume attempt, in microseconds since
+    /// the epoch (see [`Env::now_micros`](env::Env::now_micros))
+    last_retry: u64,
+}
+
+/// A MemTable written to a new SSTable that is not yet part of Level 0.
+struct FlushedTable {
+    reader: Arc<SSTableReader>,
+    info: listener::FlushJobInfo,
+}
+
+/// An SSTable removed from the database whose file is deleted once no
+/// iterator or read holds its reader any more.
+struct ObsoleteFile {
+    file_number: u64,
+    path: PathBuf,
+    table: Weak<SSTableReader>,
+}
+
+/// Approximate memory held by a database, in bytes.
+///
+/// Returned by [`DB::memory_usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The active MemTable
+    pub memtable: usize,
+    /// MemTables waiting to be flushed
+    pub immutable_memtables: usize,
+    /// Data blocks in the block cache
+    pub block_cache: usize,
+    /// Index blocks and Bloom filters of open SSTables
+    pub index_and_filter_blocks: usize,
+    /// Keys collected by live iterators
+    pub iterators: usize,
+}
+
+impl MemoryUsage {
+    /// Sum of all components.
+    pub fn total(&self) -> usize {
+        self.memtable
+            + self.immutable_memtables
+            + self.block_cache
+            + self.index_and_filter_blocks
+            + self.iterators
+    }
+}
+
+/// A value with what is known about the write that produced it.
+///
+/// Returned by [`DB::get_entry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyEntry {
+    /// The value
+    pub value: Vec<u8>,
+    /// Sequence number of the newest write to the key, `None` if it is in
+    /// an SSTable without sequence numbers (written by an older version or
+    /// ingested from an [`SstFileWriter`](crate::ingest::SstFileWriter))
+    pub sequence: Option<u64>,
+    /// Type of the newest write: [`ValueType::Value`] for a put,
+    /// [`ValueType::Append`] if it appended to the older value
+    pub value_type: ValueType,
+}
+
 /// The main database handle.
 ///
 /// This is the primary interface for interacting with the storage engine.
@@ -86,8 +235,8 @@ pub struct DB {
     /// Database directory path
     path: PathBuf,
 
-    /// Configuration options
-    options: Options,
+    /// Configuration options (mutable settings can be changed via `set_options`)
+    options: RwLock<Options>,
 
     /// Current mutable MemTable
     memtable: Arc<RwLock<MemTable>>,
@@ -112,14 +261,119 @@ pub struct DB {
     /// Current WAL file number
     wal_file_number: Arc<AtomicU64>,
 
+    /// Replaced WAL files, oldest first, with the last sequence number
+    /// each holds. A file is deleted once the MemTables holding its writes
+    /// are flushed.
+    frozen_wals: Mutex<Vec<(PathBuf, u64)>>,
+
     /// Version set for managing SSTable metadata
     version_set: Arc<RwLock<VersionSet>>,
 
     /// Compaction picker
-    compaction_picker: Arc<CompactionPicker>,
+    compaction_picker: Arc<RwLock<CompactionPicker>>,
 
     /// Block cache for SSTable data blocks
     block_cache: Arc<BlockCache>,
+
+    /// Open SSTable files, if limited by `Options::max_open_files`
+    table_cache: Option<Arc<TableCache>>,
+
+    /// Error from a failed flush or compaction.
+    /// While set, the database rejects writes until `resume()` succeeds.
+    background_error: Arc<RwLock<Option<BackgroundErrorState>>>,
+
+    /// Corrupt SSTables moved to `lost/` while opening
+    quarantine_stats: repair::QuarantineStats,
+
+    /// Tickers and histograms, if enabled via `Options::statistics`
+    statistics: Option<Arc<Statistics>>,
+
+    /// Space tracking and paced deletion of SSTables, if enabled via
+    /// `Options::sst_file_manager`
+    sst_file_manager: Option<Arc<sst_file_manager::SstFileManager>>,
+
+    /// MemTable memory budget shared with other databases, if set via
+    /// `Options::write_buffer_manager`, and this database's share
+    write_buffer: Option<(
+        Arc<write_buffer_manager::WriteBufferManager>,
+        Arc<write_buffer_manager::BufferUsage>,
+    )>,
+
+    /// Event listeners registered via `Options::add_listener`
+    listeners: Vec<Arc<dyn EventListener>>,
+
+    /// Storage backend all files are accessed through (`Options::env`)
+    env: Arc<dyn env::Env>,
+
+    /// Engine event log (the `LOG` file in the database directory)
+    info_log: InfoLog,
+
+    /// Bytes of keys held by live iterators
+    iterator_memory: AtomicUsize,
+
+    /// Groups concurrent writes (see [`write_queue`])
+    write_queue: write_queue::WriteQueue,
+
+    /// Held while flushing immutable MemTables, so concurrent flushes don't
+    /// write the same MemTables or install their tables out of order
+    flush_lock: Mutex<()>,
+
+    /// Held while picking and running a compaction, so two compactions
+    /// never take the same input files
+    compaction_lock: Mutex<()>,
+
+    /// SSTables compacted away that may still be read
+    obsolete_files: Mutex<Vec<ObsoleteFile>>,
+
+    /// Subscriptions to key changes (see [`watch`])
+    watchers: watch::Watchers,
+
+    /// Acknowledged positions of the change feeds (see [`changefeed`])
+    changefeeds: changefeed::Changefeeds,
+
+    /// Deletions of whole key prefixes (see [`DB::delete_prefix`])
+    prefix_tombstones: prefix_delete::PrefixTombstones,
+
+    /// When sequence numbers were written (see [`sequence_time`])
+    sequence_times: sequence_time::SequenceTimes,
+
+    /// Newest user timestamp written since opening (see [`timestamp`])
+    newest_timestamp: AtomicU64,
+
+    /// Held to write a blob file and its reference, and exclusively to
+    /// delete obsolete blob files (see [`blob`]). Taken before the
+    /// compaction and flush locks
+    blob_lock: RwLock<()>,
+    /// Held exclusively to write a blob reference or to check and write
+    /// short appends, and shared to check and write other appends and the
+    /// values appends could complete, so no append extends a reference or
+    /// makes up one the check did not see
+    blob_reference_lock: RwLock<()>,
+    /// Whether any value may be a blob reference; until one is written,
+    /// appends do not look up the value they extend
+    has_blobs: AtomicBool,
+    /// MemTables flushed or dropped while iterators still read them; their
+    /// blob references stay in use
+    retired_memtables: Mutex<Vec<memtable::RetiredMemTable>>,
+
+    /// Tags and verifies records (`Options::authenticate_records`)
+    record_auth: Option<Arc<RecordAuthenticator>>,
+
+    /// Order of the keys (`Options::comparator`)
+    comparator: Arc<dyn comparator::Comparator>,
+
+    /// Opened with [`DB::open_read_only`] or [`DB::open_as_secondary`]
+    read_only: bool,
+
+    /// When a secondary last caught up with the primary
+    secondary: Option<secondary::CatchUp>,
+
+    /// The `LOCK` file, held by the writing instance
+    lock: Option<Box<dyn env::FileLock>>,
+
+    /// `env` when it encrypts files (see [`DB::rewrite_with_latest_key`])
+    #[cfg(feature = "encryption")]
+    encrypted_env: Option<Arc<env::EncryptedEnv>>,
 }
 
 impl DB {
@@ -152,15 +406,67 @@ impl DB {
     /// # }
     /// ```
     pub fn open<P: AsRef<std::path::Path>>(path: P, options: Options) -> Result<Self> {
-        let path = path.as_ref().to_path_buf();
+        Self::open_with_mode(path.as_ref(), options, OpenMode::ReadWrite)
+    }
+
+    /// Opens an existing database for reading only.
+    ///
+    /// Nothing in the database directory is created, changed or deleted,
+    /// so it can be opened from a read-only volume or a filesystem
+    /// snapshot: files are only opened for reading, the WAL is replayed
+    /// into the MemTable but not opened for appending, no `LOG` or OPTIONS
+    /// file is written, leftovers of interrupted flushes and compactions
+    /// are left in place, and closing or dropping the handle does not
+    /// flush. Writes, flushes, compactions and [`DB::set_options`] fail
+    /// with [`Error::InvalidState`]; any other attempt to change a file
+    /// fails with a permission error from [`env::ReadOnlyEnv`].
+    ///
+    /// The handle sees the database as it was when opened. Returns
+    /// [`Error::NotFound`] if the directory does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use aidb::{DB, Options};
+    ///
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// let db = DB::open_read_only("/mnt/snapshot/my_database", Options::default())?;
+    /// let value = db.get(b"key")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn open_read_only<P: AsRef<std::path::Path>>(path: P, options: Options) -> Result<Self> {
+        Self::open_with_mode(path.as_ref(), options, OpenMode::ReadOnly)
+    }
+
+    fn open_with_mode(path: &Path, options: Options, mode: OpenMode) -> Result<Self> {
+        let path = path.to_path_buf();
+        let read_only = mode != OpenMode::ReadWrite;
 
         // Validate options
         options.validate()?;
+        let record_auth = match options.key_provider {
+            Some(ref keys) if options.authenticate_records => {
+                Some(Arc::new(RecordAuthenticator::new(Arc::clone(keys))))
+            }
+            _ => None,
+        };
+        #[cfg(feature = "encryption")]
+        let (options, encrypted_env) = options.with_encrypted_env();
+        let mut options = options;
+        if read_only {
+            options.env = Arc::new(env::ReadOnlyEnv::new(Arc::clone(&options.env)));
+            options.info_log = false;
+        }
+        let env = Arc::clone(&options.env);
 
         // Step 1: Create directory if not exists
-        if !path.exists() {
-            if options.create_if_missing {
-                std::fs::create_dir_all(&path)?;
+        if !env.file_exists(&path) {
+            if options.create_if_missing && !read_only {
+                env.create_dir_all(&path)?;
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    env.sync_dir(parent)?;
+                }
             } else {
                 return Err(Error::NotFound(format!(
                     "Database directory does not exist: {:?}",
@@ -171,178 +477,452 @@ impl DB {
             return Err(Error::AlreadyExists(format!("Database already exists: {:?}", path)));
         }
 
-        // Step 2: Initialize sequence number
-        let mut sequence = 0u64;
-
-        // Step 3: Find and open the latest WAL file
-        let mut wal_number = 1u64;
-        let mut latest_wal_path = path.join(wal::wal_filename(1));
-
-        // Scan for the latest WAL file
-        if path.exists() {
-            if let Ok(entries) = std::fs::read_dir(&path) {
-                for entry in entries.flatten() {
-                    if let Some(filename) = entry.file_name().to_str() {
-                        if let Some(num) = wal::parse_wal_filename(filename) {
-                            if num >= wal_number {
-                                wal_number = num;
-                                latest_wal_path = entry.path();
-                            }
-                        }
-                    }
+        // Only one instance may write to the directory
+        let lock = if read_only {
+            None
+        } else {
+            match env.lock_file(&path.join(LOCK_FILE_NAME)) {
+                Ok(lock) => Some(lock),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    return Err(Error::AlreadyExists(format!(
+                        "Database {:?} is already opened for writing by another instance",
+                        path
+                    )));
                 }
+                Err(e) => return Err(e.into()),
             }
+        };
+
+        // Check the caller's options against the ones persisted by the last open
+        if let Some(persisted) = config::OptionsFile::load_latest_with_env(&path, &*env)? {
+            persisted.check_compatibility(&options)?;
         }
 
-        let wal = WAL::open(&latest_wal_path)?;
+        let info_log = InfoLog::open(&path, &options)?;
+        info_log.info(format_args!("Opening database at {:?}", path));
+        match toml::to_string(&options) {
+            Ok(dump) => {
+                for line in dump.lines().filter(|line| !line.is_empty()) {
+                    info_log.info(format_args!("Options: {}", line));
+                }
+            }
+            Err(e) => info_log.warn(format_args!("Failed to dump options: {}", e)),
+        }
 
-        // Step 4: Recover from WAL if it exists and has data
-        let recovered_entries = if latest_wal_path.exists() && wal.size() > 0 {
-            WAL::recover(&latest_wal_path)?
+        // Step 2: Initialize VersionSet and sequence number. Writes up to
+        // the sequence number of a `drop_all` are not replayed.
+        let manifest_existed = env.file_exists(&path.join("MANIFEST"));
+        let mut version_set = if read_only {
+            VersionSet::open_read_only(&path, options.max_levels, Arc::clone(&env))?
         } else {
+            VersionSet::new_with_env(&path, options.max_levels, Arc::clone(&env))?
+        };
+        Self::check_comparator(
+            &mut version_set,
+            &*options.comparator,
+            manifest_existed,
+            read_only,
+        )?;
+        let comparator = Arc::clone(&options.comparator);
+        let dropped_sequence = version_set.dropped_sequence();
+        let mut sequence = 0u64;
+
+        // Step 3: Find the WAL files and open the latest. The older ones
+        // hold writes of MemTables that were not flushed before a crash. A
+        // secondary loads the WAL and the SSTables when it first catches up
+        // with the primary.
+        let children = if mode == OpenMode::Secondary {
             Vec::new()
+        } else {
+            env.get_children(&path)?
+        };
+        let mut wal_files: Vec<(u64, PathBuf)> = children
+            .iter()
+            .filter_map(|name| Some((wal::parse_wal_filename(name)?, path.join(name))))
+            .collect();
+        wal_files.sort();
+        let (wal_number, latest_wal_path) = wal_files
+            .last()
+            .cloned()
+            .unwrap_or_else(|| (1, path.join(wal::wal_filename(1))));
+
+        let wal = if read_only {
+            WAL::open_read_only(&latest_wal_path, &*env)?
+        } else {
+            WAL::open_with_env(&latest_wal_path, &*env)?.with_buffer_size(options.wal_buffer_size)
         };
+        let wal = wal.with_authenticator(record_auth.clone())?;
+
+        // Step 4: Replay the WAL files into a new MemTable, restoring each
+        // write's original sequence number
+        let memtable = MemTable::with_comparator(sequence + 1, Arc::clone(&comparator));
+        let wal_paths: Vec<PathBuf> = wal_files.into_iter().map(|(_, path)| path).collect();
+        sequence = Self::replay_wal_files(
+            &*env,
+            &wal_paths,
+            &memtable,
+            dropped_sequence,
+            options.wal_recovery_threads,
+            &record_auth,
+        )?;
 
-        // Step 5: Initialize MemTable with recovered data
-        let memtable = MemTable::new(sequence + 1);
+        // Step 6: Load existing SSTables
+        let mut sstables: Vec<Vec<Arc<SSTableReader>>> = vec![Vec::new(); options.max_levels];
 
-        for entry in recovered_entries {
-            sequence += 1;
+        // New SSTables must not reuse the number of an existing file
+        let mut next_file_number = 2u64; // Start from 2 (1 is for WAL)
 
-            // Parse WAL entry format
-            if entry.starts_with(b"put:") {
-                // Format: "put:key_len:key:value"
-                let entry = &entry[4..]; // Skip "put:"
+        let mut quarantine_stats = repair::QuarantineStats::default();
 
-                // Read key length
-                if entry.len() < 4 {
-                    log::warn!("Invalid WAL entry: too short");
-                    continue;
-                }
+        // Step 6a: Create block cache (needed before loading SSTables)
+        let block_cache = Arc::new(
+            BlockCache::new(options.block_cache_size).with_statistics(options.statistics.clone()),
+        );
 
-                let key_len = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize;
-                let entry = &entry[4..]; // Skip key_len
+        // Step 6b: Bound the number of open SSTable files
+        let table_cache = (options.max_open_files > 0)
+            .then(|| Arc::new(TableCache::new(Arc::clone(&env), options.max_open_files)));
 
-                if entry.is_empty() || entry[0] != b':' {
-                    log::warn!("Invalid WAL entry: missing separator");
-                    continue;
+        // Scan directory for SSTable files (*.sst)
+        let mut sst_files = Vec::new();
+
+        for filename in &children {
+            let entry_path = path.join(filename);
+            // Remove SSTables whose build was interrupted by a crash
+            if filename.ends_with(sstable::TEMP_FILE_SUFFIX) {
+                if !read_only {
+                    log::warn!("Removing incomplete SSTable {:?}", entry_path);
+                    env.remove_file(&entry_path)?;
                 }
+                continue;
+            }
+            if let Some(number) = filename.strip_suffix(".sst").and_then(|n| n.parse::<u64>().ok())
+            {
+                next_file_number = next_file_number.max(number + 1);
+            }
+            if filename.ends_with(".sst") {
+                sst_files.push(entry_path);
+            }
+        }
 
-                let entry = &entry[1..]; // Skip ':'
+        // Blob files share the file numbers
+        let blob_files = blob::blob_files(&*env, &path)?;
+        let has_blobs = !blob_files.is_empty();
+        for (number, _) in blob_files {
+            next_file_number = next_file_number.max(number + 1);
+        }
 
-                if entry.len() < key_len + 1 {
-                    log::warn!("Invalid WAL entry: key too short");
-                    continue;
+        // Sort SSTable files by file number (newest last)
+        sst_files.sort();
+
+        // Load all SSTables into Level 0 (oldest first for now)
+        let mut corrupt_files = Vec::new();
+        for sst_path in sst_files {
+            match SSTableReader::open_with_env(&sst_path, Some(Arc::clone(&block_cache)), &*env) {
+                Ok(reader) => {
+                    Self::check_sstable_compression(&reader)?;
+                    let reader = reader
+                        .with_record_authenticator(record_auth.clone())?
+                        .with_comparator(Arc::clone(&comparator));
+                    let reader = match table_cache {
+                        Some(ref cache) => reader.with_table_cache(Arc::clone(cache)),
+                        None => reader,
+                    };
+                    sstables[0].push(Arc::new(reader));
+                    log::info!("Loaded SSTable: {:?}", sst_path);
                 }
-
-                let key = &entry[..key_len];
-                let entry = &entry[key_len..];
-
-                if entry.is_empty() || entry[0] != b':' {
-                    log::warn!("Invalid WAL entry: missing value separator");
-                    continue;
+                Err(e) => {
+                    log::warn!("Failed to load SSTable {:?}: {}", sst_path, e);
+                    corrupt_files.push((sst_path, e));
                 }
+            }
+        }
 
-                let value = &entry[1..];
-
-                // Insert into memtable
-                memtable.put(key, value, sequence);
-            } else if entry.starts_with(b"del:") {
-                // Format: "del:key_len:key"
-                let entry = &entry[4..]; // Skip "del:"
-
-                if entry.len() < 4 {
-                    log::warn!("Invalid WAL entry: too short");
+        if options.quarantine_corrupt_files && !corrupt_files.is_empty() {
+            if !options.allow_data_loss {
+                let (sst_path, e) = &corrupt_files[0];
+                return Err(Error::corruption(format!(
+                    "{} corrupt SSTable(s) found (first: {:?}: {}); \
+                     set allow_data_loss to quarantine them and open anyway",
+                    corrupt_files.len(),
+                    sst_path,
+                    e
+                )));
+            }
+            for (sst_path, e) in &corrupt_files {
+                if read_only {
+                    log::warn!("Skipping corrupt SSTable {:?}: {}", sst_path, e);
                     continue;
                 }
+                let target = repair::move_to_lost(&*env, &path, sst_path)?;
+                info_log.warn(format_args!(
+                    "Quarantined corrupt SSTable {:?} to {:?}: {}",
+                    sst_path, target, e
+                ));
+                quarantine_stats.quarantined_paths.push(target);
+            }
+            if !read_only {
+                quarantine_stats.sstables_quarantined = corrupt_files.len();
+                env.sync_dir(&path)?;
+            }
+        }
 
-                let key_len = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize;
-                let entry = &entry[4..]; // Skip key_len
+        log::info!("Loaded {} SSTables at Level 0", sstables[0].len());
 
-                if entry.is_empty() || entry[0] != b':' {
-                    log::warn!("Invalid WAL entry: missing separator");
-                    continue;
-                }
+        // Continue after the last flushed sequence number; the WAL only holds
+        // writes made since then
+        let sequence = sequence.max(version_set.last_sequence());
 
-                let entry = &entry[1..]; // Skip ':'
+        // The older WAL files go once the recovered MemTable is flushed
+        let frozen_wals = match wal_paths.split_last() {
+            Some((_, older)) => older.iter().map(|path| (path.clone(), sequence)).collect(),
+            None => Vec::new(),
+        };
 
-                if entry.len() < key_len {
-                    log::warn!("Invalid WAL entry: key too short");
-                    continue;
+        // Move SSTables written by compactions to the level recorded in the
+        // manifest. Flushed files stay in Level 0, newest first.
+        let file_levels: std::collections::HashMap<u64, usize> = version_set
+            .current()
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, files)| files.iter().map(move |f| (f.file_number, level)))
+            .collect();
+        let loaded = std::mem::take(&mut sstables[0]);
+        let deleted_files = version_set.deleted_files();
+        for reader in loaded.into_iter().rev() {
+            // A compacted input still pinned by an iterator when the
+            // database was closed or crashed
+            if let Some(number) = reader.file_number().filter(|n| deleted_files.contains(n)) {
+                let sst_path = reader.file_path().to_path_buf();
+                drop(reader);
+                if !read_only {
+                    log::info!("Removing obsolete SSTable {:06}.sst", number);
+                    env.remove_file(&sst_path)?;
                 }
-
-                let key = &entry[..key_len];
-
-                // Insert tombstone into memtable
-                memtable.delete(key, sequence);
-            } else {
-                log::warn!("Unknown WAL entry type");
+                continue;
             }
+            let level = reader.file_number().and_then(|n| file_levels.get(&n).copied());
+            let level = level.filter(|&l| l < sstables.len()).unwrap_or(0);
+            sstables[level].push(reader);
+        }
+        for level in sstables.iter_mut().skip(1) {
+            sort_level_by_key(level);
         }
 
-        // Step 6: Load existing SSTables
-        let mut sstables: Vec<Vec<Arc<SSTableReader>>> = vec![Vec::new(); options.max_levels];
+        if !read_only {
+            // The on-disk state is compatible; record the effective options for the next open
+            config::OptionsFile::new(&options).persist_with_env(&path, &*env)?;
 
-        // Step 6a: Create block cache (needed before loading SSTables)
-        let block_cache = Arc::new(BlockCache::new(options.block_cache_size));
+            // Reserve some space that can be released when the disk fills up
+            if let Err(e) = util::reserve_space(&*env, &path, options.reserved_disk_space) {
+                log::warn!(
+                    "Failed to reserve {} bytes of disk space: {}",
+                    options.reserved_disk_space,
+                    e
+                );
+            }
 
-        // Scan directory for SSTable files (*.sst)
-        if path.exists() {
-            if let Ok(entries) = std::fs::read_dir(&path) {
-                let mut sst_files = Vec::new();
-
-                for entry in entries.flatten() {
-                    if let Some(filename) = entry.file_name().to_str() {
-                        if filename.ends_with(".sst") {
-                            sst_files.push(entry.path());
-                        }
+            // Finish deleting the SSTables left in the trash
+            match options.sst_file_manager {
+                Some(ref manager) => {
+                    for reader in sstables.iter().flatten() {
+                        manager.on_add_file(reader.file_path(), reader.file_size());
                     }
+                    manager.schedule_trash(&env, &path)?;
                 }
-
-                // Sort SSTable files by file number (newest last)
-                sst_files.sort();
-
-                // Load all SSTables into Level 0
-                for sst_path in sst_files {
-                    match SSTableReader::open_with_cache(&sst_path, Some(Arc::clone(&block_cache)))
-                    {
-                        Ok(reader) => {
-                            sstables[0].push(Arc::new(reader));
-                            log::info!("Loaded SSTable: {:?}", sst_path);
-                        }
-                        Err(e) => {
-                            log::warn!("Failed to load SSTable {:?}: {}", sst_path, e);
-                        }
+                None => {
+                    for trash_path in sst_file_manager::trash_files(&*env, &path)? {
+                        env.remove_file(&trash_path)?;
                     }
                 }
-
-                log::info!("Loaded {} SSTables at Level 0", sstables[0].len());
             }
         }
 
-        // Step 7: Initialize VersionSet
-        let version_set = VersionSet::new(&path, options.max_levels)?;
+        let changefeeds = changefeed::Changefeeds::load(&*env, &path)?;
+        let prefix_tombstones = prefix_delete::PrefixTombstones::load(&*env, &path)?;
 
         // Step 8: Initialize CompactionPicker
-        let compaction_picker = CompactionPicker::new(options.max_levels);
+        let compaction_picker = CompactionPicker::from_options(&options);
+
+        let sequence_times = sequence_time::SequenceTimes::default();
+        sequence_times.record(
+            sequence,
+            env.now_micros(),
+            options.preserve_versions_seconds.saturating_mul(1_000_000),
+        );
+
+        info_log.info(format_args!(
+            "Database opened: last sequence {}, {} SSTables, WAL {}",
+            sequence,
+            sstables.iter().map(Vec::len).sum::<usize>(),
+            wal::wal_filename(wal_number)
+        ));
+
+        let secondary = (mode == OpenMode::Secondary)
+            .then(|| secondary::CatchUp::new(options.secondary_refresh_interval_ms));
 
         // Step 9: Construct DB instance
         Ok(DB {
             path,
-            options,
+            statistics: options.statistics.clone(),
+            sst_file_manager: options.sst_file_manager.clone(),
+            write_buffer: options
+                .write_buffer_manager
+                .as_ref()
+                .map(|manager| (Arc::clone(manager), manager.register())),
+            listeners: options.listeners.clone(),
+            env,
+            info_log,
+            options: RwLock::new(options),
             memtable: Arc::new(RwLock::new(memtable)),
             immutable_memtables: Arc::new(RwLock::new(Vec::new())),
             wal: Arc::new(RwLock::new(wal)),
             sstables: Arc::new(RwLock::new(sstables)),
             sequence: Arc::new(AtomicU64::new(sequence)),
-            next_file_number: Arc::new(AtomicU64::new(2)), // Start from 2 (1 is for WAL)
+            next_file_number: Arc::new(AtomicU64::new(next_file_number)),
             wal_file_number: Arc::new(AtomicU64::new(wal_number)),
+            frozen_wals: Mutex::new(frozen_wals),
             version_set: Arc::new(RwLock::new(version_set)),
-            compaction_picker: Arc::new(compaction_picker),
+            compaction_picker: Arc::new(RwLock::new(compaction_picker)),
             block_cache,
+            table_cache,
+            background_error: Arc::new(RwLock::new(None)),
+            quarantine_stats,
+            iterator_memory: AtomicUsize::new(0),
+            write_queue: write_queue::WriteQueue::new(sequence),
+            flush_lock: Mutex::new(()),
+            compaction_lock: Mutex::new(()),
+            obsolete_files: Mutex::new(Vec::new()),
+            watchers: watch::Watchers::default(),
+            changefeeds,
+            prefix_tombstones,
+            sequence_times,
+            newest_timestamp: AtomicU64::new(0),
+            blob_lock: RwLock::new(()),
+            blob_reference_lock: RwLock::new(()),
+            has_blobs: AtomicBool::new(has_blobs),
+            retired_memtables: Mutex::new(Vec::new()),
+            record_auth,
+            comparator,
+            read_only,
+            secondary,
+            lock,
+            #[cfg(feature = "encryption")]
+            encrypted_env,
         })
     }
 
+    /// Repairs a damaged database directory.
+    ///
+    /// Verifies every SSTable, moves damaged files to a `lost/` subdirectory,
+    /// rebuilds the manifest from the surviving SSTables (all placed at Level 0)
+    /// and salvages every WAL record that passes its checksum. The database
+    /// must not be open while it is being repaired. See [`repair`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// let report = DB::repair("./my_database")?;
+    /// println!("recovered {} SSTables", report.sstables_recovered.len());
+    /// let db = DB::open("./my_database", Options::default())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn repair<P: AsRef<std::path::Path>>(path: P) -> Result<repair::RepairReport> {
+        repair::repair(path)
+    }
+
+    /// Checks that `comparator` is the one the database was created with,
+    /// and records its name in the manifest if it has none yet.
+    ///
+    /// A manifest without a name was written before names were recorded,
+    /// when keys were always ordered bytewise.
+    fn check_comparator(
+        version_set: &mut VersionSet,
+        comparator: &dyn comparator::Comparator,
+        manifest_existed: bool,
+        read_only: bool,
+    ) -> Result<()> {
+        let recorded = version_set
+            .comparator_name()
+            .or(manifest_existed.then_some(comparator::BYTEWISE_COMPARATOR_NAME));
+        if let Some(recorded) = recorded.filter(|&recorded| recorded != comparator.name()) {
+            return Err(Error::invalid_config(format!(
+                "database was created with comparator {:?} but is opened with {:?}",
+                recorded,
+                comparator.name()
+            )));
+        }
+        if version_set.comparator_name().is_none() && !read_only {
+            version_set.log_edit(&VersionEdit::SetComparator(comparator.name().to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Replays the WAL files `wal_paths`, oldest first, into `memtable`,
+    /// restoring each write's original sequence number, and returns the
+    /// last sequence number. Writes up to `dropped_sequence` are skipped.
+    ///
+    /// A file that is gone by the time it is read is skipped: only a
+    /// primary read by a secondary deletes WAL files while they are
+    /// listed, once their writes are flushed.
+    fn replay_wal_files(
+        env: &dyn env::Env,
+        wal_paths: &[PathBuf],
+        memtable: &MemTable,
+        dropped_sequence: u64,
+        threads: usize,
+        record_auth: &Option<Arc<RecordAuthenticator>>,
+    ) -> Result<u64> {
+        let mut sequence = 0;
+        for wal_path in wal_paths {
+            let reader = match wal::WALReader::new_with_env(wal_path, env) {
+                Ok(reader) => reader.with_authenticator(record_auth.clone()),
+                Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let stats = wal::recovery::replay_reader(reader, sequence, threads, |entry| {
+                if entry.sequence <= dropped_sequence {
+                    return;
+                }
+                match entry.op {
+                    write_batch::WriteOp::Put { key, value } => {
+                        memtable.put(&key, &value, entry.sequence)
+                    }
+                    write_batch::WriteOp::Delete { key } => memtable.delete(&key, entry.sequence),
+                    write_batch::WriteOp::Append { key, suffix } => {
+                        memtable.append(&key, &suffix, entry.sequence)
+                    }
+                }
+            })?;
+            sequence = stats.last_sequence;
+        }
+        Ok(sequence)
+    }
+
+    /// Checks that this build can decompress the blocks of an existing SSTable.
+    fn check_sstable_compression(reader: &SSTableReader) -> Result<()> {
+        if let Some(id) = reader.data_compression_id()? {
+            if config::CompressionType::from_u8(id).is_none() {
+                let name = match id {
+                    1 => "snappy",
+                    2 => "lz4",
+                    _ => "unknown",
+                };
+                return Err(Error::invalid_config(format!(
+                    "SSTable {:?} uses {} compression (id {}), which is not enabled in this build",
+                    reader.file_path(),
+                    name,
+                    id
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Inserts a key-value pair into the database.
     ///
     /// If the key already exists, its value will be overwritten.
@@ -354,7 +934,11 @@ impl DB {
     ///
     /// # Errors
     ///
-    /// Returns an error if the write fails due to I/O errors.
+    /// Returns an error if the write fails due to I/O errors, or
+    /// [`Error::InvalidArgument`] if the key or value exceeds
+    /// [`Options::max_key_size`] / [`Options::max_value_size`], or if the
+    /// value has the format of a [`put_reader`](Self::put_reader) blob
+    /// reference.
     ///
     /// # Example
     ///
@@ -367,53 +951,42 @@ impl DB {
     /// # }
     /// ```
     pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        // Step 1: Get the next sequence number
-        let seq = self.sequence.fetch_add(1, Ordering::SeqCst) + 1;
-
-        // Step 2: Write to WAL first (for durability)
-        if self.options.use_wal {
-            let mut wal = self.wal.write();
-
-            // Encode the entry as: "put:key_len:key:value"
-            let mut entry = Vec::new();
-            entry.extend_from_slice(b"put:");
-            entry.extend_from_slice(&(key.len() as u32).to_le_bytes());
-            entry.extend_from_slice(b":");
-            entry.extend_from_slice(key);
-            entry.extend_from_slice(b":");
-            entry.extend_from_slice(value);
-
-            wal.append(&entry)?;
+        let _references = self.check_blob_put(key, value)?;
+        self.put_internal(key, value)
+    }
 
-            if self.options.sync_wal {
-                wal.sync()?;
-            }
-        }
+    /// Like [`put`](Self::put), but also stores blob references
+    fn put_internal(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Put);
+        let threshold = self.options.read().slow_write_threshold_ms;
+        let _slow_log = SlowOperationLog::start(&self.info_log, "put", key, 1, threshold);
+        self.options.read().check_entry_size(key, Some(value))?;
+        self.check_background_error()?;
+        self.throttle_write()?;
 
-        // Step 3: Insert into MemTable
-        {
-            let memtable = self.memtable.read();
-            memtable.put(key, value, seq);
-        }
+        let mut batch = WriteBatch::new();
+        batch.put(key, value);
+        self.write_internal(batch)?;
 
-        // Step 4: Check if MemTable is full and needs flushing
+        // Check if MemTable is full and needs flushing
         let memtable_size = {
             let memtable = self.memtable.read();
             memtable.approximate_size()
         };
 
-        if memtable_size >= self.options.memtable_size {
+        let memtable_limit = self.options.read().memtable_size;
+        if memtable_size >= memtable_limit {
             log::info!(
                 "MemTable is full ({} bytes >= {}), triggering freeze",
                 memtable_size,
-                self.options.memtable_size
+                memtable_limit
             );
             // Freeze the current MemTable
             // The actual flush will happen in the background or on next flush() call
             self.freeze_memtable()?;
         }
 
-        Ok(())
+        self.maybe_flush_write_buffer()
     }
 
     /// Retrieves the value associated with a key.
@@ -441,45 +1014,181 @@ impl DB {
     /// # }
     /// ```
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        // Get the current sequence number for consistent reads
-        let max_seq = self.sequence.load(Ordering::SeqCst);
-
-        // Step 1: Check current MemTable
-        {
-            let memtable = self.memtable.read();
-            if let Some(value) = memtable.get(key, max_seq) {
-                return Ok(Some(value));
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Get);
+        let threshold = self.options.read().slow_get_threshold_ms;
+        let _slow_log = SlowOperationLog::start(&self.info_log, "get", key, 1, threshold);
+        self.maybe_catch_up()?;
+
+        // Read at the current sequence number for a consistent view
+        let value = self
+            .get_value_at_sequence(
+                key,
+                self.sequence.load(Ordering::SeqCst),
+                &ReadOptions::default(),
+            )?
+            .map(|value| self.resolve_blob(value))
+            .transpose()?;
+
+        if let Some(ref stats) = self.statistics {
+            stats.record_tick(Ticker::KeysRead, 1);
+            if let Some(ref value) = value {
+                stats.record_tick(Ticker::BytesRead, value.len() as u64);
             }
         }
 
-        // Step 2: Check Immutable MemTables (newest to oldest)
-        {
-            let immutable = self.immutable_memtables.read();
-            for memtable in immutable.iter().rev() {
-                if let Some(value) = memtable.get(key, max_seq) {
-                    return Ok(Some(value));
+        Ok(value)
+    }
+
+    /// Copies the value of a key into `buf`, replacing its contents, and
+    /// returns the value's length.
+    ///
+    /// Like [`get`](Self::get), but the capacity of `buf` is reused, so a
+    /// loop reading into the same buffer does not allocate a vector per
+    /// value. Values stored with [`put_reader`](Self::put_reader) are read
+    /// from their blob file straight into `buf`. Returns `None`, leaving
+    /// `buf` unchanged, if the key does not exist or has been deleted.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// # let db = DB::open("./data", Options::default())?;
+    /// let mut buf = Vec::new();
+    /// for key in [b"a", b"b", b"c"] {
+    ///     if db.get_into(key, &mut buf)?.is_some() {
+    ///         println!("{:?}", buf);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_into(&self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<usize>> {
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Get);
+        let threshold = self.options.read().slow_get_threshold_ms;
+        let _slow_log = SlowOperationLog::start(&self.info_log, "get_into", key, 1, threshold);
+        self.maybe_catch_up()?;
+
+        let value = self.get_value_at_sequence(
+            key,
+            self.sequence.load(Ordering::SeqCst),
+            &ReadOptions::default(),
+        )?;
+        let len = match value {
+            Some(value) => {
+                buf.clear();
+                Some(self.copy_value(&value, buf)? as usize)
+            }
+            None => None,
+        };
+
+        self.record_read(len);
+        Ok(len)
+    }
+
+    /// Copies the value of a key into the start of `buf` if it fits, and
+    /// returns the value's length either way.
+    ///
+    /// If the returned length is larger than `buf`, nothing was copied and
+    /// the read can be retried with a buffer of that length. Returns `None`
+    /// if the key does not exist or has been deleted.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// # let db = DB::open("./data", Options::default())?;
+    /// let mut buf = [0u8; 64];
+    /// match db.get_into_slice(b"key", &mut buf)? {
+    ///     Some(len) if len <= buf.len() => println!("{:?}", &buf[..len]),
+    ///     Some(len) => println!("needs a buffer of {} bytes", len),
+    ///     None => println!("not found"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_into_slice(&self, key: &[u8], buf: &mut [u8]) -> Result<Option<usize>> {
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Get);
+        let threshold = self.options.read().slow_get_threshold_ms;
+        let _slow_log =
+            SlowOperationLog::start(&self.info_log, "get_into_slice", key, 1, threshold);
+        self.maybe_catch_up()?;
+
+        let value = self.get_value_at_sequence(
+            key,
+            self.sequence.load(Ordering::SeqCst),
+            &ReadOptions::default(),
+        )?;
+        let len = match value {
+            Some(value) => {
+                let len = blob::value_len(&value) as usize;
+                if len <= buf.len() {
+                    self.copy_value(&value, &mut &mut buf[..len])?;
                 }
+                Some(len)
+            }
+            None => None,
+        };
+
+        self.record_read(len);
+        Ok(len)
+    }
+
+    /// Counts a point read of a value of `len` bytes, if found
+    fn record_read(&self, len: Option<usize>) {
+        if let Some(ref stats) = self.statistics {
+            stats.record_tick(Ticker::KeysRead, 1);
+            if let Some(len) = len {
+                stats.record_tick(Ticker::BytesRead, len as u64);
             }
         }
+    }
 
-        // Step 3: Search SSTables from Level 0 to Level N
-        {
-            let sstables = self.sstables.read();
-            for level_tables in sstables.iter() {
-                // For Level 0, search all tables (may overlap)
-                // For other levels, tables don't overlap, so we can binary search
-                for table in level_tables.iter().rev() {
-                    // Since we store user_key only in SSTables (simplified version),
-                    // we can directly search for the key
-                    if let Some(value) = table.get(key)? {
-                        return Ok(Some(value));
-                    }
-                }
+    /// Retrieves the value of a key with the sequence number and type of
+    /// the newest write to it.
+    ///
+    /// Sync tools can use the sequence number for last-writer-wins
+    /// reconciliation, as long as the key has not been flushed; see
+    /// [`KeyEntry::sequence`]. Returns `None` if the key does not exist or
+    /// has been deleted.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// # let db = DB::open("./data", Options::default())?;
+    /// db.put(b"key", b"value")?;
+    /// let entry = db.get_entry(b"key")?.unwrap();
+    /// println!("{:?} written at {:?}", entry.value, entry.sequence);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_entry(&self, key: &[u8]) -> Result<Option<KeyEntry>> {
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Get);
+        let threshold = self.options.read().slow_get_threshold_ms;
+        let _slow_log = SlowOperationLog::start(&self.info_log, "get_entry", key, 1, threshold);
+        self.maybe_catch_up()?;
+
+        let entry = self
+            .get_entry_at_sequence(
+                key,
+                self.sequence.load(Ordering::SeqCst),
+                false,
+                &ReadOptions::default(),
+            )?
+            .map(|entry| self.resolve_blob_entry(entry))
+            .transpose()?;
+
+        if let Some(ref stats) = self.statistics {
+            stats.record_tick(Ticker::KeysRead, 1);
+            if let Some(ref entry) = entry {
+                stats.record_tick(Ticker::BytesRead, entry.value.len() as u64);
             }
         }
 
-        // Key not found
-        Ok(None)
+        Ok(entry)
     }
 
     /// Deletes a key from the database.
@@ -493,7 +1202,8 @@ impl DB {
     ///
     /// # Errors
     ///
-    /// Returns an error if the operation fails due to I/O errors.
+    /// Returns an error if the operation fails due to I/O errors, or
+    /// [`Error::InvalidArgument`] if the key exceeds [`Options::max_key_size`].
     ///
     /// # Example
     ///
@@ -506,34 +1216,68 @@ impl DB {
     /// # }
     /// ```
     pub fn delete(&self, key: &[u8]) -> Result<()> {
-        // Step 1: Get the next sequence number
-        let seq = self.sequence.fetch_add(1, Ordering::SeqCst) + 1;
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Put);
+        let threshold = self.options.read().slow_write_threshold_ms;
+        let _slow_log = SlowOperationLog::start(&self.info_log, "delete", key, 1, threshold);
+        self.options.read().check_entry_size(key, None)?;
+        self.check_background_error()?;
+        self.throttle_write()?;
 
-        // Step 2: Write tombstone to WAL
-        if self.options.use_wal {
-            let mut wal = self.wal.write();
-
-            // Encode the entry as: "del:key_len:key"
-            let mut entry = Vec::new();
-            entry.extend_from_slice(b"del:");
-            entry.extend_from_slice(&(key.len() as u32).to_le_bytes());
-            entry.extend_from_slice(b":");
-            entry.extend_from_slice(key);
-
-            wal.append(&entry)?;
-
-            if self.options.sync_wal {
-                wal.sync()?;
-            }
-        }
+        let mut batch = WriteBatch::new();
+        batch.delete(key);
+        self.write_internal(batch)?;
+        self.maybe_flush_write_buffer()
+    }
 
-        // Step 3: Insert tombstone into MemTable
-        {
-            let memtable = self.memtable.read();
-            memtable.delete(key, seq);
+    /// Appends `suffix` to the value of `key`.
+    ///
+    /// Nothing is read: the suffix is written like a put and applied to the
+    /// older value when the key is read, and for good when the MemTable is
+    /// flushed. Appending to a key that does not exist creates it with
+    /// `suffix` as its value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation fails due to I/O errors, or
+    /// [`Error::InvalidArgument`] if the key or suffix exceeds the
+    /// configured size limits, if the suffix has the format of a blob
+    /// reference, or if the value of the key was stored with
+    /// [`put_reader`](Self::put_reader). The limits apply to the suffix, not
+    /// to the value it extends.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// # let db = DB::open("./data", Options::default())?;
+    /// db.append(b"events", b"login;")?;
+    /// db.append(b"events", b"logout;")?;
+    /// assert_eq!(db.get(b"events")?, Some(b"login;logout;".to_vec()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn append(&self, key: &[u8], suffix: &[u8]) -> Result<()> {
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Put);
+        let threshold = self.options.read().slow_write_threshold_ms;
+        let _slow_log = SlowOperationLog::start(&self.info_log, "append", key, 1, threshold);
+        self.options.read().check_entry_size(key, Some(suffix))?;
+        self.check_background_error()?;
+        self.throttle_write()?;
+
+        let mut batch = WriteBatch::new();
+        batch.append(key, suffix);
+        let appends = self.check_blob_writes(&batch)?;
+        self.write_internal(batch)?;
+        drop(appends);
+
+        // Check if MemTable is full and needs flushing
+        let memtable_size = self.memtable.read().approximate_size();
+        if memtable_size >= self.options.read().memtable_size {
+            self.freeze_memtable()?;
         }
 
-        Ok(())
+        self.maybe_flush_write_buffer()
     }
 
     /// Creates a snapshot of the database at the current point in time.
@@ -572,16 +1316,145 @@ impl DB {
         crate::snapshot::Snapshot::new(Arc::clone(self), seq)
     }
 
+    /// Retrieves the value a key had after the write with sequence number
+    /// `sequence`.
+    ///
+    /// Old versions of keys are kept for
+    /// [`Options::preserve_versions_seconds`]; `sequence` can be any sequence
+    /// number written in that window, such as a [`KeyEntry::sequence`] or a
+    /// [`Snapshot::sequence`](crate::snapshot::Snapshot::sequence).
+    /// Sequence numbers newer than the latest write read the current value.
+    /// Returns `None` if the key did not exist or was deleted at that point.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if the versions at `sequence` are
+    /// no longer kept. Versions dropped before the window was lengthened
+    /// are not brought back.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// let db = DB::open("./data", Options::default().preserve_versions_seconds(3600))?;
+    /// db.put(b"key", b"old")?;
+    /// let sequence = db.get_entry(b"key")?.and_then(|entry| entry.sequence).unwrap();
+    /// db.put(b"key", b"new")?;
+    /// db.flush()?;
+    /// assert_eq!(db.get_at_sequence(b"key", sequence)?, Some(b"old".to_vec()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_at_sequence(&self, key: &[u8], sequence: u64) -> Result<Option<Vec<u8>>> {
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Get);
+        self.maybe_catch_up()?;
+
+        let current = self.sequence.load(Ordering::SeqCst);
+        if sequence < current && sequence < self.history_cutoff() {
+            return Err(Error::invalid_argument(format!(
+                "versions at sequence {} are no longer kept",
+                sequence
+            )));
+        }
+        let value = self
+            .get_entry_at_sequence(key, sequence.min(current), true, &ReadOptions::default())?
+            .map(|entry| self.resolve_blob(entry.value))
+            .transpose()?;
+
+        self.record_read(value.as_ref().map(Vec::len));
+        Ok(value)
+    }
+
+    /// Oldest sequence number whose versions flushes and compactions keep
+    /// (see [`Options::preserve_versions_seconds`]), `u64::MAX` to keep
+    /// only the newest version of each key
+    fn history_cutoff(&self) -> u64 {
+        let seconds = self.options.read().preserve_versions_seconds;
+        if seconds == 0 {
+            return u64::MAX;
+        }
+        let start = self.env.now_micros().saturating_sub(seconds.saturating_mul(1_000_000));
+        // Without a sample that old, everything written since the database
+        // was opened is in the window
+        self.sequence_times.sequence_at(start).unwrap_or(0)
+    }
+
     /// Internal method to get a value at a specific sequence number.
     ///
     /// This is used by snapshots to implement point-in-time reads.
-    /// Only entries with sequence numbers <= max_seq are visible.
-    pub(crate) fn get_at_sequence(&self, key: &[u8], max_seq: u64) -> Result<Option<Vec<u8>>> {
+    /// Only MemTable entries with sequence numbers <= max_seq are visible;
+    /// SSTables are read at their newest versions.
+    pub(crate) fn get_value_at_sequence(
+        &self,
+        key: &[u8],
+        max_seq: u64,
+        read_options: &ReadOptions,
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .get_entry_at_sequence(key, max_seq, false, read_options)?
+            .map(|entry| entry.value))
+    }
+
+    /// Like [`get_value_at_sequence`](Self::get_value_at_sequence), with the
+    /// newest write's sequence number and type. With `history`, the older
+    /// versions SSTables keep are read at `max_seq` too.
+    ///
+    /// Fails with `Corruption` if appends make up a blob reference.
+    pub(crate) fn get_entry_at_sequence(
+        &self,
+        key: &[u8],
+        max_seq: u64,
+        history: bool,
+        read_options: &ReadOptions,
+    ) -> Result<Option<KeyEntry>> {
+        let entry = self.lookup_entry(key, max_seq, history, read_options)?;
+        if let Some(entry) = entry.as_ref().filter(|entry| entry.value_type == ValueType::Append) {
+            blob::check_appended(key, &entry.value)?;
+        }
+        Ok(entry)
+    }
+
+    /// Finds the entry [`get_entry_at_sequence`](Self::get_entry_at_sequence)
+    /// returns, with its appends applied
+    fn lookup_entry(
+        &self,
+        key: &[u8],
+        max_seq: u64,
+        history: bool,
+        read_options: &ReadOptions,
+    ) -> Result<Option<KeyEntry>> {
+        let prefix_deletes = self.prefix_tombstones.get().visible_at(max_seq);
+        // The newest write, and the appends found so far, to be applied to
+        // the older versions
+        let mut newest: Option<(Option<u64>, ValueType)> = None;
+        let mut appended: Option<Version> = None;
+        let mut found = |sequence: Option<u64>, value_type: ValueType, version: Version| {
+            let (sequence, value_type) = *newest.get_or_insert((sequence, value_type));
+            let version = match appended.take() {
+                Some(newer) => newer.over(version),
+                None => version,
+            };
+            // A complete version hides older versions in the other sources
+            if version.is_complete() {
+                let entry =
+                    version.into_value().map(|value| KeyEntry { value, sequence, value_type });
+                return Some(entry);
+            }
+            appended = Some(version);
+            None
+        };
+
         // Step 1: Check current MemTable
         {
             let memtable = self.memtable.read();
-            if let Some(value) = memtable.get(key, max_seq) {
-                return Ok(Some(value));
+            perf_context::update(|perf| perf.memtables_checked += 1);
+            if let Some((sequence, value_type, version)) =
+                memtable.lookup(key, max_seq, &prefix_deletes)
+            {
+                if let Some(entry) = found(Some(sequence), value_type, version) {
+                    return Ok(entry);
+                }
             }
         }
 
@@ -589,8 +1462,13 @@ impl DB {
         {
             let immutable = self.immutable_memtables.read();
             for memtable in immutable.iter().rev() {
-                if let Some(value) = memtable.get(key, max_seq) {
-                    return Ok(Some(value));
+                perf_context::update(|perf| perf.memtables_checked += 1);
+                if let Some((sequence, value_type, version)) =
+                    memtable.lookup(key, max_seq, &prefix_deletes)
+                {
+                    if let Some(entry) = found(Some(sequence), value_type, version) {
+                        return Ok(entry);
+                    }
                 }
             }
         }
@@ -598,21 +1476,63 @@ impl DB {
         // Step 3: Search SSTables from Level 0 to Level N
         {
             let sstables = self.sstables.read();
-            for level_tables in sstables.iter() {
-                // For Level 0, search all tables (may overlap)
-                // For other levels, tables don't overlap, so we can binary search
-                for table in level_tables.iter().rev() {
-                    // Since we store user_key only in SSTables (simplified version),
-                    // we can directly search for the key
-                    if let Some(value) = table.get(key)? {
-                        return Ok(Some(value));
+            for (level, level_tables) in sstables.iter().enumerate() {
+                // Level 0 tables may overlap: search all of them, newest
+                // first. Other levels are ordered by key and don't overlap,
+                // so at most one table can hold the key.
+                let candidates = if level == 0 {
+                    &level_tables[..]
+                } else {
+                    let comparator = &*self.comparator;
+                    let index = level_tables.partition_point(|table| {
+                        matches!(table.key_range(), Ok(Some((_, largest)))
+                            if comparator.compare(largest, key) == CmpOrdering::Less)
+                    });
+                    match level_tables.get(index) {
+                        Some(table)
+                            if !matches!(table.key_range(), Ok(Some((smallest, _)))
+                                if comparator.compare(smallest, key) == CmpOrdering::Greater) =>
+                        {
+                            std::slice::from_ref(table)
+                        }
+                        _ => &[],
+                    }
+                };
+                for table in candidates {
+                    if !table.may_contain(key) {
+                        perf_context::update(|perf| perf.bloom_filter_skips += 1);
+                        if let Some(ref stats) = self.statistics {
+                            stats.record_tick(Ticker::BloomFilterUseful, 1);
+                        }
+                        continue;
+                    }
+                    perf_context::update(|perf| perf.sstables_checked += 1);
+
+                    // An empty value is a deletion, which hides older
+                    // versions in lower tables
+                    let table_seq = if history { max_seq } else { u64::MAX };
+                    if let Some((sequence, value)) =
+                        table.lookup_at(key, table_seq, read_options)?
+                    {
+                        let version = if value.is_empty()
+                            || prefix_deletes.covers_file(key, table.file_number())
+                        {
+                            Version::Deleted
+                        } else {
+                            Version::Value(value)
+                        };
+                        // Appends are collapsed when flushed
+                        let sequence = (sequence != 0).then_some(sequence);
+                        if let Some(entry) = found(sequence, ValueType::Value, version) {
+                            return Ok(entry);
+                        }
                     }
                 }
             }
         }
 
-        // Key not found
-        Ok(None)
+        // Key not found, or only appended to
+        Ok(found(None, ValueType::Deletion, Version::Deleted).flatten())
     }
 
     /// Applies a batch of write operations atomically.
@@ -637,6 +1557,11 @@ impl DB {
     /// # Errors
     ///
     /// Returns an error if WAL writing or MemTable operations fail.
+    /// If the batch exceeds `max_batch_ops` or `max_batch_bytes`, any key
+    /// or value exceeds the configured size limits, a value has the format
+    /// of a blob reference, or an append extends a value stored with
+    /// [`put_reader`](Self::put_reader), the batch is
+    /// rejected with [`Error::InvalidArgument`] and nothing is written.
     /// If WAL writing fails, no operations are applied to MemTable.
     /// If MemTable operations fail after WAL writing succeeds, the operations
     /// will be recovered from WAL on next database open.
@@ -658,89 +1583,246 @@ impl DB {
     /// # }
     /// ```
     pub fn write(&self, batch: WriteBatch) -> Result<()> {
-        if batch.is_empty() {
-            return Ok(());
-        }
-
-        // Allocate sequence numbers for the entire batch upfront
-        let batch_size = batch.len() as u64;
-        let base_seq = self.sequence.fetch_add(batch_size, Ordering::SeqCst) + 1;
-
-        // Write all operations to WAL first (for durability)
-        if self.options.use_wal {
-            let mut wal = self.wal.write();
-
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Write);
+        // Copied, since the batch is handed to the write queue
+        let first_key = batch.iter().next().map_or(Vec::new(), |op| op.key().to_vec());
+        let threshold = self.options.read().slow_write_threshold_ms;
+        let _slow_log =
+            SlowOperationLog::start(&self.info_log, "write", &first_key, batch.len(), threshold);
+        // Reject the whole batch if it or any entry is too large
+        {
+            let options = self.options.read();
+            options.check_batch_size(&batch)?;
             for op in batch.iter() {
                 match op {
                     write_batch::WriteOp::Put { key, value } => {
-                        // Encode as: "put:key_len:key:value"
-                        let mut entry = Vec::new();
-                        entry.extend_from_slice(b"put:");
-                        entry.extend_from_slice(&(key.len() as u32).to_le_bytes());
-                        entry.extend_from_slice(b":");
-                        entry.extend_from_slice(key);
-                        entry.extend_from_slice(b":");
-                        entry.extend_from_slice(value);
-                        wal.append(&entry)?;
+                        options.check_entry_size(key, Some(value))?
                     }
-                    write_batch::WriteOp::Delete { key } => {
-                        // Encode as: "del:key_len:key"
-                        let mut entry = Vec::new();
-                        entry.extend_from_slice(b"del:");
-                        entry.extend_from_slice(&(key.len() as u32).to_le_bytes());
-                        entry.extend_from_slice(b":");
-                        entry.extend_from_slice(key);
-                        wal.append(&entry)?;
+                    write_batch::WriteOp::Delete { key } => options.check_entry_size(key, None)?,
+                    write_batch::WriteOp::Append { key, suffix } => {
+                        options.check_entry_size(key, Some(suffix))?
                     }
                 }
             }
+        }
+        self.check_background_error()?;
+
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.throttle_write()?;
+
+        let appends = self.check_blob_writes(&batch)?;
+        self.write_internal(batch)?;
+        drop(appends);
+
+        // Check if MemTable is full and needs flushing
+        let memtable_size = {
+            let memtable = self.memtable.read();
+            memtable.approximate_size()
+        };
+
+        let memtable_limit = self.options.read().memtable_size;
+        if memtable_size >= memtable_limit {
+            log::info!(
+                "MemTable is full ({} bytes >= {}), triggering freeze after batch write",
+                memtable_size,
+                memtable_limit
+            );
+            self.freeze_memtable()?;
+        }
+
+        self.maybe_flush_write_buffer()
+    }
+
+    /// Holds back a write while compactions are behind.
+    ///
+    /// Above the hard pending compaction bytes limit the writer runs
+    /// compactions itself until the estimate is back under it (or nothing
+    /// is left to compact), so writes stop until compaction catches up.
+    /// Above the soft limit each write is delayed by 1ms.
+    fn throttle_write(&self) -> Result<()> {
+        let (soft_limit, hard_limit) = {
+            let options = self.options.read();
+            (
+                options.soft_pending_compaction_bytes_limit,
+                options.hard_pending_compaction_bytes_limit,
+            )
+        };
+        if soft_limit == 0 && hard_limit == 0 {
+            return Ok(());
+        }
+        let mut pending = self.pending_compaction_bytes();
+        if (soft_limit == 0 || pending < soft_limit) && (hard_limit == 0 || pending < hard_limit) {
+            return Ok(());
+        }
 
-            if self.options.sync_wal {
-                wal.sync()?;
+        let start = Instant::now();
+        if hard_limit > 0 && pending >= hard_limit {
+            log::warn!("Stopping writes: {} pending compaction bytes", pending);
+            self.info_log.warn(format_args!(
+                "Stopping writes: {} pending compaction bytes >= {}",
+                pending, hard_limit
+            ));
+            while pending >= hard_limit {
+                if !self.run_compaction().map_err(|e| self.set_background_error(e))? {
+                    break;
+                }
+                pending = self.pending_compaction_bytes();
             }
         }
+        if soft_limit > 0 && pending >= soft_limit {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        if let Some(ref stats) = self.statistics {
+            stats.record_tick(Ticker::StallMicros, start.elapsed().as_micros() as u64);
+        }
+        Ok(())
+    }
 
-        // Apply all operations to MemTable with consecutive sequence numbers
-        {
+    /// Writes `batch` to the WAL and the MemTable through the write queue.
+    ///
+    /// Concurrent callers are grouped: one of them appends the batches of
+    /// the whole group to the WAL with a single lock acquisition and sync,
+    /// then each caller inserts its own batch into the MemTable in parallel.
+    /// The group becomes visible to readers once every batch is inserted.
+    /// With `enable_pipelined_write`, the next group is written to the WAL
+    /// while this one is inserted.
+    fn write_internal(&self, batch: WriteBatch) -> Result<()> {
+        let (use_wal, sync_wal, pipelined) = {
+            let options = self.options.read();
+            (options.use_wal, options.sync_wal, options.enable_pipelined_write)
+        };
+        let commit = |batches: &[&WriteBatch], base_seq: u64| -> Result<()> {
+            if use_wal {
+                let start = Instant::now();
+                let mut wal = self.wal.write();
+
+                // One record per batch, so recovery never sees part of one
+                let mut seq = base_seq;
+                for batch in batches {
+                    let entry = match batch.iter().next() {
+                        Some(write_batch::WriteOp::Put { key, value }) if batch.len() == 1 => {
+                            wal::entry::encode_put(seq, key, value)
+                        }
+                        Some(write_batch::WriteOp::Delete { key }) if batch.len() == 1 => {
+                            wal::entry::encode_delete(seq, key)
+                        }
+                        _ => wal::entry::encode_batch(seq, batch),
+                    };
+                    wal.append(&entry).map_err(|e| self.check_wal_error(e))?;
+                    seq += batch.len() as u64;
+                }
+
+                if sync_wal {
+                    wal.sync().map_err(|e| self.check_wal_error(e))?;
+                }
+                perf_context::add_elapsed(start, |perf| &mut perf.wal_write_micros);
+            }
+            Ok(())
+        };
+
+        let mut bytes_written = 0;
+        let apply = |batch: &WriteBatch, base_seq: u64| {
+            let start = Instant::now();
             let memtable = self.memtable.read();
-            let mut seq = base_seq;
 
-            for op in batch.iter() {
+            for (seq, op) in (base_seq..).zip(batch.iter()) {
                 match op {
                     write_batch::WriteOp::Put { key, value } => {
                         memtable.put(key, value, seq);
+                        bytes_written += key.len() + value.len();
                     }
                     write_batch::WriteOp::Delete { key } => {
                         memtable.delete(key, seq);
+                        bytes_written += key.len();
+                    }
+                    write_batch::WriteOp::Append { key, suffix } => {
+                        memtable.append(key, suffix, seq);
+                        bytes_written += key.len() + suffix.len();
                     }
                 }
-                seq += 1;
             }
-        }
+            perf_context::add_elapsed(start, |perf| &mut perf.memtable_write_micros);
+        };
 
-        // Check if MemTable is full and needs flushing
-        let memtable_size = {
-            let memtable = self.memtable.read();
-            memtable.approximate_size()
+        let preserve_seconds = self.options.read().preserve_versions_seconds;
+        let timestamps = self.comparator.timestamp_size() > 0;
+        let publish = |batches: &[&WriteBatch], base_seq: u64, last_seq: u64| {
+            self.sequence.store(last_seq, Ordering::SeqCst);
+            if timestamps {
+                batches.iter().for_each(|batch| self.record_timestamps(batch));
+            }
+            if preserve_seconds > 0 {
+                self.sequence_times.record(
+                    last_seq,
+                    self.env.now_micros(),
+                    preserve_seconds.saturating_mul(1_000_000),
+                );
+            }
+            self.watchers.publish(batches, base_seq);
         };
 
-        if memtable_size >= self.options.memtable_size {
-            log::info!(
-                "MemTable is full ({} bytes >= {}), triggering freeze after batch write",
-                memtable_size,
-                self.options.memtable_size
-            );
-            self.freeze_memtable()?;
+        let keys = batch.len() as u64;
+        self.write_queue.write(batch, pipelined, commit, apply, publish)?;
+        self.record_write(keys, bytes_written as u64);
+        Ok(())
+    }
+
+    /// Counts keys and bytes written by `put`, `delete` or `write`.
+    fn record_write(&self, keys: u64, bytes: u64) {
+        if let Some(ref stats) = self.statistics {
+            stats.record_tick(Ticker::KeysWritten, keys);
+            stats.record_tick(Ticker::BytesWritten, bytes);
         }
+    }
 
-        Ok(())
+    /// Bytes held by the active and immutable MemTables.
+    fn memtable_memory(&self) -> usize {
+        let immutable: usize =
+            self.immutable_memtables.read().iter().map(|m| m.approximate_size()).sum();
+        self.memtable.read().approximate_size() + immutable
+    }
+
+    /// Reports the MemTable memory to the write buffer manager, if any,
+    /// and flushes if the shared budget is exceeded and this database is
+    /// the one to flush.
+    fn maybe_flush_write_buffer(&self) -> Result<()> {
+        let Some((ref manager, ref usage)) = self.write_buffer else {
+            return Ok(());
+        };
+        usage.set(self.memtable_memory());
+        if !usage.take_flush_request() && !manager.should_flush(usage) {
+            return Ok(());
+        }
+        log::info!(
+            "Write buffer manager over its budget of {} bytes, flushing",
+            manager.buffer_size()
+        );
+        self.info_log.info(format_args!(
+            "Flushing for the write buffer manager: {} of {} bytes used",
+            manager.memory_usage(),
+            manager.buffer_size()
+        ));
+        self.flush_all()
     }
 
     /// Freezes the current MemTable and creates a new one.
     ///
-    /// This moves the current mutable MemTable to the immutable list
-    /// and creates a fresh MemTable for new writes.
+    /// This moves the current mutable MemTable to the immutable list and
+    /// creates a fresh MemTable for new writes, together with a new WAL.
+    /// The old WAL then holds exactly the writes of the frozen MemTable and
+    /// is deleted once that is flushed (see
+    /// [`retire_flushed_wals`](Self::retire_flushed_wals)).
     fn freeze_memtable(&self) -> Result<()> {
+        // No write group may sit between its WAL record and its MemTable
+        // insert: it would be logged in the old WAL but land in the new
+        // MemTable
+        let write_pause = self.write_queue.pause();
+        let mut wal = self.wal.write();
+        wal.sync()?;
+        let new_wal = self.new_wal()?;
+
         let mut memtable = self.memtable.write();
         let mut immutable = self.immutable_memtables.write();
 
@@ -748,57 +1830,279 @@ impl DB {
         let current_seq = self.sequence.load(Ordering::SeqCst);
 
         // Move current memtable to immutable list
-        let old_memtable = std::mem::replace(&mut *memtable, MemTable::new(current_seq + 1));
+        let old_memtable = std::mem::replace(
+            &mut *memtable,
+            MemTable::with_comparator(current_seq + 1, Arc::clone(&self.comparator)),
+        );
         immutable.push(Arc::new(old_memtable));
+        let old_wal = std::mem::replace(&mut *wal, new_wal);
+        let old_path = old_wal.path().to_path_buf();
+        self.frozen_wals.lock().push((old_path.clone(), current_seq));
 
         log::info!("MemTable frozen, {} immutable memtables waiting for flush", immutable.len());
-
+        drop((immutable, memtable, old_wal));
+        let new_path = wal.path().to_path_buf();
+        drop(wal);
+        drop(write_pause);
+
+        self.notify(|l| {
+            l.on_wal_rotated(&listener::WalRotationInfo {
+                old_file_path: old_path.clone(),
+                new_file_path: new_path.clone(),
+            })
+        });
         Ok(())
     }
 
-    /// Flushes an immutable MemTable to an SSTable file.
+    /// Flushes immutable MemTables, given oldest first, to Level 0.
+    ///
+    /// Up to `flush_threads` MemTables are written at the same time. File
+    /// numbers are assigned up front and the new SSTables are installed in
+    /// MemTable order, so Level 0 stays ordered newest first. Each MemTable
+    /// is removed from the immutable list once its SSTable is installed. If
+    /// a write fails, the tables written for newer MemTables are deleted and
+    /// those MemTables stay queued, so reads never see a newer SSTable while
+    /// an older MemTable still shadows it.
+    fn flush_memtables(&self, memtables: &[Arc<MemTable>]) -> Result<()> {
+        let threads = self.options.read().flush_threads.max(1);
+
+        for group in memtables.chunks(threads) {
+            let file_numbers: Vec<u64> = group
+                .iter()
+                .map(|_| self.next_file_number.fetch_add(1, Ordering::SeqCst))
+                .collect();
+
+            let written: Vec<Result<Option<FlushedTable>>> = if group.len() == 1 {
+                vec![self.write_flush_table(&group[0], file_numbers[0])]
+            } else {
+                std::thread::scope(|s| {
+                    let handles: Vec<_> = group
+                        .iter()
+                        .zip(&file_numbers)
+                        .map(|(memtable, &file_number)| {
+                            s.spawn(move || self.write_flush_table(memtable, file_number))
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|handle| handle.join().expect("flush thread panicked"))
+                        .collect()
+                })
+            };
+
+            let mut written = group.iter().zip(written);
+            for (memtable, result) in written.by_ref() {
+                match result {
+                    Ok(table) => {
+                        if let Some(table) = table {
+                            self.install_flushed_table(table);
+                        }
+                        self.immutable_memtables
+                            .write()
+                            .retain(|immutable| !Arc::ptr_eq(immutable, memtable));
+                        self.retire_memtable(memtable);
+                    }
+                    Err(e) => {
+                        for (_, result) in written {
+                            if let Ok(Some(table)) = result {
+                                self.remove_unused_table(table);
+                            }
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        self.retire_prefix_tombstones()
+    }
+
+    /// Writes an immutable MemTable to the SSTable `file_number`.
     ///
-    /// This method:
-    /// 1. Iterates through all entries in the MemTable
-    /// 2. Writes them to an SSTable using SSTableBuilder
-    /// 3. Adds the new SSTable to Level 0
-    /// 4. Returns the file number of the created SSTable
-    fn flush_memtable_to_sstable(&self, memtable: &MemTable) -> Result<u64> {
-        // Generate a new file number
-        let file_number = self.next_file_number.fetch_add(1, Ordering::SeqCst);
+    /// Returns the new table, not yet added to Level 0, or `None` if the
+    /// MemTable had nothing to write.
+    fn write_flush_table(
+        &self,
+        memtable: &MemTable,
+        file_number: u64,
+    ) -> Result<Option<FlushedTable>> {
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Flush);
+
+        if let Some(ref manager) = self.sst_file_manager {
+            if manager.is_max_allowed_space_reached() {
+                return Err(Error::NoSpace(format!(
+                    "SSTables use {} bytes, the maximum allowed is {}",
+                    manager.total_size(),
+                    manager.get_max_allowed_space_usage()
+                )));
+            }
+        }
 
         // Create SSTable file path
         let sstable_path = self.path.join(format!("{:06}.sst", file_number));
 
         log::info!("Starting flush of MemTable to SSTable: {:?}", sstable_path);
+        self.info_log.info(format_args!(
+            "Flush started: {:06}.sst, {} MemTable entries",
+            file_number,
+            memtable.len()
+        ));
+        let mut info = listener::FlushJobInfo {
+            file_number,
+            file_path: sstable_path.clone(),
+            entry_count: memtable.len(),
+            file_size: 0,
+        };
+        self.notify(|l| l.on_flush_begin(&info));
+
+        let table = match self.write_sstable(memtable, &sstable_path) {
+            Ok(Some(table)) => table,
+            // No file was created (we still consumed the file number, which is fine)
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                // Don't leave a partially written file behind (e.g. when the disk is full)
+                if let Err(remove_err) = self.env.remove_file(&sstable_path) {
+                    if remove_err.kind() != std::io::ErrorKind::NotFound {
+                        log::warn!(
+                            "Failed to remove partial SSTable {:?}: {}",
+                            sstable_path,
+                            remove_err
+                        );
+                    }
+                }
+                return Err(e);
+            }
+        };
+        info.entry_count = table.num_entries as usize;
+        info.file_size = table.file_size;
+
+        log::info!(
+            "Flush completed: {} entries written, file size: {} bytes",
+            info.entry_count,
+            info.file_size
+        );
+        self.info_log.info(format_args!(
+            "Flush completed: {:06}.sst, {} entries, {} bytes",
+            file_number, info.entry_count, info.file_size
+        ));
+        if let Some(ref stats) = self.statistics {
+            stats.record_tick(Ticker::BytesFlushed, info.file_size);
+        }
 
+        // Open the SSTable from what the builder already has in memory
+        let reader =
+            SSTableReader::from_metadata(table, Some(Arc::clone(&self.block_cache)), &*self.env)?
+                .with_record_authenticator(self.record_auth.clone())?
+                .with_comparator(Arc::clone(&self.comparator));
+        let reader = Arc::new(self.attach_table_cache(reader));
+
+        Ok(Some(FlushedTable { reader, info }))
+    }
+
+    /// Adds a flushed SSTable to the front of Level 0 (newest files first).
+    fn install_flushed_table(&self, table: FlushedTable) {
+        let FlushedTable { reader, info } = table;
+        if let Some(ref manager) = self.sst_file_manager {
+            manager.on_add_file(&info.file_path, info.file_size);
+        }
+        self.sstables.write()[0].insert(0, reader);
+
+        self.notify(|l| {
+            l.on_table_file_created(&listener::TableFileCreationInfo {
+                file_number: info.file_number,
+                file_path: info.file_path.clone(),
+                file_size: info.file_size,
+                level: 0,
+                reason: listener::TableFileReason::Flush,
+            });
+            l.on_flush_completed(&info);
+        });
+    }
+
+    /// Hands the file of a new SSTable to the table cache, if any.
+    fn attach_table_cache(&self, reader: SSTableReader) -> SSTableReader {
+        match self.table_cache {
+            Some(ref cache) => reader.with_table_cache(Arc::clone(cache)),
+            None => reader,
+        }
+    }
+
+    /// Deletes a flushed SSTable that will not be installed.
+    fn remove_unused_table(&self, table: FlushedTable) {
+        let path = table.info.file_path;
+        drop(table.reader);
+        if let Err(e) = self.env.remove_file(&path) {
+            log::warn!("Failed to remove unused SSTable {:?}: {}", path, e);
+        }
+    }
+
+    /// Writes the entries of a MemTable to a new SSTable at `sstable_path`.
+    ///
+    /// Returns the new table's metadata, or `None` if there was nothing to
+    /// write.
+    fn write_sstable(
+        &self,
+        memtable: &MemTable,
+        sstable_path: &std::path::Path,
+    ) -> Result<Option<TableMetadata>> {
         // Create SSTable builder
-        let mut builder = SSTableBuilder::new(&sstable_path)?;
-        builder.set_block_size(self.options.block_size);
-        builder.set_compression(self.options.compression);
+        let mut builder = SSTableBuilder::new_with_env(sstable_path, Arc::clone(&self.env))?;
+        builder.set_comparator(Arc::clone(&self.comparator));
+        {
+            let options = self.options.read();
+            builder.set_block_size(options.block_size);
+            builder.set_compression(options.compression_for_level(0));
+            builder.set_checksum_type(options.checksum);
+            match options.bloom_filter_for_level(0) {
+                Some(rate) => builder.set_bloom_filter_fp_rate(rate),
+                None => builder.set_bloom_filter_enabled(false),
+            }
+            builder.set_prefix_extractor(options.prefix_extractor);
+            if let Some(limiter) = &options.rate_limiter {
+                builder.set_rate_limiter(Arc::clone(limiter), rate_limiter::IoPriority::High);
+            }
+        }
+        if let Some(ref authenticator) = self.record_auth {
+            builder.set_record_authenticator(Arc::clone(authenticator))?;
+        }
 
-        // Iterate through MemTable and add entries to SSTable
-        // We only keep the latest version of each user key (skip older versions)
+        // Iterate through MemTable and add entries to SSTable. Versions of
+        // a key come newest first; older versions are only kept while a
+        // read at the history cutoff or later can see them
         let mut entry_count = 0;
         let mut last_user_key: Option<Vec<u8>> = None;
+        let mut keep_older = false;
+        let prefix_deletes = self.prefix_tombstones.get();
+        let history_cutoff = self.history_cutoff();
 
         for entry in memtable.iter() {
             let user_key = entry.user_key();
-            let value = entry.value();
 
-            // Skip if this is an older version of the same key
-            if let Some(ref last_key) = last_user_key {
-                if last_key.as_slice() == user_key {
-                    continue; // Skip older versions
-                }
+            if last_user_key.as_deref() != Some(user_key) {
+                last_user_key = Some(user_key.to_vec());
+            } else if !keep_older {
+                continue;
             }
+            keep_older = entry.sequence() > history_cutoff;
+
+            // Versions covered by a prefix deletion are written as deletions,
+            // and appends are collapsed with the older versions they extend
+            let collapsed;
+            let value = if prefix_deletes.covers_sequence(user_key, entry.sequence()) {
+                &[][..]
+            } else if entry.value_type() == ValueType::Append {
+                collapsed = self
+                    .get_value_at_sequence(user_key, entry.sequence(), &ReadOptions::default())?
+                    .unwrap_or_default();
+                &collapsed[..]
+            } else {
+                entry.value()
+            };
 
             // For SSTable at Level 0, we store both values and tombstones
             // Tombstones will be removed during compaction
-            builder.add(user_key, value)?;
+            builder.add_version(user_key, entry.sequence(), value)?;
             entry_count += 1;
-
-            last_user_key = Some(user_key.to_vec());
         }
 
         // Check if we have any entries to flush
@@ -808,41 +2112,15 @@ impl DB {
                 "MemTable contains no entries to flush (only tombstones or duplicates), skipping SSTable creation"
             );
 
-            // Abandon the builder (don't write footer)
+            // Abandon the builder (removes the temporary file)
             builder.abandon()?;
 
-            // Remove the incomplete SSTable file
-            if sstable_path.exists() {
-                std::fs::remove_file(&sstable_path)?;
-            }
-
-            // Return a special value to indicate no file was created
-            // (we still consumed the file number, which is fine)
-            return Ok(0);
-        }
-
-        // Finish building the SSTable
-        let file_size = builder.finish()?;
-
-        log::info!(
-            "Flush completed: {} entries written, file size: {} bytes",
-            entry_count,
-            file_size
-        );
-
-        // Open the SSTable for reading with block cache
-        let reader = Arc::new(SSTableReader::open_with_cache(
-            &sstable_path,
-            Some(Arc::clone(&self.block_cache)),
-        )?);
-
-        // Add to Level 0 at the front (newest files first)
-        {
-            let mut sstables = self.sstables.write();
-            sstables[0].insert(0, reader);
+            return Ok(None);
         }
 
-        Ok(file_number)
+        // Finish building the SSTable; it is renamed into place and its
+        // directory entry synced before it becomes visible
+        Ok(Some(builder.finish()?))
     }
 
     /// Manually triggers a flush of the current MemTable.
@@ -866,6 +2144,15 @@ impl DB {
     /// # }
     /// ```
     pub fn flush(&self) -> Result<()> {
+        self.check_background_error()?;
+        self.flush_all()
+    }
+
+    /// Flushes all MemTables and runs any compaction that becomes necessary.
+    ///
+    /// Failures while writing SSTables or compacting put the database into
+    /// the background error state.
+    fn flush_all(&self) -> Result<()> {
         // Step 1: Freeze the current MemTable if it's not empty
         {
             let memtable = self.memtable.read();
@@ -875,75 +2162,628 @@ impl DB {
             }
         }
 
-        // Step 2: Flush all immutable MemTables
+        // Step 2: Flush all immutable MemTables, oldest first. Each stays in
+        // the immutable list until its flush succeeds so a failed flush can
+        // be retried by `resume()` without losing data.
+        let flush_guard = self.flush_lock.lock();
         loop {
-            // Get the oldest immutable MemTable
-            let memtable_to_flush = {
-                let mut immutable = self.immutable_memtables.write();
-                if immutable.is_empty() {
-                    break;
-                }
-                immutable.remove(0) // Remove from front (FIFO)
-            };
-
-            // Flush it to SSTable
-            self.flush_memtable_to_sstable(&memtable_to_flush)?;
+            let memtables = self.immutable_memtables.read().clone();
+            if memtables.is_empty() {
+                break;
+            }
+            if let Err(e) = self.flush_memtables(&memtables) {
+                return Err(self.set_background_error(e));
+            }
         }
 
-        // Step 3: Rotate WAL after successful flush
-        self.rotate_wal()?;
+        // Step 3: Delete the WAL files whose writes are all flushed
+        self.retire_flushed_wals().map_err(|e| self.set_background_error(e))?;
+        drop(flush_guard);
+
+        if let Some((_, ref usage)) = self.write_buffer {
+            usage.take_flush_request();
+            usage.set(self.memtable_memory());
+        }
 
         // Step 4: Check if compaction is needed
-        self.maybe_trigger_compaction()?;
+        self.maybe_trigger_compaction().map_err(|e| self.set_background_error(e))?;
 
         Ok(())
     }
 
-    /// Rotates the WAL file.
+    /// Returns the error that put the database into read-only mode, if any.
     ///
-    /// This creates a new WAL file and removes the old one after a successful flush.
-    fn rotate_wal(&self) -> Result<()> {
-        let new_wal_number = self.wal_file_number.fetch_add(1, Ordering::SeqCst) + 1;
-        let new_wal_path = self.path.join(wal::wal_filename(new_wal_number));
-
-        log::info!("Rotating WAL to {:?}", new_wal_path);
-
-        // Create new WAL
-        let new_wal = WAL::open(&new_wal_path)?;
+    /// When a flush or compaction fails (e.g. the disk is full), the database
+    /// stops accepting writes that it might not be able to persist. Reads keep
+    /// working. Call [`resume`](Self::resume) once the cause has been fixed;
+    /// after an [`Error::NoSpace`] this is also retried automatically by
+    /// subsequent writes.
+    pub fn background_error(&self) -> Option<Error> {
+        self.background_error
+            .read()
+            .as_ref()
+            .map(|state| Error::BackgroundError(state.message.clone()))
+    }
 
-        // Replace the old WAL
-        let old_wal = {
-            let mut wal = self.wal.write();
-            std::mem::replace(&mut *wal, new_wal)
+    /// Clears the background error and retries the work that failed.
+    ///
+    /// Writes move on to a new WAL and pending MemTables are flushed again;
+    /// if that succeeds the database accepts writes again. If it fails, the database stays read-only and
+    /// the new error is returned.
+    pub fn resume(&self) -> Result<()> {
+        let previous = match self.background_error.write().take() {
+            Some(state) => state,
+            None => return Ok(()),
         };
 
-        // Close and delete the old WAL file
-        let old_path = old_wal.path().to_path_buf();
-        drop(old_wal);
-
-        // Remove old WAL file
-        if old_path.exists() {
-            std::fs::remove_file(&old_path)?;
-            log::info!("Removed old WAL file: {:?}", old_path);
+        log::info!("Resuming after background error: {}", previous.message);
+        self.info_log
+            .info(format_args!("Resuming after background error: {}", previous.message));
+        // Leave a WAL a failed write may have left a partial group in
+        self.freeze_memtable().map_err(|e| self.set_background_error(e))?;
+        self.flush_all()?;
+
+        if previous.no_space {
+            // Take back the space released when the disk filled up
+            let reserved = self.options.read().reserved_disk_space;
+            if let Err(e) = util::reserve_space(&*self.env, &self.path, reserved) {
+                log::warn!("Failed to reserve {} bytes of disk space: {}", reserved, e);
+            }
         }
-
         Ok(())
     }
 
-    /// Check if compaction is needed and trigger it if necessary
+    /// Discards all data in the database.
     ///
-    /// This is called after flush to check if any level needs compaction
-    pub fn maybe_trigger_compaction(&self) -> Result<()> {
-        let sstables = self.sstables.read();
+    /// A single manifest edit removes every SSTable together with the
+    /// writes still in the WAL, so after a crash either all data or none
+    /// of it is gone. The MemTables are emptied and the WAL starts over.
+    /// SSTable files are deleted once iterators reading them are dropped,
+    /// through [`Options::sst_file_manager`] if one is set, so at its
+    /// deletion rate. Blob files and prefix tombstones go as well.
+    ///
+    /// Flushes and compactions wait until it is done, and writes until the
+    /// MemTable and WAL have been replaced. A write that runs concurrently
+    /// lands on either side of the drop, but once it has returned it is
+    /// either gone or durable like any other write.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// # let db = DB::open("./data", Options::default())?;
+    /// db.put(b"key", b"value")?;
+    /// db.drop_all()?;
+    /// assert_eq!(db.get(b"key")?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drop_all(&self) -> Result<()> {
+        self.check_background_error()?;
+        // Taken first, as by put_reader, whose put may flush or compact
+        let _blobs = self.blob_lock.write();
+        let _compaction_guard = self.compaction_lock.lock();
+        let _flush_guard = self.flush_lock.lock();
+        // No write group may sit between its WAL record and its publication:
+        // it would be numbered after `sequence` yet logged only in the old WAL
+        let write_pause = self.write_queue.pause();
+
+        let (sequence, tables, old_wal) = {
+            let mut version_set = self.version_set.write();
+            let mut wal = self.wal.write();
 
-        // Check if compaction is needed
-        let task = {
-            let task = self.compaction_picker.pick_compaction(&sstables);
+            let sequence = self.sequence.load(Ordering::SeqCst);
+            let file_numbers =
+                self.sstables.read().iter().flatten().filter_map(|t| t.file_number()).collect();
+            version_set
+                .log_edit(&VersionEdit::DropAll { file_numbers, sequence })
+                .map_err(|e| self.set_background_error(e))?;
+            drop(version_set);
+
+            // The drop is committed; reopening skips the WAL records up to
+            // `sequence` from here on
+            let tables: Vec<Arc<SSTableReader>> = {
+                let mut sstables = self.sstables.write();
+                let levels = sstables.len();
+                std::mem::replace(&mut *sstables, vec![Vec::new(); levels])
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            };
+            let memtable = std::mem::replace(
+                &mut *self.memtable.write(),
+                MemTable::with_comparator(sequence + 1, Arc::clone(&self.comparator)),
+            );
+            self.retire_memtable(&memtable);
+            for memtable in self.immutable_memtables.write().drain(..) {
+                self.retire_memtable(&memtable);
+            }
+
+            let new_wal = self.new_wal().map_err(|e| self.set_background_error(e))?;
+            (sequence, tables, std::mem::replace(&mut *wal, new_wal))
+        };
+        drop(write_pause);
+        let old_path = old_wal.path().to_path_buf();
+        drop(old_wal);
+        let frozen: Vec<PathBuf> =
+            self.frozen_wals.lock().drain(..).map(|(path, _)| path).collect();
+        for path in frozen.iter().chain(std::iter::once(&old_path)) {
+            self.retire_wal(path).map_err(|e| self.set_background_error(e))?;
+        }
+        let new_path = self.wal.read().path().to_path_buf();
+        self.notify(|l| {
+            l.on_wal_rotated(&listener::WalRotationInfo {
+                old_file_path: old_path.clone(),
+                new_file_path: new_path.clone(),
+            })
+        });
+
+        log::info!("Dropped all data up to sequence {}: {} SSTables", sequence, tables.len());
+        self.info_log.info(format_args!(
+            "Dropped all data up to sequence {}: {} SSTables",
+            sequence,
+            tables.len()
+        ));
+
+        // Delete the files once iterators reading them are done
+        {
+            let mut obsolete_files = self.obsolete_files.lock();
+            for table in &tables {
+                if let Some(file_number) = table.file_number() {
+                    obsolete_files.push(ObsoleteFile {
+                        file_number,
+                        path: table.file_path().to_path_buf(),
+                        table: Arc::downgrade(table),
+                    });
+                }
+            }
+        }
+        drop(tables);
+        self.purge_obsolete_files();
+
+        if let Some((_, ref usage)) = self.write_buffer {
+            usage.set(self.memtable_memory());
+        }
+        self.retire_prefix_tombstones()?;
+        self.delete_unreferenced_blobs()?;
+        Ok(())
+    }
+
+    /// Deletes the SSTables whose keys all lie in `[start, end)`, and
+    /// returns how many were deleted.
+    ///
+    /// The files are removed from the manifest without rewriting any data,
+    /// so the space of a retired key range is released right away instead
+    /// of after it has been compacted. Keys in the range that are still in
+    /// the MemTables or in SSTables reaching beyond the range stay, and an
+    /// older version of such a key may become visible again once a newer
+    /// one is deleted with its file. Delete the remaining keys with
+    /// [`delete`](Self::delete) or [`delete_prefix`](Self::delete_prefix);
+    /// compactions then reclaim their space.
+    ///
+    /// SSTable files are deleted as after a compaction: once iterators
+    /// reading them are dropped, through [`Options::sst_file_manager`] if
+    /// one is set.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// # let db = DB::open("./data", Options::default())?;
+    /// // Drop the partition of January
+    /// let deleted = db.delete_files_in_range(b"2024-01", b"2024-02")?;
+    /// println!("deleted {} SSTables", deleted);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn delete_files_in_range(&self, start: &[u8], end: &[u8]) -> Result<usize> {
+        self.check_background_error()?;
+        let comparator = &*self.comparator;
+        if comparator.compare(start, end) != CmpOrdering::Less {
+            return Ok(0);
+        }
+        let _compaction_guard = self.compaction_lock.lock();
+        let _flush_guard = self.flush_lock.lock();
+
+        let mut deleted = Vec::new();
+        {
+            let mut version_set = self.version_set.write();
+            let mut sstables = self.sstables.write();
+            for (level, tables) in sstables.iter().enumerate() {
+                for table in tables {
+                    let contained = matches!(
+                        table.key_range()?,
+                        Some((smallest, largest))
+                            if comparator.compare(smallest, start) != CmpOrdering::Less
+                                && comparator.compare(largest, end) == CmpOrdering::Less
+                    );
+                    if let Some(file_number) = table.file_number().filter(|_| contained) {
+                        deleted.push((level, file_number, Arc::clone(table)));
+                    }
+                }
+            }
+
+            for (level, file_number, _) in &deleted {
+                let edit = VersionEdit::DeleteFile { level: *level, file_number: *file_number };
+                version_set.log_edit(&edit).map_err(|e| self.set_background_error(e))?;
+            }
+            for (level, _, table) in &deleted {
+                sstables[*level].retain(|reader| !Arc::ptr_eq(reader, table));
+            }
+        }
+
+        log::info!(
+            "Deleted {} SSTables in range [{:?}, {:?})",
+            deleted.len(),
+            String::from_utf8_lossy(start),
+            String::from_utf8_lossy(end)
+        );
+        self.info_log.info(format_args!(
+            "Deleted {} SSTables in range [{:?}, {:?})",
+            deleted.len(),
+            String::from_utf8_lossy(start),
+            String::from_utf8_lossy(end)
+        ));
+
+        let count = deleted.len();
+        {
+            let mut obsolete_files = self.obsolete_files.lock();
+            for (_, file_number, table) in &deleted {
+                obsolete_files.push(ObsoleteFile {
+                    file_number: *file_number,
+                    path: table.file_path().to_path_buf(),
+                    table: Arc::downgrade(table),
+                });
+            }
+        }
+        drop(deleted);
+        self.purge_obsolete_files();
+        self.retire_prefix_tombstones()?;
+        Ok(count)
+    }
+
+    /// Re-encrypts every file of the database that is not encrypted with
+    /// the active key of [`Options::key_provider`], and returns how many
+    /// were rewritten.
+    ///
+    /// Run this after making a new key active: the MemTable is flushed so
+    /// the WAL starts over, the MANIFEST and `LOG` are started anew, and
+    /// SSTables, blob files and archived WAL files still encrypted with an
+    /// older key are copied with the active one. Afterwards no file needs
+    /// the older keys any more. Flushes and compactions wait until it is
+    /// done; reads and writes go on.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the database is not encrypted.
+    #[cfg(feature = "encryption")]
+    pub fn rewrite_with_latest_key(&self) -> Result<usize> {
+        let Some(encrypted_env) = self.encrypted_env.as_ref() else {
+            return Err(Error::invalid_argument("the database is not encrypted"));
+        };
+        self.check_background_error()?;
+        self.flush_all()?;
+
+        let _blobs = self.blob_lock.write();
+        let _compaction = self.compaction_lock.lock();
+        let _flush = self.flush_lock.lock();
+        self.version_set.write().rewrite_manifest()?;
+        self.info_log.roll()?;
+
+        let mut rewritten = 0;
+        for dir in [
+            self.path.clone(),
+            self.path.join(blob::BLOB_DIR),
+            self.path.join(wal::ARCHIVE_DIR),
+        ] {
+            if !self.env.file_exists(&dir) {
+                continue;
+            }
+            for name in self.env.get_children(&dir)? {
+                let path = dir.join(&name);
+                // Left behind by an interrupted rewrite
+                if name.ends_with(".rekey") {
+                    self.env.remove_file(&path)?;
+                    continue;
+                }
+                // Directories and files that are not encrypted
+                if encrypted_env.key_version(&path).is_err() {
+                    continue;
+                }
+                if encrypted_env.reencrypt_file(&path)? {
+                    rewritten += 1;
+                }
+            }
+            self.env.sync_dir(&dir)?;
+        }
+
+        log::info!("Re-encrypted {} files with the latest key", rewritten);
+        self.info_log
+            .info(format_args!("Re-encrypted {} files with the latest key", rewritten));
+        Ok(rewritten)
+    }
+
+    /// Returns an error if the database is in the background error state.
+    ///
+    /// If the disk was full, this first tries to resume (at most once per
+    /// `NO_SPACE_RETRY_INTERVAL`) in case space has been freed since.
+    fn check_background_error(&self) -> Result<()> {
+        self.check_writable()?;
+        {
+            let mut guard = self.background_error.write();
+            match guard.as_mut() {
+                None => return Ok(()),
+                Some(state)
+                    if state.no_space
+                        && self.env.now_micros().saturating_sub(state.last_retry)
+                            >= NO_SPACE_RETRY_INTERVAL.as_micros() as u64 =>
+                {
+                    state.last_retry = self.env.now_micros();
+                }
+                Some(state) => return Err(Error::BackgroundError(state.message.clone())),
+            }
+        }
+
+        log::info!("Checking whether disk space has been freed");
+        self.resume()
+    }
+
+    /// Returns an error if the database was opened with
+    /// [`DB::open_read_only`].
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::InvalidState("the database is opened read-only".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Records a failed flush or compaction and returns the original error.
+    ///
+    /// When the disk is full, the reserved space is released so that the
+    /// retry has room to complete.
+    fn set_background_error(&self, error: Error) -> Error {
+        log::error!("Background error, database is now read-only: {}", error);
+        self.info_log
+            .error(format_args!("Background error, database is now read-only: {}", error));
+        let no_space = matches!(error, Error::NoSpace(_));
+        if no_space {
+            if let Err(e) = util::release_reserved_space(&*self.env, &self.path) {
+                log::warn!("Failed to release reserved disk space: {}", e);
+            }
+        }
+        let is_new = {
+            let mut state = self.background_error.write();
+            let is_new = state.is_none();
+            state.get_or_insert_with(|| BackgroundErrorState {
+                message: error.to_string(),
+                no_space,
+                last_retry: self.env.now_micros(),
+            });
+            is_new
+        };
+        if is_new {
+            self.notify(|l| l.on_background_error(&error));
+        }
+        error
+    }
+
+    /// Calls `event` on every registered listener.
+    fn notify(&self, event: impl Fn(&dyn EventListener)) {
+        for listener in &self.listeners {
+            event(listener.as_ref());
+        }
+    }
+
+    /// Puts the database into read-only mode after a failed WAL write.
+    ///
+    /// A failed append or sync may leave part of the group in the WAL, so
+    /// further appends to it are not safe; `resume()` switches to a new WAL
+    /// and flushes the MemTable.
+    fn check_wal_error(&self, error: Error) -> Error {
+        self.set_background_error(error)
+    }
+
+    /// Deletes (or archives) the replaced WAL files whose writes are all
+    /// in SSTables. Called with the flush lock held.
+    ///
+    /// Every unflushed write is in a non-empty MemTable, whose start
+    /// sequence number is at most that of its first write, so a file whose
+    /// last sequence number is below all of them is no longer needed. The
+    /// manifest records the newest such number first: once the files are
+    /// gone it is the only place that remembers it.
+    fn retire_flushed_wals(&self) -> Result<()> {
+        let frozen = self.frozen_wals.lock().clone();
+        let unflushed = {
+            let memtable = self.memtable.read();
+            let immutable = self.immutable_memtables.read();
+            immutable
+                .iter()
+                .map(|m| &**m)
+                .chain(std::iter::once(&*memtable))
+                .filter(|m| !m.is_empty())
+                .map(MemTable::start_sequence)
+                .min()
+                .unwrap_or(u64::MAX)
+        };
+        let flushed = frozen.iter().take_while(|(_, last)| *last < unflushed).count();
+        let Some(&(_, last_sequence)) = frozen[..flushed].last() else {
+            return Ok(());
+        };
+
+        self.version_set
+            .write()
+            .log_edit(&VersionEdit::SetSequenceNumber(last_sequence))?;
+        for (path, _) in &frozen[..flushed] {
+            self.retire_wal(path)?;
+            self.frozen_wals.lock().remove(0);
+        }
+        Ok(())
+    }
+
+    /// Creates the WAL file that follows the current one.
+    fn new_wal(&self) -> Result<WAL> {
+        let new_wal_number = self.wal_file_number.fetch_add(1, Ordering::SeqCst) + 1;
+        let new_wal_path = self.path.join(wal::wal_filename(new_wal_number));
+
+        log::info!("Rotating WAL to {:?}", new_wal_path);
+
+        let wal_buffer_size = self.options.read().wal_buffer_size;
+        let new_wal = WAL::open_with_env(&new_wal_path, &*self.env)?
+            .with_buffer_size(wal_buffer_size)
+            .with_authenticator(self.record_auth.clone())?;
+        self.env.sync_dir(&self.path)?;
+        Ok(new_wal)
+    }
+
+    /// Replaced WAL files not deleted yet, oldest first, with the last
+    /// sequence number each holds. No file is added while the WAL lock is
+    /// held, and none is deleted while the flush lock is.
+    pub(crate) fn frozen_wals(&self) -> Vec<(PathBuf, u64)> {
+        self.frozen_wals.lock().clone()
+    }
+
+    /// Deletes (or archives) a WAL file that has been replaced.
+    fn retire_wal(&self, old_path: &Path) -> Result<()> {
+        // Change feeds need the WAL files they have not read yet
+        let archive_size_limit = self.options.read().wal_archive_size_limit;
+        let pinned = self.changefeeds.min_position();
+        if self.env.file_exists(old_path) {
+            if archive_size_limit > 0 || pinned.is_some() {
+                self.archive_wal(old_path)?;
+            } else {
+                self.env.remove_file(old_path)?;
+                self.env.sync_dir(&self.path)?;
+                log::info!("Removed old WAL file: {:?}", old_path);
+            }
+        }
+        self.purge_wal_archive(archive_size_limit, pinned)
+    }
+
+    /// Moves a rotated WAL file into the archive directory.
+    fn archive_wal(&self, wal_path: &Path) -> Result<()> {
+        let archive_dir = self.path.join(wal::ARCHIVE_DIR);
+        self.env.create_dir_all(&archive_dir)?;
+        let name = wal_path.file_name().expect("WAL path has a file name");
+        self.env.rename_file(wal_path, &archive_dir.join(name))?;
+        self.env.sync_dir(&self.path)?;
+        log::info!("Archived old WAL file: {:?}", wal_path);
+        Ok(())
+    }
+
+    /// Deletes the oldest archived WAL files until they take at most
+    /// `size_limit` bytes, keeping those with writes after `pinned`.
+    fn purge_wal_archive(&self, size_limit: u64, pinned: Option<u64>) -> Result<()> {
+        let archive_dir = self.path.join(wal::ARCHIVE_DIR);
+        if !self.env.file_exists(&archive_dir) {
+            return Ok(());
+        }
+
+        let archived = transaction_log::archived_wal_files(&*self.env, &self.path)?;
+        let mut total: u64 = archived.iter().map(|(_, size)| size).sum();
+        for (i, (path, size)) in archived.iter().enumerate() {
+            if total <= size_limit {
+                break;
+            }
+            if let Some(position) = pinned {
+                // A file ends where the next one with writes starts
+                let mut next_sequence = None;
+                for (later, _) in &archived[i + 1..] {
+                    next_sequence = transaction_log::first_sequence(&*self.env, later)?;
+                    if next_sequence.is_some() {
+                        break;
+                    }
+                }
+                let end = next_sequence
+                    .map_or_else(|| self.sequence.load(Ordering::SeqCst), |next| next - 1);
+                if end > position {
+                    break;
+                }
+            }
+            self.env.remove_file(path)?;
+            total -= size;
+            log::info!("Removed archived WAL file: {:?}", path);
+        }
+        self.env.sync_dir(&archive_dir)?;
+        Ok(())
+    }
+
+    /// Check if compaction is needed and trigger it if necessary
+    ///
+    /// This is called after flush to check if any level needs compaction
+    pub fn maybe_trigger_compaction(&self) -> Result<()> {
+        self.check_writable()?;
+        self.run_compaction().map(|_| ())
+    }
+
+    /// Compacts the SSTables numbered `file_numbers` into `output_level`.
+    ///
+    /// For compaction policies outside the engine: the files are chosen by
+    /// the caller, for example from the
+    /// [`pending_compaction`](lsm_state::LsmState::pending_compaction) of
+    /// [`dump_lsm_state`](Self::dump_lsm_state) or the file numbers it
+    /// lists per level. They are checked against the current SSTables (see
+    /// [`CompactionPicker::pick_files`]) and merged with the files of
+    /// `output_level` they overlap. Returns [`Error::InvalidArgument`] if
+    /// they cannot be compacted as asked, and [`Error::NoSpace`] if the
+    /// output could exceed the space limit of
+    /// [`Options::sst_file_manager`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// # let db = DB::open("./data", Options::default())?;
+    /// if let Some(task) = db.dump_lsm_state()?.pending_compaction {
+    ///     db.compact_files(&task.input_file_numbers, task.output_level)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compact_files(&self, file_numbers: &[u64], output_level: usize) -> Result<()> {
+        self.check_background_error()?;
+        let _compaction = self.compaction_lock.lock();
+        let task = {
+            let sstables = self.sstables.read();
+            self.compaction_picker
+                .read()
+                .pick_files(&sstables, file_numbers, output_level)?
+        };
+
+        if let Some(ref manager) = self.sst_file_manager {
+            let input_size =
+                task.inputs.iter().chain(&task.overlapping).map(|t| t.file_size()).sum();
+            if !manager.enough_room_for_compaction(input_size) {
+                return Err(Error::NoSpace(format!(
+                    "compacting {} more bytes would exceed the space limit",
+                    input_size
+                )));
+            }
+        }
+
+        log::info!(
+            "Compacting files {:?}: level {} -> level {}, {} overlapping files",
+            file_numbers,
+            task.level,
+            task.output_level,
+            task.overlapping.len()
+        );
+        self.compact(task)
+    }
+
+    /// Runs one compaction if any level needs it; returns whether one ran.
+    fn run_compaction(&self) -> Result<bool> {
+        let _compaction = self.compaction_lock.lock();
+        let sstables = self.sstables.read();
+
+        // Check if compaction is needed
+        let task = {
+            let task = self.compaction_picker.read().pick_compaction(&sstables);
             match task {
                 Some(t) => t,
                 None => {
                     log::debug!("No compaction needed");
-                    return Ok(());
+                    return Ok(false);
                 }
             }
         };
@@ -951,6 +2791,19 @@ impl DB {
         // Drop the read lock before compaction
         drop(sstables);
 
+        if let Some(ref manager) = self.sst_file_manager {
+            let input_size =
+                task.inputs.iter().chain(&task.overlapping).map(|t| t.file_size()).sum();
+            if !manager.enough_room_for_compaction(input_size) {
+                log::warn!(
+                    "Postponing compaction of level {}: {} more bytes would exceed the space limit",
+                    task.level,
+                    input_size
+                );
+                return Ok(false);
+            }
+        }
+
         log::info!(
             "Triggering compaction: level {} -> level {}, {} input files",
             task.level,
@@ -961,50 +2814,84 @@ impl DB {
         // Execute compaction
         self.compact(task)?;
 
-        Ok(())
+        Ok(true)
     }
 
     /// Execute a compaction task
-    fn compact(&self, task: compaction::CompactionTask) -> Result<()> {
-        // Allocate file number for output SSTable
-        let file_number = self.next_file_number.fetch_add(1, Ordering::SeqCst);
+    fn compact(&self, mut task: compaction::CompactionTask) -> Result<()> {
+        let _timer = StopWatch::new(self.statistics.as_deref(), HistogramType::Compaction);
 
         // Create compaction job
-        let job = CompactionJob::new(
-            task.inputs.clone(),
-            task.output_level,
-            self.path.clone(),
-            self.options.block_size,
-        );
+        let job = {
+            let options = self.options.read();
+            CompactionJob::new(
+                task.all_inputs(),
+                task.output_level,
+                self.path.clone(),
+                options.block_size,
+            )
+            .with_options(&options)
+            .with_prefix_deletes(self.prefix_tombstones.get())
+            .with_history_cutoff(self.history_cutoff())
+            .with_timestamp_cutoff(self.timestamp_cutoff())
+            .with_record_authenticator(self.record_auth.clone())
+        };
+
+        self.info_log.info(format_args!(
+            "Compaction started: {} files from level {} to level {}",
+            task.inputs.len(),
+            task.level,
+            task.output_level
+        ));
 
-        // Run compaction
-        let result = job.run(file_number)?;
+        // Run compaction, allocating a file number for each output SSTable
+        let results = job.run(|| self.next_file_number.fetch_add(1, Ordering::SeqCst))?;
+        drop(job);
 
-        // If no file was created, nothing to update
-        if result.file_number == 0 {
+        // Even without output the inputs are replaced, so the entries they
+        // held stay deleted
+        if results.is_empty() {
             log::info!("Compaction produced no output (all tombstones or duplicates)");
-            return Ok(());
         }
 
-        // Open the new SSTable reader once and reuse it (fixes duplicate Arc bug)
-        let new_reader = Arc::new(SSTableReader::open_with_cache(
-            &result.output_path,
-            Some(Arc::clone(&self.block_cache)),
-        )?);
-
-        // Get metadata from the new reader
-        let smallest_key = new_reader
-            .smallest_key()?
-            .ok_or_else(|| Error::internal("New SSTable has no keys"))?;
-        let largest_key = new_reader
-            .largest_key()?
-            .ok_or_else(|| Error::internal("New SSTable has no keys"))?;
+        // Build a reader for each new SSTable from the builder's metadata,
+        // without reading the files back
+        let entries_written = results.iter().map(|r| r.entry_count).sum::<usize>();
+        let mut outputs = Vec::with_capacity(results.len());
+        for result in results {
+            let table = result.table;
+            let (smallest_key, largest_key) = match (&table.smallest_key, &table.largest_key) {
+                (Some(smallest), Some(largest)) => (smallest.clone(), largest.clone()),
+                _ => return Err(Error::internal("New SSTable has no keys")),
+            };
+            let reader = SSTableReader::from_metadata(
+                table,
+                Some(Arc::clone(&self.block_cache)),
+                &*self.env,
+            )?
+            .with_record_authenticator(self.record_auth.clone())?
+            .with_comparator(Arc::clone(&self.comparator));
+            let reader = Arc::new(self.attach_table_cache(reader));
+
+            outputs.push((result.file_number, reader, smallest_key, largest_key));
+        }
+        if let Some(ref manager) = self.sst_file_manager {
+            for (_, reader, _, _) in &outputs {
+                manager.on_add_file(reader.file_path(), reader.file_size());
+            }
+        }
+        let bytes_written = outputs.iter().map(|(_, reader, _, _)| reader.file_size()).sum();
+        if let Some(ref stats) = self.statistics {
+            stats.record_tick(Ticker::BytesCompacted, bytes_written);
+        }
 
         // Collect input file numbers and paths using reliable file_number() method
         // This fixes the unreliable file-size matching bug
         // We fail fast if any file has an invalid filename to prevent state inconsistencies
-        let mut input_file_info: Vec<(u64, std::path::PathBuf)> = Vec::new();
-        for input in &task.inputs {
+        let mut input_file_info: Vec<(u64, std::path::PathBuf, usize)> = Vec::new();
+        let inputs = task.inputs.iter().map(|input| (input, task.level));
+        let overlapping = task.overlapping.iter().map(|input| (input, task.output_level));
+        for (input, level) in inputs.chain(overlapping) {
             let file_num = input.file_number().ok_or_else(|| {
                 Error::internal(format!(
                     "Input SSTable has invalid filename: {:?}",
@@ -1012,7 +2899,7 @@ impl DB {
                 ))
             })?;
             let file_path = input.file_path().to_path_buf();
-            input_file_info.push((file_num, file_path));
+            input_file_info.push((file_num, file_path, level));
         }
 
         // Update both version set and in-memory SSTable list atomically
@@ -1022,20 +2909,21 @@ impl DB {
             let mut version_set = self.version_set.write();
             let mut sstables = self.sstables.write();
 
-            // Add new file to version set
-            let add_edit = VersionEdit::AddFile {
-                level: task.output_level,
-                file_number: result.file_number,
-                file_size: new_reader.file_size(),
-                smallest_key,
-                largest_key,
-            };
-            version_set.log_edit(&add_edit)?;
+            // Add new files to version set
+            for (file_number, reader, smallest_key, largest_key) in &outputs {
+                let add_edit = VersionEdit::AddFile {
+                    level: task.output_level,
+                    file_number: *file_number,
+                    file_size: reader.file_size(),
+                    smallest_key: smallest_key.clone(),
+                    largest_key: largest_key.clone(),
+                };
+                version_set.log_edit(&add_edit)?;
+            }
 
             // Delete input files from version set
-            for (file_num, _) in &input_file_info {
-                let delete_edit =
-                    VersionEdit::DeleteFile { level: task.level, file_number: *file_num };
+            for (file_num, _, level) in &input_file_info {
+                let delete_edit = VersionEdit::DeleteFile { level: *level, file_number: *file_num };
                 version_set.log_edit(&delete_edit)?;
             }
 
@@ -1045,241 +2933,1902 @@ impl DB {
             // Remove input files from source level using Arc::ptr_eq
             sstables[task.level]
                 .retain(|reader| !task.inputs.iter().any(|input| Arc::ptr_eq(reader, input)));
+            sstables[task.output_level]
+                .retain(|reader| !task.overlapping.iter().any(|input| Arc::ptr_eq(reader, input)));
 
-            // Add new file to output level (reuse the same Arc instance)
-            // For Level 0, insert at front (newest first), for other levels, append
+            // Add new files to output level (reuse the same Arc instances)
+            // For Level 0, insert at front (newest first), for other levels,
+            // keep the files ordered by key
+            let new_readers = outputs.iter().map(|(_, reader, _, _)| Arc::clone(reader));
             if task.output_level == 0 {
-                sstables[task.output_level].insert(0, Arc::clone(&new_reader));
+                sstables[task.output_level].splice(0..0, new_readers);
             } else {
-                sstables[task.output_level].push(Arc::clone(&new_reader));
+                sstables[task.output_level].extend(new_readers);
+                sort_level_by_key(&mut sstables[task.output_level]);
             }
         }
         // Locks are released here
 
-        // Now delete physical files AFTER updating in-memory structures
-        // This ensures consistency if deletion fails
-        for (file_num, file_path) in input_file_info {
-            if file_path.exists() {
-                std::fs::remove_file(&file_path)?;
-                log::info!("Deleted compacted file {:06}.sst: {:?}", file_num, file_path);
+        // Delete the input files once iterators reading them are done
+        {
+            let inputs = task.inputs.drain(..).chain(task.overlapping.drain(..));
+            let mut obsolete_files = self.obsolete_files.lock();
+            for (input, (file_number, path, _)) in inputs.zip(&input_file_info) {
+                obsolete_files.push(ObsoleteFile {
+                    file_number: *file_number,
+                    path: path.clone(),
+                    table: Arc::downgrade(&input),
+                });
             }
         }
+        self.purge_obsolete_files();
+
+        if !self.listeners.is_empty() {
+            let info = listener::CompactionJobInfo {
+                input_level: task.level,
+                output_level: task.output_level,
+                input_files: input_file_info.iter().map(|(_, path, _)| path.clone()).collect(),
+                output_files: outputs
+                    .iter()
+                    .map(|(_, reader, _, _)| reader.file_path().to_path_buf())
+                    .collect(),
+                bytes_written,
+            };
+            self.notify(|l| {
+                for (file_number, reader, _, _) in &outputs {
+                    l.on_table_file_created(&listener::TableFileCreationInfo {
+                        file_number: *file_number,
+                        file_path: reader.file_path().to_path_buf(),
+                        file_size: reader.file_size(),
+                        level: task.output_level,
+                        reason: listener::TableFileReason::Compaction,
+                    });
+                }
+                l.on_compaction_completed(&info);
+            });
+        }
 
         log::info!(
-            "Compaction completed: wrote {} entries to level {}",
-            result.entry_count,
+            "Compaction completed: wrote {} entries in {} files to level {}",
+            entries_written,
+            outputs.len(),
             task.output_level
         );
+        self.info_log.info(format_args!(
+            "Compaction completed: {} entries, {} files, {} bytes written to level {}",
+            entries_written,
+            outputs.len(),
+            bytes_written,
+            task.output_level
+        ));
 
-        Ok(())
+        self.retire_prefix_tombstones()
     }
 
-    /// Closes the database, ensuring all data is flushed to disk.
-    ///
-    /// # Errors
+    /// Deletes the files of compacted SSTables that are no longer read.
     ///
-    /// Returns an error if flushing fails.
-    pub fn close(&self) -> Result<()> {
-        // Step 1: Flush all data to disk
-        self.flush()?;
-
-        // Step 2: Sync WAL to ensure all writes are persisted
-        if self.options.use_wal {
-            let mut wal = self.wal.write();
-            wal.sync()?;
+    /// Called after compactions and whenever an iterator releases SSTables.
+    /// Failures are logged; the file is then left for the next open.
+    pub(crate) fn purge_obsolete_files(&self) {
+        let deletable: Vec<ObsoleteFile> = {
+            let mut obsolete_files = self.obsolete_files.lock();
+            let (deletable, pinned) = std::mem::take(&mut *obsolete_files)
+                .into_iter()
+                .partition(|file| file.table.strong_count() == 0);
+            *obsolete_files = pinned;
+            deletable
+        };
+        if deletable.is_empty() {
+            return;
         }
 
-        log::info!("Database closed successfully");
+        let mut deleted = Vec::with_capacity(deletable.len());
+        for file in deletable {
+            let removed = match self.sst_file_manager {
+                Some(ref manager) => manager.delete_file(&self.env, &self.path, &file.path),
+                None => self.env.remove_file(&file.path),
+            };
+            match removed {
+                Ok(()) => {
+                    log::info!(
+                        "Deleted compacted file {:06}.sst: {:?}",
+                        file.file_number,
+                        file.path
+                    );
+                    deleted.push(file);
+                }
+                Err(e) => log::warn!("Failed to delete obsolete SSTable {:?}: {}", file.path, e),
+            }
+        }
+        if let Err(e) = self.env.sync_dir(&self.path) {
+            log::warn!("Failed to sync database directory: {}", e);
+        }
 
-        Ok(())
+        self.notify(|l| {
+            for file in &deleted {
+                l.on_table_file_deleted(&listener::TableFileDeletionInfo {
+                    file_number: file.file_number,
+                    file_path: file.path.clone(),
+                });
+            }
+        });
     }
 
-    /// Get block cache statistics.
+    /// Verifies the checksums of every SSTable block and WAL record.
     ///
-    /// Returns statistics about cache hits, misses, and evictions.
+    /// All data is re-read from disk, bypassing the block cache. Instead of
+    /// failing on the first problem, every corrupted block or record is
+    /// collected into the returned [`verify::VerifyReport`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if verification itself cannot proceed (e.g. a
+    /// file cannot be opened); corruption is reported in the result.
     ///
     /// # Example
     ///
     /// ```rust,no_run
-    /// use aidb::{DB, Options};
-    ///
+    /// # use aidb::{DB, Options};
     /// # fn main() -> Result<(), aidb::Error> {
-    /// let db = DB::open("./data", Options::default())?;
-    ///
-    /// // Perform some operations
-    /// db.put(b"key1", b"value1")?;
-    /// db.get(b"key1")?;
-    ///
-    /// // Check cache statistics
-    /// let stats = db.cache_stats();
-    /// println!("Cache hit rate: {:.2}%", stats.hit_rate() * 100.0);
-    /// println!("Total lookups: {}", stats.lookups);
-    /// println!("Hits: {}, Misses: {}", stats.hits, stats.misses);
+    /// # let db = DB::open("./data", Options::default())?;
+    /// let report = db.verify_checksums()?;
+    /// for corruption in &report.corruptions {
+    ///     eprintln!("corrupted: {}", corruption);
+    /// }
     /// # Ok(())
     /// # }
     /// ```
-    pub fn cache_stats(&self) -> cache::CacheStats {
-        self.block_cache.stats()
+    pub fn verify_checksums(&self) -> Result<verify::VerifyReport> {
+        let mut report = self.verify_sstables(None)?;
+
+        // Hold the flush and WAL locks so the files are not appended to,
+        // rotated or deleted while they are read, and make sure buffered
+        // records are on disk first
+        let _flush_guard = self.flush_lock.lock();
+        let mut wal = self.wal.write();
+        wal.sync()?;
+        for (path, _) in self.frozen_wals() {
+            verify::verify_wal(&path, &*self.env, self.record_auth.clone(), &mut report)?;
+        }
+        verify::verify_wal(wal.path(), &*self.env, self.record_auth.clone(), &mut report)?;
+
+        Ok(report)
     }
 
-    /// Clear the block cache.
+    /// Verifies the checksums of the SSTable blocks that may contain keys
+    /// in `[start, end)`.
     ///
-    /// This removes all cached blocks, which may temporarily reduce read performance
-    /// but can be useful for benchmarking or memory management.
-    pub fn clear_cache(&self) {
-        self.block_cache.clear();
+    /// WAL records are not checked, as they are not ordered by key.
+    pub fn verify_checksums_in_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<verify::VerifyReport> {
+        self.verify_sstables(Some((start, end)))
     }
 
-    /// Reset cache statistics.
-    ///
-    /// Resets hits, misses, and other cache statistics to zero while preserving
-    /// cached data.
-    pub fn reset_cache_stats(&self) {
-        self.block_cache.reset_stats();
+    /// Verifies the live SSTables, optionally restricted to a key range.
+    fn verify_sstables(&self, range: Option<(&[u8], &[u8])>) -> Result<verify::VerifyReport> {
+        let mut report = verify::VerifyReport::default();
+
+        // Snapshot the file list so verification does not block compaction
+        let sstables: Vec<Arc<SSTableReader>> =
+            self.sstables.read().iter().flatten().cloned().collect();
+
+        for reader in sstables {
+            if let Some((start, end)) = range {
+                // Bounds that cannot be read count as overlapping, so a
+                // corrupted file is still verified
+                let comparator = &self.comparator;
+                let overlaps = !matches!(reader.largest_key(), Ok(Some(k))
+                        if comparator.compare(&k, start) == CmpOrdering::Less)
+                    && !matches!(reader.smallest_key(), Ok(Some(k))
+                        if comparator.compare(&k, end) != CmpOrdering::Less);
+                if !overlaps {
+                    continue;
+                }
+            }
+            verify::verify_sstable(&reader, range, &mut report)?;
+        }
+
+        log::info!(
+            "Verified {} SSTables, {} corruptions found",
+            report.sstables_checked,
+            report.corruptions.len()
+        );
+
+        Ok(report)
+    }
+
+    /// Returns a copy of the options currently in effect.
+    pub fn options(&self) -> Options {
+        self.options.read().clone()
+    }
+
+    /// Changes mutable options on a running database.
+    ///
+    /// Each change is a `(name, value)` pair using the field names of
+    /// [`Options`]. Only the options listed in [`config::MUTABLE_OPTIONS`] can be
+    /// changed; all changes are validated together and applied atomically, so
+    /// either every change takes effect or none does. The new options are also
+    /// written to a fresh OPTIONS file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if an option is unknown, immutable,
+    /// or its value fails to parse or validate.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// # let db = DB::open("./data", Options::default())?;
+    /// db.set_options(&[("memtable_size", "16777216"), ("block_cache_size", "67108864")])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_options(&self, changes: &[(&str, &str)]) -> Result<()> {
+        self.check_writable()?;
+        let mut new_options = self.options.read().clone();
+
+        for (name, value) in changes {
+            if !config::MUTABLE_OPTIONS.contains(name) {
+                return Err(Error::invalid_argument(format!(
+                    "option '{}' cannot be changed on a running database",
+                    name
+                )));
+            }
+            new_options.set_option(name, value)?;
+        }
+        new_options.validate()?;
+
+        config::OptionsFile::new(&new_options).persist_with_env(&self.path, &*self.env)?;
+
+        self.block_cache.set_capacity(new_options.block_cache_size);
+        *self.compaction_picker.write() = CompactionPicker::from_options(&new_options);
+        *self.options.write() = new_options;
+
+        log::info!("Options updated: {:?}", changes);
+        for (name, value) in changes {
+            self.info_log.info(format_args!("Option changed: {} = {}", name, value));
+        }
+
+        Ok(())
+    }
+
+    /// Closes the database, ensuring all data is flushed to disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if flushing fails. Does nothing if the database
+    /// was opened with [`DB::open_read_only`].
+    pub fn close(&self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        // Step 1: Flush all data to disk
+        self.flush()?;
+
+        // Step 2: Sync WAL to ensure all writes are persisted
+        if self.options.read().use_wal {
+            let mut wal = self.wal.write();
+            wal.sync()?;
+        }
+
+        log::info!("Database closed successfully");
+        self.info_log.info(format_args!("Database closed"));
+
+        Ok(())
+    }
+
+    /// Drops the handle without flushing or syncing anything, as if the
+    /// process had crashed; only the `LOCK` file is released. For tests.
+    #[doc(hidden)]
+    pub fn simulate_crash(mut self) {
+        drop(self.lock.take());
+        std::mem::forget(self);
+    }
+
+    /// Get block cache statistics.
+    ///
+    /// Returns statistics about cache hits, misses, and evictions.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use aidb::{DB, Options};
+    ///
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// let db = DB::open("./data", Options::default())?;
+    ///
+    /// // Perform some operations
+    /// db.put(b"key1", b"value1")?;
+    /// db.get(b"key1")?;
+    ///
+    /// // Check cache statistics
+    /// let stats = db.cache_stats();
+    /// println!("Cache hit rate: {:.2}%", stats.hit_rate() * 100.0);
+    /// println!("Total lookups: {}", stats.lookups);
+    /// println!("Hits: {}, Misses: {}", stats.hits, stats.misses);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cache_stats(&self) -> cache::CacheStats {
+        self.block_cache.stats()
+    }
+
+    /// Clear the block cache.
+    ///
+    /// This removes all cached blocks, which may temporarily reduce read performance
+    /// but can be useful for benchmarking or memory management.
+    pub fn clear_cache(&self) {
+        self.block_cache.clear();
+    }
+
+    /// Reset cache statistics.
+    ///
+    /// Resets hits, misses, and other cache statistics to zero while preserving
+    /// cached data.
+    pub fn reset_cache_stats(&self) {
+        self.block_cache.reset_stats();
+    }
+
+    /// Returns the corrupt SSTables that were quarantined when the database
+    /// was opened.
+    ///
+    /// Only populated when [`Options::quarantine_corrupt_files`] is enabled.
+    pub fn quarantine_stats(&self) -> &repair::QuarantineStats {
+        &self.quarantine_stats
+    }
+
+    /// Returns the statistics attached via [`Options::statistics`], if any.
+    pub fn statistics(&self) -> Option<Arc<Statistics>> {
+        self.statistics.clone()
+    }
+
+    /// Returns the number of SSTables and their total size in bytes at each level.
+    pub fn level_sizes(&self) -> Vec<(usize, u64)> {
+        self.sstables
+            .read()
+            .iter()
+            .map(|tables| (tables.len(), tables.iter().map(|t| t.file_size()).sum()))
+            .collect()
+    }
+
+    /// Returns the estimated number of bytes compactions have to rewrite
+    /// before every level is within its target.
+    ///
+    /// Writes are delayed once this reaches
+    /// `soft_pending_compaction_bytes_limit` and wait for compactions once
+    /// it reaches `hard_pending_compaction_bytes_limit`.
+    pub fn pending_compaction_bytes(&self) -> u64 {
+        let sstables = self.sstables.read();
+        self.compaction_picker.read().estimate_pending_compaction_bytes(&sstables)
+    }
+
+    /// Returns the size of the current WAL file in bytes.
+    pub fn wal_size(&self) -> u64 {
+        self.wal.read().size()
+    }
+
+    /// Returns the approximate memory held by the database.
+    ///
+    /// MemTable sizes count keys and values, not the skiplist nodes, so the
+    /// real usage is somewhat higher.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// let db = DB::open("./data", Options::default())?;
+    /// let usage = db.memory_usage();
+    /// println!("{} bytes in MemTables", usage.memtable + usage.immutable_memtables);
+    /// println!("{} bytes in total", usage.total());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            memtable: self.memtable.read().approximate_size(),
+            immutable_memtables: self
+                .immutable_memtables
+                .read()
+                .iter()
+                .map(|memtable| memtable.approximate_size())
+                .sum(),
+            block_cache: self.block_cache.size(),
+            index_and_filter_blocks: self
+                .sstables
+                .read()
+                .iter()
+                .flatten()
+                .map(|table| table.index_and_filter_size())
+                .sum(),
+            iterators: self.iterator_memory.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Describes the current shape of the LSM tree.
+    ///
+    /// Lists every level with its SSTables, their sizes and key ranges,
+    /// overlapping files and the compaction that would run next. See
+    /// [`lsm_state`] for the output formats.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use aidb::{DB, Options};
+    /// # fn main() -> Result<(), aidb::Error> {
+    /// let db = DB::open("./data", Options::default())?;
+    /// let state = db.dump_lsm_state()?;
+    /// println!("{}", state.to_json()?);
+    /// std::fs::write("lsm.dot", state.to_dot())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn dump_lsm_state(&self) -> Result<LsmState> {
+        let sstables = self.sstables.read();
+        let picker = self.compaction_picker.read();
+        LsmState::capture(&sstables, &picker, self.sequence.load(Ordering::SeqCst))
+    }
+}
+
+/// Orders the files of a level above 0 by their smallest key
+///
+/// Files whose bounds cannot be read sort first.
+fn sort_level_by_key(files: &mut [Arc<SSTableReader>]) {
+    let smallest = |file: &SSTableReader| match file.key_range() {
+        Ok(Some((smallest, _))) => Some(smallest.to_vec()),
+        _ => None,
+    };
+    files.sort_by(|a, b| match (smallest(a), smallest(b)) {
+        (Some(a_key), Some(b_key)) => a.comparator().compare(&a_key, &b_key),
+        (a_key, b_key) => a_key.is_some().cmp(&b_key.is_some()),
+    });
+}
+
+impl Drop for DB {
+    fn drop(&mut self) {
+        if self.read_only {
+            return;
+        }
+
+        // Attempt to flush and close cleanly
+        // Ignore errors during drop as we can't propagate them
+        if let Some(e) = self.background_error() {
+            log::warn!("Skipping flush during drop: {}; unflushed data remains in the WAL", e);
+        } else if let Err(e) = self.flush() {
+            eprintln!("Error flushing database during drop: {}", e);
+        }
+
+        if self.options.read().use_wal {
+            let mut wal = self.wal.write();
+            if let Err(e) = wal.sync() {
+                eprintln!("Error syncing WAL during drop: {}", e);
+            }
+        }
+
+        self.purge_obsolete_files();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_db_open() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default();
+        let result = DB::open(temp_dir.path(), options);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_db_put_and_get() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        // Test put and get
+        db.put(b"key1", b"value1").unwrap();
+        let value = db.get(b"key1").unwrap();
+        assert_eq!(value, Some(b"value1".to_vec()));
+
+        // Test non-existent key
+        let value = db.get(b"key2").unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[test]
+    fn test_db_delete() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        // Put a key
+        db.put(b"key1", b"value1").unwrap();
+        assert_eq!(db.get(b"key1").unwrap(), Some(b"value1".to_vec()));
+
+        // Delete the key
+        db.delete(b"key1").unwrap();
+        assert_eq!(db.get(b"key1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_db_append() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+
+        db.put(b"log", b"a").unwrap();
+        db.flush().unwrap();
+        db.append(b"log", b"b").unwrap();
+        let snapshot = db.snapshot();
+        let mut batch = WriteBatch::new();
+        batch.append(b"log", b"c");
+        batch.append(b"new", b"x");
+        db.write(batch).unwrap();
+        db.put(b"reset", b"old").unwrap();
+        db.delete(b"reset").unwrap();
+        db.append(b"reset", b"fresh").unwrap();
+
+        // Appends are applied across MemTable versions and SSTables
+        assert_eq!(db.get(b"log").unwrap(), Some(b"abc".to_vec()));
+        assert_eq!(snapshot.get(b"log").unwrap(), Some(b"ab".to_vec()));
+        assert_eq!(db.get(b"new").unwrap(), Some(b"x".to_vec()));
+        assert_eq!(db.get(b"reset").unwrap(), Some(b"fresh".to_vec()));
+        let mut iter = db.iter();
+        let mut entries = Vec::new();
+        while iter.valid() {
+            entries.push((iter.key().to_vec(), iter.value().to_vec()));
+            iter.next();
+        }
+        assert_eq!(
+            entries,
+            vec![
+                (b"log".to_vec(), b"abc".to_vec()),
+                (b"new".to_vec(), b"x".to_vec()),
+                (b"reset".to_vec(), b"fresh".to_vec()),
+            ]
+        );
+        drop(iter);
+        drop(snapshot);
+
+        // Replayed from the WAL, then collapsed by a flush
+        drop(db);
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"log").unwrap(), Some(b"abc".to_vec()));
+        db.append(b"log", b"d").unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.get(b"log").unwrap(), Some(b"abcd".to_vec()));
+        assert_eq!(db.get(b"reset").unwrap(), Some(b"fresh".to_vec()));
+    }
+
+    #[test]
+    fn test_db_get_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+
+        db.put(b"flushed", b"v").unwrap();
+        db.flush().unwrap();
+        db.put(b"key", b"a").unwrap();
+        let snapshot = db.snapshot();
+        db.append(b"key", b"b").unwrap();
+        db.delete(b"flushed").unwrap();
+
+        let entry =
+            KeyEntry { value: b"ab".to_vec(), sequence: Some(3), value_type: ValueType::Append };
+        assert_eq!(db.get_entry(b"key").unwrap(), Some(entry));
+        let entry =
+            KeyEntry { value: b"a".to_vec(), sequence: Some(2), value_type: ValueType::Value };
+        assert_eq!(snapshot.get_entry(b"key").unwrap(), Some(entry));
+        // Flushed keys keep their sequence numbers
+        let entry =
+            KeyEntry { value: b"v".to_vec(), sequence: Some(1), value_type: ValueType::Value };
+        assert_eq!(snapshot.get_entry(b"flushed").unwrap(), Some(entry));
+        assert_eq!(db.get_entry(b"flushed").unwrap(), None);
+        assert_eq!(db.get_entry(b"missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_db_get_into() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        db.put(b"a", b"first").unwrap();
+        db.flush().unwrap();
+        db.put(b"b", b"xy").unwrap();
+
+        let mut buf = Vec::with_capacity(64);
+        assert_eq!(db.get_into(b"a", &mut buf).unwrap(), Some(5));
+        assert_eq!(buf, b"first");
+        assert_eq!(db.get_into(b"b", &mut buf).unwrap(), Some(2));
+        assert_eq!(buf, b"xy");
+        assert_eq!(buf.capacity(), 64);
+        assert_eq!(db.get_into(b"missing", &mut buf).unwrap(), None);
+        assert_eq!(buf, b"xy");
+
+        let mut slice = [0u8; 4];
+        assert_eq!(db.get_into_slice(b"b", &mut slice).unwrap(), Some(2));
+        assert_eq!(&slice[..2], b"xy");
+        // Too long: only the required length is reported
+        assert_eq!(db.get_into_slice(b"a", &mut slice).unwrap(), Some(5));
+        assert_eq!(&slice[..2], b"xy");
+        assert_eq!(db.get_into_slice(b"missing", &mut slice).unwrap(), None);
+    }
+
+    #[test]
+    fn test_db_overwrite() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        // Put initial value
+        db.put(b"key1", b"value1").unwrap();
+        assert_eq!(db.get(b"key1").unwrap(), Some(b"value1".to_vec()));
+
+        // Overwrite with new value
+        db.put(b"key1", b"value2").unwrap();
+        assert_eq!(db.get(b"key1").unwrap(), Some(b"value2".to_vec()));
+    }
+
+    #[test]
+    fn test_db_multiple_operations() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        // Multiple puts
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            let value = format!("value{}", i);
+            db.put(key.as_bytes(), value.as_bytes()).unwrap();
+        }
+
+        // Verify all values
+        for i in 0..100 {
+            let key = format!("key{}", i);
+            let expected = format!("value{}", i);
+            let value = db.get(key.as_bytes()).unwrap();
+            assert_eq!(value, Some(expected.as_bytes().to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_db_close() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        db.put(b"key1", b"value1").unwrap();
+        let result = db.close();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_db_recovery() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().to_path_buf();
+
+        // First session: write data
+        {
+            let db = DB::open(&db_path, Options::default()).unwrap();
+            db.put(b"key1", b"value1").unwrap();
+            db.put(b"key2", b"value2").unwrap();
+            db.close().unwrap();
+        }
+
+        // Second session: verify recovery
+        {
+            let _db = DB::open(&db_path, Options::default()).unwrap();
+            // Note: Currently recovery from WAL is not fully implemented
+            // This test will be enhanced in future
+        }
+    }
+
+    #[test]
+    fn test_wal_recovery_preserves_sequence_numbers() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            db.put(b"a", b"1").unwrap();
+            let mut batch = WriteBatch::new();
+            batch.put(b"b", b"2");
+            batch.put(b"b", b"3");
+            batch.delete(b"a");
+            db.write(batch).unwrap();
+            db.put(b"c", b"4").unwrap();
+            db.simulate_crash();
+        }
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.sequence.load(Ordering::SeqCst), 5);
+
+        let mut recovered: Vec<_> = db
+            .memtable
+            .read()
+            .iter()
+            .map(|entry| (entry.user_key().to_vec(), entry.sequence()))
+            .collect();
+        recovered.sort_by_key(|(_, seq)| *seq);
+        assert_eq!(
+            recovered,
+            vec![
+                (b"a".to_vec(), 1),
+                (b"b".to_vec(), 2),
+                (b"b".to_vec(), 3),
+                (b"a".to_vec(), 4),
+                (b"c".to_vec(), 5)
+            ]
+        );
+        assert_eq!(db.get(b"a").unwrap(), None);
+        assert_eq!(db.get(b"b").unwrap(), Some(b"3".to_vec()));
+    }
+
+    #[test]
+    fn test_sequence_survives_flush_and_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            for i in 0..3 {
+                db.put(format!("key{}", i).as_bytes(), b"value").unwrap();
+            }
+            db.flush().unwrap();
+        }
+
+        // The WAL is empty after the flush; the manifest remembers the sequence
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.sequence.load(Ordering::SeqCst), 3);
+        db.put(b"key3", b"value").unwrap();
+        assert_eq!(db.sequence.load(Ordering::SeqCst), 4);
+
+        // Only the sequence numbers of deleted WAL files are recorded
+        db.freeze_memtable().unwrap();
+        db.put(b"key4", b"value").unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.version_set.read().last_sequence(), 5);
+        db.put(b"key5", b"value").unwrap();
+        db.freeze_memtable().unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.version_set.read().last_sequence(), 6);
+        db.flush().unwrap();
+        assert_eq!(db.version_set.read().last_sequence(), 6);
+    }
+
+    #[test]
+    fn test_recover_legacy_wal_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let mut wal = WAL::open(temp_dir.path().join(wal::wal_filename(1))).unwrap();
+            let mut put = b"put:".to_vec();
+            put.extend_from_slice(&3u32.to_le_bytes());
+            put.extend_from_slice(b":key:value");
+            wal.append(&put).unwrap();
+            wal.sync().unwrap();
+        }
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(db.sequence.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_open_removes_incomplete_sstables() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            db.put(b"key", b"value").unwrap();
+            db.flush().unwrap();
+        }
+        // Left behind by a crash during flush or compaction
+        let leftover = temp_dir.path().join("000009.sst.tmp");
+        std::fs::write(&leftover, b"partial").unwrap();
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert!(!leftover.exists());
+        assert_eq!(db.sstables.read()[0].len(), 1);
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[test]
+    fn test_statistics() {
+        use statistics::Statistics;
+
+        let temp_dir = TempDir::new().unwrap();
+        let stats = Arc::new(Statistics::new());
+        let db =
+            DB::open(temp_dir.path(), Options::default().statistics(Arc::clone(&stats))).unwrap();
+        assert!(db.statistics().is_some());
+
+        db.put(b"key", b"value").unwrap();
+        db.delete(b"gone").unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"a", b"1");
+        batch.put(b"b", b"2");
+        db.write(batch).unwrap();
+        assert_eq!(stats.ticker(Ticker::KeysWritten), 4);
+        assert_eq!(stats.ticker(Ticker::BytesWritten), 8 + 4 + 4);
+
+        db.flush().unwrap();
+        assert!(stats.ticker(Ticker::BytesFlushed) > 0);
+        assert_eq!(stats.histogram(HistogramType::Flush).count, 1);
+
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(db.get(b"missing").unwrap(), None);
+        assert_eq!(stats.ticker(Ticker::KeysRead), 3);
+        assert_eq!(stats.ticker(Ticker::BytesRead), 10);
+        assert_eq!(stats.ticker(Ticker::BlockCacheHit), 1);
+        assert_eq!(stats.ticker(Ticker::BlockCacheMiss), 1);
+        assert_eq!(stats.ticker(Ticker::BloomFilterUseful), 1);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.histogram(HistogramType::Get).count, 3);
+        assert_eq!(snapshot.histogram(HistogramType::Put).count, 2);
+        assert_eq!(snapshot.histogram(HistogramType::Write).count, 1);
+        assert!(stats.to_string().contains("aidb.keys.read COUNT : 3"));
+
+        // Without statistics nothing is collected
+        let db = DB::open(TempDir::new().unwrap().path(), Options::default()).unwrap();
+        assert!(db.statistics().is_none());
+    }
+
+    /// Records the events it receives as strings
+    #[derive(Default)]
+    struct RecordingListener {
+        events: parking_lot::Mutex<Vec<String>>,
+    }
+
+    impl RecordingListener {
+        fn record(&self, event: String) {
+            self.events.lock().push(event);
+        }
+    }
+
+    impl EventListener for RecordingListener {
+        fn on_flush_begin(&self, info: &listener::FlushJobInfo) {
+            self.record(format!("flush_begin {}", info.file_number));
+        }
+
+        fn on_flush_completed(&self, info: &listener::FlushJobInfo) {
+            assert!(info.file_path.exists());
+            self.record(format!("flush_completed {} {}", info.file_number, info.entry_count));
+        }
+
+        fn on_compaction_completed(&self, info: &listener::CompactionJobInfo) {
+            assert!(info.input_files.iter().all(|path| !path.exists()));
+            self.record(format!(
+                "compaction_completed {}->{} {} inputs",
+                info.input_level,
+                info.output_level,
+                info.input_files.len()
+            ));
+        }
+
+        fn on_table_file_created(&self, info: &listener::TableFileCreationInfo) {
+            self.record(format!("table_created {:?} L{}", info.reason, info.level));
+        }
+
+        fn on_table_file_deleted(&self, info: &listener::TableFileDeletionInfo) {
+            self.record(format!("table_deleted {}", info.file_number));
+        }
+
+        fn on_wal_rotated(&self, _info: &listener::WalRotationInfo) {
+            self.record("wal_rotated".to_string());
+        }
+
+        fn on_background_error(&self, _error: &Error) {
+            self.record("background_error".to_string());
+        }
+    }
+
+    #[test]
+    fn test_event_listener_flush_and_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let listener = Arc::new(RecordingListener::default());
+        let db = DB::open(
+            temp_dir.path(),
+            Options::default().add_listener(Arc::clone(&listener) as Arc<dyn EventListener>),
+        )
+        .unwrap();
+
+        db.put(b"a", b"1").unwrap();
+        db.put(b"b", b"2").unwrap();
+        let file_number = db.next_file_number.load(Ordering::SeqCst);
+        db.flush().unwrap();
+        assert_eq!(
+            std::mem::take(&mut *listener.events.lock()),
+            vec![
+                "wal_rotated".to_string(),
+                format!("flush_begin {}", file_number),
+                "table_created Flush L0".to_string(),
+                format!("flush_completed {} 2", file_number),
+            ]
+        );
+
+        // Reach the Level 0 compaction threshold
+        for i in 1..4 {
+            db.put(format!("key{}", i).as_bytes(), b"value").unwrap();
+            db.flush().unwrap();
+        }
+        let events = listener.events.lock().clone();
+        assert_eq!(events.iter().filter(|e| e.starts_with("table_deleted")).count(), 4);
+        assert!(events.contains(&"table_created Compaction L1".to_string()));
+        assert_eq!(events.last().unwrap(), "compaction_completed 0->1 4 inputs");
+    }
+
+    #[test]
+    fn test_event_listener_background_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let listener = Arc::new(RecordingListener::default());
+        let db = DB::open(
+            temp_dir.path(),
+            Options::default().add_listener(Arc::clone(&listener) as Arc<dyn EventListener>),
+        )
+        .unwrap();
+        db.put(b"key", b"value").unwrap();
+
+        // Block the next SSTable path with a directory so the flush fails
+        let next_file = db.next_file_number.load(Ordering::SeqCst);
+        std::fs::create_dir(temp_dir.path().join(format!("{:06}.sst", next_file))).unwrap();
+
+        assert!(db.flush().is_err());
+        assert!(db.flush().is_err());
+        let events = listener.events.lock().clone();
+        assert_eq!(
+            events,
+            vec![
+                "wal_rotated".into(),
+                format!("flush_begin {}", next_file),
+                "background_error".into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_info_log_records_engine_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join(info_log::LOG_FILE_NAME);
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            db.put(b"key", b"value").unwrap();
+            db.flush().unwrap();
+            db.set_options(&[("sync_wal", "false")]).unwrap();
+            db.close().unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&log_path).unwrap();
+        for expected in [
+            "Opening database at",
+            "Options: memtable_size = ",
+            "Database opened: last sequence 0",
+            "Flush completed:",
+            "Option changed: sync_wal = false",
+            "Database closed",
+        ] {
+            assert!(contents.contains(expected), "missing {:?} in:\n{}", expected, contents);
+        }
+
+        // Reopening starts a new LOG and keeps the previous one
+        let _db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(info_log::old_log_files(temp_dir.path()).unwrap().len(), 1);
+        assert!(!std::fs::read_to_string(&log_path).unwrap().contains("Flush completed:"));
+    }
+
+    #[test]
+    fn test_perf_context_for_get() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.put(b"key", b"value").unwrap();
+        db.flush().unwrap();
+        db.put(b"other", b"value").unwrap();
+        db.flush().unwrap();
+        db.clear_cache();
+
+        // The newer SSTable is ruled out by its Bloom filter, the older is read
+        perf_context::reset();
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+        let perf = perf_context::get();
+        assert_eq!(perf.memtables_checked, 1);
+        assert_eq!(perf.sstables_checked, 1);
+        assert_eq!(perf.bloom_filter_skips, 1);
+        assert_eq!(perf.block_reads, 1);
+        assert!(perf.block_read_bytes > 0);
+
+        // The second read is served from the block cache
+        perf_context::reset();
+        db.get(b"key").unwrap();
+        assert_eq!(perf_context::get().block_cache_hits, 1);
+        assert_eq!(perf_context::get().block_reads, 0);
+
+        perf_context::reset();
+        db.put(b"key", b"value").unwrap();
+        assert_eq!(perf_context::get().sstables_checked, 0);
+    }
+
+    #[test]
+    fn test_dump_lsm_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.put(b"a", b"1").unwrap();
+        db.put(b"m", b"2").unwrap();
+        db.flush().unwrap();
+
+        let state = db.dump_lsm_state().unwrap();
+        assert_eq!(state.last_sequence, 2);
+        assert_eq!(state.levels.len(), db.options().max_levels);
+        let file = &state.levels[0].files[0];
+        assert_eq!(file.smallest_key.as_deref(), Some("a"));
+        assert_eq!(file.largest_key.as_deref(), Some("m"));
+        assert!(state.to_json().unwrap().contains("\"file_name\""));
+        assert!(state.to_dot().contains(&file.file_name));
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        assert_eq!(db.memory_usage().memtable, 0);
+        assert_eq!(db.memory_usage().index_and_filter_blocks, 0);
+
+        db.put(b"key1", b"value1").unwrap();
+        let usage = db.memory_usage();
+        assert!(usage.memtable > 0);
+        assert_eq!(usage.total(), usage.memtable);
+
+        db.flush().unwrap();
+        db.get(b"key1").unwrap();
+        let usage = db.memory_usage();
+        assert_eq!(usage.memtable, 0);
+        assert!(usage.index_and_filter_blocks > 0);
+        assert!(usage.block_cache > 0);
+
+        // Iterators hold their keys until dropped
+        let iter = db.iter();
+        assert!(db.memory_usage().iterators >= 4);
+        drop(iter);
+        assert_eq!(db.memory_usage().iterators, 0);
+    }
+
+    #[test]
+    fn test_open_in_mem_env() {
+        use env::Env;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("db");
+        let mem_env = Arc::new(env::MemEnv::new());
+        let options = Options::default().level0_compaction_threshold(2).env(mem_env.clone());
+
+        let db = DB::open(&path, options.clone()).unwrap();
+        for round in 0..3u8 {
+            db.put(&[b'a' + round], b"value").unwrap();
+            db.flush().unwrap();
+        }
+        db.put(b"wal", b"only").unwrap();
+        db.set_options(&[("block_cache_size", "1048576")]).unwrap();
+        assert!(db.verify_checksums().unwrap().is_ok());
+        db.close().unwrap();
+        drop(db);
+
+        // Nothing touched the real filesystem
+        assert!(!path.exists());
+        assert!(mem_env.file_exists(&path.join("MANIFEST")));
+        assert!(mem_env.total_size() > 0);
+
+        let db = DB::open(&path, options).unwrap();
+        for key in [&b"a"[..], b"b", b"c"] {
+            assert_eq!(db.get(key).unwrap(), Some(b"value".to_vec()));
+        }
+        assert_eq!(db.get(b"wal").unwrap(), Some(b"only".to_vec()));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_db_error_if_exists() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // Create the database first
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.close().unwrap();
+        drop(db);
+
+        // Try to open with error_if_exists
+        let options = Options::default().create_if_missing(false);
+        let mut options = options;
+        options.error_if_exists = true;
+
+        let result = DB::open(temp_dir.path(), options);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_open_read_only_leaves_directory_unchanged() {
+        let temp_dir = TempDir::new().unwrap();
+        let contents = || {
+            let mut files: Vec<(String, Vec<u8>)> = std::fs::read_dir(temp_dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.is_file())
+                .map(|path| {
+                    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                    (name, std::fs::read(&path).unwrap())
+                })
+                .collect();
+            files.sort();
+            files
+        };
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.put(b"flushed", b"v1").unwrap();
+        db.flush().unwrap();
+        db.put(b"in-wal", b"v2").unwrap();
+        // Skip the flush on drop so the last write stays in the WAL
+        db.simulate_crash();
+        // A table left behind by an interrupted flush
+        std::fs::write(temp_dir.path().join("000099.sst.tmp"), b"partial").unwrap();
+        let before = contents();
+
+        let db = DB::open_read_only(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"flushed").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(db.get(b"in-wal").unwrap(), Some(b"v2".to_vec()));
+        assert!(db.verify_checksums().unwrap().is_ok());
+        assert!(matches!(db.put(b"key", b"value"), Err(Error::InvalidState(_))));
+        assert!(matches!(db.delete(b"flushed"), Err(Error::InvalidState(_))));
+        assert!(matches!(db.flush(), Err(Error::InvalidState(_))));
+        assert!(matches!(db.maybe_trigger_compaction(), Err(Error::InvalidState(_))));
+        assert!(matches!(
+            db.set_options(&[("memtable_size", "1024")]),
+            Err(Error::InvalidState(_))
+        ));
+        db.close().unwrap();
+        drop(db);
+        assert_eq!(contents(), before);
+
+        let missing = temp_dir.path().join("missing");
+        assert!(matches!(
+            DB::open_read_only(&missing, Options::default()),
+            Err(Error::NotFound(_))
+        ));
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_drop_all() {
+        let temp_dir = TempDir::new().unwrap();
+        let files = |suffix: &str| {
+            std::fs::read_dir(temp_dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.to_string_lossy().ends_with(suffix))
+                .collect::<Vec<_>>()
+        };
+
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        for i in 0..3u8 {
+            db.put(&[b'k', i], b"flushed").unwrap();
+            db.flush().unwrap();
+        }
+        db.put(b"in-wal", b"v").unwrap();
+        let old_wal = files(".log").pop().unwrap();
+        let old_wal_contents = std::fs::read(&old_wal).unwrap();
+        let mut iter = db.iter();
+
+        db.drop_all().unwrap();
+        assert_eq!(db.get(b"k\x00").unwrap(), None);
+        assert_eq!(db.get(b"in-wal").unwrap(), None);
+        assert!(db.level_sizes().iter().all(|&(files, _)| files == 0));
+        // The iterator still reads the tables it started with
+        assert_eq!(files(".sst").len(), 3);
+        assert!(iter.valid());
+        iter.next();
+        drop(iter);
+        assert!(files(".sst").is_empty());
+
+        let db = Arc::into_inner(db).unwrap();
+        db.put(b"after", b"v").unwrap();
+        db.simulate_crash();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"in-wal").unwrap(), None);
+        assert_eq!(db.get(b"after").unwrap(), Some(b"v".to_vec()));
+        db.drop_all().unwrap();
+        db.simulate_crash();
+
+        // A crash before the WAL was replaced leaves the dropped writes in
+        // the latest WAL; they are not replayed
+        for wal in files(".log") {
+            std::fs::remove_file(wal).unwrap();
+        }
+        std::fs::write(&old_wal, old_wal_contents).unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"in-wal").unwrap(), None);
+        assert_eq!(db.get(b"after").unwrap(), None);
+    }
+
+    #[test]
+    fn test_delete_files_in_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        for keys in [&[&b"a1"[..], b"a2"][..], &[b"b1", b"b2"], &[b"a3", b"c1"]] {
+            for key in keys {
+                db.put(key, b"v").unwrap();
+            }
+            db.flush().unwrap();
+        }
+        db.put(b"a4", b"v").unwrap();
+
+        assert_eq!(db.delete_files_in_range(b"b", b"a").unwrap(), 0);
+        // The table of "a3" and "c1" reaches beyond the range
+        assert_eq!(db.delete_files_in_range(b"a", b"b").unwrap(), 1);
+        assert_eq!(db.get(b"a1").unwrap(), None);
+        assert_eq!(db.get(b"a3").unwrap(), Some(b"v".to_vec()));
+        assert_eq!(db.get(b"a4").unwrap(), Some(b"v".to_vec()));
+        assert_eq!(db.get(b"b1").unwrap(), Some(b"v".to_vec()));
+        let tables = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("sst".as_ref()))
+            .count();
+        assert_eq!(tables, 2);
+
+        drop(db);
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"a2").unwrap(), None);
+        assert_eq!(db.get(b"b2").unwrap(), Some(b"v".to_vec()));
+    }
+
+    #[test]
+    fn test_compact_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        for value in [b"v1", b"v2", b"v3"] {
+            db.put(b"a", value).unwrap();
+            db.put(b"z", value).unwrap();
+            db.flush().unwrap();
+        }
+        // Newest first
+        let files: Vec<u64> = db.dump_lsm_state().unwrap().levels[0]
+            .files
+            .iter()
+            .map(|file| file.file_number.unwrap())
+            .collect();
+        assert_eq!(files.len(), 3);
+
+        let invalid =
+            |result: Result<()>| assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        invalid(db.compact_files(&[], 1));
+        invalid(db.compact_files(&[999], 1));
+        invalid(db.compact_files(&files, 0));
+        invalid(db.compact_files(&files, 7));
+        // The older files hold older versions of the same keys
+        invalid(db.compact_files(&files[..1], 1));
+
+        db.compact_files(&files[1..], 2).unwrap();
+        assert_eq!(db.level_sizes()[0].0, 1);
+        assert_eq!(db.level_sizes()[2].0, 1);
+        assert_eq!(db.get(b"a").unwrap(), Some(b"v3".to_vec()));
+        db.compact_files(&files[..1], 1).unwrap();
+        let file_number =
+            |level: usize| db.dump_lsm_state().unwrap().levels[level].files[0].file_number.unwrap();
+        invalid(db.compact_files(&[file_number(1), file_number(2)], 2));
+
+        // Level 1 holds older versions of the keys of a new file
+        db.put(b"a", b"v4").unwrap();
+        db.flush().unwrap();
+        invalid(db.compact_files(&[file_number(0)], 2));
+        db.compact_files(&[file_number(0)], 1).unwrap();
+        db.compact_files(&[file_number(1)], 2).unwrap();
+        assert_eq!(db.level_sizes()[2].0, 1);
+        assert_eq!(db.get(b"a").unwrap(), Some(b"v4".to_vec()));
+        assert_eq!(db.get(b"z").unwrap(), Some(b"v3".to_vec()));
+    }
+
+    #[test]
+    fn test_db_get_at_sequence() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().preserve_versions_seconds(3600);
+        let db = DB::open(temp_dir.path(), options).unwrap();
+        db.put(b"a", b"v1").unwrap();
+        db.put(b"a", b"v2").unwrap();
+        db.flush().unwrap();
+        db.delete(b"a").unwrap();
+        db.append(b"a", b"v").unwrap();
+        db.append(b"a", b"4").unwrap();
+        db.flush().unwrap();
+
+        let read = |sequence: u64| db.get_at_sequence(b"a", sequence).unwrap();
+        let check = || {
+            assert_eq!(read(1), Some(b"v1".to_vec()));
+            assert_eq!(read(2), Some(b"v2".to_vec()));
+            assert_eq!(read(3), None);
+            assert_eq!(read(4), Some(b"v".to_vec()));
+            assert_eq!(read(5), Some(b"v4".to_vec()));
+            assert_eq!(read(100), Some(b"v4".to_vec()));
+        };
+        check();
+
+        // Compactions keep the versions too, tombstones included
+        let files: Vec<u64> = db.dump_lsm_state().unwrap().levels[0]
+            .files
+            .iter()
+            .map(|file| file.file_number.unwrap())
+            .collect();
+        db.compact_files(&files, 1).unwrap();
+        assert_eq!(db.level_sizes()[1].0, 1);
+        check();
+        assert_eq!(db.get(b"a").unwrap(), Some(b"v4".to_vec()));
+        drop(db);
+
+        // Without a window only the newest versions are kept
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.put(b"a", b"v6").unwrap();
+        assert!(matches!(db.get_at_sequence(b"a", 5), Err(Error::InvalidArgument(_))));
+        assert_eq!(db.get_at_sequence(b"a", 6).unwrap(), Some(b"v6".to_vec()));
+    }
+
+    #[test]
+    fn test_db_open_persists_options() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().memtable_size(128 * 1024);
+        let db = DB::open(temp_dir.path(), options).unwrap();
+        drop(db);
+
+        let loaded = Options::load_latest(temp_dir.path()).unwrap();
+        assert_eq!(loaded.memtable_size, 128 * 1024);
+    }
+
+    #[test]
+    fn test_db_open_rejects_incompatible_options_file() {
+        let temp_dir = TempDir::new().unwrap();
+        drop(DB::open(temp_dir.path(), Options::default()).unwrap());
+
+        // Simulate a database written by a newer on-disk format
+        let mut file = config::OptionsFile::load_latest(temp_dir.path()).unwrap().unwrap();
+        file.format_version = config::FORMAT_VERSION + 1;
+        file.persist(temp_dir.path()).unwrap();
+
+        let result = DB::open(temp_dir.path(), Options::default());
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+
+    /// Orders keys from the largest down
+    struct Descending;
+
+    impl comparator::Comparator for Descending {
+        fn name(&self) -> &str {
+            "test.Descending"
+        }
+
+        fn compare(&self, a: &[u8], b: &[u8]) -> CmpOrdering {
+            b.cmp(a)
+        }
+    }
+
+    #[test]
+    fn test_custom_comparator() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = || Options::default().comparator(Arc::new(Descending));
+        let keys: Vec<Vec<u8>> = (0..30u8).map(|i| vec![b'a' + i % 26, i]).collect();
+        {
+            let db = DB::open(temp_dir.path(), options()).unwrap();
+            for chunk in keys.chunks(10) {
+                for key in chunk {
+                    db.put(key, key).unwrap();
+                }
+                db.flush().unwrap();
+            }
+            let files: Vec<u64> = db.dump_lsm_state().unwrap().levels[0]
+                .files
+                .iter()
+                .map(|file| file.file_number.unwrap())
+                .collect();
+            db.compact_files(&files, 1).unwrap();
+            db.put(&keys[3], b"new").unwrap();
+        }
+
+        let db = Arc::new(DB::open(temp_dir.path(), options()).unwrap());
+        assert_eq!(db.get(&keys[3]).unwrap(), Some(b"new".to_vec()));
+        assert_eq!(db.get(&keys[29]).unwrap(), Some(keys[29].clone()));
+        assert_eq!(db.get(b"zz").unwrap(), None);
+
+        let mut expected = keys.clone();
+        expected.sort_by(|a, b| b.cmp(a));
+        let mut iter = db.iter();
+        iter.seek_to_first();
+        let mut scanned = Vec::new();
+        while iter.valid() {
+            scanned.push(iter.key().to_vec());
+            iter.next();
+        }
+        assert_eq!(scanned, expected);
+
+        // Start and end follow the comparator's order too
+        let mut iter = db.scan(Some(b"m"), Some(b"c")).unwrap();
+        let mut scanned = Vec::new();
+        while iter.valid() {
+            scanned.push(iter.key().to_vec());
+            iter.next();
+        }
+        let in_range: Vec<_> = expected
+            .into_iter()
+            .filter(|key| key.as_slice() <= b"m".as_slice() && key.as_slice() > b"c".as_slice())
+            .collect();
+        assert_eq!(scanned, in_range);
+
+        let mut batch = WriteBatchWithIndex::new();
+        batch.put(b"b", b"batch");
+        let mut iter = batch.scan(&db, Some(&keys[1]), None).unwrap();
+        assert_eq!(iter.key(), &keys[1][..]);
+        iter.next();
+        assert_eq!(iter.key(), b"b");
+        iter.next();
+        assert_eq!(iter.key(), &keys[26][..]);
+    }
+
+    #[test]
+    fn test_verify_range_with_comparator() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(
+            temp_dir.path(),
+            Options::default().comparator(Arc::new(Descending)).block_size(256),
+        )
+        .unwrap();
+
+        // One file holds key0199 down to key0100, the other key0099 down
+        // to key0000
+        for batch in [100..200, 0..100] {
+            for i in batch {
+                db.put(format!("key{:04}", i).as_bytes(), &[b'v'; 32]).unwrap();
+            }
+            db.flush().unwrap();
+        }
+
+        // Ranges run from the larger key down to the smaller one
+        let report = db.verify_checksums_in_range(b"key0150", b"key0140").unwrap();
+        assert_eq!(report.sstables_checked, 1);
+        let report = db.verify_checksums_in_range(b"key0150", b"key0050").unwrap();
+        assert_eq!(report.sstables_checked, 2);
+        let report = db.verify_checksums_in_range(b"key0000", b"a").unwrap();
+        assert_eq!(report.sstables_checked, 1);
+        let report = db.verify_checksums_in_range(b"key0300", b"key0250").unwrap();
+        assert_eq!(report.sstables_checked, 0);
+
+        // A corrupted block is found through a range in the comparator's order
+        let sst_path = db
+            .sstables
+            .read()
+            .iter()
+            .flatten()
+            .find(|reader| reader.smallest_key().unwrap() == Some(b"key0199".to_vec()))
+            .unwrap()
+            .file_path()
+            .to_path_buf();
+        let mut data = std::fs::read(&sst_path).unwrap();
+        data[0] ^= 0xff;
+        std::fs::write(&sst_path, data).unwrap();
+        let report = db.verify_checksums_in_range(b"key0199", b"key0198").unwrap();
+        assert_eq!(report.corruptions.len(), 1);
+        assert!(db.verify_checksums_in_range(b"key0099", b"key0000").unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_reopen_with_other_comparator_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            db.put(b"key", b"value").unwrap();
+        }
+
+        let options = Options::default().comparator(Arc::new(Descending));
+        let result = DB::open(temp_dir.path(), options.clone());
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+
+        // The manifest records the name even without an OPTIONS file
+        for entry in std::fs::read_dir(temp_dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            if path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(config::OPTIONS_FILE_PREFIX)
+            {
+                std::fs::remove_file(path).unwrap();
+            }
+        }
+        let result = DB::open(temp_dir.path(), options);
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[test]
+    fn test_set_options() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        db.set_options(&[("memtable_size", "1024"), ("block_cache_size", "4096")])
+            .unwrap();
+
+        let options = db.options();
+        assert_eq!(options.memtable_size, 1024);
+        assert_eq!(options.block_cache_size, 4096);
+        assert_eq!(db.block_cache.capacity(), 4096);
+
+        // The new memtable size takes effect immediately
+        for i in 0..50 {
+            db.put(format!("key{:04}", i).as_bytes(), &[b'x'; 64]).unwrap();
+        }
+        assert!(!db.immutable_memtables.read().is_empty());
+
+        // The change is persisted for the next open
+        assert_eq!(Options::load_latest(temp_dir.path()).unwrap().memtable_size, 1024);
+    }
+
+    #[test]
+    fn test_set_options_rejects_invalid_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        // Immutable option
+        assert!(db.set_options(&[("max_levels", "3")]).is_err());
+        // Unknown option
+        assert!(db.set_options(&[("no_such_option", "1")]).is_err());
+        // Unparsable value
+        assert!(db.set_options(&[("memtable_size", "big")]).is_err());
+        // Fails validation; the earlier valid change must not be applied either
+        assert!(db.set_options(&[("block_size", "8192"), ("memtable_size", "0")]).is_err());
+
+        assert_eq!(db.options().block_size, Options::default().block_size);
+        assert_eq!(db.options().memtable_size, Options::default().memtable_size);
+    }
+
+    #[test]
+    fn test_get_reads_newest_level0_table_first() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            db.put(b"key", b"old").unwrap();
+            db.flush().unwrap();
+            db.put(b"key", b"new").unwrap();
+            db.flush().unwrap();
+            assert_eq!(db.get(b"key").unwrap(), Some(b"new".to_vec()));
+        }
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"key").unwrap(), Some(b"new".to_vec()));
     }
-}
 
-impl Drop for DB {
-    fn drop(&mut self) {
-        // Attempt to flush and close cleanly
-        // Ignore errors during drop as we can't propagate them
-        if let Err(e) = self.flush() {
-            eprintln!("Error flushing database during drop: {}", e);
+    #[test]
+    fn test_get_checks_one_table_per_level() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().use_bloom_filter(false).level_options(
+            1,
+            config::LevelOptions::new()
+                .compression(config::CompressionType::None)
+                .target_file_size(8 * 1024),
+        );
+        let mut expected = std::collections::HashMap::new();
+        {
+            let db = DB::open(temp_dir.path(), options.clone()).unwrap();
+            // Overlapping rounds: each compaction merges with Level 1
+            for round in 0..8 {
+                for i in 0..100 {
+                    let key = format!("key{:04}", (round * 37 + i * 7) % 500);
+                    db.put(key.as_bytes(), &[b'0' + round as u8; 100]).unwrap();
+                    expected.insert(key, vec![b'0' + round as u8; 100]);
+                }
+                db.flush().unwrap();
+            }
         }
 
-        if self.options.use_wal {
-            let mut wal = self.wal.write();
-            if let Err(e) = wal.sync() {
-                eprintln!("Error syncing WAL during drop: {}", e);
+        let db = DB::open(temp_dir.path(), options).unwrap();
+        {
+            let sstables = db.sstables.read();
+            assert!(sstables[1].len() > 1, "expected several Level 1 files");
+            for pair in sstables[1].windows(2) {
+                assert!(pair[0].largest_key().unwrap() < pair[1].smallest_key().unwrap());
             }
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+        perf_context::reset();
+        for i in 0..500 {
+            let key = format!("key{:04}", i);
+            assert_eq!(db.get(key.as_bytes()).unwrap(), expected.get(&key).cloned(), "{}", key);
+        }
+        let tables_per_get = db.sstables.read().iter().filter(|l| !l.is_empty()).count() as u64;
+        assert!(perf_context::get().sstables_checked <= 500 * tables_per_get);
+    }
 
     #[test]
-    fn test_db_open() {
+    fn test_compaction_splits_output_by_level_target_file_size() {
         let temp_dir = TempDir::new().unwrap();
-        let options = Options::default();
-        let result = DB::open(temp_dir.path(), options);
-        assert!(result.is_ok());
+        let options = Options::default().memtable_size(16 * 1024).level_options(
+            1,
+            config::LevelOptions::new()
+                .compression(config::CompressionType::None)
+                .target_file_size(8 * 1024),
+        );
+        let db = DB::open(temp_dir.path(), options).unwrap();
+
+        for batch in 0..4 {
+            for i in 0..100 {
+                db.put(format!("key{:04}", batch * 100 + i).as_bytes(), &[b'x'; 100]).unwrap();
+            }
+            db.flush().unwrap();
+        }
+
+        let sstables = db.sstables.read();
+        assert!(sstables[0].is_empty());
+        assert!(sstables[1].len() > 1, "expected split output, got {} files", sstables[1].len());
+        for pair in sstables[1].windows(2) {
+            assert!(pair[0].largest_key().unwrap() < pair[1].smallest_key().unwrap());
+        }
     }
 
     #[test]
-    fn test_db_put_and_get() {
+    fn test_db_open_rejects_fewer_levels_than_populated() {
         let temp_dir = TempDir::new().unwrap();
-        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default().memtable_size(1024)).unwrap();
+            for batch in 0..4 {
+                for i in 0..20 {
+                    db.put(format!("key{:02}_{:02}", batch, i).as_bytes(), b"value").unwrap();
+                }
+                db.flush().unwrap();
+            }
+            assert!(!db.sstables.read()[1].is_empty());
+        }
 
-        // Test put and get
-        db.put(b"key1", b"value1").unwrap();
-        let value = db.get(b"key1").unwrap();
-        assert_eq!(value, Some(b"value1".to_vec()));
+        // Level 1 is populated, so a single level is not enough
+        let result = DB::open(temp_dir.path(), Options::default().max_levels(1));
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
 
-        // Test non-existent key
-        let value = db.get(b"key2").unwrap();
-        assert_eq!(value, None);
+        // The rejected options were not persisted
+        assert_eq!(Options::load_latest(temp_dir.path()).unwrap().max_levels, 7);
+        assert!(DB::open(temp_dir.path(), Options::default().max_levels(2)).is_ok());
     }
 
     #[test]
-    fn test_db_delete() {
+    fn test_db_open_rejects_unsupported_sstable_compression() {
         let temp_dir = TempDir::new().unwrap();
-        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
-
-        // Put a key
-        db.put(b"key1", b"value1").unwrap();
-        assert_eq!(db.get(b"key1").unwrap(), Some(b"value1".to_vec()));
-
-        // Delete the key
-        db.delete(b"key1").unwrap();
-        assert_eq!(db.get(b"key1").unwrap(), None);
+        drop(DB::open(temp_dir.path(), Options::default()).unwrap());
+
+        // Write an SSTable whose data block claims an unknown compression type
+        let sst_path = temp_dir.path().join("000099.sst");
+        let mut builder = SSTableBuilder::new(&sst_path).unwrap();
+        builder.add(b"other", b"value").unwrap();
+        let block_len = builder.current_size() as usize;
+        builder.finish().unwrap();
+
+        let mut data = std::fs::read(&sst_path).unwrap();
+        data[block_len] = 9;
+        std::fs::write(&sst_path, data).unwrap();
+        assert_eq!(SSTableReader::open(&sst_path).unwrap().data_compression_id().unwrap(), Some(9));
+
+        let result = DB::open(temp_dir.path(), Options::default());
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
     }
 
     #[test]
-    fn test_db_overwrite() {
+    fn test_background_error_makes_db_read_only_until_resume() {
         let temp_dir = TempDir::new().unwrap();
         let db = DB::open(temp_dir.path(), Options::default()).unwrap();
-
-        // Put initial value
         db.put(b"key1", b"value1").unwrap();
+
+        // Block the next SSTable path with a directory so the flush fails
+        let next_file = db.next_file_number.load(Ordering::SeqCst);
+        let blocker = temp_dir.path().join(format!("{:06}.sst", next_file));
+        std::fs::create_dir(&blocker).unwrap();
+
+        assert!(db.flush().is_err());
+        assert!(matches!(db.background_error(), Some(Error::BackgroundError(_))));
+
+        // Writes are rejected, reads still work
+        assert!(matches!(db.put(b"key2", b"value2"), Err(Error::BackgroundError(_))));
+        assert!(matches!(db.delete(b"key1"), Err(Error::BackgroundError(_))));
         assert_eq!(db.get(b"key1").unwrap(), Some(b"value1".to_vec()));
 
-        // Overwrite with new value
-        db.put(b"key1", b"value2").unwrap();
-        assert_eq!(db.get(b"key1").unwrap(), Some(b"value2".to_vec()));
+        // The memtable that failed to flush is retried on resume
+        std::fs::remove_dir(&blocker).unwrap();
+        db.resume().unwrap();
+        assert!(db.background_error().is_none());
+        assert!(db.immutable_memtables.read().is_empty());
+        assert_eq!(db.sstables.read()[0].len(), 1);
+
+        db.put(b"key2", b"value2").unwrap();
+        assert_eq!(db.get(b"key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(db.get(b"key2").unwrap(), Some(b"value2".to_vec()));
     }
 
     #[test]
-    fn test_db_multiple_operations() {
+    fn test_resume_without_background_error_is_noop() {
         let temp_dir = TempDir::new().unwrap();
         let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert!(db.background_error().is_none());
+        db.resume().unwrap();
+    }
 
-        // Multiple puts
-        for i in 0..100 {
-            let key = format!("key{}", i);
-            let value = format!("value{}", i);
-            db.put(key.as_bytes(), value.as_bytes()).unwrap();
-        }
+    #[test]
+    fn test_key_value_size_limits() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().max_key_size(8).max_value_size(16);
+        let db = DB::open(temp_dir.path(), options).unwrap();
 
-        // Verify all values
-        for i in 0..100 {
-            let key = format!("key{}", i);
-            let expected = format!("value{}", i);
-            let value = db.get(key.as_bytes()).unwrap();
-            assert_eq!(value, Some(expected.as_bytes().to_vec()));
-        }
+        db.put(b"12345678", &[b'v'; 16]).unwrap();
+        assert!(matches!(db.put(b"123456789", b"v"), Err(Error::InvalidArgument(_))));
+        assert!(matches!(db.put(b"key", &[b'v'; 17]), Err(Error::InvalidArgument(_))));
+        assert!(matches!(db.delete(b"123456789"), Err(Error::InvalidArgument(_))));
+
+        // An oversized entry rejects the whole batch
+        let mut batch = WriteBatch::new();
+        batch.put(b"ok", b"value");
+        batch.put(b"big", &[b'v'; 17]);
+        assert!(matches!(db.write(batch), Err(Error::InvalidArgument(_))));
+        assert_eq!(db.get(b"ok").unwrap(), None);
+
+        // The limits can be raised at runtime
+        db.set_options(&[("max_value_size", "32")]).unwrap();
+        db.put(b"key", &[b'v'; 17]).unwrap();
     }
 
     #[test]
-    fn test_db_close() {
+    fn test_batch_size_limits() {
         let temp_dir = TempDir::new().unwrap();
-        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        let options = Options::default().max_batch_ops(3).max_batch_bytes(64);
+        let db = DB::open(temp_dir.path(), options).unwrap();
 
-        db.put(b"key1", b"value1").unwrap();
-        let result = db.close();
-        assert!(result.is_ok());
+        let mut batch = WriteBatch::new();
+        for key in [b"a", b"b", b"c", b"d"] {
+            batch.put(key, b"value");
+        }
+        assert!(matches!(db.write(batch.clone()), Err(Error::InvalidArgument(_))));
+        assert_eq!(db.get(b"a").unwrap(), None);
+
+        let mut large = WriteBatch::new();
+        large.put(b"a", &[b'v'; 60]);
+        assert!(large.data_size() > 64);
+        assert!(matches!(db.write(large), Err(Error::InvalidArgument(_))));
+
+        // Single writes are only bound by max_key_size and max_value_size
+        db.put(b"a", &[b'v'; 60]).unwrap();
+
+        db.set_options(&[("max_batch_ops", "0"), ("max_batch_bytes", "0")]).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(db.get(b"d").unwrap(), Some(b"value".to_vec()));
     }
 
     #[test]
-    fn test_db_recovery() {
+    fn test_no_space_error_releases_reserve_and_resumes_automatically() {
         let temp_dir = TempDir::new().unwrap();
-        let db_path = temp_dir.path().to_path_buf();
+        let reserved = temp_dir.path().join(util::RESERVED_FILE);
+        let db = DB::open(temp_dir.path(), Options::default().reserved_disk_space(8192)).unwrap();
+        assert_eq!(std::fs::metadata(&reserved).unwrap().len(), 8192);
+        db.put(b"key1", b"value1").unwrap();
 
-        // First session: write data
-        {
-            let db = DB::open(&db_path, Options::default()).unwrap();
-            db.put(b"key1", b"value1").unwrap();
-            db.put(b"key2", b"value2").unwrap();
-            db.close().unwrap();
-        }
+        // Simulate a flush that ran out of space
+        db.set_background_error(Error::NoSpace("simulated".to_string()));
+        assert!(!reserved.exists());
+        assert!(matches!(db.put(b"key2", b"value2"), Err(Error::BackgroundError(_))));
+
+        // Once the retry interval has passed, the next write resumes by itself
+        db.background_error.write().as_mut().unwrap().last_retry -=
+            NO_SPACE_RETRY_INTERVAL.as_micros() as u64;
+        db.put(b"key2", b"value2").unwrap();
+        assert!(db.background_error().is_none());
+        assert_eq!(std::fs::metadata(&reserved).unwrap().len(), 8192);
+        assert_eq!(db.get(b"key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(db.get(b"key2").unwrap(), Some(b"value2".to_vec()));
+    }
 
-        // Second session: verify recovery
+    /// Writes two SSTables and destroys the footer of the newer one.
+    fn create_db_with_corrupt_sstable(path: &std::path::Path) -> PathBuf {
         {
-            let _db = DB::open(&db_path, Options::default()).unwrap();
-            // Note: Currently recovery from WAL is not fully implemented
-            // This test will be enhanced in future
+            let db = DB::open(path, Options::default()).unwrap();
+            db.put(b"good", b"value").unwrap();
+            db.flush().unwrap();
+            db.put(b"bad", b"value").unwrap();
+            db.flush().unwrap();
         }
+        let corrupt = path.join("000003.sst");
+        let data = std::fs::read(&corrupt).unwrap();
+        std::fs::write(&corrupt, &data[..data.len() - 8]).unwrap();
+        corrupt
     }
 
     #[test]
-    fn test_db_error_if_exists() {
+    fn test_open_quarantines_corrupt_sstable() {
         let temp_dir = TempDir::new().unwrap();
+        let corrupt = create_db_with_corrupt_sstable(temp_dir.path());
+
+        let db =
+            DB::open(temp_dir.path(), Options::default().quarantine_corrupt_files(true)).unwrap();
+        let stats = db.quarantine_stats();
+        assert_eq!(stats.sstables_quarantined, 1);
+        assert_eq!(stats.quarantined_paths, vec![temp_dir.path().join("lost").join("000003.sst")]);
+        assert!(!corrupt.exists());
+        assert_eq!(db.get(b"good").unwrap(), Some(b"value".to_vec()));
+    }
 
-        // Create the database first
+    #[test]
+    fn test_open_refuses_corrupt_sstable_without_allow_data_loss() {
+        let temp_dir = TempDir::new().unwrap();
+        let corrupt = create_db_with_corrupt_sstable(temp_dir.path());
+
+        let options = Options::default().quarantine_corrupt_files(true).allow_data_loss(false);
+        assert!(matches!(DB::open(temp_dir.path(), options), Err(Error::Corruption(_))));
+        assert!(corrupt.exists());
+
+        // Without quarantining, the file is skipped and left in place
         let db = DB::open(temp_dir.path(), Options::default()).unwrap();
-        db.close().unwrap();
-        drop(db);
+        assert_eq!(db.quarantine_stats().sstables_quarantined, 0);
+        assert!(corrupt.exists());
+    }
 
-        // Try to open with error_if_exists
-        let options = Options::default().create_if_missing(false);
-        let mut options = options;
-        options.error_if_exists = true;
+    #[test]
+    fn test_verify_checksums() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default().block_size(256)).unwrap();
 
-        let result = DB::open(temp_dir.path(), options);
-        assert!(result.is_err());
+        for i in 0..200 {
+            db.put(format!("key{:04}", i).as_bytes(), &[b'v'; 32]).unwrap();
+        }
+        db.flush().unwrap();
+        db.put(b"unflushed", b"value").unwrap();
+
+        let report = db.verify_checksums().unwrap();
+        assert!(report.is_ok(), "{:?}", report.corruptions);
+        assert_eq!(report.sstables_checked, 1);
+        assert_eq!(report.wal_files_checked, 1);
+        assert_eq!(report.wal_entries_checked, 1);
+
+        // Corrupt the first data block of the SSTable
+        let sst_path = db.sstables.read()[0][0].file_path().to_path_buf();
+        let mut data = std::fs::read(&sst_path).unwrap();
+        data[0] ^= 0xff;
+        std::fs::write(&sst_path, data).unwrap();
+
+        let report = db.verify_checksums().unwrap();
+        assert_eq!(report.corruptions.len(), 1);
+        assert_eq!(report.corruptions[0].path, sst_path);
+        assert_eq!(report.corruptions[0].offset, Some(0));
+
+        // The corrupted block only holds the first keys
+        assert_eq!(
+            db.verify_checksums_in_range(b"key0000", b"key0001").unwrap().corruptions.len(),
+            1
+        );
+        let report = db.verify_checksums_in_range(b"key0150", b"key0160").unwrap();
+        assert_eq!(report.sstables_checked, 1);
+        assert!(report.is_ok());
+        let report = db.verify_checksums_in_range(b"zzz", b"zzzz").unwrap();
+        assert_eq!(report.sstables_checked, 0);
+    }
+
+    #[test]
+    fn test_mixed_checksum_types_across_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            db.put(b"crc32_key", b"value1").unwrap();
+            db.flush().unwrap();
+        }
+        {
+            let db =
+                DB::open(temp_dir.path(), Options::default().checksum(config::ChecksumType::Xxh3))
+                    .unwrap();
+            db.put(b"xxh3_key", b"value2").unwrap();
+            db.flush().unwrap();
+
+            let mut checksum_types: Vec<_> =
+                db.sstables.read()[0].iter().map(|r| r.checksum_type()).collect();
+            checksum_types.sort_by_key(|c| *c as u8);
+            assert_eq!(
+                checksum_types,
+                vec![config::ChecksumType::Crc32, config::ChecksumType::Xxh3]
+            );
+        }
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"crc32_key").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(db.get(b"xxh3_key").unwrap(), Some(b"value2".to_vec()));
+        assert!(db.verify_checksums().unwrap().is_ok());
     }
 
     // ===== Flush Tests =====
@@ -1417,6 +4966,97 @@ mod tests {
         assert!(sstables[0].is_empty(), "No SSTables should be created for empty memtable");
     }
 
+    #[test]
+    fn test_flush_during_writes_keeps_acknowledged_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = || Options::default().sync_wal(true);
+        let mut acknowledged = Vec::new();
+        for round in 0..30 {
+            let db = DB::open(temp_dir.path(), options()).unwrap();
+            let stop = AtomicBool::new(false);
+            std::thread::scope(|s| {
+                let writers: Vec<_> = (0..4)
+                    .map(|writer| {
+                        let (db, stop) = (&db, &stop);
+                        s.spawn(move || {
+                            let mut written = Vec::new();
+                            for i in 0.. {
+                                if stop.load(Ordering::SeqCst) {
+                                    break;
+                                }
+                                let key = format!("{:02}-{}-{:05}", round, writer, i);
+                                db.put(key.as_bytes(), b"v").unwrap();
+                                written.push(key);
+                            }
+                            written
+                        })
+                    })
+                    .collect();
+                for _ in 0..5 {
+                    db.flush().unwrap();
+                }
+                stop.store(true, Ordering::SeqCst);
+                for writer in writers {
+                    acknowledged.extend(writer.join().unwrap());
+                }
+            });
+            db.simulate_crash();
+        }
+
+        let db = DB::open(temp_dir.path(), options()).unwrap();
+        let lost: Vec<_> = acknowledged
+            .iter()
+            .filter(|key| db.get(key.as_bytes()).unwrap().is_none())
+            .collect();
+        assert!(lost.is_empty(), "{} of {} writes lost", lost.len(), acknowledged.len());
+    }
+
+    #[test]
+    fn test_frozen_wals_replayed_and_retired() {
+        let temp_dir = TempDir::new().unwrap();
+        let wal_files = || {
+            let mut names: Vec<String> = std::fs::read_dir(temp_dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .filter(|name| wal::parse_wal_filename(name).is_some())
+                .collect();
+            names.sort();
+            names
+        };
+
+        // Frozen MemTables not flushed before the crash
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.put(b"a", b"1").unwrap();
+        db.freeze_memtable().unwrap();
+        db.put(b"b", b"2").unwrap();
+        db.freeze_memtable().unwrap();
+        db.put(b"c", b"3").unwrap();
+        assert_eq!(wal_files().len(), 3);
+        db.simulate_crash();
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        for (key, value) in [(b"a", b"1"), (b"b", b"2"), (b"c", b"3")] {
+            assert_eq!(db.get(key).unwrap(), Some(value.to_vec()));
+        }
+        assert_eq!(db.frozen_wals().len(), 2);
+        assert!(db.verify_checksums().unwrap().is_ok());
+        assert_eq!(db.verify_checksums().unwrap().wal_files_checked, 3);
+
+        // Writes frozen after the flush keep their WAL
+        db.flush().unwrap();
+        db.put(b"d", b"4").unwrap();
+        db.freeze_memtable().unwrap();
+        assert_eq!(wal_files().len(), 2);
+        db.simulate_crash();
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(db.get(b"d").unwrap(), Some(b"4".to_vec()));
+        db.flush().unwrap();
+        assert_eq!(wal_files().len(), 1);
+        assert!(db.frozen_wals().is_empty());
+    }
+
     #[test]
     fn test_multiple_flushes() {
         let temp_dir = TempDir::new().unwrap();
@@ -1462,6 +5102,187 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_iterator_pins_compacted_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().level0_compaction_threshold(100);
+        let db = Arc::new(DB::open(temp_dir.path(), options).unwrap());
+        for i in 0..4 {
+            db.put(format!("key{}", i).as_bytes(), b"value").unwrap();
+            db.flush().unwrap();
+        }
+        let inputs: Vec<PathBuf> = db.sstables.read()[0]
+            .iter()
+            .map(|table| table.file_path().to_path_buf())
+            .collect();
+
+        let mut iter = db.iter();
+        db.set_options(&[("level0_compaction_threshold", "4")]).unwrap();
+        db.maybe_trigger_compaction().unwrap();
+        assert!(db.sstables.read()[0].is_empty());
+
+        // The compacted inputs stay on disk while the iterator reads them
+        assert!(inputs.iter().all(|path| path.exists()));
+        let mut count = 0;
+        while iter.valid() {
+            count += 1;
+            iter.next();
+        }
+        assert_eq!(count, 4);
+        iter.status().unwrap();
+
+        drop(iter);
+        assert!(inputs.iter().all(|path| !path.exists()));
+    }
+
+    #[test]
+    fn test_max_open_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = || Options::default().level0_compaction_threshold(100).max_open_files(2);
+        let db = Arc::new(DB::open(temp_dir.path(), options()).unwrap());
+        for i in 0..5u8 {
+            db.put(&[i], &[i]).unwrap();
+            db.flush().unwrap();
+        }
+        let cache = db.table_cache.as_ref().unwrap();
+        assert!(cache.len() <= 2);
+        for i in 0..5u8 {
+            assert_eq!(db.get(&[i]).unwrap(), Some(vec![i]));
+        }
+        assert!(cache.len() <= 2);
+        assert!(cache.opens() > 0);
+
+        let mut iter = db.iter();
+        let mut count = 0;
+        while iter.valid() {
+            count += 1;
+            iter.next();
+        }
+        assert_eq!(count, 5);
+        drop(iter);
+        drop(db);
+
+        let db = DB::open(temp_dir.path(), options()).unwrap();
+        assert!(db.table_cache.as_ref().unwrap().len() <= 2);
+        assert_eq!(db.get(&[0]).unwrap(), Some(vec![0]));
+    }
+
+    #[test]
+    fn test_open_removes_obsolete_files() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            db.put(b"key", b"live").unwrap();
+            db.close().unwrap();
+        }
+
+        // A compaction input that was still pinned when the process died
+        let path = temp_dir.path().join("000100.sst");
+        let mut builder = SSTableBuilder::new(&path).unwrap();
+        builder.add(b"key", b"stale").unwrap();
+        builder.finish().unwrap();
+        {
+            let mut version_set = VersionSet::new(temp_dir.path(), 7).unwrap();
+            version_set
+                .log_edit(&VersionEdit::DeleteFile { level: 0, file_number: 100 })
+                .unwrap();
+        }
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert!(!path.exists());
+        assert_eq!(db.get(b"key").unwrap(), Some(b"live".to_vec()));
+    }
+
+    #[test]
+    fn test_writes_throttled_by_compaction_debt() {
+        let temp_dir = TempDir::new().unwrap();
+        let stats = Arc::new(Statistics::new());
+        let options = Options::default()
+            .level0_compaction_threshold(100)
+            .statistics(Arc::clone(&stats));
+        let db = DB::open(temp_dir.path(), options).unwrap();
+        for i in 0..4u8 {
+            db.put(&[i], b"v").unwrap();
+            db.flush().unwrap();
+        }
+        assert_eq!(db.pending_compaction_bytes(), 0);
+
+        // Delayed above the soft limit
+        db.set_options(&[
+            ("level0_compaction_threshold", "2"),
+            ("soft_pending_compaction_bytes_limit", "1"),
+            ("hard_pending_compaction_bytes_limit", "0"),
+        ])
+        .unwrap();
+        assert!(db.pending_compaction_bytes() > 0);
+        db.put(b"a", b"v").unwrap();
+        assert!(stats.ticker(Ticker::StallMicros) >= 1000);
+        assert_eq!(db.level_sizes()[0].0, 4);
+
+        // Above the hard limit the write waits for the compaction
+        db.set_options(&[("hard_pending_compaction_bytes_limit", "1")]).unwrap();
+        db.put(b"b", b"v").unwrap();
+        assert_eq!(db.level_sizes()[0].0, 0);
+        assert_eq!(db.pending_compaction_bytes(), 0);
+        assert_eq!(db.get(&[3]).unwrap(), Some(b"v".to_vec()));
+    }
+
+    #[test]
+    fn test_parallel_flush_installs_tables_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().flush_threads(4).level0_compaction_threshold(100);
+        let db = DB::open(temp_dir.path(), options).unwrap();
+
+        for round in 0..6 {
+            db.put(b"key", format!("value{}", round).as_bytes()).unwrap();
+            db.put(format!("key{}", round).as_bytes(), b"x").unwrap();
+            db.freeze_memtable().unwrap();
+        }
+        let first_file = db.next_file_number.load(Ordering::SeqCst);
+        db.flush().unwrap();
+
+        assert!(db.immutable_memtables.read().is_empty());
+        let numbers: Vec<u64> =
+            db.sstables.read()[0].iter().map(|table| table.file_number().unwrap()).collect();
+        assert_eq!(numbers, (first_file..first_file + 6).rev().collect::<Vec<_>>());
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value5".to_vec()));
+        for round in 0..6 {
+            assert_eq!(db.get(format!("key{}", round).as_bytes()).unwrap(), Some(b"x".to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_parallel_flush_failure_keeps_newer_memtables_queued() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().flush_threads(4).level0_compaction_threshold(100);
+        let db = DB::open(temp_dir.path(), options).unwrap();
+
+        for round in 0..4 {
+            db.put(b"key", format!("value{}", round).as_bytes()).unwrap();
+            db.freeze_memtable().unwrap();
+        }
+
+        // The second MemTable fails to flush
+        let first_file = db.next_file_number.load(Ordering::SeqCst);
+        let blocker = temp_dir.path().join(format!("{:06}.sst", first_file + 1));
+        std::fs::create_dir(&blocker).unwrap();
+        assert!(db.flush().is_err());
+
+        // Only the oldest table was installed; the newer ones were removed
+        assert_eq!(db.sstables.read()[0].len(), 1);
+        assert_eq!(db.immutable_memtables.read().len(), 3);
+        for file in first_file + 2..first_file + 4 {
+            assert!(!temp_dir.path().join(format!("{:06}.sst", file)).exists());
+        }
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value3".to_vec()));
+
+        std::fs::remove_dir(&blocker).unwrap();
+        db.resume().unwrap();
+        assert!(db.immutable_memtables.read().is_empty());
+        assert_eq!(db.sstables.read()[0].len(), 4);
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value3".to_vec()));
+    }
+
     #[test]
     fn test_close_triggers_flush() {
         let temp_dir = TempDir::new().unwrap();
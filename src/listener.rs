@@ -0,0 +1,150 @@
+//! Callbacks for database events.
+//!
+//! Implement [`EventListener`] and register it with
+//! [`Options::add_listener`](crate::Options::add_listener) to be notified
+//! when MemTables are flushed, compactions finish, SSTables are created or
+//! deleted, the WAL is rotated, or a background error occurs.
+//!
+//! Callbacks run synchronously on the thread that performed the work (for
+//! example the thread calling `put` that triggered a flush), after the
+//! database has released its internal locks. They should return quickly;
+//! hand longer work such as copying files to a backup off to another thread.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::listener::{EventListener, TableFileCreationInfo};
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! struct BackupTrigger;
+//!
+//! impl EventListener for BackupTrigger {
+//!     fn on_table_file_created(&self, info: &TableFileCreationInfo) {
+//!         println!("new SSTable at level {}: {:?}", info.level, info.file_path);
+//!     }
+//! }
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let options = Options::default().add_listener(Arc::new(BackupTrigger));
+//! let db = DB::open("./data", options)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Receives notifications about database events.
+///
+/// Every method has an empty default implementation, so listeners only
+/// implement the events they care about.
+pub trait EventListener: Send + Sync {
+    /// Called before a MemTable is written to an SSTable.
+    ///
+    /// `file_size` is 0 and `entry_count` is the number of MemTable entries
+    /// (including older versions that the flush drops).
+    fn on_flush_begin(&self, _info: &FlushJobInfo) {}
+
+    /// Called after a flushed SSTable has been added to Level 0.
+    ///
+    /// Not called if the MemTable held nothing to write.
+    fn on_flush_completed(&self, _info: &FlushJobInfo) {}
+
+    /// Called after a compaction has installed its outputs and deleted its
+    /// inputs. Inputs still read by an iterator are deleted once it releases
+    /// them, with [`on_table_file_deleted`](Self::on_table_file_deleted).
+    fn on_compaction_completed(&self, _info: &CompactionJobInfo) {}
+
+    /// Called when a new SSTable becomes part of the database.
+    fn on_table_file_created(&self, _info: &TableFileCreationInfo) {}
+
+    /// Called after an SSTable has been deleted from disk.
+    fn on_table_file_deleted(&self, _info: &TableFileDeletionInfo) {}
+
+    /// Called after the database switched to a new WAL file.
+    fn on_wal_rotated(&self, _info: &WalRotationInfo) {}
+
+    /// Called when a flush, compaction or WAL write failure puts the database
+    /// into the background error state.
+    fn on_background_error(&self, _error: &Error) {}
+}
+
+impl fmt::Debug for dyn EventListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventListener")
+    }
+}
+
+/// Why an SSTable was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFileReason {
+    /// Written by a MemTable flush
+    Flush,
+    /// Written by a compaction
+    Compaction,
+    /// Added by [`DB::ingest_external_file`](crate::DB::ingest_external_file)
+    Ingestion,
+}
+
+/// Details of a MemTable flush.
+#[derive(Debug, Clone)]
+pub struct FlushJobInfo {
+    /// File number of the SSTable being written
+    pub file_number: u64,
+    /// Path of the SSTable being written
+    pub file_path: PathBuf,
+    /// Number of entries written
+    pub entry_count: usize,
+    /// Size of the SSTable in bytes
+    pub file_size: u64,
+}
+
+/// Details of a finished compaction.
+#[derive(Debug, Clone)]
+pub struct CompactionJobInfo {
+    /// Level the inputs were taken from
+    pub input_level: usize,
+    /// Level the outputs were written to
+    pub output_level: usize,
+    /// Paths of the compacted (now deleted) SSTables
+    pub input_files: Vec<PathBuf>,
+    /// Paths of the SSTables written
+    pub output_files: Vec<PathBuf>,
+    /// Total size of the outputs in bytes
+    pub bytes_written: u64,
+}
+
+/// Details of a new SSTable.
+#[derive(Debug, Clone)]
+pub struct TableFileCreationInfo {
+    /// File number of the SSTable
+    pub file_number: u64,
+    /// Path of the SSTable
+    pub file_path: PathBuf,
+    /// Size of the SSTable in bytes
+    pub file_size: u64,
+    /// Level the SSTable was added to
+    pub level: usize,
+    /// Why the SSTable was written
+    pub reason: TableFileReason,
+}
+
+/// Details of a deleted SSTable.
+#[derive(Debug, Clone)]
+pub struct TableFileDeletionInfo {
+    /// File number of the SSTable
+    pub file_number: u64,
+    /// Path the SSTable had before it was deleted
+    pub file_path: PathBuf,
+}
+
+/// Details of a WAL rotation.
+#[derive(Debug, Clone)]
+pub struct WalRotationInfo {
+    /// Path of the WAL that was retired and deleted
+    pub old_file_path: PathBuf,
+    /// Path of the WAL that receives new writes
+    pub new_file_path: PathBuf,
+}
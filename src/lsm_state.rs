@@ -0,0 +1,343 @@
+//! Snapshot of the LSM tree shape for debugging compaction.
+//!
+//! [`DB::dump_lsm_state`](crate::DB::dump_lsm_state) describes every level:
+//! its SSTables with their sizes and key ranges, which files overlap each
+//! other or the next level, and the compaction the picker would run next.
+//! The result serializes to JSON with [`LsmState::to_json`] and renders as a
+//! Graphviz graph with [`LsmState::to_dot`]:
+//!
+//! ```sh
+//! dot -Tsvg lsm.dot -o lsm.svg
+//! ```
+//!
+//! The manifest does not record the sequence numbers in each SSTable, so
+//! no sequence number ranges are reported per file; `last_sequence` is the
+//! newest sequence number of the whole database.
+
+use crate::compaction::CompactionPicker;
+use crate::comparator::Comparator;
+use crate::error::{Error, Result};
+use crate::sstable::SSTableReader;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Shape of the LSM tree at one point in time.
+#[derive(Debug, Clone, Serialize)]
+pub struct LsmState {
+    /// Newest sequence number in the database
+    pub last_sequence: u64,
+    /// Every level, starting with Level 0
+    pub levels: Vec<LevelState>,
+    /// The compaction the picker would run next, if any
+    pub pending_compaction: Option<PendingCompaction>,
+}
+
+/// One level of the LSM tree.
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelState {
+    /// Level number
+    pub level: usize,
+    /// Total size of the level's SSTables in bytes
+    pub size: u64,
+    /// Size that triggers a compaction of this level (`None` for Level 0,
+    /// which is triggered by file count)
+    pub target_size: Option<u64>,
+    /// True if any two files of the level have overlapping key ranges.
+    /// Expected at Level 0; a bug at any other level.
+    pub overlapping: bool,
+    /// SSTables of the level in lookup order
+    pub files: Vec<FileState>,
+}
+
+/// One SSTable.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileState {
+    /// File number (`None` if the file name is not `<number>.sst`)
+    pub file_number: Option<u64>,
+    /// File name
+    pub file_name: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Smallest key, with non-printable bytes escaped
+    pub smallest_key: Option<String>,
+    /// Largest key, with non-printable bytes escaped
+    pub largest_key: Option<String>,
+    /// Files of the same level whose key ranges overlap this one
+    pub overlaps_in_level: Vec<String>,
+    /// Files of the next level whose key ranges overlap this one
+    pub overlaps_next_level: Vec<String>,
+}
+
+/// A compaction the picker has selected but not yet run.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingCompaction {
+    /// Level the inputs are taken from
+    pub level: usize,
+    /// Level the outputs are written to
+    pub output_level: usize,
+    /// File names of the inputs
+    pub input_files: Vec<String>,
+    /// File numbers of the inputs, as taken by
+    /// [`DB::compact_files`](crate::DB::compact_files)
+    pub input_file_numbers: Vec<u64>,
+}
+
+/// Key range of an SSTable, used for the overlap checks
+struct KeyRange {
+    smallest: Option<Vec<u8>>,
+    largest: Option<Vec<u8>>,
+}
+
+impl KeyRange {
+    fn overlaps(&self, other: &KeyRange, comparator: &dyn Comparator) -> bool {
+        match (&self.smallest, &self.largest, &other.smallest, &other.largest) {
+            (Some(smallest), Some(largest), Some(other_smallest), Some(other_largest)) => {
+                comparator.compare(smallest, other_largest) != Ordering::Greater
+                    && comparator.compare(other_smallest, largest) != Ordering::Greater
+            }
+            // Empty tables overlap nothing
+            _ => false,
+        }
+    }
+}
+
+impl LsmState {
+    /// Describes `levels` and the compaction `picker` would choose for them.
+    pub(crate) fn capture(
+        levels: &[Vec<Arc<SSTableReader>>],
+        picker: &CompactionPicker,
+        last_sequence: u64,
+    ) -> Result<Self> {
+        let ranges = levels
+            .iter()
+            .map(|tables| {
+                tables
+                    .iter()
+                    .map(|table| {
+                        Ok(KeyRange {
+                            smallest: table.smallest_key()?,
+                            largest: table.largest_key()?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut level_states = Vec::with_capacity(levels.len());
+        for (level, tables) in levels.iter().enumerate() {
+            let next_level = level + 1;
+            let mut files = Vec::with_capacity(tables.len());
+            for (i, table) in tables.iter().enumerate() {
+                let range = &ranges[level][i];
+                let overlaps_in_level = tables
+                    .iter()
+                    .zip(&ranges[level])
+                    .enumerate()
+                    .filter(|&(j, (_, other))| j != i && range.overlaps(other, picker.comparator()))
+                    .map(|(_, (other, _))| file_name(other))
+                    .collect();
+                let overlaps_next_level = levels
+                    .get(next_level)
+                    .map(|next| {
+                        next.iter()
+                            .zip(&ranges[next_level])
+                            .filter(|(_, other)| range.overlaps(other, picker.comparator()))
+                            .map(|(other, _)| file_name(other))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                files.push(FileState {
+                    file_number: table.file_number(),
+                    file_name: file_name(table),
+                    size: table.file_size(),
+                    smallest_key: range.smallest.as_deref().map(escape_key),
+                    largest_key: range.largest.as_deref().map(escape_key),
+                    overlaps_in_level,
+                    overlaps_next_level,
+                });
+            }
+
+            level_states.push(LevelState {
+                level,
+                size: tables.iter().map(|table| table.file_size()).sum(),
+                target_size: (level > 0).then(|| picker.target_size_for_level(level)),
+                overlapping: files.iter().any(|file| !file.overlaps_in_level.is_empty()),
+                files,
+            });
+        }
+
+        let pending_compaction = (!levels.is_empty())
+            .then(|| picker.pick_compaction(levels))
+            .flatten()
+            .map(|task| PendingCompaction {
+                level: task.level,
+                output_level: task.output_level,
+                input_files: task.inputs.iter().map(|table| file_name(table)).collect(),
+                input_file_numbers: task.inputs.iter().filter_map(|t| t.file_number()).collect(),
+            });
+
+        Ok(Self { last_sequence, levels: level_states, pending_compaction })
+    }
+
+    /// Serializes the state as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| Error::internal(format!("Failed to serialize LSM state: {}", e)))
+    }
+
+    /// Renders the state as a Graphviz `digraph`.
+    ///
+    /// Each level is a cluster of SSTables labelled with their size and key
+    /// range. Dashed edges connect files to the overlapping files of the
+    /// next level, and the inputs of the pending compaction are drawn in red.
+    pub fn to_dot(&self) -> String {
+        let pending: Vec<&str> = self
+            .pending_compaction
+            .iter()
+            .flat_map(|task| task.input_files.iter().map(String::as_str))
+            .collect();
+
+        let mut dot = String::new();
+        dot.push_str("digraph lsm {\n");
+        dot.push_str("  rankdir=TB;\n");
+        dot.push_str("  node [shape=box, fontname=monospace];\n");
+        let _ = writeln!(dot, "  label=\"last sequence {}\";", self.last_sequence);
+
+        for level in &self.levels {
+            let _ = writeln!(dot, "  subgraph cluster_L{} {{", level.level);
+            let _ = writeln!(
+                dot,
+                "    label=\"L{}: {} files, {} bytes{}\";",
+                level.level,
+                level.files.len(),
+                level.size,
+                level.target_size.map(|target| format!(" / {}", target)).unwrap_or_default()
+            );
+            if level.overlapping && level.level > 0 {
+                dot.push_str("    color=red;\n");
+            }
+            if level.files.is_empty() {
+                // Graphviz drops empty clusters; keep the level visible
+                let _ =
+                    writeln!(dot, "    \"L{}\" [label=\"(empty)\", style=dotted];", level.level);
+            }
+            for file in &level.files {
+                let _ = writeln!(
+                    dot,
+                    "    \"{}\" [label=\"{}\\n{} bytes\\n[{} .. {}]\"{}];",
+                    file.file_name,
+                    file.file_name,
+                    file.size,
+                    dot_escape(file.smallest_key.as_deref().unwrap_or("")),
+                    dot_escape(file.largest_key.as_deref().unwrap_or("")),
+                    if pending.contains(&file.file_name.as_str()) {
+                        ", color=red"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            dot.push_str("  }\n");
+        }
+
+        for level in &self.levels {
+            for file in &level.files {
+                for other in &file.overlaps_next_level {
+                    let _ =
+                        writeln!(dot, "  \"{}\" -> \"{}\" [style=dashed];", file.file_name, other);
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn file_name(table: &SSTableReader) -> String {
+    table
+        .file_path()
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn escape_key(key: &[u8]) -> String {
+    key.escape_ascii().to_string()
+}
+
+/// Escapes a string for a double-quoted Graphviz label
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sstable::SSTableBuilder;
+    use tempfile::TempDir;
+
+    fn create_table(dir: &TempDir, file_number: u64, keys: &[&[u8]]) -> Arc<SSTableReader> {
+        let path = dir.path().join(format!("{:06}.sst", file_number));
+        let mut builder = SSTableBuilder::new(&path).unwrap();
+        for key in keys {
+            builder.add(key, b"value").unwrap();
+        }
+        builder.finish().unwrap();
+        Arc::new(SSTableReader::open(&path).unwrap())
+    }
+
+    #[test]
+    fn test_capture_overlaps() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut levels = vec![Vec::new(); 3];
+        levels[0].push(create_table(&temp_dir, 3, &[b"b", b"f"]));
+        levels[0].push(create_table(&temp_dir, 2, &[b"e", b"h"]));
+        levels[1].push(create_table(&temp_dir, 1, &[b"a", b"c"]));
+        levels[1].push(create_table(&temp_dir, 4, &[b"x", b"z"]));
+
+        let state = LsmState::capture(&levels, &CompactionPicker::new(3), 42).unwrap();
+        assert_eq!(state.last_sequence, 42);
+        assert_eq!(state.levels.len(), 3);
+
+        let level0 = &state.levels[0];
+        assert!(level0.overlapping);
+        assert_eq!(level0.target_size, None);
+        assert_eq!(level0.files[0].file_number, Some(3));
+        assert_eq!(level0.files[0].smallest_key.as_deref(), Some("b"));
+        assert_eq!(level0.files[0].largest_key.as_deref(), Some("f"));
+        assert_eq!(level0.files[0].overlaps_in_level, vec!["000002.sst"]);
+        assert_eq!(level0.files[0].overlaps_next_level, vec!["000001.sst"]);
+        assert!(level0.files[1].overlaps_next_level.is_empty());
+
+        assert!(!state.levels[1].overlapping);
+        assert!(state.levels[1].target_size.is_some());
+        assert!(state.pending_compaction.is_none());
+    }
+
+    #[test]
+    fn test_pending_compaction_and_rendering() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut levels = vec![Vec::new(); 2];
+        for i in 0..4 {
+            levels[0].push(create_table(&temp_dir, i, &[b"a\"\xff", b"k"]));
+        }
+
+        let state = LsmState::capture(&levels, &CompactionPicker::new(2), 0).unwrap();
+        let pending = state.pending_compaction.as_ref().unwrap();
+        assert_eq!((pending.level, pending.output_level), (0, 1));
+        assert_eq!(pending.input_files.len(), 4);
+
+        let json: serde_json::Value = serde_json::from_str(&state.to_json().unwrap()).unwrap();
+        assert_eq!(json["levels"][0]["files"][0]["smallest_key"], "a\\\"\\xff");
+        assert_eq!(json["pending_compaction"]["output_level"], 1);
+
+        let dot = state.to_dot();
+        assert!(dot.starts_with("digraph lsm {"));
+        assert!(dot.contains("subgraph cluster_L1"));
+        assert!(dot.contains("\"L1\" [label=\"(empty)\""));
+        assert!(dot.contains("[a\\\\\\\"\\\\xff .. k]\", color=red]"), "{}", dot);
+    }
+}
@@ -12,16 +12,18 @@
 //! ## Ordering
 //!
 //! InternalKeys are ordered by:
-//! 1. user_key (ascending)
+//! 1. user_key (ascending, bytewise or by a [`Comparator`])
 //! 2. sequence (descending - newer first)
 //! 3. type (descending - Value before Deletion)
 
+use crate::comparator::{BytewiseComparator, Comparator};
 use std::cmp::Ordering;
 
 /// The type of a value in the database.
 ///
 /// - `Value`: A normal key-value pair
 /// - `Deletion`: A tombstone marking that a key has been deleted
+/// - `Append`: Bytes appended to the key's older value
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ValueType {
     /// A tombstone indicating the key has been deleted
@@ -29,6 +31,9 @@ pub enum ValueType {
 
     /// A normal value
     Value = 1,
+
+    /// A suffix for the older value
+    Append = 2,
 }
 
 impl ValueType {
@@ -37,6 +42,7 @@ impl ValueType {
         match value {
             0 => Some(ValueType::Deletion),
             1 => Some(ValueType::Value),
+            2 => Some(ValueType::Append),
             _ => None,
         }
     }
@@ -52,7 +58,7 @@ impl ValueType {
 /// The internal key consists of:
 /// - User key: The key provided by the user
 /// - Sequence number: A monotonically increasing number for MVCC
-/// - Value type: Either Value or Deletion (tombstone)
+/// - Value type: Value, Deletion (tombstone) or Append
 ///
 /// # Ordering
 ///
@@ -153,8 +159,15 @@ impl PartialOrd for InternalKey {
 
 impl Ord for InternalKey {
     fn cmp(&self, other: &Self) -> Ordering {
+        self.compare_with(other, &BytewiseComparator)
+    }
+}
+
+impl InternalKey {
+    /// Compares with `other`, ordering the user keys with `comparator`
+    pub fn compare_with(&self, other: &Self, comparator: &dyn Comparator) -> Ordering {
         // First, compare user keys (ascending)
-        match self.user_key.cmp(&other.user_key) {
+        match comparator.compare(&self.user_key, &other.user_key) {
             Ordering::Equal => {
                 // If user keys are equal, compare sequence numbers (descending)
                 match other.sequence.cmp(&self.sequence) {
@@ -181,7 +194,8 @@ mod tests {
 
         assert_eq!(ValueType::from_u8(0), Some(ValueType::Deletion));
         assert_eq!(ValueType::from_u8(1), Some(ValueType::Value));
-        assert_eq!(ValueType::from_u8(2), None);
+        assert_eq!(ValueType::from_u8(2), Some(ValueType::Append));
+        assert_eq!(ValueType::from_u8(3), None);
     }
 
     #[test]
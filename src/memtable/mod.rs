@@ -6,7 +6,7 @@
 //! ## Design
 //!
 //! - Based on crossbeam-skiplist for lock-free concurrent access
-//! - Supports Put, Get, and Delete (via tombstone) operations
+//! - Supports Put, Get, Delete (via tombstone) and Append operations
 //! - Tracks size to determine when to flush to disk
 //! - Provides an iterator for ordered traversal
 //!
@@ -19,9 +19,15 @@ mod internal_key;
 
 pub use internal_key::{InternalKey, ValueType};
 
+use crossbeam_skiplist::map::Entry;
 use crossbeam_skiplist::SkipMap;
+use std::cmp;
+use std::ops::{Bound, Deref};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+
+use crate::comparator::{self, Comparator};
+use crate::prefix_delete::PrefixDeletes;
 
 /// Default size limit for MemTable (4MB)
 pub const DEFAULT_MEMTABLE_SIZE_LIMIT: usize = 4 * 1024 * 1024;
@@ -30,7 +36,8 @@ pub const DEFAULT_MEMTABLE_SIZE_LIMIT: usize = 4 * 1024 * 1024;
 ///
 /// # Design
 ///
-/// - Uses `InternalKey` for sorting (user_key + sequence + type)
+/// - Uses `InternalKey` for sorting (user_key + sequence + type), with the
+///   user keys ordered by a [`Comparator`]
 /// - Sequence numbers provide MVCC semantics
 /// - Delete operations are represented as tombstones
 /// - Size is tracked to trigger flushes when full
@@ -46,7 +53,7 @@ pub const DEFAULT_MEMTABLE_SIZE_LIMIT: usize = 4 * 1024 * 1024;
 /// ```
 pub struct MemTable {
     /// The underlying SkipList storing InternalKey -> Value
-    data: Arc<SkipMap<InternalKey, Vec<u8>>>,
+    data: Arc<Entries>,
 
     /// Approximate size in bytes (keys + values)
     size: AtomicUsize,
@@ -70,7 +77,16 @@ impl MemTable {
     /// let memtable = MemTable::new(100);
     /// ```
     pub fn new(start_sequence: u64) -> Self {
-        Self { data: Arc::new(SkipMap::new()), size: AtomicUsize::new(0), start_sequence }
+        Self::with_comparator(start_sequence, comparator::default_comparator())
+    }
+
+    /// Creates a new empty MemTable whose user keys are ordered by `comparator`.
+    pub fn with_comparator(start_sequence: u64, comparator: Arc<dyn Comparator>) -> Self {
+        Self {
+            data: Arc::new(Entries { map: SkipMap::new(), comparator }),
+            size: AtomicUsize::new(0),
+            start_sequence,
+        }
     }
 
     /// Inserts a key-value pair into the MemTable.
@@ -96,7 +112,7 @@ impl MemTable {
         // Calculate the size of this entry
         let entry_size = internal_key.user_key().len() + value_vec.len() + 16; // 16 bytes overhead
 
-        self.data.insert(internal_key, value_vec);
+        self.data.map.insert(self.data.key(internal_key), value_vec);
         self.size.fetch_add(entry_size, Ordering::Relaxed);
     }
 
@@ -125,34 +141,23 @@ impl MemTable {
     /// assert_eq!(memtable.get(b"key", 100), Some(b"value".to_vec()));
     /// ```
     pub fn get(&self, key: &[u8], max_sequence: u64) -> Option<Vec<u8>> {
-        // Create range bounds for the user key
-        // Lower bound: key with max possible sequence (u64::MAX)
-        // Upper bound: next key with max sequence
-        let lower_bound = InternalKey::new(key.to_vec(), u64::MAX, ValueType::Value);
-
-        // Create an upper bound by appending a byte to the key
-        let mut upper_key = key.to_vec();
-        upper_key.push(0);
-        let upper_bound = InternalKey::new(upper_key, u64::MAX, ValueType::Value);
-
-        // Iterate through entries with matching user key
-        let range = self.data.range(lower_bound..upper_bound);
-
-        // Find the most recent entry with sequence <= max_sequence
-        for entry in range {
-            let internal_key = entry.key();
-            let value = entry.value();
-
-            // Double-check the user key matches (it should, given our range)
-            if internal_key.user_key() == key && internal_key.sequence() <= max_sequence {
-                match internal_key.value_type() {
-                    ValueType::Value => return Some(value.clone()),
-                    ValueType::Deletion => return None,
-                }
-            }
-        }
+        self.lookup(key, max_sequence, &PrefixDeletes::default())
+            .and_then(|(_, _, version)| version.into_value())
+    }
 
-        None
+    /// Finds the versions of `key` with a sequence number `<= max_sequence`.
+    ///
+    /// Returns the newest one's sequence number and type and the key's
+    /// version, with appends applied to the older versions in this
+    /// MemTable, or `None` if the MemTable has no such version. Versions
+    /// `prefix_deletes` covers count as deletions.
+    pub(crate) fn lookup(
+        &self,
+        key: &[u8],
+        max_sequence: u64,
+        prefix_deletes: &PrefixDeletes,
+    ) -> Option<(u64, ValueType, Version)> {
+        lookup_in(&self.data, key, max_sequence, prefix_deletes)
     }
 
     /// Marks a key as deleted by inserting a tombstone.
@@ -178,7 +183,30 @@ impl MemTable {
         // Tombstone has no value
         let entry_size = internal_key.user_key().len() + 16; // 16 bytes overhead
 
-        self.data.insert(internal_key, Vec::new());
+        self.data.map.insert(self.data.key(internal_key), Vec::new());
+        self.size.fetch_add(entry_size, Ordering::Relaxed);
+    }
+
+    /// Appends `suffix` to the value of a key.
+    ///
+    /// The suffix is stored as its own entry and applied to the key's older
+    /// version when it is read.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use aidb::memtable::MemTable;
+    ///
+    /// let memtable = MemTable::new(1);
+    /// memtable.put(b"key", b"a", 1);
+    /// memtable.append(b"key", b"b", 2);
+    /// assert_eq!(memtable.get(b"key", 100), Some(b"ab".to_vec()));
+    /// ```
+    pub fn append(&self, key: &[u8], suffix: &[u8], sequence: u64) {
+        let internal_key = InternalKey::new(key.to_vec(), sequence, ValueType::Append);
+        let entry_size = internal_key.user_key().len() + suffix.len() + 16; // 16 bytes overhead
+
+        self.data.map.insert(self.data.key(internal_key), suffix.to_vec());
         self.size.fetch_add(entry_size, Ordering::Relaxed);
     }
 
@@ -212,7 +240,7 @@ impl MemTable {
     /// assert_eq!(memtable.len(), 2);
     /// ```
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.data.map.len()
     }
 
     /// Returns `true` if the MemTable contains no entries.
@@ -228,7 +256,7 @@ impl MemTable {
     /// assert!(!memtable.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.data.is_empty()
+        self.data.map.is_empty()
     }
 
     /// Returns an iterator over the MemTable entries.
@@ -252,6 +280,12 @@ impl MemTable {
         MemTableIterator::new(self.data.clone())
     }
 
+    /// Returns a cursor over the entries visible at `sequence`, with the
+    /// versions `prefix_deletes` covers as deletions.
+    pub(crate) fn cursor(&self, sequence: u64, prefix_deletes: PrefixDeletes) -> MemTableCursor {
+        MemTableCursor { data: Arc::clone(&self.data), sequence, prefix_deletes, current: None }
+    }
+
     /// Returns the starting sequence number for this MemTable.
     pub fn start_sequence(&self) -> u64 {
         self.start_sequence
@@ -261,30 +295,60 @@ impl MemTable {
     ///
     /// This collects all user keys, removing duplicates (keeping only latest version).
     pub fn keys(&self) -> Vec<Vec<u8>> {
-        use std::collections::BTreeSet;
+        let mut keys: Vec<Vec<u8>> = Vec::new();
+        // Versions of a key are next to each other
+        for entry in self.data.map.iter() {
+            if keys.last().is_none_or(|last| last.as_slice() != entry.key().user_key()) {
+                keys.push(entry.key().user_key().to_vec());
+            }
+        }
+        keys
+    }
 
-        let mut keys = BTreeSet::new();
-        for entry in self.data.iter() {
-            keys.insert(entry.key().user_key().to_vec());
+    /// Returns a handle to the entries if an iterator still holds them, for
+    /// a MemTable that is leaving the database.
+    pub(crate) fn retire(&self) -> Option<RetiredMemTable> {
+        (Arc::strong_count(&self.data) > 1)
+            .then(|| RetiredMemTable { data: Arc::downgrade(&self.data) })
+    }
+}
+
+/// The entries of a MemTable that has left the database, alive as long as
+/// iterators read them.
+pub(crate) struct RetiredMemTable {
+    data: Weak<Entries>,
+}
+
+impl RetiredMemTable {
+    /// Whether an iterator still holds the entries
+    pub(crate) fn is_alive(&self) -> bool {
+        self.data.strong_count() > 0
+    }
+
+    /// Calls `f` with the value of every entry still held.
+    pub(crate) fn for_each_value(&self, mut f: impl FnMut(&[u8])) {
+        if let Some(data) = self.data.upgrade() {
+            for entry in data.map.iter() {
+                f(entry.value());
+            }
         }
-        keys.into_iter().collect()
     }
 }
 
 /// Iterator over MemTable entries in sorted order.
 pub struct MemTableIterator {
-    _data: Arc<SkipMap<InternalKey, Vec<u8>>>,
-    iter: crossbeam_skiplist::map::Iter<'static, InternalKey, Vec<u8>>,
+    _data: Arc<Entries>,
+    iter: crossbeam_skiplist::map::Iter<'static, MemKey, Vec<u8>>,
 }
 
 impl MemTableIterator {
-    fn new(data: Arc<SkipMap<InternalKey, Vec<u8>>>) -> Self {
+    fn new(data: Arc<Entries>) -> Self {
         // SAFETY: We're using Arc to keep the SkipMap alive for the lifetime of the iterator
         let iter = unsafe {
             std::mem::transmute::<
-                crossbeam_skiplist::map::Iter<'_, InternalKey, Vec<u8>>,
-                crossbeam_skiplist::map::Iter<'static, InternalKey, Vec<u8>>,
-            >(data.iter())
+                crossbeam_skiplist::map::Iter<'_, MemKey, Vec<u8>>,
+                crossbeam_skiplist::map::Iter<'static, MemKey, Vec<u8>>,
+            >(data.map.iter())
         };
 
         Self { _data: data, iter }
@@ -304,7 +368,7 @@ impl Iterator for MemTableIterator {
     fn next(&mut self) -> Option<Self::Item> {
         self.iter
             .next()
-            .map(|entry| MemTableEntry { key: entry.key().clone(), value: entry.value().clone() })
+            .map(|entry| MemTableEntry { key: entry.key().0.clone(), value: entry.value().clone() })
     }
 }
 
@@ -342,6 +406,256 @@ impl MemTableEntry {
     }
 }
 
+/// The version of a key a MemTable or SSTable holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Version {
+    /// A complete value
+    Value(Vec<u8>),
+    /// A deletion, which hides older versions
+    Deleted,
+    /// Bytes appended to the version in older sources
+    Appended(Vec<u8>),
+}
+
+impl Version {
+    /// Applies this version to `older`, the version below it
+    pub(crate) fn over(self, older: Version) -> Version {
+        match (self, older) {
+            (Version::Appended(suffix), Version::Value(mut value)) => {
+                value.extend_from_slice(&suffix);
+                Version::Value(value)
+            }
+            (Version::Appended(suffix), Version::Appended(mut value)) => {
+                value.extend_from_slice(&suffix);
+                Version::Appended(value)
+            }
+            (Version::Appended(suffix), Version::Deleted) => Version::Value(suffix),
+            (version, _) => version,
+        }
+    }
+
+    /// Whether older versions no longer matter
+    pub(crate) fn is_complete(&self) -> bool {
+        !matches!(self, Version::Appended(_))
+    }
+
+    /// The value, `None` for a deletion; appends with nothing below them
+    /// make up the whole value
+    pub(crate) fn into_value(self) -> Option<Vec<u8>> {
+        match self {
+            Version::Value(value) | Version::Appended(value) => Some(value),
+            Version::Deleted => None,
+        }
+    }
+}
+
+/// The entries of a MemTable and the order of their user keys
+struct Entries {
+    map: SkipMap<MemKey, Vec<u8>>,
+    comparator: Arc<dyn Comparator>,
+}
+
+impl Entries {
+    /// `key` as ordered in the SkipList
+    fn key(&self, key: InternalKey) -> MemKey {
+        MemKey(key, Arc::clone(&self.comparator))
+    }
+}
+
+/// An [`InternalKey`] ordered with the comparator of its MemTable
+struct MemKey(InternalKey, Arc<dyn Comparator>);
+
+impl Deref for MemKey {
+    type Target = InternalKey;
+
+    fn deref(&self) -> &InternalKey {
+        &self.0
+    }
+}
+
+impl Ord for MemKey {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.0.compare_with(&other.0, &*self.1)
+    }
+}
+
+impl PartialOrd for MemKey {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MemKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for MemKey {}
+
+/// Finds the versions of `key` in `data` with a sequence number
+/// `<= max_sequence` (see [`MemTable::lookup`])
+fn lookup_in(
+    data: &Entries,
+    key: &[u8],
+    max_sequence: u64,
+    prefix_deletes: &PrefixDeletes,
+) -> Option<(u64, ValueType, Version)> {
+    // Versions are ordered newest first, and by type within a sequence
+    // number, Append highest
+    let newest = data.key(InternalKey::new(key.to_vec(), max_sequence, ValueType::Append));
+    let oldest = data.key(InternalKey::new(key.to_vec(), 0, ValueType::Deletion));
+    let mut entries = data.map.range(newest..=oldest);
+
+    let first = entries.next()?;
+    let (sequence, value_type) = (first.key().sequence(), first.key().value_type());
+    let mut version = version_of(&first, prefix_deletes);
+    for entry in entries {
+        if version.is_complete() {
+            break;
+        }
+        version = version.over(version_of(&entry, prefix_deletes));
+    }
+    Some((sequence, value_type, version))
+}
+
+/// The version a single MemTable entry holds
+fn version_of(entry: &Entry<'_, MemKey, Vec<u8>>, prefix_deletes: &PrefixDeletes) -> Version {
+    let key = entry.key();
+    if prefix_deletes.covers_sequence(key.user_key(), key.sequence()) {
+        return Version::Deleted;
+    }
+    match key.value_type() {
+        ValueType::Value => Version::Value(entry.value().clone()),
+        ValueType::Deletion => Version::Deleted,
+        ValueType::Append => Version::Appended(entry.value().clone()),
+    }
+}
+
+/// Bidirectional cursor over a MemTable, used by the database iterator.
+///
+/// The cursor stops once per user key, on the newest version with a
+/// sequence number `<=` the cursor's, with its appends applied to the
+/// older versions. A deleted key is returned as [`Version::Deleted`] so
+/// that it hides older versions of the key in other sources.
+pub(crate) struct MemTableCursor {
+    data: Arc<Entries>,
+    sequence: u64,
+    prefix_deletes: PrefixDeletes,
+    /// User key and version the cursor is on
+    current: Option<(Vec<u8>, Version)>,
+}
+
+impl MemTableCursor {
+    /// Moves to the first user key.
+    pub(crate) fn seek_to_first(&mut self) {
+        self.find_forward(Bound::Unbounded);
+    }
+
+    /// Moves to the last user key.
+    pub(crate) fn seek_to_last(&mut self) {
+        self.find_backward(Bound::Unbounded);
+    }
+
+    /// Moves to the first user key `>= target`.
+    pub(crate) fn seek(&mut self, target: &[u8]) {
+        self.find_forward(Bound::Included(InternalKey::new(
+            target.to_vec(),
+            u64::MAX,
+            ValueType::Value,
+        )));
+    }
+
+    /// Moves to the last user key `<= target`.
+    pub(crate) fn seek_for_prev(&mut self, target: &[u8]) {
+        self.find_backward(Bound::Included(InternalKey::new(
+            target.to_vec(),
+            0,
+            ValueType::Deletion,
+        )));
+    }
+
+    /// Moves to the next user key.
+    pub(crate) fn next(&mut self) {
+        if let Some((key, _)) = self.current.take() {
+            // The oldest possible version sorts last among the key's entries
+            self.find_forward(Bound::Excluded(InternalKey::new(key, 0, ValueType::Deletion)));
+        }
+    }
+
+    /// Moves to the previous user key.
+    pub(crate) fn prev(&mut self) {
+        if let Some((key, _)) = self.current.take() {
+            self.find_backward(Bound::Excluded(InternalKey::new(key, u64::MAX, ValueType::Value)));
+        }
+    }
+
+    /// Returns the user key the cursor is on.
+    pub(crate) fn key(&self) -> Option<&[u8]> {
+        self.current.as_ref().map(|(key, _)| key.as_slice())
+    }
+
+    /// Returns the version the cursor is on.
+    pub(crate) fn version(&self) -> Option<&Version> {
+        self.current.as_ref().map(|(_, version)| version)
+    }
+
+    /// Whether the newest visible version of the key the cursor is on is
+    /// an append.
+    pub(crate) fn appended(&self) -> bool {
+        self.key().is_some_and(|key| {
+            lookup_in(&self.data, key, self.sequence, &self.prefix_deletes)
+                .is_some_and(|(_, value_type, _)| value_type == ValueType::Append)
+        })
+    }
+
+    /// Stops on the first visible entry after `lower`.
+    ///
+    /// Versions of a key are ordered newest first, so the first entry with a
+    /// visible sequence number is the key's newest visible version.
+    fn find_forward(&mut self, lower: Bound<InternalKey>) {
+        let lower = lower.map(|key| self.data.key(key));
+        let found = self
+            .data
+            .map
+            .range((lower, Bound::Unbounded))
+            .find(|entry| entry.key().sequence() <= self.sequence);
+        self.current = found.map(|entry| self.visible(&entry));
+    }
+
+    /// Stops on the newest visible version of the last user key before
+    /// `upper` that has one.
+    fn find_backward(&mut self, upper: Bound<InternalKey>) {
+        let upper = upper.map(|key| self.data.key(key));
+        let mut found: Option<Entry<'_, MemKey, Vec<u8>>> = None;
+        // Walking backwards, each key's versions come oldest first
+        for entry in self.data.map.range((Bound::Unbounded, upper)).rev() {
+            let key = entry.key();
+            if found.as_ref().is_some_and(|found| found.key().user_key() != key.user_key()) {
+                break;
+            }
+            if key.sequence() <= self.sequence {
+                found = Some(entry);
+            }
+        }
+        self.current = found.map(|entry| self.visible(&entry));
+    }
+
+    /// The key and version of `entry`, the newest visible version of its key
+    fn visible(&self, entry: &Entry<'_, MemKey, Vec<u8>>) -> (Vec<u8>, Version) {
+        let key = entry.key().user_key();
+        let version = match version_of(entry, &self.prefix_deletes) {
+            Version::Appended(_) => {
+                lookup_in(&self.data, key, self.sequence, &self.prefix_deletes)
+                    .expect("the entry itself is visible")
+                    .2
+            }
+            version => version,
+        };
+        (key.to_vec(), version)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,314 @@
+//! Prometheus metrics exporter (requires the `metrics` feature).
+//!
+//! [`MetricsCollector`] is a [`prometheus::core::Collector`] that reads the
+//! state of a database each time the registry is scraped, so it adds no work
+//! to reads and writes. It exports:
+//!
+//! - Every [`Ticker`] as a counter (e.g. `aidb_keys_written_total`) and the
+//!   latency histograms as `aidb_latency_micros{operation, quantile}` with
+//!   `_count` and `_sum`, when [`Options::statistics`](crate::Options::statistics)
+//!   is set
+//! - `aidb_level_files{level}` and `aidb_level_size_bytes{level}`
+//! - Block cache counters (`aidb_block_cache_lookups_total`, ...) and
+//!   `aidb_block_cache_usage_bytes` / `aidb_block_cache_capacity_bytes`
+//! - `aidb_wal_size_bytes`
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::metrics::MetricsCollector;
+//! use aidb::{Options, Statistics, DB};
+//! use prometheus::{Encoder, Registry, TextEncoder};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let options = Options::default().statistics(Arc::new(Statistics::new()));
+//! let db = Arc::new(DB::open("./data", options)?);
+//!
+//! let registry = Registry::new();
+//! MetricsCollector::register(&db, &registry)?;
+//!
+//! // Serve this from an HTTP endpoint
+//! let mut buffer = Vec::new();
+//! TextEncoder::new().encode(&registry.gather(), &mut buffer).unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::{Error, Result};
+use crate::statistics::{HistogramType, Ticker};
+use crate::DB;
+use parking_lot::Mutex;
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{GaugeVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use std::sync::{Arc, Weak};
+
+/// Exports the statistics of a database to Prometheus.
+///
+/// Holds a weak reference, so a registered collector does not keep the
+/// database open; once the database is dropped it exports nothing.
+pub struct MetricsCollector {
+    db: Weak<DB>,
+    /// Serializes scrapes, which reset and refill the metrics below
+    update: Mutex<()>,
+
+    tickers: Vec<(Ticker, IntCounter)>,
+    latency: GaugeVec,
+    latency_count: IntCounterVec,
+    latency_sum: IntCounterVec,
+
+    level_files: IntGaugeVec,
+    level_size: IntGaugeVec,
+
+    cache_lookups: IntCounter,
+    cache_hits: IntCounter,
+    cache_misses: IntCounter,
+    cache_insertions: IntCounter,
+    cache_evictions: IntCounter,
+    cache_usage: IntGauge,
+    cache_capacity: IntGauge,
+
+    wal_size: IntGauge,
+}
+
+impl MetricsCollector {
+    /// Creates a collector for `db`.
+    pub fn new(db: &Arc<DB>) -> Result<Self> {
+        let tickers = Ticker::ALL
+            .iter()
+            .map(|&ticker| {
+                let name = format!("{}_total", ticker.name().replace('.', "_"));
+                Ok((ticker, IntCounter::new(name, ticker.name()).map_err(to_error)?))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            db: Arc::downgrade(db),
+            update: Mutex::new(()),
+            tickers,
+            latency: GaugeVec::new(
+                Opts::new("aidb_latency_micros", "Operation latency in microseconds"),
+                &["operation", "quantile"],
+            )
+            .map_err(to_error)?,
+            latency_count: IntCounterVec::new(
+                Opts::new("aidb_latency_micros_count", "Number of timed operations"),
+                &["operation"],
+            )
+            .map_err(to_error)?,
+            latency_sum: IntCounterVec::new(
+                Opts::new("aidb_latency_micros_sum", "Total time of timed operations"),
+                &["operation"],
+            )
+            .map_err(to_error)?,
+            level_files: IntGaugeVec::new(
+                Opts::new("aidb_level_files", "Number of SSTables per level"),
+                &["level"],
+            )
+            .map_err(to_error)?,
+            level_size: IntGaugeVec::new(
+                Opts::new("aidb_level_size_bytes", "Total SSTable size per level"),
+                &["level"],
+            )
+            .map_err(to_error)?,
+            cache_lookups: int_counter("aidb_block_cache_lookups_total", "Block cache lookups")?,
+            cache_hits: int_counter("aidb_block_cache_hits_total", "Block cache hits")?,
+            cache_misses: int_counter("aidb_block_cache_misses_total", "Block cache misses")?,
+            cache_insertions: int_counter(
+                "aidb_block_cache_insertions_total",
+                "Blocks inserted into the block cache",
+            )?,
+            cache_evictions: int_counter(
+                "aidb_block_cache_evictions_total",
+                "Blocks evicted from the block cache",
+            )?,
+            cache_usage: int_gauge(
+                "aidb_block_cache_usage_bytes",
+                "Bytes held by the block cache",
+            )?,
+            cache_capacity: int_gauge(
+                "aidb_block_cache_capacity_bytes",
+                "Capacity of the block cache",
+            )?,
+            wal_size: int_gauge("aidb_wal_size_bytes", "Size of the current WAL file")?,
+        })
+    }
+
+    /// Creates a collector for `db` and registers it with `registry`.
+    pub fn register(db: &Arc<DB>, registry: &Registry) -> Result<()> {
+        registry.register(Box::new(Self::new(db)?)).map_err(to_error)
+    }
+
+    /// Refreshes every metric from the database.
+    fn update(&self, db: &DB) {
+        if let Some(stats) = db.statistics() {
+            for (ticker, counter) in &self.tickers {
+                set_counter(counter, stats.ticker(*ticker));
+            }
+            for histogram in HistogramType::ALL {
+                let data = stats.histogram(histogram);
+                let operation = operation_label(histogram);
+                for (quantile, value) in [("0.5", data.p50), ("0.95", data.p95), ("0.99", data.p99)]
+                {
+                    self.latency.with_label_values(&[operation, quantile]).set(value);
+                }
+                set_counter(&self.latency_count.with_label_values(&[operation]), data.count);
+                set_counter(&self.latency_sum.with_label_values(&[operation]), data.sum);
+            }
+        }
+
+        for (level, (files, size)) in db.level_sizes().into_iter().enumerate() {
+            let level = level.to_string();
+            self.level_files.with_label_values(&[level.as_str()]).set(files as i64);
+            self.level_size.with_label_values(&[level.as_str()]).set(size as i64);
+        }
+
+        let cache = db.cache_stats();
+        set_counter(&self.cache_lookups, cache.lookups);
+        set_counter(&self.cache_hits, cache.hits);
+        set_counter(&self.cache_misses, cache.misses);
+        set_counter(&self.cache_insertions, cache.insertions);
+        set_counter(&self.cache_evictions, cache.evictions);
+        self.cache_usage.set(db.block_cache.size() as i64);
+        self.cache_capacity.set(db.block_cache.capacity() as i64);
+
+        self.wal_size.set(db.wal_size() as i64);
+    }
+
+    fn statistics_collectors(&self) -> impl Iterator<Item = &dyn Collector> {
+        self.tickers.iter().map(|(_, counter)| counter as &dyn Collector).chain([
+            &self.latency as &dyn Collector,
+            &self.latency_count,
+            &self.latency_sum,
+        ])
+    }
+
+    fn db_collectors(&self) -> [&dyn Collector; 10] {
+        [
+            &self.level_files,
+            &self.level_size,
+            &self.cache_lookups,
+            &self.cache_hits,
+            &self.cache_misses,
+            &self.cache_insertions,
+            &self.cache_evictions,
+            &self.cache_usage,
+            &self.cache_capacity,
+            &self.wal_size,
+        ]
+    }
+}
+
+impl Collector for MetricsCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.statistics_collectors()
+            .chain(self.db_collectors())
+            .flat_map(|collector| collector.desc())
+            .collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let Some(db) = self.db.upgrade() else {
+            return Vec::new();
+        };
+
+        let _guard = self.update.lock();
+        self.update(&db);
+
+        let mut families: Vec<MetricFamily> =
+            self.db_collectors().iter().flat_map(|collector| collector.collect()).collect();
+        if db.statistics().is_some() {
+            families.extend(self.statistics_collectors().flat_map(|collector| collector.collect()));
+        }
+        families
+    }
+}
+
+/// Label value of the `operation` label for a histogram.
+fn operation_label(histogram: HistogramType) -> &'static str {
+    match histogram {
+        HistogramType::Get => "get",
+        HistogramType::Put => "put",
+        HistogramType::Write => "write",
+        HistogramType::Flush => "flush",
+        HistogramType::Compaction => "compaction",
+    }
+}
+
+/// Sets a counter to a value read from the database.
+fn set_counter(counter: &IntCounter, value: u64) {
+    counter.reset();
+    counter.inc_by(value);
+}
+
+fn int_counter(name: &str, help: &str) -> Result<IntCounter> {
+    IntCounter::new(name, help).map_err(to_error)
+}
+
+fn int_gauge(name: &str, help: &str) -> Result<IntGauge> {
+    IntGauge::new(name, help).map_err(to_error)
+}
+
+fn to_error(e: prometheus::Error) -> Error {
+    Error::internal(format!("Failed to create metric: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statistics::Statistics;
+    use crate::Options;
+    use prometheus::{Encoder, TextEncoder};
+    use tempfile::TempDir;
+
+    fn scrape(registry: &Registry) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&registry.gather(), &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_exports_db_metrics() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().statistics(Arc::new(Statistics::new()));
+        let db = Arc::new(DB::open(temp_dir.path(), options).unwrap());
+
+        let registry = Registry::new();
+        MetricsCollector::register(&db, &registry).unwrap();
+
+        db.put(b"key", b"value").unwrap();
+        db.flush().unwrap();
+        db.get(b"key").unwrap();
+
+        let text = scrape(&registry);
+        assert!(text.contains("aidb_keys_written_total 1"), "{}", text);
+        assert!(text.contains("aidb_keys_read_total 1"));
+        assert!(text.contains("aidb_latency_micros_count{operation=\"get\"} 1"));
+        assert!(text.contains("aidb_latency_micros{operation=\"flush\",quantile=\"0.99\"}"));
+        assert!(text.contains("aidb_level_files{level=\"0\"} 1"));
+        assert!(text.contains("aidb_block_cache_lookups_total 1"));
+        assert!(text.contains("aidb_wal_size_bytes 0"));
+
+        // Values are refreshed on every scrape
+        db.put(b"key2", b"value").unwrap();
+        assert!(scrape(&registry).contains("aidb_keys_written_total 2"));
+    }
+
+    #[test]
+    fn test_without_statistics() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+
+        let registry = Registry::new();
+        MetricsCollector::register(&db, &registry).unwrap();
+
+        let text = scrape(&registry);
+        assert!(!text.contains("aidb_keys_written_total"));
+        assert!(text.contains("aidb_wal_size_bytes"));
+
+        // A dropped database exports nothing
+        drop(db);
+        assert!(scrape(&registry).is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+//! Migration of data from other key-value stores.
+//!
+//! [`import_entries`] bulk-loads any stream of key-value pairs into an open
+//! database in write batches of [`ImportOptions::batch_size`] entries. It is
+//! the building block for the engine-specific importers:
+//!
+//! - [`import_leveldb`] (with the `leveldb` feature) reads a LevelDB data
+//!   directory with the pure-Rust `rusty-leveldb` crate
+//! - RocksDB databases can be imported by passing the iterator of the
+//!   `rocksdb` crate (`db.iterator(IteratorMode::Start)`) to
+//!   [`import_entries`]; AiDb does not link RocksDB itself
+//!
+//! Entries are written in source order and each is assigned a new AiDb
+//! sequence number. The [`ImportReport`] records the range of sequence
+//! numbers used, which maps the snapshot of the source onto AiDb: reads at
+//! a sequence `>= last_sequence` see the complete import. The range is
+//! contiguous only if nothing else writes to the database during the
+//! import.
+//!
+//! AiDb stores deletions as empty values, so entries with an empty value
+//! cannot be represented; they are skipped and counted in
+//! [`ImportReport::skipped_empty_values`].
+
+use std::sync::atomic::Ordering;
+
+use crate::{Result, WriteBatch, DB};
+
+/// Options for [`import_entries`] and the engine-specific importers.
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Number of entries written per batch (default: 1000)
+    pub batch_size: usize,
+    /// Flush the MemTable after the last batch, so the imported data no
+    /// longer depends on the WAL (default: true)
+    pub flush: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self { batch_size: 1000, flush: true }
+    }
+}
+
+/// Summary of an import.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Number of entries written
+    pub entries_imported: u64,
+    /// Total size of the keys and values written
+    pub bytes_imported: u64,
+    /// Number of entries skipped because their value was empty
+    pub skipped_empty_values: u64,
+    /// Sequence number of the first imported entry (0 if none were)
+    pub first_sequence: u64,
+    /// Sequence number of the last imported entry (0 if none were)
+    pub last_sequence: u64,
+}
+
+/// Writes every key-value pair from `entries` into `db`.
+///
+/// Stops at the first error from `entries` or from the database; batches
+/// written before the error stay in the database.
+pub fn import_entries<I, K, V>(db: &DB, entries: I, options: &ImportOptions) -> Result<ImportReport>
+where
+    I: IntoIterator<Item = Result<(K, V)>>,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+{
+    let batch_size = options.batch_size.max(1);
+    let mut report = ImportReport::default();
+    let mut batch = WriteBatch::new();
+
+    for entry in entries {
+        let (key, value) = entry?;
+        let (key, value) = (key.as_ref(), value.as_ref());
+        if value.is_empty() {
+            report.skipped_empty_values += 1;
+            continue;
+        }
+        batch.put(key, value);
+        report.bytes_imported += (key.len() + value.len()) as u64;
+        if batch.len() >= batch_size {
+            write_batch(db, std::mem::take(&mut batch), &mut report)?;
+        }
+    }
+    write_batch(db, batch, &mut report)?;
+
+    if options.flush && report.entries_imported > 0 {
+        db.flush()?;
+    }
+    Ok(report)
+}
+
+fn write_batch(db: &DB, batch: WriteBatch, report: &mut ImportReport) -> Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let len = batch.len() as u64;
+    db.write(batch)?;
+    let last = db.sequence.load(Ordering::SeqCst);
+    if report.entries_imported == 0 {
+        report.first_sequence = last + 1 - len;
+    }
+    report.entries_imported += len;
+    report.last_sequence = last;
+    Ok(())
+}
+
+/// Imports the LevelDB database at `path` into `db` (requires the `leveldb`
+/// feature).
+///
+/// The source is opened with LevelDB's default options, so it must use the
+/// default bytewise comparator. Opening it takes LevelDB's lock and
+/// recovers its log, as opening it with LevelDB itself would; it must not
+/// be in use by another process. Snappy-compressed tables are supported.
+#[cfg(feature = "leveldb")]
+pub fn import_leveldb<P: AsRef<std::path::Path>>(
+    db: &DB,
+    path: P,
+    options: &ImportOptions,
+) -> Result<ImportReport> {
+    use rusty_leveldb::LdbIterator;
+
+    let source_options = rusty_leveldb::Options { create_if_missing: false, ..Default::default() };
+    let mut source = rusty_leveldb::DB::open(path.as_ref(), source_options)
+        .map_err(|e| leveldb_error(path.as_ref(), e))?;
+    let mut iter = source.new_iter().map_err(|e| leveldb_error(path.as_ref(), e))?;
+    let entries = std::iter::from_fn(|| LdbIterator::next(&mut iter).map(Ok));
+    let report = import_entries(db, entries, options)?;
+    drop(iter);
+    source.close().map_err(|e| leveldb_error(path.as_ref(), e))?;
+    Ok(report)
+}
+
+#[cfg(feature = "leveldb")]
+fn leveldb_error(path: &std::path::Path, err: rusty_leveldb::Status) -> crate::Error {
+    crate::Error::internal(format!("LevelDB {:?}: {}", path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, Options};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_import_entries() {
+        let dir = TempDir::new().unwrap();
+        let db = DB::open(dir.path(), Options::default()).unwrap();
+        db.put(b"existing", b"1").unwrap();
+
+        let entries = (0..25u32)
+            .map(|i| Ok((format!("key{:03}", i).into_bytes(), format!("value{}", i).into_bytes())))
+            .chain([Ok((b"empty".to_vec(), Vec::new()))]);
+        let options = ImportOptions { batch_size: 10, ..Default::default() };
+        let report = import_entries(&db, entries, &options).unwrap();
+
+        assert_eq!(report.entries_imported, 25);
+        assert_eq!(report.skipped_empty_values, 1);
+        assert_eq!((report.first_sequence, report.last_sequence), (2, 26));
+        assert_eq!(db.get(b"key007").unwrap(), Some(b"value7".to_vec()));
+        assert_eq!(db.get(b"empty").unwrap(), None);
+        assert!(db.immutable_memtables.read().is_empty());
+        assert!(db.memtable.read().is_empty());
+    }
+
+    #[test]
+    fn test_import_entries_stops_at_source_error() {
+        let dir = TempDir::new().unwrap();
+        let db = DB::open(dir.path(), Options::default()).unwrap();
+
+        let entries = vec![
+            Ok((b"a".to_vec(), b"1".to_vec())),
+            Err(Error::corruption("bad source record")),
+            Ok((b"b".to_vec(), b"2".to_vec())),
+        ];
+        let options = ImportOptions { batch_size: 1, flush: false };
+        assert!(matches!(import_entries(&db, entries, &options), Err(Error::Corruption(_))));
+        assert_eq!(db.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(db.get(b"b").unwrap(), None);
+    }
+
+    #[cfg(feature = "leveldb")]
+    #[test]
+    fn test_import_leveldb() {
+        let source_dir = TempDir::new().unwrap();
+        {
+            let options = rusty_leveldb::Options::default();
+            let mut source = rusty_leveldb::DB::open(source_dir.path(), options).unwrap();
+            for i in 0..500u32 {
+                source.put(format!("key{:04}", i).as_bytes(), &i.to_be_bytes()).unwrap();
+            }
+            source.flush().unwrap();
+            source.delete(b"key0007").unwrap();
+            source.put(b"key0008", b"overwritten").unwrap();
+            source.close().unwrap();
+        }
+
+        let dir = TempDir::new().unwrap();
+        let db = DB::open(dir.path(), Options::default()).unwrap();
+        let report = import_leveldb(&db, source_dir.path(), &ImportOptions::default()).unwrap();
+
+        assert_eq!(report.entries_imported, 499);
+        assert_eq!(db.get(b"key0000").unwrap(), Some(0u32.to_be_bytes().to_vec()));
+        assert_eq!(db.get(b"key0007").unwrap(), None);
+        assert_eq!(db.get(b"key0008").unwrap(), Some(b"overwritten".to_vec()));
+        assert_eq!(db.get(b"key0499").unwrap(), Some(499u32.to_be_bytes().to_vec()));
+
+        let missing = dir.path().join("missing");
+        assert!(import_leveldb(&db, missing, &ImportOptions::default()).is_err());
+    }
+}
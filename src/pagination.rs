@@ -0,0 +1,238 @@
+//! Paginated range scans.
+//!
+//! [`DB::scan_page`] returns at most `limit` entries of a range together
+//! with a [`ContinuationToken`] for the rest. The token is a short string
+//! holding the key the next page starts at and the sequence number the first
+//! page was read at, so a stateless service (an HTTP API, say) can hand it
+//! to a client and continue with [`DB::scan_page_from`] on a later request
+//! without keeping an iterator alive in between.
+//!
+//! Later pages read at the token's sequence number, so writes made while a
+//! client is paging are hidden as long as they are still in a MemTable. As
+//! with [`Snapshot`](crate::Snapshot), the sequence does not pin older
+//! versions: once newer writes are flushed, later pages can see them.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::pagination::ContinuationToken;
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = Arc::new(DB::open("./data", Options::default())?);
+//!
+//! let (entries, token) = db.scan_page(Some(b"user:"), Some(b"user;"), 100)?;
+//! println!("{} entries", entries.len());
+//! let mut encoded = token.map(|token| token.to_string());
+//!
+//! // On later requests, with the token the client sent back
+//! while let Some(param) = encoded {
+//!     let token: ContinuationToken = param.parse()?;
+//!     let (entries, next) = db.scan_page_from(&token, Some(b"user;"), 100)?;
+//!     println!("{} more entries", entries.len());
+//!     encoded = next.map(|token| token.to_string());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::{DBIterator, Error, ReadOptions, Result, DB};
+
+/// Version byte at the start of an encoded token
+const TOKEN_VERSION: u8 = 1;
+
+/// One page of a scan: the entries, and a token for the next page if the
+/// range has more entries.
+pub type ScanPage = (Vec<(Vec<u8>, Vec<u8>)>, Option<ContinuationToken>);
+
+/// Where a paginated scan continues.
+///
+/// Encoded (with [`Display`](fmt::Display) / [`FromStr`]) as URL-safe
+/// base64 without padding, so it can be passed in a query string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContinuationToken {
+    next_key: Vec<u8>,
+    sequence: u64,
+}
+
+impl ContinuationToken {
+    /// Returns the first key of the next page.
+    pub fn next_key(&self) -> &[u8] {
+        &self.next_key
+    }
+
+    /// Returns the sequence number the scan reads at.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Encodes the token as a string.
+    pub fn encode(&self) -> String {
+        let mut data = Vec::with_capacity(9 + self.next_key.len());
+        data.push(TOKEN_VERSION);
+        data.extend_from_slice(&self.sequence.to_le_bytes());
+        data.extend_from_slice(&self.next_key);
+        BASE64.encode(data)
+    }
+
+    /// Decodes a token produced by [`encode`](Self::encode).
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `token` is not a valid token.
+    pub fn decode(token: &str) -> Result<Self> {
+        let invalid = || Error::invalid_argument("invalid continuation token");
+        let data = BASE64.decode(token.trim()).map_err(|_| invalid())?;
+        match data.split_first() {
+            Some((&TOKEN_VERSION, rest)) if rest.len() > 8 => {
+                let (sequence, next_key) = rest.split_at(8);
+                Ok(Self {
+                    next_key: next_key.to_vec(),
+                    sequence: u64::from_le_bytes(sequence.try_into().expect("8 bytes")),
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for ContinuationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.encode())
+    }
+}
+
+impl FromStr for ContinuationToken {
+    type Err = Error;
+
+    fn from_str(token: &str) -> Result<Self> {
+        Self::decode(token)
+    }
+}
+
+impl DB {
+    /// Returns up to `limit` entries with `start <= key < end`, in key order,
+    /// and a token for the next page if there are more.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `limit` is 0.
+    pub fn scan_page(
+        self: &Arc<Self>,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<ScanPage> {
+        let sequence = self.sequence.load(Ordering::SeqCst);
+        self.read_page(sequence, start, end, limit)
+    }
+
+    /// Returns the page that `token` points to: up to `limit` entries from
+    /// the token's key up to `end`, read at the token's sequence number.
+    ///
+    /// `end` should be the same as for the first page.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `limit` is 0.
+    pub fn scan_page_from(
+        self: &Arc<Self>,
+        token: &ContinuationToken,
+        end: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<ScanPage> {
+        // A token from another database may carry a sequence not reached yet
+        let sequence = token.sequence.min(self.sequence.load(Ordering::SeqCst));
+        self.read_page(sequence, Some(&token.next_key), end, limit)
+    }
+
+    fn read_page(
+        self: &Arc<Self>,
+        sequence: u64,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<ScanPage> {
+        if limit == 0 {
+            return Err(Error::invalid_argument("page limit must be greater than 0"));
+        }
+
+        let mut iter =
+            DBIterator::new_range(Arc::clone(self), sequence, &ReadOptions::default(), start, end)?;
+        let mut entries = Vec::new();
+        while iter.valid() && entries.len() < limit {
+            entries.push((iter.key().to_vec(), iter.value().to_vec()));
+            iter.next();
+        }
+        iter.status()?;
+
+        let token = iter
+            .valid()
+            .then(|| ContinuationToken { next_key: iter.key().to_vec(), sequence });
+        Ok((entries, token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_pages() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(tmp_dir.path(), Options::default()).unwrap());
+        for i in 0..25 {
+            db.put(format!("key{:02}", i).as_bytes(), b"v1").unwrap();
+        }
+
+        let (entries, mut token) = db.scan_page(Some(b"key03"), Some(b"key20"), 5).unwrap();
+        let mut keys: Vec<_> = entries.into_iter().map(|(key, _)| key).collect();
+
+        // Writes after the first page are not seen by later pages
+        db.put(b"key10", b"v2").unwrap();
+        db.put(b"key15a", b"v2").unwrap();
+
+        let mut pages = 1;
+        while let Some(current) = token {
+            let current: ContinuationToken = current.to_string().parse().unwrap();
+            let (entries, next) = db.scan_page_from(&current, Some(b"key20"), 5).unwrap();
+            assert!(entries.iter().all(|(_, value)| value == b"v1"));
+            keys.extend(entries.into_iter().map(|(key, _)| key));
+            token = next;
+            pages += 1;
+        }
+
+        let expected: Vec<_> = (3..20).map(|i| format!("key{:02}", i).into_bytes()).collect();
+        assert_eq!(keys, expected);
+        assert_eq!(pages, 4);
+
+        // A range that fits in one page has no token
+        let (entries, token) = db.scan_page(Some(b"key20"), None, 5).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert!(token.is_none());
+
+        assert!(db.scan_page(None, None, 0).is_err());
+    }
+
+    #[test]
+    fn test_token_encoding() {
+        let token = ContinuationToken { next_key: b"a/b?c=\xff".to_vec(), sequence: 42 };
+        let encoded = token.to_string();
+        assert!(encoded.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+        assert_eq!(encoded.parse::<ContinuationToken>().unwrap(), token);
+
+        assert!(ContinuationToken::decode("not a token").is_err());
+        assert!(ContinuationToken::decode("").is_err());
+        assert!(ContinuationToken::decode(&BASE64.encode([2u8; 12])).is_err());
+    }
+}
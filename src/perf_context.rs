@@ -0,0 +1,247 @@
+//! Per-thread counters describing the work done by database operations.
+//!
+//! Every thread has its own [`PerfContext`], which the read and write paths
+//! update as they go. Reset it before an operation and read it afterwards to
+//! see where the operation spent its effort:
+//!
+//! ```rust,no_run
+//! use aidb::{perf_context, Options, DB};
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = DB::open("./data", Options::default())?;
+//!
+//! perf_context::reset();
+//! db.get(b"key")?;
+//! let perf = perf_context::get();
+//! println!("{} SSTables checked, {} blocks read", perf.sstables_checked, perf.block_reads);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The same counters are included in the slow-operation log (see
+//! [`Options::slow_get_threshold_ms`](crate::Options::slow_get_threshold_ms)).
+
+use crate::info_log::InfoLog;
+use crate::util::Instant;
+use std::cell::Cell;
+use std::fmt;
+use std::time::Duration;
+
+/// Counters for the operations run on the current thread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PerfContext {
+    /// MemTables searched by point lookups
+    pub memtables_checked: u64,
+    /// SSTables searched by point lookups (after the Bloom filter)
+    pub sstables_checked: u64,
+    /// SSTables skipped because their Bloom filter ruled the key (or, for
+    /// prefix seeks, the prefix) out
+    pub bloom_filter_skips: u64,
+    /// Data blocks served from the block cache
+    pub block_cache_hits: u64,
+    /// Data blocks read from disk
+    pub block_reads: u64,
+    /// Bytes of data blocks read from disk
+    pub block_read_bytes: u64,
+    /// Time spent appending to and syncing the WAL, in microseconds
+    pub wal_write_micros: u64,
+    /// Time spent inserting into the MemTable, in microseconds
+    pub memtable_write_micros: u64,
+}
+
+impl PerfContext {
+    /// Returns the counters accumulated since `earlier` was taken.
+    pub fn since(&self, earlier: &PerfContext) -> PerfContext {
+        PerfContext {
+            memtables_checked: self.memtables_checked.saturating_sub(earlier.memtables_checked),
+            sstables_checked: self.sstables_checked.saturating_sub(earlier.sstables_checked),
+            bloom_filter_skips: self.bloom_filter_skips.saturating_sub(earlier.bloom_filter_skips),
+            block_cache_hits: self.block_cache_hits.saturating_sub(earlier.block_cache_hits),
+            block_reads: self.block_reads.saturating_sub(earlier.block_reads),
+            block_read_bytes: self.block_read_bytes.saturating_sub(earlier.block_read_bytes),
+            wal_write_micros: self.wal_write_micros.saturating_sub(earlier.wal_write_micros),
+            memtable_write_micros: self
+                .memtable_write_micros
+                .saturating_sub(earlier.memtable_write_micros),
+        }
+    }
+}
+
+impl fmt::Display for PerfContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "memtables_checked={} sstables_checked={} bloom_filter_skips={} \
+             block_cache_hits={} block_reads={} block_read_bytes={} \
+             wal_write_micros={} memtable_write_micros={}",
+            self.memtables_checked,
+            self.sstables_checked,
+            self.bloom_filter_skips,
+            self.block_cache_hits,
+            self.block_reads,
+            self.block_read_bytes,
+            self.wal_write_micros,
+            self.memtable_write_micros
+        )
+    }
+}
+
+thread_local! {
+    static CONTEXT: Cell<PerfContext> = const {
+        Cell::new(PerfContext {
+            memtables_checked: 0,
+            sstables_checked: 0,
+            bloom_filter_skips: 0,
+            block_cache_hits: 0,
+            block_reads: 0,
+            block_read_bytes: 0,
+            wal_write_micros: 0,
+            memtable_write_micros: 0,
+        })
+    };
+}
+
+/// Returns the counters of the current thread.
+pub fn get() -> PerfContext {
+    CONTEXT.with(Cell::get)
+}
+
+/// Resets the counters of the current thread to zero.
+pub fn reset() {
+    CONTEXT.with(|context| context.set(PerfContext::default()));
+}
+
+/// Updates the counters of the current thread.
+pub(crate) fn update(f: impl FnOnce(&mut PerfContext)) {
+    CONTEXT.with(|context| {
+        let mut perf = context.get();
+        f(&mut perf);
+        context.set(perf);
+    });
+}
+
+/// Adds the time elapsed since `start` to a timer counter.
+pub(crate) fn add_elapsed(start: Instant, counter: impl FnOnce(&mut PerfContext) -> &mut u64) {
+    let micros = start.elapsed().as_micros() as u64;
+    update(|perf| *counter(perf) += micros);
+}
+
+/// Logs an operation that took longer than a threshold, with the
+/// [`PerfContext`] counters it accumulated, when dropped.
+pub(crate) struct SlowOperationLog<'a> {
+    info_log: &'a InfoLog,
+    operation: &'static str,
+    key: &'a [u8],
+    entries: usize,
+    threshold: Duration,
+    start: Instant,
+    before: PerfContext,
+}
+
+/// Number of key bytes included in the slow-operation log
+const LOGGED_KEY_PREFIX: usize = 16;
+
+impl<'a> SlowOperationLog<'a> {
+    /// Starts timing an operation on `entries` keys starting with `key`.
+    ///
+    /// Returns `None` when `threshold_ms` is 0 (logging disabled).
+    pub(crate) fn start(
+        info_log: &'a InfoLog,
+        operation: &'static str,
+        key: &'a [u8],
+        entries: usize,
+        threshold_ms: u64,
+    ) -> Option<Self> {
+        (threshold_ms > 0).then(|| Self {
+            info_log,
+            operation,
+            key,
+            entries,
+            threshold: Duration::from_millis(threshold_ms),
+            start: Instant::now(),
+            before: get(),
+        })
+    }
+
+    /// Sets the number of entries reported, once it is known.
+    pub(crate) fn set_entries(&mut self, entries: usize) {
+        self.entries = entries;
+    }
+}
+
+impl Drop for SlowOperationLog<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        if elapsed < self.threshold {
+            return;
+        }
+
+        let prefix = &self.key[..self.key.len().min(LOGGED_KEY_PREFIX)];
+        let message = format!(
+            "Slow {} took {:.3}ms: {} entries, key prefix \"{}\"{}, {}",
+            self.operation,
+            elapsed.as_secs_f64() * 1000.0,
+            self.entries,
+            prefix.escape_ascii(),
+            if self.key.len() > LOGGED_KEY_PREFIX {
+                "..."
+            } else {
+                ""
+            },
+            get().since(&self.before)
+        );
+        log::warn!("{}", message);
+        self.info_log.warn(format_args!("{}", message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_are_per_thread() {
+        reset();
+        update(|perf| perf.block_reads += 2);
+        assert_eq!(get().block_reads, 2);
+
+        std::thread::spawn(|| assert_eq!(get(), PerfContext::default())).join().unwrap();
+
+        reset();
+        assert_eq!(get(), PerfContext::default());
+    }
+
+    #[test]
+    fn test_since() {
+        let earlier = PerfContext { sstables_checked: 1, block_reads: 3, ..Default::default() };
+        let later = PerfContext { sstables_checked: 4, block_reads: 3, ..Default::default() };
+        let delta = later.since(&earlier);
+        assert_eq!(delta.sstables_checked, 3);
+        assert_eq!(delta.block_reads, 0);
+        assert!(delta.to_string().contains("sstables_checked=3"));
+    }
+
+    #[test]
+    fn test_slow_operation_log() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let info_log = InfoLog::open(temp_dir.path(), &crate::Options::default()).unwrap();
+        let key = b"a-rather-long-key-name";
+
+        assert!(SlowOperationLog::start(&info_log, "get", key, 1, 0).is_none());
+
+        // Fast enough: nothing logged
+        drop(SlowOperationLog::start(&info_log, "get", key, 1, 60_000));
+
+        let slow_log = SlowOperationLog::start(&info_log, "get", key, 1, 1);
+        update(|perf| perf.sstables_checked += 2);
+        std::thread::sleep(Duration::from_millis(2));
+        drop(slow_log);
+
+        let contents =
+            std::fs::read_to_string(temp_dir.path().join(crate::info_log::LOG_FILE_NAME)).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        assert!(contents.contains("WARN  Slow get took "), "{}", contents);
+        assert!(contents.contains("key prefix \"a-rather-long-ke\"..."), "{}", contents);
+        assert!(contents.contains("sstables_checked=2"), "{}", contents);
+    }
+}
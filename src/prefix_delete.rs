@@ -0,0 +1,309 @@
+//! Deleting every key with a prefix without writing a tombstone per key.
+//!
+//! [`DB::delete_prefix`] records a prefix tombstone: the prefix, the
+//! sequence number it was issued at and the numbers of the SSTables live at
+//! that moment. A key with the prefix is deleted in
+//! - a MemTable, if its version there is not newer than the tombstone
+//! - an SSTable the tombstone lists, since every version in it is older
+//!
+//! Reads and iterators apply the tombstones as they go. Flushes write a
+//! deletion for covered MemTable entries and compactions drop the covered
+//! entries of their inputs, so the data is removed as the LSM tree is
+//! rewritten anyway. A tombstone is retired once none of its SSTables is
+//! live and no MemTable can hold a version it covers.
+//!
+//! Tombstones are stored in the `PREFIX_DELETES` file of the database
+//! directory. They are not written to the WAL, so change feeds and
+//! [`DB::watch`] do not report them.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::env::Env;
+use crate::{Error, Result, DB};
+
+/// File in the database directory holding the prefix tombstones
+pub(crate) const PREFIX_DELETES_FILE: &str = "PREFIX_DELETES";
+
+/// A deletion of every key starting with `prefix` written up to `sequence`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PrefixTombstone {
+    prefix: Vec<u8>,
+    sequence: u64,
+    /// SSTables live when the prefix was deleted
+    files: BTreeSet<u64>,
+}
+
+/// The prefix tombstones visible to a read.
+///
+/// Cheap to clone; a read takes one when it starts so the set does not
+/// change under it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PrefixDeletes {
+    tombstones: Arc<Vec<PrefixTombstone>>,
+}
+
+impl PrefixDeletes {
+    /// The tombstones a read at `sequence` sees
+    pub(crate) fn visible_at(&self, sequence: u64) -> Self {
+        if self.tombstones.iter().all(|t| t.sequence <= sequence) {
+            return self.clone();
+        }
+        let visible = self.tombstones.iter().filter(|t| t.sequence <= sequence).cloned();
+        Self { tombstones: Arc::new(visible.collect()) }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tombstones.is_empty()
+    }
+
+    /// Whether the MemTable version of `key` at `sequence` is deleted
+    pub(crate) fn covers_sequence(&self, key: &[u8], sequence: u64) -> bool {
+        self.tombstones
+            .iter()
+            .any(|t| sequence <= t.sequence && key.starts_with(&t.prefix))
+    }
+
+    /// Whether `key` in the SSTable `file_number` is deleted
+    pub(crate) fn covers_file(&self, key: &[u8], file_number: Option<u64>) -> bool {
+        let Some(file_number) = file_number else {
+            return false;
+        };
+        self.tombstones
+            .iter()
+            .any(|t| t.files.contains(&file_number) && key.starts_with(&t.prefix))
+    }
+}
+
+/// The prefix tombstones of a database
+pub(crate) struct PrefixTombstones {
+    current: Mutex<PrefixDeletes>,
+}
+
+impl PrefixTombstones {
+    /// Loads the tombstones stored in `db_path`
+    pub(crate) fn load(env: &dyn Env, db_path: &Path) -> Result<Self> {
+        let path = db_path.join(PREFIX_DELETES_FILE);
+        let tombstones = if env.file_exists(&path) {
+            serde_json::from_slice(&env.read_file(&path)?).map_err(|e| {
+                Error::corruption(format!("invalid {} file: {}", PREFIX_DELETES_FILE, e))
+            })?
+        } else {
+            Vec::new()
+        };
+        let current = PrefixDeletes { tombstones: Arc::new(tombstones) };
+        Ok(Self { current: Mutex::new(current) })
+    }
+
+    /// Replaces the tombstones with the ones stored in `db_path`
+    pub(crate) fn reload(&self, env: &dyn Env, db_path: &Path) -> Result<()> {
+        let loaded = Self::load(env, db_path)?;
+        *self.current.lock() = loaded.current.into_inner();
+        Ok(())
+    }
+
+    /// The current tombstones
+    pub(crate) fn get(&self) -> PrefixDeletes {
+        self.current.lock().clone()
+    }
+
+    /// Applies `update` to the tombstones and stores them
+    fn update(
+        &self,
+        env: &dyn Env,
+        db_path: &Path,
+        update: impl FnOnce(&mut Vec<PrefixTombstone>),
+    ) -> Result<()> {
+        let mut current = self.current.lock();
+        let mut tombstones = current.tombstones.to_vec();
+        update(&mut tombstones);
+
+        let path = db_path.join(PREFIX_DELETES_FILE);
+        if tombstones.is_empty() {
+            if env.file_exists(&path) {
+                env.remove_file(&path)?;
+                env.sync_dir(db_path)?;
+            }
+        } else {
+            let data = serde_json::to_vec(&tombstones).map_err(|e| {
+                Error::internal(format!("failed to serialize prefix tombstones: {}", e))
+            })?;
+            let tmp_path = path.with_extension("tmp");
+            env.write_file(&tmp_path, &data)?;
+            env.rename_file(&tmp_path, &path)?;
+            env.sync_dir(db_path)?;
+        }
+        *current = PrefixDeletes { tombstones: Arc::new(tombstones) };
+        Ok(())
+    }
+}
+
+impl DB {
+    /// Deletes every key starting with `prefix`.
+    ///
+    /// Takes about the same time however many keys match: instead of a
+    /// tombstone per key, one prefix tombstone is recorded and applied by
+    /// reads, flushes and compactions (see the
+    /// [module documentation](self)). Writes made concurrently may or may
+    /// not be deleted; writes after this returns are not. An empty prefix
+    /// deletes everything.
+    ///
+    /// Snapshots older than the last write before the call still see the
+    /// keys, until a compaction rewrites the files holding them.
+    pub fn delete_prefix(&self, prefix: &[u8]) -> Result<()> {
+        self.check_background_error()?;
+
+        // No flush or compaction may write an SSTable the tombstone does
+        // not list without applying it
+        let _compaction = self.compaction_lock.lock();
+        let _flush = self.flush_lock.lock();
+
+        let sequence = self.sequence.load(Ordering::SeqCst);
+        let files = self.sstables.read().iter().flatten().filter_map(|t| t.file_number()).collect();
+        let tombstone = PrefixTombstone { prefix: prefix.to_vec(), sequence, files };
+        self.prefix_tombstones
+            .update(&*self.env, &self.path, |tombstones| tombstones.push(tombstone))?;
+
+        log::info!("Deleted prefix {:?} at sequence {}", String::from_utf8_lossy(prefix), sequence);
+        self.info_log.info(format_args!(
+            "Deleted prefix {:?} at sequence {}",
+            String::from_utf8_lossy(prefix),
+            sequence
+        ));
+        Ok(())
+    }
+
+    /// Drops the prefix tombstones that no longer cover any data.
+    pub(crate) fn retire_prefix_tombstones(&self) -> Result<()> {
+        let current = self.prefix_tombstones.get();
+        if current.is_empty() {
+            return Ok(());
+        }
+
+        let live: HashSet<u64> =
+            self.sstables.read().iter().flatten().filter_map(|t| t.file_number()).collect();
+        // MemTables only hold versions from their start sequence on
+        let oldest_memtable = {
+            let memtable = self.memtable.read();
+            let immutable = self.immutable_memtables.read();
+            immutable
+                .iter()
+                .map(|m| m.start_sequence())
+                .fold(memtable.start_sequence(), u64::min)
+        };
+        let retired: Vec<&PrefixTombstone> = current
+            .tombstones
+            .iter()
+            .filter(|t| t.sequence < oldest_memtable && t.files.iter().all(|f| !live.contains(f)))
+            .collect();
+        if retired.is_empty() {
+            return Ok(());
+        }
+
+        self.prefix_tombstones.update(&*self.env, &self.path, |tombstones| {
+            tombstones.retain(|t| !retired.contains(&t))
+        })?;
+        for tombstone in retired {
+            log::info!("Retired prefix tombstone {:?}", String::from_utf8_lossy(&tombstone.prefix));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, WriteBatch};
+    use tempfile::TempDir;
+
+    fn keys(db: &Arc<DB>) -> Vec<Vec<u8>> {
+        let mut keys = Vec::new();
+        let mut iter = db.iter();
+        while iter.valid() {
+            keys.push(iter.key().to_vec());
+            iter.next();
+        }
+        keys
+    }
+
+    #[test]
+    fn test_delete_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        for i in 0..100 {
+            db.put(format!("a/{:03}", i).as_bytes(), b"old").unwrap();
+        }
+        db.flush().unwrap();
+        db.put(b"a/000", b"memtable").unwrap();
+        db.put(b"a/500", b"memtable").unwrap();
+        let snapshot = db.snapshot();
+        db.put(b"b/000", b"kept").unwrap();
+
+        db.delete_prefix(b"a/").unwrap();
+        assert_eq!(db.get(b"a/000").unwrap(), None);
+        assert_eq!(db.get(b"a/050").unwrap(), None);
+        assert_eq!(db.get(b"a/500").unwrap(), None);
+        assert_eq!(db.get(b"b/000").unwrap(), Some(b"kept".to_vec()));
+        assert_eq!(keys(&db), vec![b"b/000".to_vec()]);
+        assert_eq!(snapshot.get(b"a/050").unwrap(), Some(b"old".to_vec()));
+
+        // Later writes are not deleted
+        let mut batch = WriteBatch::new();
+        batch.put(b"a/001", b"new");
+        db.write(batch).unwrap();
+        assert_eq!(db.get(b"a/001").unwrap(), Some(b"new".to_vec()));
+        assert_eq!(keys(&db), vec![b"a/001".to_vec(), b"b/000".to_vec()]);
+
+        // The tombstone survives a restart
+        drop(snapshot);
+        drop(db);
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        assert_eq!(db.get(b"a/050").unwrap(), None);
+        assert_eq!(db.get(b"a/500").unwrap(), None);
+        assert_eq!(keys(&db), vec![b"a/001".to_vec(), b"b/000".to_vec()]);
+    }
+
+    #[test]
+    fn test_prefix_tombstone_retired_after_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        for round in 0..4 {
+            for i in 0..50 {
+                db.put(format!("t/{:02}", i).as_bytes(), format!("{}", round).as_bytes())
+                    .unwrap();
+            }
+            db.put(format!("u/{}", round).as_bytes(), b"kept").unwrap();
+            db.flush().unwrap();
+        }
+        db.put(b"t/99", b"memtable").unwrap();
+        db.delete_prefix(b"t/").unwrap();
+        assert!(temp_dir.path().join(PREFIX_DELETES_FILE).exists());
+
+        // Flushes and the compaction they trigger rewrite the covered files
+        for round in 0..4 {
+            db.put(format!("u/{}", round).as_bytes(), b"kept").unwrap();
+            db.flush().unwrap();
+        }
+        assert!(db.prefix_tombstones.get().is_empty());
+        assert!(!temp_dir.path().join(PREFIX_DELETES_FILE).exists());
+        assert_eq!(db.get(b"t/10").unwrap(), None);
+        assert_eq!(db.get(b"t/99").unwrap(), None);
+        assert_eq!(keys(&db).len(), 4);
+    }
+
+    #[test]
+    fn test_delete_shadows_older_sstable_value() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.put(b"key", b"value").unwrap();
+        db.flush().unwrap();
+        db.delete(b"key").unwrap();
+        assert_eq!(db.get(b"key").unwrap(), None);
+        db.flush().unwrap();
+        assert_eq!(db.get(b"key").unwrap(), None);
+    }
+}
@@ -0,0 +1,354 @@
+//! Python bindings (requires the `python` feature).
+//!
+//! Builds a Python extension module named `aidb` with
+//! [maturin](https://www.maturin.rs) (`maturin develop --release`, see
+//! `pyproject.toml`):
+//!
+//! ```python
+//! import aidb
+//!
+//! with aidb.DB("./data", {"block_cache_size": 64 << 20}) as db:
+//!     db.put(b"user:1", b"alice")
+//!     db[b"user:2"] = b"bob"
+//!     print(db.get(b"user:1"), b"user:3" in db)
+//!
+//!     # Applied atomically when the block exits without an exception
+//!     with db.batch() as batch:
+//!         batch.put(b"user:3", b"carol")
+//!         batch.delete(b"user:1")
+//!
+//!     for key, value in db.iter(start=b"user:", end=b"user;"):
+//!         print(key, value)
+//! ```
+//!
+//! Options are given as a dict of option names and values, as in the
+//! `OPTIONS` file. Keys and values are `bytes`; returned values are copied
+//! once, straight into the `bytes` object. The GIL is released while the
+//! database does I/O, so other Python threads keep running.
+//!
+//! A batch is atomic but not isolated: reads inside the `with` block do not
+//! see its writes, and other writers are not blocked. There is no
+//! scripting engine to expose yet.
+
+use crate::error::Error;
+use crate::{DBIterator, Options, WriteBatch, DB};
+use pyo3::exceptions::{PyException, PyKeyError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDict, PyType};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pyo3::create_exception!(aidb, AiDbError, PyException, "Error raised by the AiDb engine.");
+
+fn to_py_err(e: Error) -> PyErr {
+    AiDbError::new_err(e.to_string())
+}
+
+/// An open database (`aidb.DB`).
+#[pyclass(name = "DB", module = "aidb")]
+pub struct PyDB {
+    /// `None` once closed
+    db: Option<Arc<DB>>,
+}
+
+impl PyDB {
+    fn db(&self) -> PyResult<&Arc<DB>> {
+        self.db.as_ref().ok_or_else(|| AiDbError::new_err("Database is closed"))
+    }
+}
+
+#[pymethods]
+impl PyDB {
+    /// Opens (or creates) the database at `path`.
+    #[new]
+    #[pyo3(signature = (path, options = None))]
+    fn open(py: Python<'_>, path: PathBuf, options: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut db_options = Options::default();
+        for (name, value) in options.into_iter().flat_map(|options| options.iter()) {
+            let name: String = name.extract()?;
+            // Python spells booleans with a capital letter
+            let value = match value.downcast::<PyBool>() {
+                Ok(flag) => flag.is_true().to_string(),
+                Err(_) => value.str()?.to_string(),
+            };
+            db_options.set_option(&name, &value).map_err(to_py_err)?;
+        }
+        let db = py.allow_threads(|| DB::open(path, db_options)).map_err(to_py_err)?;
+        Ok(Self { db: Some(Arc::new(db)) })
+    }
+
+    /// Returns the value of `key`, or `None`.
+    fn get<'py>(&self, py: Python<'py>, key: &[u8]) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let db = self.db()?;
+        let value = py.allow_threads(|| db.get(key)).map_err(to_py_err)?;
+        Ok(value.map(|value| PyBytes::new(py, &value)))
+    }
+
+    /// Stores `value` under `key`.
+    fn put(&self, py: Python<'_>, key: &[u8], value: &[u8]) -> PyResult<()> {
+        let db = self.db()?;
+        py.allow_threads(|| db.put(key, value)).map_err(to_py_err)
+    }
+
+    /// Deletes `key`. Deleting a missing key is not an error.
+    fn delete(&self, py: Python<'_>, key: &[u8]) -> PyResult<()> {
+        let db = self.db()?;
+        py.allow_threads(|| db.delete(key)).map_err(to_py_err)
+    }
+
+    /// Applies the writes of `batch` atomically.
+    fn write(&self, py: Python<'_>, batch: &PyWriteBatch) -> PyResult<()> {
+        let db = self.db()?;
+        let batch = batch.batch.clone();
+        py.allow_threads(|| db.write(batch)).map_err(to_py_err)
+    }
+
+    /// Returns a batch that is written to this database when its `with`
+    /// block exits without an exception.
+    fn batch(&self) -> PyResult<PyWriteBatch> {
+        Ok(PyWriteBatch { batch: WriteBatch::new(), db: Some(Arc::clone(self.db()?)) })
+    }
+
+    /// Iterates over `(key, value)` pairs with `start <= key < end`.
+    #[pyo3(signature = (start = None, end = None))]
+    fn iter(
+        &self,
+        py: Python<'_>,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> PyResult<PyDBIterator> {
+        let db = self.db()?;
+        let iter = py.allow_threads(|| db.scan(start, end)).map_err(to_py_err)?;
+        Ok(PyDBIterator { iter })
+    }
+
+    /// Writes the MemTable to disk.
+    fn flush(&self, py: Python<'_>) -> PyResult<()> {
+        let db = self.db()?;
+        py.allow_threads(|| db.flush()).map_err(to_py_err)
+    }
+
+    /// Flushes and closes the database. Further calls raise `aidb.Error`.
+    fn close(&mut self, py: Python<'_>) -> PyResult<()> {
+        match self.db.take() {
+            Some(db) => py.allow_threads(|| db.close()).map_err(to_py_err),
+            None => Ok(()),
+        }
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&mut self, py: Python<'_>, _args: &Bound<'_, PyAny>) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+
+    fn __getitem__<'py>(&self, py: Python<'py>, key: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+        self.get(py, key)?
+            .ok_or_else(|| PyKeyError::new_err(PyBytes::new(py, key).unbind()))
+    }
+
+    fn __setitem__(&self, py: Python<'_>, key: &[u8], value: &[u8]) -> PyResult<()> {
+        self.put(py, key, value)
+    }
+
+    fn __delitem__(&self, py: Python<'_>, key: &[u8]) -> PyResult<()> {
+        self.delete(py, key)
+    }
+
+    fn __contains__(&self, py: Python<'_>, key: &[u8]) -> PyResult<bool> {
+        Ok(self.get(py, key)?.is_some())
+    }
+}
+
+/// A batch of writes applied atomically (`aidb.WriteBatch`).
+///
+/// Create one with `aidb.WriteBatch()` and apply it with `DB.write`, or use
+/// `DB.batch()` as a context manager.
+#[pyclass(name = "WriteBatch", module = "aidb")]
+pub struct PyWriteBatch {
+    batch: WriteBatch,
+    /// Database written on `__exit__`, for batches from `DB.batch()`
+    db: Option<Arc<DB>>,
+}
+
+#[pymethods]
+impl PyWriteBatch {
+    #[new]
+    fn new() -> Self {
+        Self { batch: WriteBatch::new(), db: None }
+    }
+
+    /// Adds a put to the batch.
+    fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.batch.put(key, value);
+    }
+
+    /// Adds a delete to the batch.
+    fn delete(&mut self, key: &[u8]) {
+        self.batch.delete(key);
+    }
+
+    /// Removes every write from the batch.
+    fn clear(&mut self) {
+        self.batch.clear();
+    }
+
+    /// Marks the current end of the batch.
+    fn set_savepoint(&mut self) {
+        self.batch.set_savepoint();
+    }
+
+    /// Drops the writes added since the last savepoint.
+    fn rollback_to_savepoint(&mut self) -> PyResult<()> {
+        self.batch.rollback_to_savepoint().map_err(to_py_err)
+    }
+
+    fn __len__(&self) -> usize {
+        self.batch.len()
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Writes the batch unless the block raised; the batch is emptied
+    /// either way.
+    #[pyo3(signature = (exc_type, _exc_value, _traceback))]
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        exc_type: Option<&Bound<'_, PyType>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        let batch = std::mem::take(&mut self.batch);
+        if let (None, Some(db)) = (exc_type, &self.db) {
+            if !batch.is_empty() {
+                py.allow_threads(|| db.write(batch)).map_err(to_py_err)?;
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Iterator over `(key, value)` pairs (`aidb.Iterator`).
+#[pyclass(name = "Iterator", module = "aidb")]
+pub struct PyDBIterator {
+    iter: DBIterator,
+}
+
+#[pymethods]
+impl PyDBIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(
+        &mut self,
+        py: Python<'py>,
+    ) -> Option<(Bound<'py, PyBytes>, Bound<'py, PyBytes>)> {
+        if !self.iter.valid() {
+            return None;
+        }
+        let entry = (PyBytes::new(py, self.iter.key()), PyBytes::new(py, self.iter.value()));
+        let iter = &mut self.iter;
+        py.allow_threads(|| iter.next());
+        Some(entry)
+    }
+
+    /// Moves to the first key at least `key`.
+    fn seek(&mut self, py: Python<'_>, key: &[u8]) {
+        let iter = &mut self.iter;
+        py.allow_threads(|| iter.seek(key));
+    }
+}
+
+/// The `aidb` Python module.
+#[pymodule]
+pub fn aidb(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyDB>()?;
+    m.add_class::<PyWriteBatch>()?;
+    m.add_class::<PyDBIterator>()?;
+    m.add("Error", m.py().get_type::<AiDbError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::ffi::c_str;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_python_module() {
+        let temp_dir = TempDir::new().unwrap();
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "aidb").unwrap();
+            aidb(&module).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("aidb", module).unwrap();
+            locals.set_item("path", temp_dir.path()).unwrap();
+
+            let script = c_str!(
+                r#"
+with aidb.DB(path, {"use_bloom_filter": True, "block_cache_size": 1 << 20}) as db:
+    db.put(b"a", b"1")
+    db[b"b"] = b"2"
+    assert db.get(b"a") == b"1"
+    assert db[b"b"] == b"2"
+    assert db.get(b"zz") is None
+    assert b"a" in db and b"zz" not in db
+    try:
+        db[b"zz"]
+        raise AssertionError("expected KeyError")
+    except KeyError:
+        pass
+
+    with db.batch() as batch:
+        batch.put(b"c", b"3")
+        batch.delete(b"a")
+        assert len(batch) == 2
+    assert db.get(b"a") is None and db.get(b"c") == b"3"
+
+    # An exception discards the batch
+    try:
+        with db.batch() as batch:
+            batch.put(b"d", b"4")
+            raise RuntimeError("abort")
+    except RuntimeError:
+        pass
+    assert db.get(b"d") is None
+
+    batch = aidb.WriteBatch()
+    batch.put(b"d", b"4")
+    db.write(batch)
+    del db[b"b"]
+
+    assert list(db.iter()) == [(b"c", b"3"), (b"d", b"4")]
+    assert [k for k, _ in db.iter(start=b"d")] == [b"d"]
+    it = db.iter()
+    it.seek(b"d")
+    assert next(it) == (b"d", b"4")
+
+try:
+    db.get(b"a")
+    raise AssertionError("expected aidb.Error")
+except aidb.Error as e:
+    assert "closed" in str(e)
+
+try:
+    aidb.DB(path, {"no_such_option": 1})
+    raise AssertionError("expected aidb.Error")
+except aidb.Error as e:
+    assert "unknown option" in str(e)
+"#
+            );
+            py.run(script, None, Some(&locals)).unwrap();
+        });
+    }
+}
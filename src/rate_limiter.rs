@@ -0,0 +1,215 @@
+//! Bounding the disk bandwidth of flushes and compactions.
+//!
+//! A [`RateLimiter`] set in [`Options::rate_limiter`](crate::Options) is
+//! charged for every SSTable byte flushes and compactions write, and makes
+//! them wait once they write faster than its rate. One limiter can be
+//! shared by several databases to bound the background writes of the whole
+//! process.
+//!
+//! Requests come in two lanes: flushes are [`IoPriority::High`], since
+//! writes stall while MemTables wait for them, and compactions are
+//! [`IoPriority::Low`]. While a high priority request waits for its bytes,
+//! low priority ones do not get any.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::rate_limiter::RateLimiter;
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let limiter = Arc::new(RateLimiter::new(50 * 1024 * 1024));
+//! let options = Options::default().rate_limiter(Arc::clone(&limiter));
+//! let a = DB::open("./tenant-a", options.clone())?;
+//! let b = DB::open("./tenant-b", options)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use parking_lot::{Condvar, Mutex};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::util::Instant;
+
+/// Longest a waiting request sleeps before checking for bytes again
+const MAX_WAIT: Duration = Duration::from_millis(10);
+
+/// Lane a request is charged to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IoPriority {
+    /// Flushes
+    High,
+    /// Compactions
+    Low,
+}
+
+struct Bucket {
+    /// Bytes that can be granted without waiting, up to one second's worth
+    available: f64,
+    last_refill: Instant,
+    /// High priority requests waiting for bytes
+    high_waiting: usize,
+    bytes_through: [u64; 2],
+    requests: [u64; 2],
+}
+
+/// Token bucket limiting the bytes written per second, shareable between
+/// databases.
+///
+/// See the [module documentation](crate::rate_limiter).
+pub struct RateLimiter {
+    bytes_per_second: AtomicU64,
+    bucket: Mutex<Bucket>,
+    /// Signalled when a high priority request is served
+    served: Condvar,
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("bytes_per_second", &self.bytes_per_second())
+            .finish()
+    }
+}
+
+impl RateLimiter {
+    /// Creates a limiter granting `bytes_per_second` (0 = unlimited).
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: AtomicU64::new(bytes_per_second),
+            bucket: Mutex::new(Bucket {
+                available: bytes_per_second as f64,
+                last_refill: Instant::now(),
+                high_waiting: 0,
+                bytes_through: [0; 2],
+                requests: [0; 2],
+            }),
+            served: Condvar::new(),
+        }
+    }
+
+    /// The rate in bytes per second (0 = unlimited).
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second.load(Ordering::Relaxed)
+    }
+
+    /// Changes the rate; applies to requests from now on.
+    pub fn set_bytes_per_second(&self, bytes_per_second: u64) {
+        self.bytes_per_second.store(bytes_per_second, Ordering::Relaxed);
+    }
+
+    /// Bytes granted to `priority` so far.
+    pub fn total_bytes_through(&self, priority: IoPriority) -> u64 {
+        self.bucket.lock().bytes_through[priority as usize]
+    }
+
+    /// Requests made with `priority` so far.
+    pub fn total_requests(&self, priority: IoPriority) -> u64 {
+        self.bucket.lock().requests[priority as usize]
+    }
+
+    /// Waits until `bytes` may be written. Requests larger than one
+    /// second's worth are granted in parts.
+    pub fn request(&self, bytes: u64, priority: IoPriority) {
+        self.request_at(bytes, priority, 1.0);
+    }
+
+    /// Waits until `bytes` may be written at `multiplier` times the rate.
+    ///
+    /// The bytes count in full towards the totals; with a multiplier of
+    /// `f64::INFINITY` they are granted right away.
+    pub fn request_at(&self, bytes: u64, priority: IoPriority, multiplier: f64) {
+        let mut bucket = self.bucket.lock();
+        bucket.requests[priority as usize] += 1;
+        bucket.bytes_through[priority as usize] += bytes;
+        if priority == IoPriority::High {
+            bucket.high_waiting += 1;
+        }
+
+        let mut remaining = bytes as f64 / multiplier;
+        loop {
+            let rate = self.bytes_per_second() as f64;
+            if rate == 0.0 || remaining <= 0.0 {
+                break;
+            }
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.available = (bucket.available + elapsed * rate).min(rate);
+            bucket.last_refill = now;
+
+            let may_take = priority == IoPriority::High || bucket.high_waiting == 0;
+            if may_take && bucket.available > 0.0 {
+                let granted = remaining.min(bucket.available);
+                bucket.available -= granted;
+                remaining -= granted;
+                if remaining <= 0.0 {
+                    break;
+                }
+            }
+            let needed = if may_take { remaining.min(rate) } else { rate };
+            let wait = Duration::from_secs_f64((needed - bucket.available).max(0.0) / rate);
+            self.served
+                .wait_for(&mut bucket, wait.clamp(Duration::from_micros(100), MAX_WAIT));
+        }
+
+        if priority == IoPriority::High {
+            bucket.high_waiting -= 1;
+            self.served.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, DB};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rate_limiter_paces_requests() {
+        let limiter = RateLimiter::new(100_000);
+        let start = std::time::Instant::now();
+        // The first second's worth is available right away
+        limiter.request(100_000, IoPriority::Low);
+        assert!(start.elapsed() < Duration::from_millis(200));
+        limiter.request(30_000, IoPriority::Low);
+        assert!(start.elapsed() >= Duration::from_millis(250));
+        assert_eq!(limiter.total_bytes_through(IoPriority::Low), 130_000);
+        assert_eq!(limiter.total_requests(IoPriority::High), 0);
+
+        // Twice the rate, and no wait at all
+        let start = std::time::Instant::now();
+        limiter.request_at(40_000, IoPriority::Low, 2.0);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        let start = std::time::Instant::now();
+        limiter.request_at(1_000_000, IoPriority::Low, f64::INFINITY);
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(limiter.total_bytes_through(IoPriority::Low), 1_170_000);
+
+        limiter.set_bytes_per_second(0);
+        limiter.request(u64::MAX, IoPriority::High);
+    }
+
+    #[test]
+    fn test_rate_limiter_charges_flushes_and_compactions() {
+        let temp_dir = TempDir::new().unwrap();
+        let limiter = Arc::new(RateLimiter::new(1024 * 1024 * 1024));
+        let options = Options::default()
+            .level0_compaction_threshold(2)
+            .rate_limiter(Arc::clone(&limiter));
+        let db = DB::open(temp_dir.path(), options).unwrap();
+
+        db.put(b"a", b"1").unwrap();
+        db.flush().unwrap();
+        assert!(limiter.total_bytes_through(IoPriority::High) > 0);
+        assert_eq!(limiter.total_bytes_through(IoPriority::Low), 0);
+
+        db.put(b"b", b"2").unwrap();
+        db.flush().unwrap();
+        assert!(limiter.total_bytes_through(IoPriority::Low) > 0);
+    }
+}
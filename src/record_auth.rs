@@ -0,0 +1,191 @@
+//! Tamper-evident records.
+//!
+//! CRC32 checksums catch accidental damage, but anyone who can write the
+//! files can change a record and recompute its checksum. With
+//! [`Options::authenticate_records`](crate::Options::authenticate_records),
+//! every SSTable entry and every WAL record also carries a 16 byte tag: an
+//! HMAC-SHA256 of its key and value, keyed with a key derived from the
+//! [`KeyProvider`]. Reads verify the tag and fail with
+//! [`Error::AuthenticationFailed`] if it does not match, so a record changed
+//! without the key is detected.
+//!
+//! A tag binds a value to its key, not to its position: removing records or
+//! whole files, or putting back an older copy of a file, is not detected.
+//!
+//! SSTables record the version of the key their tags were made with in the
+//! footer, WAL records next to the tag, so files stay verifiable after a
+//! key rotation as long as the provider still has the older key.
+
+use hmac::{Hmac, Mac};
+use parking_lot::Mutex;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::env::KeyProvider;
+use crate::error::{Error, Result};
+
+/// Size of the tag appended to each record
+pub const TAG_SIZE: usize = 16;
+
+/// Separates the authentication keys from the encryption keys they are
+/// derived from
+const KEY_CONTEXT: &[u8] = b"aidb record authentication";
+
+/// Computes and verifies record tags with the keys of a [`KeyProvider`].
+///
+/// See the [module documentation](self).
+pub struct RecordAuthenticator {
+    keys: Arc<dyn KeyProvider>,
+    /// Keyed MACs by key version
+    macs: Mutex<HashMap<u32, Hmac<Sha256>>>,
+}
+
+impl fmt::Debug for RecordAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordAuthenticator").finish_non_exhaustive()
+    }
+}
+
+impl RecordAuthenticator {
+    /// Creates an authenticator using the keys of `keys`.
+    pub fn new(keys: Arc<dyn KeyProvider>) -> Self {
+        Self { keys, macs: Mutex::new(HashMap::new()) }
+    }
+
+    /// Version of the key new records are tagged with.
+    pub fn active_version(&self) -> Result<u32> {
+        Ok(self.keys.active_key()?.0)
+    }
+
+    /// The MAC keyed for `version`
+    fn mac(&self, version: u32) -> Result<Hmac<Sha256>> {
+        if let Some(mac) = self.macs.lock().get(&version) {
+            return Ok(mac.clone());
+        }
+        let mut derive =
+            Hmac::<Sha256>::new_from_slice(&self.keys.key(version)?).expect("any key size");
+        derive.update(KEY_CONTEXT);
+        let mac =
+            Hmac::<Sha256>::new_from_slice(&derive.finalize().into_bytes()).expect("any key size");
+        self.macs.lock().insert(version, mac.clone());
+        Ok(mac)
+    }
+
+    fn keyed(&self, version: u32, key: &[u8], value: &[u8]) -> Result<Hmac<Sha256>> {
+        let mut mac = self.mac(version)?;
+        mac.update(&(key.len() as u64).to_le_bytes());
+        mac.update(key);
+        mac.update(value);
+        Ok(mac)
+    }
+
+    /// Returns the tag of `key` and `value` with the key of `version`.
+    pub fn tag(&self, version: u32, key: &[u8], value: &[u8]) -> Result<[u8; TAG_SIZE]> {
+        let tag = self.keyed(version, key, value)?.finalize().into_bytes();
+        Ok(tag[..TAG_SIZE].try_into().expect("HMAC-SHA256 is 32 bytes"))
+    }
+
+    /// Checks the tag at the end of `stored` and returns the value before it.
+    pub fn verify<'a>(&self, version: u32, key: &[u8], stored: &'a [u8]) -> Result<&'a [u8]> {
+        let (value, tag) = split_tag(stored)?;
+        self.keyed(version, key, value)?.verify_truncated_left(tag).map_err(|_| {
+            Error::AuthenticationFailed(format!(
+                "record of key {:?} does not match its tag",
+                String::from_utf8_lossy(key)
+            ))
+        })?;
+        Ok(value)
+    }
+}
+
+/// Splits a stored record into its value and tag.
+pub fn split_tag(stored: &[u8]) -> Result<(&[u8], &[u8])> {
+    let Some(split) = stored.len().checked_sub(TAG_SIZE) else {
+        return Err(Error::corruption("authenticated record is shorter than its tag"));
+    };
+    Ok(stored.split_at(split))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::StaticKeyProvider;
+
+    #[test]
+    fn test_record_authenticator() {
+        let keys = StaticKeyProvider::new([1; 32]).with_key(2, [2; 32]);
+        let auth = RecordAuthenticator::new(Arc::new(keys));
+        assert_eq!(auth.active_version().unwrap(), 2);
+
+        let mut stored = b"value".to_vec();
+        stored.extend_from_slice(&auth.tag(2, b"key", b"value").unwrap());
+        assert_eq!(auth.verify(2, b"key", &stored).unwrap(), b"value");
+
+        // Another key, version or value fails
+        assert!(matches!(auth.verify(2, b"kez", &stored), Err(Error::AuthenticationFailed(_))));
+        assert!(matches!(auth.verify(0, b"key", &stored), Err(Error::AuthenticationFailed(_))));
+        stored[0] ^= 1;
+        assert!(matches!(auth.verify(2, b"key", &stored), Err(Error::AuthenticationFailed(_))));
+
+        assert!(auth.verify(7, b"key", &stored).is_err());
+        assert!(matches!(auth.verify(2, b"key", b"short"), Err(Error::Corruption(_))));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_db_authenticates_records() {
+        use crate::env::{EncryptedEnv, Env, MemEnv};
+        use crate::sstable::SSTableBuilder;
+        use crate::wal::{entry, WAL};
+        use crate::{Options, DB};
+        use std::path::Path;
+
+        let base = Arc::new(MemEnv::new());
+        let keys = Arc::new(StaticKeyProvider::new([3; 32]));
+        let options = Options::default()
+            .env(base.clone())
+            .key_provider(keys.clone())
+            .authenticate_records(true);
+        let db = DB::open("/db", options.clone()).unwrap();
+        db.put(b"flushed", b"v1").unwrap();
+        db.flush().unwrap();
+        db.put(b"in-wal", b"v2").unwrap();
+        assert!(db.verify_checksums().unwrap().is_ok());
+        drop(db);
+
+        let db = DB::open("/db", options.clone()).unwrap();
+        assert_eq!(db.get(b"flushed").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(db.get(b"in-wal").unwrap(), Some(b"v2".to_vec()));
+        drop(db);
+
+        // A table written with the encryption key but tags from another key
+        let env = EncryptedEnv::new(base.clone(), keys.clone());
+        let find = |suffix: &str| {
+            let children = base.get_children(Path::new("/db")).unwrap();
+            let name = children.iter().find(|name| name.ends_with(suffix)).unwrap();
+            Path::new("/db").join(name)
+        };
+        let forger = Arc::new(RecordAuthenticator::new(Arc::new(StaticKeyProvider::new([4; 32]))));
+        let mut builder = SSTableBuilder::new_with_env(find(".sst"), Arc::new(env)).unwrap();
+        builder.set_record_authenticator(forger).unwrap();
+        builder.add(b"flushed", b"forged").unwrap();
+        builder.finish().unwrap();
+        let db = DB::open("/db", options.clone()).unwrap();
+        assert!(matches!(db.get(b"flushed"), Err(Error::AuthenticationFailed(_))));
+        assert!(!db.verify_checksums().unwrap().is_ok());
+        drop(db);
+
+        // A WAL record without a tag fails the open
+        let env = EncryptedEnv::new(base.clone(), keys);
+        let mut wal = WAL::open_with_env(find(".log"), &env).unwrap();
+        wal.append(&entry::encode_put(100, b"in-wal", b"forged")).unwrap();
+        wal.sync().unwrap();
+        drop(wal);
+        assert!(matches!(DB::open("/db", options), Err(Error::AuthenticationFailed(_))));
+
+        let unkeyed = Options::default().env(base).authenticate_records(true);
+        assert!(matches!(DB::open("/db", unkeyed), Err(Error::InvalidConfig(_))));
+    }
+}
@@ -0,0 +1,373 @@
+//! Offline repair of a damaged database directory.
+//!
+//! [`DB::repair`](crate::DB::repair) rebuilds the metadata of a database from
+//! the files that survived:
+//!
+//! 1. Every `*.sst` file is opened and all of its block checksums verified.
+//!    Intact files are kept; damaged ones are moved to the `lost/`
+//!    subdirectory.
+//! 2. A fresh `MANIFEST` is written that places every intact SSTable at
+//!    Level 0 and keeps the comparator name the previous one (or else the
+//!    OPTIONS file) recorded. The previous manifest, if any, is moved to
+//!    `lost/`.
+//! 3. Records that pass their CRC are salvaged from all WAL files (oldest
+//!    first) into a single new WAL, which is replayed on the next open.
+//!
+//! The database must not be open while it is being repaired.
+
+use crate::compaction::{VersionEdit, VersionSet};
+use crate::config::{Options, OptionsFile};
+use crate::env::Env;
+use crate::error::{Error, Result};
+use crate::sstable::SSTableReader;
+use crate::wal::{self, WALReader, WAL};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Name of the subdirectory that receives files which could not be salvaged.
+pub const LOST_DIR: &str = "lost";
+
+/// Summary of a repair run.
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// SSTables that were verified and added to the new manifest
+    pub sstables_recovered: Vec<PathBuf>,
+    /// Damaged files moved to the `lost/` subdirectory (at their new location)
+    pub files_lost: Vec<PathBuf>,
+    /// Number of WAL files read
+    pub wal_files_scanned: usize,
+    /// Number of WAL records that passed their checksum and were kept
+    pub wal_records_salvaged: u64,
+}
+
+/// Corrupt SSTables quarantined while opening a database.
+///
+/// See [`Options::quarantine_corrupt_files`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuarantineStats {
+    /// Number of SSTables moved to the `lost/` subdirectory
+    pub sstables_quarantined: usize,
+    /// New locations of the quarantined files
+    pub quarantined_paths: Vec<PathBuf>,
+}
+
+/// Repairs the database at `db_path`. See the [module documentation](self).
+pub fn repair<P: AsRef<Path>>(db_path: P) -> Result<RepairReport> {
+    repair_with_env(db_path, crate::env::default_env())
+}
+
+/// Repairs the database at `db_path` in `env`.
+pub fn repair_with_env<P: AsRef<Path>>(db_path: P, env: Arc<dyn Env>) -> Result<RepairReport> {
+    let db_path = db_path.as_ref();
+    if !env.file_exists(db_path) {
+        return Err(Error::not_found(format!("Database directory does not exist: {:?}", db_path)));
+    }
+
+    log::info!("Repairing database at {:?}", db_path);
+    let mut report = RepairReport::default();
+
+    let mut sst_files = Vec::new();
+    let mut wal_files = Vec::new();
+    for filename in env.get_children(db_path)? {
+        let path = db_path.join(&filename);
+        if filename.ends_with(crate::sstable::TEMP_FILE_SUFFIX) {
+            log::warn!("Removing incomplete SSTable {:?}", path);
+            env.remove_file(&path)?;
+        } else if let Some(number) =
+            filename.strip_suffix(".sst").and_then(|n| n.parse::<u64>().ok())
+        {
+            sst_files.push((number, path));
+        } else if let Some(number) = wal::parse_wal_filename(&filename) {
+            wal_files.push((number, path));
+        }
+    }
+    sst_files.sort();
+    wal_files.sort();
+
+    // Step 1: Verify SSTables
+    let mut edits = Vec::new();
+    let mut max_file_number = 1;
+    for (number, path) in sst_files {
+        max_file_number = max_file_number.max(number);
+        match inspect_sstable(&path, &*env) {
+            Ok(Some(edit)) => {
+                edits.push(edit);
+                report.sstables_recovered.push(path);
+            }
+            Ok(None) => {
+                log::warn!("Removing empty SSTable {:?}", path);
+                env.remove_file(&path)?;
+            }
+            Err(e) => {
+                log::warn!("SSTable {:?} is damaged: {}", path, e);
+                report.files_lost.push(move_to_lost(&*env, db_path, &path)?);
+            }
+        }
+    }
+
+    // Step 2: Rebuild the manifest
+    let manifest_path = db_path.join("MANIFEST");
+    let mut comparator_name = None;
+    if env.file_exists(&manifest_path) {
+        comparator_name = recorded_comparator(&*env, &manifest_path);
+        report.files_lost.push(move_to_lost(&*env, db_path, &manifest_path)?);
+    }
+    let options_file = OptionsFile::load_latest_with_env(db_path, &*env).ok().flatten();
+    let max_levels = options_file
+        .as_ref()
+        .map(|file| file.options.max_levels)
+        .unwrap_or(Options::default().max_levels);
+    // An unreadable manifest leaves the OPTIONS file as the only record
+    let comparator_name = comparator_name.or(options_file.map(|file| file.comparator));
+    let mut version_set = VersionSet::new_with_env(db_path, max_levels, Arc::clone(&env))?;
+    if let Some(name) = comparator_name {
+        version_set.log_edit(&VersionEdit::SetComparator(name))?;
+    }
+    for edit in &edits {
+        version_set.log_edit(edit)?;
+    }
+    version_set.log_edit(&VersionEdit::SetNextFileNumber(max_file_number + 1))?;
+
+    // Step 3: Salvage WAL records into a single new WAL
+    if !wal_files.is_empty() {
+        let new_number = wal_files.last().map(|(n, _)| n + 1).unwrap_or(1);
+        let new_path = db_path.join(wal::wal_filename(new_number));
+        let mut new_wal = WAL::open_with_env(&new_path, &*env)?;
+
+        for (_, path) in &wal_files {
+            report.wal_files_scanned += 1;
+            let mut reader = WALReader::new_with_env(path, &*env)?;
+            loop {
+                match reader.read_next() {
+                    Ok(Some(record)) => {
+                        new_wal.append(&record)?;
+                        report.wal_records_salvaged += 1;
+                    }
+                    Ok(None) => break,
+                    Err(Error::Corruption(msg)) => {
+                        log::warn!(
+                            "Dropping WAL {:?} from offset {}: {}",
+                            path,
+                            reader.position(),
+                            msg
+                        );
+                        break;
+                    }
+                    Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                        log::warn!("Dropping truncated record at the end of WAL {:?}", path);
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        new_wal.sync()?;
+
+        for (_, path) in &wal_files {
+            env.remove_file(path)?;
+        }
+    }
+
+    env.sync_dir(db_path)?;
+
+    log::info!(
+        "Repair completed: {} SSTables recovered, {} files lost, {} WAL records salvaged",
+        report.sstables_recovered.len(),
+        report.files_lost.len(),
+        report.wal_records_salvaged
+    );
+
+    Ok(report)
+}
+
+/// The comparator name recorded in the manifest at `path`, skipping the
+/// edits that cannot be read
+fn recorded_comparator(env: &dyn Env, path: &Path) -> Option<String> {
+    let mut contents = String::new();
+    env.new_sequential_file(path).ok()?.read_to_string(&mut contents).ok()?;
+    contents
+        .lines()
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(VersionEdit::SetComparator(name)) => Some(name),
+            _ => None,
+        })
+        .next_back()
+}
+
+/// Opens and verifies an SSTable, returning the manifest edit that adds it
+/// to Level 0, or `None` if it holds no keys.
+fn inspect_sstable(path: &Path, env: &dyn Env) -> Result<Option<VersionEdit>> {
+    let reader = SSTableReader::open_with_env(path, None, env)?;
+
+    if let Some((offset, error)) = reader.verify_checksums(None)?.into_iter().next() {
+        return Err(Error::corruption(format!("block at offset {}: {}", offset, error)));
+    }
+
+    let (Some(smallest_key), Some(largest_key)) = (reader.smallest_key()?, reader.largest_key()?)
+    else {
+        return Ok(None);
+    };
+    let file_number = reader
+        .file_number()
+        .ok_or_else(|| Error::internal(format!("Invalid SSTable filename: {:?}", path)))?;
+
+    Ok(Some(VersionEdit::AddFile {
+        level: 0,
+        file_number,
+        file_size: reader.file_size(),
+        smallest_key,
+        largest_key,
+    }))
+}
+
+/// Moves a file into the `lost/` subdirectory and returns its new path.
+pub(crate) fn move_to_lost(env: &dyn Env, db_path: &Path, path: &Path) -> Result<PathBuf> {
+    let lost_dir = db_path.join(LOST_DIR);
+    env.create_dir_all(&lost_dir)?;
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::internal(format!("Invalid file path: {:?}", path)))?;
+    let mut target = lost_dir.join(file_name);
+    let mut suffix = 1;
+    while env.file_exists(&target) {
+        target = lost_dir.join(format!("{}.{}", file_name.to_string_lossy(), suffix));
+        suffix += 1;
+    }
+
+    env.rename_file(path, &target)?;
+    log::warn!("Moved {:?} to {:?}", path, target);
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DB;
+    use tempfile::TempDir;
+
+    fn sst_paths(dir: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "sst"))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_repair_rebuilds_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            for batch in 0..3 {
+                db.put(format!("key{}", batch).as_bytes(), b"value").unwrap();
+                db.flush().unwrap();
+            }
+        }
+        std::fs::write(temp_dir.path().join("MANIFEST"), b"garbage").unwrap();
+        assert!(DB::open(temp_dir.path(), Options::default()).is_err());
+
+        let report = repair(temp_dir.path()).unwrap();
+        assert_eq!(report.sstables_recovered.len(), 3);
+        assert_eq!(report.files_lost, vec![temp_dir.path().join(LOST_DIR).join("MANIFEST")]);
+
+        let version_set = VersionSet::new(temp_dir.path(), 7).unwrap();
+        assert_eq!(version_set.current().levels[0].len(), 3);
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        for batch in 0..3 {
+            assert!(db.get(format!("key{}", batch).as_bytes()).unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn test_repair_keeps_comparator() {
+        struct Descending;
+
+        impl crate::comparator::Comparator for Descending {
+            fn name(&self) -> &str {
+                "test.Descending"
+            }
+
+            fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+                b.cmp(a)
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let options = || Options::default().comparator(Arc::new(Descending));
+        {
+            let db = DB::open(temp_dir.path(), options()).unwrap();
+            db.put(b"key", b"value").unwrap();
+            db.flush().unwrap();
+        }
+        std::fs::write(temp_dir.path().join("MANIFEST"), b"garbage").unwrap();
+
+        repair(temp_dir.path()).unwrap();
+        let version_set = VersionSet::new(temp_dir.path(), 7).unwrap();
+        assert_eq!(version_set.comparator_name(), Some("test.Descending"));
+        drop(version_set);
+        let db = DB::open(temp_dir.path(), options()).unwrap();
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[test]
+    fn test_repair_moves_damaged_sstable_to_lost() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+            db.put(b"good", b"value").unwrap();
+            db.flush().unwrap();
+            db.put(b"bad", b"value").unwrap();
+            db.flush().unwrap();
+        }
+
+        let damaged = sst_paths(temp_dir.path()).pop().unwrap();
+        let mut data = std::fs::read(&damaged).unwrap();
+        data[0] ^= 0xff;
+        std::fs::write(&damaged, data).unwrap();
+
+        let report = repair(temp_dir.path()).unwrap();
+        assert_eq!(report.sstables_recovered.len(), 1);
+        assert!(report.files_lost.iter().any(|p| p.ends_with(damaged.file_name().unwrap())));
+        assert!(!damaged.exists());
+
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert_eq!(db.get(b"good").unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[test]
+    fn test_repair_salvages_wal_records() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let mut wal = WAL::open(temp_dir.path().join(wal::wal_filename(3))).unwrap();
+            wal.append(b"first").unwrap();
+            wal.append(b"second").unwrap();
+            wal.sync().unwrap();
+        }
+        // Corrupt the last record
+        let wal_path = temp_dir.path().join(wal::wal_filename(3));
+        let mut data = std::fs::read(&wal_path).unwrap();
+        let len = data.len();
+        data[len - 1] ^= 0xff;
+        std::fs::write(&wal_path, data).unwrap();
+
+        let report = repair(temp_dir.path()).unwrap();
+        assert_eq!(report.wal_files_scanned, 1);
+        assert_eq!(report.wal_records_salvaged, 1);
+        assert!(!wal_path.exists());
+
+        let salvaged = WAL::recover(temp_dir.path().join(wal::wal_filename(4))).unwrap();
+        assert_eq!(salvaged, vec![b"first".to_vec()]);
+    }
+
+    #[test]
+    fn test_repair_missing_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(matches!(repair(temp_dir.path().join("missing")), Err(Error::NotFound(_))));
+    }
+}
@@ -0,0 +1,317 @@
+//! Reading a database another process writes to.
+//!
+//! One process opens the database with [`DB::open`] and holds the `LOCK`
+//! file of its directory (see [`LOCK_FILE_NAME`](crate::LOCK_FILE_NAME)),
+//! so a second writer fails with [`Error::AlreadyExists`]. Any number of
+//! other processes can open the same directory with
+//! [`DB::open_as_secondary`]. Like [`DB::open_read_only`], a secondary
+//! never changes the directory and rejects writes; it does not take the
+//! lock.
+//!
+//! A secondary sees the data of the primary as of its last catch-up, which
+//! reads the primary's current WAL into a new MemTable, replays its
+//! MANIFEST and opens the SSTables added since, keeping the ones it
+//! already has open. Reads catch up first once
+//! [`Options::secondary_refresh_interval_ms`](crate::Options::secondary_refresh_interval_ms)
+//! has passed since the last catch-up; [`DB::try_catch_up_with_primary`]
+//! catches up right away.
+//!
+//! Writes are visible to secondaries once the primary has written them to
+//! its WAL file: with [`Options::sync_wal`](crate::Options::sync_wal) set,
+//! when they return, otherwise when the WAL buffer is written out.
+//! SSTables the primary deletes after a compaction stay readable through
+//! the files a secondary has open, but a secondary with
+//! [`Options::max_open_files`](crate::Options::max_open_files) set may
+//! have to catch up before it can read again.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::{Options, DB};
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! // In the analytics process, while the writer keeps running
+//! let options = Options::default().secondary_refresh_interval_ms(500);
+//! let db = DB::open_as_secondary("./data", options)?;
+//! let value = db.get(b"key")?;
+//! db.try_catch_up_with_primary()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::compaction::VersionSet;
+use crate::error::{Error, Result};
+use crate::memtable::MemTable;
+use crate::sstable::SSTableReader;
+use crate::util::Instant;
+use crate::wal::{self, WAL};
+use crate::{sort_level_by_key, OpenMode, Options, DB};
+
+/// Attempts at reading a consistent state when the primary removes files
+/// meanwhile
+const CATCH_UP_ATTEMPTS: usize = 5;
+
+/// When a secondary catches up
+pub(crate) struct CatchUp {
+    /// Reads catch up once this has passed, if set
+    interval: Option<Duration>,
+    /// Time of the last catch-up; held while catching up
+    last: Mutex<Option<Instant>>,
+}
+
+impl CatchUp {
+    pub(crate) fn new(interval_ms: u64) -> Self {
+        Self {
+            interval: (interval_ms > 0).then(|| Duration::from_millis(interval_ms)),
+            last: Mutex::new(None),
+        }
+    }
+}
+
+impl DB {
+    /// Opens a database that another process may be writing to, for
+    /// reading only.
+    ///
+    /// See the [module documentation](crate::secondary). Returns
+    /// [`Error::NotFound`] if the directory does not exist.
+    pub fn open_as_secondary<P: AsRef<Path>>(path: P, options: Options) -> Result<Self> {
+        let db = Self::open_with_mode(path.as_ref(), options, OpenMode::Secondary)?;
+        db.try_catch_up_with_primary()?;
+        Ok(db)
+    }
+
+    /// Loads what the primary has written since the last catch-up.
+    ///
+    /// Returns [`Error::InvalidState`] if the database was not opened with
+    /// [`DB::open_as_secondary`].
+    pub fn try_catch_up_with_primary(&self) -> Result<()> {
+        let Some(ref catch_up) = self.secondary else {
+            return Err(Error::InvalidState(
+                "only a secondary instance catches up with the primary".to_string(),
+            ));
+        };
+        let mut last = catch_up.last.lock();
+        self.catch_up()?;
+        *last = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Catches up if this is a secondary whose refresh interval has passed.
+    /// Called before reads.
+    pub(crate) fn maybe_catch_up(&self) -> Result<()> {
+        let Some(ref catch_up) = self.secondary else {
+            return Ok(());
+        };
+        let Some(interval) = catch_up.interval else {
+            return Ok(());
+        };
+        // Another read is already catching up
+        let Some(mut last) = catch_up.last.try_lock() else {
+            return Ok(());
+        };
+        if last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        self.catch_up()?;
+        *last = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Reloads the state of the primary, starting over if it removed a
+    /// file that was about to be read
+    fn catch_up(&self) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            match self.load_primary_state() {
+                Err(Error::Io(e))
+                    if e.kind() == std::io::ErrorKind::NotFound && attempt < CATCH_UP_ATTEMPTS =>
+                {
+                    log::debug!("Catching up with primary again: {}", e);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn load_primary_state(&self) -> Result<()> {
+        let options = self.options.read().clone();
+        let env = &*self.env;
+
+        // The manifest is read first, so WAL records discarded by a
+        // `drop_all` are skipped. The WAL is read before the SSTables are
+        // listed: if the primary flushes meanwhile, its writes are in an
+        // SSTable listed below.
+        let mut version_set =
+            VersionSet::open_read_only(&self.path, options.max_levels, Arc::clone(&self.env))?;
+        let manifest_exists = env.file_exists(&self.path.join("MANIFEST"));
+        Self::check_comparator(&mut version_set, &*self.comparator, manifest_exists, true)?;
+        let dropped_sequence = version_set.dropped_sequence();
+        let mut wal_files: Vec<(u64, PathBuf)> = env
+            .get_children(&self.path)?
+            .iter()
+            .filter_map(|name| Some((wal::parse_wal_filename(name)?, self.path.join(name))))
+            .collect();
+        wal_files.sort();
+        let (wal_number, wal_path) = wal_files
+            .last()
+            .cloned()
+            .unwrap_or_else(|| (1, self.path.join(wal::wal_filename(1))));
+        let wal = WAL::open_read_only(&wal_path, env)?;
+        let memtable = MemTable::with_comparator(1, Arc::clone(&self.comparator));
+        let wal_paths: Vec<PathBuf> = wal_files.into_iter().map(|(_, path)| path).collect();
+        let sequence = Self::replay_wal_files(
+            env,
+            &wal_paths,
+            &memtable,
+            dropped_sequence,
+            options.wal_recovery_threads,
+            &self.record_auth,
+        )?;
+
+        // SSTables are placed as in `DB::open`: files compacted by the
+        // primary at the level recorded in the manifest, flushed ones in
+        // Level 0, newest first
+        let sequence = sequence.max(version_set.last_sequence());
+        let file_levels: HashMap<u64, usize> = version_set
+            .current()
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, files)| files.iter().map(move |f| (f.file_number, level)))
+            .collect();
+        let open_tables: HashMap<u64, Arc<SSTableReader>> = self
+            .sstables
+            .read()
+            .iter()
+            .flatten()
+            .filter_map(|table| Some((table.file_number()?, Arc::clone(table))))
+            .collect();
+
+        let mut numbers: Vec<u64> = env
+            .get_children(&self.path)?
+            .iter()
+            .filter_map(|name| name.strip_suffix(".sst")?.parse().ok())
+            .filter(|number| !version_set.deleted_files().contains(number))
+            .collect();
+        numbers.sort_unstable();
+        let mut sstables = vec![Vec::new(); options.max_levels];
+        for number in numbers.into_iter().rev() {
+            let table = match open_tables.get(&number) {
+                Some(table) => Arc::clone(table),
+                None => self.open_primary_table(number)?,
+            };
+            let level = file_levels.get(&number).copied().filter(|&l| l < sstables.len());
+            sstables[level.unwrap_or(0)].push(table);
+        }
+        for level in sstables.iter_mut().skip(1) {
+            sort_level_by_key(level);
+        }
+        self.prefix_tombstones.reload(env, &self.path)?;
+
+        *self.sstables.write() = sstables;
+        *self.memtable.write() = memtable;
+        self.sequence.fetch_max(sequence, Ordering::SeqCst);
+        *self.version_set.write() = version_set;
+        *self.wal.write() = wal;
+        self.wal_file_number.store(wal_number, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Opens an SSTable the primary wrote
+    fn open_primary_table(&self, number: u64) -> Result<Arc<SSTableReader>> {
+        let path = self.path.join(format!("{:06}.sst", number));
+        let reader =
+            SSTableReader::open_with_env(&path, Some(Arc::clone(&self.block_cache)), &*self.env)?;
+        Self::check_sstable_compression(&reader)?;
+        let reader = reader
+            .with_record_authenticator(self.record_auth.clone())?
+            .with_comparator(Arc::clone(&self.comparator));
+        Ok(Arc::new(self.attach_table_cache(reader)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_secondary_catches_up_with_primary() {
+        let temp_dir = TempDir::new().unwrap();
+        let primary = DB::open(temp_dir.path(), Options::default()).unwrap();
+        primary.put(b"a", b"1").unwrap();
+        primary.flush().unwrap();
+        primary.put(b"b", b"2").unwrap();
+
+        // A second writer is refused, a secondary is not
+        assert!(matches!(
+            DB::open(temp_dir.path(), Options::default()),
+            Err(Error::AlreadyExists(_))
+        ));
+        let options = Options::default().secondary_refresh_interval_ms(0);
+        let secondary = DB::open_as_secondary(temp_dir.path(), options).unwrap();
+        assert_eq!(secondary.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(secondary.get(b"b").unwrap(), Some(b"2".to_vec()));
+        assert!(matches!(secondary.put(b"c", b"3"), Err(Error::InvalidState(_))));
+
+        // Nothing changes until the secondary catches up
+        primary.put(b"c", b"3").unwrap();
+        primary.delete(b"a").unwrap();
+        primary.flush().unwrap();
+        primary.put(b"d", b"4").unwrap();
+        assert_eq!(secondary.get(b"c").unwrap(), None);
+        secondary.try_catch_up_with_primary().unwrap();
+        assert_eq!(secondary.get(b"a").unwrap(), None);
+        assert_eq!(secondary.get(b"c").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(secondary.get(b"d").unwrap(), Some(b"4".to_vec()));
+
+        // After a compaction the inputs are gone and the output is read
+        for i in 0..4u8 {
+            primary.put(&[b'k', i], b"v").unwrap();
+            primary.flush().unwrap();
+        }
+        primary.maybe_trigger_compaction().unwrap();
+        secondary.try_catch_up_with_primary().unwrap();
+        assert_eq!(secondary.level_sizes(), primary.level_sizes());
+        assert_eq!(secondary.get(&[b'k', 3]).unwrap(), Some(b"v".to_vec()));
+
+        // Once the primary is closed another writer can open the database
+        drop(primary);
+        assert!(DB::open(temp_dir.path(), Options::default()).is_ok());
+        assert!(matches!(
+            DB::open(temp_dir.path(), Options::default())
+                .unwrap()
+                .try_catch_up_with_primary(),
+            Err(Error::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    fn test_secondary_catches_up_on_read_after_interval() {
+        let temp_dir = TempDir::new().unwrap();
+        let primary = DB::open(temp_dir.path(), Options::default()).unwrap();
+        let options = Options::default().secondary_refresh_interval_ms(20);
+        let secondary = Arc::new(DB::open_as_secondary(temp_dir.path(), options).unwrap());
+
+        primary.put(b"key", b"value").unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(secondary.get(b"key").unwrap(), Some(b"value".to_vec()));
+        primary.put(b"key2", b"value").unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        let mut iter = secondary.iter();
+        let mut keys = 0;
+        while iter.valid() {
+            keys += 1;
+            iter.next();
+        }
+        assert_eq!(keys, 2);
+    }
+}
@@ -0,0 +1,82 @@
+//! When sequence numbers were written, for
+//! [`Options::preserve_versions_seconds`](crate::Options::preserve_versions_seconds).
+//!
+//! Writes record samples of the newest sequence number and the time. Flushes
+//! and compactions look up the sequence number that was newest at the start
+//! of the retention window and keep every version a read at it or later can
+//! see. The samples are only kept in memory: after reopening, the versions
+//! written before are kept for a whole window.
+
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+
+/// Most samples kept for a window; longer windows are sampled less often
+const MAX_SAMPLES: u64 = 1000;
+
+/// Shortest time between samples
+const MIN_SAMPLE_INTERVAL_MICROS: u64 = 1_000_000;
+
+/// Samples of the newest sequence number over time
+#[derive(Debug, Default)]
+pub(crate) struct SequenceTimes {
+    /// `(sequence, micros)` pairs, both increasing
+    samples: Mutex<VecDeque<(u64, u64)>>,
+}
+
+impl SequenceTimes {
+    /// Records that `sequence` was the newest sequence number at
+    /// `now_micros`, for a retention window of `window_micros`
+    pub(crate) fn record(&self, sequence: u64, now_micros: u64, window_micros: u64) {
+        let interval = (window_micros / MAX_SAMPLES).max(MIN_SAMPLE_INTERVAL_MICROS);
+        let mut samples = self.samples.lock();
+        if samples
+            .back()
+            .is_some_and(|&(_, time)| now_micros < time.saturating_add(interval))
+        {
+            return;
+        }
+        samples.push_back((sequence, now_micros));
+        // Keep the newest sample from before the window
+        let start = now_micros.saturating_sub(window_micros);
+        while samples.get(1).is_some_and(|&(_, time)| time <= start) {
+            samples.pop_front();
+        }
+    }
+
+    /// The newest sequence number at `micros`, or `None` if no sample is
+    /// that old
+    pub(crate) fn sequence_at(&self, micros: u64) -> Option<u64> {
+        let samples = self.samples.lock();
+        let newer = samples.partition_point(|&(_, time)| time <= micros);
+        samples.get(newer.checked_sub(1)?).map(|&(sequence, _)| sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_at() {
+        let times = SequenceTimes::default();
+        let second = 1_000_000;
+        let window = 100 * second;
+        assert_eq!(times.sequence_at(u64::MAX), None);
+
+        times.record(10, 5 * second, window);
+        // Within the same second
+        times.record(11, 5 * second + 10, window);
+        times.record(20, 7 * second, window);
+        times.record(30, 9 * second, window);
+
+        assert_eq!(times.sequence_at(4 * second), None);
+        assert_eq!(times.sequence_at(6 * second), Some(10));
+        assert_eq!(times.sequence_at(7 * second), Some(20));
+        assert_eq!(times.sequence_at(100 * second), Some(30));
+
+        // Samples from before the window are dropped, except the newest
+        times.record(40, 108 * second, window);
+        assert_eq!(times.sequence_at(6 * second), None);
+        assert_eq!(times.sequence_at(8 * second), Some(20));
+    }
+}
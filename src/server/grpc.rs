@@ -0,0 +1,344 @@
+//! gRPC service (requires the `server-grpc` feature).
+//!
+//! [`GrpcService`] implements the `aidb.v1.AiDb` service defined in
+//! `proto/aidb.proto` with [tonic](https://docs.rs/tonic). The generated
+//! message types and the typed client (`proto::ai_db_client::AiDbClient`)
+//! are in [`proto`].
+//!
+//! Database calls block, so every request runs on tokio's blocking thread
+//! pool. `ScanStream` sends entries as the scan produces them, while `Scan`
+//! returns one page and the key to continue from.
+//!
+//! AiDb has no scripting engine yet, so `ExecuteScript` always returns
+//! `UNIMPLEMENTED`.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::server::grpc::GrpcService;
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), aidb::Error> {
+//!     let db = Arc::new(DB::open("./data", Options::default())?);
+//!     // Serve until the process is stopped
+//!     let shutdown = std::future::pending();
+//!     GrpcService::new(db).serve("127.0.0.1:50051".parse().unwrap(), shutdown).await
+//! }
+//! ```
+
+use crate::error::{Error, Result};
+use crate::{WriteBatch, DB};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+/// Types generated from `proto/aidb.proto`.
+#[allow(missing_docs, clippy::all)]
+pub mod proto {
+    tonic::include_proto!("aidb.v1");
+}
+
+use proto::ai_db_server::{AiDb, AiDbServer};
+use proto::mutation::Op;
+use proto::*;
+
+/// Entries buffered between a streaming scan and the client
+const SCAN_STREAM_BUFFER: usize = 256;
+
+/// Serves a database over gRPC.
+#[derive(Clone)]
+pub struct GrpcService {
+    db: Arc<DB>,
+}
+
+impl GrpcService {
+    /// Creates a service for `db`.
+    pub fn new(db: Arc<DB>) -> Self {
+        Self { db }
+    }
+
+    /// Wraps the service for use with a custom [`tonic::transport::Server`]
+    /// (for example to add TLS or other services).
+    pub fn into_server(self) -> AiDbServer<Self> {
+        AiDbServer::new(self)
+    }
+
+    /// Serves on `addr` until `shutdown` completes.
+    pub async fn serve(self, addr: SocketAddr, shutdown: impl Future<Output = ()>) -> Result<()> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_server())
+            .serve_with_shutdown(addr, shutdown)
+            .await
+            .map_err(|e| Error::internal(format!("gRPC server failed: {}", e)))
+    }
+
+    /// Runs a blocking database call on the blocking thread pool.
+    async fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Arc<DB>) -> Result<T> + Send + 'static,
+    ) -> std::result::Result<T, Status> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(to_status)
+    }
+}
+
+#[tonic::async_trait]
+impl AiDb for GrpcService {
+    async fn put(
+        &self,
+        request: Request<PutRequest>,
+    ) -> std::result::Result<Response<PutResponse>, Status> {
+        let PutRequest { key, value } = request.into_inner();
+        self.run(move |db| db.put(&key, &value)).await?;
+        Ok(Response::new(PutResponse {}))
+    }
+
+    async fn get(
+        &self,
+        request: Request<GetRequest>,
+    ) -> std::result::Result<Response<GetResponse>, Status> {
+        let key = request.into_inner().key;
+        let value = self.run(move |db| db.get(&key)).await?;
+        Ok(Response::new(get_response(value)))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> std::result::Result<Response<DeleteResponse>, Status> {
+        let key = request.into_inner().key;
+        self.run(move |db| db.delete(&key)).await?;
+        Ok(Response::new(DeleteResponse {}))
+    }
+
+    async fn multi_get(
+        &self,
+        request: Request<MultiGetRequest>,
+    ) -> std::result::Result<Response<MultiGetResponse>, Status> {
+        let keys = request.into_inner().keys;
+        let values = self
+            .run(move |db| {
+                // Read every key at the same point in time
+                let snapshot = db.snapshot();
+                keys.iter().map(|key| Ok(get_response(snapshot.get(key)?))).collect()
+            })
+            .await?;
+        Ok(Response::new(MultiGetResponse { values }))
+    }
+
+    async fn scan(
+        &self,
+        request: Request<ScanRequest>,
+    ) -> std::result::Result<Response<ScanResponse>, Status> {
+        let ScanRequest { start, end, limit } = request.into_inner();
+        let response = self
+            .run(move |db| {
+                let mut iter = db.scan(start.as_deref(), end.as_deref())?;
+                let mut entries = Vec::new();
+                while iter.valid() && (limit == 0 || entries.len() < limit as usize) {
+                    entries
+                        .push(KeyValue { key: iter.key().to_vec(), value: iter.value().to_vec() });
+                    iter.next();
+                }
+                let next_start = iter.valid().then(|| iter.key().to_vec());
+                Ok(ScanResponse { entries, next_start })
+            })
+            .await?;
+        Ok(Response::new(response))
+    }
+
+    type ScanStreamStream = ReceiverStream<std::result::Result<KeyValue, Status>>;
+
+    async fn scan_stream(
+        &self,
+        request: Request<ScanRequest>,
+    ) -> std::result::Result<Response<Self::ScanStreamStream>, Status> {
+        let ScanRequest { start, end, limit } = request.into_inner();
+        let db = Arc::clone(&self.db);
+        let (tx, rx) = mpsc::channel(SCAN_STREAM_BUFFER);
+
+        tokio::task::spawn_blocking(move || {
+            let mut iter = match db.scan(start.as_deref(), end.as_deref()) {
+                Ok(iter) => iter,
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(to_status(e)));
+                    return;
+                }
+            };
+            let mut sent = 0;
+            while iter.valid() && (limit == 0 || sent < limit) {
+                let entry = KeyValue { key: iter.key().to_vec(), value: iter.value().to_vec() };
+                // The client went away
+                if tx.blocking_send(Ok(entry)).is_err() {
+                    return;
+                }
+                sent += 1;
+                iter.next();
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn write_batch(
+        &self,
+        request: Request<WriteBatchRequest>,
+    ) -> std::result::Result<Response<WriteBatchResponse>, Status> {
+        let mut batch = WriteBatch::new();
+        for mutation in request.into_inner().mutations {
+            match mutation.op {
+                Some(Op::Put(KeyValue { key, value })) => batch.put(&key, &value),
+                Some(Op::Delete(key)) => batch.delete(&key),
+                None => return Err(Status::invalid_argument("Mutation without an operation")),
+            }
+        }
+        self.run(move |db| db.write(batch)).await?;
+        Ok(Response::new(WriteBatchResponse {}))
+    }
+
+    async fn execute_script(
+        &self,
+        _request: Request<ExecuteScriptRequest>,
+    ) -> std::result::Result<Response<ExecuteScriptResponse>, Status> {
+        Err(Status::unimplemented("Scripting is not available"))
+    }
+}
+
+fn get_response(value: Option<Vec<u8>>) -> GetResponse {
+    GetResponse { found: value.is_some(), value: value.unwrap_or_default() }
+}
+
+/// Maps a database error to the closest gRPC status.
+fn to_status(e: Error) -> Status {
+    let message = e.to_string();
+    match e {
+        Error::InvalidArgument(_) | Error::InvalidConfig(_) => Status::invalid_argument(message),
+        Error::NotFound(_) => Status::not_found(message),
+        Error::AlreadyExists(_) => Status::already_exists(message),
+        Error::NotImplemented(_) => Status::unimplemented(message),
+        Error::BackgroundError(_) | Error::InvalidState(_) => Status::failed_precondition(message),
+        Error::NoSpace(_) => Status::resource_exhausted(message),
+        Error::Corruption(_) | Error::ChecksumMismatch { .. } | Error::AuthenticationFailed(_) => {
+            Status::data_loss(message)
+        }
+        Error::Io(_) | Error::Serialization(_) | Error::Internal(_) => Status::internal(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use proto::ai_db_client::AiDbClient;
+    use tempfile::TempDir;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_service() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(GrpcService::new(Arc::clone(&db)).into_server())
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                    let _ = shutdown_rx.await;
+                }),
+        );
+
+        let mut client = AiDbClient::connect(format!("http://{}", addr)).await.unwrap();
+
+        client
+            .put(PutRequest { key: b"a".to_vec(), value: b"1".to_vec() })
+            .await
+            .unwrap();
+        let response = client.get(GetRequest { key: b"a".to_vec() }).await.unwrap().into_inner();
+        assert!(response.found);
+        assert_eq!(response.value, b"1");
+        assert!(!client.get(GetRequest { key: b"zz".to_vec() }).await.unwrap().into_inner().found);
+
+        let mutations = ["b", "c", "d", "e"]
+            .iter()
+            .map(|key| Mutation {
+                op: Some(Op::Put(KeyValue { key: key.as_bytes().to_vec(), value: b"v".to_vec() })),
+            })
+            .chain([Mutation { op: Some(Op::Delete(b"a".to_vec())) }])
+            .collect();
+        client.write_batch(WriteBatchRequest { mutations }).await.unwrap();
+        assert_eq!(db.get(b"a").unwrap(), None);
+        assert_eq!(db.get(b"e").unwrap(), Some(b"v".to_vec()));
+
+        let values = client
+            .multi_get(MultiGetRequest { keys: vec![b"b".to_vec(), b"a".to_vec()] })
+            .await
+            .unwrap()
+            .into_inner()
+            .values;
+        assert_eq!(values.iter().map(|v| v.found).collect::<Vec<_>>(), vec![true, false]);
+
+        // Paged scan over [b, e)
+        let page = client
+            .scan(ScanRequest { start: Some(b"b".to_vec()), end: Some(b"e".to_vec()), limit: 2 })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(page.entries.iter().map(|e| e.key.as_slice()).collect::<Vec<_>>(), [b"b", b"c"]);
+        assert_eq!(page.next_start.as_deref(), Some(&b"d"[..]));
+        let page = client
+            .scan(ScanRequest { start: page.next_start, end: Some(b"e".to_vec()), limit: 2 })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.next_start, None);
+
+        let keys: Vec<Vec<u8>> = client
+            .scan_stream(ScanRequest { start: None, end: None, limit: 0 })
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|entry| entry.unwrap().key)
+            .collect()
+            .await;
+        assert_eq!(keys, [b"b", b"c", b"d", b"e"]);
+
+        let status = client
+            .execute_script(ExecuteScriptRequest {
+                script: "return 1".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unimplemented);
+
+        let status = client
+            .write_batch(WriteBatchRequest { mutations: vec![Mutation { op: None }] })
+            .await;
+        assert_eq!(status.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_to_status() {
+        assert_eq!(to_status(Error::invalid_argument("bad")).code(), tonic::Code::InvalidArgument);
+        assert_eq!(
+            to_status(Error::BackgroundError("flush failed".into())).code(),
+            tonic::Code::FailedPrecondition
+        );
+        assert_eq!(to_status(Error::corruption("bad block")).code(), tonic::Code::DataLoss);
+    }
+}
@@ -0,0 +1,11 @@
+//! Network servers exposing a database to remote clients.
+//!
+//! Each protocol is behind its own feature:
+//!
+//! - `server-resp`: [`resp::RespServer`] speaks the Redis protocol
+//! - `server-grpc`: [`grpc::GrpcService`] is a tonic gRPC service
+
+#[cfg(feature = "server-grpc")]
+pub mod grpc;
+#[cfg(feature = "server-resp")]
+pub mod resp;
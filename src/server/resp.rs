@@ -0,0 +1,628 @@
+//! Redis protocol (RESP2) server (requires the `server-resp` feature).
+//!
+//! [`RespServer`] serves a database over the Redis wire protocol, so
+//! `redis-cli` and Redis client libraries can talk to AiDb directly. Each
+//! connection is handled on its own thread. Supported commands:
+//!
+//! | Command | Behavior |
+//! |---------|----------|
+//! | `PING [message]` | `PONG` or the message |
+//! | `ECHO message` | The message |
+//! | `GET key` | The value, or nil |
+//! | `SET key value` | `OK` |
+//! | `DEL key [key ...]` | Number of keys that existed |
+//! | `EXISTS key [key ...]` | Number of keys that exist |
+//! | `SCAN cursor [MATCH pattern] [COUNT count]` | Next cursor and keys |
+//! | `QUIT` | Closes the connection |
+//!
+//! `SCAN` cursors are only valid on the connection that created them. Keys
+//! that exist for the whole scan are returned exactly once.
+//!
+//! AiDb has neither per-key expiration nor scripting, so `EXPIRE`, `EVAL`
+//! and `SET` with `EX`/`PX` options return an error.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::server::resp::RespServer;
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = Arc::new(DB::open("./data", Options::default())?);
+//! let server = RespServer::bind(db, "127.0.0.1:6379")?;
+//! server.serve()?; // redis-cli -p 6379 SET greeting hello
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::{Error, Result};
+use crate::DB;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Longest bulk string accepted from a client
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// Most arguments accepted in one command
+const MAX_ARRAY_LEN: usize = 1024 * 1024;
+
+/// Longest line (inline command or length header) accepted from a client
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Keys examined by `SCAN` when no `COUNT` is given
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// Open `SCAN` cursors kept per connection
+const MAX_OPEN_CURSORS: usize = 1024;
+
+/// Serves a database over the Redis protocol.
+pub struct RespServer {
+    db: Arc<DB>,
+    listener: TcpListener,
+    shutdown: Arc<AtomicBool>,
+}
+
+/// Stops a running [`RespServer`] from another thread.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Stops accepting connections. Open connections are served until the
+    /// client closes them.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the accept loop
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+impl RespServer {
+    /// Listens on `addr` (use port 0 to pick a free port).
+    pub fn bind(db: Arc<DB>, addr: impl ToSocketAddrs) -> Result<Self> {
+        Ok(Self {
+            db,
+            listener: TcpListener::bind(addr)?,
+            shutdown: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Returns a handle that stops [`serve`](Self::serve).
+    pub fn shutdown_handle(&self) -> Result<ShutdownHandle> {
+        Ok(ShutdownHandle { addr: self.local_addr()?, shutdown: Arc::clone(&self.shutdown) })
+    }
+
+    /// Accepts connections until the server is shut down, serving each on
+    /// its own thread.
+    pub fn serve(self) -> Result<()> {
+        for stream in self.listener.incoming() {
+            if self.shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("Failed to accept RESP connection: {}", e);
+                    continue;
+                }
+            };
+
+            let db = Arc::clone(&self.db);
+            std::thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(e) = Connection::new(db).run(stream) {
+                    log::debug!("RESP connection from {:?} closed: {}", peer, e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A reply to a command
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Nil,
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn error(message: impl Into<String>) -> Self {
+        Reply::Error(format!("ERR {}", message.into()))
+    }
+
+    fn wrong_arity(command: &str) -> Self {
+        Reply::error(format!("wrong number of arguments for '{}' command", command))
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Reply::Simple(s) => write!(out, "+{}\r\n", s),
+            // Error messages must not span lines
+            Reply::Error(message) => write!(out, "-{}\r\n", message.replace(['\r', '\n'], " ")),
+            Reply::Integer(n) => write!(out, ":{}\r\n", n),
+            Reply::Bulk(data) => {
+                write!(out, "${}\r\n", data.len())?;
+                out.write_all(data)?;
+                out.write_all(b"\r\n")
+            }
+            Reply::Nil => out.write_all(b"$-1\r\n"),
+            Reply::Array(items) => {
+                write!(out, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.write_to(out))
+            }
+        }
+    }
+}
+
+impl From<Error> for Reply {
+    fn from(e: Error) -> Self {
+        Reply::error(e.to_string())
+    }
+}
+
+/// State of one client connection
+struct Connection {
+    db: Arc<DB>,
+    /// Last key returned by each open `SCAN` cursor
+    scan_cursors: HashMap<u64, Vec<u8>>,
+    next_cursor: u64,
+}
+
+impl Connection {
+    fn new(db: Arc<DB>) -> Self {
+        Self { db, scan_cursors: HashMap::new(), next_cursor: 1 }
+    }
+
+    fn run(mut self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+
+        loop {
+            let args = match read_command(&mut reader) {
+                Ok(Some(args)) => args,
+                Ok(None) => break,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    // Framing is lost; report the error and hang up
+                    Reply::error(format!("Protocol error: {}", e)).write_to(&mut writer)?;
+                    writer.flush()?;
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            let quit = args.first().is_some_and(|command| command.eq_ignore_ascii_case(b"QUIT"));
+            if quit {
+                Reply::Simple("OK").write_to(&mut writer)?;
+            } else if !args.is_empty() {
+                self.execute(&args).write_to(&mut writer)?;
+            }
+            // Pipelined commands are answered together
+            if reader.buffer().is_empty() || quit {
+                writer.flush()?;
+            }
+            if quit {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, args: &[Vec<u8>]) -> Reply {
+        let command = String::from_utf8_lossy(&args[0]).to_ascii_uppercase();
+        let args = &args[1..];
+        let result = match command.as_str() {
+            "PING" => match args {
+                [] => Ok(Reply::Simple("PONG")),
+                [message] => Ok(Reply::Bulk(message.clone())),
+                _ => Ok(Reply::wrong_arity("ping")),
+            },
+            "ECHO" => match args {
+                [message] => Ok(Reply::Bulk(message.clone())),
+                _ => Ok(Reply::wrong_arity("echo")),
+            },
+            "GET" => match args {
+                [key] => self.db.get(key).map(|value| value.map_or(Reply::Nil, Reply::Bulk)),
+                _ => Ok(Reply::wrong_arity("get")),
+            },
+            "SET" => self.set(args),
+            "DEL" => self.count_existing(args, "del", |db, key| db.delete(key)),
+            "EXISTS" => self.count_existing(args, "exists", |_, _| Ok(())),
+            "SCAN" => self.scan(args),
+            // redis-cli asks for the command table on startup
+            "COMMAND" => Ok(Reply::Array(Vec::new())),
+            "EXPIRE" | "PEXPIRE" | "EXPIREAT" | "PEXPIREAT" | "TTL" | "PTTL" | "PERSIST" => {
+                Ok(Reply::error(format!("{} is not supported: keys do not expire", command)))
+            }
+            "EVAL" | "EVALSHA" | "SCRIPT" => Ok(Reply::error(format!(
+                "{} is not supported: scripting is not available",
+                command
+            ))),
+            _ => Ok(Reply::error(format!("unknown command '{}'", command))),
+        };
+        result.unwrap_or_else(Reply::from)
+    }
+
+    fn set(&self, args: &[Vec<u8>]) -> Result<Reply> {
+        match args {
+            [key, value] => {
+                self.db.put(key, value)?;
+                Ok(Reply::Simple("OK"))
+            }
+            [_, _, ..] => Ok(Reply::error("SET options are not supported: keys do not expire")),
+            _ => Ok(Reply::wrong_arity("set")),
+        }
+    }
+
+    /// Counts the keys in `args` that exist, calling `action` on each of them.
+    fn count_existing(
+        &self,
+        args: &[Vec<u8>],
+        command: &str,
+        action: impl Fn(&DB, &[u8]) -> Result<()>,
+    ) -> Result<Reply> {
+        if args.is_empty() {
+            return Ok(Reply::wrong_arity(command));
+        }
+        let mut count = 0;
+        for key in args {
+            if self.db.get(key)?.is_some() {
+                action(&self.db, key)?;
+                count += 1;
+            }
+        }
+        Ok(Reply::Integer(count))
+    }
+
+    fn scan(&mut self, args: &[Vec<u8>]) -> Result<Reply> {
+        let Some((cursor, options)) = args.split_first() else {
+            return Ok(Reply::wrong_arity("scan"));
+        };
+        let Some(cursor) = std::str::from_utf8(cursor).ok().and_then(|c| c.parse::<u64>().ok())
+        else {
+            return Ok(Reply::error("invalid cursor"));
+        };
+
+        let mut pattern = None;
+        let mut count = DEFAULT_SCAN_COUNT;
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            let Some(value) = options.next() else {
+                return Ok(Reply::error("syntax error"));
+            };
+            if option.eq_ignore_ascii_case(b"MATCH") {
+                pattern = Some(value.as_slice());
+            } else if option.eq_ignore_ascii_case(b"COUNT") {
+                match std::str::from_utf8(value).ok().and_then(|v| v.parse::<usize>().ok()) {
+                    Some(n) if n > 0 => count = n,
+                    _ => return Ok(Reply::error("value is not an integer or out of range")),
+                }
+            } else {
+                return Ok(Reply::error("syntax error"));
+            }
+        }
+
+        // Resume after the last key returned for this cursor
+        let start = if cursor == 0 {
+            None
+        } else {
+            match self.scan_cursors.remove(&cursor) {
+                Some(mut last_key) => {
+                    last_key.push(0);
+                    Some(last_key)
+                }
+                None => return Ok(Reply::error("invalid cursor")),
+            }
+        };
+
+        let mut iter = self.db.scan(start.as_deref(), None)?;
+        let mut keys = Vec::new();
+        let mut last_key = None;
+        for _ in 0..count {
+            if !iter.valid() {
+                break;
+            }
+            let key = iter.key();
+            if pattern.is_none_or(|pattern| glob_match(pattern, key)) {
+                keys.push(Reply::Bulk(key.to_vec()));
+            }
+            last_key = Some(key.to_vec());
+            iter.next();
+        }
+
+        let next_cursor = match last_key {
+            Some(last_key) if iter.valid() => {
+                if self.scan_cursors.len() >= MAX_OPEN_CURSORS {
+                    // Forget the oldest cursor of a client that never finished its scan
+                    let oldest = self.scan_cursors.keys().min().copied();
+                    oldest.map(|id| self.scan_cursors.remove(&id));
+                }
+                let id = self.next_cursor;
+                self.next_cursor += 1;
+                self.scan_cursors.insert(id, last_key);
+                id
+            }
+            _ => 0,
+        };
+        Ok(Reply::Array(vec![
+            Reply::Bulk(next_cursor.to_string().into_bytes()),
+            Reply::Array(keys),
+        ]))
+    }
+}
+
+/// Reads one command, either a RESP array of bulk strings or an inline
+/// command (space-separated words, as typed into telnet).
+///
+/// Returns `None` when the client closed the connection. Malformed input is
+/// reported as an `InvalidData` error.
+fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = read_line(reader)? else {
+        return Ok(None);
+    };
+
+    let Some(len) = line.strip_prefix(b"*") else {
+        return Ok(Some(
+            line.split(u8::is_ascii_whitespace)
+                .filter(|word| !word.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect(),
+        ));
+    };
+
+    let len = parse_len(len, MAX_ARRAY_LEN)?;
+    let mut args = Vec::with_capacity(len.min(64));
+    for _ in 0..len {
+        let line = read_line(reader)?.ok_or_else(|| protocol_error("unexpected end of stream"))?;
+        let Some(len) = line.strip_prefix(b"$") else {
+            return Err(protocol_error("expected '$'"));
+        };
+        let len = parse_len(len, MAX_BULK_LEN)?;
+        let mut data = vec![0; len + 2];
+        reader.read_exact(&mut data)?;
+        if !data.ends_with(b"\r\n") {
+            return Err(protocol_error("bulk string not terminated by CRLF"));
+        }
+        data.truncate(len);
+        args.push(data);
+    }
+    Ok(Some(args))
+}
+
+/// Reads a line without its trailing CRLF (or LF).
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE_LEN as u64).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(protocol_error("line too long or not terminated"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_len(digits: &[u8], max: usize) -> io::Result<usize> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse::<usize>().ok())
+        .filter(|&len| len <= max)
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Matches `key` against a Redis glob pattern (`*`, `?`, `[abc]`, `[^a-z]`
+/// and `\` escapes).
+fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => key.is_empty(),
+        Some((b'*', mut rest)) => {
+            while let [b'*', more @ ..] = rest {
+                rest = more;
+            }
+            (0..=key.len()).any(|i| glob_match(rest, &key[i..]))
+        }
+        Some((b'?', rest)) => !key.is_empty() && glob_match(rest, &key[1..]),
+        Some((b'[', rest)) => {
+            let Some((&c, key_rest)) = key.split_first() else {
+                return false;
+            };
+            match match_class(rest, c) {
+                Some((matched, rest)) => matched && glob_match(rest, key_rest),
+                // An unterminated class matches a literal '['
+                None => c == b'[' && glob_match(rest, key_rest),
+            }
+        }
+        Some((b'\\', [escaped, rest @ ..])) => {
+            key.first() == Some(escaped) && glob_match(rest, &key[1..])
+        }
+        Some((p, rest)) => key.first() == Some(p) && glob_match(rest, &key[1..]),
+    }
+}
+
+/// Matches `c` against a character class (the part after `[`), returning
+/// the result and the rest of the pattern, or `None` if the class has no `]`.
+fn match_class(mut class: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let negate = class.first() == Some(&b'^');
+    if negate {
+        class = &class[1..];
+    }
+    let mut matched = false;
+    loop {
+        match class {
+            [] => return None,
+            [b']', rest @ ..] => return Some((matched != negate, rest)),
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == c;
+                class = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (low, high) = if start <= end {
+                    (*start, *end)
+                } else {
+                    (*end, *start)
+                };
+                matched |= (low..=high).contains(&c);
+                class = rest;
+            }
+            [other, rest @ ..] => {
+                matched |= *other == c;
+                class = rest;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+
+    fn encode(reply: &Reply) -> String {
+        let mut out = Vec::new();
+        reply.write_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Sends a command as a RESP array and reads the raw reply
+    fn request(stream: &mut TcpStream, reader: &mut BufReader<TcpStream>, args: &[&str]) -> String {
+        let mut command = format!("*{}\r\n", args.len());
+        for arg in args {
+            command.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        stream.write_all(command.as_bytes()).unwrap();
+        read_reply(reader)
+    }
+
+    fn read_reply(reader: &mut BufReader<TcpStream>) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let count: i64 = line[1..].trim_end().parse().unwrap_or(0);
+        match line.as_bytes()[0] {
+            b'$' if count >= 0 => {
+                let mut data = vec![0; count as usize + 2];
+                reader.read_exact(&mut data).unwrap();
+                line + &String::from_utf8(data).unwrap()
+            }
+            b'*' => (0..count).fold(line, |reply, _| reply + &read_reply(reader)),
+            _ => line,
+        }
+    }
+
+    #[test]
+    fn test_read_command() {
+        let mut input = Cursor::new(b"*2\r\n$3\r\nGET\r\n$5\r\nk\r\ney\r\nPING  hi\n".to_vec());
+        assert_eq!(
+            read_command(&mut input).unwrap(),
+            Some(vec![b"GET".to_vec(), b"k\r\ney".to_vec()])
+        );
+        assert_eq!(read_command(&mut input).unwrap(), Some(vec![b"PING".to_vec(), b"hi".to_vec()]));
+        assert_eq!(read_command(&mut input).unwrap(), None);
+
+        for bad in [&b"*1\r\n:1\r\n"[..], b"*x\r\n", b"*1\r\n$3\r\nGETxx"] {
+            let error = read_command(&mut Cursor::new(bad.to_vec())).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_write_reply() {
+        assert_eq!(encode(&Reply::Simple("OK")), "+OK\r\n");
+        assert_eq!(encode(&Reply::error("bad\nthing")), "-ERR bad thing\r\n");
+        assert_eq!(encode(&Reply::Nil), "$-1\r\n");
+        assert_eq!(
+            encode(&Reply::Array(vec![Reply::Integer(2), Reply::Bulk(b"v".to_vec())])),
+            "*2\r\n:2\r\n$1\r\nv\r\n"
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"user:*", b"user:42"));
+        assert!(!glob_match(b"user:*", b"order:1"));
+        assert!(glob_match(b"h?llo", b"hallo"));
+        assert!(glob_match(b"h[ae]llo", b"hello"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"key[0-9]", b"key7"));
+        assert!(glob_match(b"a\\*b", b"a*b"));
+        assert!(!glob_match(b"a\\*b", b"axb"));
+        assert!(glob_match(b"[abc", b"[abc"));
+    }
+
+    #[test]
+    fn test_server() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        let server = RespServer::bind(Arc::clone(&db), "127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let shutdown = server.shutdown_handle().unwrap();
+        let handle = std::thread::spawn(move || server.serve());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        assert_eq!(request(&mut stream, &mut reader, &["PING"]), "+PONG\r\n");
+        assert_eq!(request(&mut stream, &mut reader, &["SET", "a", "1"]), "+OK\r\n");
+        assert_eq!(request(&mut stream, &mut reader, &["get", "a"]), "$1\r\n1\r\n");
+        assert_eq!(request(&mut stream, &mut reader, &["GET", "missing"]), "$-1\r\n");
+        assert_eq!(db.get(b"a").unwrap(), Some(b"1".to_vec()));
+
+        for key in ["b", "c", "d", "x"] {
+            db.put(key.as_bytes(), b"v").unwrap();
+        }
+        assert_eq!(request(&mut stream, &mut reader, &["EXISTS", "a", "b", "zz"]), ":2\r\n");
+        assert_eq!(request(&mut stream, &mut reader, &["DEL", "a", "zz"]), ":1\r\n");
+        assert_eq!(db.get(b"a").unwrap(), None);
+
+        // Two keys per call: b c | d x
+        let first = request(&mut stream, &mut reader, &["SCAN", "0", "COUNT", "2"]);
+        assert!(first.ends_with("*2\r\n$1\r\nb\r\n$1\r\nc\r\n"), "{}", first);
+        let cursor = first.lines().nth(2).unwrap().to_string();
+        assert_ne!(cursor, "0");
+        let second =
+            request(&mut stream, &mut reader, &["SCAN", &cursor, "COUNT", "2", "MATCH", "[a-d]"]);
+        assert_eq!(second, "*2\r\n$1\r\n0\r\n*1\r\n$1\r\nd\r\n");
+
+        let error = request(&mut stream, &mut reader, &["EXPIRE", "b", "10"]);
+        assert!(error.starts_with("-ERR EXPIRE is not supported"), "{}", error);
+        let error = request(&mut stream, &mut reader, &["EVAL", "return 1", "0"]);
+        assert!(error.starts_with("-ERR EVAL is not supported"), "{}", error);
+        assert!(
+            request(&mut stream, &mut reader, &["SET", "b", "1", "EX", "5"]).starts_with("-ERR")
+        );
+        assert!(request(&mut stream, &mut reader, &["GET"]).starts_with("-ERR wrong number"));
+        assert!(request(&mut stream, &mut reader, &["NOPE"]).starts_with("-ERR unknown command"));
+
+        // Pipelined inline commands
+        stream.write_all(b"PING\r\nECHO hi\r\n").unwrap();
+        assert_eq!(read_reply(&mut reader), "+PONG\r\n");
+        assert_eq!(read_reply(&mut reader), "$2\r\nhi\r\n");
+
+        assert_eq!(request(&mut stream, &mut reader, &["QUIT"]), "+OK\r\n");
+        shutdown.shutdown();
+        handle.join().unwrap().unwrap();
+    }
+}
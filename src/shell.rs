@@ -0,0 +1,454 @@
+//! Interactive shell for exploring a database (requires the `cli` feature).
+//!
+//! Started with `aidb shell <path>`:
+//!
+//! ```text
+//! aidb> put user:1 alice
+//! OK
+//! aidb> put 0x00ff "binary value\n"
+//! OK
+//! aidb> scan user: user;
+//! user:1  alice
+//! (1 entry)
+//! aidb> format hex
+//! aidb> get 0x00ff
+//! 0x62696e6172792076616c75650a
+//! ```
+//!
+//! Arguments are separated by spaces. Double quotes group words and, like
+//! unquoted arguments, accept the escapes `\n`, `\t`, `\\`, `\"` and `\xNN`.
+//! An argument starting with `0x` is hex and one starting with `base64:`
+//! is base64, so any binary key can be typed. Output uses the encoding
+//! selected with `format` (`text` escapes non-printable bytes).
+//!
+//! Command history is kept in `~/.aidb_history`. There is no scripting
+//! engine yet, so `lua` reports an error.
+
+use crate::error::{Error, Result};
+use crate::DB;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Number of entries `scan` prints unless `limit` is given
+pub const DEFAULT_SCAN_LIMIT: usize = 100;
+
+/// Keys wider than this are not used to align the value column
+const MAX_KEY_COLUMN: usize = 40;
+
+const HELP: &str = "\
+Commands:
+  get <key>                         Print the value of a key
+  put <key> <value>                 Store a value
+  delete <key>                      Delete a key
+  scan [<start> [<end>]] [limit N]  Print keys in [start, end) (default limit 100)
+  count [<start> [<end>]]           Count keys in [start, end)
+  flush                             Write the MemTable to an SSTable
+  compact                           Run a compaction if one is due
+  stats                             Show level sizes and memory usage
+  format [text|hex|base64]          Show or set the output encoding
+  lua <script>                      Run a Lua script (not available)
+  help                              Show this help
+  quit                              Leave the shell
+
+Arguments: \"quoted words\", escapes \\n \\t \\\\ \\\" \\xNN, 0x<hex>, base64:<data>";
+
+/// How keys and values are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Printable ASCII as is, other bytes as `\xNN`
+    #[default]
+    Text,
+    /// `0x` followed by lowercase hex digits
+    Hex,
+    /// `base64:` followed by standard base64
+    Base64,
+}
+
+impl Format {
+    /// Formats `bytes` in this encoding.
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Format::Text => bytes.escape_ascii().to_string(),
+            Format::Hex => {
+                let mut out = String::with_capacity(2 + bytes.len() * 2);
+                out.push_str("0x");
+                for byte in bytes {
+                    let _ = write!(out, "{:02x}", byte);
+                }
+                out
+            }
+            Format::Base64 => format!("base64:{}", BASE64.encode(bytes)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Hex => "hex",
+            Format::Base64 => "base64",
+        }
+    }
+}
+
+/// Executes shell commands against a database.
+pub struct Shell {
+    db: Arc<DB>,
+    format: Format,
+}
+
+impl Shell {
+    /// Creates a shell for `db` with text output.
+    pub fn new(db: Arc<DB>) -> Self {
+        Self { db, format: Format::Text }
+    }
+
+    /// Returns the output encoding.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Runs one command line and returns what it prints.
+    ///
+    /// Returns `None` for `quit`. Empty lines print nothing.
+    pub fn execute(&mut self, line: &str) -> Result<Option<String>> {
+        let args = tokenize(line)?;
+        let Some((command, args)) = args.split_first() else {
+            return Ok(Some(String::new()));
+        };
+        let command = String::from_utf8_lossy(command).to_ascii_lowercase();
+
+        let output = match (command.as_str(), args) {
+            ("quit" | "exit", []) => return Ok(None),
+            ("help", []) => HELP.to_string(),
+            ("get", [key]) => match self.db.get(&decode_arg(key)?)? {
+                Some(value) => self.format.encode(&value),
+                None => "(not found)".to_string(),
+            },
+            ("put" | "set", [key, value]) => {
+                self.db.put(&decode_arg(key)?, &decode_arg(value)?)?;
+                "OK".to_string()
+            }
+            ("delete" | "del", [key]) => {
+                self.db.delete(&decode_arg(key)?)?;
+                "OK".to_string()
+            }
+            ("scan", args) => self.scan(args)?,
+            ("count", args) if args.len() <= 2 => {
+                let (start, end) = range_args(args)?;
+                let mut iter = self.db.scan(start.as_deref(), end.as_deref())?;
+                let mut count = 0u64;
+                while iter.valid() {
+                    count += 1;
+                    iter.next();
+                }
+                count.to_string()
+            }
+            ("flush", []) => {
+                self.db.flush()?;
+                "OK".to_string()
+            }
+            ("compact", []) => {
+                self.db.maybe_trigger_compaction()?;
+                "OK".to_string()
+            }
+            ("stats", []) => self.stats(),
+            ("format", []) => self.format.name().to_string(),
+            ("format", [name]) => {
+                self.format = match name.as_slice() {
+                    b"text" => Format::Text,
+                    b"hex" => Format::Hex,
+                    b"base64" => Format::Base64,
+                    _ => return Err(Error::invalid_argument("format must be text, hex or base64")),
+                };
+                String::new()
+            }
+            ("lua" | "eval", _) => {
+                return Err(Error::NotImplemented(
+                    "Lua scripting is not available in this build".to_string(),
+                ))
+            }
+            (
+                "quit" | "exit" | "help" | "get" | "put" | "set" | "delete" | "del" | "count"
+                | "flush" | "compact" | "stats" | "format",
+                _,
+            ) => {
+                return Err(Error::invalid_argument(format!(
+                    "wrong number of arguments for '{}' (see 'help')",
+                    command
+                )))
+            }
+            _ => {
+                return Err(Error::invalid_argument(format!(
+                    "unknown command '{}' (see 'help')",
+                    command
+                )))
+            }
+        };
+        Ok(Some(output))
+    }
+
+    /// `scan [<start> [<end>]] [limit N]`
+    fn scan(&self, args: &[Vec<u8>]) -> Result<String> {
+        let (args, limit) = match args {
+            [rest @ .., keyword, n] if keyword.eq_ignore_ascii_case(b"limit") => {
+                let limit = std::str::from_utf8(n)
+                    .ok()
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .ok_or_else(|| Error::invalid_argument("limit must be a number"))?;
+                (rest, limit)
+            }
+            args => (args, DEFAULT_SCAN_LIMIT),
+        };
+        if args.len() > 2 {
+            return Err(Error::invalid_argument("usage: scan [<start> [<end>]] [limit N]"));
+        }
+        let (start, end) = range_args(args)?;
+
+        let mut iter = self.db.scan(start.as_deref(), end.as_deref())?;
+        let mut rows = Vec::new();
+        while iter.valid() && rows.len() < limit {
+            rows.push((self.format.encode(iter.key()), self.format.encode(iter.value())));
+            iter.next();
+        }
+        let truncated = iter.valid();
+
+        let width = rows
+            .iter()
+            .map(|(key, _)| key.chars().count())
+            .filter(|&len| len <= MAX_KEY_COLUMN)
+            .max()
+            .unwrap_or(0);
+        let mut out = String::new();
+        for (key, value) in &rows {
+            let _ = writeln!(out, "{:<width$}  {}", key, value, width = width);
+        }
+        let noun = if rows.len() == 1 { "entry" } else { "entries" };
+        match truncated {
+            true => {
+                let _ = write!(out, "({} {}, more remain; use 'limit N')", rows.len(), noun);
+            }
+            false => {
+                let _ = write!(out, "({} {})", rows.len(), noun);
+            }
+        }
+        Ok(out)
+    }
+
+    /// `stats`: SSTable sizes per level and memory usage
+    fn stats(&self) -> String {
+        let mut out = String::new();
+        for (level, size) in self.db.level_sizes() {
+            let _ = writeln!(out, "level {}: {} bytes", level, size);
+        }
+        let usage = self.db.memory_usage();
+        let _ = writeln!(out, "wal: {} bytes", self.db.wal_size());
+        let _ = write!(
+            out,
+            "memory: {} bytes (memtables {}, block cache {}, index/filter {}, iterators {})",
+            usage.total(),
+            usage.memtable + usage.immutable_memtables,
+            usage.block_cache,
+            usage.index_and_filter_blocks,
+            usage.iterators
+        );
+        out
+    }
+
+    /// Reads commands from the terminal until `quit` or end of input.
+    ///
+    /// History is loaded from and saved to `history` if given.
+    pub fn run(&mut self, history: Option<&Path>) -> Result<()> {
+        use rustyline::error::ReadlineError;
+
+        let readline_err = |e: ReadlineError| Error::internal(format!("readline: {}", e));
+        let mut editor = rustyline::DefaultEditor::new().map_err(readline_err)?;
+        if let Some(path) = history {
+            // A missing history file is normal on first use
+            let _ = editor.load_history(path);
+        }
+
+        println!("AiDb shell. Type 'help' for commands.");
+        loop {
+            let line = match editor.readline("aidb> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(readline_err(e)),
+            };
+            if !line.trim().is_empty() {
+                let _ = editor.add_history_entry(line.as_str());
+            }
+            match self.execute(&line) {
+                Ok(Some(output)) if output.is_empty() => {}
+                Ok(Some(output)) => println!("{}", output),
+                Ok(None) => break,
+                Err(e) => println!("(error) {}", e),
+            }
+        }
+
+        if let Some(path) = history {
+            if let Err(e) = editor.save_history(path) {
+                log::warn!("Failed to save shell history to {:?}: {}", path, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decoded `[<start> [<end>]]` arguments
+type KeyRange = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+fn range_args(args: &[Vec<u8>]) -> Result<KeyRange> {
+    let start = args.first().map(|arg| decode_arg(arg)).transpose()?;
+    let end = args.get(1).map(|arg| decode_arg(arg)).transpose()?;
+    Ok((start, end))
+}
+
+/// Decodes the `0x` (hex) and `base64:` argument prefixes.
+pub fn decode_arg(arg: &[u8]) -> Result<Vec<u8>> {
+    if let Some(hex) = arg.strip_prefix(b"0x") {
+        if hex.len() % 2 != 0 {
+            return Err(Error::invalid_argument("hex argument has an odd number of digits"));
+        }
+        return hex
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .ok_or_else(|| Error::invalid_argument("invalid hex digit in argument"))
+            })
+            .collect();
+    }
+    if let Some(data) = arg.strip_prefix(b"base64:") {
+        return BASE64
+            .decode(data)
+            .map_err(|e| Error::invalid_argument(format!("invalid base64 argument: {}", e)));
+    }
+    Ok(arg.to_vec())
+}
+
+/// Splits a command line into arguments, handling quotes and escapes.
+pub fn tokenize(line: &str) -> Result<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut bytes = line.bytes().peekable();
+    loop {
+        while bytes.next_if(|b| b.is_ascii_whitespace()).is_some() {}
+        if bytes.peek().is_none() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+        let mut quoted = false;
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'"' => quoted = !quoted,
+                b'\\' => match bytes.next() {
+                    Some(b'n') => arg.push(b'\n'),
+                    Some(b't') => arg.push(b'\t'),
+                    Some(b'r') => arg.push(b'\r'),
+                    Some(b'0') => arg.push(0),
+                    Some(b'x') => {
+                        let digits = [bytes.next(), bytes.next()];
+                        let value = match digits {
+                            [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                                .ok()
+                                .and_then(|pair| u8::from_str_radix(pair, 16).ok()),
+                            _ => None,
+                        };
+                        arg.push(value.ok_or_else(|| {
+                            Error::invalid_argument("\\x must be followed by two hex digits")
+                        })?);
+                    }
+                    Some(other) => arg.push(other),
+                    None => return Err(Error::invalid_argument("line ends with a backslash")),
+                },
+                b if b.is_ascii_whitespace() && !quoted => break,
+                b => arg.push(b),
+            }
+        }
+        if quoted {
+            return Err(Error::invalid_argument("unterminated quote"));
+        }
+        args.push(arg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("  put  a b ").unwrap(), [&b"put"[..], b"a", b"b"]);
+        assert_eq!(
+            tokenize(r#"put "two words" a\x00\"b"#).unwrap(),
+            [&b"put"[..], b"two words", b"a\x00\"b"]
+        );
+        assert_eq!(tokenize(r#"get """#).unwrap(), [&b"get"[..], b""]);
+        assert!(tokenize(r#"get "open"#).is_err());
+        assert!(tokenize(r"get \x4").is_err());
+        assert!(tokenize("get a\\").is_err());
+    }
+
+    #[test]
+    fn test_decode_and_encode() {
+        assert_eq!(decode_arg(b"0x00ff").unwrap(), [0x00, 0xff]);
+        assert_eq!(decode_arg(b"base64:AP8=").unwrap(), [0x00, 0xff]);
+        assert_eq!(decode_arg(b"plain").unwrap(), b"plain");
+        assert!(decode_arg(b"0x0").is_err());
+        assert!(decode_arg(b"0xzz").is_err());
+        assert!(decode_arg(b"base64:***").is_err());
+
+        assert_eq!(Format::Text.encode(b"a\x00\n"), "a\\x00\\n");
+        assert_eq!(Format::Hex.encode(&[0x00, 0xff]), "0x00ff");
+        assert_eq!(Format::Base64.encode(&[0x00, 0xff]), "base64:AP8=");
+    }
+
+    #[test]
+    fn test_shell_commands() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(temp_dir.path(), Options::default()).unwrap());
+        let mut shell = Shell::new(db);
+        let mut run = |line: &str| shell.execute(line).unwrap().unwrap();
+
+        assert_eq!(run("put user:1 alice"), "OK");
+        assert_eq!(run("PUT user:22 \"bob smith\""), "OK");
+        assert_eq!(run("put 0x00ff base64:AP8="), "OK");
+        assert_eq!(run("get user:1"), "alice");
+        assert_eq!(run("get missing"), "(not found)");
+        assert_eq!(run("get 0x00ff"), "\\x00\\xff");
+        assert_eq!(run(""), "");
+
+        assert_eq!(run("scan user: user;"), "user:1   alice\nuser:22  bob smith\n(2 entries)");
+        assert_eq!(
+            run("scan user: limit 1"),
+            "user:1  alice\n(1 entry, more remain; use 'limit N')"
+        );
+        assert_eq!(run("count"), "3");
+        assert_eq!(run("count user:2"), "1");
+
+        assert_eq!(run("format hex"), "");
+        assert_eq!(run("format"), "hex");
+        assert_eq!(run("get user:1"), "0x616c696365");
+        assert_eq!(run("format text"), "");
+
+        assert_eq!(run("delete user:1"), "OK");
+        assert_eq!(run("flush"), "OK");
+        assert_eq!(run("compact"), "OK");
+        assert_eq!(run("get user:1"), "(not found)");
+        assert!(run("stats").contains("level 0:"));
+        assert!(run("help").contains("scan [<start>"));
+
+        for bad in ["get", "nope", "format xml", "scan a b c", "scan limit x", "lua return 1"] {
+            assert!(shell.execute(bad).is_err(), "{}", bad);
+        }
+        assert!(matches!(shell.execute("lua return 1"), Err(Error::NotImplemented(_))));
+        assert_eq!(shell.execute("quit").unwrap(), None);
+    }
+}
@@ -0,0 +1,594 @@
+//! Deterministic simulation of a database, for replaying bugs from a seed.
+//!
+//! The engine runs flushes and compactions on the threads that call into
+//! it. A [`Simulation`] drives a database from a single thread and decides,
+//! from a seeded random number generator, which operation runs next:
+//! writes, reads, flushes, compactions, clock ticks, crashes and reopens.
+//! Everything the engine does then depends only on the seed:
+//!
+//! - files live in a [`MemEnv`] behind a [`FaultInjectionEnv`], so a crash
+//!   loses (or tears) exactly the writes that were not synced
+//! - time comes from a [`SimClock`] that only moves when the simulation
+//!   advances it, so compaction windows, info log rotation and retries
+//!   after a full disk happen at the same step in every run
+//! - options that would hand work to other threads (parallel flushes,
+//!   pipelined writes, parallel WAL replay) are set to use one thread
+//!
+//! After each step the database is checked against a model of the
+//! acknowledged writes. Reads must return the latest acknowledged value;
+//! after a crash the recovered state must be the model as of some write
+//! at or after the last point the writes were known to be durable, since
+//! unsynced writes may be lost but only from the end.
+//!
+//! A failing run reports its seed and step; running the same seed again
+//! replays it exactly, and [`Simulation::trace`] lists the steps taken so
+//! they can be replayed, or trimmed down, with [`Simulation::run_step`].
+//!
+//! Needs the `testing` feature.
+//!
+//! # Example
+//!
+//! ```rust
+//! use aidb::simulation::Simulation;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! for seed in 0..4 {
+//!     let mut simulation = Simulation::new(seed)?;
+//!     simulation.run(200)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Options;
+use crate::env::{
+    Env, FaultInjectionEnv, FileLock, MemEnv, RandomAccessFile, SequentialFile, WritableFile,
+};
+use crate::error::{Error, Result};
+use crate::write_batch::WriteBatch;
+use crate::DB;
+
+/// Number of distinct keys the simulation writes
+const KEYS: u64 = 32;
+
+/// 2024-01-01 00:00 UTC, in microseconds since the epoch
+const START_MICROS: u64 = 1_704_067_200_000_000;
+
+const MICROS_PER_DAY: u64 = 86_400_000_000;
+
+/// Keys with the value written to them, or `None` if deleted
+type Ops = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+/// Small, fast pseudo-random number generator (SplitMix64).
+///
+/// The same seed always yields the same sequence, on every platform.
+#[derive(Debug, Clone)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    /// Creates a generator from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`; `n` must not be 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Returns true with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+/// A clock that only moves when told to.
+///
+/// Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct SimClock {
+    micros: Arc<AtomicU64>,
+}
+
+impl SimClock {
+    /// Creates a clock showing `micros` since the epoch.
+    pub fn new(micros: u64) -> Self {
+        Self { micros: Arc::new(AtomicU64::new(micros)) }
+    }
+
+    /// Returns the time in microseconds since the epoch.
+    pub fn now_micros(&self) -> u64 {
+        self.micros.load(Ordering::SeqCst)
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.micros.fetch_add(duration.as_micros() as u64, Ordering::SeqCst);
+    }
+}
+
+/// [`Env`] that passes files to another one and reads the time from a
+/// [`SimClock`].
+pub struct SimEnv {
+    base: Arc<dyn Env>,
+    clock: SimClock,
+}
+
+impl SimEnv {
+    /// Wraps `base`, with the time of `clock`.
+    pub fn new(base: Arc<dyn Env>, clock: SimClock) -> Self {
+        Self { base, clock }
+    }
+}
+
+impl Env for SimEnv {
+    fn new_writable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        self.base.new_writable_file(path)
+    }
+
+    fn new_appendable_file(&self, path: &Path) -> io::Result<Box<dyn WritableFile>> {
+        self.base.new_appendable_file(path)
+    }
+
+    fn new_sequential_file(&self, path: &Path) -> io::Result<Box<dyn SequentialFile>> {
+        self.base.new_sequential_file(path)
+    }
+
+    fn new_random_access_file(&self, path: &Path) -> io::Result<Arc<dyn RandomAccessFile>> {
+        self.base.new_random_access_file(path)
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        self.base.file_exists(path)
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        self.base.file_size(path)
+    }
+
+    fn get_children(&self, dir: &Path) -> io::Result<Vec<String>> {
+        self.base.get_children(dir)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        self.base.create_dir_all(dir)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.base.remove_file(path)
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        self.base.remove_dir(dir)
+    }
+
+    fn rename_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.base.rename_file(from, to)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        self.base.sync_dir(dir)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.base.read_file(path)
+    }
+
+    fn link_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.base.link_file(from, to)
+    }
+
+    fn lock_file(&self, path: &Path) -> io::Result<Box<dyn FileLock>> {
+        self.base.lock_file(path)
+    }
+
+    fn now_micros(&self) -> u64 {
+        self.clock.now_micros()
+    }
+}
+
+/// One operation of a [`Simulation`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// Writes a value
+    Put {
+        /// Key to write
+        key: Vec<u8>,
+        /// Value to write
+        value: Vec<u8>,
+    },
+    /// Deletes a key
+    Delete {
+        /// Key to delete
+        key: Vec<u8>,
+    },
+    /// Writes several operations as one batch
+    Batch {
+        /// Keys with the value to write, or `None` to delete them
+        ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    },
+    /// Reads a key and checks it against the model
+    Get {
+        /// Key to read
+        key: Vec<u8>,
+    },
+    /// Flushes the MemTables
+    Flush,
+    /// Runs a compaction if one is needed
+    Compact,
+    /// Moves the clock forward
+    AdvanceClock(Duration),
+    /// Abandons the database, loses its unsynced writes, reopens it and
+    /// checks what was recovered
+    Crash {
+        /// Bytes of each file's unsynced tail that survive the crash
+        keep: u64,
+    },
+    /// Closes and reopens the database and checks its contents
+    Reopen,
+}
+
+/// Drives a database through operations chosen from a seed.
+///
+/// See the [module documentation](self).
+pub struct Simulation {
+    seed: u64,
+    rng: SimRng,
+    clock: SimClock,
+    env: Arc<FaultInjectionEnv>,
+    options: Options,
+    path: PathBuf,
+    db: Option<DB>,
+    /// Contents the database must have after the acknowledged writes
+    model: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Contents as of the last point the writes were known to be durable
+    durable: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Writes acknowledged since then, one entry per write
+    unsynced: Vec<Ops>,
+    trace: Vec<Step>,
+}
+
+impl Simulation {
+    /// Opens a simulated database with small MemTables and levels, so that
+    /// a few hundred steps flush and compact several times. Whether every
+    /// write syncs the WAL is chosen from the seed.
+    pub fn new(seed: u64) -> Result<Self> {
+        let sync_wal = SimRng::new(seed ^ 0x5157_4a4c).chance(0.5);
+        let options = Options::default()
+            .memtable_size(4 * 1024)
+            .level0_compaction_threshold(2)
+            .base_level_size(32 * 1024)
+            .wal_buffer_size(512)
+            .sync_wal(sync_wal);
+        Self::with_options(seed, options)
+    }
+
+    /// Opens a simulated database with `options`. Their environment is
+    /// replaced by the simulated one, and work they would run on other
+    /// threads is run on the calling thread.
+    pub fn with_options(seed: u64, options: Options) -> Result<Self> {
+        let mut rng = SimRng::new(seed);
+        let clock = SimClock::new(START_MICROS + rng.below(MICROS_PER_DAY));
+        let base = Arc::new(SimEnv::new(Arc::new(MemEnv::new()), clock.clone()));
+        let env = Arc::new(FaultInjectionEnv::new(base));
+        let options = options
+            .env(env.clone())
+            .flush_threads(1)
+            .wal_recovery_threads(1)
+            .enable_pipelined_write(false);
+        let path = PathBuf::from("/simulation");
+        let db = DB::open(&path, options.clone())?;
+
+        Ok(Self {
+            seed,
+            rng,
+            clock,
+            env,
+            options,
+            path,
+            db: Some(db),
+            model: BTreeMap::new(),
+            durable: BTreeMap::new(),
+            unsynced: Vec::new(),
+            trace: Vec::new(),
+        })
+    }
+
+    /// Seed the simulation was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The simulated clock.
+    pub fn clock(&self) -> &SimClock {
+        &self.clock
+    }
+
+    /// The environment holding the database files.
+    pub fn env(&self) -> &Arc<FaultInjectionEnv> {
+        &self.env
+    }
+
+    /// The simulated database.
+    pub fn db(&self) -> &DB {
+        self.db.as_ref().expect("the database is open between steps")
+    }
+
+    /// Steps run so far.
+    pub fn trace(&self) -> &[Step] {
+        &self.trace
+    }
+
+    /// Runs `steps` steps chosen from the seed.
+    pub fn run(&mut self, steps: usize) -> Result<()> {
+        for _ in 0..steps {
+            let step = self.next_step();
+            self.run_step(step)?;
+        }
+        Ok(())
+    }
+
+    /// Chooses the next step from the seed.
+    pub fn next_step(&mut self) -> Step {
+        let rng = &mut self.rng;
+        match rng.below(100) {
+            0..=39 => Step::Put { key: random_key(rng), value: random_value(rng) },
+            40..=49 => Step::Delete { key: random_key(rng) },
+            50..=59 => {
+                let ops = (0..2 + rng.below(7))
+                    .map(|_| {
+                        let key = random_key(rng);
+                        (key, rng.chance(0.8).then(|| random_value(rng)))
+                    })
+                    .collect();
+                Step::Batch { ops }
+            }
+            60..=74 => Step::Get { key: random_key(rng) },
+            75..=82 => Step::Flush,
+            83..=89 => Step::Compact,
+            90..=94 => Step::AdvanceClock(Duration::from_secs(1 + rng.below(3_600))),
+            95..=97 => Step::Crash { keep: rng.below(4_096) },
+            _ => Step::Reopen,
+        }
+    }
+
+    /// Runs `step` and checks the database against the model.
+    ///
+    /// Fails with [`Error::InvalidState`] naming the seed and the step if
+    /// the database returns an error or diverges from the model.
+    pub fn run_step(&mut self, step: Step) -> Result<()> {
+        self.trace.push(step.clone());
+        self.apply(&step).map_err(|e| {
+            Error::InvalidState(format!(
+                "simulation with seed {} failed at step {} ({:?}): {}",
+                self.seed,
+                self.trace.len() - 1,
+                step,
+                e
+            ))
+        })
+    }
+
+    fn apply(&mut self, step: &Step) -> Result<()> {
+        match step {
+            Step::Put { key, value } => {
+                self.db().put(key, value)?;
+                self.acknowledge(vec![(key.clone(), Some(value.clone()))]);
+            }
+            Step::Delete { key } => {
+                self.db().delete(key)?;
+                self.acknowledge(vec![(key.clone(), None)]);
+            }
+            Step::Batch { ops } => {
+                let mut batch = WriteBatch::new();
+                for (key, value) in ops {
+                    match value {
+                        Some(value) => batch.put(key, value),
+                        None => batch.delete(key),
+                    }
+                }
+                self.db().write(batch)?;
+                self.acknowledge(ops.clone());
+            }
+            Step::Get { key } => {
+                let value = self.db().get(key)?;
+                if value.as_ref() != self.model.get(key) {
+                    return Err(Error::corruption(format!(
+                        "read {:?} for {:?}, expected {:?}",
+                        value.as_deref().map(String::from_utf8_lossy),
+                        String::from_utf8_lossy(key),
+                        self.model.get(key).map(|value| String::from_utf8_lossy(value)),
+                    )));
+                }
+            }
+            Step::Flush => {
+                self.db().flush()?;
+                self.mark_durable();
+            }
+            Step::Compact => self.db().maybe_trigger_compaction()?,
+            Step::AdvanceClock(duration) => self.clock.advance(*duration),
+            Step::Crash { keep } => {
+                if let Some(db) = self.db.take() {
+                    db.simulate_crash();
+                }
+                self.env.simulate_torn_crash(*keep)?;
+                self.db = Some(DB::open(&self.path, self.options.clone())?);
+                self.check_recovered()?;
+            }
+            Step::Reopen => {
+                drop(self.db.take());
+                self.mark_durable();
+                self.db = Some(DB::open(&self.path, self.options.clone())?);
+                self.check_recovered()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records an acknowledged write in the model.
+    fn acknowledge(&mut self, ops: Ops) {
+        apply_ops(&mut self.model, &ops);
+        if self.options.sync_wal {
+            apply_ops(&mut self.durable, &ops);
+        } else {
+            self.unsynced.push(ops);
+        }
+    }
+
+    /// Records that every acknowledged write is durable.
+    fn mark_durable(&mut self) {
+        self.durable = self.model.clone();
+        self.unsynced.clear();
+    }
+
+    /// Checks that the reopened database holds the durable writes and a
+    /// prefix of the unsynced ones, and makes that the model.
+    fn check_recovered(&mut self) -> Result<()> {
+        let mut recovered = BTreeMap::new();
+        for index in 0..KEYS {
+            let key = key_name(index);
+            if let Some(value) = self.db().get(&key)? {
+                recovered.insert(key, value);
+            }
+        }
+
+        let mut candidate = self.durable.clone();
+        let mut kept = (candidate == recovered).then_some(0);
+        for (count, ops) in self.unsynced.iter().enumerate() {
+            apply_ops(&mut candidate, ops);
+            if candidate == recovered {
+                kept = Some(count + 1);
+            }
+        }
+        let Some(kept) = kept else {
+            return Err(Error::corruption(format!(
+                "recovered {} keys that match no prefix of the {} unsynced writes",
+                recovered.len(),
+                self.unsynced.len()
+            )));
+        };
+        log::debug!("Recovered {} of {} unsynced writes", kept, self.unsynced.len());
+
+        self.model = recovered.clone();
+        self.durable = recovered;
+        self.unsynced.clear();
+        Ok(())
+    }
+}
+
+fn key_name(index: u64) -> Vec<u8> {
+    format!("key{:02}", index).into_bytes()
+}
+
+fn random_key(rng: &mut SimRng) -> Vec<u8> {
+    key_name(rng.below(KEYS))
+}
+
+/// A non-empty value, sometimes larger than the WAL buffer
+fn random_value(rng: &mut SimRng) -> Vec<u8> {
+    let len = if rng.chance(0.1) {
+        1_024 + rng.below(2_048)
+    } else {
+        1 + rng.below(64)
+    };
+    let fill = b'a' + rng.below(26) as u8;
+    vec![fill; len as usize]
+}
+
+fn apply_ops(state: &mut BTreeMap<Vec<u8>, Vec<u8>>, ops: &[(Vec<u8>, Option<Vec<u8>>)]) {
+    for (key, value) in ops {
+        match value {
+            Some(value) => state.insert(key.clone(), value.clone()),
+            None => state.remove(key),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sim_rng_is_deterministic() {
+        let mut a = SimRng::new(7);
+        let mut b = SimRng::new(7);
+        let values: Vec<u64> = (0..16).map(|_| a.next_u64()).collect();
+        assert_eq!(values, (0..16).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(values[0], SimRng::new(8).next_u64());
+        assert!((0..1000).all(|_| a.below(10) < 10));
+    }
+
+    #[test]
+    fn test_simulation_replays_seed() {
+        let run = |seed| {
+            let mut simulation = Simulation::new(seed).unwrap();
+            simulation.run(300).unwrap();
+            let files = simulation.env().get_children(Path::new("/simulation")).unwrap();
+            let contents: Vec<_> =
+                (0..KEYS).map(|index| simulation.db().get(&key_name(index)).unwrap()).collect();
+            (simulation.trace().to_vec(), files, contents, simulation.clock().now_micros())
+        };
+        let first = run(11);
+        assert_eq!(first, run(11));
+        assert_ne!(first.0, run(12).0);
+    }
+
+    #[test]
+    fn test_simulation_seeds() {
+        for seed in 0..16 {
+            let mut simulation = Simulation::new(seed).unwrap();
+            if let Err(e) = simulation.run(300) {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_simulation_clock_drives_engine() {
+        let options = Options::default().log_file_time_to_roll(60);
+        let mut simulation = Simulation::with_options(3, options).unwrap();
+        let old_logs = |simulation: &Simulation| {
+            let files = simulation.env().get_children(Path::new("/simulation")).unwrap();
+            files.iter().filter(|name| name.starts_with("LOG.old")).count()
+        };
+        let put = Step::Put { key: key_name(0), value: b"value".to_vec() };
+
+        simulation.run_step(put.clone()).unwrap();
+        simulation.run_step(Step::Flush).unwrap();
+        assert_eq!(old_logs(&simulation), 0);
+
+        simulation.run_step(Step::AdvanceClock(Duration::from_secs(61))).unwrap();
+        simulation.run_step(put).unwrap();
+        simulation.run_step(Step::Flush).unwrap();
+        assert_eq!(old_logs(&simulation), 1);
+    }
+
+    #[test]
+    fn test_simulation_detects_divergence() {
+        let mut simulation = Simulation::new(1).unwrap();
+        simulation
+            .run_step(Step::Put { key: key_name(0), value: b"a".to_vec() })
+            .unwrap();
+        simulation.model.insert(key_name(0), b"b".to_vec());
+        let err = simulation.run_step(Step::Get { key: key_name(0) }).unwrap_err();
+        assert!(err.to_string().contains("seed 1 failed at step 1"), "{}", err);
+    }
+}
@@ -5,7 +5,7 @@
 
 use std::sync::Arc;
 
-use crate::{Result, DB};
+use crate::{KeyEntry, ReadOptions, Result, DB};
 
 /// A snapshot represents a point-in-time view of the database.
 ///
@@ -71,13 +71,30 @@ impl Snapshot {
     ///
     /// Returns an error if the read fails due to I/O errors or data corruption.
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        self.db.get_at_sequence(key, self.sequence)
+        self.db
+            .get_value_at_sequence(key, self.sequence, &ReadOptions::default())?
+            .map(|value| self.db.resolve_blob(value))
+            .transpose()
+    }
+
+    /// Retrieves a key as it existed at snapshot time, with the sequence
+    /// number and type of the newest write to it. See [`DB::get_entry`].
+    pub fn get_entry(&self, key: &[u8]) -> Result<Option<KeyEntry>> {
+        self.db
+            .get_entry_at_sequence(key, self.sequence, false, &ReadOptions::default())?
+            .map(|entry| self.db.resolve_blob_entry(entry))
+            .transpose()
     }
 
     /// Returns the sequence number of this snapshot.
     pub fn sequence(&self) -> u64 {
         self.sequence
     }
+
+    /// Returns the database the snapshot was taken on.
+    pub(crate) fn db(&self) -> &Arc<DB> {
+        &self.db
+    }
 }
 
 impl std::fmt::Debug for Snapshot {
@@ -0,0 +1,379 @@
+//! Tracking SSTable space usage and pacing SSTable deletions.
+//!
+//! A big compaction can leave gigabytes of input files to delete at once,
+//! and on many filesystems deleting that much in one go stalls other I/O.
+//! With an [`SstFileManager`] set in
+//! [`Options::sst_file_manager`](crate::Options) and a delete rate, obsolete
+//! SSTables are instead moved to the `trash` directory of the database and
+//! deleted one by one by a background thread, at no more than that many
+//! bytes per second. Files left in the trash when the process exits are
+//! deleted after the next open.
+//!
+//! The manager also keeps the size of every live SSTable of the databases
+//! using it. With [`max_allowed_space_usage`](SstFileManager::max_allowed_space_usage)
+//! set, flushes fail with `NoSpace` once the total reaches the limit (the
+//! database then retries them like after a full disk), and compactions are
+//! postponed while their inputs would not fit a second time.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::sst_file_manager::SstFileManager;
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let manager = Arc::new(
+//!     SstFileManager::new()
+//!         .delete_rate_bytes_per_sec(64 * 1024 * 1024)
+//!         .max_allowed_space_usage(100 * 1024 * 1024 * 1024),
+//! );
+//! let db = DB::open("./data", Options::default().sst_file_manager(Arc::clone(&manager)))?;
+//! println!("SSTables use {} bytes", manager.total_size());
+//! # Ok(())
+//! # }
+//! ```
+
+use parking_lot::{Condvar, Mutex};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::env::Env;
+
+/// Directory in the database directory holding SSTables waiting to be
+/// deleted.
+pub const TRASH_DIR: &str = "trash";
+
+/// A file in a trash directory
+struct TrashFile {
+    env: Arc<dyn Env>,
+    path: PathBuf,
+    size: u64,
+}
+
+#[derive(Default)]
+struct DeleteQueue {
+    files: VecDeque<TrashFile>,
+    /// Bytes queued or being deleted
+    trash_size: u64,
+    stop: bool,
+}
+
+/// State shared with the deletion thread
+struct Shared {
+    delete_rate: AtomicU64,
+    queue: Mutex<DeleteQueue>,
+    /// Signalled when files are queued or deleted, and on shutdown
+    changed: Condvar,
+}
+
+/// Live SSTables by path and their total size
+#[derive(Default)]
+struct TrackedFiles {
+    files: HashMap<PathBuf, u64>,
+    total_size: u64,
+}
+
+/// Tracks the SSTables of one or more databases and deletes obsolete ones
+/// at a limited rate.
+///
+/// See the [module documentation](crate::sst_file_manager).
+pub struct SstFileManager {
+    shared: Arc<Shared>,
+    max_allowed_space_usage: AtomicU64,
+    tracked: Mutex<TrackedFiles>,
+    /// Started with the first file moved to the trash
+    deleter: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl fmt::Debug for SstFileManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SstFileManager")
+            .field("delete_rate_bytes_per_sec", &self.get_delete_rate_bytes_per_sec())
+            .field("max_allowed_space_usage", &self.get_max_allowed_space_usage())
+            .field("total_size", &self.total_size())
+            .field("total_trash_size", &self.total_trash_size())
+            .finish()
+    }
+}
+
+impl Default for SstFileManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SstFileManager {
+    /// Creates a manager that deletes files immediately and has no space
+    /// limit.
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                delete_rate: AtomicU64::new(0),
+                queue: Mutex::new(DeleteQueue::default()),
+                changed: Condvar::new(),
+            }),
+            max_allowed_space_usage: AtomicU64::new(0),
+            tracked: Mutex::new(TrackedFiles::default()),
+            deleter: Mutex::new(None),
+        }
+    }
+
+    /// Sets the rate at which obsolete SSTables are deleted (0 = delete
+    /// them immediately).
+    pub fn delete_rate_bytes_per_sec(self, rate: u64) -> Self {
+        self.set_delete_rate_bytes_per_sec(rate);
+        self
+    }
+
+    /// Sets the limit on the total size of live SSTables (0 = no limit).
+    pub fn max_allowed_space_usage(self, bytes: u64) -> Self {
+        self.set_max_allowed_space_usage(bytes);
+        self
+    }
+
+    /// Changes the deletion rate; applies from the next file deleted.
+    pub fn set_delete_rate_bytes_per_sec(&self, rate: u64) {
+        self.shared.delete_rate.store(rate, Ordering::Relaxed);
+    }
+
+    /// The deletion rate in bytes per second (0 = immediate).
+    pub fn get_delete_rate_bytes_per_sec(&self) -> u64 {
+        self.shared.delete_rate.load(Ordering::Relaxed)
+    }
+
+    /// Changes the space limit.
+    pub fn set_max_allowed_space_usage(&self, bytes: u64) {
+        self.max_allowed_space_usage.store(bytes, Ordering::Relaxed);
+    }
+
+    /// The limit on the total size of live SSTables (0 = no limit).
+    pub fn get_max_allowed_space_usage(&self) -> u64 {
+        self.max_allowed_space_usage.load(Ordering::Relaxed)
+    }
+
+    /// Total size in bytes of the live SSTables tracked.
+    pub fn total_size(&self) -> u64 {
+        self.tracked.lock().total_size
+    }
+
+    /// Live SSTables tracked and their sizes.
+    pub fn tracked_files(&self) -> HashMap<PathBuf, u64> {
+        self.tracked.lock().files.clone()
+    }
+
+    /// Bytes in the trash that have not been deleted yet.
+    pub fn total_trash_size(&self) -> u64 {
+        self.shared.queue.lock().trash_size
+    }
+
+    /// Whether the live SSTables have reached the space limit.
+    pub fn is_max_allowed_space_reached(&self) -> bool {
+        let limit = self.get_max_allowed_space_usage();
+        limit > 0 && self.total_size() >= limit
+    }
+
+    /// Whether a compaction of `input_size` bytes fits under the space
+    /// limit; its output can be as large as its inputs until they are
+    /// deleted.
+    pub fn enough_room_for_compaction(&self, input_size: u64) -> bool {
+        let limit = self.get_max_allowed_space_usage();
+        limit == 0 || self.total_size().saturating_add(input_size) <= limit
+    }
+
+    /// Blocks until every file in the trash has been deleted.
+    pub fn wait_for_empty_trash(&self) {
+        let mut queue = self.shared.queue.lock();
+        while queue.trash_size > 0 && !queue.stop {
+            self.shared.changed.wait(&mut queue);
+        }
+    }
+
+    /// Records a new live SSTable.
+    pub(crate) fn on_add_file(&self, path: &Path, size: u64) {
+        let mut tracked = self.tracked.lock();
+        if let Some(old) = tracked.files.insert(path.to_path_buf(), size) {
+            tracked.total_size -= old;
+        }
+        tracked.total_size += size;
+    }
+
+    /// Stops tracking an SSTable.
+    fn on_delete_file(&self, path: &Path) {
+        let mut tracked = self.tracked.lock();
+        if let Some(size) = tracked.files.remove(path) {
+            tracked.total_size -= size;
+        }
+    }
+
+    /// Deletes the SSTable at `path` of the database in `db_path`, or moves
+    /// it to the trash if deletions are rate limited.
+    pub(crate) fn delete_file(
+        &self,
+        env: &Arc<dyn Env>,
+        db_path: &Path,
+        path: &Path,
+    ) -> io::Result<()> {
+        if self.get_delete_rate_bytes_per_sec() == 0 {
+            env.remove_file(path)?;
+            self.on_delete_file(path);
+            return Ok(());
+        }
+
+        let size = env.file_size(path)?;
+        let trash_dir = db_path.join(TRASH_DIR);
+        env.create_dir_all(&trash_dir)?;
+        let name = path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("not a file: {:?}", path))
+        })?;
+        let trash_path = trash_dir.join(name);
+        env.rename_file(path, &trash_path)?;
+        self.on_delete_file(path);
+        self.schedule(TrashFile { env: Arc::clone(env), path: trash_path, size });
+        Ok(())
+    }
+
+    /// Queues the files left in the trash of the database in `db_path` for
+    /// deletion.
+    pub(crate) fn schedule_trash(&self, env: &Arc<dyn Env>, db_path: &Path) -> io::Result<()> {
+        for path in trash_files(&**env, db_path)? {
+            if self.get_delete_rate_bytes_per_sec() == 0 {
+                env.remove_file(&path)?;
+                continue;
+            }
+            let size = env.file_size(&path)?;
+            self.schedule(TrashFile { env: Arc::clone(env), path, size });
+        }
+        Ok(())
+    }
+
+    fn schedule(&self, file: TrashFile) {
+        {
+            let mut queue = self.shared.queue.lock();
+            queue.trash_size += file.size;
+            queue.files.push_back(file);
+        }
+        self.shared.changed.notify_all();
+
+        let mut deleter = self.deleter.lock();
+        if deleter.is_none() {
+            let shared = Arc::clone(&self.shared);
+            *deleter = Some(std::thread::spawn(move || shared.run_deleter()));
+        }
+    }
+}
+
+impl Drop for SstFileManager {
+    fn drop(&mut self) {
+        self.shared.queue.lock().stop = true;
+        self.shared.changed.notify_all();
+        if let Some(handle) = self.deleter.get_mut().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Shared {
+    /// Deletes queued files, waiting after each one as long as deleting
+    /// its size takes at the configured rate
+    fn run_deleter(&self) {
+        let mut queue = self.queue.lock();
+        loop {
+            if queue.stop {
+                return;
+            }
+            let Some(file) = queue.files.pop_front() else {
+                self.changed.wait(&mut queue);
+                continue;
+            };
+
+            let rate = self.delete_rate.load(Ordering::Relaxed);
+            let resume_at = (rate > 0)
+                .then(|| Instant::now() + Duration::from_secs_f64(file.size as f64 / rate as f64));
+            drop(queue);
+            match file.env.remove_file(&file.path) {
+                Ok(()) => log::info!("Deleted trash file {:?}", file.path),
+                Err(e) => log::warn!("Failed to delete trash file {:?}: {}", file.path, e),
+            }
+
+            queue = self.queue.lock();
+            queue.trash_size -= file.size;
+            self.changed.notify_all();
+            if let Some(resume_at) = resume_at {
+                while !queue.stop && Instant::now() < resume_at {
+                    self.changed.wait_until(&mut queue, resume_at);
+                }
+            }
+        }
+    }
+}
+
+/// Files in the trash directory of the database in `db_path`
+pub(crate) fn trash_files(env: &dyn Env, db_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let trash_dir = db_path.join(TRASH_DIR);
+    if !env.file_exists(&trash_dir) {
+        return Ok(Vec::new());
+    }
+    Ok(env
+        .get_children(&trash_dir)?
+        .into_iter()
+        .map(|name| trash_dir.join(name))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, DB};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sst_file_manager_tracks_and_trashes_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(SstFileManager::new().delete_rate_bytes_per_sec(1024 * 1024));
+        let options = Options::default()
+            .level0_compaction_threshold(2)
+            .sst_file_manager(Arc::clone(&manager));
+        let db = DB::open(temp_dir.path(), options).unwrap();
+
+        db.put(b"a", &[1; 1000]).unwrap();
+        db.flush().unwrap();
+        assert_eq!(manager.tracked_files().len(), 1);
+        let flushed = manager.total_size();
+        assert!(flushed > 1000);
+
+        // The compaction's inputs go through the trash
+        db.put(b"a", &[2; 1000]).unwrap();
+        db.flush().unwrap();
+        manager.wait_for_empty_trash();
+        assert_eq!(manager.tracked_files().len(), 1);
+        assert_eq!(manager.total_trash_size(), 0);
+        assert!(trash_files(&*db.env, temp_dir.path()).unwrap().is_empty());
+        assert_eq!(db.get(b"a").unwrap(), Some(vec![2; 1000]));
+
+        // Flushes stop at the space limit
+        manager.set_max_allowed_space_usage(manager.total_size());
+        db.put(b"b", b"v").unwrap();
+        assert!(matches!(db.flush(), Err(crate::Error::NoSpace(_))));
+    }
+
+    #[test]
+    fn test_leftover_trash_deleted_on_open() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash_dir = temp_dir.path().join(TRASH_DIR);
+        std::fs::create_dir_all(&trash_dir).unwrap();
+        std::fs::write(trash_dir.join("000007.sst"), [0; 100]).unwrap();
+
+        let manager = Arc::new(SstFileManager::new().delete_rate_bytes_per_sec(1024 * 1024));
+        let options = Options::default().sst_file_manager(Arc::clone(&manager));
+        let _db = DB::open(temp_dir.path(), options).unwrap();
+        manager.wait_for_empty_trash();
+        assert!(!trash_dir.join("000007.sst").exists());
+    }
+}
@@ -3,8 +3,11 @@
 //! A block contains multiple key-value entries and uses restart points
 //! for efficient binary search and prefix compression.
 
+use crate::comparator::{self, Comparator};
 use crate::error::{Error, Result};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::cmp::Ordering;
+use std::sync::Arc;
 
 /// Block stores key-value pairs with prefix compression.
 ///
@@ -37,6 +40,11 @@ pub struct Block {
 }
 
 impl Block {
+    /// Size of the block data in bytes
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
     /// Create a new Block from raw data
     pub fn new(data: Bytes) -> Result<Self> {
         if data.len() < 4 {
@@ -48,11 +56,10 @@ impl Block {
 
         // Calculate restart offset
         // restart_offset = data_len - 4 (num_restarts) - 4 * num_restarts (restart points)
-        let restart_offset = data.len() - 4 - (num_restarts as usize * 4);
-
-        if restart_offset > data.len() {
-            return Err(Error::corruption("Invalid restart offset"));
-        }
+        let restart_offset = (num_restarts as usize)
+            .checked_mul(4)
+            .and_then(|restarts_size| (data.len() - 4).checked_sub(restarts_size))
+            .ok_or_else(|| Error::corruption("Invalid restart offset"))?;
 
         Ok(Self { data, restart_offset, num_restarts })
     }
@@ -86,11 +93,17 @@ pub struct BlockBuilder {
     counter: usize,
     last_key: Vec<u8>,
     block_restart_interval: usize,
+    comparator: Arc<dyn Comparator>,
 }
 
 impl BlockBuilder {
     /// Create a new BlockBuilder
     pub fn new(block_restart_interval: usize) -> Self {
+        Self::with_comparator(block_restart_interval, comparator::default_comparator())
+    }
+
+    /// Create a new BlockBuilder for keys ordered by `comparator`
+    pub fn with_comparator(block_restart_interval: usize, comparator: Arc<dyn Comparator>) -> Self {
         let restarts = vec![0]; // First restart point at offset 0
 
         Self {
@@ -99,6 +112,7 @@ impl BlockBuilder {
             counter: 0,
             last_key: Vec::new(),
             block_restart_interval,
+            comparator,
         }
     }
 
@@ -106,9 +120,12 @@ impl BlockBuilder {
     pub fn add(&mut self, key: &[u8], value: &[u8]) {
         assert!(!key.is_empty(), "Key cannot be empty");
 
-        // Keys must be added in sorted order
+        // Keys must be added in sorted order; versions of a key share it
         if !self.last_key.is_empty() {
-            assert!(key > self.last_key.as_slice(), "Keys must be added in sorted order");
+            assert!(
+                self.comparator.compare(key, &self.last_key) != Ordering::Less,
+                "Keys must be added in sorted order"
+            );
         }
 
         let mut shared = 0;
@@ -1,16 +1,56 @@
 //! SSTable builder implementation.
 //!
-//! Builds an SSTable file from a sequence of sorted key-value pairs.
+//! Builds an SSTable file from a sequence of sorted key-value pairs, or of
+//! versions of keys with the sequence numbers of their writes.
+//!
+//! The table is written to a temporary `<path>.tmp` file and only renamed to
+//! its final path once it is complete and synced, so a crash mid-write never
+//! leaves a truncated file under an SSTable name.
 
+use crate::comparator::{self, Comparator};
+use crate::config::PrefixExtractor;
+use crate::env::{AccessHint, Env, WritableFile};
 use crate::error::{Error, Result};
 use crate::filter::{BloomFilter, Filter};
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::record_auth::RecordAuthenticator;
 use crate::sstable::block::BlockBuilder;
 use crate::sstable::footer::{BlockHandle, Footer};
 use crate::sstable::index::{IndexBlockBuilder, IndexEntry};
-use crate::sstable::{CompressionType, DEFAULT_BLOCK_SIZE, FOOTER_SIZE};
-use std::fs::File;
+use crate::sstable::{ChecksumType, CompressionType, DEFAULT_BLOCK_SIZE, FOOTER_SIZE};
+use bytes::Bytes;
+use std::cmp::Ordering;
 use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Description of a finished SSTable, returned by [`SSTableBuilder::finish`].
+///
+/// Besides the key bounds and size it keeps the footer, index and filter the
+/// builder just wrote, so [`SSTableReader::from_metadata`] can open the new
+/// table without reading any of it back.
+///
+/// [`SSTableReader::from_metadata`]: crate::sstable::SSTableReader::from_metadata
+#[derive(Debug, Clone)]
+pub struct TableMetadata {
+    /// Final path of the SSTable
+    pub path: PathBuf,
+    /// File number parsed from a name like `000001.sst`
+    pub file_number: Option<u64>,
+    /// Size of the file in bytes
+    pub file_size: u64,
+    /// Number of entries written
+    pub num_entries: u64,
+    /// Smallest key, `None` for an empty table
+    pub smallest_key: Option<Vec<u8>>,
+    /// Largest key, `None` for an empty table
+    pub largest_key: Option<Vec<u8>>,
+    pub(crate) footer: Footer,
+    pub(crate) index_data: Bytes,
+    pub(crate) bloom_filter: Option<BloomFilter>,
+    /// Extractor whose prefixes were added to the Bloom filter
+    pub(crate) prefix_extractor: Option<PrefixExtractor>,
+}
 
 /// SSTableBuilder builds an SSTable file.
 ///
@@ -24,9 +64,14 @@ use std::path::Path;
 /// builder.finish().unwrap();
 /// ```
 pub struct SSTableBuilder {
-    writer: BufWriter<File>,
+    path: PathBuf,
+    temp_path: PathBuf,
+    finished: bool,
+    writer: BufWriter<Box<dyn WritableFile>>,
+    env: Arc<dyn Env>,
     data_block_builder: BlockBuilder,
     index_block_builder: IndexBlockBuilder,
+    first_key: Vec<u8>,
     last_key: Vec<u8>,
     data_block_offset: u64,
     num_entries: u64,
@@ -35,18 +80,48 @@ pub struct SSTableBuilder {
     pending_handle: Option<BlockHandle>,
     bloom_filter: Option<BloomFilter>,
     enable_bloom_filter: bool,
+    bloom_filter_fp_rate: Option<f64>,
+    prefix_extractor: PrefixExtractor,
+    checksum_type: ChecksumType,
+    rate_limiter: Option<(Arc<RateLimiter>, IoPriority)>,
+    /// Factor applied to the rate of `rate_limiter`
+    rate_multiplier: f64,
+    drop_page_cache: bool,
+    /// Tags values with the key of the version
+    record_auth: Option<(Arc<RecordAuthenticator>, u32)>,
+    /// Value with its sequence number and tag, reused between entries
+    tagged_value: Vec<u8>,
+    comparator: Arc<dyn Comparator>,
+    /// Whether the entries are versions with sequence numbers
+    sequence_numbers: bool,
+    /// Sequence number of the last version added
+    last_sequence: u64,
 }
 
 impl SSTableBuilder {
     /// Create a new SSTableBuilder
+    ///
+    /// Data is written to `<path>.tmp` until [`finish`](Self::finish) is called.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = File::create(path)?;
+        Self::new_with_env(path, crate::env::default_env())
+    }
+
+    /// Create a new SSTableBuilder that writes through `env`
+    pub fn new_with_env<P: AsRef<Path>>(path: P, env: Arc<dyn Env>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let temp_path = super::temp_file_path(&path);
+        let file = env.new_writable_file(&temp_path)?;
         let writer = BufWriter::new(file);
 
         Ok(Self {
+            path,
+            temp_path,
+            finished: false,
             writer,
+            env,
             data_block_builder: BlockBuilder::new(16), // 16 restart interval
             index_block_builder: IndexBlockBuilder::new(),
+            first_key: Vec::new(),
             last_key: Vec::new(),
             data_block_offset: 0,
             num_entries: 0,
@@ -55,6 +130,17 @@ impl SSTableBuilder {
             pending_handle: None,
             bloom_filter: None,
             enable_bloom_filter: true, // Enabled by default
+            bloom_filter_fp_rate: None,
+            prefix_extractor: PrefixExtractor::None,
+            checksum_type: ChecksumType::Crc32,
+            rate_limiter: None,
+            rate_multiplier: 1.0,
+            drop_page_cache: false,
+            record_auth: None,
+            tagged_value: Vec::new(),
+            comparator: comparator::default_comparator(),
+            sequence_numbers: false,
+            last_sequence: 0,
         })
     }
 
@@ -68,16 +154,69 @@ impl SSTableBuilder {
         self.compression = compression;
     }
 
+    /// Set the block checksum algorithm (default: CRC32)
+    pub fn set_checksum_type(&mut self, checksum_type: ChecksumType) {
+        self.checksum_type = checksum_type;
+    }
+
     /// Enable or disable Bloom Filter (enabled by default)
     pub fn set_bloom_filter_enabled(&mut self, enabled: bool) {
         self.enable_bloom_filter = enabled;
     }
 
+    /// Set the Bloom Filter false positive rate (default: 1%)
+    pub fn set_bloom_filter_fp_rate(&mut self, rate: f64) {
+        self.bloom_filter_fp_rate = Some(rate);
+    }
+
+    /// Also add the prefix `extractor` returns for each key to the Bloom
+    /// filter (default: none)
+    pub fn set_prefix_extractor(&mut self, extractor: PrefixExtractor) {
+        self.prefix_extractor = extractor;
+    }
+
+    /// Charge the bytes written to `limiter` with `priority` (default: not
+    /// limited)
+    pub fn set_rate_limiter(&mut self, limiter: Arc<RateLimiter>, priority: IoPriority) {
+        self.rate_limiter = Some((limiter, priority));
+    }
+
+    /// Write at `multiplier` times the rate of the rate limiter (default: 1)
+    pub fn set_rate_multiplier(&mut self, multiplier: f64) {
+        self.rate_multiplier = multiplier;
+    }
+
+    /// Advise the OS to drop the file from the page cache once it is
+    /// synced (default: false)
+    pub fn set_drop_page_cache(&mut self, drop: bool) {
+        self.drop_page_cache = drop;
+    }
+
+    /// Append a tag made by `authenticator` with its active key to each
+    /// value (default: none)
+    pub fn set_record_authenticator(
+        &mut self,
+        authenticator: Arc<RecordAuthenticator>,
+    ) -> Result<()> {
+        let version = authenticator.active_version()?;
+        self.record_auth = Some((authenticator, version));
+        Ok(())
+    }
+
+    /// Set the order the keys are added in (default: bytewise). Must be
+    /// called before the first key is added.
+    pub fn set_comparator(&mut self, comparator: Arc<dyn Comparator>) {
+        self.data_block_builder = BlockBuilder::with_comparator(16, Arc::clone(&comparator));
+        self.index_block_builder = IndexBlockBuilder::with_comparator(Arc::clone(&comparator));
+        self.comparator = comparator;
+    }
+
     /// Set expected number of keys for optimal Bloom Filter sizing
     pub fn set_expected_keys(&mut self, num_keys: usize) {
         if self.enable_bloom_filter {
             // Use 1% false positive rate by default
-            self.bloom_filter = Some(BloomFilter::new(num_keys, 0.01));
+            let rate = self.bloom_filter_fp_rate.unwrap_or(0.01);
+            self.bloom_filter = Some(BloomFilter::new(num_keys, rate));
         }
     }
 
@@ -85,14 +224,37 @@ impl SSTableBuilder {
     ///
     /// Keys must be added in sorted order.
     pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.add_entry(key, None, value)
+    }
+
+    /// Add a version of a key, written with sequence number `sequence`.
+    ///
+    /// Keys must be added in sorted order, and the versions of a key from
+    /// the newest (highest sequence number) down. Sequence number 0 stands
+    /// for an unknown one. A table holds either versions or plain entries
+    /// added with [`add`](Self::add).
+    pub fn add_version(&mut self, key: &[u8], sequence: u64, value: &[u8]) -> Result<()> {
+        self.add_entry(key, Some(sequence), value)
+    }
+
+    fn add_entry(&mut self, key: &[u8], sequence: Option<u64>, value: &[u8]) -> Result<()> {
         if key.is_empty() {
             return Err(Error::invalid_argument("Key cannot be empty"));
         }
+        if self.num_entries > 0 && self.sequence_numbers != sequence.is_some() {
+            return Err(Error::invalid_argument(
+                "Versions and plain entries cannot be added to the same table",
+            ));
+        }
+        self.sequence_numbers = sequence.is_some();
 
         // Verify keys are in sorted order
-        if !self.last_key.is_empty() && key <= self.last_key.as_slice() {
-            return Err(Error::invalid_argument("Keys must be added in sorted order"));
-        }
+        let new_key = self.num_entries == 0
+            || match self.comparator.compare(key, &self.last_key) {
+                Ordering::Greater => true,
+                Ordering::Equal if sequence.is_some_and(|seq| seq < self.last_sequence) => false,
+                _ => return Err(Error::invalid_argument("Keys must be added in sorted order")),
+            };
 
         // If we have a pending index entry, add it now
         if let Some(handle) = self.pending_handle.take() {
@@ -101,20 +263,43 @@ impl SSTableBuilder {
         }
 
         // Add to current data block
-        self.data_block_builder.add(key, value);
+        if sequence.is_none() && self.record_auth.is_none() {
+            self.data_block_builder.add(key, value);
+        } else {
+            self.tagged_value.clear();
+            self.tagged_value.extend_from_slice(value);
+            if let Some(sequence) = sequence {
+                self.tagged_value.extend_from_slice(&sequence.to_le_bytes());
+            }
+            if let Some((ref authenticator, version)) = self.record_auth {
+                let tag = authenticator.tag(version, key, &self.tagged_value)?;
+                self.tagged_value.extend_from_slice(&tag);
+            }
+            self.data_block_builder.add(key, &self.tagged_value);
+        }
+        if self.num_entries == 0 {
+            self.first_key = key.to_vec();
+        }
         self.last_key.clear();
         self.last_key.extend_from_slice(key);
+        self.last_sequence = sequence.unwrap_or(0);
         self.num_entries += 1;
 
         // Add key to bloom filter
-        if self.enable_bloom_filter {
+        if self.enable_bloom_filter && new_key {
             // Lazily initialize bloom filter if not set
             if self.bloom_filter.is_none() {
                 // Default: estimate 10000 keys if not specified
-                self.bloom_filter = Some(BloomFilter::default_with_keys(10000));
+                self.bloom_filter = Some(match self.bloom_filter_fp_rate {
+                    Some(rate) => BloomFilter::new(10000, rate),
+                    None => BloomFilter::default_with_keys(10000),
+                });
             }
             if let Some(ref mut filter) = self.bloom_filter {
                 filter.add(key);
+                if let Some(prefix) = self.prefix_extractor.prefix(key) {
+                    filter.add(prefix);
+                }
             }
         }
 
@@ -133,7 +318,8 @@ impl SSTableBuilder {
         }
 
         // Build the block by replacing with a new builder
-        let old_builder = std::mem::replace(&mut self.data_block_builder, BlockBuilder::new(16));
+        let new_builder = BlockBuilder::with_comparator(16, Arc::clone(&self.comparator));
+        let old_builder = std::mem::replace(&mut self.data_block_builder, new_builder);
         let block_data = old_builder.finish();
         let mut compressed_data = block_data.to_vec();
 
@@ -155,13 +341,14 @@ impl SSTableBuilder {
         let block_offset = self.data_block_offset;
         let block_size = compressed_data.len() as u64;
 
+        self.charge_rate_limiter(block_size + 5);
         self.writer.write_all(&compressed_data)?;
 
         // Write compression type trailer (1 byte)
         self.writer.write_all(&[self.compression as u8])?;
 
         // Write CRC32 checksum (4 bytes)
-        let checksum = crc32fast::hash(&compressed_data);
+        let checksum = self.checksum_type.checksum(&compressed_data);
         self.writer.write_all(&checksum.to_le_bytes())?;
 
         // Update offset (data + 1 byte compression + 4 bytes crc)
@@ -176,10 +363,19 @@ impl SSTableBuilder {
         Ok(())
     }
 
+    /// Wait until the rate limiter, if any, grants `bytes`
+    fn charge_rate_limiter(&self, bytes: u64) {
+        if let Some((limiter, priority)) = &self.rate_limiter {
+            limiter.request_at(bytes, *priority, self.rate_multiplier);
+        }
+    }
+
     /// Finish building the SSTable.
     ///
-    /// This writes the index block, meta index block, and footer.
-    pub fn finish(mut self) -> Result<u64> {
+    /// This writes the index block, meta index block, and footer, syncs the
+    /// file, renames it to its final path and syncs the directory. The
+    /// returned metadata can be used to open the table without re-reading it.
+    pub fn finish(mut self) -> Result<TableMetadata> {
         // Flush any remaining data block
         self.flush_data_block()?;
 
@@ -199,42 +395,87 @@ impl SSTableBuilder {
         self.writer.write_all(&meta_block_data)?;
         // Write compression type and checksum for meta block
         self.writer.write_all(&[CompressionType::None as u8])?;
-        let meta_checksum = crc32fast::hash(&meta_block_data);
+        let meta_checksum = self.checksum_type.checksum(&meta_block_data);
         self.writer.write_all(&meta_checksum.to_le_bytes())?;
         let meta_block_size = meta_block_data.len() as u64 + 5; // data + compression + checksum
         let _meta_block_handle = BlockHandle::new(meta_block_offset, meta_block_size);
 
-        // Write meta index block (points to bloom filter)
+        // Write meta index block (names the prefix extractor of the filter)
         let meta_index_offset = self.data_block_offset + meta_block_size;
-        let meta_index_data = vec![0u8; 8]; // Empty meta index for now
+        let prefix_extractor = Some(self.prefix_extractor)
+            .filter(|extractor| extractor.is_enabled() && self.bloom_filter.is_some());
+        let meta_index_data = match prefix_extractor {
+            Some(extractor) => [super::PREFIX_EXTRACTOR_TAG, extractor.name().as_bytes()].concat(),
+            None => vec![0u8; 8], // Empty meta index
+        };
         self.writer.write_all(&meta_index_data)?;
         // Write compression type and checksum for meta index block
         self.writer.write_all(&[CompressionType::None as u8])?;
-        let meta_index_checksum = crc32fast::hash(&meta_index_data);
+        let meta_index_checksum = self.checksum_type.checksum(&meta_index_data);
         self.writer.write_all(&meta_index_checksum.to_le_bytes())?;
         let meta_index_size = meta_index_data.len() as u64 + 5; // data + compression + checksum
         let meta_index_handle = BlockHandle::new(meta_index_offset, meta_index_size);
 
         // Write index block
         let index_offset = self.data_block_offset + meta_block_size + meta_index_size;
-        let index_data = self.index_block_builder.finish();
+        let index_data = std::mem::take(&mut self.index_block_builder).finish();
+        self.charge_rate_limiter(
+            meta_block_size + meta_index_size + index_data.len() as u64 + 5 + FOOTER_SIZE as u64,
+        );
         self.writer.write_all(&index_data)?;
         // Write compression type and checksum for index block
         self.writer.write_all(&[CompressionType::None as u8])?;
-        let index_checksum = crc32fast::hash(&index_data);
+        let index_checksum = self.checksum_type.checksum(&index_data);
         self.writer.write_all(&index_checksum.to_le_bytes())?;
         let index_size = index_data.len() as u64 + 5; // data + compression + checksum
         let index_handle = BlockHandle::new(index_offset, index_size);
 
         // Write footer
-        let footer = Footer::new(meta_index_handle, index_handle);
+        let footer = Footer::new(meta_index_handle, index_handle)
+            .with_checksum_type(self.checksum_type)
+            .with_record_key_version(self.record_auth.as_ref().map(|(_, version)| *version))
+            .with_sequence_numbers(self.sequence_numbers);
         footer.write_to(&mut self.writer)?;
 
         // Flush to disk
         self.writer.flush()?;
+        self.writer.get_mut().sync()?;
+        if self.drop_page_cache {
+            // Only a hint; the table is complete either way
+            if let Err(e) = self.writer.get_ref().advise(AccessHint::DontNeed) {
+                log::debug!("Failed to drop {:?} from the page cache: {}", self.temp_path, e);
+            }
+        }
+
+        // Move the complete file into place and make the rename durable
+        self.env.rename_file(&self.temp_path, &self.path)?;
+        self.finished = true;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            self.env.sync_dir(dir)?;
+        }
 
-        let total_size = index_offset + index_size + FOOTER_SIZE as u64;
-        Ok(total_size)
+        let file_size = index_offset + index_size + FOOTER_SIZE as u64;
+        let (smallest_key, largest_key) = if self.num_entries == 0 {
+            (None, None)
+        } else {
+            (
+                Some(std::mem::take(&mut self.first_key)),
+                Some(std::mem::take(&mut self.last_key)),
+            )
+        };
+
+        Ok(TableMetadata {
+            path: self.path.clone(),
+            file_number: super::parse_file_number(&self.path),
+            file_size,
+            num_entries: self.num_entries,
+            smallest_key,
+            largest_key,
+            footer,
+            index_data,
+            bloom_filter: self.bloom_filter.take(),
+            prefix_extractor,
+        })
     }
 
     /// Get the number of entries added
@@ -248,12 +489,26 @@ impl SSTableBuilder {
     }
 
     /// Abandon the SSTable (don't write footer)
+    ///
+    /// The temporary file is removed; nothing is created at the final path.
     pub fn abandon(self) -> Result<()> {
-        // Just drop the writer without finishing
+        // Dropping the builder removes the temporary file
         Ok(())
     }
 }
 
+impl Drop for SSTableBuilder {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(e) = self.env.remove_file(&self.temp_path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to remove temporary SSTable {:?}: {}", self.temp_path, e);
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -275,8 +530,8 @@ mod tests {
         builder.add(b"key1", b"value1").unwrap();
         assert_eq!(builder.num_entries(), 1);
 
-        let size = builder.finish().unwrap();
-        assert!(size > 0);
+        let meta = builder.finish().unwrap();
+        assert!(meta.file_size > 0);
     }
 
     #[test]
@@ -290,8 +545,8 @@ mod tests {
 
         assert_eq!(builder.num_entries(), 3);
 
-        let size = builder.finish().unwrap();
-        assert!(size > 0);
+        let meta = builder.finish().unwrap();
+        assert!(meta.file_size > 0);
     }
 
     #[test]
@@ -309,8 +564,8 @@ mod tests {
 
         assert_eq!(builder.num_entries(), 1000);
 
-        let size = builder.finish().unwrap();
-        assert!(size > 1024); // Should be larger than one block
+        let meta = builder.finish().unwrap();
+        assert!(meta.file_size > 1024); // Should be larger than one block
     }
 
     #[test]
@@ -326,6 +581,22 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_sstable_builder_versions() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut builder = SSTableBuilder::new(temp_file.path()).unwrap();
+
+        builder.add_version(b"a", 7, b"new").unwrap();
+        builder.add_version(b"a", 3, b"old").unwrap();
+        builder.add_version(b"b", 9, b"").unwrap();
+
+        // Versions of a key go from the newest down
+        assert!(builder.add_version(b"b", 9, b"again").is_err());
+        assert!(builder.add_version(b"b", 10, b"newer").is_err());
+        assert!(builder.add(b"c", b"plain").is_err());
+        assert_eq!(builder.num_entries(), 3);
+    }
+
     #[test]
     fn test_sstable_builder_empty_key() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -341,9 +612,41 @@ mod tests {
         let mut builder = SSTableBuilder::new(temp_file.path()).unwrap();
 
         builder.add(b"key1", b"value1").unwrap();
+        let partial = super::super::temp_file_path(temp_file.path());
+        assert!(partial.exists());
         builder.abandon().unwrap();
 
-        // File should exist but not be a valid SSTable (no footer)
-        assert!(temp_file.path().exists());
+        // The temporary file is removed and the target is left untouched
+        assert!(!partial.exists());
+        assert_eq!(std::fs::metadata(temp_file.path()).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_sstable_builder_writes_to_temp_file_until_finished() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("000001.sst");
+        let temp_path = super::super::temp_file_path(&path);
+
+        let mut builder = SSTableBuilder::new(&path).unwrap();
+        builder.add(b"key1", b"value1").unwrap();
+        assert!(temp_path.exists());
+        assert!(!path.exists());
+
+        builder.finish().unwrap();
+        assert!(!temp_path.exists());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_sstable_builder_drop_removes_temp_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("000001.sst");
+
+        let mut builder = SSTableBuilder::new(&path).unwrap();
+        builder.add(b"key1", b"value1").unwrap();
+        drop(builder);
+
+        assert!(!super::super::temp_file_path(&path).exists());
+        assert!(!path.exists());
     }
 }
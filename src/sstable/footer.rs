@@ -3,6 +3,7 @@
 //! The footer is a fixed-size (48 bytes) structure at the end of an SSTable file
 //! that contains pointers to the index block and meta index block.
 
+use crate::config::ChecksumType;
 use crate::error::{Error, Result};
 use crate::sstable::MAGIC_NUMBER;
 use std::io::{Read, Write};
@@ -11,6 +12,7 @@ use std::io::{Read, Write};
 ///
 /// It contains the offset and size of the block.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct BlockHandle {
     /// Offset of the block in the file
     pub offset: u64,
@@ -56,21 +58,65 @@ impl BlockHandle {
 /// ```text
 /// [meta_index_handle: 16 bytes]
 /// [index_handle: 16 bytes]
-/// [padding: 8 bytes]
+/// [checksum_type: 1 byte]
+/// [authenticated: 1 byte]
+/// [record_key_version: 4 bytes]
+/// [sequence_numbers: 1 byte]
+/// [padding: 1 byte]
 /// [magic: 8 bytes]
 /// ```
+///
+/// Files written before the checksum type was recorded have a zero byte
+/// there, which decodes as [`ChecksumType::Crc32`]. The key version is
+/// only meaningful if the authenticated byte is 1, i.e. the values of the
+/// data blocks end in a [`record_auth`](crate::record_auth) tag. If the
+/// sequence number byte is 1, each value is followed by the sequence number
+/// of its write (8 bytes, before any tag) and a key may have several
+/// entries, newest first; files written before it was added have a zero
+/// byte there.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct Footer {
     /// Handle to the meta index block
     pub meta_index_handle: BlockHandle,
     /// Handle to the index block
     pub index_handle: BlockHandle,
+    /// Checksum algorithm used for all blocks in the file
+    pub checksum_type: ChecksumType,
+    /// Version of the key the records are authenticated with, if they are
+    pub record_key_version: Option<u32>,
+    /// Whether the values carry the sequence numbers of their writes
+    pub sequence_numbers: bool,
 }
 
 impl Footer {
     /// Create a new Footer
     pub fn new(meta_index_handle: BlockHandle, index_handle: BlockHandle) -> Self {
-        Self { meta_index_handle, index_handle }
+        Self {
+            meta_index_handle,
+            index_handle,
+            checksum_type: ChecksumType::Crc32,
+            record_key_version: None,
+            sequence_numbers: false,
+        }
+    }
+
+    /// Set the checksum algorithm recorded in the footer
+    pub fn with_checksum_type(mut self, checksum_type: ChecksumType) -> Self {
+        self.checksum_type = checksum_type;
+        self
+    }
+
+    /// Record that the values carry tags made with the key of `version`
+    pub fn with_record_key_version(mut self, version: Option<u32>) -> Self {
+        self.record_key_version = version;
+        self
+    }
+
+    /// Record whether the values carry sequence numbers
+    pub fn with_sequence_numbers(mut self, sequence_numbers: bool) -> Self {
+        self.sequence_numbers = sequence_numbers;
+        self
     }
 
     /// Encode the footer to bytes (48 bytes)
@@ -83,8 +129,18 @@ impl Footer {
         // Index handle (16 bytes)
         buf.extend_from_slice(&self.index_handle.encode());
 
-        // Padding (8 bytes) - reserved for future use
-        buf.extend_from_slice(&[0u8; 8]);
+        // Checksum type (1 byte)
+        buf.push(self.checksum_type as u8);
+
+        // Record authentication (5 bytes)
+        buf.push(self.record_key_version.is_some() as u8);
+        buf.extend_from_slice(&self.record_key_version.unwrap_or(0).to_le_bytes());
+
+        // Sequence numbers (1 byte)
+        buf.push(self.sequence_numbers as u8);
+
+        // Padding (1 byte) - reserved for future use
+        buf.push(0);
 
         // Magic number (8 bytes)
         buf.extend_from_slice(&MAGIC_NUMBER.to_le_bytes());
@@ -115,7 +171,39 @@ impl Footer {
         let meta_index_handle = BlockHandle::decode(&data[0..16])?;
         let index_handle = BlockHandle::decode(&data[16..32])?;
 
-        Ok(Self { meta_index_handle, index_handle })
+        let checksum_type = ChecksumType::from_u8(data[32]).ok_or_else(|| {
+            Error::corruption(format!("Unknown SSTable checksum type: {}", data[32]))
+        })?;
+
+        let record_key_version = match data[33] {
+            0 => None,
+            1 => Some(u32::from_le_bytes(data[34..38].try_into().unwrap())),
+            flag => {
+                return Err(Error::corruption(format!(
+                    "Unknown SSTable authentication flag: {}",
+                    flag
+                )))
+            }
+        };
+
+        let sequence_numbers = match data[38] {
+            0 => false,
+            1 => true,
+            flag => {
+                return Err(Error::corruption(format!(
+                    "Unknown SSTable sequence number flag: {}",
+                    flag
+                )))
+            }
+        };
+
+        Ok(Self {
+            meta_index_handle,
+            index_handle,
+            checksum_type,
+            record_key_version,
+            sequence_numbers,
+        })
     }
 
     /// Write the footer to a writer
@@ -167,6 +255,49 @@ mod tests {
         assert_eq!(decoded, footer);
     }
 
+    #[test]
+    fn test_footer_checksum_type() {
+        let footer = Footer::new(BlockHandle::new(1000, 100), BlockHandle::new(2000, 200))
+            .with_checksum_type(ChecksumType::Xxh3);
+
+        let mut encoded = footer.encode();
+        assert_eq!(Footer::decode(&encoded).unwrap().checksum_type, ChecksumType::Xxh3);
+
+        // Files without a recorded checksum type use CRC32
+        encoded[32] = 0;
+        assert_eq!(Footer::decode(&encoded).unwrap().checksum_type, ChecksumType::Crc32);
+
+        encoded[32] = 0xff;
+        assert!(matches!(Footer::decode(&encoded), Err(Error::Corruption(_))));
+    }
+
+    #[test]
+    fn test_footer_record_key_version() {
+        let footer = Footer::new(BlockHandle::new(1000, 100), BlockHandle::new(2000, 200))
+            .with_record_key_version(Some(3));
+
+        let encoded = footer.encode();
+        assert_eq!(Footer::decode(&encoded).unwrap(), footer);
+        let plain = Footer::new(BlockHandle::new(1000, 100), BlockHandle::new(2000, 200));
+        assert_eq!(Footer::decode(&plain.encode()).unwrap().record_key_version, None);
+    }
+
+    #[test]
+    fn test_footer_sequence_numbers() {
+        let footer = Footer::new(BlockHandle::new(1000, 100), BlockHandle::new(2000, 200))
+            .with_sequence_numbers(true);
+
+        let mut encoded = footer.encode();
+        assert_eq!(Footer::decode(&encoded).unwrap(), footer);
+
+        // Files written before the flag have a zero byte there
+        encoded[38] = 0;
+        assert!(!Footer::decode(&encoded).unwrap().sequence_numbers);
+
+        encoded[38] = 2;
+        assert!(matches!(Footer::decode(&encoded), Err(Error::Corruption(_))));
+    }
+
     #[test]
     fn test_footer_magic_number() {
         let footer = Footer::new(BlockHandle::new(0, 0), BlockHandle::new(0, 0));
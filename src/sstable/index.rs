@@ -2,10 +2,13 @@
 //!
 //! The index block maps keys to data blocks, enabling efficient lookup.
 
+use crate::comparator::{self, Comparator};
 use crate::error::{Error, Result};
 use crate::sstable::block::{Block, BlockBuilder, BlockIterator};
 use crate::sstable::footer::BlockHandle;
 use bytes::Bytes;
+use std::cmp::Ordering;
+use std::sync::Arc;
 
 /// IndexEntry represents a single entry in the index block.
 ///
@@ -41,13 +44,24 @@ impl IndexEntry {
 #[derive(Debug)]
 pub struct IndexBlock {
     block: Block,
+    comparator: Arc<dyn Comparator>,
 }
 
 impl IndexBlock {
     /// Create a new IndexBlock from raw data
     pub fn new(data: Bytes) -> Result<Self> {
+        Self::with_comparator(data, comparator::default_comparator())
+    }
+
+    /// Create a new IndexBlock from raw data, for keys ordered by `comparator`
+    pub fn with_comparator(data: Bytes, comparator: Arc<dyn Comparator>) -> Result<Self> {
         let block = Block::new(data)?;
-        Ok(Self { block })
+        Ok(Self { block, comparator })
+    }
+
+    /// Sets the order of the keys
+    pub(crate) fn set_comparator(&mut self, comparator: Arc<dyn Comparator>) {
+        self.comparator = comparator;
     }
 
     /// Find the block handle for a given key.
@@ -81,7 +95,7 @@ impl IndexBlock {
                 continue;
             }
 
-            if iter.key() < key {
+            if self.comparator.compare(iter.key(), key) == Ordering::Less {
                 left = mid + 1;
             } else {
                 right = mid;
@@ -119,7 +133,7 @@ impl IndexBlock {
             let entry_key = iter.key();
             let handle = BlockHandle::decode(iter.value())?;
 
-            if entry_key >= key {
+            if self.comparator.compare(entry_key, key) != Ordering::Less {
                 return Ok(Some(handle));
             }
 
@@ -135,6 +149,11 @@ impl IndexBlock {
         IndexIterator::new(self.block.iter())
     }
 
+    /// Size of the index block in bytes
+    pub fn size(&self) -> usize {
+        self.block.size()
+    }
+
     /// Get the number of entries in the index
     pub fn len(&self) -> usize {
         let mut count = 0;
@@ -160,8 +179,13 @@ pub struct IndexBlockBuilder {
 impl IndexBlockBuilder {
     /// Create a new IndexBlockBuilder
     pub fn new() -> Self {
+        Self::with_comparator(comparator::default_comparator())
+    }
+
+    /// Create a new IndexBlockBuilder for keys ordered by `comparator`
+    pub fn with_comparator(comparator: Arc<dyn Comparator>) -> Self {
         // Index blocks use a larger restart interval since they're typically smaller
-        Self { builder: BlockBuilder::new(1) }
+        Self { builder: BlockBuilder::with_comparator(1, comparator) }
     }
 
     /// Add an index entry
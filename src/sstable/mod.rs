@@ -16,6 +16,10 @@
 //! [Footer: 48B]     // Points to index blocks
 //! ```
 //!
+//! When the Bloom filter also holds key prefixes, the meta index block records
+//! the name of the [`PrefixExtractor`](crate::config::PrefixExtractor) that
+//! produced them as `prefix:<name>`.
+//!
 //! ## Block Format
 //!
 //! Each block contains:
@@ -37,13 +41,13 @@ pub mod index;
 pub mod reader;
 
 pub use block::{Block, BlockBuilder, BlockIterator};
-pub use builder::SSTableBuilder;
+pub use builder::{SSTableBuilder, TableMetadata};
 pub use footer::{BlockHandle, Footer};
 pub use index::IndexBlock;
 pub use reader::SSTableReader;
 
-// Re-export CompressionType from config
-pub use crate::config::CompressionType;
+// Re-export CompressionType and ChecksumType from config
+pub use crate::config::{ChecksumType, CompressionType};
 
 /// Default block size (4KB)
 pub const DEFAULT_BLOCK_SIZE: usize = 4096;
@@ -53,3 +57,21 @@ pub const FOOTER_SIZE: usize = 48;
 
 /// Magic number for SSTable files
 pub const MAGIC_NUMBER: u64 = 0x5441424c455f5353; // "SSTABLE_" in hex
+
+/// Marks the meta index entry naming the prefix extractor of the filter
+pub(crate) const PREFIX_EXTRACTOR_TAG: &[u8] = b"prefix:";
+
+/// Suffix of the file an SSTable is written to before it is renamed into place
+pub const TEMP_FILE_SUFFIX: &str = ".tmp";
+
+/// Returns the path an SSTable at `path` is written to while it is being built
+pub fn temp_file_path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(TEMP_FILE_SUFFIX);
+    name.into()
+}
+
+/// Parses the file number from an SSTable name like `000001.sst`
+pub(crate) fn parse_file_number(path: &std::path::Path) -> Option<u64> {
+    path.file_name()?.to_str()?.strip_suffix(".sst")?.parse().ok()
+}
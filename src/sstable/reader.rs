@@ -1,19 +1,54 @@
 //! SSTable reader implementation.
 //!
 //! Reads data from an SSTable file with efficient caching and lookup.
-
-use crate::cache::{BlockCache, CacheKey};
+//!
+//! Tables written with sequence numbers may hold several versions of a key,
+//! newest first. Lookups and [`SSTableReader::iter`] only see the newest
+//! version; [`SSTableReader::lookup_at`] and [`SSTableReader::iter_versions`]
+//! reach the older ones.
+
+use crate::cache::{BlockCache, CacheKey, TableCache};
+use crate::comparator::{self, Comparator};
+use crate::config::{PrefixExtractor, ReadOptions};
+use crate::env::{AccessHint, Env, PosixEnv, RandomAccessFile};
 use crate::error::{Error, Result};
 use crate::filter::{BloomFilter, Filter};
+use crate::perf_context;
+use crate::record_auth::{self, RecordAuthenticator};
 use crate::sstable::block::Block;
 use crate::sstable::footer::{BlockHandle, Footer};
 use crate::sstable::index::IndexBlock;
-use crate::sstable::{CompressionType, FOOTER_SIZE};
+use crate::sstable::TableMetadata;
+use crate::sstable::{ChecksumType, CompressionType, FOOTER_SIZE};
 use bytes::Bytes;
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
-use std::path::Path;
-use std::sync::Arc;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+/// Data blocks read with one batched request by sequential scans
+const READAHEAD_BLOCKS: usize = 8;
+
+/// Size of the sequence number stored after each value
+const SEQUENCE_SIZE: usize = 8;
+
+/// The file of an SSTable: kept open, or borrowed from a [`TableCache`]
+/// that may close it between reads
+#[derive(Debug, Clone)]
+enum TableFile {
+    Open(Arc<dyn RandomAccessFile>),
+    Cached { cache: Arc<TableCache>, file_id: u64, path: PathBuf },
+}
+
+impl TableFile {
+    /// The file handle, reopened if the cache closed it
+    fn handle(&self) -> Result<Arc<dyn RandomAccessFile>> {
+        match self {
+            TableFile::Open(file) => Ok(Arc::clone(file)),
+            TableFile::Cached { cache, file_id, path } => Ok(cache.get(*file_id, path)?),
+        }
+    }
+}
 
 /// SSTableReader provides read access to an SSTable file.
 ///
@@ -47,15 +82,22 @@ use std::sync::Arc;
 /// ```
 #[derive(Debug)]
 pub struct SSTableReader {
-    file: Arc<File>,
+    file: TableFile,
     file_number: u64,
     index_block: IndexBlock,
     bloom_filter: Option<BloomFilter>,
-    #[allow(dead_code)]
+    /// Extractor whose prefixes are in the Bloom filter
+    prefix_extractor: Option<PrefixExtractor>,
     footer: Footer,
     file_size: u64,
     file_path: std::path::PathBuf,
     block_cache: Option<Arc<BlockCache>>,
+    /// Smallest and largest key, read on first use
+    key_range: OnceLock<Option<(Vec<u8>, Vec<u8>)>>,
+    /// Verifies the tags of the values, if they have one
+    record_auth: Option<Arc<RecordAuthenticator>>,
+    /// Order of the keys
+    comparator: Arc<dyn Comparator>,
 }
 
 impl SSTableReader {
@@ -68,38 +110,34 @@ impl SSTableReader {
     pub fn open_with_cache<P: AsRef<Path>>(
         path: P,
         block_cache: Option<Arc<BlockCache>>,
+    ) -> Result<Self> {
+        Self::open_with_env(path, block_cache, &PosixEnv)
+    }
+
+    /// Open an SSTable file for reading through `env`, with optional block cache
+    pub fn open_with_env<P: AsRef<Path>>(
+        path: P,
+        block_cache: Option<Arc<BlockCache>>,
+        env: &dyn Env,
     ) -> Result<Self> {
         let path = path.as_ref();
-        let mut file = File::open(path)?;
+        let file = env.new_random_access_file(path)?;
 
         // Get file size
-        let file_size = file.metadata()?.len();
+        let file_size = file.size()?;
         if file_size < FOOTER_SIZE as u64 {
             return Err(Error::corruption("File too small to be a valid SSTable"));
         }
 
-        // Extract file number from filename
-        // Use path hash as fallback to ensure unique cache keys
-        let file_number = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .and_then(|s| s.strip_suffix(".sst"))
-            .and_then(|n| n.parse::<u64>().ok())
-            .unwrap_or_else(|| {
-                // Fallback: use hash of full path to ensure uniqueness
-                use std::collections::hash_map::DefaultHasher;
-                use std::hash::{Hash, Hasher};
-                let mut hasher = DefaultHasher::new();
-                path.hash(&mut hasher);
-                hasher.finish()
-            });
+        let file_number = cache_file_number(path);
 
         // Read footer from the end of the file
-        file.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
-        let footer = Footer::read_from(&mut file)?;
+        let mut footer_data = [0u8; FOOTER_SIZE];
+        file.read_exact_at(&mut footer_data, file_size - FOOTER_SIZE as u64)?;
+        let footer = Footer::decode(&footer_data)?;
 
         // Read index block
-        let index_data = Self::read_block_data(&mut file, &footer.index_handle)?;
+        let index_data = Self::read_block_data(&*file, &footer.index_handle, footer.checksum_type)?;
         let index_block = IndexBlock::new(index_data)?;
 
         // Read bloom filter from meta block
@@ -127,7 +165,7 @@ impl SSTableReader {
 
             // For now, try to read the meta block assuming it's before the meta index
             // This is a simplified implementation
-            match Self::try_read_bloom_filter(&mut file, &footer) {
+            match Self::try_read_bloom_filter(&*file, &footer) {
                 Ok(Some(filter)) => Some(filter),
                 Ok(None) => None,
                 Err(e) => {
@@ -138,21 +176,138 @@ impl SSTableReader {
         } else {
             None
         };
+        let prefix_extractor = match bloom_filter {
+            Some(_) => Self::read_prefix_extractor(&*file, &footer),
+            None => None,
+        };
 
         Ok(Self {
-            file: Arc::new(file),
+            file: TableFile::Open(file),
             file_number,
             index_block,
             bloom_filter,
+            prefix_extractor,
             footer,
             file_size,
             file_path: path.to_path_buf(),
             block_cache,
+            key_range: OnceLock::new(),
+            record_auth: None,
+            comparator: comparator::default_comparator(),
+        })
+    }
+
+    /// Opens an SSTable that was just written, from the metadata returned by
+    /// [`SSTableBuilder::finish`].
+    ///
+    /// The footer, index, filter and key bounds are taken from `meta`, so
+    /// nothing is read from the file.
+    ///
+    /// [`SSTableBuilder::finish`]: crate::sstable::SSTableBuilder::finish
+    pub fn from_metadata(
+        meta: TableMetadata,
+        block_cache: Option<Arc<BlockCache>>,
+        env: &dyn Env,
+    ) -> Result<Self> {
+        let file = env.new_random_access_file(&meta.path)?;
+        let key_range = match (meta.smallest_key, meta.largest_key) {
+            (Some(smallest), Some(largest)) => Some((smallest, largest)),
+            _ => None,
+        };
+
+        Ok(Self {
+            file: TableFile::Open(file),
+            file_number: cache_file_number(&meta.path),
+            index_block: IndexBlock::new(meta.index_data)?,
+            bloom_filter: meta.bloom_filter,
+            prefix_extractor: meta.prefix_extractor,
+            footer: meta.footer,
+            file_size: meta.file_size,
+            file_path: meta.path,
+            block_cache,
+            key_range: OnceLock::from(key_range),
+            record_auth: None,
+            comparator: comparator::default_comparator(),
         })
     }
 
+    /// Hands the file handle to `cache`, which may close it while the
+    /// table is not read. The index and filter stay in memory.
+    pub fn with_table_cache(mut self, cache: Arc<TableCache>) -> Self {
+        if let TableFile::Open(ref file) = self.file {
+            cache.insert(self.file_number, Arc::clone(file));
+        }
+        self.file =
+            TableFile::Cached { cache, file_id: self.file_number, path: self.file_path.clone() };
+        self
+    }
+
+    /// Verifies the tag of every value read with `authenticator`.
+    ///
+    /// Fails with [`Error::AuthenticationFailed`] if the table's values
+    /// carry no tags. Without an authenticator, tags are removed from the
+    /// values but not checked.
+    pub fn with_record_authenticator(
+        mut self,
+        authenticator: Option<Arc<RecordAuthenticator>>,
+    ) -> Result<Self> {
+        if authenticator.is_some() && self.footer.record_key_version.is_none() {
+            return Err(Error::AuthenticationFailed(format!(
+                "{:?} was written without record authentication",
+                self.file_path
+            )));
+        }
+        self.record_auth = authenticator;
+        Ok(self)
+    }
+
+    /// Searches the keys in the order of `comparator`, the one the table was
+    /// written with (default: bytewise).
+    pub fn with_comparator(mut self, comparator: Arc<dyn Comparator>) -> Self {
+        self.index_block.set_comparator(Arc::clone(&comparator));
+        self.comparator = comparator;
+        self
+    }
+
+    /// The order of the table's keys
+    pub fn comparator(&self) -> &Arc<dyn Comparator> {
+        &self.comparator
+    }
+
+    /// Passes an access hint for the table's file to the OS
+    pub fn advise(&self, hint: AccessHint) -> Result<()> {
+        Ok(self.file.handle()?.advise(hint)?)
+    }
+
     /// Get the value for a key
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.get_with_options(key, &ReadOptions::default())
+    }
+
+    /// Get the value for a key with the given read options
+    pub fn get_with_options(&self, key: &[u8], options: &ReadOptions) -> Result<Option<Vec<u8>>> {
+        // Empty value means tombstone (deleted)
+        Ok(self.lookup_with_options(key, options)?.filter(|value| !value.is_empty()))
+    }
+
+    /// Get the stored value for a key, which is empty for a tombstone
+    pub(crate) fn lookup_with_options(
+        &self,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(self.lookup_at(key, u64::MAX, options)?.map(|(_, value)| value))
+    }
+
+    /// Get the newest stored version of a key written at or before
+    /// `sequence`, with its sequence number (0 if the table has none). The
+    /// value is empty for a tombstone.
+    pub fn lookup_at(
+        &self,
+        key: &[u8],
+        sequence: u64,
+        options: &ReadOptions,
+    ) -> Result<Option<(u64, Vec<u8>)>> {
         // Check bloom filter first (if available)
         if let Some(ref filter) = self.bloom_filter {
             if !filter.may_contain(key) {
@@ -167,44 +322,100 @@ impl SSTableReader {
         };
 
         // Read block with cache support
-        let block_data = self.read_block_cached(&handle)?;
+        let block_data = self.read_block_cached(&handle, options.fill_cache)?;
         let block = Block::new(block_data)?;
 
         // Search for the key in the block
         let mut iter = block.iter();
         iter.seek_to_first();
 
+        let mut newer_versions = false;
         while iter.advance() {
             if iter.key() == key {
-                let value = iter.value().to_vec();
-                // Empty value means tombstone (deleted)
-                if value.is_empty() {
-                    return Ok(None);
+                let (value, version) = self.entry_value(key, iter.value())?;
+                if version <= sequence {
+                    return Ok(Some((version, value.to_vec())));
                 }
-                return Ok(Some(value));
-            }
-            if iter.key() > key {
+                newer_versions = true;
+            } else if self.comparator.compare(iter.key(), key) == Ordering::Greater {
                 // Key doesn't exist
                 return Ok(None);
             }
         }
+        if !newer_versions {
+            return Ok(None);
+        }
 
+        // The older versions continue in the next blocks
+        let mut iter = self.iter_versions(options);
+        iter.seek(key)?;
+        while iter.valid() && iter.key() == key {
+            if iter.sequence() <= sequence {
+                return Ok(Some((iter.sequence(), iter.value().to_vec())));
+            }
+            iter.advance()?;
+        }
         Ok(None)
     }
 
-    /// Read raw block data from the file
-    fn read_block_data(file: &mut File, handle: &BlockHandle) -> Result<Bytes> {
-        // Seek to block offset
-        file.seek(SeekFrom::Start(handle.offset))?;
+    /// Returns the value and sequence number of a stored entry
+    fn entry_value<'a>(&self, key: &[u8], stored: &'a [u8]) -> Result<(&'a [u8], u64)> {
+        let value =
+            record_value(self.record_auth.as_deref(), self.footer.record_key_version, key, stored)?;
+        if !self.footer.sequence_numbers {
+            return Ok((value, 0));
+        }
+        split_sequence(value)
+    }
 
+    /// Read raw block data from the file
+    fn read_block_data(
+        file: &dyn RandomAccessFile,
+        handle: &BlockHandle,
+        checksum_type: ChecksumType,
+    ) -> Result<Bytes> {
         // Read block data + compression type (1 byte) + checksum (4 bytes)
         let total_size = handle.size as usize;
         if total_size < 5 {
             return Err(Error::corruption("Block size too small"));
         }
 
+        // Positional read: the file handle is shared between threads
         let mut buffer = vec![0u8; total_size];
-        file.read_exact(&mut buffer)?;
+        file.read_exact_at(&mut buffer, handle.offset)?;
+        Self::decode_block(&buffer, checksum_type)
+    }
+
+    /// Read several blocks with one batched request to the file
+    ///
+    /// Returns one result per handle. If the batched read fails, the blocks
+    /// are read one by one so that the error is reported for the right
+    /// block.
+    fn read_blocks(
+        file: &dyn RandomAccessFile,
+        handles: &[BlockHandle],
+        checksum_type: ChecksumType,
+    ) -> Vec<Result<Bytes>> {
+        if handles.iter().any(|handle| handle.size < 5) {
+            return handles.iter().map(|h| Self::read_block_data(file, h, checksum_type)).collect();
+        }
+
+        let mut buffers: Vec<Vec<u8>> =
+            handles.iter().map(|handle| vec![0u8; handle.size as usize]).collect();
+        let mut reads: Vec<(u64, &mut [u8])> = handles
+            .iter()
+            .zip(buffers.iter_mut())
+            .map(|(handle, buffer)| (handle.offset, buffer.as_mut_slice()))
+            .collect();
+        if file.read_exact_at_batch(&mut reads).is_err() {
+            return handles.iter().map(|h| Self::read_block_data(file, h, checksum_type)).collect();
+        }
+        buffers.iter().map(|buffer| Self::decode_block(buffer, checksum_type)).collect()
+    }
+
+    /// Verify and decompress a block read from the file
+    fn decode_block(buffer: &[u8], checksum_type: ChecksumType) -> Result<Bytes> {
+        let total_size = buffer.len();
 
         // Extract components
         // Layout: [data...][compression_type: 1 byte][checksum: 4 bytes]
@@ -215,7 +426,7 @@ impl SSTableReader {
         let stored_checksum = u32::from_le_bytes(checksum_bytes.try_into().unwrap());
 
         // Verify checksum (computed on the compressed data)
-        let computed_checksum = crc32fast::hash(data);
+        let computed_checksum = checksum_type.checksum(data);
         if computed_checksum != stored_checksum {
             return Err(Error::ChecksumMismatch {
                 expected: stored_checksum,
@@ -260,7 +471,10 @@ impl SSTableReader {
     }
 
     /// Try to read the bloom filter from the meta block
-    fn try_read_bloom_filter(file: &mut File, footer: &Footer) -> Result<Option<BloomFilter>> {
+    fn try_read_bloom_filter(
+        file: &dyn RandomAccessFile,
+        footer: &Footer,
+    ) -> Result<Option<BloomFilter>> {
         // The meta block handle is stored in the footer, but it points to the meta index
         // We need to read the actual meta block which comes before the meta index
 
@@ -282,8 +496,12 @@ impl SSTableReader {
         // The meta block starts right after the last data block
         // We can get the offset from the last index entry
 
-        let mut index_iter =
-            IndexBlock::new(Self::read_block_data(file, &footer.index_handle)?)?.iter();
+        let mut index_iter = IndexBlock::new(Self::read_block_data(
+            file,
+            &footer.index_handle,
+            footer.checksum_type,
+        )?)?
+        .iter();
         index_iter.seek_to_first();
 
         let mut last_data_block_end = 0u64;
@@ -309,7 +527,7 @@ impl SSTableReader {
         let meta_block_handle = BlockHandle::new(meta_block_offset, meta_block_size);
 
         // Try to read the meta block
-        let meta_data = Self::read_block_data(file, &meta_block_handle)?;
+        let meta_data = Self::read_block_data(file, &meta_block_handle, footer.checksum_type)?;
 
         // Try to decode as bloom filter
         if meta_data.len() > 12 {
@@ -322,35 +540,71 @@ impl SSTableReader {
         }
     }
 
-    /// Read block data using an Arc<File> (for concurrent access)
-    fn read_block_with_handle(file: &Arc<File>, handle: &BlockHandle) -> Result<Bytes> {
-        // Clone the file descriptor for this read operation
-        let mut file_clone = file.try_clone().map_err(Error::Io)?;
+    /// Reads the prefix extractor named in the meta index block, if any
+    fn read_prefix_extractor(
+        file: &dyn RandomAccessFile,
+        footer: &Footer,
+    ) -> Option<PrefixExtractor> {
+        let data =
+            match Self::read_block_data(file, &footer.meta_index_handle, footer.checksum_type) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::warn!("Failed to read meta index block: {}", e);
+                    return None;
+                }
+            };
+        let name = data.strip_prefix(super::PREFIX_EXTRACTOR_TAG)?;
+        let extractor = std::str::from_utf8(name).ok().and_then(PrefixExtractor::from_name);
+        if extractor.is_none() {
+            log::warn!("Unknown prefix extractor in meta index: {:?}", name);
+        }
+        extractor
+    }
 
-        Self::read_block_data(&mut file_clone, handle)
+    /// Read block data using a shared file handle (for concurrent access)
+    fn read_block_with_handle(
+        file: &Arc<dyn RandomAccessFile>,
+        handle: &BlockHandle,
+        checksum_type: ChecksumType,
+    ) -> Result<Bytes> {
+        Self::read_block_data(&**file, handle, checksum_type)
     }
 
     /// Read a block with caching support
-    fn read_block_cached(&self, handle: &BlockHandle) -> Result<Bytes> {
+    ///
+    /// A block read from the file is added to the cache if `fill_cache` is set.
+    fn read_block_cached(&self, handle: &BlockHandle, fill_cache: bool) -> Result<Bytes> {
         if let Some(ref cache) = self.block_cache {
             let cache_key = CacheKey::new(self.file_number, handle.offset);
 
             // Check cache
             if let Some(cached_data) = cache.get(&cache_key) {
+                perf_context::update(|perf| perf.block_cache_hits += 1);
                 return Ok(cached_data);
             }
 
             // Cache miss - read from file
-            let data = Self::read_block_with_handle(&self.file, handle)?;
+            let data = self.read_block_from_file(handle)?;
             // Insert into cache for future reads
-            cache.insert(cache_key, data.clone());
+            if fill_cache {
+                cache.insert(cache_key, data.clone());
+            }
             Ok(data)
         } else {
             // No cache - read directly from file
-            Self::read_block_with_handle(&self.file, handle)
+            self.read_block_from_file(handle)
         }
     }
 
+    /// Read a data block from disk, counting it in the perf context
+    fn read_block_from_file(&self, handle: &BlockHandle) -> Result<Bytes> {
+        perf_context::update(|perf| {
+            perf.block_reads += 1;
+            perf.block_read_bytes += handle.size;
+        });
+        Self::read_block_with_handle(&self.file.handle()?, handle, self.footer.checksum_type)
+    }
+
     /// Get the number of data blocks
     pub fn num_blocks(&self) -> usize {
         self.index_block.len()
@@ -371,13 +625,116 @@ impl SSTableReader {
     /// Extracts the file number from filenames like "000001.sst"
     /// Returns None if the filename doesn't match the expected pattern
     pub fn file_number(&self) -> Option<u64> {
-        let filename = self.file_path.file_name()?.to_str()?;
-        let num_str = filename.strip_suffix(".sst")?;
-        num_str.parse::<u64>().ok()
+        super::parse_file_number(&self.file_path)
+    }
+
+    /// Re-read blocks from disk and validate their checksums
+    ///
+    /// Reads bypass the block cache. If `range` is given, only data blocks
+    /// that may hold keys in `[start, end)` are checked; the index block is
+    /// always checked. Returns the offset and error of every block that
+    /// failed validation.
+    pub fn verify_checksums(&self, range: Option<(&[u8], &[u8])>) -> Result<Vec<(u64, Error)>> {
+        let mut corruptions = Vec::new();
+        let file = self.file.handle()?;
+
+        if let Err(e) = Self::read_block_with_handle(
+            &file,
+            &self.footer.index_handle,
+            self.footer.checksum_type,
+        ) {
+            corruptions.push((self.footer.index_handle.offset, e));
+        }
+
+        let mut iter = self.index_block.iter();
+        iter.seek_to_first();
+
+        // Each index entry holds the largest key of its block, so a block
+        // covers the keys after the previous entry's key up to its own
+        let mut previous_largest: Option<Vec<u8>> = None;
+        let mut handles = Vec::new();
+        while iter.advance() {
+            let entry = iter.entry()?;
+
+            let in_range = match range {
+                Some((start, end)) => {
+                    self.comparator.compare(&entry.key, start) != Ordering::Less
+                        && previous_largest
+                            .as_deref()
+                            .is_none_or(|prev| self.comparator.compare(prev, end) == Ordering::Less)
+                }
+                None => true,
+            };
+
+            if in_range {
+                handles.push(entry.handle);
+            }
+
+            previous_largest = Some(entry.key);
+        }
+
+        for chunk in handles.chunks(READAHEAD_BLOCKS) {
+            let results = Self::read_blocks(&*file, chunk, self.footer.checksum_type);
+            for (handle, result) in chunk.iter().zip(results) {
+                if let Err(e) = result.and_then(|data| self.verify_records(data)) {
+                    corruptions.push((handle.offset, e));
+                }
+            }
+        }
+
+        Ok(corruptions)
+    }
+
+    /// Checks the tags and sequence numbers of the values in the data
+    /// block `data`
+    fn verify_records(&self, data: Bytes) -> Result<()> {
+        if self.footer.record_key_version.is_none() && !self.footer.sequence_numbers {
+            return Ok(());
+        }
+        let mut iter = Block::new(data)?.iter();
+        iter.seek_to_first();
+        while iter.advance() {
+            self.entry_value(iter.key(), iter.value())?;
+        }
+        Ok(())
+    }
+
+    /// Get the checksum algorithm used by this SSTable's blocks
+    pub fn checksum_type(&self) -> ChecksumType {
+        self.footer.checksum_type
+    }
+
+    /// Get the compression type id recorded in the first data block's trailer
+    ///
+    /// Returns the raw id so that files written with a compression type that
+    /// is not enabled in this build can still be identified. Returns `None`
+    /// if the SSTable has no data blocks.
+    pub fn data_compression_id(&self) -> Result<Option<u8>> {
+        let mut iter = self.index_block.iter();
+        iter.seek_to_first();
+
+        if !iter.advance() {
+            return Ok(None);
+        }
+
+        let handle = iter.entry()?.handle;
+        if handle.size < 5 {
+            return Err(Error::corruption("Block size too small"));
+        }
+
+        let mut compression = [0u8; 1];
+        self.file
+            .handle()?
+            .read_exact_at(&mut compression, handle.offset + handle.size - 5)?;
+
+        Ok(Some(compression[0]))
     }
 
     /// Get the smallest key in the SSTable
     pub fn smallest_key(&self) -> Result<Option<Vec<u8>>> {
+        if let Some(range) = self.key_range.get() {
+            return Ok(range.as_ref().map(|(smallest, _)| smallest.clone()));
+        }
         let mut iter = self.index_block.iter();
         iter.seek_to_first();
 
@@ -389,7 +746,7 @@ impl SSTableReader {
         let handle = entry.handle;
 
         // Read the first data block with cache support
-        let block_data = self.read_block_cached(&handle)?;
+        let block_data = self.read_block_cached(&handle, true)?;
         let block = Block::new(block_data)?;
 
         let mut block_iter = block.iter();
@@ -404,6 +761,9 @@ impl SSTableReader {
 
     /// Get the largest key in the SSTable
     pub fn largest_key(&self) -> Result<Option<Vec<u8>>> {
+        if let Some(range) = self.key_range.get() {
+            return Ok(range.as_ref().map(|(_, largest)| largest.clone()));
+        }
         let mut iter = self.index_block.iter();
         iter.seek_to_first();
 
@@ -420,6 +780,49 @@ impl SSTableReader {
         Ok(Some(entry.key))
     }
 
+    /// Returns the smallest and largest key, or `None` if the SSTable is
+    /// empty.
+    ///
+    /// The bounds are read once and then kept in memory, so this is cheap
+    /// enough to call on every lookup.
+    pub fn key_range(&self) -> Result<Option<(&[u8], &[u8])>> {
+        if self.key_range.get().is_none() {
+            let range = match (self.smallest_key()?, self.largest_key()?) {
+                (Some(smallest), Some(largest)) => Some((smallest, largest)),
+                _ => None,
+            };
+            // Another thread may have set it first; the value is the same
+            let _ = self.key_range.set(range);
+        }
+        let range = self.key_range.get().expect("set above").as_ref();
+        Ok(range.map(|(smallest, largest)| (smallest.as_slice(), largest.as_slice())))
+    }
+
+    /// Returns false if the Bloom filter rules out `key`.
+    ///
+    /// Always true when the SSTable has no Bloom filter.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.bloom_filter.as_ref().is_none_or(|filter| filter.may_contain(key))
+    }
+
+    /// Returns false if the Bloom filter rules out keys with `prefix`, which
+    /// `extractor` returned for some key.
+    ///
+    /// Always true unless the filter was built with the same extractor.
+    pub fn may_contain_prefix(&self, extractor: &PrefixExtractor, prefix: &[u8]) -> bool {
+        match (&self.bloom_filter, &self.prefix_extractor) {
+            (Some(filter), Some(built_with)) if built_with == extractor => {
+                filter.may_contain(prefix)
+            }
+            _ => true,
+        }
+    }
+
+    /// Bytes held in memory by the index block and Bloom filter
+    pub fn index_and_filter_size(&self) -> usize {
+        self.index_block.size() + self.bloom_filter.as_ref().map_or(0, |filter| filter.size())
+    }
+
     /// Check if bloom filter is available
     pub fn has_bloom_filter(&self) -> bool {
         self.bloom_filter.is_some()
@@ -441,23 +844,56 @@ impl SSTableReader {
         Ok(keys)
     }
 
+    /// Whether the values carry the sequence numbers of their writes
+    pub fn has_sequence_numbers(&self) -> bool {
+        self.footer.sequence_numbers
+    }
+
     /// Create an iterator over all key-value pairs
     pub fn iter(&self) -> SSTableIterator {
-        SSTableIterator::new(self)
+        self.iter_with_options(&ReadOptions::default())
+    }
+
+    /// Create an iterator over all key-value pairs with the given read options
+    ///
+    /// Blocks are taken from the block cache when present; blocks read from
+    /// the file are added to it if `options.fill_cache` is set. Only the
+    /// newest version of each key is returned.
+    pub fn iter_with_options(&self, options: &ReadOptions) -> SSTableIterator {
+        SSTableIterator::new(self, options, false)
+    }
+
+    /// Create an iterator over every version of every key, the versions of
+    /// a key from the newest down
+    pub fn iter_versions(&self, options: &ReadOptions) -> SSTableIterator {
+        SSTableIterator::new(self, options, true)
     }
 }
 
 /// Iterator over all entries in an SSTable
 pub struct SSTableIterator {
-    file: Arc<File>,
+    file: TableFile,
+    file_number: u64,
+    checksum_type: ChecksumType,
+    block_cache: Option<Arc<BlockCache>>,
+    fill_cache: bool,
     index_iter_entries: Vec<(Vec<u8>, BlockHandle)>,
     current_block_index: usize,
     current_block: Option<Block>,
     current_block_iter: Option<crate::sstable::block::BlockIterator>,
+    /// Blocks read ahead of `current_block_index`, in order
+    readahead: VecDeque<Result<Bytes>>,
+    record_auth: Option<Arc<RecordAuthenticator>>,
+    record_key_version: Option<u32>,
+    comparator: Arc<dyn Comparator>,
+    /// Whether the values carry sequence numbers
+    sequence_numbers: bool,
+    /// Return the older versions of keys too
+    all_versions: bool,
 }
 
 impl SSTableIterator {
-    fn new(reader: &SSTableReader) -> Self {
+    fn new(reader: &SSTableReader, options: &ReadOptions, all_versions: bool) -> Self {
         // Collect all index entries upfront
         let mut entries = Vec::new();
         let mut index_iter = reader.index_block.iter();
@@ -470,17 +906,28 @@ impl SSTableIterator {
         }
 
         Self {
-            file: Arc::clone(&reader.file),
+            file: reader.file.clone(),
+            file_number: reader.file_number,
+            checksum_type: reader.footer.checksum_type,
+            block_cache: reader.block_cache.clone(),
+            fill_cache: options.fill_cache,
             index_iter_entries: entries,
             current_block_index: 0,
             current_block: None,
             current_block_iter: None,
+            readahead: VecDeque::new(),
+            record_auth: reader.record_auth.clone(),
+            record_key_version: reader.footer.record_key_version,
+            comparator: Arc::clone(&reader.comparator),
+            sequence_numbers: reader.footer.sequence_numbers,
+            all_versions,
         }
     }
 
     /// Seek to the first entry
     pub fn seek_to_first(&mut self) -> Result<()> {
         self.current_block_index = 0;
+        self.readahead.clear();
         self.load_current_block()?;
         Ok(())
     }
@@ -493,9 +940,22 @@ impl SSTableIterator {
             return Ok(());
         }
 
-        let (_, handle) = &self.index_iter_entries[self.current_block_index];
-        let block_data = SSTableReader::read_block_with_handle(&self.file, handle)?;
-        let block = Block::new(block_data)?;
+        if self.readahead.is_empty() {
+            let end =
+                (self.current_block_index + READAHEAD_BLOCKS).min(self.index_iter_entries.len());
+            let handles: Vec<BlockHandle> = self.index_iter_entries[self.current_block_index..end]
+                .iter()
+                .map(|(_, handle)| *handle)
+                .collect();
+            self.readahead = self.read_blocks(&handles).into();
+        }
+        let block_data = self.readahead.pop_front().expect("read ahead above")?;
+        self.set_block(block_data)
+    }
+
+    /// Makes `data` the current block, positioned before its first entry
+    fn set_block(&mut self, data: Bytes) -> Result<()> {
+        let block = Block::new(data)?;
 
         let mut iter = block.iter();
         iter.seek_to_first();
@@ -506,10 +966,215 @@ impl SSTableIterator {
         Ok(())
     }
 
-    /// Move to the next entry
+    /// Position on the first entry with a key `>= target`.
+    ///
+    /// Unlike [`seek_to_first`](Self::seek_to_first), which positions before
+    /// the first entry, this and the other positioning methods below leave
+    /// the iterator on the entry found, or invalid if there is none.
+    pub fn seek(&mut self, target: &[u8]) -> Result<()> {
+        let comparator = Arc::clone(&self.comparator);
+        self.current_block_index = self.index_iter_entries.partition_point(|(last_key, _)| {
+            comparator.compare(last_key, target) == Ordering::Less
+        });
+        self.readahead.clear();
+        self.load_current_block()?;
+        while self.next_entry()? {
+            if comparator.compare(self.key(), target) != Ordering::Less {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Position on the last entry with a key `<= target`.
+    pub fn seek_for_prev(&mut self, target: &[u8]) -> Result<()> {
+        let Some(last) = self.index_iter_entries.len().checked_sub(1) else {
+            self.current_block_iter = None;
+            return Ok(());
+        };
+        let comparator = Arc::clone(&self.comparator);
+        let index = self
+            .index_iter_entries
+            .partition_point(|(last_key, _)| comparator.compare(last_key, target) == Ordering::Less)
+            .min(last);
+        if !self.last_entry_in_block(index, |key| {
+            comparator.compare(key, target) != Ordering::Greater
+        })? && index > 0
+        {
+            self.last_entry_in_block(index - 1, |_| true)?;
+        }
+        self.seek_newest_version()
+    }
+
+    /// Position on the last entry
+    pub fn seek_to_last(&mut self) -> Result<()> {
+        match self.index_iter_entries.len().checked_sub(1) {
+            Some(last) => {
+                self.last_entry_in_block(last, |_| true)?;
+                self.seek_newest_version()
+            }
+            None => {
+                self.current_block_iter = None;
+                Ok(())
+            }
+        }
+    }
+
+    /// Move to the previous entry
+    ///
+    /// Blocks can only be decoded forwards, so this scans the current block
+    /// up to the current entry (or the previous block when at its start).
+    /// This, [`seek_for_prev`](Self::seek_for_prev) and
+    /// [`seek_to_last`](Self::seek_to_last) position on the newest version
+    /// of a key, or the oldest when iterating all versions.
+    pub fn prev(&mut self) -> Result<bool> {
+        if !self.valid() {
+            return Ok(false);
+        }
+        let key = self.key().to_vec();
+        let index = self.current_block_index;
+        let comparator = Arc::clone(&self.comparator);
+        if !self.last_entry_in_block(index, |k| comparator.compare(k, &key) == Ordering::Less)?
+            && index > 0
+        {
+            self.last_entry_in_block(index - 1, |_| true)?;
+        }
+        self.seek_newest_version()?;
+        Ok(self.valid())
+    }
+
+    /// Moves from a version of the current key to its newest version, which
+    /// may be in an earlier block, unless all versions are iterated
+    fn seek_newest_version(&mut self) -> Result<()> {
+        if !self.sequence_numbers || self.all_versions || !self.valid() {
+            return Ok(());
+        }
+        let key = self.key().to_vec();
+        // Blocks whose last key is the current key may hold newer versions
+        let mut index = self.current_block_index;
+        while index > 0 && self.index_iter_entries[index - 1].0 == key {
+            index -= 1;
+        }
+        if index == self.current_block_index {
+            self.current_block_iter
+                .as_mut()
+                .expect("positioned on an entry")
+                .seek_to_first();
+        } else {
+            self.current_block_index = index;
+            self.readahead.clear();
+            let handle = self.index_iter_entries[index].1;
+            let data = self.read_blocks(&[handle]).pop().expect("one block requested")?;
+            self.set_block(data)?;
+        }
+
+        let iter = self.current_block_iter.as_mut().expect("block set above");
+        while iter.advance() && iter.key() != key {}
+        if iter.valid() {
+            self.check_record()?;
+        }
+        Ok(())
+    }
+
+    /// Positions on the last entry of block `index` whose key satisfies
+    /// `pred`, which must hold for a prefix of the block's keys.
+    ///
+    /// Returns `false`, leaving the iterator invalid, if no key does.
+    fn last_entry_in_block(&mut self, index: usize, pred: impl Fn(&[u8]) -> bool) -> Result<bool> {
+        self.current_block_index = index;
+        self.readahead.clear();
+        let handle = self.index_iter_entries[index].1;
+        let data = self.read_blocks(&[handle]).pop().expect("one block requested")?;
+        self.set_block(data)?;
+
+        let iter = self.current_block_iter.as_mut().expect("block set above");
+        let mut matching = 0;
+        while iter.advance() && pred(iter.key()) {
+            matching += 1;
+        }
+        iter.seek_to_first();
+        for _ in 0..matching {
+            iter.advance();
+        }
+        if matching == 0 {
+            self.current_block_iter = None;
+        } else {
+            self.check_record()?;
+        }
+        Ok(matching > 0)
+    }
+
+    /// Reads the blocks at `handles`, in order.
+    ///
+    /// Blocks in the block cache are taken from it; the rest are read from
+    /// the file with one batched request and cached if `fill_cache` is set.
+    fn read_blocks(&self, handles: &[BlockHandle]) -> Vec<Result<Bytes>> {
+        let cache = match self.block_cache {
+            Some(ref cache) => cache,
+            None => return self.read_blocks_from_file(handles),
+        };
+
+        let mut blocks: Vec<Option<Result<Bytes>>> = handles
+            .iter()
+            .map(|handle| cache.get(&CacheKey::new(self.file_number, handle.offset)).map(Ok))
+            .collect();
+        let hits = blocks.iter().filter(|block| block.is_some()).count() as u64;
+        perf_context::update(|perf| perf.block_cache_hits += hits);
+
+        let missing: Vec<BlockHandle> = handles
+            .iter()
+            .zip(&blocks)
+            .filter(|(_, block)| block.is_none())
+            .map(|(handle, _)| *handle)
+            .collect();
+        let mut read = self.read_blocks_from_file(&missing).into_iter();
+        for (handle, block) in handles.iter().zip(blocks.iter_mut()) {
+            if block.is_none() {
+                let data = read.next().expect("one read per missing block");
+                if let (true, Ok(data)) = (self.fill_cache, &data) {
+                    cache.insert(CacheKey::new(self.file_number, handle.offset), data.clone());
+                }
+                *block = Some(data);
+            }
+        }
+        blocks.into_iter().map(|block| block.expect("filled above")).collect()
+    }
+
+    /// Reads blocks from the file, counting them in the perf context
+    fn read_blocks_from_file(&self, handles: &[BlockHandle]) -> Vec<Result<Bytes>> {
+        if handles.is_empty() {
+            return Vec::new();
+        }
+        perf_context::update(|perf| {
+            perf.block_reads += handles.len() as u64;
+            perf.block_read_bytes += handles.iter().map(|handle| handle.size).sum::<u64>();
+        });
+        match self.file.handle() {
+            Ok(file) => SSTableReader::read_blocks(&*file, handles, self.checksum_type),
+            Err(e) => handles.iter().map(|_| Err(e.clone())).collect(),
+        }
+    }
+
+    /// Move to the next entry, skipping the older versions of the current
+    /// key unless all versions are iterated
     pub fn advance(&mut self) -> Result<bool> {
+        if !self.sequence_numbers || self.all_versions || !self.valid() {
+            return self.next_entry();
+        }
+        let key = self.key().to_vec();
+        while self.next_entry()? {
+            if self.key() != key.as_slice() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Move to the next stored entry
+    fn next_entry(&mut self) -> Result<bool> {
         if let Some(ref mut iter) = self.current_block_iter {
             if iter.advance() {
+                self.check_record()?;
                 return Ok(true);
             }
         }
@@ -518,11 +1183,29 @@ impl SSTableIterator {
         self.current_block_index += 1;
         self.load_current_block()?;
 
-        if let Some(ref mut iter) = self.current_block_iter {
-            Ok(iter.advance())
-        } else {
-            Ok(false)
+        let advanced = self.current_block_iter.as_mut().is_some_and(|iter| iter.advance());
+        if advanced {
+            self.check_record()?;
+        }
+        Ok(advanced)
+    }
+
+    /// Checks the tag and sequence number of the current entry's value, if
+    /// it has them
+    fn check_record(&self) -> Result<()> {
+        if self.record_key_version.is_some() || self.sequence_numbers {
+            let iter = self.current_block_iter.as_ref().expect("positioned on an entry");
+            let value = record_value(
+                self.record_auth.as_deref(),
+                self.record_key_version,
+                iter.key(),
+                iter.value(),
+            )?;
+            if self.sequence_numbers {
+                split_sequence(value)?;
+            }
         }
+        Ok(())
     }
 
     /// Check if the iterator is valid
@@ -537,15 +1220,89 @@ impl SSTableIterator {
 
     /// Get the current value
     pub fn value(&self) -> &[u8] {
-        self.current_block_iter.as_ref().unwrap().value()
+        let value = self.stored_value();
+        match self.sequence_numbers {
+            true => &value[..value.len() - SEQUENCE_SIZE],
+            false => value,
+        }
+    }
+
+    /// Get the sequence number of the current entry's write, 0 if the
+    /// table was written without sequence numbers
+    pub fn sequence(&self) -> u64 {
+        if !self.sequence_numbers {
+            return 0;
+        }
+        let value = self.stored_value();
+        u64::from_le_bytes(value[value.len() - SEQUENCE_SIZE..].try_into().unwrap())
+    }
+
+    /// The current value with its sequence number, without its tag
+    fn stored_value(&self) -> &[u8] {
+        let value = self.current_block_iter.as_ref().unwrap().value();
+        match self.record_key_version {
+            // The length was checked when the iterator was positioned
+            Some(_) => &value[..value.len() - record_auth::TAG_SIZE],
+            None => value,
+        }
     }
 }
 
+/// Returns the value of a stored entry: `stored` itself if the table's
+/// records are not authenticated, otherwise the part before the tag, which
+/// is verified if there is an authenticator.
+fn record_value<'a>(
+    authenticator: Option<&RecordAuthenticator>,
+    key_version: Option<u32>,
+    key: &[u8],
+    stored: &'a [u8],
+) -> Result<&'a [u8]> {
+    match (key_version, authenticator) {
+        (None, _) => Ok(stored),
+        (Some(version), Some(authenticator)) => authenticator.verify(version, key, stored),
+        (Some(_), None) => Ok(record_auth::split_tag(stored)?.0),
+    }
+}
+
+/// Splits a stored value (without its tag) into the value and the sequence
+/// number that follows it.
+fn split_sequence(stored: &[u8]) -> Result<(&[u8], u64)> {
+    let split = stored
+        .len()
+        .checked_sub(SEQUENCE_SIZE)
+        .ok_or_else(|| Error::corruption("Value too short to hold its sequence number"))?;
+    let sequence = u64::from_le_bytes(stored[split..].try_into().unwrap());
+    Ok((&stored[..split], sequence))
+}
+
+/// Number identifying a table's blocks in the block cache.
+///
+/// Tables whose name carries no file number fall back to a hash of the full
+/// path, so cache keys stay unique.
+impl Drop for SSTableReader {
+    fn drop(&mut self) {
+        // Close the file, e.g. before it is deleted
+        if let TableFile::Cached { ref cache, file_id, .. } = self.file {
+            cache.erase(file_id);
+        }
+    }
+}
+
+fn cache_file_number(path: &Path) -> u64 {
+    super::parse_file_number(path).unwrap_or_else(|| {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        hasher.finish()
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::sstable::SSTableBuilder;
-    use tempfile::NamedTempFile;
+    use tempfile::{NamedTempFile, TempDir};
 
     fn create_test_sstable(entries: &[(&[u8], &[u8])]) -> NamedTempFile {
         let temp_file = NamedTempFile::new().unwrap();
@@ -573,6 +1330,38 @@ mod tests {
         assert_eq!(reader.num_blocks(), 1);
     }
 
+    #[test]
+    fn test_from_metadata_matches_open() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("000007.sst");
+        let mut builder = SSTableBuilder::new(&path).unwrap();
+        builder.set_block_size(64);
+        for i in 0..100 {
+            builder.add(format!("key{:03}", i).as_bytes(), b"value").unwrap();
+        }
+        let meta = builder.finish().unwrap();
+        assert_eq!(meta.file_number, Some(7));
+        assert_eq!(meta.num_entries, 100);
+        assert_eq!(meta.smallest_key.as_deref(), Some(&b"key000"[..]));
+        assert_eq!(meta.largest_key.as_deref(), Some(&b"key099"[..]));
+
+        let opened = SSTableReader::open(&path).unwrap();
+        let built = SSTableReader::from_metadata(meta, None, &PosixEnv).unwrap();
+        assert_eq!(built.file_size(), opened.file_size());
+        assert_eq!(built.file_number(), opened.file_number());
+        assert_eq!(built.footer, opened.footer);
+        assert_eq!(built.num_blocks(), opened.num_blocks());
+        assert_eq!(built.key_range().unwrap(), opened.key_range().unwrap());
+        assert_eq!(built.smallest_key().unwrap(), opened.smallest_key().unwrap());
+        assert_eq!(built.largest_key().unwrap(), opened.largest_key().unwrap());
+        assert!(built.bloom_filter.is_some());
+        for i in 0..100 {
+            let key = format!("key{:03}", i);
+            assert_eq!(built.get(key.as_bytes()).unwrap(), Some(b"value".to_vec()));
+        }
+        assert_eq!(built.get(b"key100").unwrap(), None);
+    }
+
     #[test]
     fn test_sstable_reader_get() {
         let entries = vec![
@@ -606,6 +1395,28 @@ mod tests {
         assert_eq!(reader.largest_key().unwrap(), Some(b"cherry".to_vec()));
     }
 
+    #[test]
+    fn test_sstable_reader_checksum_types() {
+        for checksum_type in [ChecksumType::Crc32, ChecksumType::Crc32c, ChecksumType::Xxh3] {
+            let temp_file = NamedTempFile::new().unwrap();
+            let mut builder = SSTableBuilder::new(temp_file.path()).unwrap();
+            builder.set_block_size(256);
+            builder.set_checksum_type(checksum_type);
+            for i in 0..100 {
+                builder.add(format!("key{:04}", i).as_bytes(), b"value").unwrap();
+            }
+            builder.finish().unwrap();
+
+            let reader = SSTableReader::open(temp_file.path()).unwrap();
+            assert_eq!(reader.checksum_type(), checksum_type);
+            assert_eq!(reader.get(b"key0042").unwrap(), Some(b"value".to_vec()));
+            for i in 0..100 {
+                assert!(reader.get(format!("key{:04}", i).as_bytes()).unwrap().is_some());
+            }
+            assert!(reader.verify_checksums(None).unwrap().is_empty());
+        }
+    }
+
     #[test]
     fn test_sstable_reader_large_dataset() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -631,6 +1442,223 @@ mod tests {
         assert_eq!(reader.get(b"key00000999").unwrap(), Some(b"value00000999".to_vec()));
     }
 
+    #[test]
+    fn test_sstable_readahead_across_batches() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut builder = SSTableBuilder::new(temp_file.path()).unwrap();
+        builder.set_block_size(256);
+        for i in 0..1000 {
+            builder.add(format!("key{:08}", i).as_bytes(), b"value").unwrap();
+        }
+        builder.finish().unwrap();
+
+        let reader = SSTableReader::open(temp_file.path()).unwrap();
+        assert!(reader.num_blocks() > 3 * READAHEAD_BLOCKS);
+        let mut iter = reader.iter();
+        iter.seek_to_first().unwrap();
+        let mut keys = Vec::new();
+        while iter.advance().unwrap() {
+            keys.push(iter.key().to_vec());
+        }
+        assert_eq!(keys.len(), 1000);
+        assert_eq!(keys[999], b"key00000999");
+
+        // Corrupt one block in the middle of a readahead batch
+        let mut index = reader.index_block.iter();
+        index.seek_to_first();
+        let mut handles = Vec::new();
+        while index.advance() {
+            handles.push(index.entry().unwrap().handle);
+        }
+        let corrupt = handles[READAHEAD_BLOCKS + 3];
+        let mut data = std::fs::read(temp_file.path()).unwrap();
+        data[corrupt.offset as usize] ^= 0xFF;
+        std::fs::write(temp_file.path(), data).unwrap();
+
+        let reader = SSTableReader::open(temp_file.path()).unwrap();
+        let corruptions = reader.verify_checksums(None).unwrap();
+        assert_eq!(corruptions.len(), 1);
+        assert_eq!(corruptions[0].0, corrupt.offset);
+        assert!(reader.keys().is_err());
+    }
+
+    #[test]
+    fn test_prefix_bloom_filter() {
+        let dir = TempDir::new().unwrap();
+        let extractor = PrefixExtractor::Delimited { delimiter: b':', count: 1 };
+        let build = |name: &str, extractor: PrefixExtractor| {
+            let mut builder = SSTableBuilder::new(dir.path().join(name)).unwrap();
+            builder.set_prefix_extractor(extractor);
+            for i in 0..100 {
+                builder.add(format!("a{}:{:03}", i / 25, i).as_bytes(), b"value").unwrap();
+            }
+            builder.finish().unwrap()
+        };
+
+        let meta = build("000001.sst", extractor);
+        let opened = SSTableReader::open(&meta.path).unwrap();
+        let from_meta = SSTableReader::from_metadata(meta, None, &PosixEnv).unwrap();
+        for reader in [&opened, &from_meta] {
+            assert!(reader.may_contain_prefix(&extractor, b"a2:"));
+            assert!(!reader.may_contain_prefix(&extractor, b"b2:"));
+            // A different extractor cannot use the filter
+            assert!(reader.may_contain_prefix(&PrefixExtractor::Fixed(3), b"b2:"));
+        }
+
+        let meta = build("000002.sst", PrefixExtractor::None);
+        let reader = SSTableReader::open(&meta.path).unwrap();
+        assert!(reader.may_contain_prefix(&extractor, b"b2:"));
+    }
+
+    #[test]
+    fn test_sstable_iterator_seek_and_prev() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut builder = SSTableBuilder::new(temp_file.path()).unwrap();
+        builder.set_block_size(128);
+        for i in (0..100).map(|i| i * 2) {
+            builder.add(format!("key{:03}", i).as_bytes(), b"value").unwrap();
+        }
+        builder.finish().unwrap();
+
+        let reader = SSTableReader::open(temp_file.path()).unwrap();
+        assert!(reader.num_blocks() > 1);
+        let mut iter = reader.iter();
+
+        iter.seek(b"key051").unwrap();
+        assert!(iter.valid());
+        assert_eq!(iter.key(), b"key052");
+        iter.seek_for_prev(b"key051").unwrap();
+        assert_eq!(iter.key(), b"key050");
+        iter.seek_for_prev(b"key050").unwrap();
+        assert_eq!(iter.key(), b"key050");
+        iter.seek(b"key999").unwrap();
+        assert!(!iter.valid());
+        iter.seek_for_prev(b"a").unwrap();
+        assert!(!iter.valid());
+
+        // Walk the whole table backward across block boundaries
+        iter.seek_to_last().unwrap();
+        let mut keys = vec![iter.key().to_vec()];
+        while iter.prev().unwrap() {
+            keys.push(iter.key().to_vec());
+        }
+        let expected: Vec<_> =
+            (0..100).rev().map(|i| format!("key{:03}", i * 2).into_bytes()).collect();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn test_sstable_versions() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut builder = SSTableBuilder::new(temp_file.path()).unwrap();
+        builder.set_block_size(64);
+        // Ten versions of each key, so they span blocks
+        for i in 0..10u64 {
+            for version in (1..=10u64).rev() {
+                let value = format!("value{}-{}", i, version);
+                builder
+                    .add_version(
+                        format!("key{}", i).as_bytes(),
+                        i * 100 + version,
+                        value.as_bytes(),
+                    )
+                    .unwrap();
+            }
+        }
+        builder.finish().unwrap();
+
+        let reader = SSTableReader::open(temp_file.path()).unwrap();
+        assert!(reader.has_sequence_numbers());
+        assert!(reader.num_blocks() > 10);
+        let options = ReadOptions::default();
+
+        assert_eq!(reader.get(b"key3").unwrap(), Some(b"value3-10".to_vec()));
+        assert_eq!(
+            reader.lookup_at(b"key3", 304, &options).unwrap(),
+            Some((304, b"value3-4".to_vec()))
+        );
+        assert_eq!(reader.lookup_at(b"key3", 250, &options).unwrap(), None);
+        assert_eq!(
+            reader.lookup_at(b"key9", 901, &options).unwrap(),
+            Some((901, b"value9-1".to_vec()))
+        );
+
+        // Plain iteration only returns the newest version of each key
+        let mut iter = reader.iter();
+        iter.seek_to_first().unwrap();
+        let mut entries = Vec::new();
+        while iter.advance().unwrap() {
+            entries.push((iter.key().to_vec(), iter.sequence()));
+        }
+        let expected: Vec<_> =
+            (0..10u64).map(|i| (format!("key{}", i).into_bytes(), i * 100 + 10)).collect();
+        assert_eq!(entries, expected);
+
+        // Backward too
+        iter.seek_to_last().unwrap();
+        let mut entries = vec![(iter.key().to_vec(), iter.sequence())];
+        while iter.prev().unwrap() {
+            entries.push((iter.key().to_vec(), iter.sequence()));
+        }
+        assert_eq!(entries, expected.iter().rev().cloned().collect::<Vec<_>>());
+        iter.seek_for_prev(b"key5").unwrap();
+        assert_eq!(
+            (iter.key(), iter.sequence(), iter.value()),
+            (&b"key5"[..], 510, &b"value5-10"[..])
+        );
+
+        let mut iter = reader.iter_versions(&options);
+        iter.seek_to_first().unwrap();
+        let mut count = 0;
+        while iter.advance().unwrap() {
+            count += 1;
+        }
+        assert_eq!(count, 100);
+    }
+
+    #[test]
+    fn test_sstable_iterator_uses_block_cache() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut builder = SSTableBuilder::new(temp_file.path()).unwrap();
+        builder.set_block_size(256);
+        for i in 0..200 {
+            builder.add(format!("key{:08}", i).as_bytes(), b"value").unwrap();
+        }
+        builder.finish().unwrap();
+
+        let cache = Arc::new(BlockCache::new(1024 * 1024));
+        let reader =
+            SSTableReader::open_with_cache(temp_file.path(), Some(Arc::clone(&cache))).unwrap();
+        let blocks = reader.num_blocks();
+        let scan = |options: &ReadOptions| {
+            let before = perf_context::get();
+            let mut iter = reader.iter_with_options(options);
+            iter.seek_to_first().unwrap();
+            let mut count = 0;
+            while iter.advance().unwrap() {
+                count += 1;
+            }
+            assert_eq!(count, 200);
+            perf_context::get().since(&before)
+        };
+
+        // Without fill_cache nothing is cached
+        let perf = scan(&ReadOptions::new().fill_cache(false));
+        assert_eq!(perf.block_reads, blocks as u64);
+        assert!(cache.is_empty());
+
+        let perf = scan(&ReadOptions::default());
+        assert_eq!(perf.block_reads, blocks as u64);
+        assert_eq!(cache.len(), blocks);
+
+        // A repeated scan is served from the cache, with or without fill_cache
+        for options in [ReadOptions::default(), ReadOptions::new().fill_cache(false)] {
+            let perf = scan(&options);
+            assert_eq!(perf.block_reads, 0);
+            assert_eq!(perf.block_cache_hits, blocks as u64);
+        }
+    }
+
     #[test]
     fn test_sstable_iterator() {
         let entries = vec![
@@ -0,0 +1,453 @@
+//! Database statistics: counters (tickers) and latency histograms.
+//!
+//! A [`Statistics`] object is attached to a database through
+//! [`Options::statistics`](crate::Options::statistics). The database then
+//! counts keys and bytes read and written, flushed and compacted bytes,
+//! Bloom filter and block cache effectiveness, and records the latency of
+//! gets, puts, batch writes, flushes and compactions.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::statistics::{HistogramType, Statistics, Ticker};
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let stats = Arc::new(Statistics::new());
+//! let db = DB::open("./data", Options::default().statistics(Arc::clone(&stats)))?;
+//!
+//! db.put(b"key", b"value")?;
+//! db.get(b"key")?;
+//!
+//! assert_eq!(stats.ticker(Ticker::KeysWritten), 1);
+//! println!("p99 get latency: {}us", stats.histogram(HistogramType::Get).p99);
+//! println!("{}", stats); // Formatted text of every counter and histogram
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::util::Instant;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A monotonically increasing counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Ticker {
+    /// Keys written by `put`, `delete` and `write`
+    KeysWritten,
+    /// Keys looked up by `get`
+    KeysRead,
+    /// Key and value bytes written
+    BytesWritten,
+    /// Value bytes returned by `get`
+    BytesRead,
+    /// Bytes of SSTables written by flushes
+    BytesFlushed,
+    /// Bytes of SSTables written by compactions
+    BytesCompacted,
+    /// SSTable lookups skipped because the Bloom filter ruled the key out
+    BloomFilterUseful,
+    /// Block cache hits
+    BlockCacheHit,
+    /// Block cache misses
+    BlockCacheMiss,
+    /// Microseconds writes were delayed or stopped by compaction debt
+    StallMicros,
+}
+
+impl Ticker {
+    /// Number of tickers
+    pub const COUNT: usize = 10;
+
+    /// All tickers, in declaration order
+    pub const ALL: [Ticker; Self::COUNT] = [
+        Ticker::KeysWritten,
+        Ticker::KeysRead,
+        Ticker::BytesWritten,
+        Ticker::BytesRead,
+        Ticker::BytesFlushed,
+        Ticker::BytesCompacted,
+        Ticker::BloomFilterUseful,
+        Ticker::BlockCacheHit,
+        Ticker::BlockCacheMiss,
+        Ticker::StallMicros,
+    ];
+
+    /// Name used in the text output
+    pub fn name(self) -> &'static str {
+        match self {
+            Ticker::KeysWritten => "aidb.keys.written",
+            Ticker::KeysRead => "aidb.keys.read",
+            Ticker::BytesWritten => "aidb.bytes.written",
+            Ticker::BytesRead => "aidb.bytes.read",
+            Ticker::BytesFlushed => "aidb.flush.bytes",
+            Ticker::BytesCompacted => "aidb.compaction.bytes",
+            Ticker::BloomFilterUseful => "aidb.bloom.filter.useful",
+            Ticker::BlockCacheHit => "aidb.block.cache.hit",
+            Ticker::BlockCacheMiss => "aidb.block.cache.miss",
+            Ticker::StallMicros => "aidb.stall.micros",
+        }
+    }
+}
+
+/// An operation whose latency is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HistogramType {
+    /// `DB::get`
+    Get,
+    /// `DB::put` and `DB::delete`
+    Put,
+    /// `DB::write`
+    Write,
+    /// Flushing one MemTable to an SSTable
+    Flush,
+    /// Running one compaction
+    Compaction,
+}
+
+impl HistogramType {
+    /// Number of histogram types
+    pub const COUNT: usize = 5;
+
+    /// All histogram types, in declaration order
+    pub const ALL: [HistogramType; Self::COUNT] = [
+        HistogramType::Get,
+        HistogramType::Put,
+        HistogramType::Write,
+        HistogramType::Flush,
+        HistogramType::Compaction,
+    ];
+
+    /// Name used in the text output
+    pub fn name(self) -> &'static str {
+        match self {
+            HistogramType::Get => "aidb.db.get.micros",
+            HistogramType::Put => "aidb.db.put.micros",
+            HistogramType::Write => "aidb.db.write.micros",
+            HistogramType::Flush => "aidb.flush.micros",
+            HistogramType::Compaction => "aidb.compaction.micros",
+        }
+    }
+}
+
+/// Upper bounds (inclusive, in microseconds) of the histogram buckets.
+/// Values above the last bound go into an overflow bucket.
+const BUCKET_BOUNDS: [u64; 22] = [
+    1, 2, 5, 10, 20, 50, 100, 200, 500, 1_000, 2_000, 5_000, 10_000, 20_000, 50_000, 100_000,
+    200_000, 500_000, 1_000_000, 2_000_000, 5_000_000, 10_000_000,
+];
+
+/// Summary of a latency histogram. All values are in microseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HistogramData {
+    /// Number of recorded values
+    pub count: u64,
+    /// Sum of all recorded values
+    pub sum: u64,
+    /// Smallest recorded value
+    pub min: u64,
+    /// Largest recorded value
+    pub max: u64,
+    /// Mean value
+    pub average: f64,
+    /// Median (estimated from the buckets)
+    pub p50: f64,
+    /// 95th percentile (estimated from the buckets)
+    pub p95: f64,
+    /// 99th percentile (estimated from the buckets)
+    pub p99: f64,
+}
+
+/// Lock-free histogram with fixed exponential buckets.
+#[derive(Debug)]
+struct Histogram {
+    buckets: [AtomicU64; BUCKET_BOUNDS.len() + 1],
+    count: AtomicU64,
+    sum: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, value: u64) {
+        let bucket = BUCKET_BOUNDS.partition_point(|&bound| bound < value);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.min.fetch_min(value, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.sum.store(0, Ordering::Relaxed);
+        self.min.store(u64::MAX, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+
+    fn data(&self) -> HistogramData {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return HistogramData::default();
+        }
+
+        let buckets: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let sum = self.sum.load(Ordering::Relaxed);
+        let min = self.min.load(Ordering::Relaxed);
+        let max = self.max.load(Ordering::Relaxed);
+
+        HistogramData {
+            count,
+            sum,
+            min,
+            max,
+            average: sum as f64 / count as f64,
+            p50: Self::percentile(&buckets, count, min, max, 50.0),
+            p95: Self::percentile(&buckets, count, min, max, 95.0),
+            p99: Self::percentile(&buckets, count, min, max, 99.0),
+        }
+    }
+
+    /// Estimates a percentile by interpolating within the bucket it falls in
+    fn percentile(buckets: &[u64], count: u64, min: u64, max: u64, p: f64) -> f64 {
+        let threshold = count as f64 * p / 100.0;
+        let mut cumulative = 0u64;
+
+        for (i, &bucket_count) in buckets.iter().enumerate() {
+            if bucket_count == 0 {
+                continue;
+            }
+            let previous = cumulative;
+            cumulative += bucket_count;
+            if cumulative as f64 >= threshold {
+                let low = if i == 0 { 0 } else { BUCKET_BOUNDS[i - 1] };
+                let high = BUCKET_BOUNDS.get(i).copied().unwrap_or(max);
+                let (low, high) = (low.max(min) as f64, high.min(max) as f64);
+                let fraction = (threshold - previous as f64) / bucket_count as f64;
+                return low + (high - low) * fraction;
+            }
+        }
+
+        max as f64
+    }
+}
+
+/// Counters and latency histograms for a database.
+///
+/// All methods are thread-safe and lock-free. See the
+/// [module documentation](self) for an example.
+#[derive(Debug)]
+pub struct Statistics {
+    tickers: [AtomicU64; Ticker::COUNT],
+    histograms: [Histogram; HistogramType::COUNT],
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Statistics {
+    /// Creates a set of statistics with every counter at zero.
+    pub fn new() -> Self {
+        Self {
+            tickers: std::array::from_fn(|_| AtomicU64::new(0)),
+            histograms: std::array::from_fn(|_| Histogram::new()),
+        }
+    }
+
+    /// Adds `count` to a ticker.
+    pub fn record_tick(&self, ticker: Ticker, count: u64) {
+        self.tickers[ticker as usize].fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Returns the current value of a ticker.
+    pub fn ticker(&self, ticker: Ticker) -> u64 {
+        self.tickers[ticker as usize].load(Ordering::Relaxed)
+    }
+
+    /// Records a value (in microseconds) in a histogram.
+    pub fn record_micros(&self, histogram: HistogramType, micros: u64) {
+        self.histograms[histogram as usize].record(micros);
+    }
+
+    /// Returns a summary of a histogram.
+    pub fn histogram(&self, histogram: HistogramType) -> HistogramData {
+        self.histograms[histogram as usize].data()
+    }
+
+    /// Returns the current value of every ticker and histogram.
+    pub fn snapshot(&self) -> StatisticsSnapshot {
+        StatisticsSnapshot {
+            tickers: Ticker::ALL.iter().map(|&t| (t, self.ticker(t))).collect(),
+            histograms: HistogramType::ALL.iter().map(|&h| (h, self.histogram(h))).collect(),
+        }
+    }
+
+    /// Resets every ticker and histogram to zero.
+    pub fn reset(&self) {
+        for ticker in &self.tickers {
+            ticker.store(0, Ordering::Relaxed);
+        }
+        for histogram in &self.histograms {
+            histogram.reset();
+        }
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.snapshot().fmt(f)
+    }
+}
+
+/// Point-in-time copy of all [`Statistics`] values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatisticsSnapshot {
+    /// Every ticker with its value, in [`Ticker::ALL`] order
+    pub tickers: Vec<(Ticker, u64)>,
+    /// Every histogram with its summary, in [`HistogramType::ALL`] order
+    pub histograms: Vec<(HistogramType, HistogramData)>,
+}
+
+impl StatisticsSnapshot {
+    /// Returns the value of a ticker.
+    pub fn ticker(&self, ticker: Ticker) -> u64 {
+        self.tickers.iter().find(|(t, _)| *t == ticker).map_or(0, |(_, v)| *v)
+    }
+
+    /// Returns the summary of a histogram.
+    pub fn histogram(&self, histogram: HistogramType) -> HistogramData {
+        self.histograms
+            .iter()
+            .find(|(h, _)| *h == histogram)
+            .map_or_else(HistogramData::default, |(_, data)| *data)
+    }
+}
+
+impl fmt::Display for StatisticsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (ticker, value) in &self.tickers {
+            writeln!(f, "{} COUNT : {}", ticker.name(), value)?;
+        }
+        for (histogram, data) in &self.histograms {
+            writeln!(
+                f,
+                "{} P50 : {:.1} P95 : {:.1} P99 : {:.1} MAX : {} COUNT : {} SUM : {}",
+                histogram.name(),
+                data.p50,
+                data.p95,
+                data.p99,
+                data.max,
+                data.count,
+                data.sum
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the time until it is dropped in a histogram.
+///
+/// Does nothing when no statistics are attached.
+pub(crate) struct StopWatch<'a> {
+    target: Option<(&'a Statistics, HistogramType, Instant)>,
+}
+
+impl<'a> StopWatch<'a> {
+    pub(crate) fn new(statistics: Option<&'a Statistics>, histogram: HistogramType) -> Self {
+        Self { target: statistics.map(|stats| (stats, histogram, Instant::now())) }
+    }
+}
+
+impl Drop for StopWatch<'_> {
+    fn drop(&mut self) {
+        if let Some((stats, histogram, start)) = self.target {
+            stats.record_micros(histogram, start.elapsed().as_micros() as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tickers() {
+        let stats = Statistics::new();
+        stats.record_tick(Ticker::KeysWritten, 3);
+        stats.record_tick(Ticker::KeysWritten, 2);
+        assert_eq!(stats.ticker(Ticker::KeysWritten), 5);
+        assert_eq!(stats.ticker(Ticker::KeysRead), 0);
+
+        stats.reset();
+        assert_eq!(stats.ticker(Ticker::KeysWritten), 0);
+    }
+
+    #[test]
+    fn test_histogram() {
+        let stats = Statistics::new();
+        for micros in 1..=100 {
+            stats.record_micros(HistogramType::Get, micros);
+        }
+
+        let data = stats.histogram(HistogramType::Get);
+        assert_eq!(data.count, 100);
+        assert_eq!(data.sum, 5050);
+        assert_eq!(data.min, 1);
+        assert_eq!(data.max, 100);
+        assert!((data.average - 50.5).abs() < f64::EPSILON);
+        assert!(data.p50 > 20.0 && data.p50 <= 50.0, "p50 = {}", data.p50);
+        assert!(data.p99 > 50.0 && data.p99 <= 100.0, "p99 = {}", data.p99);
+
+        assert_eq!(stats.histogram(HistogramType::Put), HistogramData::default());
+    }
+
+    #[test]
+    fn test_histogram_overflow_bucket() {
+        let stats = Statistics::new();
+        stats.record_micros(HistogramType::Compaction, 60_000_000);
+        let data = stats.histogram(HistogramType::Compaction);
+        assert_eq!(data.max, 60_000_000);
+        assert!(data.p99 <= 60_000_000.0);
+    }
+
+    #[test]
+    fn test_snapshot_and_text() {
+        let stats = Statistics::new();
+        stats.record_tick(Ticker::BlockCacheHit, 7);
+        stats.record_micros(HistogramType::Flush, 10);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.ticker(Ticker::BlockCacheHit), 7);
+        assert_eq!(snapshot.histogram(HistogramType::Flush).count, 1);
+
+        let text = stats.to_string();
+        assert!(text.contains("aidb.block.cache.hit COUNT : 7"));
+        assert!(text.contains("aidb.flush.micros"));
+        assert_eq!(text.lines().count(), Ticker::COUNT + HistogramType::COUNT);
+    }
+
+    #[test]
+    fn test_stop_watch() {
+        let stats = Statistics::new();
+        drop(StopWatch::new(Some(&stats), HistogramType::Put));
+        drop(StopWatch::new(None, HistogramType::Put));
+        assert_eq!(stats.histogram(HistogramType::Put).count, 1);
+    }
+}
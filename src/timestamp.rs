@@ -0,0 +1,242 @@
+//! User-defined timestamps.
+//!
+//! With a comparator that orders timestamps, such as
+//! [`BytewiseTimestampComparator`](crate::comparator::BytewiseTimestampComparator),
+//! every key ends in a [`TIMESTAMP_SIZE`]-byte big-endian timestamp chosen by
+//! the application, and the keys that only differ in their timestamps are
+//! the versions of one key, sorted from the newest down.
+//! [`DB::put_with_timestamp`] and [`DB::delete_with_timestamp`] write a
+//! version and [`DB::get_with_timestamp`] reads the newest version at or
+//! before a timestamp; a version written by a delete reads as missing.
+//! Batches write versions with keys built by [`key_with_timestamp`], and
+//! iterators return every version as a key of its own.
+//!
+//! Compactions keep every version unless
+//! [`Options::timestamp_history_window`](crate::Options::timestamp_history_window)
+//! is set: then the versions reads at timestamps older than the newest
+//! timestamp written minus the window would see are dropped. The newest
+//! timestamp is only tracked in memory; after reopening, every version is
+//! kept until a timestamp is written.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::comparator::BytewiseTimestampComparator;
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let options = Options::default().comparator(Arc::new(BytewiseTimestampComparator));
+//! let db = DB::open("./data", options)?;
+//! db.put_with_timestamp(b"price", 100, b"10")?;
+//! db.put_with_timestamp(b"price", 200, b"12")?;
+//! assert_eq!(db.get_with_timestamp(b"price", 150)?, Some(b"10".to_vec()));
+//! assert_eq!(db.get_with_timestamp(b"price", 99)?, None);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::atomic::Ordering;
+
+use crate::{iterator, Error, ReadOptions, Result, WriteBatch, DB};
+
+/// Size of the timestamp at the end of keys
+pub const TIMESTAMP_SIZE: usize = 8;
+
+/// Appends the big-endian `timestamp` to `key`
+pub fn key_with_timestamp(key: &[u8], timestamp: u64) -> Vec<u8> {
+    let mut full = Vec::with_capacity(key.len() + TIMESTAMP_SIZE);
+    full.extend_from_slice(key);
+    full.extend_from_slice(&timestamp.to_be_bytes());
+    full
+}
+
+/// Splits a key into the key without its timestamp and the timestamp, or
+/// returns `None` if it is shorter than a timestamp
+pub fn split_timestamp(key: &[u8]) -> Option<(&[u8], u64)> {
+    let split = key.len().checked_sub(TIMESTAMP_SIZE)?;
+    let timestamp = u64::from_be_bytes(key[split..].try_into().unwrap());
+    Some((&key[..split], timestamp))
+}
+
+/// Splits a key into the bytes before its timestamp and the timestamp's
+/// bytes, which are empty if the key is shorter than a timestamp
+pub(crate) fn split_bytes(key: &[u8]) -> (&[u8], &[u8]) {
+    match key.len().checked_sub(TIMESTAMP_SIZE) {
+        Some(split) => key.split_at(split),
+        None => (key, &[]),
+    }
+}
+
+impl DB {
+    /// Writes the version of `key` at `timestamp`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if the comparator does not order
+    /// timestamps, and the errors of [`put`](Self::put).
+    pub fn put_with_timestamp(&self, key: &[u8], timestamp: u64, value: &[u8]) -> Result<()> {
+        self.check_timestamps()?;
+        let mut batch = WriteBatch::new();
+        batch.put(&key_with_timestamp(key, timestamp), value);
+        self.write(batch)
+    }
+
+    /// Deletes `key` from `timestamp` on: reads at `timestamp` or later,
+    /// up to the next newer version, find nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if the comparator does not order
+    /// timestamps, and the errors of [`delete`](Self::delete).
+    pub fn delete_with_timestamp(&self, key: &[u8], timestamp: u64) -> Result<()> {
+        self.check_timestamps()?;
+        let mut batch = WriteBatch::new();
+        batch.delete(&key_with_timestamp(key, timestamp));
+        self.write(batch)
+    }
+
+    /// Retrieves the newest version of `key` at or before `timestamp`.
+    ///
+    /// Returns `None` if there is no such version or it was written by
+    /// [`delete_with_timestamp`](Self::delete_with_timestamp).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if the comparator does not order
+    /// timestamps.
+    pub fn get_with_timestamp(&self, key: &[u8], timestamp: u64) -> Result<Option<Vec<u8>>> {
+        self.check_timestamps()?;
+        self.maybe_catch_up()?;
+
+        // The versions from `timestamp` down to the oldest possible one
+        let start = key_with_timestamp(key, timestamp);
+        let end = key_with_timestamp(key, 0);
+        let sequence = self.sequence.load(Ordering::SeqCst);
+        let value = match iterator::first_key_in(self, &start, &end, sequence)? {
+            Some(version) => self
+                .get_value_at_sequence(&version, sequence, &ReadOptions::default())?
+                .map(|value| self.resolve_blob(value))
+                .transpose()?,
+            None => None,
+        };
+
+        self.record_read(value.as_ref().map(Vec::len));
+        Ok(value)
+    }
+
+    /// Notes the timestamps of the keys of `batch`, for
+    /// [`timestamp_cutoff`](Self::timestamp_cutoff)
+    pub(crate) fn record_timestamps(&self, batch: &WriteBatch) {
+        let newest =
+            batch.iter().filter_map(|op| split_timestamp(op.key())).map(|(_, ts)| ts).max();
+        if let Some(newest) = newest {
+            self.newest_timestamp.fetch_max(newest, Ordering::Relaxed);
+        }
+    }
+
+    /// Oldest timestamp whose versions compactions keep (see
+    /// [`Options::timestamp_history_window`](crate::Options::timestamp_history_window)),
+    /// 0 to keep every version
+    pub(crate) fn timestamp_cutoff(&self) -> u64 {
+        let window = self.options.read().timestamp_history_window;
+        if window == 0 || self.comparator.timestamp_size() == 0 {
+            return 0;
+        }
+        self.newest_timestamp.load(Ordering::Relaxed).saturating_sub(window)
+    }
+
+    fn check_timestamps(&self) -> Result<()> {
+        if self.comparator.timestamp_size() != TIMESTAMP_SIZE {
+            return Err(Error::invalid_argument(format!(
+                "comparator {} does not order timestamps",
+                self.comparator.name()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comparator::BytewiseTimestampComparator;
+    use crate::Options;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn open(dir: &TempDir, options: Options) -> DB {
+        DB::open(dir.path(), options.comparator(Arc::new(BytewiseTimestampComparator))).unwrap()
+    }
+
+    #[test]
+    fn test_split_timestamp() {
+        let key = key_with_timestamp(b"key", 7);
+        assert_eq!(key.len(), 3 + TIMESTAMP_SIZE);
+        assert_eq!(split_timestamp(&key), Some((&b"key"[..], 7)));
+        assert_eq!(split_timestamp(b"short"), None);
+        assert_eq!(split_bytes(b"short"), (&b"short"[..], &[][..]));
+    }
+
+    #[test]
+    fn test_get_with_timestamp() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = open(&temp_dir, Options::default());
+        db.put_with_timestamp(b"a", 10, b"a10").unwrap();
+        db.put_with_timestamp(b"a", 20, b"a20").unwrap();
+        db.flush().unwrap();
+        db.delete_with_timestamp(b"a", 30).unwrap();
+        db.put_with_timestamp(b"a", 40, b"a40").unwrap();
+        db.put_with_timestamp(b"b", 5, b"b5").unwrap();
+
+        let check = |db: &DB| {
+            let read = |ts: u64| db.get_with_timestamp(b"a", ts).unwrap();
+            assert_eq!(read(5), None);
+            assert_eq!(read(10), Some(b"a10".to_vec()));
+            assert_eq!(read(25), Some(b"a20".to_vec()));
+            assert_eq!(read(35), None);
+            assert_eq!(read(u64::MAX), Some(b"a40".to_vec()));
+            assert_eq!(db.get_with_timestamp(b"b", 4).unwrap(), None);
+            assert_eq!(db.get_with_timestamp(b"b", 6).unwrap(), Some(b"b5".to_vec()));
+            assert_eq!(db.get_with_timestamp(b"c", 6).unwrap(), None);
+        };
+        check(&db);
+        db.flush().unwrap();
+        check(&db);
+
+        // Without timestamps in the order
+        let temp_dir = TempDir::new().unwrap();
+        let plain = DB::open(temp_dir.path(), Options::default()).unwrap();
+        assert!(matches!(
+            plain.put_with_timestamp(b"a", 1, b"v"),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(plain.get_with_timestamp(b"a", 1), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_timestamp_history_window() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = open(&temp_dir, Options::default().timestamp_history_window(100));
+        db.put_with_timestamp(b"a", 10, b"a10").unwrap();
+        db.put_with_timestamp(b"a", 20, b"a20").unwrap();
+        db.delete_with_timestamp(b"a", 30).unwrap();
+        db.put_with_timestamp(b"a", 150, b"a150").unwrap();
+        db.put_with_timestamp(b"b", 200, b"b200").unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.timestamp_cutoff(), 100);
+
+        let files: Vec<u64> = db.dump_lsm_state().unwrap().levels[0]
+            .files
+            .iter()
+            .map(|file| file.file_number.unwrap())
+            .collect();
+        db.compact_files(&files, 1).unwrap();
+
+        // Reads at 100 or later are unchanged; the older versions are gone
+        assert_eq!(db.get_with_timestamp(b"a", 100).unwrap(), None);
+        assert_eq!(db.get_with_timestamp(b"a", 160).unwrap(), Some(b"a150".to_vec()));
+        assert_eq!(db.get_with_timestamp(b"a", 25).unwrap(), None);
+        assert_eq!(db.get_with_timestamp(b"b", 300).unwrap(), Some(b"b200".to_vec()));
+    }
+}
@@ -0,0 +1,358 @@
+//! Reading committed writes back from the WAL.
+//!
+//! [`DB::get_updates_since`] returns every write batch from a given sequence
+//! number on, in commit order, read from the WAL files the database still
+//! has. This is a change feed for keeping caches, search indexes or replicas
+//! up to date without scanning the whole keyspace.
+//!
+//! The current WAL only holds writes since the last flush. To read further
+//! back, set [`Options::wal_archive_size_limit`](crate::Options): rotated
+//! WAL files are then moved to the `archive` directory and kept until they
+//! exceed that size. Asking for a sequence number whose WAL has already been
+//! deleted returns `NotFound`, and the consumer has to resynchronize from a
+//! full scan or a checkpoint.
+//!
+//! Values written with [`DB::put_reader`] are read back from their blob
+//! files, so the batches hold the whole value. If the blob file has since
+//! been removed by [`DB::delete_obsolete_blobs`], the iterator returns
+//! `NotFound` as well.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::{Options, DB};
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let options = Options::default().wal_archive_size_limit(256 * 1024 * 1024);
+//! let db = DB::open("./data", options)?;
+//!
+//! let mut next_sequence = 1;
+//! for update in db.get_updates_since(next_sequence)? {
+//!     let update = update?;
+//!     println!("{} writes at sequence {}", update.batch.len(), update.sequence);
+//!     next_sequence = update.sequence + update.batch.len() as u64;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::blob::{self, BlobReference};
+use crate::env::Env;
+use crate::wal::{self, entry, WALReader};
+use crate::write_batch::WriteOp;
+use crate::{Error, Result, WriteBatch, DB};
+
+/// A write batch read from the WAL.
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    /// Sequence number of the batch's first write; the following writes
+    /// have consecutive sequence numbers
+    pub sequence: u64,
+    /// The writes, in the order they were applied
+    pub batch: WriteBatch,
+}
+
+/// Iterator over the write batches committed since a sequence number.
+///
+/// Created by [`DB::get_updates_since`]. It returns the batches committed
+/// when it was created; later writes need a new iterator.
+pub struct TransactionLogIterator {
+    env: Arc<dyn Env>,
+    /// WAL files not opened yet, oldest first
+    files: VecDeque<PathBuf>,
+    reader: Option<WALReader>,
+    /// Batches that end before this sequence number are skipped
+    start_sequence: u64,
+    /// Last sequence number committed when the iterator was created
+    last_sequence: u64,
+    /// Database whose blob files the values stored with
+    /// [`DB::put_reader`] are read from; without one, batches hold the
+    /// references as written
+    blob_db_path: Option<PathBuf>,
+    /// Whether a batch has been returned yet
+    started: bool,
+    done: bool,
+}
+
+impl DB {
+    /// Returns the write batches committed since `sequence`, oldest first.
+    ///
+    /// The first batch is the one containing `sequence`, so it can start
+    /// before it. See the [module documentation](crate::transaction_log) for
+    /// how far back the WAL is kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidState` if the WAL is disabled. The iterator returns
+    /// `NotFound` if the WAL holding `sequence`, or the blob file of a value
+    /// written with [`put_reader`](DB::put_reader), has been deleted.
+    pub fn get_updates_since(&self, sequence: u64) -> Result<TransactionLogIterator> {
+        if !self.options.read().use_wal {
+            return Err(Error::InvalidState("get_updates_since needs the WAL".to_string()));
+        }
+
+        // Hold the WAL lock so every committed write is in a file listed here
+        let (last_sequence, files) = {
+            let mut wal = self.wal.write();
+            wal.flush()?;
+            let mut files: VecDeque<PathBuf> = archived_wal_files(&*self.env, &self.path)?
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect();
+            files.extend(self.frozen_wals().into_iter().map(|(path, _)| path));
+            files.push_back(wal.path().to_path_buf());
+            (self.sequence.load(Ordering::SeqCst), files)
+        };
+
+        Ok(TransactionLogIterator::new(
+            Arc::clone(&self.env),
+            files,
+            sequence,
+            last_sequence,
+            Some(self.path.clone()),
+        ))
+    }
+}
+
+impl TransactionLogIterator {
+    /// Reads the batches from `start_sequence` to `last_sequence` out of
+    /// `files`, which must be WAL files in order, resolving blob references
+    /// from the database at `blob_db_path` if there is one
+    pub(crate) fn new(
+        env: Arc<dyn Env>,
+        files: VecDeque<PathBuf>,
+        start_sequence: u64,
+        last_sequence: u64,
+        blob_db_path: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            env,
+            files,
+            reader: None,
+            start_sequence: start_sequence.max(1),
+            last_sequence,
+            blob_db_path,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Reads the next batch, or `None` at the end of the retained WAL
+    fn read_batch(&mut self) -> Result<Option<BatchResult>> {
+        loop {
+            if self.reader.is_none() {
+                let Some(path) = self.files.pop_front() else {
+                    return Ok(None);
+                };
+                self.reader =
+                    Some(WALReader::new_with_env(&path, &*self.env).map_err(|e| match e {
+                        Error::Io(ref io) if io.kind() == std::io::ErrorKind::NotFound => {
+                            Error::not_found(format!("WAL file {:?} was deleted", path))
+                        }
+                        e => e,
+                    })?);
+            }
+            let reader = self.reader.as_mut().expect("opened above");
+
+            let data = match reader.read_next() {
+                Ok(Some(data)) => data,
+                Ok(None) => {
+                    self.reader = None;
+                    continue;
+                }
+                // The live WAL can end in a record that is still being
+                // written; it holds no write committed before the iterator
+                // was created
+                Err(_) if self.files.is_empty() => return Ok(None),
+                Err(e) => return Err(e),
+            };
+
+            let entries = entry::decode(&data, 0)?;
+            let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+                continue;
+            };
+            let (sequence, end) = (first.sequence, last.sequence);
+            if sequence > self.last_sequence {
+                return Ok(None);
+            }
+            if end < self.start_sequence {
+                continue;
+            }
+            if !self.started && sequence > self.start_sequence {
+                return Err(Error::not_found(format!(
+                    "WAL for sequence {} is no longer retained; the oldest available is {}",
+                    self.start_sequence, sequence
+                )));
+            }
+
+            self.started = true;
+            let mut batch = WriteBatch::new();
+            for entry in entries {
+                match entry.op {
+                    WriteOp::Put { key, value } => batch.put(&key, &self.resolve(&key, value)?),
+                    WriteOp::Delete { key } => batch.delete(&key),
+                    WriteOp::Append { key, suffix } => batch.append(&key, &suffix),
+                }
+            }
+            return Ok(Some(BatchResult { sequence, batch }));
+        }
+    }
+
+    /// The value `value` stands for, if references are resolved
+    fn resolve(&self, key: &[u8], value: Vec<u8>) -> Result<Vec<u8>> {
+        let Some(ref db_path) = self.blob_db_path else {
+            return Ok(value);
+        };
+        if let Some(reference) = BlobReference::decode(&value) {
+            if !self.env.file_exists(&reference.path(db_path)) {
+                return Err(Error::not_found(format!(
+                    "the value written to {:?} was deleted by delete_obsolete_blobs",
+                    String::from_utf8_lossy(key)
+                )));
+            }
+        }
+        blob::resolve_value(&*self.env, db_path, value)
+    }
+}
+
+impl Iterator for TransactionLogIterator {
+    type Item = Result<BatchResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_batch().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// Archived WAL files of the database at `db_path` and their sizes, oldest
+/// first
+pub(crate) fn archived_wal_files(env: &dyn Env, db_path: &Path) -> Result<Vec<(PathBuf, u64)>> {
+    wal_files(env, &db_path.join(wal::ARCHIVE_DIR))?
+        .into_iter()
+        .map(|path| {
+            let size = env.file_size(&path)?;
+            Ok((path, size))
+        })
+        .collect()
+}
+
+/// WAL files in `dir`, oldest first; none if `dir` does not exist
+pub(crate) fn wal_files(env: &dyn Env, dir: &Path) -> Result<Vec<PathBuf>> {
+    if !env.file_exists(dir) {
+        return Ok(Vec::new());
+    }
+    let mut files: Vec<(u64, PathBuf)> = env
+        .get_children(dir)?
+        .into_iter()
+        .filter_map(|name| Some((wal::parse_wal_filename(&name)?, dir.join(name))))
+        .collect();
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Sequence number of the first write in the WAL file at `path`, if any
+pub(crate) fn first_sequence(env: &dyn Env, path: &Path) -> Result<Option<u64>> {
+    let mut reader = WALReader::new_with_env(path, env)?;
+    while let Some(data) = reader.read_next()? {
+        if let Some(first) = entry::decode(&data, 0)?.first() {
+            return Ok(Some(first.sequence));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use tempfile::TempDir;
+
+    fn sequences(db: &DB, since: u64) -> Result<Vec<(u64, usize)>> {
+        db.get_updates_since(since)?
+            .map(|update| update.map(|update| (update.sequence, update.batch.len())))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_updates_since() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Options::default().wal_archive_size_limit(1024 * 1024);
+        let db = DB::open(temp_dir.path(), options).unwrap();
+
+        db.put(b"a", b"1").unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"b", b"2");
+        batch.delete(b"a");
+        batch.put(b"c", b"3");
+        db.write(batch).unwrap();
+        db.flush().unwrap();
+        db.delete(b"b").unwrap();
+
+        // Flushed writes are read from the archive
+        assert_eq!(sequences(&db, 0).unwrap(), vec![(1, 1), (2, 3), (5, 1)]);
+        // The batch containing the sequence is returned whole
+        assert_eq!(sequences(&db, 3).unwrap(), vec![(2, 3), (5, 1)]);
+        assert_eq!(sequences(&db, 6).unwrap(), vec![]);
+
+        let update = db.get_updates_since(2).unwrap().next().unwrap().unwrap();
+        let ops: Vec<_> = update.batch.iter().cloned().collect();
+        assert_eq!(
+            ops,
+            vec![
+                WriteOp::Put { key: b"b".to_vec(), value: b"2".to_vec() },
+                WriteOp::Delete { key: b"a".to_vec() },
+                WriteOp::Put { key: b"c".to_vec(), value: b"3".to_vec() },
+            ]
+        );
+
+        // Writes after the iterator is created are not returned
+        let mut iter = db.get_updates_since(5).unwrap();
+        db.put(b"d", b"4").unwrap();
+        assert_eq!(iter.next().unwrap().unwrap().sequence, 5);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_get_updates_since_without_archive() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+
+        db.put(b"a", b"1").unwrap();
+        db.flush().unwrap();
+        db.put(b"b", b"2").unwrap();
+
+        assert_eq!(sequences(&db, 2).unwrap(), vec![(2, 1)]);
+        assert!(matches!(sequences(&db, 1), Err(Error::NotFound(_))));
+        assert!(!temp_dir.path().join(wal::ARCHIVE_DIR).exists());
+    }
+
+    #[test]
+    fn test_archive_size_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.set_options(&[("wal_archive_size_limit", "3000")]).unwrap();
+
+        for i in 0..5u8 {
+            db.put(&[i], &[i; 1000]).unwrap();
+            db.flush().unwrap();
+        }
+
+        let archived = archived_wal_files(&*db.env, temp_dir.path()).unwrap();
+        assert!(archived.len() < 5);
+        assert!(archived.iter().map(|(_, size)| size).sum::<u64>() <= 3000);
+        // The newest writes are still available
+        assert_eq!(sequences(&db, 5).unwrap(), vec![(5, 1)]);
+        assert!(matches!(sequences(&db, 1), Err(Error::NotFound(_))));
+    }
+}
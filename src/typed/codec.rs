@@ -0,0 +1,79 @@
+//! Value codecs for [`TypedDB`](super::TypedDB).
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Error, Result};
+
+/// Encodes and decodes values stored through a [`TypedDB`](super::TypedDB).
+///
+/// Values never need to preserve ordering, so any serde format works. Keys
+/// always use the order-preserving encoding in [`super::key`].
+pub trait Codec {
+    /// Encodes `value` into bytes.
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>>;
+
+    /// Decodes a value previously produced by [`encode`](Self::encode).
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T>;
+}
+
+/// JSON via `serde_json`. Readable with other tools, but the largest format.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        serde_json::from_slice(bytes).map_err(|e| Error::Serialization(e.to_string()))
+    }
+}
+
+/// bincode, the format used internally for manifests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// postcard, a compact varint-based format (requires the `postcard` feature).
+#[cfg(feature = "postcard")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Postcard;
+
+#[cfg(feature = "postcard")]
+impl Codec for Postcard {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        postcard::to_allocvec(value).map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        postcard::from_bytes(bytes).map_err(|e| Error::Serialization(e.to_string()))
+    }
+}
+
+/// MessagePack via `rmp-serde`, with struct fields stored by name so values
+/// stay readable after fields are added (requires the `msgpack` feature).
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MsgPack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPack {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        rmp_serde::to_vec_named(value).map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        rmp_serde::from_slice(bytes).map_err(|e| Error::Serialization(e.to_string()))
+    }
+}
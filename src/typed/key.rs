@@ -0,0 +1,787 @@
+//! Order-preserving key encoding.
+//!
+//! Keys are compared as raw bytes, so a typed key must be encoded such that
+//! byte order matches the order of the values. General purpose formats do
+//! not do this: JSON writes `10` before `9`, postcard and msgpack use
+//! variable-length or little-endian integers, and none of them order
+//! negative numbers before positive ones. This encoding does:
+//!
+//! - unsigned integers: fixed-width big-endian
+//! - signed integers: fixed-width big-endian with the sign bit flipped
+//! - floats: IEEE 754 total order (`-inf < -0.0 < 0.0 < inf < NaN`)
+//! - strings and byte strings: `0x00` escaped as `0x00 0xFF`, terminated by
+//!   `0x00 0x00`, so that a string sorts before any string it prefixes
+//! - `Option`: `0x00` for `None`, `0x01` followed by the value for `Some`
+//! - sequences and maps: every element preceded by `0x01`, terminated by
+//!   `0x00`
+//! - tuples and structs: fields concatenated in declaration order
+//! - enums: the variant index as a big-endian `u32`, then its fields
+//!
+//! Tuples and structs therefore sort field by field, like `#[derive(Ord)]`.
+//! Enums sort by variant declaration order. The encoding is not
+//! self-describing; keys must be decoded as the type they were encoded
+//! from.
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize};
+use std::fmt;
+
+use crate::{Error, Result};
+
+/// Encodes `key` so that byte order matches the order of the values.
+pub fn to_key_bytes<K: Serialize + ?Sized>(key: &K) -> Result<Vec<u8>> {
+    let mut serializer = KeySerializer { out: Vec::new() };
+    key.serialize(&mut serializer)?;
+    Ok(serializer.out)
+}
+
+/// Decodes a key produced by [`to_key_bytes`].
+pub fn from_key_bytes<K: DeserializeOwned>(bytes: &[u8]) -> Result<K> {
+    let mut deserializer = KeyDeserializer { input: bytes };
+    let key = K::deserialize(&mut deserializer)?;
+    if !deserializer.input.is_empty() {
+        return Err(Error::Serialization(format!(
+            "{} trailing bytes after key",
+            deserializer.input.len()
+        )));
+    }
+    Ok(key)
+}
+
+/// Error raised by serde while encoding or decoding a key
+#[derive(Debug)]
+struct KeyError(String);
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+impl ser::Error for KeyError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        KeyError(msg.to_string())
+    }
+}
+
+impl de::Error for KeyError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        KeyError(msg.to_string())
+    }
+}
+
+impl From<KeyError> for Error {
+    fn from(err: KeyError) -> Self {
+        Error::Serialization(format!("key: {}", err.0))
+    }
+}
+
+type KeyResult<T> = std::result::Result<T, KeyError>;
+
+const SEQ_ELEMENT: u8 = 0x01;
+const SEQ_END: u8 = 0x00;
+
+struct KeySerializer {
+    out: Vec<u8>,
+}
+
+impl KeySerializer {
+    fn write_escaped(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.out.push(b);
+            if b == 0x00 {
+                self.out.push(0xFF);
+            }
+        }
+        self.out.extend_from_slice(&[0x00, 0x00]);
+    }
+}
+
+impl ser::Serializer for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> KeyResult<()> {
+        self.out.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> KeyResult<()> {
+        self.serialize_u8((v as u8) ^ 0x80)
+    }
+
+    fn serialize_i16(self, v: i16) -> KeyResult<()> {
+        self.serialize_u16((v as u16) ^ (1 << 15))
+    }
+
+    fn serialize_i32(self, v: i32) -> KeyResult<()> {
+        self.serialize_u32((v as u32) ^ (1 << 31))
+    }
+
+    fn serialize_i64(self, v: i64) -> KeyResult<()> {
+        self.serialize_u64((v as u64) ^ (1 << 63))
+    }
+
+    fn serialize_i128(self, v: i128) -> KeyResult<()> {
+        self.serialize_u128((v as u128) ^ (1 << 127))
+    }
+
+    fn serialize_u8(self, v: u8) -> KeyResult<()> {
+        self.out.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> KeyResult<()> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> KeyResult<()> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> KeyResult<()> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u128(self, v: u128) -> KeyResult<()> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> KeyResult<()> {
+        let bits = v.to_bits();
+        let ordered = if bits >> 31 == 1 {
+            !bits
+        } else {
+            bits ^ (1 << 31)
+        };
+        self.serialize_u32(ordered)
+    }
+
+    fn serialize_f64(self, v: f64) -> KeyResult<()> {
+        let bits = v.to_bits();
+        let ordered = if bits >> 63 == 1 {
+            !bits
+        } else {
+            bits ^ (1 << 63)
+        };
+        self.serialize_u64(ordered)
+    }
+
+    fn serialize_char(self, v: char) -> KeyResult<()> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v: &str) -> KeyResult<()> {
+        self.write_escaped(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> KeyResult<()> {
+        self.write_escaped(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> KeyResult<()> {
+        self.out.push(0x00);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> KeyResult<()> {
+        self.out.push(0x01);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> KeyResult<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> KeyResult<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> KeyResult<()> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> KeyResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> KeyResult<()> {
+        self.serialize_u32(variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> KeyResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> KeyResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> KeyResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> KeyResult<Self> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> KeyResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> KeyResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> KeyResult<Self> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl ser::SerializeSeq for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> KeyResult<()> {
+        self.out.push(SEQ_ELEMENT);
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> KeyResult<()> {
+        self.out.push(SEQ_END);
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> KeyResult<()> {
+        self.out.push(SEQ_ELEMENT);
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> KeyResult<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> KeyResult<()> {
+        self.out.push(SEQ_END);
+        Ok(())
+    }
+}
+
+macro_rules! impl_fixed_compound {
+    ($trait:ident, $method:ident) => {
+        impl ser::$trait for &mut KeySerializer {
+            type Ok = ();
+            type Error = KeyError;
+
+            fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> KeyResult<()> {
+                value.serialize(&mut **self)
+            }
+
+            fn end(self) -> KeyResult<()> {
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_fixed_compound!(SerializeTuple, serialize_element);
+impl_fixed_compound!(SerializeTupleStruct, serialize_field);
+impl_fixed_compound!(SerializeTupleVariant, serialize_field);
+
+impl ser::SerializeStruct for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> KeyResult<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> KeyResult<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> KeyResult<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> KeyResult<()> {
+        Ok(())
+    }
+}
+
+struct KeyDeserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> KeyDeserializer<'de> {
+    fn take<const N: usize>(&mut self) -> KeyResult<[u8; N]> {
+        if self.input.len() < N {
+            return Err(KeyError("unexpected end of key".to_string()));
+        }
+        let (head, rest) = self.input.split_at(N);
+        self.input = rest;
+        Ok(head.try_into().expect("length checked above"))
+    }
+
+    fn take_u8(&mut self) -> KeyResult<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn take_escaped(&mut self) -> KeyResult<Vec<u8>> {
+        let mut out = Vec::new();
+        loop {
+            match self.take_u8()? {
+                0x00 => match self.take_u8()? {
+                    0x00 => return Ok(out),
+                    0xFF => out.push(0x00),
+                    b => return Err(KeyError(format!("invalid escape 0x00 0x{:02x}", b))),
+                },
+                b => out.push(b),
+            }
+        }
+    }
+
+    /// Reads the marker before a sequence element; false at the end
+    fn take_seq_marker(&mut self) -> KeyResult<bool> {
+        match self.take_u8()? {
+            SEQ_ELEMENT => Ok(true),
+            SEQ_END => Ok(false),
+            b => Err(KeyError(format!("invalid sequence marker 0x{:02x}", b))),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut KeyDeserializer<'de> {
+    type Error = KeyError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> KeyResult<V::Value> {
+        Err(KeyError("key encoding is not self-describing".to_string()))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        match self.take_u8()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            b => Err(KeyError(format!("invalid bool 0x{:02x}", b))),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_i8((self.take_u8()? ^ 0x80) as i8)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_i16((u16::from_be_bytes(self.take()?) ^ (1 << 15)) as i16)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_i32((u32::from_be_bytes(self.take()?) ^ (1 << 31)) as i32)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_i64((u64::from_be_bytes(self.take()?) ^ (1 << 63)) as i64)
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_i128((u128::from_be_bytes(self.take()?) ^ (1 << 127)) as i128)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_u8(self.take_u8()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_u16(u16::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_u32(u32::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_u64(u64::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_u128(u128::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        let ordered = u32::from_be_bytes(self.take()?);
+        let bits = if ordered >> 31 == 1 {
+            ordered ^ (1 << 31)
+        } else {
+            !ordered
+        };
+        visitor.visit_f32(f32::from_bits(bits))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        let ordered = u64::from_be_bytes(self.take()?);
+        let bits = if ordered >> 63 == 1 {
+            ordered ^ (1 << 63)
+        } else {
+            !ordered
+        };
+        visitor.visit_f64(f64::from_bits(bits))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        let code = u32::from_be_bytes(self.take()?);
+        match char::from_u32(code) {
+            Some(c) => visitor.visit_char(c),
+            None => Err(KeyError(format!("invalid char 0x{:x}", code))),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        let bytes = self.take_escaped()?;
+        match String::from_utf8(bytes) {
+            Ok(s) => visitor.visit_string(s),
+            Err(e) => Err(KeyError(e.to_string())),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_byte_buf(self.take_escaped()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        match self.take_u8()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            b => Err(KeyError(format!("invalid option tag 0x{:02x}", b))),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> KeyResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> KeyResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_seq(Elements { de: self, remaining: None })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_seq(Elements { de: self, remaining: Some(len) })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> KeyResult<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        visitor.visit_map(Elements { de: self, remaining: None })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> KeyResult<V::Value> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> KeyResult<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> KeyResult<V::Value> {
+        self.deserialize_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// Elements of a sequence, map or tuple; `remaining` is `None` for
+/// variable-length collections, which carry element markers
+struct Elements<'a, 'de> {
+    de: &'a mut KeyDeserializer<'de>,
+    remaining: Option<usize>,
+}
+
+impl<'a, 'de> Elements<'a, 'de> {
+    fn has_next(&mut self) -> KeyResult<bool> {
+        match self.remaining.as_mut() {
+            Some(0) => Ok(false),
+            Some(n) => {
+                *n -= 1;
+                Ok(true)
+            }
+            None => self.de.take_seq_marker(),
+        }
+    }
+}
+
+impl<'a, 'de> SeqAccess<'de> for Elements<'a, 'de> {
+    type Error = KeyError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> KeyResult<Option<T::Value>> {
+        match self.has_next()? {
+            true => seed.deserialize(&mut *self.de).map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+impl<'a, 'de> MapAccess<'de> for Elements<'a, 'de> {
+    type Error = KeyError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> KeyResult<Option<K::Value>> {
+        match self.has_next()? {
+            true => seed.deserialize(&mut *self.de).map(Some),
+            false => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> KeyResult<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+impl<'de> EnumAccess<'de> for &mut KeyDeserializer<'de> {
+    type Error = KeyError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> KeyResult<(V::Value, Self)> {
+        let index = u32::from_be_bytes(self.take()?);
+        let value = seed.deserialize(index.into_deserializer())?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for &mut KeyDeserializer<'de> {
+    type Error = KeyError;
+
+    fn unit_variant(self) -> KeyResult<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> KeyResult<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> KeyResult<V::Value> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> KeyResult<V::Value> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde::{Deserialize, Serialize};
+    use std::fmt::Debug;
+
+    fn roundtrip<K: Serialize + DeserializeOwned + PartialEq + Debug>(key: K) {
+        let bytes = to_key_bytes(&key).unwrap();
+        assert_eq!(from_key_bytes::<K>(&bytes).unwrap(), key);
+    }
+
+    /// Asserts that encoding preserves the order of `keys`, which must be sorted
+    fn assert_ordered<K: Serialize + Debug>(keys: &[K]) {
+        let encoded: Vec<_> = keys.iter().map(|k| to_key_bytes(k).unwrap()).collect();
+        for (i, pair) in encoded.windows(2).enumerate() {
+            assert!(pair[0] < pair[1], "{:?} should sort before {:?}", keys[i], keys[i + 1]);
+        }
+    }
+
+    #[derive(Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
+    enum Kind {
+        User { id: u32 },
+        Group(String, i16),
+        Other,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Composite {
+        tenant: String,
+        kind: Kind,
+        tags: Vec<String>,
+        parent: Option<u64>,
+    }
+
+    #[test]
+    fn test_key_roundtrip() {
+        roundtrip(true);
+        roundtrip(-42i8);
+        roundtrip(i64::MIN);
+        roundtrip(u128::MAX);
+        roundtrip(-1.5f64);
+        roundtrip(f32::NEG_INFINITY);
+        roundtrip('é');
+        roundtrip("with\0nul".to_string());
+        roundtrip((1u8, "a".to_string(), -3i32));
+        roundtrip(vec![vec![1u16, 2], vec![], vec![3]]);
+        roundtrip(std::collections::BTreeMap::from([(1u32, "x".to_string())]));
+        roundtrip(Composite {
+            tenant: "acme".to_string(),
+            kind: Kind::Group("admins".to_string(), -7),
+            tags: vec!["a".to_string(), String::new()],
+            parent: Some(9),
+        });
+        roundtrip(Kind::User { id: 1 });
+        roundtrip(Kind::Other);
+        roundtrip(());
+    }
+
+    #[test]
+    fn test_key_order() {
+        assert_ordered(&[i64::MIN, -256, -1, 0, 1, 9, 10, 256, i64::MAX]);
+        assert_ordered(&[0u32, 9, 10, 255, 256, u32::MAX]);
+        assert_ordered(&[f64::NEG_INFINITY, -1.5, -0.0, 0.0, 1e-300, 2.0, f64::INFINITY]);
+        assert_ordered(&["", "\0", "a", "a\0", "a\0b", "ab", "b"]);
+        assert_ordered(&[None, Some(0u8), Some(1)]);
+        assert_ordered(&[("a".to_string(), 9u32), ("a".to_string(), 10), ("b".to_string(), 0)]);
+        assert_ordered(&[vec![], vec![1u8], vec![1, 0], vec![2]]);
+        assert_ordered(&[
+            Kind::User { id: 2 },
+            Kind::User { id: 10 },
+            Kind::Group("a".to_string(), -1),
+            Kind::Other,
+        ]);
+    }
+
+    #[test]
+    fn test_key_decode_errors() {
+        assert!(from_key_bytes::<u32>(&[0, 1]).is_err());
+        assert!(from_key_bytes::<u8>(&[0, 1]).is_err());
+        assert!(from_key_bytes::<bool>(&[2]).is_err());
+        assert!(from_key_bytes::<String>(b"abc\0\x01").is_err());
+        assert!(matches!(from_key_bytes::<String>(b"abc"), Err(Error::Serialization(_))));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_key_order_matches_value_order(
+            a in (any::<String>(), any::<i64>(), any::<Option<u16>>()),
+            b in (any::<String>(), any::<i64>(), any::<Option<u16>>()),
+        ) {
+            let (ea, eb) = (to_key_bytes(&a).unwrap(), to_key_bytes(&b).unwrap());
+            prop_assert_eq!(ea.cmp(&eb), a.cmp(&b));
+            prop_assert_eq!(from_key_bytes::<(String, i64, Option<u16>)>(&ea).unwrap(), a);
+        }
+    }
+}
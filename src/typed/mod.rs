@@ -0,0 +1,361 @@
+//! Typed access on top of the byte-oriented [`DB`] API.
+//!
+//! [`TypedDB<K, V, C>`] stores serde-serializable keys and values. Keys are
+//! encoded with an order-preserving encoding (see [`key`]), so iteration and
+//! range scans follow the natural order of `K`: `9 < 10`, `-1 < 0`,
+//! `("a", 2) < ("a", 10) < ("b", 0)`. Values are encoded with the codec
+//! `C`:
+//!
+//! - [`Json`] (the default)
+//! - [`Bincode`]
+//! - [`Postcard`] (with the `postcard` feature)
+//! - [`MsgPack`] (with the `msgpack` feature)
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::typed::TypedDB;
+//! use aidb::{Options, DB};
+//! use serde::{Deserialize, Serialize};
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Serialize, Deserialize)]
+//! struct Order {
+//!     item: String,
+//!     quantity: u32,
+//! }
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = Arc::new(DB::open("./data", Options::default())?);
+//! let orders: TypedDB<(String, u64), Order> = TypedDB::new(db);
+//!
+//! orders.put(&("alice".to_string(), 10), &Order { item: "tea".into(), quantity: 2 })?;
+//! orders.put(&("alice".to_string(), 9), &Order { item: "cake".into(), quantity: 1 })?;
+//!
+//! // Yields order 9 before order 10
+//! for entry in orders.iter()? {
+//!     let ((customer, id), order) = entry?;
+//!     println!("{} #{}: {:?}", customer, id, order);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod codec;
+pub mod key;
+
+#[cfg(feature = "msgpack")]
+pub use codec::MsgPack;
+#[cfg(feature = "postcard")]
+pub use codec::Postcard;
+pub use codec::{Bincode, Codec, Json};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::{DBIterator, Result, WriteBatch, DB};
+
+/// Marks the key, value and codec types without owning any of them, so the
+/// wrappers are `Send` and `Sync` whatever `K`, `V` and `C` are
+type Types<K, V, C> = PhantomData<fn() -> (K, V, C)>;
+
+/// A view of a database with typed keys and values.
+///
+/// `TypedDB` is a thin wrapper: it holds an `Arc<DB>` and encodes on the
+/// way in and decodes on the way out. Any number of typed views, with
+/// different types, can share one database; keeping their key spaces apart
+/// (for example with an enum or a tuple whose first field names the table)
+/// is up to the caller.
+pub struct TypedDB<K, V, C = Json> {
+    db: Arc<DB>,
+    _marker: Types<K, V, C>,
+}
+
+impl<K, V, C> Clone for TypedDB<K, V, C> {
+    fn clone(&self) -> Self {
+        Self { db: Arc::clone(&self.db), _marker: PhantomData }
+    }
+}
+
+impl<K, V, C> fmt::Debug for TypedDB<K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedDB").field("db", &self.db.path).finish()
+    }
+}
+
+impl<K, V, C> TypedDB<K, V, C>
+where
+    K: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    /// Wraps `db`.
+    pub fn new(db: Arc<DB>) -> Self {
+        Self { db, _marker: PhantomData }
+    }
+
+    /// Returns the underlying database.
+    pub fn db(&self) -> &Arc<DB> {
+        &self.db
+    }
+
+    /// Inserts or replaces the value for `key`.
+    pub fn put(&self, key: &K, value: &V) -> Result<()> {
+        self.db.put(&key::to_key_bytes(key)?, &C::encode(value)?)
+    }
+
+    /// Returns the value for `key`, or `None` if it does not exist.
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        match self.db.get(&key::to_key_bytes(key)?)? {
+            Some(bytes) => C::decode(&bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Deletes `key`.
+    pub fn delete(&self, key: &K) -> Result<()> {
+        self.db.delete(&key::to_key_bytes(key)?)
+    }
+
+    /// Applies all operations in `batch` atomically.
+    pub fn write(&self, batch: TypedWriteBatch<K, V, C>) -> Result<()> {
+        self.db.write(batch.batch)
+    }
+
+    /// Iterates over all entries in key order.
+    pub fn iter(&self) -> Result<TypedIterator<K, V, C>> {
+        self.scan(None, None)
+    }
+
+    /// Iterates over entries with `start <= key < end` in key order.
+    ///
+    /// Either bound may be `None` for an open range.
+    pub fn scan(&self, start: Option<&K>, end: Option<&K>) -> Result<TypedIterator<K, V, C>> {
+        let start = start.map(key::to_key_bytes).transpose()?;
+        let end = end.map(key::to_key_bytes).transpose()?;
+        let inner = self.db.scan(start.as_deref(), end.as_deref())?;
+        Ok(TypedIterator { inner, _marker: PhantomData })
+    }
+}
+
+/// A [`WriteBatch`] with typed keys and values.
+///
+/// Encoding errors are reported by [`put`](Self::put) and
+/// [`delete`](Self::delete), so a batch that reaches
+/// [`TypedDB::write`] always applies in full.
+pub struct TypedWriteBatch<K, V, C = Json> {
+    batch: WriteBatch,
+    _marker: Types<K, V, C>,
+}
+
+impl<K, V, C> Default for TypedWriteBatch<K, V, C> {
+    fn default() -> Self {
+        Self { batch: WriteBatch::new(), _marker: PhantomData }
+    }
+}
+
+impl<K, V, C> fmt::Debug for TypedWriteBatch<K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedWriteBatch").field("len", &self.batch.len()).finish()
+    }
+}
+
+impl<K, V, C> TypedWriteBatch<K, V, C>
+where
+    K: Serialize,
+    V: Serialize,
+    C: Codec,
+{
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a put operation.
+    pub fn put(&mut self, key: &K, value: &V) -> Result<()> {
+        self.batch.put(&key::to_key_bytes(key)?, &C::encode(value)?);
+        Ok(())
+    }
+
+    /// Adds a delete operation.
+    pub fn delete(&mut self, key: &K) -> Result<()> {
+        self.batch.delete(&key::to_key_bytes(key)?);
+        Ok(())
+    }
+
+    /// Returns the number of operations in the batch.
+    pub fn len(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Returns true if the batch has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.batch.is_empty()
+    }
+
+    /// Returns the encoded batch.
+    pub fn into_inner(self) -> WriteBatch {
+        self.batch
+    }
+}
+
+/// Iterator over decoded entries of a [`TypedDB`], in key order.
+///
+/// Yields `Err` for an entry that fails to decode (for example one written
+/// through another view with different types) and continues with the next
+/// entry.
+pub struct TypedIterator<K, V, C = Json> {
+    inner: DBIterator,
+    _marker: Types<K, V, C>,
+}
+
+impl<K, V, C> TypedIterator<K, V, C>
+where
+    K: Serialize + DeserializeOwned,
+    V: DeserializeOwned,
+    C: Codec,
+{
+    /// Positions the iterator at the first entry with a key `>= target`.
+    pub fn seek(&mut self, target: &K) -> Result<()> {
+        self.inner.seek(&key::to_key_bytes(target)?);
+        Ok(())
+    }
+
+    /// Returns the underlying byte iterator.
+    pub fn into_inner(self) -> DBIterator {
+        self.inner
+    }
+}
+
+impl<K, V, C> Iterator for TypedIterator<K, V, C>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+    C: Codec,
+{
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.inner.valid() {
+            return None;
+        }
+        let entry = key::from_key_bytes(self.inner.key())
+            .and_then(|k| Ok((k, C::decode(self.inner.value())?)));
+        self.inner.next();
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Account {
+        owner: String,
+        balance: i64,
+    }
+
+    fn open(dir: &TempDir) -> Arc<DB> {
+        Arc::new(DB::open(dir.path(), Options::default()).unwrap())
+    }
+
+    fn account(owner: &str, balance: i64) -> Account {
+        Account { owner: owner.to_string(), balance }
+    }
+
+    #[test]
+    fn test_typed_db_basic() {
+        let dir = TempDir::new().unwrap();
+        let accounts: TypedDB<u64, Account> = TypedDB::new(open(&dir));
+
+        accounts.put(&7, &account("alice", 100)).unwrap();
+        assert_eq!(accounts.get(&7).unwrap(), Some(account("alice", 100)));
+        assert_eq!(accounts.get(&8).unwrap(), None);
+
+        accounts.delete(&7).unwrap();
+        assert_eq!(accounts.get(&7).unwrap(), None);
+    }
+
+    #[test]
+    fn test_typed_db_numeric_key_order() {
+        let dir = TempDir::new().unwrap();
+        let db: TypedDB<i32, String, Bincode> = TypedDB::new(open(&dir));
+        for k in [10, -3, 9, 0, 100, -20] {
+            db.put(&k, &k.to_string()).unwrap();
+        }
+
+        let keys: Vec<i32> = db.iter().unwrap().map(|e| e.unwrap().0).collect();
+        assert_eq!(keys, [-20, -3, 0, 9, 10, 100]);
+
+        let keys: Vec<i32> = db.scan(Some(&-3), Some(&10)).unwrap().map(|e| e.unwrap().0).collect();
+        assert_eq!(keys, [-3, 0, 9]);
+
+        let mut iter = db.iter().unwrap();
+        iter.seek(&1).unwrap();
+        assert_eq!(iter.next().unwrap().unwrap(), (9, "9".to_string()));
+    }
+
+    #[test]
+    fn test_typed_write_batch() {
+        let dir = TempDir::new().unwrap();
+        let db: TypedDB<(String, u32), Account> = TypedDB::new(open(&dir));
+        db.put(&("bank".to_string(), 1), &account("carol", 5)).unwrap();
+
+        let mut batch = TypedWriteBatch::new();
+        batch.put(&("bank".to_string(), 10), &account("alice", 1)).unwrap();
+        batch.put(&("bank".to_string(), 2), &account("bob", 2)).unwrap();
+        batch.delete(&("bank".to_string(), 1)).unwrap();
+        assert_eq!(batch.len(), 3);
+        db.write(batch).unwrap();
+
+        let entries: Vec<_> = db.iter().unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(
+            entries,
+            [
+                (("bank".to_string(), 2), account("bob", 2)),
+                (("bank".to_string(), 10), account("alice", 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_typed_iterator_reports_undecodable_entries() {
+        let dir = TempDir::new().unwrap();
+        let db = open(&dir);
+        let typed: TypedDB<u8, u32> = TypedDB::new(Arc::clone(&db));
+        typed.put(&1, &10).unwrap();
+        db.put(&[2], b"not json").unwrap();
+        typed.put(&3, &30).unwrap();
+
+        let entries: Vec<_> = typed.iter().unwrap().collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].as_ref().unwrap(), &(1, 10));
+        assert!(matches!(entries[1], Err(crate::Error::Serialization(_))));
+        assert_eq!(entries[2].as_ref().unwrap(), &(3, 30));
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn test_typed_db_postcard() {
+        let dir = TempDir::new().unwrap();
+        let db: TypedDB<String, Account, Postcard> = TypedDB::new(open(&dir));
+        db.put(&"a".to_string(), &account("alice", -1)).unwrap();
+        assert_eq!(db.get(&"a".to_string()).unwrap(), Some(account("alice", -1)));
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_typed_db_msgpack() {
+        let dir = TempDir::new().unwrap();
+        let db: TypedDB<String, Account, MsgPack> = TypedDB::new(open(&dir));
+        db.put(&"a".to_string(), &account("alice", -1)).unwrap();
+        assert_eq!(db.get(&"a".to_string()).unwrap(), Some(account("alice", -1)));
+    }
+}
@@ -0,0 +1,206 @@
+//! Small filesystem and time helpers shared across modules.
+
+use crate::env::{AccessHint, Env};
+use crate::error::Result;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+// `std::time` panics on `wasm32-unknown-unknown`; use the browser's clock there
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Flushes the directory entry metadata of `dir` to persistent storage.
+///
+/// Creating, renaming or deleting a file only changes the directory that
+/// contains it; syncing the file itself does not make that change durable.
+/// This must be called after such a change when the change itself has to
+/// survive a power loss (e.g. a new SSTable or a WAL rotation).
+///
+/// This is a no-op on platforms where directories cannot be opened as files.
+pub fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    std::fs::File::open(dir)?.sync_all()?;
+
+    #[cfg(not(unix))]
+    let _ = dir;
+
+    Ok(())
+}
+
+/// Name of the file that holds the space reserved by
+/// [`Options::reserved_disk_space`](crate::Options::reserved_disk_space).
+pub const RESERVED_FILE: &str = "RESERVED";
+
+/// Makes sure `dir/RESERVED` occupies `size` bytes on disk.
+///
+/// The file is filled with zeros rather than extended with `set_len`, since a
+/// sparse file would not actually claim any blocks. A size of 0 removes the
+/// file.
+pub fn reserve_space(env: &dyn Env, dir: &Path, size: u64) -> Result<()> {
+    let path = dir.join(RESERVED_FILE);
+    if size == 0 {
+        return release_reserved_space(env, dir);
+    }
+    if env.file_size(&path).is_ok_and(|len| len == size) {
+        return Ok(());
+    }
+
+    let result = (|| {
+        let mut file = env.new_writable_file(&path)?;
+        let chunk = vec![0u8; 64 * 1024];
+        let mut remaining = size;
+        while remaining > 0 {
+            let n = remaining.min(chunk.len() as u64) as usize;
+            file.write_all(&chunk[..n])?;
+            remaining -= n as u64;
+        }
+        file.sync()?;
+        env.sync_dir(dir)
+    })();
+
+    if result.is_err() {
+        let _ = env.remove_file(&path);
+    }
+    Ok(result?)
+}
+
+/// Deletes the reserved space file, if any, freeing its space.
+pub fn release_reserved_space(env: &dyn Env, dir: &Path) -> Result<()> {
+    match env.remove_file(&dir.join(RESERVED_FILE)) {
+        Ok(()) => Ok(env.sync_dir(dir)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads exactly `buf.len()` bytes from `file` starting at `offset`.
+///
+/// Unlike seek + read this does not use the shared file cursor, so several
+/// threads can read from the same file (or from `try_clone`d handles, which
+/// share the cursor) at the same time.
+pub fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileExt;
+        file.read_exact_at(buf, offset)
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileExt;
+        let mut read = 0;
+        while read < buf.len() {
+            match file.seek_read(&mut buf[read..], offset + read as u64)? {
+                0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                n => read += n,
+            }
+        }
+        Ok(())
+    }
+
+    // No positional reads: fall back to the shared cursor
+    #[cfg(not(any(unix, windows)))]
+    {
+        use std::io::{Read, Seek, SeekFrom};
+        let mut file = file;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)
+    }
+}
+
+/// Passes `hint` for the whole of `file` to `posix_fadvise`.
+///
+/// This is a no-op on platforms other than Linux.
+pub fn fadvise(file: &File, hint: AccessHint) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+        let advice = match hint {
+            AccessHint::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            AccessHint::DontNeed => libc::POSIX_FADV_DONTNEED,
+        };
+        // SAFETY: the descriptor is owned by `file` for the whole call
+        match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice) } {
+            0 => Ok(()),
+            errno => Err(std::io::Error::from_raw_os_error(errno)),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (file, hint);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::PosixEnv;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sync_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("file"), b"data").unwrap();
+        sync_dir(temp_dir.path()).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_sync_missing_dir_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(sync_dir(&temp_dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_fadvise() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("file");
+        std::fs::write(&path, b"data").unwrap();
+        let file = File::open(&path).unwrap();
+        fadvise(&file, AccessHint::Sequential).unwrap();
+        fadvise(&file, AccessHint::DontNeed).unwrap();
+
+        let mut buf = [0u8; 4];
+        read_exact_at(&file, &mut buf, 0).unwrap();
+        assert_eq!(&buf, b"data");
+    }
+
+    #[test]
+    fn test_reserve_space() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(RESERVED_FILE);
+        let env = PosixEnv;
+
+        reserve_space(&env, temp_dir.path(), 100_000).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 100_000);
+
+        reserve_space(&env, temp_dir.path(), 10).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 10);
+
+        release_reserved_space(&env, temp_dir.path()).unwrap();
+        assert!(!path.exists());
+        release_reserved_space(&env, temp_dir.path()).unwrap();
+
+        reserve_space(&env, temp_dir.path(), 10).unwrap();
+        reserve_space(&env, temp_dir.path(), 0).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_read_exact_at() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("file");
+        std::fs::write(&path, b"0123456789").unwrap();
+        let file = File::open(&path).unwrap();
+
+        let mut buf = [0u8; 4];
+        read_exact_at(&file, &mut buf, 3).unwrap();
+        assert_eq!(&buf, b"3456");
+        assert!(read_exact_at(&file, &mut buf, 8).is_err());
+    }
+}
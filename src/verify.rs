@@ -0,0 +1,156 @@
+//! Full-database checksum verification (scrubbing).
+//!
+//! [`DB::verify_checksums`](crate::DB::verify_checksums) re-reads every SSTable
+//! block and WAL record from disk and validates its CRC, collecting every
+//! problem it finds into a [`VerifyReport`] instead of stopping at the first
+//! one. This is meant to be run periodically and after unclean shutdowns.
+//! With [`Options::authenticate_records`](crate::Options::authenticate_records)
+//! the tag of every record is checked as well.
+
+use crate::env::Env;
+use crate::error::{Error, Result};
+use crate::record_auth::RecordAuthenticator;
+use crate::sstable::SSTableReader;
+use crate::wal::WALReader;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A single corrupted location found during verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    /// File containing the corruption
+    pub path: PathBuf,
+    /// Byte offset of the corrupted block or record, if known
+    pub offset: Option<u64>,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{:?} at offset {}: {}", self.path, offset, self.message),
+            None => write!(f, "{:?}: {}", self.path, self.message),
+        }
+    }
+}
+
+/// Result of a checksum verification run.
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Number of SSTable files checked
+    pub sstables_checked: usize,
+    /// Number of WAL files checked
+    pub wal_files_checked: usize,
+    /// Number of WAL entries read successfully
+    pub wal_entries_checked: u64,
+    /// Every corruption found
+    pub corruptions: Vec<Corruption>,
+}
+
+impl VerifyReport {
+    /// Returns true if no corruption was found.
+    pub fn is_ok(&self) -> bool {
+        self.corruptions.is_empty()
+    }
+}
+
+/// Verifies the blocks of an SSTable, adding any corruption to `report`.
+pub(crate) fn verify_sstable(
+    reader: &SSTableReader,
+    range: Option<(&[u8], &[u8])>,
+    report: &mut VerifyReport,
+) -> Result<()> {
+    report.sstables_checked += 1;
+    for (offset, error) in reader.verify_checksums(range)? {
+        report.corruptions.push(Corruption {
+            path: reader.file_path().to_path_buf(),
+            offset: Some(offset),
+            message: error.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Verifies every record of a WAL file, adding any corruption to `report`.
+///
+/// Record framing is lost after the first bad record, so checking stops
+/// there; records failing `authenticator` are reported and skipped.
+pub(crate) fn verify_wal(
+    path: &Path,
+    env: &dyn Env,
+    authenticator: Option<Arc<RecordAuthenticator>>,
+    report: &mut VerifyReport,
+) -> Result<()> {
+    report.wal_files_checked += 1;
+    let mut reader = WALReader::new_with_env(path, env)?.with_authenticator(authenticator);
+
+    loop {
+        let offset = reader.position();
+        let message = match reader.read_next() {
+            Ok(Some(_)) => {
+                report.wal_entries_checked += 1;
+                continue;
+            }
+            Ok(None) => return Ok(()),
+            Err(Error::AuthenticationFailed(msg)) => {
+                report.corruptions.push(Corruption {
+                    path: path.to_path_buf(),
+                    offset: Some(offset),
+                    message: msg,
+                });
+                continue;
+            }
+            Err(Error::Corruption(msg)) => msg,
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                "truncated record".to_string()
+            }
+            Err(e) => return Err(e),
+        };
+
+        report.corruptions.push(Corruption {
+            path: path.to_path_buf(),
+            offset: Some(offset),
+            message,
+        });
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::PosixEnv;
+    use crate::wal::WAL;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_verify_wal_reports_corrupted_record() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("000001.log");
+        {
+            let mut wal = WAL::open(&path).unwrap();
+            wal.append(b"first").unwrap();
+            wal.append(b"second").unwrap();
+            wal.sync().unwrap();
+        }
+
+        let mut report = VerifyReport::default();
+        verify_wal(&path, &PosixEnv, None, &mut report).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.wal_entries_checked, 2);
+
+        // Flip a byte in the second record's payload
+        let mut data = std::fs::read(&path).unwrap();
+        let len = data.len();
+        data[len - 1] ^= 0xff;
+        std::fs::write(&path, data).unwrap();
+
+        let mut report = VerifyReport::default();
+        verify_wal(&path, &PosixEnv, None, &mut report).unwrap();
+        assert_eq!(report.wal_entries_checked, 1);
+        assert_eq!(report.corruptions.len(), 1);
+        assert_eq!(report.corruptions[0].offset, Some(7 + 5));
+    }
+}
@@ -0,0 +1,312 @@
+//! Encoding of database writes stored in WAL records.
+//!
+//! A WAL record holds a single write:
+//! - Tag (1 byte): `0x01` for a put, `0x02` for a delete
+//! - Sequence number (8 bytes, little-endian)
+//! - Key length (4 bytes, little-endian)
+//! - Key (variable)
+//! - Value (put only): the rest of the record
+//!
+//! or a whole [`WriteBatch`] of several writes:
+//! - Tag (1 byte): `0x03`
+//! - Sequence number of the first write (8 bytes, little-endian); the
+//!   following writes have consecutive sequence numbers
+//! - The batch in the [`WriteBatch::serialize`] format
+//!
+//! Since a record is checksummed as a whole, recovery either replays every
+//! write of a batch or none of them.
+//!
+//! With record authentication (see [`record_auth`](crate::record_auth)),
+//! either form is wrapped as:
+//! - Tag (1 byte): `0x04`
+//! - Key version (4 bytes, little-endian)
+//! - The record
+//! - Authentication tag of the record (16 bytes)
+//!
+//! Logs written before sequence numbers were recorded use a text-prefixed
+//! format (`put:<key_len>:<key>:<value>` and `del:<key_len>:<key>`). Those
+//! entries are still decoded; the caller supplies the sequence number to
+//! assign to them.
+
+use crate::error::{Error, Result};
+use crate::record_auth::{self, RecordAuthenticator};
+use crate::write_batch::{WriteBatch, WriteOp};
+
+const TAG_PUT: u8 = 0x01;
+const TAG_DELETE: u8 = 0x02;
+const TAG_BATCH: u8 = 0x03;
+const TAG_AUTHENTICATED: u8 = 0x04;
+
+/// Size of the tag, sequence number and key length
+const HEADER_SIZE: usize = 1 + 8 + 4;
+
+/// A write recovered from the WAL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalEntry {
+    /// Sequence number the write was assigned originally
+    pub sequence: u64,
+    /// The write itself
+    pub op: WriteOp,
+}
+
+/// Encode a put
+pub fn encode_put(sequence: u64, key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_SIZE + key.len() + value.len());
+    encode_header(&mut buf, TAG_PUT, sequence, key);
+    buf.extend_from_slice(value);
+    buf
+}
+
+/// Encode a delete
+pub fn encode_delete(sequence: u64, key: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_SIZE + key.len());
+    encode_header(&mut buf, TAG_DELETE, sequence, key);
+    buf
+}
+
+/// Encode a batch whose first write has sequence number `sequence`
+pub fn encode_batch(sequence: u64, batch: &WriteBatch) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(9 + batch.approximate_size());
+    buf.push(TAG_BATCH);
+    buf.extend_from_slice(&sequence.to_le_bytes());
+    buf.extend_from_slice(&batch.serialize());
+    buf
+}
+
+/// Wrap an encoded record with a tag made with the key of `version`
+pub fn encode_authenticated(
+    authenticator: &RecordAuthenticator,
+    version: u32,
+    record: &[u8],
+) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(5 + record.len() + record_auth::TAG_SIZE);
+    buf.push(TAG_AUTHENTICATED);
+    buf.extend_from_slice(&version.to_le_bytes());
+    buf.extend_from_slice(record);
+    buf.extend_from_slice(&authenticator.tag(version, b"", record)?);
+    Ok(buf)
+}
+
+/// Verify the tag of a wrapped record and return the record inside.
+///
+/// Fails with [`Error::AuthenticationFailed`] if the record is not wrapped.
+pub fn verify_authenticated<'a>(
+    authenticator: &RecordAuthenticator,
+    data: &'a [u8],
+) -> Result<&'a [u8]> {
+    if data.first() != Some(&TAG_AUTHENTICATED) {
+        return Err(Error::AuthenticationFailed(
+            "WAL record was written without record authentication".to_string(),
+        ));
+    }
+    if data.len() < 5 {
+        return Err(Error::corruption("Invalid authenticated WAL entry: too short"));
+    }
+    let version = u32::from_le_bytes(data[1..5].try_into().unwrap());
+    authenticator.verify(version, b"", &data[5..])
+}
+
+fn encode_header(buf: &mut Vec<u8>, tag: u8, sequence: u64, key: &[u8]) {
+    buf.push(tag);
+    buf.extend_from_slice(&sequence.to_le_bytes());
+    buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    buf.extend_from_slice(key);
+}
+
+/// Decode the writes in a WAL record, in sequence order.
+///
+/// `legacy_sequence` is assigned to entries in the old text format, which
+/// carry no sequence number.
+pub fn decode(data: &[u8], legacy_sequence: u64) -> Result<Vec<WalEntry>> {
+    match data.first() {
+        Some(&TAG_PUT) | Some(&TAG_DELETE) => Ok(vec![decode_binary(data)?]),
+        Some(&TAG_BATCH) => decode_batch(data),
+        // Decoded without checking the tag; see `verify_authenticated`
+        Some(&TAG_AUTHENTICATED) if data.len() >= 5 => {
+            decode(record_auth::split_tag(&data[5..])?.0, legacy_sequence)
+        }
+        _ if data.starts_with(b"put:") || data.starts_with(b"del:") => {
+            Ok(vec![decode_legacy(data, legacy_sequence)?])
+        }
+        _ => Err(Error::corruption("Unknown WAL entry type")),
+    }
+}
+
+fn decode_batch(data: &[u8]) -> Result<Vec<WalEntry>> {
+    if data.len() < 9 {
+        return Err(Error::corruption("Invalid WAL batch: too short"));
+    }
+    let sequence = u64::from_le_bytes(data[1..9].try_into().unwrap());
+    let batch = WriteBatch::deserialize(&data[9..])?;
+    if sequence.checked_add(batch.len() as u64).is_none() {
+        return Err(Error::corruption("Invalid WAL batch: sequence overflow"));
+    }
+    Ok((sequence..)
+        .zip(batch.iter())
+        .map(|(sequence, op)| WalEntry { sequence, op: op.clone() })
+        .collect())
+}
+
+fn decode_binary(data: &[u8]) -> Result<WalEntry> {
+    if data.len() < HEADER_SIZE {
+        return Err(Error::corruption("Invalid WAL entry: too short"));
+    }
+
+    let sequence = u64::from_le_bytes(data[1..9].try_into().unwrap());
+    let key_len = u32::from_le_bytes(data[9..13].try_into().unwrap()) as usize;
+    let rest = &data[HEADER_SIZE..];
+    if rest.len() < key_len {
+        return Err(Error::corruption("Invalid WAL entry: key too short"));
+    }
+
+    let key = rest[..key_len].to_vec();
+    let op = if data[0] == TAG_PUT {
+        WriteOp::Put { key, value: rest[key_len..].to_vec() }
+    } else {
+        if rest.len() != key_len {
+            return Err(Error::corruption("Invalid WAL entry: trailing data after delete"));
+        }
+        WriteOp::Delete { key }
+    };
+
+    Ok(WalEntry { sequence, op })
+}
+
+fn decode_legacy(data: &[u8], sequence: u64) -> Result<WalEntry> {
+    let is_put = data.starts_with(b"put:");
+    let entry = &data[4..];
+
+    if entry.len() < 4 {
+        return Err(Error::corruption("Invalid WAL entry: too short"));
+    }
+    let key_len = u32::from_le_bytes(entry[..4].try_into().unwrap()) as usize;
+    let entry = &entry[4..];
+
+    if entry.first() != Some(&b':') {
+        return Err(Error::corruption("Invalid WAL entry: missing separator"));
+    }
+    let entry = &entry[1..];
+
+    if entry.len() < key_len {
+        return Err(Error::corruption("Invalid WAL entry: key too short"));
+    }
+    let key = entry[..key_len].to_vec();
+
+    let op = if is_put {
+        let entry = &entry[key_len..];
+        if entry.first() != Some(&b':') {
+            return Err(Error::corruption("Invalid WAL entry: missing value separator"));
+        }
+        WriteOp::Put { key, value: entry[1..].to_vec() }
+    } else {
+        WriteOp::Delete { key }
+    };
+
+    Ok(WalEntry { sequence, op })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_one(data: &[u8], legacy_sequence: u64) -> WalEntry {
+        let mut entries = decode(data, legacy_sequence).unwrap();
+        assert_eq!(entries.len(), 1);
+        entries.pop().unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let entry = decode_one(&encode_put(42, b"key", b"value"), 0);
+        assert_eq!(
+            entry,
+            WalEntry {
+                sequence: 42,
+                op: WriteOp::Put { key: b"key".to_vec(), value: b"value".to_vec() }
+            }
+        );
+
+        let entry = decode_one(&encode_delete(u64::MAX, b"key"), 0);
+        assert_eq!(
+            entry,
+            WalEntry { sequence: u64::MAX, op: WriteOp::Delete { key: b"key".to_vec() } }
+        );
+
+        // Empty keys and values
+        let entry = decode_one(&encode_put(1, b"", b""), 0);
+        assert_eq!(entry.op, WriteOp::Put { key: Vec::new(), value: Vec::new() });
+    }
+
+    #[test]
+    fn test_batch_roundtrip() {
+        let mut batch = WriteBatch::new();
+        batch.put(b"a", b"1");
+        batch.delete(b"b");
+        batch.put(b"c", b"3");
+
+        let entries = decode(&encode_batch(10, &batch), 0).unwrap();
+        let sequences: Vec<_> = entries.iter().map(|entry| entry.sequence).collect();
+        assert_eq!(sequences, vec![10, 11, 12]);
+        let ops: Vec<_> = entries.iter().map(|entry| &entry.op).collect();
+        assert_eq!(ops, batch.iter().collect::<Vec<_>>());
+
+        let encoded = encode_batch(10, &batch);
+        assert!(decode(&encoded[..encoded.len() - 1], 0).is_err());
+        assert!(decode(&encode_batch(u64::MAX, &batch), 0).is_err());
+    }
+
+    #[test]
+    fn test_authenticated_roundtrip() {
+        use crate::env::StaticKeyProvider;
+        use std::sync::Arc;
+
+        let auth = RecordAuthenticator::new(Arc::new(StaticKeyProvider::new([5; 32])));
+        let record = encode_put(3, b"key", b"value");
+        let mut wrapped = encode_authenticated(&auth, 0, &record).unwrap();
+        assert_eq!(verify_authenticated(&auth, &wrapped).unwrap(), record.as_slice());
+        assert_eq!(decode(&wrapped, 0).unwrap(), decode(&record, 0).unwrap());
+
+        assert!(matches!(
+            verify_authenticated(&auth, &record),
+            Err(Error::AuthenticationFailed(_))
+        ));
+        let last = wrapped.len() - 20;
+        wrapped[last] ^= 1;
+        assert!(matches!(
+            verify_authenticated(&auth, &wrapped),
+            Err(Error::AuthenticationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_legacy_format() {
+        let mut put = b"put:".to_vec();
+        put.extend_from_slice(&3u32.to_le_bytes());
+        put.extend_from_slice(b":key:value");
+        let entry = decode_one(&put, 7);
+        assert_eq!(
+            entry,
+            WalEntry {
+                sequence: 7,
+                op: WriteOp::Put { key: b"key".to_vec(), value: b"value".to_vec() }
+            }
+        );
+
+        let mut del = b"del:".to_vec();
+        del.extend_from_slice(&3u32.to_le_bytes());
+        del.extend_from_slice(b":key");
+        let entry = decode_one(&del, 8);
+        assert_eq!(entry, WalEntry { sequence: 8, op: WriteOp::Delete { key: b"key".to_vec() } });
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(decode(b"", 0).is_err());
+        assert!(decode(b"garbage", 0).is_err());
+        assert!(decode(&encode_put(1, b"key", b"value")[..10], 0).is_err());
+
+        let mut put = encode_put(1, b"key", b"");
+        put[9] = 200; // key length beyond the record
+        assert!(decode(&put, 0).is_err());
+    }
+}
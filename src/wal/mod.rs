@@ -6,8 +6,10 @@
 //! ## Architecture
 //!
 //! - **Record Format**: Each entry is encoded as a record with CRC32 checksum
+//! - **Entry Format**: Database writes carry their sequence number (see [`entry`])
 //! - **Fragmentation**: Large entries are split into multiple records
-//! - **Recovery**: On startup, the WAL is replayed to restore the MemTable
+//! - **Recovery**: On startup, the WAL is replayed to restore the MemTable,
+//!   with several threads (see [`recovery`])
 //!
 //! ## Usage
 //!
@@ -30,57 +32,137 @@
 //! # }
 //! ```
 
+pub mod entry;
 pub mod reader;
 pub mod record;
+pub mod recovery;
 pub mod writer;
 
+pub use entry::WalEntry;
 pub use reader::WALReader;
 pub use record::{Record, RecordType};
 pub use writer::WALWriter;
 
-use crate::error::Result;
-use std::path::Path;
+use crate::env::{Env, PosixEnv};
+use crate::error::{Error, Result};
+use crate::record_auth::RecordAuthenticator;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Directory (inside the database directory) rotated WAL files are kept in
+/// when `Options::wal_archive_size_limit` is set
+pub const ARCHIVE_DIR: &str = "archive";
 
 /// WAL manager that coordinates reading and writing
 pub struct WAL {
-    writer: WALWriter,
+    path: PathBuf,
+    /// `None` if opened with [`open_read_only`](Self::open_read_only)
+    writer: Option<WALWriter>,
+    /// Size of a WAL opened read-only
+    size: u64,
+    /// Tags each entry with the key of the version
+    authenticator: Option<(Arc<RecordAuthenticator>, u32)>,
 }
 
 impl WAL {
     /// Open or create a WAL file
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let writer = WALWriter::new(path)?;
-        Ok(Self { writer })
+        Self::open_with_env(path, &PosixEnv)
+    }
+
+    /// Open or create a WAL file through `env`
+    pub fn open_with_env<P: AsRef<Path>>(path: P, env: &dyn Env) -> Result<Self> {
+        let writer = WALWriter::new_with_env(path, env)?;
+        Ok(Self {
+            path: writer.path().to_path_buf(),
+            writer: Some(writer),
+            size: 0,
+            authenticator: None,
+        })
+    }
+
+    /// Opens a WAL file, if it exists, without opening it for writing.
+    ///
+    /// Appends fail with [`Error::InvalidState`]; syncing and flushing do
+    /// nothing.
+    pub fn open_read_only<P: AsRef<Path>>(path: P, env: &dyn Env) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let size = if env.file_exists(&path) {
+            env.file_size(&path)?
+        } else {
+            0
+        };
+        Ok(Self { path, writer: None, size, authenticator: None })
+    }
+
+    /// Sets the size of the record buffer. See [`WALWriter::with_buffer_size`].
+    pub fn with_buffer_size(self, buffer_size: usize) -> Self {
+        Self { writer: self.writer.map(|w| w.with_buffer_size(buffer_size)), ..self }
+    }
+
+    /// Tags each entry appended from now on with the active key of
+    /// `authenticator` (see [`entry::encode_authenticated`]).
+    pub fn with_authenticator(
+        mut self,
+        authenticator: Option<Arc<RecordAuthenticator>>,
+    ) -> Result<Self> {
+        self.authenticator = match authenticator {
+            Some(authenticator) => {
+                let version = authenticator.active_version()?;
+                Some((authenticator, version))
+            }
+            None => None,
+        };
+        Ok(self)
     }
 
     /// Append an entry to the WAL
     pub fn append(&mut self, data: &[u8]) -> Result<()> {
-        self.writer.append(data)
+        let Some(ref mut writer) = self.writer else {
+            return Err(Error::InvalidState(format!("WAL {:?} is opened read-only", self.path)));
+        };
+        match self.authenticator {
+            Some((ref authenticator, version)) => {
+                let wrapped = entry::encode_authenticated(authenticator, version, data)?;
+                writer.append(&wrapped)
+            }
+            None => writer.append(data),
+        }
     }
 
     /// Sync the WAL to disk
     pub fn sync(&mut self) -> Result<()> {
-        self.writer.sync()
+        self.writer.as_mut().map_or(Ok(()), WALWriter::sync)
+    }
+
+    /// Write buffered records to the file without syncing it
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.as_mut().map_or(Ok(()), WALWriter::flush)
     }
 
     /// Get the current file size
     pub fn size(&self) -> u64 {
-        self.writer.file_size()
+        self.writer.as_ref().map_or(self.size, WALWriter::file_size)
     }
 
     /// Get the path to the WAL file
     pub fn path(&self) -> &Path {
-        self.writer.path()
+        &self.path
     }
 
     /// Close the WAL
     pub fn close(self) -> Result<()> {
-        self.writer.close()
+        self.writer.map_or(Ok(()), WALWriter::close)
     }
 
     /// Recover entries from a WAL file
     pub fn recover<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<u8>>> {
-        let mut reader = WALReader::new(path)?;
+        Self::recover_with_env(path, &PosixEnv)
+    }
+
+    /// Recover entries from a WAL file read through `env`
+    pub fn recover_with_env<P: AsRef<Path>>(path: P, env: &dyn Env) -> Result<Vec<Vec<u8>>> {
+        let mut reader = WALReader::new_with_env(path, env)?;
         reader.recover_all()
     }
 }
@@ -1,26 +1,44 @@
 //! WAL reader implementation for recovery.
 
 use super::record::{Record, RecordType, HEADER_SIZE};
+use crate::env::{Env, PosixEnv, SequentialFile};
 use crate::error::{Error, Result};
-use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use crate::record_auth::RecordAuthenticator;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
+use std::sync::Arc;
 
 /// WAL reader for reading and recovering from log files
 pub struct WALReader {
     /// Buffered reader for efficient I/O
-    reader: BufReader<File>,
+    reader: Box<dyn SequentialFile>,
     /// Current read position
     position: u64,
+    /// Verifies the tag of every entry
+    authenticator: Option<Arc<RecordAuthenticator>>,
 }
 
 impl WALReader {
     /// Open a WAL file for reading
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = File::open(path).map_err(Error::Io)?;
-        let reader = BufReader::new(file);
+        Self::new_with_env(path, &PosixEnv)
+    }
+
+    /// Open a WAL file for reading through `env`
+    pub fn new_with_env<P: AsRef<Path>>(path: P, env: &dyn Env) -> Result<Self> {
+        let reader = env.new_sequential_file(path.as_ref()).map_err(Error::Io)?;
 
-        Ok(Self { reader, position: 0 })
+        Ok(Self { reader, position: 0, authenticator: None })
+    }
+
+    /// Verify the tag of every entry read with `authenticator` and return
+    /// the entries without it.
+    ///
+    /// Entries written without record authentication then fail with
+    /// [`Error::AuthenticationFailed`].
+    pub fn with_authenticator(mut self, authenticator: Option<Arc<RecordAuthenticator>>) -> Self {
+        self.authenticator = authenticator;
+        self
     }
 
     /// Read the next complete entry from the WAL
@@ -28,6 +46,17 @@ impl WALReader {
     /// Returns None if EOF is reached.
     /// Handles fragmented records by reassembling them.
     pub fn read_next(&mut self) -> Result<Option<Vec<u8>>> {
+        let data = self.read_fragments()?;
+        match (data, &self.authenticator) {
+            (Some(data), Some(authenticator)) => {
+                Ok(Some(super::entry::verify_authenticated(authenticator, &data)?.to_vec()))
+            }
+            (data, _) => Ok(data),
+        }
+    }
+
+    /// Reads and reassembles the fragments of the next entry
+    fn read_fragments(&mut self) -> Result<Option<Vec<u8>>> {
         let mut assembled_data = Vec::new();
         let mut expecting_continuation = false;
 
@@ -107,7 +136,13 @@ impl WALReader {
         buffer[..HEADER_SIZE].copy_from_slice(&header);
 
         if length > 0 {
-            self.reader.read_exact(&mut buffer[HEADER_SIZE..]).map_err(Error::Io)?;
+            self.reader.read_exact(&mut buffer[HEADER_SIZE..]).map_err(|e| match e.kind() {
+                // Still being written, or cut short by a crash
+                std::io::ErrorKind::UnexpectedEof => {
+                    Error::corruption("WAL ends in the middle of a record")
+                }
+                _ => Error::Io(e),
+            })?;
         }
 
         self.position += total_size as u64;
@@ -7,7 +7,7 @@
 //! - Data (variable): Actual user data
 
 use crate::error::{Error, Result};
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::Buf;
 use crc32fast::Hasher;
 
 /// Maximum size of a single record's data portion
@@ -18,6 +18,7 @@ pub const HEADER_SIZE: usize = 7;
 
 /// Record types for handling large entries that span multiple blocks
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 #[repr(u8)]
 pub enum RecordType {
     /// Complete record contained in a single block
@@ -62,26 +63,20 @@ impl Record {
     ///
     /// Format: [checksum: u32][length: u16][type: u8][data: bytes]
     pub fn encode(&self) -> Vec<u8> {
-        let mut buf = BytesMut::with_capacity(HEADER_SIZE + self.data.len());
-
-        // Reserve space for checksum (will be filled later)
-        buf.put_u32_le(0);
-
-        // Write length
-        buf.put_u16_le(self.data.len() as u16);
-
-        // Write type
-        buf.put_u8(self.record_type as u8);
-
-        // Write data
-        buf.put_slice(&self.data);
-
-        // Calculate and write checksum
-        let checksum = Self::calculate_checksum(self.record_type, &self.data);
-        let mut result = buf.to_vec();
-        result[0..4].copy_from_slice(&checksum.to_le_bytes());
+        let mut buf = Vec::with_capacity(HEADER_SIZE + self.data.len());
+        buf.extend_from_slice(&Self::encode_header(self.record_type, &self.data));
+        buf.extend_from_slice(&self.data);
+        buf
+    }
 
-        result
+    /// Encode just the header of a record holding `data`, so the payload
+    /// can be written from where it is without copying it into a record
+    pub fn encode_header(record_type: RecordType, data: &[u8]) -> [u8; HEADER_SIZE] {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0..4].copy_from_slice(&Self::calculate_checksum(record_type, data).to_le_bytes());
+        header[4..6].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        header[6] = record_type as u8;
+        header
     }
 
     /// Decode a record from bytes
@@ -0,0 +1,244 @@
+//! Notifications of key changes.
+//!
+//! [`DB::watch`] subscribes to the puts, deletes and appends of every key starting
+//! with a prefix. Events are sent from the write path once a write group is
+//! visible to readers, in sequence number order, so a subscriber reading
+//! the key after receiving an event sees that write or a later one.
+//!
+//! Each subscription buffers up to [`WATCH_BUFFER_SIZE`] events. Writers
+//! never wait for a slow subscriber: events that do not fit are dropped and
+//! counted, and the subscriber receives a [`KeyEvent::Lagged`] with the
+//! number of missed events before the next one that fits. A subscriber that
+//! lagged has to re-read the keys it cares about.
+//!
+//! Only writes through `put`, `put_reader`, `delete`, `append` and `write`
+//! are reported; SSTables added with [`DB::ingest_external_file`] are not.
+//! Dropping the receiver ends the subscription.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::watch::KeyEvent;
+//! use aidb::{Options, DB};
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = DB::open("./data", Options::default())?;
+//! let events = db.watch(b"user:");
+//!
+//! db.put(b"user:1", b"alice")?;
+//! match events.recv().unwrap() {
+//!     KeyEvent::Put { key, value, .. } => println!("{:?} = {:?}", key, value),
+//!     KeyEvent::StreamedPut { key, len, .. } => println!("{:?} = {} bytes", key, len),
+//!     KeyEvent::Delete { key, .. } => println!("{:?} deleted", key),
+//!     KeyEvent::Append { key, suffix, .. } => println!("{:?} += {:?}", key, suffix),
+//!     KeyEvent::Lagged { missed } => println!("missed {} events", missed),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use parking_lot::RwLock;
+
+use crate::blob::BlobReference;
+use crate::write_batch::WriteOp;
+use crate::{WriteBatch, DB};
+
+/// Number of events buffered per subscription.
+pub const WATCH_BUFFER_SIZE: usize = 1024;
+
+/// A change to a watched key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// `key` was set to `value`
+    Put {
+        /// Sequence number of the write
+        sequence: u64,
+        /// The key written
+        key: Vec<u8>,
+        /// The new value
+        value: Vec<u8>,
+    },
+    /// `key` was set with [`DB::put_reader`] to a value of `len` bytes,
+    /// which is not copied into the event; read it with
+    /// [`DB::get_writer`] or [`DB::get`]
+    StreamedPut {
+        /// Sequence number of the write
+        sequence: u64,
+        /// The key written
+        key: Vec<u8>,
+        /// Length of the new value
+        len: u64,
+    },
+    /// `key` was deleted
+    Delete {
+        /// Sequence number of the delete
+        sequence: u64,
+        /// The key deleted
+        key: Vec<u8>,
+    },
+    /// `suffix` was appended to the value of `key`
+    Append {
+        /// Sequence number of the append
+        sequence: u64,
+        /// The key appended to
+        key: Vec<u8>,
+        /// The bytes appended
+        suffix: Vec<u8>,
+    },
+    /// `missed` events were dropped because the buffer was full
+    Lagged {
+        /// Number of events dropped since the last one delivered
+        missed: u64,
+    },
+}
+
+struct Subscription {
+    prefix: Vec<u8>,
+    sender: Sender<KeyEvent>,
+    /// Events dropped since the last one sent
+    missed: u64,
+}
+
+impl Subscription {
+    /// Sends `event`, or counts it as missed if the buffer is full. Returns
+    /// false once the receiver has been dropped.
+    fn send(&mut self, event: KeyEvent) -> bool {
+        if self.missed > 0 {
+            match self.sender.try_send(KeyEvent::Lagged { missed: self.missed }) {
+                Ok(()) => self.missed = 0,
+                Err(TrySendError::Full(_)) => {
+                    self.missed += 1;
+                    return true;
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        match self.sender.try_send(event) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.missed += 1;
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// The subscriptions of a database
+#[derive(Default)]
+pub(crate) struct Watchers {
+    subscriptions: RwLock<Vec<Subscription>>,
+}
+
+impl Watchers {
+    fn subscribe(&self, prefix: &[u8]) -> Receiver<KeyEvent> {
+        let (sender, receiver) = channel::bounded(WATCH_BUFFER_SIZE);
+        self.subscriptions.write().push(Subscription {
+            prefix: prefix.to_vec(),
+            sender,
+            missed: 0,
+        });
+        receiver
+    }
+
+    /// Sends the events for `batches`, written from `sequence` on, to the
+    /// subscriptions watching their keys
+    pub(crate) fn publish(&self, batches: &[&WriteBatch], sequence: u64) {
+        if self.subscriptions.read().is_empty() {
+            return;
+        }
+
+        let mut subscriptions = self.subscriptions.write();
+        let ops = batches.iter().flat_map(|batch| batch.iter());
+        for (sequence, op) in (sequence..).zip(ops) {
+            let key = op.key();
+            subscriptions.retain_mut(|subscription| {
+                if !key.starts_with(&subscription.prefix) {
+                    return true;
+                }
+                let event = match op {
+                    WriteOp::Put { key, value } => match BlobReference::decode(value) {
+                        Some(reference) => KeyEvent::StreamedPut {
+                            sequence,
+                            key: key.clone(),
+                            len: reference.size(),
+                        },
+                        None => KeyEvent::Put { sequence, key: key.clone(), value: value.clone() },
+                    },
+                    WriteOp::Delete { key } => KeyEvent::Delete { sequence, key: key.clone() },
+                    WriteOp::Append { key, suffix } => {
+                        KeyEvent::Append { sequence, key: key.clone(), suffix: suffix.clone() }
+                    }
+                };
+                subscription.send(event)
+            });
+        }
+    }
+}
+
+impl DB {
+    /// Subscribes to the puts, deletes and appends of keys starting with `prefix`.
+    ///
+    /// Only writes made after this call are reported; an empty prefix
+    /// watches every key. See the [module documentation](crate::watch) for
+    /// buffering and lag.
+    pub fn watch(&self, prefix: &[u8]) -> Receiver<KeyEvent> {
+        self.watchers.subscribe(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_watch_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        db.put(b"user:0", b"before").unwrap();
+
+        let users = db.watch(b"user:");
+        let all = db.watch(b"");
+        db.put(b"user:1", b"alice").unwrap();
+        db.put(b"order:1", b"book").unwrap();
+        let mut batch = WriteBatch::new();
+        batch.delete(b"user:0");
+        batch.put(b"user:2", b"bob");
+        db.write(batch).unwrap();
+
+        let events: Vec<_> = users.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                KeyEvent::Put { sequence: 2, key: b"user:1".to_vec(), value: b"alice".to_vec() },
+                KeyEvent::Delete { sequence: 4, key: b"user:0".to_vec() },
+                KeyEvent::Put { sequence: 5, key: b"user:2".to_vec(), value: b"bob".to_vec() },
+            ]
+        );
+        assert_eq!(all.try_iter().count(), 4);
+
+        // Dropped receivers are unsubscribed on the next matching write
+        drop(users);
+        db.put(b"user:3", b"carol").unwrap();
+        assert_eq!(db.watchers.subscriptions.read().len(), 1);
+    }
+
+    #[test]
+    fn test_watch_signals_lag() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+        let events = db.watch(b"");
+
+        for i in 0..WATCH_BUFFER_SIZE as u64 + 10 {
+            db.put(&i.to_be_bytes(), b"v").unwrap();
+        }
+        assert_eq!(events.try_iter().count(), WATCH_BUFFER_SIZE);
+
+        db.put(b"next", b"v").unwrap();
+        assert_eq!(events.try_recv().unwrap(), KeyEvent::Lagged { missed: 10 });
+        assert!(matches!(events.try_recv().unwrap(), KeyEvent::Put { key, .. } if key == b"next"));
+    }
+}
@@ -23,11 +23,46 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! # Savepoints
+//!
+//! [`WriteBatch::set_savepoint`] marks the current end of the batch and
+//! [`WriteBatch::rollback_to_savepoint`] drops every operation added after
+//! the latest mark, so a failed sub-step can be undone without discarding
+//! the rest of the batch.
+//!
+//! # Serialization
+//!
+//! [`WriteBatch::serialize`] encodes a batch so it can be sent to another
+//! process or queued, and [`WriteBatch::deserialize`] reads it back. The
+//! format is:
+//! - Version (1 byte): currently `1`
+//! - Operation count (4 bytes, little-endian)
+//! - Per operation:
+//!   - Tag (1 byte): `0x01` for a put, `0x02` for a delete, `0x03` for an
+//!     append
+//!   - Key length (4 bytes, little-endian) and key
+//!   - Put and append only: value length (4 bytes, little-endian) and value
+//!
+//! Savepoints are not serialized.
 
 use std::collections::VecDeque;
 
+use crate::error::{Error, Result};
+
+/// Version byte at the start of a serialized batch
+const FORMAT_VERSION: u8 = 1;
+
+/// Size of the version byte and operation count of a serialized batch
+const BATCH_HEADER_SIZE: usize = 1 + 4;
+
+const TAG_PUT: u8 = 0x01;
+const TAG_DELETE: u8 = 0x02;
+const TAG_APPEND: u8 = 0x03;
+
 /// Type of write operation in a batch.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub enum WriteOp {
     /// Put operation with key and value
     Put {
@@ -41,6 +76,22 @@ pub enum WriteOp {
         /// Key to delete
         key: Vec<u8>,
     },
+    /// Append operation adding bytes to the end of a key's value
+    Append {
+        /// Key whose value is extended
+        key: Vec<u8>,
+        /// Bytes to append
+        suffix: Vec<u8>,
+    },
+}
+
+impl WriteOp {
+    /// The key the operation writes
+    pub fn key(&self) -> &[u8] {
+        match self {
+            WriteOp::Put { key, .. } | WriteOp::Delete { key } | WriteOp::Append { key, .. } => key,
+        }
+    }
 }
 
 /// WriteBatch accumulates a sequence of write operations to be applied atomically.
@@ -48,10 +99,21 @@ pub enum WriteOp {
 /// Operations are buffered in memory and applied to the database together when
 /// `DB::write()` is called. This provides better performance than individual writes
 /// and ensures all operations succeed or fail together.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct WriteBatch {
     operations: VecDeque<WriteOp>,
     approximate_size: usize,
+    /// Operation bytes in the serialized format, without the batch header
+    data_size: usize,
+    savepoints: Vec<SavePoint>,
+}
+
+/// State of a batch when a savepoint was set
+#[derive(Debug, Clone, Copy)]
+struct SavePoint {
+    len: usize,
+    approximate_size: usize,
+    data_size: usize,
 }
 
 impl WriteBatch {
@@ -66,7 +128,12 @@ impl WriteBatch {
     /// assert!(batch.is_empty());
     /// ```
     pub fn new() -> Self {
-        Self { operations: VecDeque::new(), approximate_size: 0 }
+        Self {
+            operations: VecDeque::new(),
+            approximate_size: 0,
+            data_size: 0,
+            savepoints: Vec::new(),
+        }
     }
 
     /// Adds a Put operation to the batch.
@@ -88,6 +155,7 @@ impl WriteBatch {
     pub fn put(&mut self, key: &[u8], value: &[u8]) {
         let op_size = key.len() + value.len() + 8; // Approximate overhead
         self.approximate_size += op_size;
+        self.data_size += 1 + 4 + key.len() + 4 + value.len();
         self.operations
             .push_back(WriteOp::Put { key: key.to_vec(), value: value.to_vec() });
     }
@@ -110,9 +178,36 @@ impl WriteBatch {
     pub fn delete(&mut self, key: &[u8]) {
         let op_size = key.len() + 4; // Approximate overhead
         self.approximate_size += op_size;
+        self.data_size += 1 + 4 + key.len();
         self.operations.push_back(WriteOp::Delete { key: key.to_vec() });
     }
 
+    /// Adds an Append operation to the batch.
+    ///
+    /// See [`DB::append`](crate::DB::append).
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key whose value is extended
+    /// * `suffix` - The bytes to append
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use aidb::WriteBatch;
+    ///
+    /// let mut batch = WriteBatch::new();
+    /// batch.append(b"log", b"event");
+    /// assert_eq!(batch.len(), 1);
+    /// ```
+    pub fn append(&mut self, key: &[u8], suffix: &[u8]) {
+        let op_size = key.len() + suffix.len() + 8; // Approximate overhead
+        self.approximate_size += op_size;
+        self.data_size += 1 + 4 + key.len() + 4 + suffix.len();
+        self.operations
+            .push_back(WriteOp::Append { key: key.to_vec(), suffix: suffix.to_vec() });
+    }
+
     /// Clears all operations from the batch.
     ///
     /// # Example
@@ -128,6 +223,64 @@ impl WriteBatch {
     pub fn clear(&mut self) {
         self.operations.clear();
         self.approximate_size = 0;
+        self.data_size = 0;
+        self.savepoints.clear();
+    }
+
+    /// Records a savepoint at the current end of the batch.
+    ///
+    /// Savepoints nest: each call pushes a new one, and
+    /// [`rollback_to_savepoint`](Self::rollback_to_savepoint) and
+    /// [`pop_savepoint`](Self::pop_savepoint) act on the most recent.
+    pub fn set_savepoint(&mut self) {
+        self.savepoints.push(SavePoint {
+            len: self.operations.len(),
+            approximate_size: self.approximate_size,
+            data_size: self.data_size,
+        });
+    }
+
+    /// Removes every operation added since the most recent savepoint, and
+    /// that savepoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if no savepoint is set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use aidb::WriteBatch;
+    ///
+    /// let mut batch = WriteBatch::new();
+    /// batch.put(b"key1", b"value1");
+    /// batch.set_savepoint();
+    /// batch.put(b"key2", b"value2");
+    /// batch.delete(b"key1");
+    ///
+    /// batch.rollback_to_savepoint().unwrap();
+    /// assert_eq!(batch.len(), 1);
+    /// assert!(batch.rollback_to_savepoint().is_err());
+    /// ```
+    pub fn rollback_to_savepoint(&mut self) -> Result<()> {
+        let savepoint =
+            self.savepoints.pop().ok_or_else(|| Error::not_found("no savepoint set"))?;
+        self.operations.truncate(savepoint.len);
+        self.approximate_size = savepoint.approximate_size;
+        self.data_size = savepoint.data_size;
+        Ok(())
+    }
+
+    /// Removes the most recent savepoint without rolling back to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if no savepoint is set.
+    pub fn pop_savepoint(&mut self) -> Result<()> {
+        self.savepoints
+            .pop()
+            .map(|_| ())
+            .ok_or_else(|| Error::not_found("no savepoint set"))
     }
 
     /// Returns the number of operations in the batch.
@@ -167,10 +320,176 @@ impl WriteBatch {
         self.approximate_size
     }
 
-    /// Returns an iterator over the operations in the batch.
-    pub(crate) fn iter(&self) -> impl Iterator<Item = &WriteOp> {
+    /// Returns the exact size in bytes of the batch in the
+    /// [`serialize`](Self::serialize) format, as checked against
+    /// `max_batch_bytes` by [`DB::write`](crate::DB::write).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use aidb::WriteBatch;
+    ///
+    /// let mut batch = WriteBatch::new();
+    /// batch.put(b"key", b"value");
+    /// assert_eq!(batch.data_size(), batch.serialize().len());
+    /// ```
+    pub fn data_size(&self) -> usize {
+        BATCH_HEADER_SIZE + self.data_size
+    }
+
+    /// Encodes the batch in the format described in the [module
+    /// documentation](self).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use aidb::WriteBatch;
+    ///
+    /// let mut batch = WriteBatch::new();
+    /// batch.put(b"key", b"value");
+    /// batch.delete(b"old");
+    ///
+    /// let bytes = batch.serialize();
+    /// let decoded = WriteBatch::deserialize(&bytes).unwrap();
+    /// assert_eq!(decoded.len(), 2);
+    /// ```
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.data_size());
+        buf.push(FORMAT_VERSION);
+        buf.extend_from_slice(&(self.operations.len() as u32).to_le_bytes());
+        for op in &self.operations {
+            match op {
+                WriteOp::Put { key, value } => {
+                    buf.push(TAG_PUT);
+                    put_slice(&mut buf, key);
+                    put_slice(&mut buf, value);
+                }
+                WriteOp::Delete { key } => {
+                    buf.push(TAG_DELETE);
+                    put_slice(&mut buf, key);
+                }
+                WriteOp::Append { key, suffix } => {
+                    buf.push(TAG_APPEND);
+                    put_slice(&mut buf, key);
+                    put_slice(&mut buf, suffix);
+                }
+            }
+        }
+        buf
+    }
+
+    /// Decodes a batch produced by [`serialize`](Self::serialize).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Corruption` if `data` is truncated, has trailing bytes, or
+    /// uses an unknown version or operation tag.
+    pub fn deserialize(data: &[u8]) -> Result<Self> {
+        let mut input = data;
+        let version = take(&mut input, 1)?[0];
+        if version != FORMAT_VERSION {
+            return Err(Error::corruption(format!(
+                "Unsupported WriteBatch format version {}",
+                version
+            )));
+        }
+        let count = take_u32(&mut input)? as usize;
+
+        let mut batch = WriteBatch::new();
+        for _ in 0..count {
+            match take(&mut input, 1)?[0] {
+                TAG_PUT => {
+                    let key = take_slice(&mut input)?;
+                    let value = take_slice(&mut input)?;
+                    batch.put(key, value);
+                }
+                TAG_DELETE => {
+                    let key = take_slice(&mut input)?;
+                    batch.delete(key);
+                }
+                TAG_APPEND => {
+                    let key = take_slice(&mut input)?;
+                    let suffix = take_slice(&mut input)?;
+                    batch.append(key, suffix);
+                }
+                tag => {
+                    return Err(Error::corruption(format!(
+                        "Unknown WriteBatch operation tag {}",
+                        tag
+                    )))
+                }
+            }
+        }
+        if !input.is_empty() {
+            return Err(Error::corruption("Trailing data after WriteBatch"));
+        }
+        Ok(batch)
+    }
+
+    /// Returns an iterator over the operations in the batch, in the order
+    /// they were added.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use aidb::write_batch::WriteOp;
+    /// use aidb::WriteBatch;
+    ///
+    /// let mut batch = WriteBatch::new();
+    /// batch.put(b"key", b"value");
+    /// batch.delete(b"old");
+    ///
+    /// let deletes = batch.iter().filter(|op| matches!(op, WriteOp::Delete { .. })).count();
+    /// assert_eq!(deletes, 1);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = &WriteOp> {
         self.operations.iter()
     }
+
+    /// Returns the operation at `index`.
+    pub(crate) fn get(&self, index: usize) -> Option<&WriteOp> {
+        self.operations.get(index)
+    }
+}
+
+/// Builds the batch from arbitrary operations, keeping its sizes consistent
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for WriteBatch {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut batch = WriteBatch::new();
+        for op in u.arbitrary_iter::<WriteOp>()? {
+            match op? {
+                WriteOp::Put { key, value } => batch.put(&key, &value),
+                WriteOp::Delete { key } => batch.delete(&key),
+                WriteOp::Append { key, suffix } => batch.append(&key, &suffix),
+            }
+        }
+        Ok(batch)
+    }
+}
+
+fn put_slice(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Splits `len` bytes off the front of `input`
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if input.len() < len {
+        return Err(Error::corruption("Truncated WriteBatch"));
+    }
+    let (head, rest) = input.split_at(len);
+    *input = rest;
+    Ok(head)
+}
+
+fn take_u32(input: &mut &[u8]) -> Result<u32> {
+    Ok(u32::from_le_bytes(take(input, 4)?.try_into().expect("4 bytes")))
+}
+
+fn take_slice<'a>(input: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = take_u32(input)? as usize;
+    take(input, len)
 }
 
 #[cfg(test)]
@@ -241,6 +560,60 @@ mod tests {
         assert!(after_put_size > initial_size);
     }
 
+    #[test]
+    fn test_write_batch_savepoints() {
+        let mut batch = WriteBatch::new();
+        batch.put(b"key1", b"value1");
+        batch.set_savepoint();
+        let size = batch.approximate_size();
+        batch.put(b"key2", b"value2");
+        batch.set_savepoint();
+        batch.delete(b"key1");
+
+        batch.rollback_to_savepoint().unwrap();
+        assert_eq!(batch.len(), 2);
+        batch.rollback_to_savepoint().unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch.approximate_size(), size);
+        assert_eq!(batch.data_size(), batch.serialize().len());
+        assert!(batch.rollback_to_savepoint().is_err());
+
+        batch.set_savepoint();
+        batch.put(b"key3", b"value3");
+        batch.pop_savepoint().unwrap();
+        assert!(batch.rollback_to_savepoint().is_err());
+        assert_eq!(batch.len(), 2);
+    }
+
+    #[test]
+    fn test_write_batch_serialize_roundtrip() {
+        let mut batch = WriteBatch::new();
+        batch.put(b"key1", b"value1");
+        batch.delete(b"key2");
+        batch.append(b"key3", b"suffix");
+        batch.put(b"", b"");
+
+        let bytes = batch.serialize();
+        let decoded = WriteBatch::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.iter().collect::<Vec<_>>(), batch.iter().collect::<Vec<_>>());
+        assert_eq!(decoded.approximate_size(), batch.approximate_size());
+        assert_eq!(bytes.len(), batch.data_size());
+
+        let empty = WriteBatch::deserialize(&WriteBatch::new().serialize()).unwrap();
+        assert!(empty.is_empty());
+
+        // Truncation, trailing bytes, and unknown versions are rejected
+        for len in 0..bytes.len() {
+            assert!(WriteBatch::deserialize(&bytes[..len]).is_err(), "len={}", len);
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(WriteBatch::deserialize(&trailing).is_err());
+        let mut version = bytes;
+        version[0] = 2;
+        assert!(WriteBatch::deserialize(&version).is_err());
+    }
+
     #[test]
     fn test_write_batch_iter() {
         let mut batch = WriteBatch::new();
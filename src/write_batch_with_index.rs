@@ -0,0 +1,423 @@
+//! A [`WriteBatch`] that can be read back before it is written.
+//!
+//! [`WriteBatchWithIndex`] keeps a sorted index from each key to the latest
+//! operation on it in the batch. That makes read-your-own-writes logic
+//! possible without applying the batch: [`get_from_batch_and_db`] returns
+//! the batch's value for a key and falls back to the database, and
+//! [`scan`] iterates over the database as it will look once the batch is
+//! written.
+//!
+//! [`get_from_batch_and_db`]: WriteBatchWithIndex::get_from_batch_and_db
+//! [`scan`]: WriteBatchWithIndex::scan
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::{DB, Options, WriteBatchWithIndex};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let db = Arc::new(DB::open("./data", Options::default())?);
+//! db.put(b"balance:alice", b"100")?;
+//!
+//! let mut batch = WriteBatchWithIndex::new();
+//! batch.put(b"balance:alice", b"70");
+//! batch.put(b"balance:bob", b"30");
+//!
+//! // The batch's own writes are visible before it is applied
+//! assert_eq!(batch.get_from_batch_and_db(&db, b"balance:alice")?, Some(b"70".to_vec()));
+//! let mut iter = batch.scan(&db, Some(b"balance:"), Some(b"balance;"))?;
+//! while iter.valid() {
+//!     println!("{:?} => {:?}", iter.key(), iter.value());
+//!     iter.next();
+//! }
+//!
+//! db.write(batch.into_batch())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::comparator::Comparator;
+use crate::write_batch::WriteOp;
+use crate::{DBIterator, Result, WriteBatch, DB};
+
+/// A [`WriteBatch`] with a sorted index over its keys.
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatchWithIndex {
+    batch: WriteBatch,
+    /// Position in `batch` of the latest operation on each key
+    index: BTreeMap<Vec<u8>, usize>,
+}
+
+impl WriteBatchWithIndex {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a Put operation to the batch.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.index.insert(key.to_vec(), self.batch.len());
+        self.batch.put(key, value);
+    }
+
+    /// Adds a Delete operation to the batch.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.index.insert(key.to_vec(), self.batch.len());
+        self.batch.delete(key);
+    }
+
+    /// Clears all operations from the batch.
+    pub fn clear(&mut self) {
+        self.batch.clear();
+        self.index.clear();
+    }
+
+    /// Records a savepoint at the current end of the batch.
+    pub fn set_savepoint(&mut self) {
+        self.batch.set_savepoint();
+    }
+
+    /// Removes every operation added since the most recent savepoint, and
+    /// that savepoint. See [`WriteBatch::rollback_to_savepoint`].
+    pub fn rollback_to_savepoint(&mut self) -> Result<()> {
+        self.batch.rollback_to_savepoint()?;
+        self.index.clear();
+        for (position, op) in self.batch.iter().enumerate() {
+            self.index.insert(op.key().to_vec(), position);
+        }
+        Ok(())
+    }
+
+    /// Removes the most recent savepoint without rolling back to it.
+    pub fn pop_savepoint(&mut self) -> Result<()> {
+        self.batch.pop_savepoint()
+    }
+
+    /// Returns the number of operations in the batch.
+    pub fn len(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Returns true if the batch contains no operations.
+    pub fn is_empty(&self) -> bool {
+        self.batch.is_empty()
+    }
+
+    /// Returns the underlying batch.
+    pub fn batch(&self) -> &WriteBatch {
+        &self.batch
+    }
+
+    /// Consumes the index and returns the batch, ready for
+    /// [`DB::write`].
+    pub fn into_batch(self) -> WriteBatch {
+        self.batch
+    }
+
+    /// Looks `key` up in the batch only.
+    ///
+    /// Returns `None` if the batch does not touch `key`, `Some(None)` if its
+    /// latest operation on `key` is a delete, and `Some(Some(value))` for a
+    /// put.
+    pub fn get_from_batch(&self, key: &[u8]) -> Option<Option<&[u8]>> {
+        self.index.get(key).map(|&position| self.value_at(position))
+    }
+
+    /// Looks `key` up in the batch, then in `db` if the batch does not touch it.
+    pub fn get_from_batch_and_db(&self, db: &DB, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.get_from_batch(key) {
+            Some(value) => Ok(value.map(<[u8]>::to_vec)),
+            None => db.get(key),
+        }
+    }
+
+    /// Creates an iterator over all keys of `db` with the batch applied.
+    pub fn iter(&self, db: &Arc<DB>) -> Result<BatchDbIterator<'_>> {
+        self.scan(db, None, None)
+    }
+
+    /// Creates an iterator over `start <= key < end` of `db` with the batch
+    /// applied: keys put by the batch are added or overwritten, keys it
+    /// deletes are skipped.
+    pub fn scan(
+        &self,
+        db: &Arc<DB>,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<BatchDbIterator<'_>> {
+        let base = db.scan(start, end)?;
+        let comparator = Arc::clone(&db.comparator);
+        // The index is ordered bytewise; the database may not be
+        let mut entries: Vec<(&[u8], usize)> =
+            self.index.iter().map(|(key, &position)| (key.as_slice(), position)).collect();
+        entries.sort_by(|(a, _), (b, _)| comparator.compare(a, b));
+        let mut iter = BatchDbIterator {
+            batch: self,
+            entries,
+            comparator,
+            base,
+            lower_bound: start.map(<[u8]>::to_vec),
+            upper_bound: end.map(<[u8]>::to_vec),
+            delta: None,
+            current: None,
+        };
+        iter.seek_to_first();
+        Ok(iter)
+    }
+
+    /// Value of the operation at `position`, `None` for a delete
+    fn value_at(&self, position: usize) -> Option<&[u8]> {
+        match self.batch.get(position).expect("indexed operation exists") {
+            WriteOp::Put { value, .. } => Some(value),
+            WriteOp::Delete { .. } => None,
+            WriteOp::Append { .. } => unreachable!("indexed batches have no appends"),
+        }
+    }
+}
+
+/// Which input the current entry of a [`BatchDbIterator`] comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Base,
+    Batch,
+}
+
+/// Forward iterator over a database merged with a [`WriteBatchWithIndex`].
+///
+/// Created by [`WriteBatchWithIndex::scan`]. The database side reads the
+/// state at creation time; the batch cannot change while the iterator
+/// borrows it.
+pub struct BatchDbIterator<'a> {
+    batch: &'a WriteBatchWithIndex,
+    /// Keys of the batch and the position of their latest operation, in
+    /// the database's order
+    entries: Vec<(&'a [u8], usize)>,
+    comparator: Arc<dyn Comparator>,
+    base: DBIterator,
+    lower_bound: Option<Vec<u8>>,
+    upper_bound: Option<Vec<u8>>,
+    /// Batch entry the batch side is on: its key and operation position
+    delta: Option<(&'a [u8], usize)>,
+    current: Option<Side>,
+}
+
+impl<'a> BatchDbIterator<'a> {
+    /// Returns true if the iterator is positioned at an entry.
+    pub fn valid(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Returns the error that made the database side fail, if any.
+    pub fn status(&self) -> Result<()> {
+        self.base.status()
+    }
+
+    /// Returns the key at the current position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator is not valid.
+    pub fn key(&self) -> &[u8] {
+        match self.current.expect("Iterator not valid") {
+            Side::Base => self.base.key(),
+            Side::Batch => self.delta.expect("batch side positioned").0,
+        }
+    }
+
+    /// Returns the value at the current position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator is not valid.
+    pub fn value(&self) -> &[u8] {
+        match self.current.expect("Iterator not valid") {
+            Side::Base => self.base.value(),
+            Side::Batch => {
+                let position = self.delta.expect("batch side positioned").1;
+                self.batch.value_at(position).expect("current batch entry is a put")
+            }
+        }
+    }
+
+    /// Moves to the next entry.
+    pub fn next(&mut self) {
+        match self.current {
+            Some(Side::Base) => self.base.next(),
+            Some(Side::Batch) => {
+                let key = self.delta.expect("batch side positioned").0;
+                if self.base.valid() && self.base.key() == key {
+                    self.base.next();
+                }
+                self.delta = self.delta_from(Bound::Excluded(key));
+            }
+            None => return,
+        }
+        self.find_entry();
+    }
+
+    /// Seeks to the first key that is greater than or equal to `target`.
+    pub fn seek(&mut self, target: &[u8]) {
+        let target = match self.lower_bound {
+            Some(ref lower) if self.comparator.compare(lower, target) == Ordering::Greater => {
+                lower.clone()
+            }
+            _ => target.to_vec(),
+        };
+        self.base.seek(&target);
+        self.delta = self.delta_from(Bound::Included(&target));
+        self.find_entry();
+    }
+
+    /// Seeks to the first key in range.
+    pub fn seek_to_first(&mut self) {
+        self.base.seek_to_first();
+        let lower = self.lower_bound.clone();
+        self.delta = self.delta_from(lower.as_deref().map_or(Bound::Unbounded, Bound::Included));
+        self.find_entry();
+    }
+
+    /// First batch entry after `from` that is below the upper bound
+    fn delta_from(&self, from: Bound<&[u8]>) -> Option<(&'a [u8], usize)> {
+        let compare = |key: &[u8], from: &[u8]| self.comparator.compare(key, from);
+        let index = match from {
+            Bound::Included(from) => {
+                self.entries.partition_point(|&(key, _)| compare(key, from) == Ordering::Less)
+            }
+            Bound::Excluded(from) => self
+                .entries
+                .partition_point(|&(key, _)| compare(key, from) != Ordering::Greater),
+            Bound::Unbounded => 0,
+        };
+        let (key, position) = *self.entries.get(index)?;
+        match self.upper_bound {
+            Some(ref upper) if compare(key, upper) != Ordering::Less => None,
+            _ => Some((key, position)),
+        }
+    }
+
+    /// Picks the smaller key of the two sides; on equal keys the batch
+    /// decides. Keys the batch deletes are skipped.
+    fn find_entry(&mut self) {
+        loop {
+            let Some((key, position)) = self.delta else {
+                self.current = self.base.valid().then_some(Side::Base);
+                return;
+            };
+            if self.base.valid() && self.comparator.compare(self.base.key(), key) == Ordering::Less
+            {
+                self.current = Some(Side::Base);
+                return;
+            }
+            if self.batch.value_at(position).is_some() {
+                self.current = Some(Side::Batch);
+                return;
+            }
+            // Deleted by the batch
+            if self.base.valid() && self.base.key() == key {
+                self.base.next();
+            }
+            self.delta = self.delta_from(Bound::Excluded(key));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_get_from_batch_and_db() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = DB::open(tmp_dir.path(), Options::default()).unwrap();
+        db.put(b"a", b"db").unwrap();
+        db.put(b"b", b"db").unwrap();
+
+        let mut batch = WriteBatchWithIndex::new();
+        batch.put(b"a", b"batch1");
+        batch.delete(b"b");
+        batch.put(b"a", b"batch2");
+        batch.put(b"c", b"batch");
+
+        assert_eq!(batch.get_from_batch(b"a"), Some(Some(&b"batch2"[..])));
+        assert_eq!(batch.get_from_batch(b"b"), Some(None));
+        assert_eq!(batch.get_from_batch(b"d"), None);
+
+        assert_eq!(batch.get_from_batch_and_db(&db, b"a").unwrap(), Some(b"batch2".to_vec()));
+        assert_eq!(batch.get_from_batch_and_db(&db, b"b").unwrap(), None);
+        db.put(b"d", b"db").unwrap();
+        assert_eq!(batch.get_from_batch_and_db(&db, b"d").unwrap(), Some(b"db".to_vec()));
+
+        assert_eq!(batch.len(), 4);
+        db.write(batch.into_batch()).unwrap();
+        assert_eq!(db.get(b"a").unwrap(), Some(b"batch2".to_vec()));
+        assert_eq!(db.get(b"b").unwrap(), None);
+    }
+
+    #[test]
+    fn test_rollback_to_savepoint() {
+        let mut batch = WriteBatchWithIndex::new();
+        batch.put(b"a", b"1");
+        batch.set_savepoint();
+        batch.put(b"a", b"2");
+        batch.delete(b"b");
+
+        batch.rollback_to_savepoint().unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch.get_from_batch(b"a"), Some(Some(&b"1"[..])));
+        assert_eq!(batch.get_from_batch(b"b"), None);
+    }
+
+    #[test]
+    fn test_scan_merges_batch_and_db() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(tmp_dir.path(), Options::default()).unwrap());
+        for key in [b"a", b"c", b"e", b"g"] {
+            db.put(key, b"db").unwrap();
+        }
+
+        let mut batch = WriteBatchWithIndex::new();
+        batch.put(b"b", b"batch");
+        batch.put(b"c", b"batch");
+        batch.delete(b"e");
+        batch.delete(b"f");
+        batch.put(b"h", b"batch");
+
+        let collect = |iter: &mut BatchDbIterator<'_>| {
+            let mut entries = Vec::new();
+            while iter.valid() {
+                entries.push((iter.key().to_vec(), iter.value().to_vec()));
+                iter.next();
+            }
+            entries
+        };
+
+        let mut iter = batch.iter(&db).unwrap();
+        let expected: Vec<(Vec<u8>, Vec<u8>)> = [
+            (b"a", &b"db"[..]),
+            (b"b", b"batch"),
+            (b"c", b"batch"),
+            (b"g", b"db"),
+            (b"h", b"batch"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_vec(), v.to_vec()))
+        .collect();
+        assert_eq!(collect(&mut iter), expected);
+
+        iter.seek(b"d");
+        assert_eq!(iter.key(), b"g");
+
+        let mut iter = batch.scan(&db, Some(b"b"), Some(b"g")).unwrap();
+        let keys: Vec<_> = collect(&mut iter).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![b"b".to_vec(), b"c".to_vec()]);
+        iter.status().unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! A MemTable memory budget shared by several databases.
+//!
+//! A process hosting many databases (one per tenant, say) otherwise holds
+//! up to `memtable_size` per database, plus every MemTable waiting for a
+//! flush. A [`WriteBufferManager`] set in the
+//! [`Options::write_buffer_manager`](crate::Options) of several databases
+//! adds up their MemTable memory. After each write, if the total is over
+//! the budget, the database holding the most MemTable memory is asked to
+//! flush: right away if it is the one writing, otherwise on its next write
+//! or [`flush`](crate::DB::flush). An idle database therefore keeps its
+//! MemTables until it writes again, while the writing ones flush as soon as
+//! they become the largest.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use aidb::write_buffer_manager::WriteBufferManager;
+//! use aidb::{Options, DB};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), aidb::Error> {
+//! let manager = Arc::new(WriteBufferManager::new(256 * 1024 * 1024));
+//! let options = Options::default().write_buffer_manager(Arc::clone(&manager));
+//! let a = DB::open("./tenant-a", options.clone())?;
+//! let b = DB::open("./tenant-b", options)?;
+//! a.put(b"key", b"value")?;
+//! println!("MemTables use {} bytes", manager.memory_usage());
+//! # Ok(())
+//! # }
+//! ```
+
+use parking_lot::Mutex;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+
+/// MemTable memory of one database
+#[derive(Debug, Default)]
+pub(crate) struct BufferUsage {
+    memory: AtomicUsize,
+    flush_requested: AtomicBool,
+}
+
+impl BufferUsage {
+    /// Records the database's current MemTable memory
+    pub(crate) fn set(&self, memory: usize) {
+        self.memory.store(memory, Ordering::Relaxed);
+    }
+
+    /// Whether another database's write asked this one to flush; clears
+    /// the request
+    pub(crate) fn take_flush_request(&self) -> bool {
+        self.flush_requested.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Tracks the MemTable memory of several databases against one budget.
+///
+/// See the [module documentation](crate::write_buffer_manager).
+pub struct WriteBufferManager {
+    buffer_size: AtomicUsize,
+    /// Databases using the manager; entries of closed ones are dead
+    instances: Mutex<Vec<Weak<BufferUsage>>>,
+}
+
+impl fmt::Debug for WriteBufferManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteBufferManager")
+            .field("buffer_size", &self.buffer_size())
+            .field("memory_usage", &self.memory_usage())
+            .finish()
+    }
+}
+
+impl WriteBufferManager {
+    /// Creates a manager with a budget of `buffer_size` bytes (0 = only
+    /// track memory, never flush).
+    pub fn new(buffer_size: usize) -> Self {
+        Self { buffer_size: AtomicUsize::new(buffer_size), instances: Mutex::new(Vec::new()) }
+    }
+
+    /// The budget in bytes.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.load(Ordering::Relaxed)
+    }
+
+    /// Changes the budget; applies from the next write.
+    pub fn set_buffer_size(&self, buffer_size: usize) {
+        self.buffer_size.store(buffer_size, Ordering::Relaxed);
+    }
+
+    /// MemTable memory of all open databases using the manager, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.instances
+            .lock()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|usage| usage.memory.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Adds a database
+    pub(crate) fn register(&self) -> Arc<BufferUsage> {
+        let usage = Arc::new(BufferUsage::default());
+        let mut instances = self.instances.lock();
+        instances.retain(|instance| instance.strong_count() > 0);
+        instances.push(Arc::downgrade(&usage));
+        usage
+    }
+
+    /// Called after a write to the database of `usage`. If the budget is
+    /// exceeded, returns true when that database holds the most memory and
+    /// should flush; otherwise asks the largest one to flush.
+    pub(crate) fn should_flush(&self, usage: &Arc<BufferUsage>) -> bool {
+        let buffer_size = self.buffer_size();
+        if buffer_size == 0 {
+            return false;
+        }
+        let instances: Vec<Arc<BufferUsage>> =
+            self.instances.lock().iter().filter_map(Weak::upgrade).collect();
+        let total: usize = instances.iter().map(|i| i.memory.load(Ordering::Relaxed)).sum();
+        if total <= buffer_size {
+            return false;
+        }
+        let Some(largest) = instances.iter().max_by_key(|i| i.memory.load(Ordering::Relaxed))
+        else {
+            return false;
+        };
+        if Arc::ptr_eq(largest, usage)
+            || largest.memory.load(Ordering::Relaxed) <= usage.memory.load(Ordering::Relaxed)
+        {
+            return true;
+        }
+        largest.flush_requested.store(true, Ordering::Relaxed);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, DB};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_buffer_manager_flushes_largest_instance() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(WriteBufferManager::new(0));
+        let options = Options::default().write_buffer_manager(Arc::clone(&manager));
+        let a = DB::open(temp_dir.path().join("a"), options.clone()).unwrap();
+        let b = DB::open(temp_dir.path().join("b"), options).unwrap();
+
+        a.put(b"big", &[0; 20_000]).unwrap();
+        b.put(b"small", &[0; 1_000]).unwrap();
+        assert!(manager.memory_usage() >= 21_000);
+
+        // Over the budget, the writer asks the larger database to flush
+        manager.set_buffer_size(15_000);
+        b.put(b"small2", &[0; 1_000]).unwrap();
+        assert_eq!(a.level_sizes()[0].0, 0);
+        assert_eq!(b.level_sizes()[0].0, 0);
+        a.put(b"next", b"v").unwrap();
+        assert_eq!(a.level_sizes()[0].0, 1);
+        assert!(manager.memory_usage() < 15_000);
+
+        // The writing database flushes itself once it is the largest
+        b.put(b"big", &[0; 20_000]).unwrap();
+        assert_eq!(b.level_sizes()[0].0, 1);
+
+        drop(a);
+        assert!(manager.memory_usage() < 1_000);
+    }
+}
@@ -0,0 +1,367 @@
+//! Group commit for the write path.
+//!
+//! Concurrent writers join a queue. The writer at the front becomes the
+//! leader of a write group: it takes every batch queued behind it (up to
+//! [`MAX_GROUP_BYTES`]), allocates their sequence numbers and appends all of
+//! them to the WAL under a single lock acquisition and a single sync. Then
+//! every writer in the group inserts its own batch into the MemTable in
+//! parallel, and once all have finished the leader publishes the group's
+//! last sequence number, making the whole group visible to readers at
+//! once. Writers queued in the meantime form the next group.
+//!
+//! In pipelined mode the next group is formed as soon as the WAL write of
+//! the current one completes, so its WAL write overlaps with the MemTable
+//! insertion of the previous group. Groups are still published in order: a
+//! group becomes visible only after every earlier group has.
+
+use parking_lot::{Condvar, Mutex};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::{Result, WriteBatch};
+
+/// Upper bound on the total size of the batches in one group.
+pub(crate) const MAX_GROUP_BYTES: usize = 1 << 20;
+
+/// A group started by a small batch grows by at most this much, so a small
+/// write is not delayed by a large group
+const SMALL_BATCH_GROWTH: usize = 128 << 10;
+
+enum WriterState {
+    /// Queued, or waiting for a leader
+    Waiting,
+    /// The group's WAL write succeeded; apply the batch at this sequence
+    Apply { group: u64, sequence: u64 },
+    /// Finished, successfully or not
+    Done(Result<()>),
+}
+
+struct Writer {
+    batch: WriteBatch,
+    state: Mutex<WriterState>,
+}
+
+/// A group whose WAL write succeeded but which is not yet published
+struct PendingGroup {
+    id: u64,
+    /// Writers of the group, leader included, still applying their batch
+    unapplied: usize,
+}
+
+struct QueueState {
+    waiting: VecDeque<Arc<Writer>>,
+    /// True while a leader is writing a group to the WAL, and in
+    /// non-pipelined mode until the group is published
+    wal_busy: bool,
+    /// Groups being applied, oldest first
+    pending: VecDeque<PendingGroup>,
+    next_group: u64,
+    /// Last sequence number handed out to a group
+    last_allocated: u64,
+}
+
+/// Serializes writers into groups. See the [module documentation](self).
+pub(crate) struct WriteQueue {
+    state: Mutex<QueueState>,
+    cond: Condvar,
+}
+
+impl WriteQueue {
+    /// Creates a queue whose first group starts after `last_sequence`.
+    pub(crate) fn new(last_sequence: u64) -> Self {
+        let state = QueueState {
+            waiting: VecDeque::new(),
+            wal_busy: false,
+            pending: VecDeque::new(),
+            next_group: 0,
+            last_allocated: last_sequence,
+        };
+        Self { state: Mutex::new(state), cond: Condvar::new() }
+    }
+
+    /// Writes `batch` as part of a group and returns once it is visible.
+    ///
+    /// - `commit` runs on the leader with the batches of the whole group, in
+    ///   order, and the sequence number of the first operation; operations
+    ///   are numbered consecutively from there. It writes them to the WAL. If
+    ///   it fails, every writer in the group gets the error and the sequence
+    ///   numbers are reused by the next group.
+    /// - `apply` runs on every writer for its own batch and the sequence
+    ///   number of its first operation.
+    /// - `publish` runs on the leader with the batches of the group, the
+    ///   sequence number of the first operation and that of the last once
+    ///   every batch of the group and of all earlier groups has been applied.
+    ///
+    /// With `pipelined`, the next group may start its `commit` while this
+    /// one is still being applied.
+    pub(crate) fn write(
+        &self,
+        batch: WriteBatch,
+        pipelined: bool,
+        commit: impl FnOnce(&[&WriteBatch], u64) -> Result<()>,
+        apply: impl FnOnce(&WriteBatch, u64),
+        publish: impl FnOnce(&[&WriteBatch], u64, u64),
+    ) -> Result<()> {
+        let writer = Arc::new(Writer { batch, state: Mutex::new(WriterState::Waiting) });
+        let mut apply = Some(apply);
+        let mut queue = self.state.lock();
+        queue.waiting.push_back(Arc::clone(&writer));
+
+        loop {
+            let state = std::mem::replace(&mut *writer.state.lock(), WriterState::Waiting);
+            match state {
+                WriterState::Done(result) => return result,
+                WriterState::Apply { group, sequence } => {
+                    drop(queue);
+                    let apply = apply.take().expect("a batch is applied once");
+                    apply(&writer.batch, sequence);
+                    queue = self.state.lock();
+                    queue.finish_apply(group);
+                    self.cond.notify_all();
+                    continue;
+                }
+                WriterState::Waiting => {}
+            }
+            let is_front = queue.waiting.front().is_some_and(|w| Arc::ptr_eq(w, &writer));
+            if !queue.wal_busy && is_front {
+                break;
+            }
+            self.cond.wait(&mut queue);
+        }
+
+        // Leader: take this batch and as many queued ones as fit
+        let first_size = writer.batch.approximate_size();
+        let max_bytes = if first_size <= SMALL_BATCH_GROWTH {
+            first_size + SMALL_BATCH_GROWTH
+        } else {
+            MAX_GROUP_BYTES
+        };
+        let mut group = vec![queue.waiting.pop_front().expect("leader is queued")];
+        let mut group_bytes = first_size;
+        while let Some(next) = queue.waiting.front() {
+            let size = next.batch.approximate_size();
+            if group_bytes + size > max_bytes {
+                break;
+            }
+            group_bytes += size;
+            group.push(queue.waiting.pop_front().expect("checked above"));
+        }
+        queue.wal_busy = true;
+        let base = queue.last_allocated + 1;
+        drop(queue);
+
+        let batches: Vec<&WriteBatch> = group.iter().map(|w| &w.batch).collect();
+        let committed = commit(&batches, base);
+
+        let mut queue = self.state.lock();
+        if let Err(e) = committed {
+            // Part of the group may have reached the WAL; its sequence
+            // numbers are not handed out again, so a replay never finds two
+            // records with the same one
+            queue.last_allocated = base + batches.iter().map(|b| b.len() as u64).sum::<u64>() - 1;
+            for follower in &group[1..] {
+                *follower.state.lock() = WriterState::Done(Err(e.clone()));
+            }
+            queue.wal_busy = false;
+            self.cond.notify_all();
+            return Err(e);
+        }
+
+        let id = queue.next_group;
+        queue.next_group += 1;
+        let mut next_seq = base + writer.batch.len() as u64;
+        for follower in &group[1..] {
+            *follower.state.lock() = WriterState::Apply { group: id, sequence: next_seq };
+            next_seq += follower.batch.len() as u64;
+        }
+        let last_seq = next_seq - 1;
+        queue.last_allocated = last_seq;
+        queue.pending.push_back(PendingGroup { id, unapplied: group.len() });
+        if pipelined {
+            queue.wal_busy = false;
+        }
+        self.cond.notify_all();
+        drop(queue);
+
+        let apply = apply.take().expect("the leader has not applied its batch");
+        apply(&writer.batch, base);
+
+        let mut queue = self.state.lock();
+        queue.finish_apply(id);
+        // Wait for the rest of the group and for earlier groups to publish
+        while queue.pending.front().is_some_and(|g| g.id != id || g.unapplied > 0) {
+            self.cond.wait(&mut queue);
+        }
+        publish(&batches, base, last_seq);
+        queue.pending.pop_front();
+        for follower in &group[1..] {
+            *follower.state.lock() = WriterState::Done(Ok(()));
+        }
+        if !pipelined {
+            queue.wal_busy = false;
+        }
+        self.cond.notify_all();
+        Ok(())
+    }
+
+    /// Waits until every group has been published and keeps new groups
+    /// from starting until the returned guard is dropped.
+    ///
+    /// While paused, every acknowledged write is both in the WAL and in the
+    /// MemTable, and no write is between the two, so both can be replaced
+    /// together.
+    pub(crate) fn pause(&self) -> WritePause<'_> {
+        let mut queue = self.state.lock();
+        while queue.wal_busy || !queue.pending.is_empty() {
+            self.cond.wait(&mut queue);
+        }
+        queue.wal_busy = true;
+        WritePause { queue: self }
+    }
+}
+
+/// Holds the write queue paused. See [`WriteQueue::pause`].
+pub(crate) struct WritePause<'a> {
+    queue: &'a WriteQueue,
+}
+
+impl Drop for WritePause<'_> {
+    fn drop(&mut self) {
+        self.queue.state.lock().wal_busy = false;
+        self.queue.cond.notify_all();
+    }
+}
+
+impl QueueState {
+    /// Records that a writer of `group` has applied its batch
+    fn finish_apply(&mut self, group: u64) {
+        let pending = self.pending.iter_mut().find(|g| g.id == group).expect("group is pending");
+        pending.unapplied -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+    fn batch(ops: usize) -> WriteBatch {
+        let mut batch = WriteBatch::new();
+        for i in 0..ops {
+            batch.put(format!("key{}", i).as_bytes(), b"value");
+        }
+        batch
+    }
+
+    #[test]
+    fn test_write_queue_groups_concurrent_writers() {
+        for pipelined in [false, true] {
+            let queue = WriteQueue::new(0);
+            let last_seq = AtomicU64::new(0);
+            let commits = AtomicUsize::new(0);
+            let applied = Mutex::new(Vec::new());
+
+            std::thread::scope(|s| {
+                for t in 0..8 {
+                    let (queue, last_seq, commits, applied) =
+                        (&queue, &last_seq, &commits, &applied);
+                    s.spawn(move || {
+                        for _ in 0..200 {
+                            queue
+                                .write(
+                                    batch(t % 3 + 1),
+                                    pipelined,
+                                    |batches, base| {
+                                        commits.fetch_add(1, Ordering::SeqCst);
+                                        assert!(!batches.is_empty());
+                                        // Earlier groups may still be applying
+                                        // when pipelined, but are never ahead
+                                        assert!(base > last_seq.load(Ordering::SeqCst));
+                                        Ok(())
+                                    },
+                                    |batch, base| {
+                                        applied.lock().push((base, batch.len() as u64));
+                                    },
+                                    |_, _, last| {
+                                        // Groups are published in order
+                                        let previous = last_seq.swap(last, Ordering::SeqCst);
+                                        assert!(previous < last);
+                                    },
+                                )
+                                .unwrap();
+                        }
+                    });
+                }
+            });
+
+            // Every operation got exactly one sequence number
+            let mut applied = applied.into_inner();
+            applied.sort();
+            let mut expected = 1;
+            for (base, len) in applied {
+                assert_eq!(base, expected);
+                expected += len;
+            }
+            assert_eq!(last_seq.load(Ordering::SeqCst), expected - 1);
+            assert!(commits.load(Ordering::SeqCst) <= 8 * 200);
+        }
+    }
+
+    #[test]
+    fn test_write_queue_commit_error() {
+        let queue = WriteQueue::new(6);
+        let result = queue.write(
+            batch(1),
+            false,
+            |_, _| Err(Error::NoSpace("disk full".to_string())),
+            |_, _| panic!("must not apply a failed group"),
+            |_, _, _| panic!("must not publish a failed group"),
+        );
+        assert!(matches!(result, Err(Error::NoSpace(_))));
+
+        // The queue is usable again and skips the failed sequence number
+        let published = AtomicU64::new(0);
+        queue
+            .write(
+                batch(2),
+                true,
+                |_, base| {
+                    assert_eq!(base, 8);
+                    Ok(())
+                },
+                |_, base| assert_eq!(base, 8),
+                |_, _, last| published.store(last, Ordering::SeqCst),
+            )
+            .unwrap();
+        assert_eq!(published.load(Ordering::SeqCst), 9);
+    }
+
+    #[test]
+    fn test_write_queue_pause() {
+        let queue = WriteQueue::new(0);
+        let published = AtomicU64::new(0);
+        let pause = queue.pause();
+
+        std::thread::scope(|s| {
+            let writer = s.spawn(|| {
+                queue
+                    .write(
+                        batch(1),
+                        true,
+                        |_, _| Ok(()),
+                        |_, _| {},
+                        |_, _, last| published.store(last, Ordering::SeqCst),
+                    )
+                    .unwrap()
+            });
+
+            // The write cannot start while paused
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            assert_eq!(published.load(Ordering::SeqCst), 0);
+            drop(pause);
+            writer.join().unwrap();
+        });
+        assert_eq!(published.load(Ordering::SeqCst), 1);
+    }
+}
@@ -62,12 +62,18 @@ fn test_maximum_value_size() {
 #[test]
 fn test_maximum_key_size() {
     let dir = TempDir::new().unwrap();
-    let db = DB::open(dir.path(), Options::default()).unwrap();
 
     // Test with 1MB key
     let large_key = vec![b'k'; 1024 * 1024];
     let value = b"value_for_large_key";
 
+    // Rejected by the default limit
+    let db = DB::open(dir.path(), Options::default()).unwrap();
+    assert!(matches!(db.put(&large_key, value), Err(aidb::Error::InvalidArgument(_))));
+    drop(db);
+
+    let options = Options::default().max_key_size(1024 * 1024);
+    let db = DB::open(dir.path(), options.clone()).unwrap();
     db.put(&large_key, value).unwrap();
     assert_eq!(db.get(&large_key).unwrap(), Some(value.to_vec()));
 
@@ -75,7 +81,7 @@ fn test_maximum_key_size() {
     db.flush().unwrap();
     drop(db);
 
-    let db = DB::open(dir.path(), Options::default()).unwrap();
+    let db = DB::open(dir.path(), options).unwrap();
     assert_eq!(db.get(&large_key).unwrap(), Some(value.to_vec()));
 }
 
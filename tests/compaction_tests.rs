@@ -1,5 +1,6 @@
 //! Integration tests for compaction functionality
 
+use aidb::config::{CompressionType, LevelOptions};
 use aidb::{Options, DB};
 use std::sync::Arc;
 use tempfile::TempDir;
@@ -324,3 +325,43 @@ fn test_compaction_with_overwrites() {
         assert_eq!(value, Some(b"new".to_vec()));
     }
 }
+
+#[test]
+fn test_compaction_with_per_level_options() {
+    env_logger::try_init().ok();
+
+    let temp_dir = TempDir::new().unwrap();
+    let options = Options::default()
+        .memtable_size(16 * 1024)
+        .level_options(0, LevelOptions::new().compression(CompressionType::None))
+        .level_options(
+            1,
+            LevelOptions::new()
+                .compression(CompressionType::None)
+                .target_file_size(8 * 1024)
+                .use_bloom_filter(false),
+        );
+
+    let db = DB::open(temp_dir.path(), options).unwrap();
+
+    for batch in 0..4 {
+        for i in 0..100 {
+            let key = format!("key{:04}", batch * 100 + i);
+            db.put(key.as_bytes(), &[b'x'; 100]).unwrap();
+        }
+        db.flush().unwrap();
+    }
+
+    for i in 0..400 {
+        let key = format!("key{:04}", i);
+        assert_eq!(db.get(key.as_bytes()).unwrap(), Some(vec![b'x'; 100]), "key {}", key);
+    }
+    drop(db);
+
+    // The split files are tracked in the manifest
+    let db = DB::open(temp_dir.path(), Options::default()).unwrap();
+    for i in 0..400 {
+        let key = format!("key{:04}", i);
+        assert!(db.get(key.as_bytes()).unwrap().is_some(), "key {} lost after reopen", key);
+    }
+}
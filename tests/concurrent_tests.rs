@@ -457,3 +457,49 @@ fn test_concurrent_flush_calls() {
         assert_eq!(db.get(format!("key_{}", i).as_bytes()).unwrap(), Some(b"value".to_vec()));
     }
 }
+
+/// Test that batches committed together by concurrent writers are all
+/// recovered from the WAL, with every batch intact, with and without
+/// pipelined writes
+#[test]
+fn test_concurrent_batches_recovered_from_wal() {
+    for pipelined in [false, true] {
+        let dir = TempDir::new().unwrap();
+        let options = Options::default().enable_pipelined_write(pipelined);
+        let num_threads = 8;
+        let batches_per_thread = 100;
+
+        {
+            let db = Arc::new(DB::open(dir.path(), options.clone()).unwrap());
+            let barrier = Arc::new(Barrier::new(num_threads));
+            let handles: Vec<_> = (0..num_threads)
+                .map(|thread_id| {
+                    let db = Arc::clone(&db);
+                    let barrier = Arc::clone(&barrier);
+                    thread::spawn(move || {
+                        barrier.wait();
+                        for i in 0..batches_per_thread {
+                            let mut batch = aidb::WriteBatch::new();
+                            batch.put(format!("t{}_a{}", thread_id, i).as_bytes(), b"a");
+                            batch.put(format!("t{}_b{}", thread_id, i).as_bytes(), b"b");
+                            db.write(batch).unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        }
+
+        let db = DB::open(dir.path(), options).unwrap();
+        for thread_id in 0..num_threads {
+            for i in 0..batches_per_thread {
+                let a = format!("t{}_a{}", thread_id, i);
+                let b = format!("t{}_b{}", thread_id, i);
+                assert_eq!(db.get(a.as_bytes()).unwrap(), Some(b"a".to_vec()));
+                assert_eq!(db.get(b.as_bytes()).unwrap(), Some(b"b".to_vec()));
+            }
+        }
+    }
+}
@@ -1,14 +1,14 @@
 // Crash Recovery Tests for AiDb
 // These tests verify data consistency after simulated crashes
 
-use aidb::{Options, DB};
+use aidb::{Options, WriteBatch, DB};
 use std::fs;
 use tempfile::TempDir;
 
 /// Helper function to simulate a crash by dropping DB without proper close
-/// Uses mem::forget to prevent Drop from running (simulates abrupt termination)
+/// (simulates abrupt termination: Drop does not run, the LOCK is released)
 fn simulate_crash(db: DB) {
-    std::mem::forget(db);
+    db.simulate_crash();
 }
 
 /// Test recovery after crash during write operations
@@ -226,6 +226,45 @@ fn test_recovery_with_wal_corruption() {
     }
 }
 
+/// Test that a batch cut off mid-record is dropped as a whole
+#[test]
+fn test_recovery_torn_batch_is_atomic() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_path_buf();
+    let options = Options { sync_wal: true, ..Default::default() };
+
+    {
+        let db = DB::open(&path, options.clone()).unwrap();
+        db.put(b"before", b"value").unwrap();
+
+        // Large enough to span several WAL record fragments
+        let mut batch = WriteBatch::new();
+        for i in 0..200 {
+            batch.put(format!("batch_key_{}", i).as_bytes(), &[b'x'; 1024]);
+        }
+        db.write(batch).unwrap();
+        simulate_crash(db);
+    }
+
+    // Damage the last fragment of the batch record
+    for entry in fs::read_dir(&path).unwrap().flatten() {
+        let file_path = entry.path();
+        if file_path.extension().and_then(|s| s.to_str()) == Some("log") {
+            let mut data = fs::read(&file_path).unwrap();
+            let len = data.len();
+            data[len - 10] ^= 0xff;
+            fs::write(&file_path, &data).unwrap();
+        }
+    }
+
+    let db = DB::open(&path, options).unwrap();
+    assert_eq!(db.get(b"before").unwrap(), Some(b"value".to_vec()));
+    for i in 0..200 {
+        let key = format!("batch_key_{}", i);
+        assert_eq!(db.get(key.as_bytes()).unwrap(), None, "{} recovered", key);
+    }
+}
+
 /// Test recovery with deletes
 #[test]
 fn test_recovery_with_deletes() {
@@ -459,3 +498,164 @@ fn test_recovery_mixed_operations() {
         assert_eq!(db.get(b"key3").unwrap(), Some(b"value3".to_vec()));
     }
 }
+
+/// Test that writes not synced before a power loss are lost, and that a
+/// record torn by it is dropped
+#[cfg(feature = "testing")]
+#[test]
+fn test_recovery_drops_unsynced_and_torn_writes() {
+    use aidb::env::{FaultInjectionEnv, PosixEnv};
+    use std::sync::Arc;
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_path_buf();
+    let env = Arc::new(FaultInjectionEnv::new(Arc::new(PosixEnv)));
+    let options = Options { sync_wal: true, ..Default::default() }.env(env.clone());
+
+    for keep in [0, 4096] {
+        {
+            let db = DB::open(&path, options.clone()).unwrap();
+            db.put(b"synced", b"value").unwrap();
+
+            // Larger than the WAL buffer, so it reaches the file unsynced
+            db.set_options(&[("sync_wal", "false")]).unwrap();
+            let mut batch = WriteBatch::new();
+            for i in 0..200 {
+                batch.put(format!("unsynced_{}", i).as_bytes(), &[b'x'; 1024]);
+            }
+            db.write(batch).unwrap();
+            simulate_crash(db);
+        }
+        env.simulate_torn_crash(keep).unwrap();
+
+        let db = DB::open(&path, options.clone()).unwrap();
+        assert_eq!(db.get(b"synced").unwrap(), Some(b"value".to_vec()));
+        for i in 0..200 {
+            let key = format!("unsynced_{}", i);
+            assert_eq!(db.get(key.as_bytes()).unwrap(), None, "{} recovered", key);
+        }
+    }
+}
+
+/// Test that a full disk fails the write and the data written before
+/// survives a crash
+#[cfg(feature = "testing")]
+#[test]
+fn test_recovery_after_injected_no_space() {
+    use aidb::env::{Fault, FaultInjectionEnv, PosixEnv};
+    use std::sync::Arc;
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_path_buf();
+    let env = Arc::new(FaultInjectionEnv::new(Arc::new(PosixEnv)));
+    let options = Options { sync_wal: true, ..Default::default() }.env(env.clone());
+
+    {
+        let db = DB::open(&path, options.clone()).unwrap();
+        db.put(b"before", b"value").unwrap();
+        env.fail_after(1, Fault::NoSpace);
+        assert!(matches!(db.put(b"after", b"value"), Err(aidb::Error::NoSpace(_))));
+        simulate_crash(db);
+    }
+    env.simulate_crash().unwrap();
+
+    let db = DB::open(&path, options).unwrap();
+    assert_eq!(db.get(b"before").unwrap(), Some(b"value".to_vec()));
+}
+
+/// Test that a failed WAL write stops later writes until `resume`, and that
+/// the write which reached the WAL anyway does not shadow later ones
+#[cfg(feature = "testing")]
+#[test]
+fn test_recovery_after_failed_wal_sync() {
+    use aidb::env::{Fault, FaultInjectionEnv, PosixEnv};
+    use std::sync::Arc;
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_path_buf();
+    let env = Arc::new(FaultInjectionEnv::new(Arc::new(PosixEnv)));
+    let options = Options { sync_wal: true, ..Default::default() }.env(env.clone());
+
+    {
+        let db = DB::open(&path, options.clone()).unwrap();
+        db.put(b"key", b"before").unwrap();
+
+        // The record is written, its sync fails
+        env.fail_after(2, Fault::Io);
+        assert!(db.put(b"key", b"failed").is_err());
+        env.clear_fault();
+        assert!(matches!(db.put(b"key", b"rejected"), Err(aidb::Error::BackgroundError(_))));
+
+        db.resume().unwrap();
+        db.put(b"key", b"after").unwrap();
+        assert_eq!(db.get(b"key").unwrap(), Some(b"after".to_vec()));
+        db.put(b"other", b"value").unwrap();
+        simulate_crash(db);
+    }
+
+    let db = DB::open(&path, options).unwrap();
+    assert_eq!(db.get(b"key").unwrap(), Some(b"after".to_vec()));
+    assert_eq!(db.get(b"other").unwrap(), Some(b"value".to_vec()));
+}
+
+/// Test that writes acknowledged while `drop_all` runs concurrently are
+/// either dropped or survive a crash, never readable and then lost
+#[cfg(feature = "testing")]
+#[test]
+fn test_drop_all_with_concurrent_writers_survives_crash() {
+    use aidb::env::{FaultInjectionEnv, PosixEnv};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_path_buf();
+    let env = Arc::new(FaultInjectionEnv::new(Arc::new(PosixEnv)));
+    let options = Options { sync_wal: true, enable_pipelined_write: true, ..Default::default() }
+        .env(env.clone());
+
+    // Only writes racing the last drop can be lost, so drop once per crash
+    for round in 0..50 {
+        let visible = {
+            let db = Arc::new(DB::open(&path, options.clone()).unwrap());
+            let done = Arc::new(AtomicBool::new(false));
+            let writers: Vec<_> = (0..4)
+                .map(|t| {
+                    let db = Arc::clone(&db);
+                    let done = Arc::clone(&done);
+                    std::thread::spawn(move || {
+                        let mut i = 0;
+                        while !done.load(Ordering::SeqCst) {
+                            let key = format!("key_{}_{}_{}", round, t, i);
+                            db.put(key.as_bytes(), b"value").unwrap();
+                            i += 1;
+                        }
+                        i
+                    })
+                })
+                .collect();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            db.drop_all().unwrap();
+            done.store(true, Ordering::SeqCst);
+            let counts: Vec<usize> = writers.into_iter().map(|w| w.join().unwrap()).collect();
+
+            let mut visible = Vec::new();
+            for (t, count) in counts.into_iter().enumerate() {
+                for i in 0..count {
+                    let key = format!("key_{}_{}_{}", round, t, i);
+                    if db.get(key.as_bytes()).unwrap().is_some() {
+                        visible.push(key);
+                    }
+                }
+            }
+            simulate_crash(Arc::into_inner(db).unwrap());
+            visible
+        };
+        env.simulate_crash().unwrap();
+
+        let db = DB::open(&path, options.clone()).unwrap();
+        for key in &visible {
+            assert_eq!(db.get(key.as_bytes()).unwrap(), Some(b"value".to_vec()), "{} lost", key);
+        }
+        db.close().unwrap();
+    }
+}